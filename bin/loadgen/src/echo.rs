@@ -0,0 +1,107 @@
+//! Echo backends with injectable faults
+//!
+//! Each backend answers with the request body it was sent. A configurable
+//! share of requests is delayed, answered with 503, or has its connection
+//! closed without a response, so retries, circuit breaking, and outlier
+//! handling can be exercised under load.
+
+use anyhow::Result;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rand::Rng;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// Faults the echo backends inject
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FaultPattern {
+    /// Share of requests answered with 503
+    pub error_rate: f64,
+    /// Share of requests delayed before answering
+    pub delay_rate: f64,
+    /// How long delayed requests wait
+    pub delay: Duration,
+    /// Share of requests whose connection is closed without a response
+    pub reset_rate: f64,
+}
+
+impl FaultPattern {
+    /// Whether any faults are injected
+    pub fn is_none(&self) -> bool {
+        self.error_rate <= 0.0 && self.delay_rate <= 0.0 && self.reset_rate <= 0.0
+    }
+}
+
+/// Error returned by the service to drop a connection
+#[derive(Debug)]
+struct Reset;
+
+impl std::fmt::Display for Reset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("injected connection reset")
+    }
+}
+
+impl std::error::Error for Reset {}
+
+/// Answer one request, injecting faults
+async fn echo(req: Request<Incoming>, faults: FaultPattern) -> Result<Response<Full<Bytes>>, Reset> {
+    let (reset, error, delay) = {
+        let mut rng = rand::thread_rng();
+        (
+            rng.gen_bool(faults.reset_rate.clamp(0.0, 1.0)),
+            rng.gen_bool(faults.error_rate.clamp(0.0, 1.0)),
+            rng.gen_bool(faults.delay_rate.clamp(0.0, 1.0)),
+        )
+    };
+    if reset {
+        return Err(Reset);
+    }
+    if delay {
+        tokio::time::sleep(faults.delay).await;
+    }
+    if error {
+        let mut response = Response::new(Full::new(Bytes::from_static(b"injected failure\n")));
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        return Ok(response);
+    }
+
+    let body = req.into_body().collect().await.map(|c| c.to_bytes()).unwrap_or_default();
+    Ok(Response::new(Full::new(body)))
+}
+
+/// Start an echo backend on `addr`, returning the address it listens on
+pub async fn spawn(addr: SocketAddr, faults: FaultPattern) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    info!("Echo backend listening on {}", local);
+
+    let faults = Arc::new(faults);
+    let handle = tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    debug!("Echo backend accept error: {}", e);
+                    continue;
+                }
+            };
+            let faults = faults.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| echo(req, *faults));
+                if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                    debug!("Echo backend connection closed: {}", e);
+                }
+            });
+        }
+    });
+    Ok((local, handle))
+}
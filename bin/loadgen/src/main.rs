@@ -0,0 +1,258 @@
+//! Load generator for soak and stress testing the gateway
+//!
+//! Starts echo backends, sends requests at a fixed rate to a target, and
+//! reports status counts and latency percentiles. Point the gateway at the
+//! first backend with `ROUTER_BACKEND_URL=http://127.0.0.1:9000` and pass
+//! the gateway's address as `--target`; without a target, requests go to
+//! the echo backend directly as a baseline.
+//!
+//! Latency is measured from when a request was scheduled rather than sent,
+//! so a saturated target shows up in the percentiles instead of lowering
+//! the request rate.
+
+mod echo;
+mod report;
+
+use anyhow::{anyhow, bail, Context, Result};
+use echo::FaultPattern;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use report::{Outcome, Report};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::info;
+use tracing_subscriber::fmt::init as tracing_init;
+
+const USAGE: &str = "\
+Usage: loadgen [OPTIONS]
+
+Options:
+  --target URL        Gateway to load (default: the first echo backend)
+  --rps N             Requests per second (default: 100)
+  --connections N     Most requests in flight at once (default: 10)
+  --duration SECS     Length of the run (default: 10)
+  --payload BYTES     Request body size; 0 sends GETs, more sends POSTs (default: 0)
+  --timeout-ms N      Request timeout (default: 5000)
+  --backends N        Echo backends to start (default: 1)
+  --echo-port PORT    Port of the first echo backend, the rest follow; 0 picks any (default: 9000)
+  --error-rate F      Share of requests the backends answer with 503 (default: 0)
+  --delay-rate F      Share of requests the backends delay (default: 0)
+  --delay-ms N        How long delayed requests wait (default: 100)
+  --reset-rate F      Share of requests whose connection the backends close (default: 0)
+  --echo-only         Only serve the echo backends, until Ctrl-C
+  --help              Show this help";
+
+/// Load run settings
+#[derive(Clone, Debug)]
+struct Config {
+    target: Option<String>,
+    rps: u32,
+    connections: usize,
+    duration: Duration,
+    payload: usize,
+    timeout: Duration,
+    backends: u16,
+    echo_port: u16,
+    faults: FaultPattern,
+    echo_only: bool,
+    help: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            target: None,
+            rps: 100,
+            connections: 10,
+            duration: Duration::from_secs(10),
+            payload: 0,
+            timeout: Duration::from_secs(5),
+            backends: 1,
+            echo_port: 9000,
+            faults: FaultPattern {
+                delay: Duration::from_millis(100),
+                ..Default::default()
+            },
+            echo_only: false,
+            help: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parse command line arguments
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", flag));
+            match flag.as_str() {
+                "--target" => config.target = Some(value()?),
+                "--rps" => config.rps = parse(&flag, &value()?)?,
+                "--connections" => config.connections = parse(&flag, &value()?)?,
+                "--duration" => config.duration = Duration::from_secs(parse(&flag, &value()?)?),
+                "--payload" => config.payload = parse(&flag, &value()?)?,
+                "--timeout-ms" => config.timeout = Duration::from_millis(parse(&flag, &value()?)?),
+                "--backends" => config.backends = parse(&flag, &value()?)?,
+                "--echo-port" => config.echo_port = parse(&flag, &value()?)?,
+                "--error-rate" => config.faults.error_rate = parse(&flag, &value()?)?,
+                "--delay-rate" => config.faults.delay_rate = parse(&flag, &value()?)?,
+                "--delay-ms" => config.faults.delay = Duration::from_millis(parse(&flag, &value()?)?),
+                "--reset-rate" => config.faults.reset_rate = parse(&flag, &value()?)?,
+                "--echo-only" => config.echo_only = true,
+                "--help" | "-h" => config.help = true,
+                other => bail!("Unknown option {}", other),
+            }
+        }
+        if config.rps == 0 || config.connections == 0 || config.backends == 0 {
+            bail!("--rps, --connections, and --backends must be at least 1");
+        }
+        Ok(config)
+    }
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| anyhow!("Invalid value for {}: {}", flag, value))
+}
+
+/// Send requests to `target` at the configured rate for the configured duration
+async fn run(config: &Config, target: &str) -> Result<Report> {
+    let uri: hyper::Uri = format!("{}/loadgen", target.trim_end_matches('/'))
+        .parse()
+        .with_context(|| format!("Invalid target {}", target))?;
+    let client = Client::builder(TokioExecutor::new())
+        .pool_max_idle_per_host(config.connections)
+        .build_http::<Full<Bytes>>();
+    let in_flight = Arc::new(Semaphore::new(config.connections));
+    let payload = Bytes::from(vec![b'x'; config.payload]);
+    let method = if payload.is_empty() { Method::GET } else { Method::POST };
+
+    let (results, mut received) = mpsc::unbounded_channel();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / config.rps as f64));
+    let started = Instant::now();
+    while started.elapsed() < config.duration {
+        let scheduled = ticker.tick().await;
+        let permit = in_flight.clone().acquire_owned().await?;
+        let request = Request::builder()
+            .method(method.clone())
+            .uri(uri.clone())
+            .body(Full::new(payload.clone()))?;
+        let client = client.clone();
+        let results = results.clone();
+        let timeout = config.timeout;
+        tokio::spawn(async move {
+            let outcome = tokio::time::timeout(timeout, async {
+                let response = client.request(request).await.ok()?;
+                let status = response.status().as_u16();
+                response.into_body().collect().await.ok()?;
+                Some(status)
+            })
+            .await;
+            let outcome = match outcome {
+                Ok(Some(status)) => Outcome::Status(status),
+                _ => Outcome::Failed,
+            };
+            let _ = results.send((scheduled.elapsed(), outcome));
+            drop(permit);
+        });
+    }
+    drop(results);
+
+    let mut report = Report::default();
+    while let Some((latency, outcome)) = received.recv().await {
+        report.record(latency, outcome);
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_init();
+
+    let config = match Config::parse(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if config.help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let mut backends = Vec::new();
+    for i in 0..config.backends {
+        let port = match config.echo_port {
+            0 => 0,
+            first => first.checked_add(i).context("Echo backend ports out of range")?,
+        };
+        let (addr, _) = echo::spawn(SocketAddr::from(([127, 0, 0, 1], port)), config.faults).await?;
+        backends.push(addr);
+    }
+    if !config.faults.is_none() {
+        info!("Echo backends inject faults: {:?}", config.faults);
+    }
+    if config.echo_only {
+        info!("Serving echo backends until Ctrl-C");
+        tokio::signal::ctrl_c().await?;
+        return Ok(());
+    }
+
+    let target = config.target.clone().unwrap_or_else(|| format!("http://{}", backends[0]));
+    info!(
+        "Sending {} req/s over up to {} connections to {} for {:?}",
+        config.rps, config.connections, target, config.duration
+    );
+    let report = run(&config, &target).await?;
+    print!("{}", report);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let config = Config::parse(args("--rps 500 --payload 1024 --error-rate 0.05 --delay-ms 250")).unwrap();
+        assert_eq!(config.rps, 500);
+        assert_eq!(config.payload, 1024);
+        assert_eq!(config.faults.error_rate, 0.05);
+        assert_eq!(config.faults.delay, Duration::from_millis(250));
+        assert_eq!(config.connections, 10);
+
+        assert!(Config::parse(args("--rps")).is_err());
+        assert!(Config::parse(args("--rps 0")).is_err());
+        assert!(Config::parse(args("--bogus 1")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_against_echo_backend() {
+        let faults = FaultPattern {
+            error_rate: 0.5,
+            ..Default::default()
+        };
+        let (addr, _) = echo::spawn(SocketAddr::from(([127, 0, 0, 1], 0)), faults).await.unwrap();
+        let config = Config {
+            rps: 200,
+            duration: Duration::from_millis(250),
+            payload: 64,
+            ..Default::default()
+        };
+
+        let report = run(&config, &format!("http://{}", addr)).await.unwrap();
+        assert!(report.total() >= 40, "{}", report);
+        assert!(report.succeeded() > 0 && report.succeeded() < report.total(), "{}", report);
+    }
+}
@@ -0,0 +1,106 @@
+//! Results of a load run
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Outcome of one request
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    /// A response with this status
+    Status(u16),
+    /// No response: connection refused, reset, or timed out
+    Failed,
+}
+
+/// Latencies and outcomes of a run
+#[derive(Debug, Default)]
+pub struct Report {
+    latencies: Vec<Duration>,
+    statuses: BTreeMap<u16, u64>,
+    failed: u64,
+    /// Wall-clock length of the run
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Record a request, timed from when it was scheduled
+    pub fn record(&mut self, latency: Duration, outcome: Outcome) {
+        self.latencies.push(latency);
+        match outcome {
+            Outcome::Status(status) => *self.statuses.entry(status).or_default() += 1,
+            Outcome::Failed => self.failed += 1,
+        }
+    }
+
+    /// Number of requests sent
+    pub fn total(&self) -> u64 {
+        self.latencies.len() as u64
+    }
+
+    /// Number of requests answered with a 2xx status
+    pub fn succeeded(&self) -> u64 {
+        self.statuses.range(200..300).map(|(_, n)| n).sum()
+    }
+
+    /// Latency below which `percentile` percent of requests finished
+    pub fn percentile(&mut self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        self.latencies.sort_unstable();
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    /// Requests completed per second
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.total() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sorted = Report {
+            latencies: self.latencies.clone(),
+            ..Default::default()
+        };
+        writeln!(f, "Requests:    {} in {:.1}s ({:.1}/s)", self.total(), self.elapsed.as_secs_f64(), self.throughput())?;
+        writeln!(f, "Succeeded:   {}", self.succeeded())?;
+        for (status, count) in &self.statuses {
+            writeln!(f, "  {}:       {}", status, count)?;
+        }
+        writeln!(f, "  failed:    {}", self.failed)?;
+        writeln!(f, "Latency:")?;
+        for (label, percentile) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("p99.9", 99.9), ("max", 100.0)] {
+            writeln!(f, "  {:<6}     {:.2}ms", label, sorted.percentile(percentile).as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut report = Report::default();
+        for ms in (1..=100).rev() {
+            report.record(Duration::from_millis(ms), Outcome::Status(if ms % 10 == 0 { 503 } else { 200 }));
+        }
+        report.record(Duration::from_millis(500), Outcome::Failed);
+        report.elapsed = Duration::from_secs(2);
+
+        assert_eq!(report.total(), 101);
+        assert_eq!(report.succeeded(), 90);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(51));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(100));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(500));
+        assert_eq!(report.throughput(), 50.5);
+        assert!(report.to_string().contains("503:       10"));
+    }
+}
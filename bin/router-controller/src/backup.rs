@@ -0,0 +1,292 @@
+//! Scheduled backups of the router's resources
+//!
+//! Routing configuration lives only in the API server, so a mistaken
+//! `kubectl delete` takes routes down with no record of what they were.
+//! When `ROUTER_BACKUP_DIR` is set, the controller periodically exports
+//! every router.datum.net resource users write into a timestamped JSON file
+//! there, keeping the newest few. Point it at a persistent volume, or a
+//! bucket mounted as one, to keep backups off the cluster.
+//!
+//! `router-controller restore <file>` recreates the resources of a backup
+//! that no longer exist. Resources that do exist are left alone, so
+//! restoring never rolls back a change made since the backup. RouterGateways
+//! describe running replicas and are not backed up.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use kube::api::{ListParams, PostParams};
+use kube::core::{ClusterResourceScope, NamespaceResourceScope};
+use kube::{Api, Client, Resource, ResourceExt};
+use router_api::{RouterConfig, ServiceBinding, VPCEgress, VPCIngress, VPCRoute, VPCService};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::metrics::ControllerMetrics;
+
+/// Prefix of backup file names; the rest is the time the backup was taken
+const FILE_PREFIX: &str = "router-backup-";
+
+/// A backup file
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    /// When the backup was taken (RFC 3339)
+    pub created_at: String,
+    /// Resources with their metadata trimmed to what is needed to recreate them
+    pub resources: Vec<Value>,
+}
+
+/// How many resources a restore created and found already present
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreSummary {
+    pub created: usize,
+    pub existing: usize,
+}
+
+/// Writes a backup every interval, keeping the newest ones
+pub struct BackupJob {
+    client: Client,
+    metrics: ControllerMetrics,
+    dir: PathBuf,
+    interval: Duration,
+    keep: usize,
+}
+
+impl BackupJob {
+    /// Back up to `dir` every `interval`, keeping the newest `keep` backups
+    pub fn new(client: Client, metrics: ControllerMetrics, dir: PathBuf, interval: Duration, keep: usize) -> Self {
+        Self { client, metrics, dir, interval, keep: keep.max(1) }
+    }
+
+    /// Back up until the process exits, starting right away
+    pub async fn run(self) {
+        info!("Backing up router resources to {} every {:?}", self.dir.display(), self.interval);
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            match self.backup().await {
+                Ok(path) => {
+                    self.metrics.backup_runs_total.with_label_values(&["success"]).inc();
+                    info!("Backed up router resources to {}", path.display());
+                }
+                Err(e) => {
+                    self.metrics.backup_runs_total.with_label_values(&["error"]).inc();
+                    warn!("Backup failed: {:#}", e);
+                }
+            }
+        }
+    }
+
+    /// Write one backup and prune old ones, returning the new file
+    ///
+    /// The file is written under a temporary name and renamed, so a backup
+    /// cut short never looks complete.
+    async fn backup(&self) -> Result<PathBuf> {
+        let backup = export(&self.client).await?;
+        let name = format!("{}{}.json", FILE_PREFIX, Utc::now().format("%Y%m%dT%H%M%SZ"));
+        let path = self.dir.join(&name);
+        let partial = self.dir.join(format!(".{}.partial", name));
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(&partial, serde_json::to_vec_pretty(&backup)?).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        self.prune().await?;
+        Ok(path)
+    }
+
+    /// Delete all but the newest `keep` backups
+    async fn prune(&self) -> Result<()> {
+        let mut backups = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(FILE_PREFIX) && name.ends_with(".json") {
+                backups.push(entry.path());
+            }
+        }
+        // Timestamps in the names sort chronologically
+        backups.sort();
+        let excess = backups.len().saturating_sub(self.keep);
+        for path in &backups[..excess] {
+            debug!("Removing old backup {}", path.display());
+            tokio::fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Every backed-up resource, as it is now
+pub async fn export(client: &Client) -> Result<Backup> {
+    let mut resources = Vec::new();
+    resources.extend(export_kind::<VPCService>(client).await?);
+    resources.extend(export_kind::<VPCRoute>(client).await?);
+    resources.extend(export_kind::<VPCIngress>(client).await?);
+    resources.extend(export_kind::<VPCEgress>(client).await?);
+    resources.extend(export_kind::<ServiceBinding>(client).await?);
+    resources.extend(export_kind::<RouterConfig>(client).await?);
+    Ok(Backup { created_at: Utc::now().to_rfc3339(), resources })
+}
+
+async fn export_kind<K>(client: &Client) -> Result<Vec<Value>>
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let api: Api<K> = Api::all(client.clone());
+    let list = api
+        .list(&ListParams::default())
+        .await
+        .with_context(|| format!("listing {}", K::plural(&())))?;
+    list.items.iter().map(|resource| Ok(trim(serde_json::to_value(resource)?))).collect()
+}
+
+/// Keep what is needed to recreate a resource: its type, identity,
+/// labels, annotations, and spec
+fn trim(resource: Value) -> Value {
+    let metadata = &resource["metadata"];
+    let mut trimmed_metadata = json!({ "name": metadata["name"] });
+    for field in ["namespace", "labels", "annotations"] {
+        if !metadata[field].is_null() {
+            trimmed_metadata[field] = metadata[field].clone();
+        }
+    }
+    json!({
+        "apiVersion": resource["apiVersion"],
+        "kind": resource["kind"],
+        "metadata": trimmed_metadata,
+        "spec": resource["spec"],
+    })
+}
+
+/// Recreate the resources of a backup that don't exist
+pub async fn restore(client: &Client, backup: &Backup) -> Result<RestoreSummary> {
+    let mut summary = RestoreSummary::default();
+    for resource in &backup.resources {
+        let created = match resource["kind"].as_str().unwrap_or_default() {
+            "VPCService" => restore_namespaced::<VPCService>(client, resource).await?,
+            "VPCRoute" => restore_namespaced::<VPCRoute>(client, resource).await?,
+            "VPCIngress" => restore_cluster::<VPCIngress>(client, resource).await?,
+            "VPCEgress" => restore_cluster::<VPCEgress>(client, resource).await?,
+            "ServiceBinding" => restore_cluster::<ServiceBinding>(client, resource).await?,
+            "RouterConfig" => restore_cluster::<RouterConfig>(client, resource).await?,
+            other => return Err(anyhow!("unknown kind {:?} in backup", other)),
+        };
+        if created {
+            summary.created += 1;
+        } else {
+            summary.existing += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Create a namespaced `resource` unless it exists
+async fn restore_namespaced<K>(client: &Client, resource: &Value) -> Result<bool>
+where
+    K: Resource<DynamicType = (), Scope = NamespaceResourceScope> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let resource: K = serde_json::from_value(resource.clone())?;
+    let namespace = resource
+        .namespace()
+        .ok_or_else(|| anyhow!("{} {} in backup has no namespace", K::kind(&()), resource.name_any()))?;
+    create_missing(Api::namespaced(client.clone(), &namespace), resource).await
+}
+
+/// Create a cluster-scoped `resource` unless it exists
+async fn restore_cluster<K>(client: &Client, resource: &Value) -> Result<bool>
+where
+    K: Resource<DynamicType = (), Scope = ClusterResourceScope> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let resource: K = serde_json::from_value(resource.clone())?;
+    create_missing(Api::all(client.clone()), resource).await
+}
+
+/// Create `resource` through `api` unless it exists; returns whether it
+/// was created
+async fn create_missing<K>(api: Api<K>, resource: K) -> Result<bool>
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let name = resource.name_any();
+    if api.get_opt(&name).await?.is_some() {
+        debug!("{} {} exists, not restoring it", K::kind(&()), name);
+        return Ok(false);
+    }
+    api.create(&PostParams::default(), &resource)
+        .await
+        .with_context(|| format!("restoring {} {}", K::kind(&()), name))?;
+    info!("Restored {} {}", K::kind(&()), name);
+    Ok(true)
+}
+
+/// Run `router-controller restore <file>`
+pub async fn run_command(client: Client, path: &Path) -> Result<()> {
+    let backup: Backup = serde_json::from_slice(&tokio::fs::read(path).await?)
+        .with_context(|| format!("reading backup {}", path.display()))?;
+    let summary = restore(&client, &backup).await?;
+    println!(
+        "Restored {} resources from the backup taken at {}; {} already existed",
+        summary.created, backup.created_at, summary.existing
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let server = FakeApiServer::new();
+        let mut svc = VPCService::new("api", Default::default());
+        svc.metadata.namespace = Some("default".to_string());
+        svc.metadata.labels = Some([("team".to_string(), "payments".to_string())].into());
+        svc.spec.port = 8080;
+        server.insert(&svc);
+        let mut route = VPCRoute::new("api-route", Default::default());
+        route.metadata.namespace = Some("default".to_string());
+        server.insert(&route);
+        server.insert(&RouterConfig::new("default", Default::default()));
+
+        let backup = export(&server.client()).await.unwrap();
+        assert_eq!(backup.resources.len(), 3);
+        let exported = &backup.resources[0];
+        assert_eq!(exported["kind"], "VPCService");
+        assert_eq!(exported["metadata"]["labels"]["team"], "payments");
+        assert!(exported["metadata"].get("resourceVersion").is_none());
+        assert!(exported.get("status").is_none());
+
+        // Only what was deleted comes back
+        server.remove::<VPCService>("default", "api");
+        let summary = restore(&server.client(), &backup).await.unwrap();
+        assert_eq!(summary, RestoreSummary { created: 1, existing: 2 });
+        let restored: VPCService = server.get("default", "api").unwrap();
+        assert_eq!(restored.spec.port, 8080);
+    }
+
+    #[tokio::test]
+    async fn test_backups_are_pruned() {
+        let server = FakeApiServer::new();
+        let dir = std::env::temp_dir().join(format!("router-backup-test-{}", std::process::id()));
+        let job = BackupJob::new(server.client(), ControllerMetrics::new().unwrap(), dir.clone(), Duration::from_secs(60), 2);
+
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        for stamp in ["20260101T000000Z", "20260102T000000Z"] {
+            tokio::fs::write(dir.join(format!("{}{}.json", FILE_PREFIX, stamp)), "{}").await.unwrap();
+        }
+        let newest = job.backup().await.unwrap();
+
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![format!("{}20260102T000000Z.json", FILE_PREFIX), newest.file_name().unwrap().to_string_lossy().into_owned()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+//! Blue/green route switching
+//!
+//! Switches a VPCRoute's active destination set between its prepared blue
+//! and green sets in a single patch, after verifying the target set is
+//! healthy. Rolling back is a switch to the other color.
+
+use anyhow::{anyhow, Result};
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client, ResourceExt};
+use router_api::v1alpha1::vpc_route::{DeploymentColor, RouteDestination};
+use router_api::{VPCRoute, VPCService};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// Field manager used for blue/green patches
+const FIELD_MANAGER: &str = "router-controller-blue-green";
+
+/// Switches VPCRoutes between their blue and green destination sets
+pub struct BlueGreenSwitcher {
+    client: Client,
+}
+
+impl BlueGreenSwitcher {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Make `color` the active destination set of a route
+    ///
+    /// Fails without changing the route if any destination in the target
+    /// set is not ready. Returns the previously active color.
+    pub async fn switch(&self, namespace: &str, name: &str, color: DeploymentColor) -> Result<DeploymentColor> {
+        let routes: Api<VPCRoute> = Api::namespaced(self.client.clone(), namespace);
+        let route = routes.get(name).await?;
+
+        let blue_green = route
+            .spec
+            .blue_green
+            .as_ref()
+            .ok_or_else(|| anyhow!("VPCRoute {}/{} has no blueGreen configuration", namespace, name))?;
+        let previous = blue_green.active;
+
+        if previous == color {
+            info!("VPCRoute {}/{} is already serving {}", namespace, name, color.as_str());
+            return Ok(previous);
+        }
+
+        let target = blue_green.destinations(&color);
+        if target.is_empty() {
+            return Err(anyhow!("VPCRoute {}/{} has no {} destinations", namespace, name, color.as_str()));
+        }
+        self.verify_ready(namespace, target).await?;
+
+        // A single-field patch flips all destinations at once. The resource
+        // version makes it fail if the route changed since it was verified,
+        // rather than switch to destinations that were never checked.
+        let patch = active_patch(route.resource_version(), color);
+        match routes
+            .patch(name, &PatchParams::apply(FIELD_MANAGER), &Patch::Merge(&patch))
+            .await
+        {
+            Ok(_) => {}
+            Err(kube::Error::Api(e)) if e.code == 409 => {
+                return Err(anyhow!(
+                    "VPCRoute {}/{} was modified while switching to {}, not switched; retry",
+                    namespace,
+                    name,
+                    color.as_str()
+                ));
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        info!(
+            "Switched VPCRoute {}/{} from {} to {}",
+            namespace,
+            name,
+            previous.as_str(),
+            color.as_str()
+        );
+        Ok(previous)
+    }
+
+    /// Switch a route back to the color that is not currently active
+    pub async fn rollback(&self, namespace: &str, name: &str) -> Result<DeploymentColor> {
+        let routes: Api<VPCRoute> = Api::namespaced(self.client.clone(), namespace);
+        let route = routes.get(name).await?;
+        let active = route
+            .spec
+            .blue_green
+            .as_ref()
+            .map(|bg| bg.active)
+            .ok_or_else(|| anyhow!("VPCRoute {}/{} has no blueGreen configuration", namespace, name))?;
+
+        self.switch(namespace, name, active.other()).await
+    }
+
+    /// Check that every destination's VPCService is ready with endpoints
+    ///
+    /// Direct responses are served by the gateway and are always ready.
+    async fn verify_ready(&self, route_namespace: &str, destinations: &[RouteDestination]) -> Result<()> {
+        for destination in destinations.iter().filter(|d| !d.is_direct()) {
+            let service_ref = &destination.vpc_service_ref;
+            let namespace = service_ref.namespace.as_deref().unwrap_or(route_namespace);
+            let services: Api<VPCService> = Api::namespaced(self.client.clone(), namespace);
+
+            let service = services.get_opt(&service_ref.name).await?.ok_or_else(|| {
+                anyhow!("VPCService {}/{} not found", namespace, service_ref.name)
+            })?;
+
+            let healthy = service
+                .status
+                .as_ref()
+                .map(|status| status.ready && status.endpoint_count > 0)
+                .unwrap_or(false);
+            if !healthy {
+                warn!("VPCService {}/{} is not ready", namespace, service_ref.name);
+                return Err(anyhow!(
+                    "VPCService {}/{} is not ready, refusing to switch",
+                    namespace,
+                    service_ref.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merge patch making `color` active, guarded by the route's resource version
+fn active_patch(resource_version: Option<String>, color: DeploymentColor) -> Value {
+    json!({
+        "metadata": { "resourceVersion": resource_version },
+        "spec": { "blueGreen": { "active": color.as_str() } },
+    })
+}
+
+/// Run `router-controller switch <namespace>/<route> <blue|green>`, or
+/// `router-controller rollback <namespace>/<route>` without a color
+pub async fn run_command(client: Client, route: &str, color: Option<DeploymentColor>) -> Result<()> {
+    let switcher = BlueGreenSwitcher::new(client);
+    let (namespace, name) = route
+        .split_once('/')
+        .ok_or_else(|| anyhow!("route must be given as <namespace>/<name>, got {}", route))?;
+
+    let previous = match color {
+        Some(color) => switcher.switch(namespace, name, color).await?,
+        None => switcher.rollback(namespace, name).await?,
+    };
+
+    println!("{}/{}: previously serving {}", namespace, name, previous.as_str());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_patch_carries_resource_version() {
+        let patch = active_patch(Some("42".to_string()), DeploymentColor::Green);
+        assert_eq!(patch["metadata"]["resourceVersion"], "42");
+        assert_eq!(patch["spec"]["blueGreen"]["active"], "green");
+    }
+}
@@ -0,0 +1,205 @@
+//! Command-line flags and admin subcommands
+//!
+//! Without a subcommand the controller runs its reconcilers. Every flag can
+//! also be set through the environment variable shown in `--help`.
+
+use crate::gateways::GatewayService;
+use clap::builder::{BoolishValueParser, PossibleValuesParser, TypedValueParser};
+use clap::{Parser, Subcommand};
+use router_api::v1alpha1::vpc_route::DeploymentColor;
+use router_core::cli::{positive_rate, KubeArgs, LogArgs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Reconciles router resources and rolls out gateway configuration
+#[derive(Debug, Parser)]
+#[command(name = "router-controller", version, long_version = router_core::long_version!())]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Port serving /metrics, /healthz, and /ready
+    #[arg(long, env = "ROUTER_CONTROLLER_METRICS_PORT", value_name = "PORT", default_value_t = 8080,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    pub metrics_port: u16,
+
+    /// Log status and registry changes instead of applying them
+    #[arg(long, env = "ROUTER_CONTROLLER_DRY_RUN", num_args = 0..=1, default_missing_value = "true",
+          default_value_t = false, value_parser = BoolishValueParser::new())]
+    pub dry_run: bool,
+
+    /// Sustained status writes per second across all reconcilers
+    #[arg(long, env = "ROUTER_STATUS_WRITE_QPS", value_name = "QPS", default_value_t = 5.0, value_parser = positive_rate)]
+    pub status_write_qps: f64,
+
+    /// Status writes allowed at once after an idle period
+    #[arg(long, env = "ROUTER_STATUS_WRITE_BURST", value_name = "WRITES", default_value_t = 10,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub status_write_burst: u32,
+
+    /// RouterConfig the gateways use
+    #[arg(long, env = "ROUTER_CONFIG_NAME", value_name = "NAME", default_value = "default")]
+    pub config_name: String,
+
+    /// Bearer token gateways require on config pushes
+    #[arg(long, env = "ROUTER_CONFIG_TOKEN", value_name = "TOKEN", hide_env_values = true)]
+    pub config_token: Option<String>,
+
+    /// Gateway Service as namespace/name; enables config rollout and
+    /// endpoint stats aggregation, otherwise the RouterConfig is only validated
+    #[arg(long, env = "ROUTER_GATEWAY_SERVICE", value_name = "NAMESPACE/NAME")]
+    pub gateway_service: Option<GatewayService>,
+
+    /// Port serving /endpoint-stats and /config on gateway pods
+    #[arg(long, env = "ROUTER_GATEWAY_STATS_PORT", value_name = "PORT", default_value_t = 8080,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    pub gateway_stats_port: u16,
+
+    /// Seconds between endpoint stats collections from the gateways
+    #[arg(long, env = "ROUTER_ENDPOINT_STATS_INTERVAL_SECS", value_name = "SECS", default_value_t = 30,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub endpoint_stats_interval_secs: u64,
+
+    /// Seconds between full resyncs removing registry entries of deleted VPCServices
+    #[arg(long, env = "ROUTER_RESYNC_INTERVAL_SECS", value_name = "SECS", default_value_t = 300,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub resync_interval_secs: u64,
+
+    /// Directory router resource backups are written to; enables backups
+    #[arg(long, env = "ROUTER_BACKUP_DIR", value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Seconds between backups
+    #[arg(long, env = "ROUTER_BACKUP_INTERVAL_SECS", value_name = "SECS", default_value_t = 3600,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub backup_interval_secs: u64,
+
+    /// Backups kept, newest first
+    #[arg(long, env = "ROUTER_BACKUP_KEEP", value_name = "COUNT", default_value_t = 24,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub backup_keep: u64,
+
+    #[command(flatten)]
+    pub kube: KubeArgs,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+/// Admin commands, run once against the cluster
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Make a VPCRoute's blue or green destination set active
+    Switch {
+        /// Route as namespace/name
+        #[arg(value_name = "NAMESPACE/ROUTE", value_parser = namespaced_name)]
+        route: String,
+        /// Destination set to serve
+        #[arg(value_parser = PossibleValuesParser::new(["blue", "green"]).map(|color| {
+            if color == "blue" { DeploymentColor::Blue } else { DeploymentColor::Green }
+        }))]
+        color: DeploymentColor,
+    },
+    /// Switch a VPCRoute back to the destination set it served before
+    Rollback {
+        /// Route as namespace/name
+        #[arg(value_name = "NAMESPACE/ROUTE", value_parser = namespaced_name)]
+        route: String,
+    },
+    /// Recreate router resources missing from the cluster from a backup
+    Restore {
+        /// Backup file written by the controller
+        file: PathBuf,
+    },
+}
+
+impl Cli {
+    pub fn endpoint_stats_interval(&self) -> Duration {
+        Duration::from_secs(self.endpoint_stats_interval_secs)
+    }
+
+    pub fn resync_interval(&self) -> Duration {
+        Duration::from_secs(self.resync_interval_secs)
+    }
+
+    pub fn backup_interval(&self) -> Duration {
+        Duration::from_secs(self.backup_interval_secs)
+    }
+}
+
+/// Check a `namespace/name` reference
+fn namespaced_name(value: &str) -> Result<String, String> {
+    match value.split_once('/') {
+        Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(value.to_string())
+        }
+        _ => Err("must be given as <namespace>/<name>".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("router-controller").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_command_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let cli = parse(&[]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.metrics_port, 8080);
+        assert!(!cli.dry_run);
+        assert_eq!(cli.config_name, "default");
+        assert!(cli.gateway_service.is_none());
+        assert_eq!(cli.resync_interval(), Duration::from_secs(300));
+
+        let cli = parse(&["--dry-run", "--gateway-service", "router/gateway", "--backup-dir", "/backups"]).unwrap();
+        assert!(cli.dry_run);
+        let service = cli.gateway_service.unwrap();
+        assert_eq!((service.namespace.as_str(), service.name.as_str()), ("router", "gateway"));
+        assert_eq!(cli.backup_dir, Some(PathBuf::from("/backups")));
+        assert!(!parse(&["--dry-run=false"]).unwrap().dry_run);
+
+        // Errors name the flag
+        for args in [
+            ["--metrics-port", "0"],
+            ["--status-write-qps", "0"],
+            ["--gateway-service", "gateway"],
+            ["--resync-interval-secs", "soon"],
+        ] {
+            let error = parse(&args).unwrap_err().to_string();
+            assert!(error.contains(args[0]), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        match parse(&["switch", "default/web", "green"]).unwrap().command {
+            Some(Command::Switch { route, color }) => {
+                assert_eq!(route, "default/web");
+                assert_eq!(color, DeploymentColor::Green);
+            }
+            other => panic!("unexpected command {:?}", other),
+        }
+        assert!(matches!(
+            parse(&["rollback", "default/web"]).unwrap().command,
+            Some(Command::Rollback { route }) if route == "default/web"
+        ));
+        assert!(matches!(
+            parse(&["restore", "/backups/latest.json"]).unwrap().command,
+            Some(Command::Restore { file }) if file.as_path() == std::path::Path::new("/backups/latest.json")
+        ));
+
+        assert!(parse(&["switch", "default/web", "purple"]).is_err());
+        assert!(parse(&["switch", "web", "blue"]).is_err());
+        assert!(parse(&["rollback"]).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Deprecated spec values, reported in status and as events
+//!
+//! Resources using values v1alpha2 won't accept get a `Deprecated`
+//! condition listing them, and a Warning event whenever the list changes,
+//! so `kubectl describe` shows what to migrate. Once the values are fixed
+//! the condition turns False; resources that never used any get none.
+
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use kube::{Api, Client, Resource, ResourceExt};
+use router_api::v1alpha1::deprecation::{summarize, Deprecation, DEPRECATED_CONDITION};
+use router_api::v1alpha1::vpc_service::Condition;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Debug;
+use tracing::{debug, warn};
+
+use crate::status_writer::StatusWriter;
+
+/// Controller name events are reported under
+const REPORTER: &str = "router-controller";
+
+/// Record `deprecations` in `resource`'s conditions, written through `api`
+///
+/// `conditions` are the resource's current conditions. Returns them as
+/// they are after the update, so later writes of the same list keep it.
+pub async fn report<K>(
+    client: &Client,
+    writer: &StatusWriter,
+    api: &Api<K>,
+    resource: &K,
+    conditions: &[Condition],
+    deprecations: &[Deprecation],
+) -> kube::Result<Vec<Condition>>
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let message = (!deprecations.is_empty()).then(|| summarize(deprecations));
+    let status = if message.is_some() { "True" } else { "False" };
+    let current = conditions.iter().find(|c| c.condition_type == DEPRECATED_CONDITION);
+    let unchanged = match current {
+        Some(condition) => condition.status == status && condition.message == message,
+        // Nothing to clear on resources that never used deprecated values
+        None => message.is_none(),
+    };
+    if unchanged {
+        return Ok(conditions.to_vec());
+    }
+
+    let mut updated: Vec<Condition> = conditions
+        .iter()
+        .filter(|c| c.condition_type != DEPRECATED_CONDITION)
+        .cloned()
+        .collect();
+    updated.push(Condition {
+        condition_type: DEPRECATED_CONDITION.to_string(),
+        status: status.to_string(),
+        reason: Some(if message.is_some() { "DeprecatedFields" } else { "NoDeprecatedFields" }.to_string()),
+        message: message.clone(),
+        last_update_time: Some(chrono::Utc::now().to_rfc3339()),
+    });
+    writer
+        .apply_status(api, resource, "deprecations", json!({ "conditions": updated }))
+        .await?;
+
+    if let Some(message) = message {
+        let kind = K::kind(&()).to_string();
+        let name = format!("{}/{}", resource.namespace().unwrap_or_default(), resource.name_any());
+        warn!("{} {} uses deprecated values: {}", kind, name, message);
+        if writer.dry_run() {
+            writer.skipped(&kind, &format!("publish a Deprecated warning event for {} {}", kind, name));
+        } else {
+            let reporter = Reporter { controller: REPORTER.to_string(), instance: None };
+            let recorder = Recorder::new(client.clone(), reporter, resource.object_ref(&()));
+            let event = Event {
+                type_: EventType::Warning,
+                reason: "Deprecated".to_string(),
+                note: Some(message),
+                action: "Reconcile".to_string(),
+                secondary: None,
+            };
+            // The condition already carries the warning
+            if let Err(e) = recorder.publish(event).await {
+                debug!("Failed to publish deprecation event for {} {}: {}", kind, name, e);
+            }
+        }
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+    use crate::metrics::ControllerMetrics;
+    use crate::status_writer::WriteRateLimit;
+    use router_api::VPCService;
+
+    #[tokio::test]
+    async fn test_report_deprecations() {
+        let server = FakeApiServer::new();
+        let writer = StatusWriter::new(false, ControllerMetrics::new().unwrap(), WriteRateLimit::default());
+        let mut svc = VPCService::new("api", Default::default());
+        svc.metadata.namespace = Some("default".to_string());
+        svc.spec.protocol = "grpc".to_string();
+        server.insert(&svc);
+        let services: Api<VPCService> = Api::namespaced(server.client(), "default");
+
+        let deprecations = svc.spec.deprecations();
+        let conditions = report(&server.client(), &writer, &services, &svc, &[], &deprecations).await.unwrap();
+        assert_eq!(conditions[0].status, "True");
+        let stored: VPCService = server.get("default", "api").unwrap();
+        let condition = &stored.status.as_ref().unwrap().conditions[0];
+        assert_eq!(condition.condition_type, DEPRECATED_CONDITION);
+        assert!(condition.message.as_deref().unwrap().starts_with("spec.protocol: "));
+        assert_eq!(server.count("POST", "/events"), 1);
+
+        // Unchanged deprecations are neither written nor announced again
+        report(&server.client(), &writer, &services, &stored, &conditions, &deprecations).await.unwrap();
+        assert_eq!(server.count("PATCH", "/status"), 1);
+        assert_eq!(server.count("POST", "/events"), 1);
+
+        // Fixing the spec clears the condition without another event
+        let conditions = report(&server.client(), &writer, &services, &stored, &conditions, &[]).await.unwrap();
+        assert_eq!(conditions[0].status, "False");
+        assert_eq!(server.count("POST", "/events"), 1);
+
+        // Nothing is written for resources that never had deprecations
+        svc.metadata.name = Some("web".to_string());
+        assert!(report(&server.client(), &writer, &services, &svc, &[], &[]).await.unwrap().is_empty());
+        assert_eq!(server.count("PATCH", "/status"), 2);
+    }
+}
@@ -0,0 +1,134 @@
+//! Publishes live endpoint traffic statistics in VPCService status
+//!
+//! Every interval the controller collects the per-endpoint counters of
+//! each gateway replica (listed from the gateway Service's Endpoints), sums
+//! them, and writes them to the matching endpoints in VPCService status.
+
+use anyhow::Result;
+use kube::api::ListParams;
+use kube::{Api, Client, ResourceExt};
+use router_api::v1alpha1::vpc_service::EndpointStats;
+use router_api::VPCService;
+use router_core::stats::{self, EndpointCounterMap};
+use serde_json::json;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::gateways::GatewayService;
+use crate::status_writer::StatusWriter;
+
+/// Collects endpoint statistics from gateway replicas
+pub struct EndpointStatsAggregator {
+    client: Client,
+    http: reqwest::Client,
+    gateway_service: GatewayService,
+    stats_port: u16,
+    interval: Duration,
+    writer: StatusWriter,
+    /// Totals from the previous collection, for interval error rates
+    previous: EndpointCounterMap,
+}
+
+impl EndpointStatsAggregator {
+    /// Create an aggregator for the gateway Service
+    pub fn new(
+        client: Client,
+        gateway_service: GatewayService,
+        stats_port: u16,
+        interval: Duration,
+        writer: StatusWriter,
+    ) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?;
+
+        Ok(Self {
+            client,
+            http,
+            gateway_service,
+            stats_port,
+            interval,
+            writer,
+            previous: EndpointCounterMap::new(),
+        })
+    }
+
+    /// Collect and publish statistics until the process exits
+    pub async fn run(mut self) {
+        info!("Aggregating endpoint stats from {} every {:?}", self.gateway_service, self.interval);
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.collect_and_publish().await {
+                warn!("Failed to publish endpoint stats: {}", e);
+            }
+        }
+    }
+
+    async fn collect_and_publish(&mut self) -> Result<()> {
+        let totals = stats::aggregate(self.collect().await?);
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let services: Api<VPCService> = Api::all(self.client.clone());
+        for vpc_svc in services.list(&ListParams::default()).await? {
+            let Some(status) = &vpc_svc.status else { continue };
+            if status.endpoints.is_empty() {
+                continue;
+            }
+
+            let mut endpoints = status.endpoints.clone();
+            let mut changed = false;
+            for endpoint in &mut endpoints {
+                let address = format!("{}:{}", endpoint.ip, endpoint.port);
+                let Some(counters) = totals.get(&address) else { continue };
+                endpoint.stats = Some(EndpointStats {
+                    active_connections: counters.active_connections,
+                    total_requests: counters.requests,
+                    error_rate: counters.error_rate_since(self.previous.get(&address)),
+                    bytes_received: counters.bytes_received,
+                    bytes_sent: counters.bytes_sent,
+                    last_update_time: Some(now.clone()),
+                });
+                changed = true;
+            }
+            if !changed {
+                continue;
+            }
+
+            let namespace = vpc_svc.namespace().unwrap_or_else(|| "default".to_string());
+            let api: Api<VPCService> = Api::namespaced(self.client.clone(), &namespace);
+            let status = json!({ "endpoints": endpoints });
+            if let Err(e) = self.writer.apply_status(&api, &vpc_svc, "endpoint-stats", status).await {
+                warn!("Failed to update endpoint stats for {}/{}: {}", namespace, vpc_svc.name_any(), e);
+            }
+        }
+
+        self.previous = totals;
+        Ok(())
+    }
+
+    /// Fetch the counters of every ready gateway replica
+    ///
+    /// Unreachable replicas are skipped; their traffic is missing from
+    /// this interval only.
+    async fn collect(&self) -> Result<Vec<EndpointCounterMap>> {
+        let addresses = self.gateway_service.replicas(&self.client).await?.ready;
+
+        let mut reports = Vec::with_capacity(addresses.len());
+        for ip in addresses {
+            let url = format!("http://{}:{}/endpoint-stats", ip, self.stats_port);
+            let report = async {
+                self.http
+                    .get(&url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<EndpointCounterMap>()
+                    .await
+            };
+            match report.await {
+                Ok(report) => reports.push(report),
+                Err(e) => debug!("Skipping gateway {}: {}", ip, e),
+            }
+        }
+        Ok(reports)
+    }
+}
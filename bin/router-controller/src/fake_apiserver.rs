@@ -0,0 +1,354 @@
+//! In-memory stand-in for the Kubernetes API server
+//!
+//! Reconcile logic is tested by handing it a [`kube::Client`] backed by
+//! [`FakeApiServer`] instead of a cluster. Objects are kept as JSON by URL
+//! path and the server answers the calls the controllers make: get, list,
+//! create (including generated names), delete, and status patches
+//! (server-side apply and merge). Watches are not served, so tests call the
+//! reconcile functions directly rather than running a whole controller.
+//!
+//! Status applies merge the applied fields into the stored status; field
+//! ownership is not tracked, so fields dropped from an apply are kept.
+//! Requests with `dryRun=All` are answered without persisting anything.
+
+use futures::future::BoxFuture;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, Response, StatusCode};
+use kube::client::Body;
+use kube::{Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Object store and request log shared by every client clone
+#[derive(Default)]
+struct State {
+    /// Objects by URL path, e.g. `/apis/g/v/namespaces/ns/plural/name`
+    objects: BTreeMap<String, Value>,
+    /// Requests received, as `METHOD path`
+    requests: Vec<String>,
+    /// Last resourceVersion handed out
+    resource_version: u64,
+}
+
+/// Fake API server; clones share the same objects
+#[derive(Clone, Default)]
+pub struct FakeApiServer {
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeApiServer {
+    /// Create an empty server
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A client whose requests are served by this server
+    pub fn client(&self) -> Client {
+        Client::new(self.clone(), "default")
+    }
+
+    /// Store an object, replacing any with the same name
+    pub fn insert<K>(&self, resource: &K)
+    where
+        K: Resource<DynamicType = ()> + Serialize,
+    {
+        let path = object_path(resource);
+        let mut object = serde_json::to_value(resource).expect("resource serializes");
+        let mut state = self.state.lock().unwrap();
+        state.resource_version += 1;
+        object["metadata"]["resourceVersion"] = json!(state.resource_version.to_string());
+        state.objects.insert(path, object);
+    }
+
+    /// The stored copy of an object
+    pub fn get<K>(&self, namespace: &str, name: &str) -> Option<K>
+    where
+        K: Resource<DynamicType = ()> + DeserializeOwned,
+    {
+        let path = format!("{}/{}", K::url_path(&(), Some(namespace)), name);
+        let state = self.state.lock().unwrap();
+        state
+            .objects
+            .get(&path)
+            .map(|object| serde_json::from_value(object.clone()).expect("stored object deserializes"))
+    }
+
+    /// Remove an object, as if it was deleted behind the controller's back
+    pub fn remove<K>(&self, namespace: &str, name: &str)
+    where
+        K: Resource<DynamicType = ()>,
+    {
+        let path = format!("{}/{}", K::url_path(&(), Some(namespace)), name);
+        self.state.lock().unwrap().objects.remove(&path);
+    }
+
+    /// Requests received so far, as `METHOD path` without the query
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Number of requests received with `method` whose path ends in `suffix`
+    pub fn count(&self, method: &str, suffix: &str) -> usize {
+        let prefix = format!("{} ", method);
+        self.requests()
+            .iter()
+            .filter(|r| r.starts_with(&prefix) && r.ends_with(suffix))
+            .count()
+    }
+
+    /// Answer one request
+    fn handle(&self, method: &Method, path: &str, query: &str, body: &[u8]) -> (StatusCode, Value) {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(format!("{} {}", method, path));
+        let dry_run = query.split('&').any(|p| p == "dryRun=All");
+
+        let (path, subresource) = match path.strip_suffix("/status") {
+            Some(object) => (object, Some("status")),
+            None => (path, None),
+        };
+
+        match *method {
+            Method::GET => {
+                if let Some(object) = state.objects.get(path) {
+                    return (StatusCode::OK, object.clone());
+                }
+                match list(&state.objects, path) {
+                    Some(items) => (
+                        StatusCode::OK,
+                        json!({
+                            "apiVersion": "v1",
+                            "kind": "List",
+                            "metadata": { "resourceVersion": state.resource_version.to_string() },
+                            "items": items,
+                        }),
+                    ),
+                    None => not_found(path),
+                }
+            }
+            Method::POST => {
+                let Ok(mut object) = serde_json::from_slice::<Value>(body) else {
+                    return failure(StatusCode::BAD_REQUEST, "BadRequest", "invalid object");
+                };
+                // Generated names end in the next resourceVersion, which is unique
+                let generated = object["metadata"]["generateName"]
+                    .as_str()
+                    .map(|prefix| format!("{}{}", prefix, state.resource_version + 1));
+                let Some(name) = object["metadata"]["name"].as_str().map(str::to_string).or(generated) else {
+                    return failure(StatusCode::UNPROCESSABLE_ENTITY, "Invalid", "metadata.name is required");
+                };
+                object["metadata"]["name"] = json!(name);
+                let object_path = format!("{}/{}", path, name);
+                if state.objects.contains_key(&object_path) {
+                    return failure(StatusCode::CONFLICT, "AlreadyExists", &format!("{} already exists", name));
+                }
+                if let Some(namespace) = namespace_of(path) {
+                    object["metadata"]["namespace"] = json!(namespace);
+                }
+                if !dry_run {
+                    state.resource_version += 1;
+                    object["metadata"]["resourceVersion"] = json!(state.resource_version.to_string());
+                    state.objects.insert(object_path, object.clone());
+                }
+                (StatusCode::CREATED, object)
+            }
+            Method::PATCH => {
+                let Some(mut object) = state.objects.get(path).cloned() else {
+                    return not_found(path);
+                };
+                let Ok(patch) = serde_json::from_slice::<Value>(body) else {
+                    return failure(StatusCode::BAD_REQUEST, "BadRequest", "invalid patch");
+                };
+                match subresource {
+                    Some(_) => merge(&mut object["status"], &patch["status"]),
+                    None => {
+                        // Status only changes through the subresource
+                        let mut spec_patch = patch.clone();
+                        if let Some(fields) = spec_patch.as_object_mut() {
+                            fields.remove("status");
+                        }
+                        merge(&mut object, &spec_patch);
+                    }
+                }
+                if !dry_run {
+                    state.resource_version += 1;
+                    object["metadata"]["resourceVersion"] = json!(state.resource_version.to_string());
+                    state.objects.insert(path.to_string(), object.clone());
+                }
+                (StatusCode::OK, object)
+            }
+            Method::DELETE => {
+                let removed = if dry_run {
+                    state.objects.get(path).cloned()
+                } else {
+                    state.objects.remove(path)
+                };
+                match removed {
+                    Some(object) => (StatusCode::OK, object),
+                    None => not_found(path),
+                }
+            }
+            _ => failure(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "method not supported"),
+        }
+    }
+}
+
+impl tower::Service<Request<Body>> for FakeApiServer {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let server = self.clone();
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let body = body.collect().await.map(|b| b.to_bytes()).unwrap_or_default();
+            let path = parts.uri.path().to_string();
+            let query = parts.uri.query().unwrap_or_default().to_string();
+
+            let (status, value) = server.handle(&parts.method, &path, &query, &body);
+            let response = Response::builder()
+                .status(status)
+                .header("content-type", "application/json")
+                .body(Full::new(Bytes::from(value.to_string())))
+                .expect("valid response");
+            Ok(response)
+        })
+    }
+}
+
+/// URL path of an object
+fn object_path<K>(resource: &K) -> String
+where
+    K: Resource<DynamicType = ()>,
+{
+    let namespace = resource.namespace();
+    format!("{}/{}", K::url_path(&(), namespace.as_deref()), resource.name_any())
+}
+
+/// Objects directly under a collection path, or None if it isn't one
+///
+/// Cluster-wide paths (`/apis/g/v/plural`) list every namespace.
+fn list(objects: &BTreeMap<String, Value>, path: &str) -> Option<Vec<Value>> {
+    let (group_version, plural) = collection(path)?;
+    let namespace = namespace_of(path);
+    let items = objects
+        .iter()
+        .filter(|(object_path, _)| {
+            collection(parent(object_path)).is_some_and(|(gv, p)| gv == group_version && p == plural)
+                && (namespace.is_none() || namespace_of(object_path) == namespace)
+        })
+        .map(|(_, object)| object.clone())
+        .collect();
+    Some(items)
+}
+
+/// Group/version prefix and resource plural of a collection path
+fn collection(path: &str) -> Option<(&str, &str)> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let version_end = match segments.first() {
+        Some(&"api") => 2,
+        Some(&"apis") => 3,
+        _ => return None,
+    };
+    let rest = segments.get(version_end..)?;
+    let plural = match rest {
+        [plural] => plural,
+        ["namespaces", _, plural] => plural,
+        _ => return None,
+    };
+    let prefix_len = segments[..version_end].iter().map(|s| s.len() + 1).sum::<usize>();
+    Some((&path[..prefix_len], plural))
+}
+
+/// Path of the collection holding an object
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or(path)
+}
+
+/// Namespace named in a path
+fn namespace_of(path: &str) -> Option<&str> {
+    let mut segments = path.split('/');
+    segments.find(|s| *s == "namespaces")?;
+    segments.next()
+}
+
+/// Merge `patch` into `target`, JSON merge patch style
+fn merge(target: &mut Value, patch: &Value) {
+    match (target.as_object_mut(), patch.as_object()) {
+        (Some(target), Some(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(key);
+                } else {
+                    merge(target.entry(key.clone()).or_insert(Value::Null), value);
+                }
+            }
+        }
+        _ if patch.is_null() => {}
+        _ => *target = patch.clone(),
+    }
+}
+
+/// A NotFound Status
+fn not_found(path: &str) -> (StatusCode, Value) {
+    failure(StatusCode::NOT_FOUND, "NotFound", &format!("{} not found", path))
+}
+
+/// A failure Status as the API server returns it
+fn failure(code: StatusCode, reason: &str, message: &str) -> (StatusCode, Value) {
+    (
+        code,
+        json!({
+            "apiVersion": "v1",
+            "kind": "Status",
+            "metadata": {},
+            "status": "Failure",
+            "message": message,
+            "reason": reason,
+            "code": code.as_u16(),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::api::{ListParams, PostParams};
+    use kube::Api;
+    use router_api::VPCService;
+
+    fn service(namespace: &str, name: &str) -> VPCService {
+        let mut svc = VPCService::new(name, Default::default());
+        svc.metadata.namespace = Some(namespace.to_string());
+        svc
+    }
+
+    #[tokio::test]
+    async fn test_get_list_and_create() {
+        let server = FakeApiServer::new();
+        server.insert(&service("default", "api"));
+        server.insert(&service("other", "web"));
+
+        let default_api: Api<VPCService> = Api::namespaced(server.client(), "default");
+        assert_eq!(default_api.get("api").await.unwrap().name_any(), "api");
+        assert!(default_api.get_opt("web").await.unwrap().is_none());
+
+        let all: Api<VPCService> = Api::all(server.client());
+        assert_eq!(all.list(&ListParams::default()).await.unwrap().items.len(), 2);
+        assert_eq!(default_api.list(&ListParams::default()).await.unwrap().items.len(), 1);
+
+        default_api.create(&PostParams::default(), &service("default", "new")).await.unwrap();
+        assert!(server.get::<VPCService>("default", "new").is_some());
+        assert!(default_api.create(&PostParams::default(), &service("default", "new")).await.is_err());
+    }
+}
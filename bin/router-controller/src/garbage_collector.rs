@@ -0,0 +1,121 @@
+//! Garbage collection of orphaned registry entries
+//!
+//! The registry is filled from reconcile events, but a VPCService deleted
+//! while the controller was down or disconnected never produces one. A
+//! periodic full resync lists every VPCService and drops registry entries
+//! that no longer have an owning resource.
+
+use anyhow::Result;
+use kube::api::ListParams;
+use kube::{Api, Client, ResourceExt};
+use router_api::VPCService;
+use router_core::ServiceRegistry;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::metrics::ControllerMetrics;
+
+/// Removes registry entries whose owning VPCService is gone
+pub struct OrphanCollector {
+    client: Client,
+    registry: Arc<ServiceRegistry>,
+    metrics: ControllerMetrics,
+    interval: Duration,
+}
+
+impl OrphanCollector {
+    /// Create a collector resyncing every `interval`
+    pub fn new(client: Client, registry: Arc<ServiceRegistry>, metrics: ControllerMetrics, interval: Duration) -> Self {
+        Self {
+            client,
+            registry,
+            metrics,
+            interval,
+        }
+    }
+
+    /// Resync until the process exits
+    pub async fn run(self) {
+        info!("Collecting orphaned registry entries every {:?}", self.interval);
+        let mut ticker = tokio::time::interval(self.interval);
+        // The first tick fires immediately; give the controllers a chance
+        // to populate the registry first
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match self.collect().await {
+                Ok(removed) => {
+                    self.metrics.orphan_gc_runs_total.with_label_values(&["success"]).inc();
+                    if removed > 0 {
+                        info!("Removed {} orphaned registry entries", removed);
+                    } else {
+                        debug!("No orphaned registry entries");
+                    }
+                }
+                Err(e) => {
+                    self.metrics.orphan_gc_runs_total.with_label_values(&["error"]).inc();
+                    warn!("Orphan collection failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Run one full resync and return how many entries were removed
+    ///
+    /// A failed list removes nothing, so an API outage never empties the
+    /// registry.
+    async fn collect(&self) -> Result<usize> {
+        let services: Api<VPCService> = Api::all(self.client.clone());
+        let live: HashSet<String> = services
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .map(|svc| format!("{}/{}", svc.namespace().unwrap_or_else(|| "default".to_string()), svc.name_any()))
+            .collect();
+
+        let orphans = self.registry.prune_services(&live).await;
+        for service_id in &orphans {
+            info!("Removed orphaned service {} from registry", service_id);
+        }
+        self.metrics
+            .orphans_collected_total
+            .with_label_values(&["service"])
+            .inc_by(orphans.len() as u64);
+        Ok(orphans.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+
+    #[tokio::test]
+    async fn test_collect_removes_deleted_services() {
+        let server = FakeApiServer::new();
+        let registry = Arc::new(ServiceRegistry::new());
+        for name in ["api", "web"] {
+            let mut svc = VPCService::new(name, Default::default());
+            svc.metadata.namespace = Some("default".to_string());
+            server.insert(&svc);
+            registry
+                .register_service("default".into(), name.into(), 80, "HTTP".into(), Vec::new())
+                .await
+                .unwrap();
+        }
+
+        let collector = OrphanCollector::new(
+            server.client(),
+            registry.clone(),
+            ControllerMetrics::new().unwrap(),
+            Duration::from_secs(300),
+        );
+        assert_eq!(collector.collect().await.unwrap(), 0);
+
+        server.remove::<VPCService>("default", "web");
+        assert_eq!(collector.collect().await.unwrap(), 1);
+        assert_eq!(registry.service_count().await, 1);
+    }
+}
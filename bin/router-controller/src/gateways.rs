@@ -0,0 +1,58 @@
+//! Gateway replicas, as listed in the gateway Service's Endpoints
+
+use anyhow::{Result, anyhow};
+use k8s_openapi::api::core::v1::Endpoints;
+use kube::{Api, Client};
+use std::fmt;
+use std::str::FromStr;
+
+/// The Service in front of the gateway replicas
+#[derive(Clone, Debug)]
+pub struct GatewayService {
+    pub namespace: String,
+    pub name: String,
+}
+
+/// Pod IPs of the gateway replicas
+#[derive(Clone, Debug, Default)]
+pub struct GatewayReplicas {
+    /// Replicas passing their readiness probe
+    pub ready: Vec<String>,
+    /// Replicas starting, draining, or failing their readiness probe
+    pub not_ready: Vec<String>,
+}
+
+impl GatewayService {
+    /// Parse a `namespace/name` reference
+    pub fn parse(service: &str) -> Result<Self> {
+        let (namespace, name) = service
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Gateway service must be namespace/name, got {}", service))?;
+        Ok(Self { namespace: namespace.to_string(), name: name.to_string() })
+    }
+
+    /// List the replicas behind the Service
+    pub async fn replicas(&self, client: &Client) -> Result<GatewayReplicas> {
+        let endpoints: Api<Endpoints> = Api::namespaced(client.clone(), &self.namespace);
+        let mut replicas = GatewayReplicas::default();
+        for subset in endpoints.get(&self.name).await?.subsets.unwrap_or_default() {
+            replicas.ready.extend(subset.addresses.unwrap_or_default().into_iter().map(|a| a.ip));
+            replicas.not_ready.extend(subset.not_ready_addresses.unwrap_or_default().into_iter().map(|a| a.ip));
+        }
+        Ok(replicas)
+    }
+}
+
+impl FromStr for GatewayService {
+    type Err = anyhow::Error;
+
+    fn from_str(service: &str) -> Result<Self> {
+        Self::parse(service)
+    }
+}
+
+impl fmt::Display for GatewayService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.namespace, self.name)
+    }
+}
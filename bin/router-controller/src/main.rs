@@ -1,26 +1,92 @@
 use anyhow::Result;
-use kube::Client;
+use clap::Parser;
 use tracing::{info, error};
-use tracing_subscriber::fmt::init as tracing_init;
 
+mod cli;
 mod vpc_service_controller;
 mod vpc_route_controller;
 mod vpc_ingress_controller;
+mod backup;
+mod blue_green;
+mod deprecations;
+mod endpoint_stats;
+mod gateways;
+mod garbage_collector;
+mod metrics;
+mod programmed;
+mod router_config_controller;
+mod status_writer;
+#[cfg(test)]
+mod fake_apiserver;
 
 use vpc_service_controller::VPCServiceController;
 use vpc_route_controller::VPCRouteController;
 use vpc_ingress_controller::VPCIngressController;
+use endpoint_stats::EndpointStatsAggregator;
+use router_config_controller::{GatewayAdmin, RouterConfigController};
+use backup::BackupJob;
+use garbage_collector::OrphanCollector;
+use metrics::ControllerMetrics;
+use cli::{Cli, Command};
+use router_core::kube_client::LoggingApiObserver;
+use router_core::{ServiceRegistry, WatchObserver};
+use status_writer::{StatusWriter, WriteRateLimit};
+use std::net::SocketAddr;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_init();
+    let cli = Cli::parse();
+    cli.log.init();
+    let kube_config = cli.kube.client_config("router-controller");
 
-    info!("Starting router-controller...");
+    // Admin commands (e.g. blue/green switch) run once and exit
+    match &cli.command {
+        Some(Command::Switch { route, color }) => {
+            let client = kube_config.build(Arc::new(LoggingApiObserver)).await?;
+            return blue_green::run_command(client, route, Some(*color)).await;
+        }
+        Some(Command::Rollback { route }) => {
+            let client = kube_config.build(Arc::new(LoggingApiObserver)).await?;
+            return blue_green::run_command(client, route, None).await;
+        }
+        Some(Command::Restore { file }) => {
+            let client = kube_config.build(Arc::new(LoggingApiObserver)).await?;
+            return backup::run_command(client, file).await;
+        }
+        None => {}
+    }
+
+    info!("Starting router-controller {}...", metrics::BUILD_INFO);
+
+    let registry = Arc::new(ServiceRegistry::new());
+
+    // Serve metrics and health probes
+    let metrics = ControllerMetrics::new()?;
+    let client = kube_config.build(Arc::new(metrics.clone())).await?;
+    let metrics_server = metrics.clone();
+    let metrics_addr = SocketAddr::from(([0, 0, 0, 0], cli.metrics_port));
+    tokio::spawn(async move {
+        if let Err(e) = metrics_server.serve(metrics_addr).await {
+            error!("Metrics server error: {}", e);
+        }
+    });
+
+    // Reconcile without persisting anything, logging the changes instead
+    if cli.dry_run {
+        info!("Dry-run mode: status and registry changes are logged, not applied");
+    }
 
-    let client = Client::try_default().await?;
+    // Status writes per second across all reconcilers
+    let rate_limit = WriteRateLimit {
+        qps: cli.status_write_qps,
+        burst: cli.status_write_burst,
+    };
+    let writer = StatusWriter::new(cli.dry_run, metrics.clone(), rate_limit);
+    let watch_observer: Arc<dyn WatchObserver> = Arc::new(metrics.clone());
 
     // Start VPCService reconciliation controller
-    let vpc_service_controller = VPCServiceController::new(client.clone()).await?;
+    let vpc_service_controller = VPCServiceController::new(client.clone(), watch_observer.clone(), registry.clone(), writer.clone()).await?;
     tokio::spawn(async move {
         if let Err(e) = vpc_service_controller.run().await {
             error!("VPCService controller error: {}", e);
@@ -28,7 +94,7 @@ async fn main() -> Result<()> {
     });
 
     // Start VPCRoute reconciliation controller
-    let vpc_route_controller = VPCRouteController::new(client.clone()).await?;
+    let vpc_route_controller = VPCRouteController::new(client.clone(), watch_observer.clone(), writer.clone()).await?;
     tokio::spawn(async move {
         if let Err(e) = vpc_route_controller.run().await {
             error!("VPCRoute controller error: {}", e);
@@ -36,13 +102,53 @@ async fn main() -> Result<()> {
     });
 
     // Start VPCIngress reconciliation controller
-    let vpc_ingress_controller = VPCIngressController::new(client.clone()).await?;
+    let vpc_ingress_controller = VPCIngressController::new(client.clone(), watch_observer.clone(), writer.clone()).await?;
     tokio::spawn(async move {
         if let Err(e) = vpc_ingress_controller.run().await {
             error!("VPCIngress controller error: {}", e);
         }
     });
 
+    // Roll RouterConfig changes out to the gateways; without a gateway
+    // service the config is only validated
+    let gateway_admin = cli.gateway_service.clone().and_then(|service| {
+        GatewayAdmin::new(service, cli.gateway_stats_port, cli.config_token.clone())
+            .map_err(|e| error!("RouterConfig rollout disabled: {}", e))
+            .ok()
+    });
+    let router_config_controller = RouterConfigController::new(
+        client.clone(),
+        watch_observer.clone(),
+        writer.clone(),
+        cli.config_name.clone(),
+        gateway_admin,
+    );
+    tokio::spawn(async move {
+        if let Err(e) = router_config_controller.run().await {
+            error!("RouterConfig controller error: {}", e);
+        }
+    });
+
+    // Remove registry entries whose VPCService was deleted unnoticed
+    tokio::spawn(OrphanCollector::new(client.clone(), registry, metrics.clone(), cli.resync_interval()).run());
+
+    // Back up router resources, restored with `router-controller restore <file>`
+    if let Some(dir) = &cli.backup_dir {
+        let job = BackupJob::new(client.clone(), metrics, dir.clone(), cli.backup_interval(), cli.backup_keep as usize);
+        tokio::spawn(job.run());
+    }
+
+    // Publish live endpoint stats collected from the gateways
+    if let Some(gateway_service) = cli.gateway_service.clone() {
+        let interval = cli.endpoint_stats_interval();
+        match EndpointStatsAggregator::new(client.clone(), gateway_service, cli.gateway_stats_port, interval, writer.clone()) {
+            Ok(aggregator) => {
+                tokio::spawn(aggregator.run());
+            }
+            Err(e) => error!("Endpoint stats aggregation disabled: {}", e),
+        }
+    }
+
     // Keep the process alive
     tokio::signal::ctrl_c().await?;
     info!("Shutdown signal received, exiting...");
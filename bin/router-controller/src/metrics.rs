@@ -0,0 +1,217 @@
+//! Controller metrics and the metrics/health/version endpoint
+
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use prometheus::{Counter, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use router_core::{ApiObserver, BuildInfo, WatchObserver};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+/// Version and build of the controller
+pub static BUILD_INFO: BuildInfo = router_core::build_info!();
+
+/// Prometheus metrics exported by the controller
+#[derive(Clone)]
+pub struct ControllerMetrics {
+    /// Registry entries removed because their resource no longer exists, by kind
+    pub orphans_collected_total: IntCounterVec,
+    /// Orphan collection passes by result
+    pub orphan_gc_runs_total: IntCounterVec,
+    /// Backups of router resources by result
+    pub backup_runs_total: IntCounterVec,
+    /// Changes skipped in dry-run mode, by resource kind
+    pub dry_run_changes_total: IntCounterVec,
+    /// Status writes by resource kind and result ("applied" or "skipped")
+    pub status_writes_total: IntCounterVec,
+    /// Watch restarts by resource kind and reason
+    pub watch_restarts_total: IntCounterVec,
+    /// Full re-lists by resource kind
+    pub watch_relists_total: IntCounterVec,
+    /// Kubernetes API requests by verb, resource, and response code
+    pub kube_api_requests_total: IntCounterVec,
+    /// Kubernetes API request latency by verb
+    pub kube_api_request_duration_seconds: HistogramVec,
+    /// Kubernetes API requests in flight by verb
+    pub kube_api_requests_in_flight: IntGaugeVec,
+    /// Time Kubernetes API requests waited for the client rate limit
+    pub kube_api_rate_limit_wait_seconds_total: Counter,
+    registry: Arc<Registry>,
+}
+
+impl ControllerMetrics {
+    /// Create and register the controller metrics
+    pub fn new() -> Result<Self> {
+        let registry = Arc::new(Registry::new());
+
+        let orphans_collected_total = IntCounterVec::new(
+            Opts::new(
+                "router_controller_orphans_collected_total",
+                "Registry entries removed because their resource no longer exists",
+            ),
+            &["kind"],
+        )?;
+        let orphan_gc_runs_total = IntCounterVec::new(
+            Opts::new("router_controller_orphan_gc_runs_total", "Orphan collection passes by result"),
+            &["result"],
+        )?;
+        let backup_runs_total = IntCounterVec::new(
+            Opts::new("router_controller_backup_runs_total", "Backups of router resources by result"),
+            &["result"],
+        )?;
+
+        let dry_run_changes_total = IntCounterVec::new(
+            Opts::new("router_controller_dry_run_changes_total", "Changes skipped in dry-run mode"),
+            &["kind"],
+        )?;
+
+        let status_writes_total = IntCounterVec::new(
+            Opts::new("router_controller_status_writes_total", "Status writes by result"),
+            &["kind", "result"],
+        )?;
+
+        let watch_restarts_total = IntCounterVec::new(
+            Opts::new("router_controller_watch_restarts_total", "Watch restarts by reason"),
+            &["resource", "reason"],
+        )?;
+        let watch_relists_total = IntCounterVec::new(
+            Opts::new("router_controller_watch_relists_total", "Full re-lists of watched resources"),
+            &["resource"],
+        )?;
+
+        let kube_api_requests_total = IntCounterVec::new(
+            Opts::new("router_controller_kube_api_requests_total", "Kubernetes API requests by response code"),
+            &["verb", "resource", "code"],
+        )?;
+        let kube_api_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "router_controller_kube_api_request_duration_seconds",
+                "Kubernetes API request latency in seconds",
+            ),
+            &["verb"],
+        )?;
+        let kube_api_requests_in_flight = IntGaugeVec::new(
+            Opts::new("router_controller_kube_api_requests_in_flight", "Kubernetes API requests in flight"),
+            &["verb"],
+        )?;
+        let kube_api_rate_limit_wait_seconds_total = Counter::new(
+            "router_controller_kube_api_rate_limit_wait_seconds_total",
+            "Time Kubernetes API requests waited for the client rate limit",
+        )?;
+
+        let build_info = IntGaugeVec::new(
+            Opts::new("router_controller_build_info", "Version and build of the controller"),
+            &["version", "git_sha", "rustc", "build_date", "features"],
+        )?;
+        build_info.with_label_values(&BUILD_INFO.labels().map(|(_, value)| value)).set(1);
+
+        registry.register(Box::new(orphans_collected_total.clone()))?;
+        registry.register(Box::new(orphan_gc_runs_total.clone()))?;
+        registry.register(Box::new(backup_runs_total.clone()))?;
+        registry.register(Box::new(dry_run_changes_total.clone()))?;
+        registry.register(Box::new(status_writes_total.clone()))?;
+        registry.register(Box::new(watch_restarts_total.clone()))?;
+        registry.register(Box::new(watch_relists_total.clone()))?;
+        registry.register(Box::new(kube_api_requests_total.clone()))?;
+        registry.register(Box::new(kube_api_request_duration_seconds.clone()))?;
+        registry.register(Box::new(kube_api_requests_in_flight.clone()))?;
+        registry.register(Box::new(kube_api_rate_limit_wait_seconds_total.clone()))?;
+        registry.register(Box::new(build_info.clone()))?;
+
+        Ok(Self {
+            orphans_collected_total,
+            orphan_gc_runs_total,
+            backup_runs_total,
+            dry_run_changes_total,
+            status_writes_total,
+            watch_restarts_total,
+            watch_relists_total,
+            kube_api_requests_total,
+            kube_api_request_duration_seconds,
+            kube_api_requests_in_flight,
+            kube_api_rate_limit_wait_seconds_total,
+            registry,
+        })
+    }
+
+    /// Export metrics in Prometheus text format
+    pub fn gather(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Serve /metrics, /healthz, /ready, and /version until the process exits
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Controller metrics listening on {}", addr);
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let metrics = self.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req: Request<Incoming>| {
+                    let metrics = metrics.clone();
+                    async move { Ok::<_, Infallible>(metrics.respond(req.uri().path())) }
+                });
+                if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                    debug!("Error serving metrics connection: {}", e);
+                }
+            });
+        }
+    }
+
+    fn respond(&self, path: &str) -> Response<Full<Bytes>> {
+        let (status, body) = match path {
+            "/metrics" => match self.gather() {
+                Ok(text) => (StatusCode::OK, text),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            },
+            "/healthz" | "/ready" => (StatusCode::OK, "ok".to_string()),
+            "/version" => match serde_json::to_string(&BUILD_INFO) {
+                Ok(json) => (StatusCode::OK, json),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            },
+            _ => (StatusCode::NOT_FOUND, "not found".to_string()),
+        };
+        let mut response = Response::new(Full::new(Bytes::from(body)));
+        *response.status_mut() = status;
+        response
+    }
+}
+
+impl WatchObserver for ControllerMetrics {
+    fn restarted(&self, resource: &str, reason: &str) {
+        self.watch_restarts_total.with_label_values(&[resource, reason]).inc();
+    }
+
+    fn relisted(&self, resource: &str) {
+        self.watch_relists_total.with_label_values(&[resource]).inc();
+    }
+}
+
+impl ApiObserver for ControllerMetrics {
+    fn request_started(&self, verb: &str, _resource: &str) {
+        self.kube_api_requests_in_flight.with_label_values(&[verb]).inc();
+    }
+
+    fn request_finished(&self, verb: &str, resource: &str, code: &str, duration: Duration) {
+        self.kube_api_requests_in_flight.with_label_values(&[verb]).dec();
+        self.kube_api_requests_total.with_label_values(&[verb, resource, code]).inc();
+        self.kube_api_request_duration_seconds
+            .with_label_values(&[verb])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn throttled(&self, wait: Duration) {
+        self.kube_api_rate_limit_wait_seconds_total.inc_by(wait.as_secs_f64());
+    }
+}
@@ -0,0 +1,172 @@
+//! Whether changes to routing resources are live on the gateways
+//!
+//! Each gateway replica reports in its RouterGateway the spec generation of
+//! every VPCRoute and VPCIngress in its route table. A resource's
+//! `programmedGeneration` is the oldest generation any ready replica runs,
+//! so once it equals `metadata.generation` the change is live everywhere.
+//! Replicas that are draining or starting don't hold traffic and are left
+//! out. Without registered gateways nothing is reported.
+
+use kube::api::ListParams;
+use kube::{Api, Client, Resource, ResourceExt};
+use router_api::RouterGateway;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Debug;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::status_writer::StatusWriter;
+
+/// How often to check again while gateways are catching up
+pub const PROGRAMMING_RECHECK: Duration = Duration::from_secs(10);
+
+/// How far the ready gateways have programmed a resource
+#[derive(Clone, Debug, PartialEq)]
+pub struct Programmed {
+    /// Oldest spec generation a ready gateway runs; unset while any lacks the resource
+    pub generation: Option<i64>,
+    /// Ready gateways running the current spec generation
+    pub gateways: u32,
+    /// Whether every ready gateway runs the current spec generation
+    pub current: bool,
+}
+
+/// Report in `resource`'s status, written through `api`, which generation
+/// the gateways programmed
+///
+/// Returns whether the gateways are caught up, so the caller can check
+/// again soon when they aren't. Gateways that can't be listed are logged
+/// and treated as caught up.
+pub async fn report<K>(client: &Client, writer: &StatusWriter, api: &Api<K>, resource: &K) -> kube::Result<bool>
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + Serialize + DeserializeOwned,
+{
+    let gateways: Api<RouterGateway> = Api::all(client.clone());
+    let gateways = match gateways.list(&ListParams::default()).await {
+        Ok(list) => list.items,
+        Err(e) => {
+            debug!("Not reporting programmed generation, gateways can't be listed: {}", e);
+            return Ok(true);
+        }
+    };
+
+    let kind = K::kind(&()).to_string();
+    let namespace = resource.namespace().unwrap_or_default();
+    let generation = resource.meta().generation.unwrap_or(0);
+    let Some(programmed) = programmed(&gateways, &kind, &namespace, &resource.name_any(), generation) else {
+        return Ok(true);
+    };
+    if !programmed.current {
+        info!(
+            "{} {} generation {} programmed on {} gateways so far",
+            kind,
+            resource.name_any(),
+            generation,
+            programmed.gateways
+        );
+    }
+
+    let status = json!({
+        "programmedGeneration": programmed.generation,
+        "programmedGateways": programmed.gateways,
+    });
+    writer.apply_status(api, resource, "programmed", status).await?;
+    Ok(programmed.current)
+}
+
+/// How far the ready `gateways` programmed a resource at `generation`
+///
+/// None if no gateway is ready.
+pub fn programmed(gateways: &[RouterGateway], kind: &str, namespace: &str, name: &str, generation: i64) -> Option<Programmed> {
+    let applied: Vec<Option<i64>> = gateways
+        .iter()
+        .filter_map(|gateway| gateway.status.as_ref())
+        .filter(|status| status.ready)
+        .map(|status| {
+            status
+                .routes
+                .iter()
+                .find(|route| route.kind == kind && route.namespace == namespace && route.name == name)
+                .map(|route| route.generation)
+        })
+        .collect();
+    if applied.is_empty() {
+        return None;
+    }
+
+    let gateways = applied.iter().filter(|g| g.is_some_and(|g| g >= generation)).count() as u32;
+    let generation = applied.iter().copied().min().flatten();
+    Some(Programmed { generation, gateways, current: gateways as usize == applied.len() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+    use crate::metrics::ControllerMetrics;
+    use crate::status_writer::WriteRateLimit;
+    use router_api::v1alpha1::router_gateway::{AppliedRoute, RouterGatewayStatus};
+    use router_api::VPCRoute;
+
+    fn gateway(ready: bool, route_generation: Option<i64>) -> RouterGateway {
+        let mut gateway = RouterGateway::new("gateway", Default::default());
+        gateway.status = Some(RouterGatewayStatus {
+            ready,
+            routes: route_generation
+                .map(|generation| AppliedRoute {
+                    kind: "VPCRoute".to_string(),
+                    namespace: "default".to_string(),
+                    name: "api".to_string(),
+                    generation,
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        gateway
+    }
+
+    #[test]
+    fn test_programmed() {
+        let programmed_at = |gateways: &[RouterGateway], generation| programmed(gateways, "VPCRoute", "default", "api", generation);
+
+        assert_eq!(programmed_at(&[], 3), None);
+        assert_eq!(programmed_at(&[gateway(false, Some(3))], 3), None);
+
+        // One replica hasn't seen the route yet
+        let gateways = [gateway(true, Some(3)), gateway(true, None)];
+        assert_eq!(programmed_at(&gateways, 3), Some(Programmed { generation: None, gateways: 1, current: false }));
+
+        // One replica still runs the previous generation; a draining one doesn't count
+        let gateways = [gateway(true, Some(3)), gateway(true, Some(2)), gateway(false, Some(1))];
+        assert_eq!(programmed_at(&gateways, 3), Some(Programmed { generation: Some(2), gateways: 1, current: false }));
+
+        let gateways = [gateway(true, Some(3)), gateway(true, Some(3))];
+        assert_eq!(programmed_at(&gateways, 3), Some(Programmed { generation: Some(3), gateways: 2, current: true }));
+        assert!(programmed(&gateways, "VPCIngress", "default", "api", 3).unwrap().generation.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let server = FakeApiServer::new();
+        let writer = StatusWriter::new(false, ControllerMetrics::new().unwrap(), WriteRateLimit::default());
+        let mut vpc_route = VPCRoute::new("api", Default::default());
+        vpc_route.metadata.namespace = Some("default".to_string());
+        vpc_route.metadata.generation = Some(3);
+        server.insert(&vpc_route);
+        let routes: Api<VPCRoute> = Api::namespaced(server.client(), "default");
+
+        // Nothing to report without gateways
+        assert!(report(&server.client(), &writer, &routes, &vpc_route).await.unwrap());
+        assert_eq!(server.count("PATCH", "/status"), 0);
+
+        let mut behind = gateway(true, Some(2));
+        behind.metadata.namespace = Some("datum-router".to_string());
+        server.insert(&behind);
+        assert!(!report(&server.client(), &writer, &routes, &vpc_route).await.unwrap());
+        let status = server.get::<VPCRoute>("default", "api").unwrap().status.unwrap();
+        assert_eq!((status.programmed_generation, status.programmed_gateways), (Some(2), Some(0)));
+    }
+}
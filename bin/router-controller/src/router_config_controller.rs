@@ -0,0 +1,344 @@
+//! RouterConfig controller rolling gateway settings out to the gateways
+//!
+//! Gateways read the RouterConfig named by their `--config-name` flag (or
+//! `ROUTER_CONFIG_NAME`) when they start, and report the generation they run at `GET /config`. When the
+//! spec changes, the controller asks one stale gateway at a time to pick up
+//! the new generation with `PUT /config`; the gateway drains and exits, and
+//! Kubernetes restarts it with the new settings. The next gateway is only
+//! asked once every replica is ready again, so the fleet never loses more
+//! than one replica to a rollout.
+
+use chrono::Utc;
+use futures::StreamExt;
+use kube::{Api, Client, ResourceExt};
+use kube_runtime::{Controller, controller::Action};
+use router_api::RouterConfig;
+use router_core::watch::{self, WatchConfig, WatchObserver};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+use crate::gateways::GatewayService;
+use crate::status_writer::StatusWriter;
+
+#[derive(Debug)]
+pub struct ReconcileError(pub String);
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reconciliation error: {}", self.0)
+    }
+}
+
+impl Error for ReconcileError {}
+
+/// Config a gateway reports running, and the body of a push
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GatewayConfigVersion {
+    /// Name of the RouterConfig
+    pub name: String,
+    /// Generation of its spec; unset if the gateway couldn't load it
+    pub generation: Option<i64>,
+}
+
+/// How the controller reaches the gateways' `/config` endpoint
+#[derive(Clone)]
+pub struct GatewayAdmin {
+    service: GatewayService,
+    port: u16,
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl GatewayAdmin {
+    /// Reach the replicas of `service` on `port`
+    ///
+    /// Gateways only accept pushes carrying their `ROUTER_CONFIG_TOKEN`.
+    pub fn new(service: GatewayService, port: u16, token: Option<String>) -> anyhow::Result<Self> {
+        Ok(Self {
+            service,
+            port,
+            token,
+            http: reqwest::Client::builder().timeout(Duration::from_secs(5)).build()?,
+        })
+    }
+
+    /// Config a gateway runs
+    async fn running(&self, ip: &str) -> reqwest::Result<GatewayConfigVersion> {
+        self.http
+            .get(format!("http://{}:{}/config", ip, self.port))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Ask a gateway to run `version`
+    async fn push(&self, ip: &str, version: &GatewayConfigVersion) -> reqwest::Result<()> {
+        let mut request = self.http.put(format!("http://{}:{}/config", ip, self.port)).json(version);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+pub struct RouterConfigController {
+    client: Client,
+    watch_observer: Arc<dyn WatchObserver>,
+    writer: StatusWriter,
+    config_name: String,
+    gateways: Option<GatewayAdmin>,
+}
+
+impl RouterConfigController {
+    /// Roll out the RouterConfig named `config_name` to `gateways`
+    ///
+    /// Without gateways the config is only validated; gateways still read
+    /// it when they start.
+    pub fn new(
+        client: Client,
+        watch_observer: Arc<dyn WatchObserver>,
+        writer: StatusWriter,
+        config_name: String,
+        gateways: Option<GatewayAdmin>,
+    ) -> Self {
+        Self { client, watch_observer, writer, config_name, gateways }
+    }
+
+    pub async fn run(&self) -> anyhow::Result<()> {
+        info!("Starting RouterConfig reconciliation for {}", self.config_name);
+
+        let configs: Api<RouterConfig> = Api::all(self.client.clone());
+        let (reader, changes) = watch::reflect(configs, &WatchConfig::default(), self.watch_observer.clone());
+        let controller = Controller::for_stream(changes, reader);
+
+        let mut stream = controller
+            .run(
+                |config, ctx| async move {
+                    info!("Reconciling RouterConfig: {}", config.name_any());
+                    reconcile_config(&config, &ctx).await
+                },
+                |_config, _e: &ReconcileError, _ctx| {
+                    error!("Error reconciling RouterConfig");
+                    Action::requeue(Duration::from_secs(60))
+                },
+                Arc::new(ReconcileContext {
+                    client: self.client.clone(),
+                    writer: self.writer.clone(),
+                    config_name: self.config_name.clone(),
+                    gateways: self.gateways.clone(),
+                }),
+            )
+            .boxed();
+
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(_) => debug!("Reconciled RouterConfig successfully"),
+                Err(e) => error!("Error in reconciliation stream: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// State shared by reconcile calls
+struct ReconcileContext {
+    client: Client,
+    writer: StatusWriter,
+    config_name: String,
+    gateways: Option<GatewayAdmin>,
+}
+
+/// Validate the config and move the rollout one step forward
+async fn reconcile_config(config: &RouterConfig, ctx: &ReconcileContext) -> Result<Action, ReconcileError> {
+    let generation = config.metadata.generation.unwrap_or(0);
+
+    if config.name_any() != ctx.config_name {
+        let message = format!("Ignored: gateways use RouterConfig {}", ctx.config_name);
+        write_status(config, ctx, json!({ "observedGeneration": generation, "message": message })).await?;
+        return Ok(Action::await_change());
+    }
+
+    if let Err(e) = config.spec.validate() {
+        warn!("RouterConfig {}: {}", config.name_any(), e);
+        write_status(config, ctx, json!({ "observedGeneration": generation, "message": e.to_string() })).await?;
+        return Ok(Action::await_change());
+    }
+
+    let Some(gateways) = &ctx.gateways else {
+        let message = "Gateways load the config when they start";
+        write_status(config, ctx, json!({ "observedGeneration": generation, "message": message })).await?;
+        return Ok(Action::await_change());
+    };
+
+    let replicas = gateways
+        .service
+        .replicas(&ctx.client)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    let mut running = Vec::with_capacity(replicas.ready.len());
+    for ip in &replicas.ready {
+        match gateways.running(ip).await {
+            Ok(version) => running.push((ip.clone(), Some(version))),
+            Err(e) => {
+                debug!("Gateway {} config unknown: {}", ip, e);
+                running.push((ip.clone(), None));
+            }
+        }
+    }
+
+    let wanted = GatewayConfigVersion { name: ctx.config_name.clone(), generation: Some(generation) };
+    let step = next_step(&wanted, &running, replicas.not_ready.len());
+    let total = (replicas.ready.len() + replicas.not_ready.len()) as u32;
+    let mut status = json!({
+        "observedGeneration": generation,
+        "gateways": total,
+        "gatewaysSynced": step.synced,
+        "message": step.message,
+    });
+
+    if let Some(ip) = &step.push {
+        match gateways.push(ip, &wanted).await {
+            Ok(()) => {
+                info!("Asked gateway {} to load RouterConfig {} generation {}", ip, ctx.config_name, generation);
+                status["lastPushTime"] = json!(Utc::now().to_rfc3339());
+            }
+            Err(e) => {
+                warn!("Failed to push RouterConfig to gateway {}: {}", ip, e);
+                status["message"] = json!(format!("Gateway {} rejected the config: {}", ip, e));
+            }
+        }
+    }
+    if status.get("lastPushTime").is_none() {
+        if let Some(last) = config.status.as_ref().and_then(|s| s.last_push_time.clone()) {
+            status["lastPushTime"] = json!(last);
+        }
+    }
+    write_status(config, ctx, status).await?;
+
+    if step.synced == total {
+        Ok(Action::requeue(Duration::from_secs(300)))
+    } else {
+        Ok(Action::requeue(Duration::from_secs(10)))
+    }
+}
+
+async fn write_status(config: &RouterConfig, ctx: &ReconcileContext, status: serde_json::Value) -> Result<(), ReconcileError> {
+    let configs: Api<RouterConfig> = Api::all(ctx.client.clone());
+    ctx.writer
+        .apply_status(&configs, config, "rollout", status)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))
+}
+
+/// Progress of a rollout
+#[derive(Debug, PartialEq)]
+struct RolloutStep {
+    /// Ready gateways running the wanted config
+    synced: u32,
+    /// Gateway to ask to load the wanted config now
+    push: Option<String>,
+    message: String,
+}
+
+/// Decide which gateway, if any, loads the wanted config next
+///
+/// `running` lists each ready gateway with the config it reported, or None
+/// if it couldn't be reached. Nothing is pushed while a gateway is not
+/// ready or unreachable, so at most one replica restarts at a time.
+fn next_step(wanted: &GatewayConfigVersion, running: &[(String, Option<GatewayConfigVersion>)], not_ready: usize) -> RolloutStep {
+    let is_current = |version: &GatewayConfigVersion| version.name == wanted.name && version.generation == wanted.generation;
+    let synced = running.iter().filter(|(_, v)| v.as_ref().is_some_and(is_current)).count() as u32;
+    let unreachable = running.iter().filter(|(_, v)| v.is_none()).count();
+    let stale = running.iter().find(|(_, v)| v.as_ref().is_some_and(|v| !is_current(v)));
+    let generation = wanted.generation.unwrap_or(0);
+    let total = running.len() + not_ready;
+
+    let (push, message) = match stale {
+        _ if synced as usize == total => (None, format!("All {} gateways run generation {}", total, generation)),
+        Some((ip, _)) if not_ready == 0 && unreachable == 0 => (
+            Some(ip.clone()),
+            format!("{}/{} gateways run generation {}; restarting {}", synced, total, generation, ip),
+        ),
+        _ => (
+            None,
+            format!(
+                "{}/{} gateways run generation {}; waiting for {} restarting and {} unreachable",
+                synced, total, generation, not_ready, unreachable
+            ),
+        ),
+    };
+    RolloutStep { synced, push, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+    use crate::metrics::ControllerMetrics;
+    use crate::status_writer::WriteRateLimit;
+
+    fn context(server: &FakeApiServer) -> ReconcileContext {
+        ReconcileContext {
+            client: server.client(),
+            writer: StatusWriter::new(false, ControllerMetrics::new().unwrap(), WriteRateLimit::default()),
+            config_name: "default".to_string(),
+            gateways: None,
+        }
+    }
+
+    fn version(generation: i64) -> Option<GatewayConfigVersion> {
+        Some(GatewayConfigVersion { name: "default".to_string(), generation: Some(generation) })
+    }
+
+    async fn status(server: &FakeApiServer, name: &str) -> router_api::v1alpha1::router_config::RouterConfigStatus {
+        let configs: Api<RouterConfig> = Api::all(server.client());
+        configs.get(name).await.unwrap().status.unwrap()
+    }
+
+    #[test]
+    fn test_rollout_restarts_one_gateway_at_a_time() {
+        let wanted = version(4).unwrap();
+        let running = vec![("10.0.0.1".to_string(), version(4)), ("10.0.0.2".to_string(), version(3))];
+
+        let step = next_step(&wanted, &running, 0);
+        assert_eq!(step.synced, 1);
+        assert_eq!(step.push.as_deref(), Some("10.0.0.2"));
+
+        // A replica restarting or unreachable holds the rollout
+        assert_eq!(next_step(&wanted, &running, 1).push, None);
+        let unreachable = vec![running[1].clone(), ("10.0.0.3".to_string(), None)];
+        assert_eq!(next_step(&wanted, &unreachable, 0).push, None);
+
+        let done = next_step(&wanted, &running[..1], 0);
+        assert_eq!((done.synced, done.push), (1, None));
+        assert_eq!(done.message, "All 1 gateways run generation 4");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_and_ignored_configs() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server);
+
+        let mut invalid = RouterConfig::new("default", Default::default());
+        invalid.spec.listeners.http_port = Some(0);
+        server.insert(&invalid);
+        reconcile_config(&invalid, &ctx).await.unwrap();
+        let message = status(&server, "default").await.message.unwrap();
+        assert_eq!(message, "invalid RouterConfigSpec: listeners.httpPort must not be 0");
+
+        let other = RouterConfig::new("staging", Default::default());
+        server.insert(&other);
+        reconcile_config(&other, &ctx).await.unwrap();
+        let message = status(&server, "staging").await.message.unwrap();
+        assert_eq!(message, "Ignored: gateways use RouterConfig default");
+    }
+}
@@ -0,0 +1,231 @@
+//! Status writes shared by all reconcilers
+//!
+//! Status is written with server-side apply, one field manager per concern
+//! (maintenance, schedule, ...), so each owns only its own fields. An apply
+//! that would change nothing is skipped, and writes are rate limited so a
+//! resync of many resources can't flood the API server.
+//!
+//! In dry-run mode applies are sent with server-side dry run, so the API
+//! server validates them against the CRD schema but persists nothing, and
+//! the changes they would make are logged. The registry (the data plane
+//! programmed by the controller) is left untouched.
+
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use crate::metrics::ControllerMetrics;
+
+/// Field manager prefix for status applies
+const FIELD_MANAGER: &str = "router-controller";
+
+/// Client-side limit on status writes
+#[derive(Clone, Copy, Debug)]
+pub struct WriteRateLimit {
+    /// Sustained writes per second
+    pub qps: f64,
+    /// Writes allowed at once after an idle period
+    pub burst: u32,
+}
+
+impl Default for WriteRateLimit {
+    fn default() -> Self {
+        Self { qps: 5.0, burst: 10 }
+    }
+}
+
+/// Writes resource status, or reports what it would write in dry-run mode
+#[derive(Clone)]
+pub struct StatusWriter {
+    dry_run: bool,
+    metrics: ControllerMetrics,
+    interval: Duration,
+    burst: u32,
+    /// Earliest time the next write may start
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl StatusWriter {
+    /// Create a writer; with `dry_run` set, nothing is persisted
+    pub fn new(dry_run: bool, metrics: ControllerMetrics, rate_limit: WriteRateLimit) -> Self {
+        Self {
+            dry_run,
+            metrics,
+            interval: Duration::from_secs_f64(1.0 / rate_limit.qps.max(0.001)),
+            burst: rate_limit.burst.max(1),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Whether writes are only simulated
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Apply the status fields owned by `concern`
+    ///
+    /// `status` holds every field the concern manages; fields it applied
+    /// before and leaves out are removed. Nothing is sent when the
+    /// resource already has exactly these values.
+    pub async fn apply_status<K>(&self, api: &Api<K>, resource: &K, concern: &str, status: Value) -> kube::Result<()>
+    where
+        K: Resource<DynamicType = ()> + Clone + Debug + Serialize + DeserializeOwned,
+    {
+        let kind = K::kind(&()).to_string();
+        let manager = format!("{}-{}", FIELD_MANAGER, concern);
+        let status = strip_nulls(status);
+
+        if !changes_status(resource, &manager, &status) {
+            debug!("{} {} status for {} unchanged, skipping", kind, resource.name_any(), concern);
+            self.metrics.status_writes_total.with_label_values(&[&kind, "skipped"]).inc();
+            return Ok(());
+        }
+
+        self.acquire().await;
+        let params = PatchParams {
+            dry_run: self.dry_run,
+            ..PatchParams::apply(&manager).force()
+        };
+        let body = json!({
+            "apiVersion": K::api_version(&()),
+            "kind": kind,
+            "metadata": { "name": resource.name_any() },
+            "status": status,
+        });
+        let updated = api.patch_status(&resource.name_any(), &params, &Patch::Apply(&body)).await?;
+        self.metrics.status_writes_total.with_label_values(&[&kind, "applied"]).inc();
+        if self.dry_run {
+            self.report(resource, &updated);
+        }
+        Ok(())
+    }
+
+    /// Record a data plane change that was skipped
+    pub fn skipped(&self, kind: &str, description: &str) {
+        info!("[dry-run] Would {}", description);
+        self.metrics.dry_run_changes_total.with_label_values(&[kind]).inc();
+    }
+
+    /// Wait for a write slot
+    async fn acquire(&self) {
+        let wait = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            // Unused slots accumulate up to the burst size
+            let earliest = now.checked_sub(self.interval * (self.burst - 1)).unwrap_or(now);
+            let slot = (*next_slot).max(earliest);
+            *next_slot = slot + self.interval;
+            slot.saturating_duration_since(now)
+        };
+        if !wait.is_zero() {
+            debug!("Status write rate limited for {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Log the status fields an apply would change
+    fn report<K>(&self, before: &K, after: &K)
+    where
+        K: Resource<DynamicType = ()> + Serialize,
+    {
+        let mut changes = Vec::new();
+        diff("status", &current_status(before), &current_status(after), &mut changes);
+
+        let kind = K::kind(&()).to_string();
+        let name = format!("{}/{}", before.namespace().unwrap_or_default(), before.name_any());
+        if changes.is_empty() {
+            info!("[dry-run] {} {}: status unchanged", kind, name);
+            return;
+        }
+        for change in &changes {
+            info!("[dry-run] {} {}: {}", kind, name, change);
+        }
+        self.metrics
+            .dry_run_changes_total
+            .with_label_values(&[&kind])
+            .inc_by(changes.len() as u64);
+    }
+}
+
+/// A resource's status as JSON
+fn current_status<K: Serialize>(resource: &K) -> Value {
+    serde_json::to_value(resource)
+        .ok()
+        .and_then(|mut v| v.get_mut("status").map(Value::take))
+        .unwrap_or(Value::Null)
+}
+
+/// Whether applying `status` as `manager` would change the resource
+///
+/// Changes either set a field to a new value or drop a status field the
+/// manager owns but no longer applies.
+fn changes_status<K: Resource + Serialize>(resource: &K, manager: &str, status: &Value) -> bool {
+    let current = current_status(resource);
+    let applied = status.as_object().cloned().unwrap_or_default();
+    if applied.iter().any(|(field, value)| current.get(field) != Some(value)) {
+        return true;
+    }
+    owned_status_fields(resource, manager)
+        .iter()
+        .any(|field| !applied.contains_key(field) && current.get(field).is_some_and(|v| !v.is_null()))
+}
+
+/// Top-level status fields owned by a field manager
+fn owned_status_fields<K: Resource>(resource: &K, manager: &str) -> BTreeSet<String> {
+    resource
+        .meta()
+        .managed_fields
+        .iter()
+        .flatten()
+        .filter(|entry| entry.manager.as_deref() == Some(manager))
+        .filter_map(|entry| entry.fields_v1.as_ref())
+        .filter_map(|fields| fields.0.get("f:status").and_then(Value::as_object))
+        .flat_map(|status| status.keys())
+        .filter_map(|key| key.strip_prefix("f:"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Drop null fields, which server-side apply treats as unset
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect::<Map<_, _>>(),
+        ),
+        other => other,
+    }
+}
+
+/// Describe the differences between two JSON values, field by field
+fn diff(path: &str, before: &Value, after: &Value, changes: &mut Vec<String>) {
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field = format!("{}.{}", path, key);
+                diff(
+                    &field,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if before != after => changes.push(format!("{}: {} -> {}", path, before, after)),
+        _ => {}
+    }
+}
@@ -4,6 +4,7 @@ use kube::{Api, Client};
 use kube_runtime::{Controller, controller::Action};
 use futures::StreamExt;
 use router_api::VPCIngress;
+use router_core::watch::{self, WatchConfig, WatchObserver};
 use router_core::ServiceRegistry;
 use std::sync::Arc;
 use std::time::Duration;
@@ -11,6 +12,9 @@ use std::error::Error;
 use std::fmt;
 use tracing::{info, debug, error};
 
+use crate::programmed::{self, PROGRAMMING_RECHECK};
+use crate::status_writer::StatusWriter;
+
 #[derive(Debug)]
 pub struct ReconcileError(pub String);
 
@@ -24,13 +28,27 @@ impl Error for ReconcileError {}
 
 pub struct VPCIngressController {
     client: Client,
+    watch_observer: Arc<dyn WatchObserver>,
+    #[allow(dead_code)]
     registry: Arc<ServiceRegistry>,
+    writer: StatusWriter,
+}
+
+/// State shared by reconcile calls
+struct ReconcileContext {
+    client: Client,
+    writer: StatusWriter,
 }
 
 impl VPCIngressController {
-    pub async fn new(client: Client) -> anyhow::Result<Self> {
+    pub async fn new(client: Client, watch_observer: Arc<dyn WatchObserver>, writer: StatusWriter) -> anyhow::Result<Self> {
         let registry = Arc::new(ServiceRegistry::new());
-        Ok(Self { client, registry })
+        Ok(Self {
+            client,
+            watch_observer,
+            registry,
+            writer,
+        })
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
@@ -39,11 +57,12 @@ impl VPCIngressController {
         let vpc_ingresses: Api<VPCIngress> = Api::all(self.client.clone());
 
         // Watch for VPCIngress changes
-        let controller = Controller::new(vpc_ingresses.clone(), Default::default());
+        let (reader, changes) = watch::reflect(vpc_ingresses.clone(), &WatchConfig::default(), self.watch_observer.clone());
+        let controller = Controller::for_stream(changes, reader);
 
         let mut stream = controller
             .run(
-                |vpc_ingress, _ctx| async move {
+                |vpc_ingress, ctx| async move {
                     let name = &vpc_ingress.metadata.name;
                     let namespace = &vpc_ingress.metadata.namespace;
                     info!(
@@ -60,13 +79,26 @@ impl VPCIngressController {
                     // - Configure TLS if specified
                     // - Update load balancer configuration
 
+                    // Report which generation the gateways have programmed,
+                    // checking again soon until all of them run this one
+                    let vpc_ingresses: Api<VPCIngress> = Api::all(ctx.client.clone());
+                    let programmed = programmed::report(&ctx.client, &ctx.writer, &vpc_ingresses, vpc_ingress.as_ref())
+                        .await
+                        .map_err(|e| ReconcileError(e.to_string()))?;
+                    if !programmed {
+                        return Ok(Action::requeue(PROGRAMMING_RECHECK));
+                    }
+
                     Ok(Action::requeue(Duration::from_secs(300)))
                 },
                 |_vpc_ingress, _e: &ReconcileError, _ctx| {
                     error!("Error reconciling VPCIngress");
                     Action::requeue(Duration::from_secs(60))
                 },
-                Arc::new(()),
+                Arc::new(ReconcileContext {
+                    client: self.client.clone(),
+                    writer: self.writer.clone(),
+                }),
             )
             .boxed();
 
@@ -1,15 +1,22 @@
 //! VPCRoute controller for reconciling VPCRoute resources
 
-use kube::{Api, Client};
+use kube::{Api, Client, ResourceExt};
 use kube_runtime::{Controller, controller::Action};
 use futures::StreamExt;
-use router_api::VPCRoute;
-use router_core::ServiceRegistry;
+use router_api::v1alpha1::vpc_route::ReadWriteSplit;
+use router_api::{VPCRoute, VPCService};
+use router_core::watch::{self, WatchConfig, WatchObserver};
+use router_core::{schedule, ServiceRegistry};
+use serde_json::json;
 use std::sync::Arc;
 use std::time::Duration;
 use std::error::Error;
 use std::fmt;
-use tracing::{info, debug, error};
+use tracing::{info, debug, error, warn};
+
+use crate::deprecations;
+use crate::programmed::{self, PROGRAMMING_RECHECK};
+use crate::status_writer::StatusWriter;
 
 #[derive(Debug)]
 pub struct ReconcileError(pub String);
@@ -24,13 +31,27 @@ impl Error for ReconcileError {}
 
 pub struct VPCRouteController {
     client: Client,
+    watch_observer: Arc<dyn WatchObserver>,
+    #[allow(dead_code)]
     registry: Arc<ServiceRegistry>,
+    writer: StatusWriter,
+}
+
+/// State shared by reconcile calls
+struct ReconcileContext {
+    client: Client,
+    writer: StatusWriter,
 }
 
 impl VPCRouteController {
-    pub async fn new(client: Client) -> anyhow::Result<Self> {
+    pub async fn new(client: Client, watch_observer: Arc<dyn WatchObserver>, writer: StatusWriter) -> anyhow::Result<Self> {
         let registry = Arc::new(ServiceRegistry::new());
-        Ok(Self { client, registry })
+        Ok(Self {
+            client,
+            watch_observer,
+            registry,
+            writer,
+        })
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
@@ -39,11 +60,12 @@ impl VPCRouteController {
         let vpc_routes: Api<VPCRoute> = Api::all(self.client.clone());
 
         // Watch for VPCRoute changes
-        let controller = Controller::new(vpc_routes.clone(), Default::default());
+        let (reader, changes) = watch::reflect(vpc_routes.clone(), &WatchConfig::default(), self.watch_observer.clone());
+        let controller = Controller::for_stream(changes, reader);
 
         let mut stream = controller
             .run(
-                |vpc_route, _ctx| async move {
+                |vpc_route, ctx| async move {
                     let name = &vpc_route.metadata.name;
                     let namespace = &vpc_route.metadata.namespace;
                     info!(
@@ -54,14 +76,51 @@ impl VPCRouteController {
 
                     // Log route configuration
                     debug!("VPCRoute spec: {:?}", vpc_route.spec);
+                    if let Some(blue_green) = &vpc_route.spec.blue_green {
+                        info!(
+                            "VPCRoute is serving {} ({} destinations)",
+                            blue_green.active.as_str(),
+                            vpc_route.spec.effective_destinations().len()
+                        );
+                    }
+
+                    // Report which generation the gateways have programmed,
+                    // checking again soon until all of them run this one
+                    let namespace = vpc_route.namespace().unwrap_or_else(|| "default".to_string());
+                    let routes: Api<VPCRoute> = Api::namespaced(ctx.client.clone(), &namespace);
+                    let programmed = programmed::report(&ctx.client, &ctx.writer, &routes, vpc_route.as_ref())
+                        .await
+                        .map_err(|e| ReconcileError(e.to_string()))?;
+                    let requeue = if programmed { Duration::from_secs(300) } else { PROGRAMMING_RECHECK };
+
+                    // Warn about values v1alpha2 won't accept
+                    let conditions = vpc_route.status.as_ref().map(|s| s.conditions.as_slice()).unwrap_or_default();
+                    deprecations::report(&ctx.client, &ctx.writer, &routes, vpc_route.as_ref(), conditions, &vpc_route.spec.deprecations())
+                        .await
+                        .map_err(|e| ReconcileError(e.to_string()))?;
+
+                    // Read/write splits are only programmed once both sets resolve
+                    if let Some(split) = &vpc_route.spec.read_write_split {
+                        verify_read_write_split(&vpc_route, split, &ctx).await?;
+                    }
 
-                    Ok(Action::requeue(Duration::from_secs(300)))
+                    // Scheduled routes: publish whether the window is open and
+                    // come back when it next opens or closes
+                    if let Some(route_schedule) = &vpc_route.spec.schedule {
+                        let next = reconcile_schedule(&vpc_route, route_schedule, &ctx).await?;
+                        return Ok(Action::requeue(next.min(requeue)));
+                    }
+
+                    Ok(Action::requeue(requeue))
                 },
                 |_vpc_route, _e: &ReconcileError, _ctx| {
                     error!("Error reconciling VPCRoute");
                     Action::requeue(Duration::from_secs(60))
                 },
-                Arc::new(()),
+                Arc::new(ReconcileContext {
+                    client: self.client.clone(),
+                    writer: self.writer.clone(),
+                }),
             )
             .boxed();
 
@@ -76,3 +135,207 @@ impl VPCRouteController {
         Ok(())
     }
 }
+
+/// Evaluate a route's schedule and record the result in its status
+///
+/// Returns when to evaluate it again.
+async fn reconcile_schedule(
+    vpc_route: &VPCRoute,
+    route_schedule: &router_api::v1alpha1::vpc_route::RouteSchedule,
+    ctx: &ReconcileContext,
+) -> Result<Duration, ReconcileError> {
+    let now = chrono::Utc::now();
+    let state = schedule::evaluate(route_schedule, now)
+        .map_err(|e| ReconcileError(e.to_string()))?;
+
+    let was_active = vpc_route.status.as_ref().and_then(|s| s.schedule_active);
+    if was_active != Some(state.active) {
+        info!(
+            "VPCRoute {} schedule is now {}",
+            vpc_route.name_any(),
+            if state.active { "active" } else { "inactive" }
+        );
+    }
+
+    let namespace = vpc_route.namespace().unwrap_or_else(|| "default".to_string());
+    let routes: Api<VPCRoute> = Api::namespaced(ctx.client.clone(), &namespace);
+    let status = json!({
+        "scheduleActive": state.active,
+        "nextScheduleTransition": state.next_transition.map(|t| t.to_rfc3339()),
+    });
+    ctx.writer
+        .apply_status(&routes, vpc_route, "schedule", status)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+
+    // Requeue right after the next transition, but at least every 5 minutes
+    let requeue = state
+        .next_transition
+        .and_then(|t| (t - now).to_std().ok())
+        .map(|d| d + Duration::from_secs(1))
+        .unwrap_or(Duration::from_secs(300))
+        .min(Duration::from_secs(300));
+    Ok(requeue)
+}
+
+/// Methods that never modify state and may be served by read replicas
+const SAFE_METHODS: [&str; 4] = ["GET", "HEAD", "OPTIONS", "TRACE"];
+
+/// Check that a read/write split is safe to program
+///
+/// Both destination sets must be non-empty and every VPCService they
+/// reference must exist, and only safe methods may go to the read set. On
+/// failure the route is marked not ready with the reason.
+async fn verify_read_write_split(
+    vpc_route: &VPCRoute,
+    split: &ReadWriteSplit,
+    ctx: &ReconcileContext,
+) -> Result<(), ReconcileError> {
+    let namespace = vpc_route.namespace().unwrap_or_else(|| "default".to_string());
+    let mut problems = Vec::new();
+
+    for method in &split.read_methods {
+        if !SAFE_METHODS.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+            problems.push(format!("{} modifies state and cannot be sent to the read set", method));
+        }
+    }
+
+    for (set, destinations) in [("read", &split.read), ("write", &split.write)] {
+        if destinations.is_empty() {
+            problems.push(format!("{} destination set is empty", set));
+        }
+        for destination in destinations.iter().filter(|d| !d.is_direct()) {
+            let service_ref = &destination.vpc_service_ref;
+            let service_namespace = service_ref.namespace.as_deref().unwrap_or(&namespace);
+            let services: Api<VPCService> = Api::namespaced(ctx.client.clone(), service_namespace);
+            let found = services
+                .get_opt(&service_ref.name)
+                .await
+                .map_err(|e| ReconcileError(e.to_string()))?;
+            if found.is_none() {
+                problems.push(format!(
+                    "{} destination VPCService {}/{} not found",
+                    set, service_namespace, service_ref.name
+                ));
+            }
+        }
+    }
+
+    let routes: Api<VPCRoute> = Api::namespaced(ctx.client.clone(), &namespace);
+    if problems.is_empty() {
+        // Clear a previously reported problem
+        ctx.writer
+            .apply_status(&routes, vpc_route, "read-write-split", json!({}))
+            .await
+            .map_err(|e| ReconcileError(e.to_string()))?;
+        return Ok(());
+    }
+
+    let message = format!("Invalid readWriteSplit: {}", problems.join("; "));
+    warn!("VPCRoute {}/{}: {}", namespace, vpc_route.name_any(), message);
+    let status = json!({ "ready": false, "message": message });
+    ctx.writer
+        .apply_status(&routes, vpc_route, "read-write-split", status)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    Err(ReconcileError(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+    use crate::metrics::ControllerMetrics;
+    use crate::status_writer::WriteRateLimit;
+    use router_api::v1alpha1::vpc_route::{RouteDestination, RouteSchedule, ServiceRef};
+
+    fn context(server: &FakeApiServer) -> ReconcileContext {
+        ReconcileContext {
+            client: server.client(),
+            writer: StatusWriter::new(false, ControllerMetrics::new().unwrap(), WriteRateLimit::default()),
+        }
+    }
+
+    fn route(name: &str) -> VPCRoute {
+        let mut route = VPCRoute::new(name, Default::default());
+        route.metadata.namespace = Some("default".to_string());
+        route
+    }
+
+    fn destination(service: &str) -> RouteDestination {
+        RouteDestination {
+            vpc_service_ref: ServiceRef {
+                name: service.to_string(),
+                namespace: None,
+            },
+            weight: 100,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_write_split_missing_service() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server);
+        let mut primary = VPCService::new("primary", Default::default());
+        primary.metadata.namespace = Some("default".to_string());
+        server.insert(&primary);
+
+        let vpc_route = route("db");
+        server.insert(&vpc_route);
+        let split = ReadWriteSplit {
+            read_methods: vec!["GET".to_string(), "POST".to_string()],
+            read: vec![destination("replica")],
+            write: vec![destination("primary")],
+        };
+
+        let err = verify_read_write_split(&vpc_route, &split, &ctx).await.unwrap_err();
+        assert!(err.0.contains("POST modifies state"));
+        assert!(err.0.contains("read destination VPCService default/replica not found"));
+
+        let status = server.get::<VPCRoute>("default", "db").unwrap().status.unwrap();
+        assert!(!status.ready);
+        assert_eq!(status.message.as_deref(), Some(err.0.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_read_write_split_valid() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server);
+        for name in ["primary", "replica"] {
+            let mut svc = VPCService::new(name, Default::default());
+            svc.metadata.namespace = Some("default".to_string());
+            server.insert(&svc);
+        }
+
+        let vpc_route = route("db");
+        server.insert(&vpc_route);
+        let split = ReadWriteSplit {
+            read_methods: vec!["GET".to_string()],
+            read: vec![destination("replica")],
+            write: vec![destination("primary")],
+        };
+
+        verify_read_write_split(&vpc_route, &split, &ctx).await.unwrap();
+        // Nothing to clear on a route that never reported a problem
+        assert_eq!(server.count("PATCH", "/status"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_status() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server);
+        let vpc_route = route("launch");
+        server.insert(&vpc_route);
+        let route_schedule = RouteSchedule {
+            active_from: Some("2000-01-01T00:00:00Z".to_string()),
+            active_until: Some("2999-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+
+        reconcile_schedule(&vpc_route, &route_schedule, &ctx).await.unwrap();
+        let status = server.get::<VPCRoute>("default", "launch").unwrap().status.unwrap();
+        assert_eq!(status.schedule_active, Some(true));
+        assert!(status.next_schedule_transition.is_some());
+    }
+}
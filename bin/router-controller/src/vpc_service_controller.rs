@@ -1,17 +1,24 @@
 //! VPCService controller for reconciling VPCService resources
 
-use kube::{Api, Client};
+use kube::{Api, Client, ResourceExt};
 use kube_runtime::{Controller, controller::Action};
 use futures::StreamExt;
+use router_api::galactic::VPCAttachment;
+use router_api::v1alpha1::vpc_service::{Condition, VPCReference};
 use router_api::VPCService;
-use router_core::ServiceRegistry;
+use router_core::watch::{self, WatchConfig, WatchObserver};
+use router_core::{Endpoint, ServiceRegistry};
 use router_galactic::VPCDiscovery;
+use serde_json::json;
 use tracing::{info, debug, error};
 use std::sync::Arc;
 use std::time::Duration;
 use std::error::Error;
 use std::fmt;
 
+use crate::deprecations;
+use crate::status_writer::StatusWriter;
+
 #[derive(Debug)]
 pub struct ReconcileError(pub String);
 
@@ -25,27 +32,39 @@ impl Error for ReconcileError {}
 
 pub struct VPCServiceController {
     client: Client,
+    watch_observer: Arc<dyn WatchObserver>,
     registry: Arc<ServiceRegistry>,
+    writer: StatusWriter,
 }
 
 impl VPCServiceController {
-    pub async fn new(client: Client) -> anyhow::Result<Self> {
-        let registry = Arc::new(ServiceRegistry::new());
-        Ok(Self { client, registry })
+    pub async fn new(
+        client: Client,
+        watch_observer: Arc<dyn WatchObserver>,
+        registry: Arc<ServiceRegistry>,
+        writer: StatusWriter,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client,
+            watch_observer,
+            registry,
+            writer,
+        })
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
         info!("Starting VPCService reconciliation");
 
         let vpc_services: Api<VPCService> = Api::all(self.client.clone());
-        let _discovery = VPCDiscovery::new().await?;
+        let _discovery = VPCDiscovery::new(self.client.clone());
 
         // Watch for VPCService changes
-        let controller = Controller::new(vpc_services.clone(), Default::default());
+        let (reader, changes) = watch::reflect(vpc_services.clone(), &WatchConfig::default(), self.watch_observer.clone());
+        let controller = Controller::for_stream(changes, reader);
 
         let mut stream = controller
             .run(
-                |vpc_svc, _ctx| async move {
+                |vpc_svc, ctx| async move {
                     let name = &vpc_svc.metadata.name;
                     let namespace = &vpc_svc.metadata.namespace;
                     info!(
@@ -53,13 +72,19 @@ impl VPCServiceController {
                         namespace.as_ref().unwrap_or(&"default".to_string()),
                         name.as_ref().unwrap_or(&"unknown".to_string())
                     );
-                    Ok(Action::requeue(Duration::from_secs(300)))
+                    register_service(&vpc_svc, &ctx).await?;
+                    let vpc_svc = report_deprecations(&vpc_svc, &ctx).await?;
+                    reconcile_maintenance(&vpc_svc, &ctx).await
                 },
                 |_vpc_svc, _e: &ReconcileError, _ctx| {
                     error!("Error reconciling VPCService");
                     Action::requeue(Duration::from_secs(60))
                 },
-                Arc::new(()),
+                Arc::new(ReconcileContext {
+                    client: self.client.clone(),
+                    registry: self.registry.clone(),
+                    writer: self.writer.clone(),
+                }),
             )
             .boxed();
 
@@ -74,3 +99,290 @@ impl VPCServiceController {
         Ok(())
     }
 }
+
+/// State shared by reconcile calls
+struct ReconcileContext {
+    client: Client,
+    registry: Arc<ServiceRegistry>,
+    writer: StatusWriter,
+}
+
+/// Record the service, its current endpoints, its visibility, its health
+/// check settings, its drained endpoints, and its VPC in the registry
+///
+/// The VPC is that of the service's VPCAttachment and decides whether its
+/// endpoints need address translation. Deleted services are removed by
+/// the orphan collector's resync.
+async fn register_service(vpc_svc: &VPCService, ctx: &ReconcileContext) -> Result<(), ReconcileError> {
+    let namespace = vpc_svc.namespace().unwrap_or_else(|| "default".to_string());
+    let endpoints: Vec<Endpoint> = vpc_svc
+        .status
+        .as_ref()
+        .map(|status| {
+            status
+                .endpoints
+                .iter()
+                .map(|e| Endpoint {
+                    ip: e.ip.clone(),
+                    port: e.port,
+                    ready: e.ready,
+                    priority: e.priority,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if ctx.writer.dry_run() {
+        ctx.writer.skipped(
+            "VPCService",
+            &format!("register service {}/{} with {} endpoints", namespace, vpc_svc.name_any(), endpoints.len()),
+        );
+        return Ok(());
+    }
+
+    let service_id = format!("{}/{}", namespace, vpc_svc.name_any());
+    ctx.registry
+        .register_service(namespace, vpc_svc.name_any(), vpc_svc.spec.port, vpc_svc.spec.protocol.clone(), endpoints)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    ctx.registry
+        .set_visibility(&service_id, vpc_svc.spec.visibility.clone())
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    ctx.registry
+        .set_health_check(&service_id, vpc_svc.spec.health_check.clone())
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    ctx.registry
+        .set_drained(&service_id, vpc_svc.drained_endpoints().into_iter().collect())
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+    ctx.registry
+        .set_vpc(&service_id, attachment_vpc(vpc_svc, &ctx.client).await)
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))
+}
+
+/// The VPC of a service's VPCAttachment, if the attachment can be read
+async fn attachment_vpc(vpc_svc: &VPCService, client: &Client) -> Option<VPCReference> {
+    let attachment_ref = &vpc_svc.spec.vpc_attachment_ref;
+    let attachments: Api<VPCAttachment> = Api::all(client.clone());
+    match attachments.get_opt(&attachment_ref.name).await {
+        Ok(Some(attachment)) => Some(VPCReference {
+            name: attachment.spec.vpc.name,
+            namespace: attachment.spec.vpc.namespace,
+        }),
+        Ok(None) => {
+            debug!("VPCAttachment {} of VPCService {} not found", attachment_ref.name, vpc_svc.name_any());
+            None
+        }
+        Err(e) => {
+            debug!("Failed to read VPCAttachment {}: {}", attachment_ref.name, e);
+            None
+        }
+    }
+}
+
+/// Report deprecated values of the service's spec in its status
+///
+/// Returns the service with its conditions as written, so the maintenance
+/// condition written next keeps them.
+async fn report_deprecations(vpc_svc: &VPCService, ctx: &ReconcileContext) -> Result<VPCService, ReconcileError> {
+    let namespace = vpc_svc.namespace().unwrap_or_else(|| "default".to_string());
+    let services: Api<VPCService> = Api::namespaced(ctx.client.clone(), &namespace);
+    let conditions = vpc_svc.status.as_ref().map(|s| s.conditions.as_slice()).unwrap_or_default();
+    let conditions = deprecations::report(&ctx.client, &ctx.writer, &services, vpc_svc, conditions, &vpc_svc.spec.deprecations())
+        .await
+        .map_err(|e| ReconcileError(e.to_string()))?;
+
+    let mut vpc_svc = vpc_svc.clone();
+    vpc_svc.status.get_or_insert_with(Default::default).conditions = conditions;
+    Ok(vpc_svc)
+}
+
+/// Condition type reporting planned maintenance
+const MAINTENANCE_CONDITION: &str = "Maintenance";
+
+/// Record planned maintenance in the service's status conditions
+///
+/// The Maintenance condition lets alerting tell a planned drain from an
+/// outage. Requeues when a maintenance window is due to end.
+async fn reconcile_maintenance(vpc_svc: &VPCService, ctx: &ReconcileContext) -> Result<Action, ReconcileError> {
+    let now = chrono::Utc::now();
+    let reason = vpc_svc.maintenance_reason(now);
+
+    let conditions = vpc_svc.status.as_ref().map(|s| s.conditions.as_slice()).unwrap_or_default();
+    let current = conditions.iter().find(|c| c.condition_type == MAINTENANCE_CONDITION);
+    let status = if reason.is_some() { "True" } else { "False" };
+    let unchanged = match current {
+        Some(condition) => condition.status == status && condition.message == reason,
+        // Nothing to clear on services that were never in maintenance
+        None => reason.is_none(),
+    };
+
+    if !unchanged {
+        match &reason {
+            Some(reason) => info!("VPCService {} entering maintenance: {}", vpc_svc.name_any(), reason),
+            None => info!("VPCService {} leaving maintenance", vpc_svc.name_any()),
+        }
+
+        let mut updated: Vec<Condition> = conditions
+            .iter()
+            .filter(|c| c.condition_type != MAINTENANCE_CONDITION)
+            .cloned()
+            .collect();
+        updated.push(Condition {
+            condition_type: MAINTENANCE_CONDITION.to_string(),
+            status: status.to_string(),
+            reason: Some(if reason.is_some() { "PlannedMaintenance" } else { "MaintenanceEnded" }.to_string()),
+            message: reason.clone(),
+            last_update_time: Some(now.to_rfc3339()),
+        });
+
+        let namespace = vpc_svc.namespace().unwrap_or_else(|| "default".to_string());
+        let services: Api<VPCService> = Api::namespaced(ctx.client.clone(), &namespace);
+        ctx.writer
+            .apply_status(&services, vpc_svc, "maintenance", json!({ "conditions": updated }))
+            .await
+            .map_err(|e| ReconcileError(e.to_string()))?;
+    }
+
+    // Come back right after a maintenance window closes
+    let requeue = vpc_svc
+        .spec
+        .maintenance
+        .as_ref()
+        .filter(|_| reason.is_some())
+        .and_then(|window| window.until.as_deref())
+        .and_then(|until| chrono::DateTime::parse_from_rfc3339(until).ok())
+        .and_then(|until| (until.with_timezone(&chrono::Utc) - now).to_std().ok())
+        .map(|d| d + Duration::from_secs(1))
+        .unwrap_or(Duration::from_secs(300))
+        .min(Duration::from_secs(300));
+    Ok(Action::requeue(requeue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_apiserver::FakeApiServer;
+    use crate::metrics::ControllerMetrics;
+    use crate::status_writer::WriteRateLimit;
+    use router_api::v1alpha1::vpc_service::{
+        EndpointStatus, HealthCheckConfig, MaintenanceWindow, VPCServiceStatus, DRAIN_ANNOTATION,
+    };
+
+    fn context(server: &FakeApiServer, dry_run: bool) -> ReconcileContext {
+        ReconcileContext {
+            client: server.client(),
+            registry: Arc::new(ServiceRegistry::new()),
+            writer: StatusWriter::new(dry_run, ControllerMetrics::new().unwrap(), WriteRateLimit::default()),
+        }
+    }
+
+    fn service(name: &str) -> VPCService {
+        let mut svc = VPCService::new(name, Default::default());
+        svc.metadata.namespace = Some("default".to_string());
+        svc.spec.port = 8080;
+        svc.status = Some(VPCServiceStatus {
+            endpoints: vec![EndpointStatus {
+                ip: "10.0.0.1".to_string(),
+                port: 8080,
+                ready: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        svc
+    }
+
+    #[tokio::test]
+    async fn test_register_service() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server, false);
+        register_service(&service("api"), &ctx).await.unwrap();
+        assert_eq!(ctx.registry.service_count().await, 1);
+        assert!(ctx.registry.get_service("default/api").await.unwrap().vpc.is_none());
+
+        // The VPC comes from the service's attachment
+        let mut attachment = VPCAttachment::new("api-attachment", Default::default());
+        attachment.spec.vpc.name = "payments".to_string();
+        attachment.spec.vpc.namespace = "default".to_string();
+        server.insert(&attachment);
+        let mut svc = service("api");
+        svc.spec.vpc_attachment_ref.name = "api-attachment".to_string();
+        svc.annotations_mut().insert(DRAIN_ANNOTATION.to_string(), "10.0.0.1:8080".to_string());
+        svc.spec.health_check = Some(HealthCheckConfig::http("/ready"));
+        register_service(&svc, &ctx).await.unwrap();
+        let registered = ctx.registry.get_service("default/api").await.unwrap();
+        let vpc = registered.vpc.unwrap();
+        assert_eq!((vpc.namespace.as_str(), vpc.name.as_str()), ("default", "payments"));
+        assert!(!registered.endpoints[0].ready);
+        assert_eq!(registered.health_check.unwrap().http_path.as_deref(), Some("/ready"));
+
+        let dry_run = context(&server, true);
+        register_service(&service("api"), &dry_run).await.unwrap();
+        assert_eq!(dry_run.registry.service_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_condition() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server, false);
+        let mut svc = service("api");
+        svc.spec.maintenance = Some(MaintenanceWindow {
+            reason: Some("kernel upgrade".to_string()),
+            until: None,
+        });
+        server.insert(&svc);
+
+        reconcile_maintenance(&svc, &ctx).await.unwrap();
+        let stored: VPCService = server.get("default", "api").unwrap();
+        let condition = &stored.status.as_ref().unwrap().conditions[0];
+        assert_eq!(condition.condition_type, MAINTENANCE_CONDITION);
+        assert_eq!(condition.status, "True");
+        assert_eq!(condition.message.as_deref(), Some("kernel upgrade"));
+        assert_eq!(server.count("PATCH", "/status"), 1);
+
+        // Reconciling the written object again changes nothing
+        reconcile_maintenance(&stored, &ctx).await.unwrap();
+        assert_eq!(server.count("PATCH", "/status"), 1);
+
+        // Ending maintenance flips the condition
+        let mut ended = stored.clone();
+        ended.spec.maintenance = None;
+        reconcile_maintenance(&ended, &ctx).await.unwrap();
+        let stored: VPCService = server.get("default", "api").unwrap();
+        assert_eq!(stored.status.unwrap().conditions[0].status, "False");
+    }
+
+    #[tokio::test]
+    async fn test_deprecations_keep_maintenance_condition() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server, false);
+        let mut svc = service("api");
+        svc.spec.protocol = "https".to_string();
+        svc.spec.maintenance = Some(MaintenanceWindow::default());
+        server.insert(&svc);
+
+        let svc = report_deprecations(&svc, &ctx).await.unwrap();
+        reconcile_maintenance(&svc, &ctx).await.unwrap();
+        let stored: VPCService = server.get("default", "api").unwrap();
+        let types: Vec<String> = stored.status.unwrap().conditions.into_iter().map(|c| c.condition_type).collect();
+        assert_eq!(types, vec!["Deprecated".to_string(), MAINTENANCE_CONDITION.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_dry_run_persists_nothing() {
+        let server = FakeApiServer::new();
+        let ctx = context(&server, true);
+        let mut svc = service("api");
+        svc.spec.maintenance = Some(MaintenanceWindow::default());
+        server.insert(&svc);
+
+        reconcile_maintenance(&svc, &ctx).await.unwrap();
+        assert_eq!(server.count("PATCH", "/status"), 1);
+        let stored: VPCService = server.get("default", "api").unwrap();
+        assert!(stored.status.unwrap().conditions.is_empty());
+    }
+}
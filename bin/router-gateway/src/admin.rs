@@ -0,0 +1,283 @@
+//! Admin endpoints the gateway answers itself, ahead of routing
+//!
+//! Metrics, traffic counters and in-flight requests are read by operators
+//! and the controller; `/config` and `/drains` take changes from the
+//! controller's rollout and administrators, guarded by the admin token
+//! (ROUTER_CONFIG_TOKEN); `/readyz` and `/healthz` are probed by Kubernetes.
+
+use http_body_util::Full;
+use hyper::body::{Body, Bytes};
+use hyper::{Method, Request, Response, StatusCode};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::router::constant_time_eq;
+use crate::{config, drains, GatewayState, BUILD_INFO};
+
+/// An endpoint the gateway answers itself instead of routing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminEndpoint {
+    /// `GET /metrics`: Prometheus metrics
+    Metrics,
+    /// `GET /endpoint-stats`: per-endpoint traffic counters, collected by
+    /// the controller
+    EndpointStats,
+    /// `GET /vpc-traffic`: traffic totals per source and destination VPC,
+    /// for chargeback and capacity planning
+    VpcTraffic,
+    /// `GET /inflight`: requests being handled, oldest first, for
+    /// diagnosing stuck upstreams
+    Inflight,
+    /// `GET /config`: RouterConfig generation in use, read by the
+    /// controller's rollout
+    Config,
+    /// `PUT /config`: load another RouterConfig generation
+    ReloadConfig,
+    /// `GET /drains`: endpoints and services drained by an administrator
+    Drains,
+    /// `PUT /drains` or `DELETE /drains`: drain an endpoint or service
+    /// fleet-wide, or re-enable it
+    SetDrain { drained: bool },
+    /// `/readyz`: not ready once shutdown starts so traffic moves elsewhere
+    Ready,
+    /// `GET /version`: version and build of this replica
+    Version,
+    /// `/healthz`: liveness
+    Health,
+    /// `DELETE /cache`: purge cached responses
+    PurgeCache,
+}
+
+impl AdminEndpoint {
+    /// The admin endpoint a request is for, if any
+    pub fn from_request(method: &Method, path: &str) -> Option<Self> {
+        let endpoint = match (path, method) {
+            ("/metrics", &Method::GET) => Self::Metrics,
+            ("/endpoint-stats", &Method::GET) => Self::EndpointStats,
+            ("/vpc-traffic", &Method::GET) => Self::VpcTraffic,
+            ("/inflight", &Method::GET) => Self::Inflight,
+            ("/config", &Method::GET) => Self::Config,
+            ("/config", &Method::PUT) => Self::ReloadConfig,
+            ("/drains", &Method::GET) => Self::Drains,
+            ("/drains", &Method::PUT) => Self::SetDrain { drained: true },
+            ("/drains", &Method::DELETE) => Self::SetDrain { drained: false },
+            ("/readyz", _) => Self::Ready,
+            ("/version", &Method::GET) => Self::Version,
+            ("/healthz", _) => Self::Health,
+            ("/cache", &Method::DELETE) => Self::PurgeCache,
+            _ => return None,
+        };
+        Some(endpoint)
+    }
+
+    /// Answer `req` for this endpoint
+    pub async fn respond<B>(self, req: Request<B>, state: &GatewayState) -> Response<Full<Bytes>>
+    where
+        B: Body,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        match self {
+            Self::Metrics => {
+                let metrics = state
+                    .metrics_collector
+                    .gather()
+                    .unwrap_or_else(|_| "Failed to gather metrics\n".to_string());
+                response(StatusCode::OK, Some("text/plain; version=0.0.4"), metrics)
+            }
+            Self::EndpointStats => json_response(&state.endpoint_stats.snapshot(), "{}"),
+            Self::VpcTraffic => json_response(&state.vpc_traffic.snapshot(), "[]"),
+            Self::Inflight => json_response(&state.inflight.snapshot(), "[]"),
+            Self::Config => match &state.router_config {
+                Some(running) => json_response(running, "{}"),
+                None => response(StatusCode::NOT_FOUND, Some("application/json"), "{}"),
+            },
+            Self::ReloadConfig => response(request_config_reload(req, state).await, None, Bytes::new()),
+            Self::Drains => json_response(&state.drains.list(), "[]"),
+            Self::SetDrain { drained } => response(request_drain(req, state, drained).await, None, Bytes::new()),
+            Self::Ready if state.drain.is_draining() => response(StatusCode::SERVICE_UNAVAILABLE, None, "Draining\n"),
+            Self::Ready => response(StatusCode::OK, None, "Ready\n"),
+            Self::Version => json_response(&BUILD_INFO, ""),
+            Self::Health => response(StatusCode::OK, None, "OK\n"),
+            Self::PurgeCache => match authorize_cache_purge(req.headers(), state) {
+                Ok(()) => {
+                    let (status, body) = purge_cache(req.uri(), state);
+                    let content_type = (status == StatusCode::OK).then_some("application/json");
+                    response(status, content_type, body)
+                }
+                Err(status) => response(status, None, Bytes::new()),
+            },
+        }
+    }
+}
+
+/// Build an admin response, with a Content-Type when there's a body to describe
+fn response(status: StatusCode, content_type: Option<&str>, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    let mut response = Response::builder().status(status);
+    if let Some(content_type) = content_type {
+        response = response.header("Content-Type", content_type);
+    }
+    response.body(Full::new(body.into())).unwrap()
+}
+
+/// Answer 200 with `value` as JSON, or `fallback` if it can't be serialized
+fn json_response(value: &impl Serialize, fallback: &'static str) -> Response<Full<Bytes>> {
+    let body = serde_json::to_string(value).unwrap_or_else(|_| fallback.to_string());
+    response(StatusCode::OK, Some("application/json"), body)
+}
+
+/// Check a cache purge carries the cache purge API key in X-API-Key, or
+/// the admin token
+fn authorize_cache_purge(headers: &hyper::HeaderMap, state: &GatewayState) -> Result<(), StatusCode> {
+    let key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+    match (key, &state.cache_purge_key) {
+        (Some(key), Some(expected)) if constant_time_eq(key.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => authorize_admin(headers, state),
+    }
+}
+
+/// Purge the cached responses a `DELETE /cache` names: those of the
+/// VPCRoute `?route=namespace/name`, those whose path matches
+/// `?pattern=/catalog/*/images`, those under `?prefix=/path`, or all of them
+///
+/// Answers with how many were purged, or 404 for an unknown route.
+fn purge_cache(uri: &hyper::Uri, state: &GatewayState) -> (StatusCode, String) {
+    let Some(cache) = &state.response_cache else {
+        return (StatusCode::OK, serde_json::json!({ "purged": 0 }).to_string());
+    };
+    let purged = if let Some(route_id) = query_param(uri, "route") {
+        let routes = state.router.routes();
+        let Some(route) = routes.iter().find(|route| route.id == route_id) else {
+            return (StatusCode::NOT_FOUND, String::new());
+        };
+        cache.purge_where(|path| {
+            let path = path.split('?').next().unwrap_or_default();
+            state.router.match_route_path(&route.spec.r#match, path)
+        })
+    } else if let Some(pattern) = query_param(uri, "pattern") {
+        cache.purge_matching(pattern)
+    } else {
+        cache.purge(query_param(uri, "prefix"))
+    };
+    info!("Purged {} cached responses", purged);
+    (StatusCode::OK, serde_json::json!({ "purged": purged }).to_string())
+}
+
+/// Value of the query parameter `name`, as sent
+fn query_param<'a>(uri: &'a hyper::Uri, name: &str) -> Option<&'a str> {
+    uri.query()?.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Restart the gateway if the controller asks for another RouterConfig generation
+///
+/// The gateway drains and exits; Kubernetes restarts it and it loads the
+/// config again. Answers 202 when restarting, 200 when the generation is
+/// already running.
+async fn request_config_reload<B>(req: Request<B>, state: &GatewayState) -> StatusCode
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    use http_body_util::{BodyExt, Limited};
+
+    let Some(running) = &state.router_config else {
+        return StatusCode::NOT_FOUND;
+    };
+    if let Err(status) = authorize_admin(req.headers(), state) {
+        return status;
+    }
+
+    let Ok(body) = Limited::new(req.into_body(), 4096).collect().await else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let Ok(wanted) = serde_json::from_slice::<config::ConfigVersion>(&body.to_bytes()) else {
+        return StatusCode::BAD_REQUEST;
+    };
+    if wanted.name != running.name {
+        return StatusCode::CONFLICT;
+    }
+    if wanted.generation == running.generation {
+        return StatusCode::OK;
+    }
+
+    info!(
+        "RouterConfig {} generation {:?} requested (running {:?}), restarting to load it",
+        wanted.name, wanted.generation, running.generation
+    );
+    state.drain.start();
+    StatusCode::ACCEPTED
+}
+
+/// Check the bearer token of an administrative request
+///
+/// Requests are refused outright unless ROUTER_CONFIG_TOKEN is set.
+fn authorize_admin(headers: &hyper::HeaderMap, state: &GatewayState) -> Result<(), StatusCode> {
+    let Some(token) = &state.config_token else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let authorized = headers
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|v| constant_time_eq(v.as_bytes(), token.as_bytes()));
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+/// Drain, or with `drained` false re-enable, an endpoint or a whole
+/// VPCService across the fleet
+///
+/// Answers 202 once the drain is recorded; every replica applies it as its
+/// VPCService watch sees the change. A conflicting concurrent change
+/// answers 409 and can be retried.
+async fn request_drain<B>(req: Request<B>, state: &GatewayState, drained: bool) -> StatusCode
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    use http_body_util::{BodyExt, Limited};
+
+    if let Err(status) = authorize_admin(req.headers(), state) {
+        return status;
+    }
+    let Ok(body) = Limited::new(req.into_body(), 4096).collect().await else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let Ok(request) = serde_json::from_slice::<drains::DrainRequest>(&body.to_bytes()) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    match state.drains.set(&request, drained).await {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(drains::DrainError::Invalid) => StatusCode::BAD_REQUEST,
+        Err(drains::DrainError::NotFound | drains::DrainError::Unavailable) => StatusCode::NOT_FOUND,
+        Err(drains::DrainError::Api(kube::Error::Api(e))) if e.code == 409 => StatusCode::CONFLICT,
+        Err(drains::DrainError::Api(e)) => {
+            warn!("Failed to record drain of {}: {}", request.service, e);
+            StatusCode::BAD_GATEWAY
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_request_matches_method_and_path() {
+        assert_eq!(AdminEndpoint::from_request(&Method::GET, "/metrics"), Some(AdminEndpoint::Metrics));
+        assert_eq!(AdminEndpoint::from_request(&Method::GET, "/config"), Some(AdminEndpoint::Config));
+        assert_eq!(AdminEndpoint::from_request(&Method::PUT, "/config"), Some(AdminEndpoint::ReloadConfig));
+        assert_eq!(
+            AdminEndpoint::from_request(&Method::DELETE, "/drains"),
+            Some(AdminEndpoint::SetDrain { drained: false })
+        );
+        assert_eq!(AdminEndpoint::from_request(&Method::HEAD, "/healthz"), Some(AdminEndpoint::Health));
+
+        // Other methods on admin paths, and other paths, are routed
+        assert_eq!(AdminEndpoint::from_request(&Method::POST, "/metrics"), None);
+        assert_eq!(AdminEndpoint::from_request(&Method::GET, "/cache"), None);
+        assert_eq!(AdminEndpoint::from_request(&Method::GET, "/metrics/extra"), None);
+    }
+}
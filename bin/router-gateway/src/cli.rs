@@ -0,0 +1,134 @@
+//! Command-line flags
+//!
+//! Listeners, protocol toggles, the replica's identity, and the Kubernetes
+//! client are set with flags, each of which can also be set through the
+//! environment variable shown in `--help`. Listener settings given neither
+//! way fall back to the RouterConfig (see [`crate::config`]) and then to
+//! their defaults. Every other setting is a `ROUTER_*` environment variable
+//! read by the loaders in `main.rs`.
+
+use crate::config;
+use clap::builder::BoolishValueParser;
+use clap::Parser;
+use router_core::cli::{KubeArgs, LogArgs};
+use std::str::FromStr;
+
+/// Routes and proxies traffic between VPC services
+#[derive(Debug, Parser)]
+#[command(
+    name = "router-gateway",
+    version,
+    long_version = router_core::long_version!(),
+    after_help = "Middleware, routing, and upstream settings are read from ROUTER_* environment variables; see the README."
+)]
+pub struct Cli {
+    /// Port of the plaintext HTTP listener [default: 8080]
+    #[arg(long, env = "ROUTER_HTTP_PORT", value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    pub http_port: Option<u16>,
+
+    /// Port of the HTTPS listener, started when a certificate is configured [default: 8443]
+    #[arg(long, env = "ROUTER_HTTPS_PORT", value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    pub https_port: Option<u16>,
+
+    /// Accept prior-knowledge HTTP/2 (h2c) on the HTTP port
+    #[arg(long, env = "ROUTER_H2C", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true",
+          value_parser = BoolishValueParser::new())]
+    pub h2c: Option<bool>,
+
+    /// Serve HTTP/3 over QUIC next to the HTTPS listener
+    #[arg(long, env = "ROUTER_HTTP3", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true",
+          value_parser = BoolishValueParser::new())]
+    pub http3: Option<bool>,
+
+    /// UDP port for HTTP/3 [default: 8443]
+    #[arg(long, env = "ROUTER_HTTP3_PORT", value_name = "PORT", value_parser = clap::value_parser!(u16).range(1..))]
+    pub http3_port: Option<u16>,
+
+    /// Cluster-scoped RouterConfig to load settings from [default: none]
+    #[arg(long, env = "ROUTER_CONFIG_NAME", value_name = "NAME")]
+    pub config_name: Option<String>,
+
+    /// The replica's pod; with --namespace, registers the replica as a RouterGateway
+    #[arg(long, env = "POD_NAME", value_name = "NAME")]
+    pub pod_name: Option<String>,
+
+    /// Namespace of the replica's pod, its RouterGateway, and its policy ConfigMaps
+    #[arg(long, env = "POD_NAMESPACE", value_name = "NAMESPACE")]
+    pub namespace: Option<String>,
+
+    #[command(flatten)]
+    pub kube: KubeArgs,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+impl Cli {
+    /// Port of the plaintext HTTP listener
+    pub fn http_port(&self) -> u16 {
+        or_setting(self.http_port, "ROUTER_HTTP_PORT").unwrap_or(8080)
+    }
+
+    /// Port of the HTTPS listener
+    pub fn https_port(&self) -> u16 {
+        or_setting(self.https_port, "ROUTER_HTTPS_PORT").unwrap_or(8443)
+    }
+
+    /// Whether the HTTP listener accepts h2c
+    pub fn h2c(&self) -> bool {
+        or_setting(self.h2c, "ROUTER_H2C").unwrap_or(false)
+    }
+
+    /// Whether HTTP/3 is served
+    pub fn http3(&self) -> bool {
+        or_setting(self.http3, "ROUTER_HTTP3").unwrap_or(false)
+    }
+
+    /// UDP port for HTTP/3, if not the default
+    pub fn http3_port(&self) -> Option<u16> {
+        or_setting(self.http3_port, "ROUTER_HTTP3_PORT")
+    }
+}
+
+/// A flag's value, or the RouterConfig's when neither the flag nor its
+/// environment variable is set
+fn or_setting<T: FromStr>(value: Option<T>, name: &str) -> Option<T> {
+    value.or_else(|| config::var(name).ok()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("router-gateway").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_command_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.http_port(), 8080);
+        assert_eq!(cli.https_port(), 8443);
+        assert!(!cli.h2c());
+        assert!(!cli.http3());
+        assert_eq!(cli.http3_port(), None);
+
+        let cli = parse(&["--http-port", "9080", "--h2c", "--http3=no", "--namespace", "router"]).unwrap();
+        assert_eq!(cli.http_port(), 9080);
+        assert!(cli.h2c());
+        assert_eq!(cli.http3, Some(false));
+        assert_eq!(cli.namespace.as_deref(), Some("router"));
+
+        // Errors name the flag
+        for args in [["--http-port", "0"], ["--https-port", "65536"], ["--h2c", "maybe"]] {
+            let error = parse(&args).unwrap_err().to_string();
+            assert!(error.contains(args[0]), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Gateway settings from the cluster-wide RouterConfig resource
+//!
+//! With `--config-name` set, the gateway reads that RouterConfig once
+//! at startup and every setting it holds stands in for the matching
+//! `ROUTER_*` environment variable; variables set on the pod still win, so
+//! a single replica can be overridden. Settings are read through [`var`]
+//! instead of `std::env::var`.
+//!
+//! The controller rolls out changes with `PUT /config`: a gateway asked to
+//! run another generation than the one it loaded drains and exits, and
+//! comes back with the new settings when Kubernetes restarts it.
+
+use kube::{Api, ResourceExt};
+use router_api::v1alpha1::router_config::RouterConfigSpec;
+use router_api::RouterConfig;
+use crate::cli::Cli;
+use router_core::kube_client::LoggingApiObserver;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env::VarError;
+use std::sync::{Arc, OnceLock};
+use tracing::{info, warn};
+
+/// Settings from the RouterConfig, by environment variable name
+static SETTINGS: OnceLock<BTreeMap<&'static str, String>> = OnceLock::new();
+
+/// RouterConfig a gateway runs, as reported at `GET /config` and requested
+/// with `PUT /config`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigVersion {
+    /// Name of the RouterConfig
+    pub name: String,
+    /// Generation of its spec; unset if it couldn't be loaded
+    pub generation: Option<i64>,
+}
+
+/// A gateway setting: the environment variable if set, otherwise the
+/// RouterConfig's value
+pub fn var(name: &str) -> Result<String, VarError> {
+    match std::env::var(name) {
+        Err(VarError::NotPresent) => SETTINGS
+            .get()
+            .and_then(|settings| settings.get(name))
+            .cloned()
+            .ok_or(VarError::NotPresent),
+        result => result,
+    }
+}
+
+/// Load the RouterConfig named by `--config-name`, if set
+///
+/// Must run before any setting is read. A config that can't be fetched
+/// leaves the gateway on its environment alone, reporting no generation so
+/// the controller asks it to try again.
+pub async fn load(cli: &Cli) -> Option<ConfigVersion> {
+    let name = cli.config_name.clone()?;
+    let fetched = async {
+        let client = cli
+            .kube
+            .client_config("router-gateway")
+            .build(Arc::new(LoggingApiObserver))
+            .await?;
+        let configs: Api<RouterConfig> = Api::all(client);
+        anyhow::Ok(configs.get(&name).await?)
+    };
+    let generation = match fetched.await {
+        Ok(config) => {
+            let settings = env_vars(&config.spec);
+            info!(
+                "Loaded RouterConfig {} generation {} ({} settings)",
+                config.name_any(),
+                config.metadata.generation.unwrap_or(0),
+                settings.len()
+            );
+            let _ = SETTINGS.set(settings);
+            Some(config.metadata.generation.unwrap_or(0))
+        }
+        Err(e) => {
+            warn!("Failed to load RouterConfig {}, using environment variables only: {}", name, e);
+            None
+        }
+    };
+    Some(ConfigVersion { name, generation })
+}
+
+/// The environment variables a RouterConfig spec stands in for
+fn env_vars(spec: &RouterConfigSpec) -> BTreeMap<&'static str, String> {
+    let mut vars = BTreeMap::new();
+    let mut set = |name: &'static str, value: Option<String>| {
+        if let Some(value) = value {
+            vars.insert(name, value);
+        }
+    };
+
+    let listeners = &spec.listeners;
+    set("ROUTER_HTTP_PORT", listeners.http_port.map(|v| v.to_string()));
+    set("ROUTER_HTTPS_PORT", listeners.https_port.map(|v| v.to_string()));
+    set("ROUTER_TLS_CERT", listeners.tls_cert_file.clone());
+    set("ROUTER_TLS_KEY", listeners.tls_key_file.clone());
+    set("ROUTER_H2C", listeners.h2c.map(|v| v.to_string()));
+    set("ROUTER_HTTP3", listeners.http3.map(|v| v.to_string()));
+    set("ROUTER_HTTP3_PORT", listeners.http3_port.map(|v| v.to_string()));
+    if !listeners.tcp.is_empty() {
+        let proxies = listeners
+            .tcp
+            .iter()
+            .map(|tcp| format!("{} {} {}", tcp.port, tcp.service, tcp.endpoints.join(",")))
+            .collect::<Vec<_>>();
+        set("ROUTER_TCP_PROXIES", Some(proxies.join(";")));
+    }
+
+    let defaults = &spec.defaults;
+    set("ROUTER_MAX_REQUEST_BODY_BYTES", defaults.max_request_body_bytes.map(|v| v.to_string()));
+    set("ROUTER_MAX_CONCURRENT_REQUESTS", defaults.max_concurrent_requests.map(|v| v.to_string()));
+    set("ROUTER_QUEUE_LENGTH", defaults.queue_length.map(|v| v.to_string()));
+    set("ROUTER_QUEUE_TIMEOUT_MS", defaults.queue_timeout_ms.map(|v| v.to_string()));
+    set("ROUTER_RATE_LIMIT_RPS", defaults.rate_limit_rps.map(|v| v.to_string()));
+    set("ROUTER_RATE_LIMIT_BURST", defaults.rate_limit_burst.map(|v| v.to_string()));
+    set("ROUTER_ERROR_FORMAT", defaults.error_format.clone());
+    set("ROUTER_DRAIN_TIMEOUT_SECS", defaults.drain_timeout_seconds.map(|v| v.to_string()));
+
+    let middleware = &spec.middleware;
+    set("ROUTER_ACCESS_LOG", middleware.access_log.clone());
+    set("ROUTER_COMPRESSION", middleware.compression.map(|v| v.to_string()));
+    set("ROUTER_SERVER_TIMING", middleware.server_timing.map(|v| v.to_string()));
+    set("ROUTER_REQUEST_ID_HEADER", middleware.request_id_header.clone());
+
+    let metrics = &spec.metrics;
+    set("ROUTER_METRICS_BACKEND", metrics.backend.clone());
+    set("ROUTER_STATSD_ADDR", metrics.statsd_address.clone());
+    set("ROUTER_STATSD_PREFIX", metrics.statsd_prefix.clone());
+    set("ROUTER_OTLP_METRICS_ENDPOINT", metrics.otlp_endpoint.clone());
+    set("ROUTER_OTLP_METRICS_INTERVAL_SECS", metrics.otlp_interval_seconds.map(|v| v.to_string()));
+    set("ROUTER_METRICS_MAX_PATHS", metrics.max_path_labels.map(|v| v.to_string()));
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::v1alpha1::router_config::{ListenerSettings, MiddlewareSettings, TcpListenerSettings};
+
+    #[test]
+    fn test_env_vars() {
+        let spec = RouterConfigSpec {
+            listeners: ListenerSettings {
+                http_port: Some(9080),
+                h2c: Some(true),
+                tcp: vec![
+                    TcpListenerSettings {
+                        port: 5432,
+                        service: "default/postgres".to_string(),
+                        endpoints: vec!["10.0.0.1:5432".to_string(), "10.0.0.2:5432".to_string()],
+                    },
+                    TcpListenerSettings {
+                        port: 6379,
+                        service: "default/redis".to_string(),
+                        endpoints: vec!["10.0.0.3:6379".to_string()],
+                    },
+                ],
+                ..Default::default()
+            },
+            middleware: MiddlewareSettings {
+                compression: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let vars = env_vars(&spec);
+        assert_eq!(vars.len(), 4);
+        assert_eq!(vars["ROUTER_HTTP_PORT"], "9080");
+        assert_eq!(vars["ROUTER_H2C"], "true");
+        assert_eq!(vars["ROUTER_COMPRESSION"], "false");
+        assert_eq!(
+            vars["ROUTER_TCP_PROXIES"],
+            "5432 default/postgres 10.0.0.1:5432,10.0.0.2:5432;6379 default/redis 10.0.0.3:6379"
+        );
+        assert!(env_vars(&RouterConfigSpec::default()).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+//! Endpoints and services drained by an administrator
+//!
+//! Node maintenance needs traffic moved off an endpoint, or a whole
+//! VPCService, on every gateway without editing the VPCService spec. Any
+//! replica accepts the request at `PUT /drains` (and `DELETE /drains` to
+//! re-enable) and records it in the VPCService's annotations: drained
+//! endpoints in `router.datum.net/drained-endpoints`, a drained service as
+//! planned maintenance in `router.datum.net/maintenance`. Every replica,
+//! and the controller, watch VPCServices and apply the annotations, so
+//! the drain reaches the whole fleet through the API server and outlives
+//! replica restarts.
+//!
+//! The gateway's VPCService watch, in [`crate::services`], applies them
+//! along with each service's health check settings, which the gateway's
+//! health check monitor reads from the registry.
+
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client, ResourceExt};
+use router_api::v1alpha1::vpc_service::{DRAIN_ANNOTATION, MAINTENANCE_ANNOTATION};
+use router_api::VPCService;
+use router_core::ServiceRegistry;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+/// Maintenance reason recorded for services drained without one
+const DEFAULT_REASON: &str = "Drained by an administrator";
+
+/// Field manager of the gateway's drain patches
+const FIELD_MANAGER: &str = "router-gateway";
+
+/// Body of `PUT /drains` and `DELETE /drains`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainRequest {
+    /// VPCService, as namespace/name
+    pub service: String,
+    /// Endpoint of the service, as ip:port; unset drains the whole service
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Why the service is drained, shown in its Maintenance condition
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl DrainRequest {
+    /// Namespace and name of the service, if the request is well formed
+    fn target(&self) -> Option<(&str, &str)> {
+        let (namespace, name) = self.service.split_once('/')?;
+        if namespace.is_empty() || name.is_empty() {
+            return None;
+        }
+        if self.endpoint.as_deref().is_some_and(|e| e.parse::<SocketAddr>().is_err()) {
+            return None;
+        }
+        Some((namespace, name))
+    }
+}
+
+/// Drains of one VPCService, as listed at `GET /drains`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainedService {
+    /// VPCService, as namespace/name
+    pub service: String,
+    /// Maintenance reason, when the whole service is drained
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Drained endpoints, as ip:port
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<String>,
+}
+
+/// Why setting a drain failed
+#[derive(Debug)]
+pub enum DrainError {
+    /// The gateway doesn't run in Kubernetes and can't record drains
+    Unavailable,
+    /// The request doesn't name a service, or its endpoint isn't ip:port
+    Invalid,
+    /// The VPCService doesn't exist
+    NotFound,
+    /// The API server refused or failed the update
+    Api(kube::Error),
+}
+
+/// Drains known to the fleet, and how to record new ones
+pub struct Drains {
+    client: Option<Client>,
+    registry: Arc<ServiceRegistry>,
+    current: RwLock<BTreeMap<String, DrainedService>>,
+}
+
+impl Drains {
+    /// Drains applied to `registry`, recorded through `client`
+    ///
+    /// Without a client, outside Kubernetes, drains can't be set.
+    pub fn new(registry: Arc<ServiceRegistry>, client: Option<Client>) -> Self {
+        Self { client, registry, current: RwLock::default() }
+    }
+
+    /// Drained services and endpoints, ordered by service
+    pub fn list(&self) -> Vec<DrainedService> {
+        self.current.read().unwrap().values().cloned().collect()
+    }
+
+    /// Drain what `request` names, or re-enable it with `drained` false
+    ///
+    /// The change is recorded on the VPCService and applied once the
+    /// watches see it. Re-enabling a service only lifts a drain set here
+    /// or with the annotation; a maintenance window in the spec stays.
+    pub async fn set(&self, request: &DrainRequest, drained: bool) -> Result<(), DrainError> {
+        let client = self.client.as_ref().ok_or(DrainError::Unavailable)?;
+        let (namespace, name) = request.target().ok_or(DrainError::Invalid)?;
+        let services: Api<VPCService> = Api::namespaced(client.clone(), namespace);
+        let service = services.get_opt(name).await.map_err(DrainError::Api)?.ok_or(DrainError::NotFound)?;
+
+        let (annotation, value) = match &request.endpoint {
+            Some(endpoint) => {
+                let mut endpoints = service.drained_endpoints();
+                endpoints.retain(|e| e != endpoint);
+                if drained {
+                    endpoints.push(endpoint.clone());
+                }
+                endpoints.sort();
+                (DRAIN_ANNOTATION, Some(endpoints.join(",")).filter(|v| !v.is_empty()))
+            }
+            None => {
+                let reason = request.reason.clone().filter(|r| !r.trim().is_empty());
+                (MAINTENANCE_ANNOTATION, drained.then(|| reason.unwrap_or_else(|| DEFAULT_REASON.to_string())))
+            }
+        };
+
+        // The resource version makes concurrent edits of the endpoint list
+        // fail with a conflict rather than drop one of them
+        let patch = json!({
+            "metadata": {
+                "resourceVersion": service.resource_version(),
+                "annotations": { annotation: value },
+            }
+        });
+        services
+            .patch(name, &PatchParams::apply(FIELD_MANAGER), &Patch::Merge(&patch))
+            .await
+            .map_err(DrainError::Api)?;
+        info!(
+            "{} {}{}",
+            if drained { "Drained" } else { "Re-enabled" },
+            request.service,
+            request.endpoint.as_deref().map(|e| format!(" endpoint {}", e)).unwrap_or_default()
+        );
+        Ok(())
+    }
+
+    /// Take the drains of `services` as current, and apply them and the
+    /// services' health check settings to the services in the registry
+    pub(crate) async fn publish(&self, services: &[Arc<VPCService>]) {
+        let now = chrono::Utc::now();
+        let mut current = BTreeMap::new();
+        for service in services {
+            let service_id = format!("{}/{}", service.namespace().unwrap_or_default(), service.name_any());
+            let reason = service.maintenance_reason(now);
+            let endpoints = service.drained_endpoints();
+
+            // Services this replica doesn't route to aren't registered
+            if self.registry.get_service(&service_id).await.is_ok() {
+                if let Err(e) = self.registry.set_maintenance(&service_id, reason.clone()).await {
+                    debug!("Failed to apply maintenance of {}: {}", service_id, e);
+                }
+                if let Err(e) = self.registry.set_drained(&service_id, endpoints.iter().cloned().collect()).await {
+                    debug!("Failed to apply drains of {}: {}", service_id, e);
+                }
+                if let Err(e) = self.registry.set_health_check(&service_id, service.spec.health_check.clone()).await {
+                    debug!("Failed to apply health check of {}: {}", service_id, e);
+                }
+            }
+            if reason.is_some() || !endpoints.is_empty() {
+                current.insert(service_id.clone(), DrainedService { service: service_id, reason, endpoints });
+            }
+        }
+        *self.current.write().unwrap() = current;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_core::Endpoint;
+
+    fn service(annotations: &[(&str, &str)]) -> Arc<VPCService> {
+        let mut service = VPCService::new("api", Default::default());
+        service.metadata.namespace = Some("default".to_string());
+        for (key, value) in annotations {
+            service.annotations_mut().insert(key.to_string(), value.to_string());
+        }
+        Arc::new(service)
+    }
+
+    #[test]
+    fn test_request_target() {
+        let request = |service: &str, endpoint: Option<&str>| DrainRequest {
+            service: service.to_string(),
+            endpoint: endpoint.map(str::to_string),
+            reason: None,
+        };
+        assert_eq!(request("default/api", None).target(), Some(("default", "api")));
+        assert_eq!(request("default/api", Some("10.0.0.1:8080")).target(), Some(("default", "api")));
+        assert_eq!(request("api", None).target(), None);
+        assert_eq!(request("default/api", Some("10.0.0.1")).target(), None);
+    }
+
+    #[tokio::test]
+    async fn test_publish_applies_drains() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = vec![
+            Endpoint { ip: "10.0.0.1".into(), port: 8080, ready: true, priority: 0 },
+            Endpoint { ip: "10.0.0.2".into(), port: 8080, ready: true, priority: 0 },
+        ];
+        registry
+            .register_service("default".into(), "api".into(), 8080, "HTTP".into(), endpoints)
+            .await
+            .unwrap();
+        let drains = Drains::new(registry.clone(), None);
+
+        drains.publish(&[service(&[(DRAIN_ANNOTATION, "10.0.0.2:8080")])]).await;
+        let ready: Vec<bool> = registry.get_endpoints("default/api").await.unwrap().iter().map(|e| e.ready).collect();
+        assert_eq!(ready, vec![true, false]);
+        assert_eq!(drains.list()[0].endpoints, vec!["10.0.0.2:8080".to_string()]);
+
+        drains.publish(&[service(&[(MAINTENANCE_ANNOTATION, DEFAULT_REASON)])]).await;
+        assert!(registry.in_maintenance("default/api").await);
+        assert!(registry.get_endpoints("default/api").await.unwrap().iter().all(|e| e.ready));
+
+        drains.publish(&[service(&[])]).await;
+        assert!(!registry.in_maintenance("default/api").await);
+        assert!(drains.list().is_empty());
+
+        // Nothing is recorded without a client
+        let request = DrainRequest { service: "default/api".to_string(), endpoint: None, reason: None };
+        assert!(matches!(drains.set(&request, true).await, Err(DrainError::Unavailable)));
+    }
+
+    #[tokio::test]
+    async fn test_publish_applies_health_checks() {
+        use router_api::v1alpha1::vpc_service::HealthCheckConfig as ServiceHealthCheck;
+        use router_proxy::{HealthCheckConfig, HealthChecker};
+        use std::time::Duration;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = vec![Endpoint { ip: "10.0.0.1".into(), port: 8080, ready: true, priority: 0 }];
+        registry
+            .register_service("default".into(), "api".into(), 8080, "HTTP".into(), endpoints)
+            .await
+            .unwrap();
+        let drains = Drains::new(registry.clone(), None);
+        let checker = HealthChecker::new(HealthCheckConfig::default());
+
+        let mut checked = (*service(&[])).clone();
+        checked.spec.health_check = Some(ServiceHealthCheck {
+            interval_seconds: 30,
+            ..ServiceHealthCheck::http("/ready")
+        });
+        drains.publish(&[Arc::new(checked)]).await;
+
+        // The monitor checks each service with the config built from its spec
+        let config = checker.service_config(&registry.get_service("default/api").await.unwrap());
+        assert_eq!(config.http_path, "/ready");
+        assert_eq!(config.check_interval, Duration::from_secs(30));
+
+        drains.publish(&[service(&[])]).await;
+        let config = checker.service_config(&registry.get_service("default/api").await.unwrap());
+        assert_eq!(config.http_path, "/healthz");
+        assert_eq!(config.check_interval, Duration::from_secs(10));
+    }
+}
@@ -1,173 +1,487 @@
 use anyhow::Result;
+use clap::Parser;
 use hyper::{
-    body::Bytes,
+    body::{Body, Bytes},
+    header::{HeaderValue, ALT_SVC},
     server::conn::http1,
     service::service_fn,
     Request, Response, StatusCode,
 };
-use hyper_util::rt::tokio::TokioIo;
+use hyper_util::rt::tokio::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
 use http_body_util::Full;
-use router_core::ServiceRegistry;
-use router_proxy::{HttpProxy, HealthCheckConfig, HealthChecker, TrafficPolicy, RequestForwarder, TlsServerConfig, MiddlewareChain, LoggingMiddleware, HeaderInspectionMiddleware, MetricsCollector, MetricsMiddleware, TracingMiddleware, TlsClientConfig};
+use router_core::cli::LogArgs;
+use router_core::{BuildInfo, ServiceRegistry};
+use router_proxy::{problem, RequestIdMiddleware, WasmMiddleware, WasmPluginConfig, AuthzDecision, ExtAuthorizer, ExtAuthzConfig, PolicyAuthorizer, PolicyAuthzConfig, AccessLogConfig, AccessLogEntry, AccessLogFormat, AccessLogUpstream, AccessLogger, ErrorFormat, ForwardError, InflightTracker, CacheConfig, CacheLookup, ResponseCache, PathLabelConfig, PathLabeler, TcpProxy, TcpProxyConfig, LoadBalancer, ActiveConnection, CachingResolver, DnsCacheConfig, PoolConfig, HttpProxy, HealthCheckConfig, HealthCheckMonitor, HealthChecker, TimeoutPolicy, TrafficPolicy, RequestForwarder, ResponseLimit, UpstreamService, TlsServerConfig, MiddlewareChain, LoggingMiddleware, HeaderInspectionMiddleware, MetricsCollector, MetricsMiddleware, TracingMiddleware, TlsClientConfig, OAuth2ClientConfig, OAuth2TokenProvider, OAuth2TokenInjector, TokenExchangeConfig, TokenExchanger, ExchangeTarget, Redactor, PiiAction, PiiPolicy, PiiScanner, GraphQLGuard, GraphQLLimits, ConcurrencyConfig, ConcurrencyLimiter, AffinityKeyExtractor, SessionPins, Replica, ReplicaRing, Ownership, RateLimiter, StateStore, StateStoreConfig, ConditionalConfig, ConditionalResponder, ConnectionBuckets, ThrottledBody, UpstreamProtocol, CircuitBreakerRegistry, SharedCircuitState, Http3Config, Http3Server, EndpointStatsRecorder, VpcTrafficRecorder, RequestBodyError, ForwardedConfig, ForwardedHeaders, MetricsBackend, MetricsSink, StatsdSink, OtlpSink, CompressionConfig, ResponseCompressor, HeaderRewrite, UpstreamHost, PathRewrite, RouteRedirect, StaticResponse, ClientProtocol, ClientProtocolConfig, FaultInjector, TrailersBody, UpstreamTrailers, ServerTiming, DebugConfig, RequestDebugger, UpstreamTiming, DEBUG_SPAN, normalize_path};
+use router_api::v1alpha1::vpc_egress::RateLimitConfig;
+use router_api::v1alpha1::vpc_service::ConnectionPoolConfig;
+use router_core::{Endpoint, SourceVpc};
+use router_galactic::nat::{NatTable, DEFAULT_NAT_PREFIX};
+use router_proxy::load_balancer::DEFAULT_FAILOVER_THRESHOLD;
+use ipnetwork::Ipv6Network;
+use router_api::v1alpha1::vpc_route::AffinitySource;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
 use tokio_rustls::TlsAcceptor;
-use tracing::{info, debug, warn};
-use tracing_subscriber::fmt::init as tracing_init;
+use tracing::{info, debug, warn, Instrument};
+use tracing_subscriber::EnvFilter;
 
+mod admin;
+mod cli;
+mod config;
+mod drains;
+mod policies;
+mod registration;
 mod router;
+mod routes;
+mod services;
+mod sources;
+mod shutdown;
+mod tasks;
 
-use router::Router;
+use admin::AdminEndpoint;
+use router::{Route, Router};
+use shutdown::Drain;
+
+/// Version and build of this gateway, served at /version
+static BUILD_INFO: BuildInfo = router_core::build_info!();
+
+/// How often secrets referenced by routes are re-read
+const SECRET_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Shared components used by every connection handler
+struct GatewayState {
+    /// VPCRoutes requests are matched against, and how their backends are chosen
+    router: Arc<Router>,
+    forwarder: Arc<RequestForwarder>,
+    middleware: Arc<MiddlewareChain>,
+    metrics_collector: Arc<MetricsCollector>,
+    token_injector: Arc<OAuth2TokenInjector>,
+    token_exchanger: Option<Arc<TokenExchanger>>,
+    pii_scanner: Arc<PiiScanner>,
+    /// Concurrency limit of requests whose VPCRoute sets none
+    concurrency_limiter: Option<Arc<ConcurrencyLimiter>>,
+    replica_ring: Option<Arc<ReplicaRing>>,
+    replica_key: Option<AffinityKeyExtractor>,
+    /// Session-to-endpoint pins shared between replicas, for routes with affinity
+    session_pins: Option<SessionPins>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    rate_limit_key: Option<AffinityKeyExtractor>,
+    conditional: Option<Arc<ConditionalResponder>>,
+    response_cache: Option<ResponseCache>,
+    /// API key allowed to purge the response cache
+    cache_purge_key: Option<String>,
+    compressor: Option<Arc<ResponseCompressor>>,
+    client_protocol: ClientProtocol,
+    endpoint_stats: Arc<EndpointStatsRecorder>,
+    /// Traffic between source and destination VPCs, listed at /vpc-traffic
+    vpc_traffic: Arc<VpcTrafficRecorder>,
+    /// Requests being handled, listed at /inflight
+    inflight: InflightTracker,
+    forwarded_headers: ForwardedHeaders,
+    debugger: Option<RequestDebugger>,
+    server_timing: bool,
+    error_format: ErrorFormat,
+    access_log: Option<Arc<AccessLogger>>,
+    ext_authz: Option<Arc<ExtAuthorizer>>,
+    /// Cedar policies authorizing requests, loaded from ConfigMaps
+    authz_policy: Option<Arc<PolicyAuthorizer>>,
+    /// Backend requests are forwarded to while no VPCRoutes are loaded
+    backend_url: String,
+    request_ids: RequestIdMiddleware,
+    drain: Drain,
+    /// RouterConfig loaded at startup, if the gateway uses one
+    router_config: Option<config::ConfigVersion>,
+    /// Bearer token the controller sends with config pushes
+    config_token: Option<String>,
+    /// Routing resources applied, reported in the replica's RouterGateway
+    routes: Arc<routes::RouteTables>,
+    /// Source VPCs by attachment address
+    sources: Arc<sources::Sources>,
+    /// Endpoints and services drained by an administrator, set at /drains
+    drains: Arc<drains::Drains>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_init();
+    let cli = cli::Cli::parse();
+    tracing_init(&cli.log);
 
-    info!("Starting router-gateway...");
+    info!("Starting router-gateway {}...", BUILD_INFO);
+
+    // Cluster-wide settings; read before any other setting
+    let router_config = config::load(&cli).await;
 
     // Create service registry
     let registry = Arc::new(ServiceRegistry::new());
     info!("Service registry initialized");
 
-    // Create HTTP proxy
-    let proxy = Arc::new(HttpProxy::new(registry.clone()));
-    info!("HTTP proxy initialized");
-
     // Create router
-    let router = Arc::new(Router::new(registry.clone()));
+    let mut router = Router::new(registry.clone()).with_sticky_cookie_key(&load_sticky_cookie_key());
+    if let Ok(secrets_dir) = config::var("ROUTER_SECRETS_DIR") {
+        info!("Resolving route secrets from {}", secrets_dir);
+        router = router.with_secrets_dir(secrets_dir);
+    }
+    let router = Arc::new(router);
     info!("Router initialized");
 
-    // Initialize health checker
+    // Route secrets are re-read in the background, never per request, so
+    // rotated Secrets take effect within a refresh period
+    if router.has_secrets_dir() {
+        let router = router.clone();
+        tasks::spawn("secret-refresh", async move {
+            let mut refresh = tokio::time::interval(SECRET_REFRESH_INTERVAL);
+            loop {
+                refresh.tick().await;
+                router.refresh_secrets();
+            }
+        });
+    }
+
+    // Initialize health checker; services override these settings in their spec
     let health_check_config = HealthCheckConfig {
         http_path: "/healthz".to_string(),
         check_interval: Duration::from_secs(10),
         timeout: Duration::from_secs(5),
         unhealthy_threshold: 3,
         healthy_threshold: 2,
+        ..Default::default()
     };
-    let _health_checker = Arc::new(HealthChecker::new(health_check_config));
+    let health_checker = Arc::new(load_health_checker(health_check_config));
     info!("Health checker initialized");
 
     // Initialize traffic policy
-    let _traffic_policy = Arc::new(TrafficPolicy::default());
+    let _traffic_policy = Arc::new(TrafficPolicy {
+        timeout: load_timeout_policy(),
+        ..Default::default()
+    });
     info!("Traffic policy initialized");
-    info!("  - Timeout: {:?}", _traffic_policy.timeout.request_timeout);
+    info!(
+        "  - Timeouts: connect {:?}, first byte {:?}, idle {:?}, total {:?}",
+        _traffic_policy.timeout.connect_timeout,
+        _traffic_policy.timeout.first_byte_timeout,
+        _traffic_policy.timeout.idle_timeout,
+        _traffic_policy.timeout.total_timeout
+    );
     info!("  - Max Retries: {}", _traffic_policy.retry.max_retries);
     info!("  - Circuit Breaker Failure Threshold: {}", _traffic_policy.circuit_breaker.failure_threshold);
 
+    // Initialize metrics collector
+    let mut metrics_collector = MetricsCollector::new()
+        .expect("Failed to create metrics collector");
+    if let Some(sink) = load_metrics_sink() {
+        metrics_collector = metrics_collector.with_sink(sink);
+    }
+    metrics_collector.set_build_info(&BUILD_INFO);
+    let metrics_collector = Arc::new(metrics_collector);
+    info!("Metrics collector initialized");
+
     // Initialize request forwarder with optional mTLS support
     let client_mtls_config = load_client_mtls_config();
     let forwarder = if let Some(mtls_config) = client_mtls_config {
         match RequestForwarder::with_tls(Duration::from_secs(30), mtls_config) {
             Ok(forwarder) => {
                 info!("Request forwarder initialized with mTLS support");
-                Arc::new(forwarder)
+                forwarder
             }
             Err(e) => {
                 warn!("Failed to initialize mTLS forwarder: {}, falling back to HTTP-only", e);
-                Arc::new(RequestForwarder::new(Duration::from_secs(30)))
+                RequestForwarder::new(Duration::from_secs(30))
             }
         }
     } else {
-        Arc::new(RequestForwarder::new(Duration::from_secs(30)))
+        RequestForwarder::new(Duration::from_secs(30))
     };
-    info!("Request forwarder initialized with 30s timeout");
+    let forwarder = forwarder
+        .with_timeout_policy(_traffic_policy.timeout.clone())
+        .with_retry_policy(_traffic_policy.retry.clone())
+        .with_circuit_breakers(Arc::new(load_circuit_breakers(&_traffic_policy).await))
+        .with_metrics(metrics_collector.clone());
+    let forwarder = load_max_request_body(load_upstream_protocols(forwarder));
+    let forwarder = load_dns_cache(forwarder, &metrics_collector);
+    let forwarder = load_connection_pools(forwarder);
+    let forwarder = load_upstream_concurrency(forwarder);
+    let forwarder = Arc::new(forwarder);
+    info!("Request forwarder initialized");
 
-    // Initialize metrics collector
-    let metrics_collector = MetricsCollector::new()
-        .expect("Failed to create metrics collector");
-    let metrics_collector = Arc::new(metrics_collector);
-    info!("Metrics collector initialized");
+    // Initialize OAuth2 token injection for backends that require it
+    let token_injector = Arc::new(load_oauth2_injector());
+
+    // Initialize token exchange for identity propagation across trust domains
+    let token_exchanger = load_token_exchanger().map(Arc::new);
+
+    // Request IDs come first so every other middleware sees them
+    let request_ids = load_request_ids();
+
+    // WebAssembly plugins, placed early or late in the chain
+    let (early_plugins, late_plugins) = load_wasm_plugins();
 
     // Initialize middleware chain
-    let middleware = Arc::new(
-        MiddlewareChain::new()
-            .add(TracingMiddleware::new())
-            .add(LoggingMiddleware)
-            .add(HeaderInspectionMiddleware::new(vec![
-                "content-type".to_string(),
-                "authorization".to_string(),
-                "user-agent".to_string(),
-            ]))
-            .add(MetricsMiddleware::new((*metrics_collector).clone()))
-    );
-    info!("Middleware chain initialized with tracing, logging, header inspection, and metrics");
+    let mut chain = MiddlewareChain::new().add(request_ids.clone());
+    for plugin in early_plugins {
+        chain = chain.add(plugin);
+    }
+    chain = chain
+        .add(TracingMiddleware::new())
+        .add(LoggingMiddleware)
+        .add(HeaderInspectionMiddleware::new(vec![
+            "content-type".to_string(),
+            "authorization".to_string(),
+            "user-agent".to_string(),
+        ]))
+        .add(MetricsMiddleware::new((*metrics_collector).clone()).with_path_labels(load_path_labels()));
+    for plugin in late_plugins {
+        chain = chain.add(plugin);
+    }
+    let middleware = Arc::new(chain);
+    info!("Middleware chain initialized with request IDs, tracing, logging, header inspection, and metrics");
+
+    // PII scanning for responses of routes with a PII policy
+    let pii_scanner = Arc::new(PiiScanner::new()?);
+
+    // Capacity limit on proxied requests, unless their route sets its own
+    let concurrency_limiter = load_concurrency_limiter().map(Arc::new);
+
+    // Replica ownership of session-sticky clients
+    let (replica_ring, replica_key) = match load_replica_ring() {
+        Some((ring, key)) => (Some(Arc::new(ring)), key),
+        None => (None, None),
+    };
+
+    // Endpoints of affinity sessions, shared between replicas
+    let session_pins = load_session_pins().await;
+
+    // Per-client request rate limits, optionally shared between replicas
+    let (rate_limiter, rate_limit_key) = match load_rate_limiter().await {
+        Some((limiter, key)) => (Some(Arc::new(limiter)), key),
+        None => (None, None),
+    };
+
+    // ETags and conditional requests answered at the edge for routes with an ETag policy
+    let conditional = load_conditional_responder().map(Arc::new);
+
+    // Cached GET responses for caching routes
+    let response_cache = load_response_cache(&metrics_collector);
+
+    // gzip/brotli compression of eligible responses
+    let compressor = load_response_compressor()
+        .map(|c| Arc::new(c.with_metrics(metrics_collector.clone())));
+
+    // Gateway phase timings for browser devtools
+    let server_timing = config::var("ROUTER_SERVER_TIMING")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if server_timing {
+        info!("Server-Timing headers enabled");
+    }
+
+    // Traffic between VPCs, for chargeback and capacity planning
+    let vpc_traffic = Arc::new(VpcTrafficRecorder::new().with_metrics(metrics_collector.clone()));
+
+    // The replica's RouterGateway, when running in Kubernetes; its client
+    // also records administrative drains
+    let registration = registration::Registration::from_env(&cli, router_config.clone(), metrics_collector.clone()).await;
+    let drains = Arc::new(drains::Drains::new(registry.clone(), registration.as_ref().map(|r| r.client())));
 
-    // Try to load TLS configuration from environment or default
-    let tls_config = load_tls_config();
+    // Policies are read from ConfigMaps, so without Kubernetes none load
+    let authz_policy = load_authz_policy(cli.namespace.as_deref());
+    if authz_policy.is_some() && registration.is_none() {
+        warn!("Authorization policies are loaded from ConfigMaps, which needs --pod-name and --namespace; every request will be denied");
+    }
+
+    let state = Arc::new(GatewayState {
+        router,
+        forwarder,
+        middleware,
+        metrics_collector,
+        token_injector,
+        token_exchanger,
+        pii_scanner,
+        concurrency_limiter,
+        replica_ring,
+        replica_key,
+        session_pins,
+        rate_limiter,
+        rate_limit_key,
+        conditional,
+        response_cache,
+        cache_purge_key: config::var("ROUTER_CACHE_PURGE_KEY").ok().filter(|k| !k.is_empty()),
+        compressor,
+        client_protocol: load_client_protocol(),
+        endpoint_stats: Arc::new(EndpointStatsRecorder::new()),
+        vpc_traffic,
+        inflight: InflightTracker::new(),
+        forwarded_headers: load_forwarded_headers(),
+        debugger: load_request_debugger(),
+        server_timing,
+        error_format: load_error_format(),
+        access_log: load_access_log().map(Arc::new),
+        ext_authz: load_ext_authz().map(Arc::new),
+        authz_policy: authz_policy.as_ref().map(|(authorizer, _)| authorizer.clone()),
+        // Backend requests are forwarded to without VPCRoutes (ROUTER_BACKEND_URL, default: http://backend-service:8080)
+        backend_url: config::var("ROUTER_BACKEND_URL").unwrap_or_else(|_| "http://backend-service:8080".to_string()),
+        request_ids,
+        drain: load_drain(),
+        router_config,
+        // Config pushes are refused unless ROUTER_CONFIG_TOKEN is set
+        config_token: config::var("ROUTER_CONFIG_TOKEN").ok().filter(|t| !t.is_empty()),
+        routes: Arc::new(routes::RouteTables::new()),
+        sources: Arc::new(sources::Sources::new(load_nat_prefix())),
+        drains,
+    });
+
+    // Report this replica, and the route changes it applies, in its
+    // RouterGateway; route by VPCRoutes to the VPCServices they name;
+    // resolve source VPCs from VPCAttachment addresses; apply drains from
+    // VPCService annotations and authorization policies from ConfigMaps
+    if let Some(registration) = registration {
+        if let Some((authorizer, source)) = authz_policy {
+            tasks::spawn("authz-policy-watch", policies::watch(registration.client(), source, authorizer));
+        }
+        tasks::spawn("route-watch", routes::watch(registration.client(), state.routes.clone()));
+        tasks::spawn("router-watch", routes::watch_router(registration.client(), state.router.clone()));
+        tasks::spawn("source-watch", sources::watch(registration.client(), state.sources.clone()));
+        tasks::spawn("service-watch", services::watch(registration.client(), registry.clone(), state.drains.clone()));
+        tasks::spawn("gateway-registration", registration.run(state.clone()));
+        let prefetch = load_cache_prefetch();
+        if !prefetch.is_empty() {
+            tasks::spawn("cache-prefetch", prefetch_cache(state.clone(), prefetch));
+        }
+    }
+
+    // Try to load TLS configuration from environment or default; HTTP/2 is
+    // negotiated via ALPN on the TLS listener
+    let tls_config = load_tls_config().map(TlsServerConfig::with_http2);
     let tls_acceptor = tls_config.as_ref().map(|config| {
         TlsAcceptor::from(config.config.clone())
     });
 
-    // Start HTTP server on port 8080
-    let http_addr: SocketAddr = ([0, 0, 0, 0], 8080).into();
+    // Start HTTP server
+    let http_addr: SocketAddr = ([0, 0, 0, 0], cli.http_port()).into();
     let http_listener = TcpListener::bind(&http_addr).await?;
-    info!("HTTP server listening on {}", http_addr);
+    let h2c = cli.h2c();
+    info!(
+        "HTTP server listening on {}{}",
+        http_addr,
+        if h2c { " (HTTP/1.1 and h2c)" } else { "" }
+    );
 
-    // Optionally start HTTPS server on port 8443
+    // Optionally start HTTPS server
+    let mut https_task = None;
     if tls_acceptor.is_some() {
-        let https_addr: SocketAddr = ([0, 0, 0, 0], 8443).into();
+        let https_addr: SocketAddr = ([0, 0, 0, 0], cli.https_port()).into();
         let https_listener = TcpListener::bind(&https_addr).await?;
         info!("HTTPS server listening on {} (TLS configured)", https_addr);
 
+        // Optionally serve HTTP/3 over QUIC and advertise it on HTTPS responses
+        let alt_svc = match (load_http3_config(&cli), tls_config.as_ref()) {
+            (Some(http3), Some(tls)) => {
+                let http3_addr: SocketAddr = ([0, 0, 0, 0], http3.port).into();
+                match Http3Server::bind(tls, http3_addr) {
+                    Ok(server) => {
+                        let state = state.clone();
+                        tasks::spawn("http3-server", server.serve(move |peer_addr| {
+                            let state = state.clone();
+                            let connection_buckets = Arc::new(ConnectionBuckets::default());
+                            move |req| serve_request(req, peer_addr, "https", state.clone(), connection_buckets.clone())
+                        }));
+                        Some(http3.alt_svc())
+                    }
+                    Err(e) => {
+                        warn!("Failed to start HTTP/3 listener on {}: {}", http3_addr, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
         let tls_acceptor = tls_acceptor.clone();
-        let proxy = proxy.clone();
-        let router = router.clone();
-        let forwarder = forwarder.clone();
-        let middleware = middleware.clone();
-        let metrics_collector = metrics_collector.clone();
-
-        tokio::task::spawn(accept_https_connections(
-            https_listener,
-            proxy,
-            router,
-            forwarder,
-            middleware,
-            metrics_collector,
-            tls_acceptor.unwrap(),
+        let state = state.clone();
+
+        https_task = Some(tasks::spawn(
+            "https-accept",
+            accept_https_connections(https_listener, state, tls_acceptor.unwrap(), alt_svc),
         ));
     } else {
         warn!("TLS not configured - HTTPS listener not started");
         warn!("Set ROUTER_TLS_CERT and ROUTER_TLS_KEY environment variables to enable HTTPS");
     }
 
-    // Accept HTTP connections in a loop
+    // Stream TCP services on their own ports
+    for (port, proxy) in load_tcp_proxies(&registry, &state.metrics_collector).await {
+        let addr: SocketAddr = ([0, 0, 0, 0], port).into();
+        let listener = TcpListener::bind(&addr).await?;
+        info!("TCP proxy for {} listening on {}", proxy.service_id(), addr);
+        tasks::spawn(&format!("tcp-proxy:{}", port), Arc::new(proxy).serve(listener));
+    }
+
+    // Check registered endpoints, taking failing ones out of rotation
+    let health_monitor = Arc::new(
+        HealthCheckMonitor::new(health_checker, registry.clone()).with_metrics(state.metrics_collector.clone()),
+    );
+    health_monitor.start_monitoring();
+
+    // Report not-ready and drain on SIGTERM or Ctrl-C
+    tasks::spawn("shutdown-signal", {
+        let state = state.clone();
+        async move {
+            shutdown::signal().await;
+            info!("Shutting down: no longer ready, draining connections");
+            state.drain.start();
+        }
+    });
+
+    // Accept HTTP connections until shutdown starts
+    let connections = GracefulShutdown::new();
     loop {
-        let (stream, peer_addr) = http_listener.accept().await?;
+        let (stream, peer_addr) = tokio::select! {
+            accepted = http_listener.accept() => accepted?,
+            _ = state.drain.started() => break,
+        };
         let io = TokioIo::new(stream);
 
-        let proxy = proxy.clone();
-        let router = router.clone();
-        let forwarder = forwarder.clone();
-        let middleware = middleware.clone();
-        let metrics_collector = metrics_collector.clone();
+        let state = state.clone();
+        let connection_buckets = Arc::new(ConnectionBuckets::default());
+        let watcher = connections.watcher();
 
-        tokio::task::spawn(async move {
+        tasks::spawn("http-connection", async move {
             let service = service_fn(move |req| {
-                let proxy = proxy.clone();
-                let router = router.clone();
-                let forwarder = forwarder.clone();
-                let middleware = middleware.clone();
-                let metrics_collector = metrics_collector.clone();
-                handle_request(req, proxy, router, forwarder, middleware, metrics_collector)
+                serve_request(req, peer_addr, "http", state.clone(), connection_buckets.clone())
             });
 
-            if let Err(e) = http1::Builder::new()
-                .serve_connection(io, service)
-                .await
-            {
+            let result = if h2c {
+                // Detects the HTTP/2 connection preface, otherwise serves HTTP/1.1
+                let builder = auto::Builder::new(TokioExecutor::new());
+                watcher.watch(builder.serve_connection(io, service)).await
+            } else {
+                watcher
+                    .watch(http1::Builder::new().serve_connection(io, service))
+                    .await
+                    .map_err(Into::into)
+            };
+            if let Err(e) = result {
                 debug!("Error serving HTTP connection from {}: {}", peer_addr, e);
             }
         });
     }
+
+    drop(http_listener);
+    state.drain.finish(connections, "HTTP").await;
+    if let Some(task) = https_task {
+        let _ = task.await;
+    }
+    info!("router-gateway stopped");
+    Ok(())
 }
 
 /// Load server-side TLS configuration from environment variables
 fn load_tls_config() -> Option<TlsServerConfig> {
-    let cert_path = std::env::var("ROUTER_TLS_CERT").ok();
-    let key_path = std::env::var("ROUTER_TLS_KEY").ok();
+    let cert_path = config::var("ROUTER_TLS_CERT").ok();
+    let key_path = config::var("ROUTER_TLS_KEY").ok();
 
     match (cert_path, key_path) {
         (Some(cert_path), Some(key_path)) => {
@@ -210,8 +524,8 @@ fn load_tls_config() -> Option<TlsServerConfig> {
 /// - ROUTER_CLIENT_CA: Optional path to CA certificate for server verification
 /// - ROUTER_CLIENT_VERIFY_SERVER: "true" or "false" (default: true)
 fn load_client_mtls_config() -> Option<TlsClientConfig> {
-    let cert_path = std::env::var("ROUTER_CLIENT_CERT").ok();
-    let key_path = std::env::var("ROUTER_CLIENT_KEY").ok();
+    let cert_path = config::var("ROUTER_CLIENT_CERT").ok();
+    let key_path = config::var("ROUTER_CLIENT_KEY").ok();
 
     match (cert_path, key_path) {
         (Some(cert_path), Some(key_path)) => {
@@ -221,12 +535,12 @@ fn load_client_mtls_config() -> Option<TlsClientConfig> {
             ) {
                 (Ok(cert), Ok(key)) => {
                     // Load optional CA certificate
-                    let ca_cert = std::env::var("ROUTER_CLIENT_CA").ok()
+                    let ca_cert = config::var("ROUTER_CLIENT_CA").ok()
                         .and_then(|ca_path| std::fs::read(&ca_path).ok());
                     let has_ca_cert = ca_cert.is_some();
 
                     // Load server verification setting (default: true)
-                    let verify_server = std::env::var("ROUTER_CLIENT_VERIFY_SERVER")
+                    let verify_server = config::var("ROUTER_CLIENT_VERIFY_SERVER")
                         .ok()
                         .map(|v| v.to_lowercase() == "true")
                         .unwrap_or(true);
@@ -264,157 +578,3163 @@ fn load_client_mtls_config() -> Option<TlsClientConfig> {
     }
 }
 
-/// Accept HTTPS connections with TLS
-async fn accept_https_connections(
-    listener: TcpListener,
-    proxy: Arc<HttpProxy>,
-    router: Arc<Router>,
-    forwarder: Arc<RequestForwarder>,
-    middleware: Arc<MiddlewareChain>,
-    metrics_collector: Arc<MetricsCollector>,
-    tls_acceptor: TlsAcceptor,
-) {
-    loop {
-        match listener.accept().await {
-            Ok((stream, peer_addr)) => {
-                let tls_acceptor = tls_acceptor.clone();
-                let proxy = proxy.clone();
-                let router = router.clone();
-                let forwarder = forwarder.clone();
-                let middleware = middleware.clone();
-                let metrics_collector = metrics_collector.clone();
+/// Build the endpoint health checker, probing over HTTPS if configured
+///
+/// The client certificate from ROUTER_CLIENT_CERT and ROUTER_CLIENT_KEY, if
+/// set, is presented to endpoints requiring mTLS.
+///
+/// Environment variables:
+/// - ROUTER_HEALTH_CHECK_TLS: "true" to check endpoints over HTTPS (default: false)
+/// - ROUTER_HEALTH_CHECK_SERVER_NAME: Name verified against endpoint certificates
+///   (default: the endpoint IP)
+fn load_health_checker(mut config: HealthCheckConfig) -> HealthChecker {
+    let tls = config::var("ROUTER_HEALTH_CHECK_TLS")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+    if !tls {
+        return HealthChecker::new(config);
+    }
 
-                tokio::task::spawn(async move {
-                    match tls_acceptor.accept(stream).await {
-                        Ok(tls_stream) => {
-                            let io = TokioIo::new(tls_stream);
-                            let service = service_fn(move |req| {
-                                let proxy = proxy.clone();
-                                let router = router.clone();
-                                let forwarder = forwarder.clone();
-                                let middleware = middleware.clone();
-                                let metrics_collector = metrics_collector.clone();
-                                handle_request(req, proxy, router, forwarder, middleware, metrics_collector)
-                            });
+    config.tls_server_name = config::var("ROUTER_HEALTH_CHECK_SERVER_NAME").ok();
+    let tls_config = load_client_mtls_config()
+        .unwrap_or_else(|| TlsClientConfig::new(Vec::new(), Vec::new(), None, true));
+    let has_client_cert = !tls_config.cert_pem.is_empty();
+    match HealthChecker::with_tls(config.clone(), tls_config) {
+        Ok(checker) => {
+            info!("Health checks use HTTPS (client certificate: {})", has_client_cert);
+            checker
+        }
+        Err(e) => {
+            warn!("Failed to configure HTTPS health checks, falling back to HTTP: {}", e);
+            HealthChecker::new(config)
+        }
+    }
+}
 
-                            if let Err(e) = http1::Builder::new()
-                                .serve_connection(io, service)
-                                .await
-                            {
-                                debug!("Error serving HTTPS connection from {}: {}", peer_addr, e);
-                            }
-                        }
-                        Err(e) => {
-                            debug!("TLS error from {}: {}", peer_addr, e);
-                        }
-                    }
-                });
+/// Load HTTP/3 listener configuration, enabled by `--http3`
+///
+/// HTTP/3 requires the HTTPS listener to be configured.
+///
+/// Environment variables:
+/// - ROUTER_HTTP3_ALT_SVC_MAX_AGE_SECS: How long clients may cache the Alt-Svc advertisement (default: 86400)
+fn load_http3_config(cli: &cli::Cli) -> Option<Http3Config> {
+    if !cli.http3() {
+        return None;
+    }
+
+    let mut config = Http3Config::default();
+    if let Some(port) = cli.http3_port() {
+        config.port = port;
+    }
+    if let Some(max_age) = config::var("ROUTER_HTTP3_ALT_SVC_MAX_AGE_SECS").ok().and_then(|v| v.parse().ok()) {
+        config.alt_svc_max_age = Duration::from_secs(max_age);
+    }
+    Some(config)
+}
+
+/// Load per-upstream protocol selection from environment variables
+///
+/// Environment variables:
+/// - ROUTER_UPSTREAM_PROTOCOLS: Comma-separated `upstream=protocol` pairs, where
+///   upstream is a VPCService (namespace/name), or the configured backend's host:port,
+///   and protocol is `auto` (default), `http1`, or `http2`/`h2c`
+fn load_upstream_protocols(mut forwarder: RequestForwarder) -> RequestForwarder {
+    let Ok(protocols) = config::var("ROUTER_UPSTREAM_PROTOCOLS") else {
+        return forwarder;
+    };
+
+    for entry in protocols.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=').and_then(|(upstream, protocol)| {
+            UpstreamProtocol::from_string(protocol.trim()).map(|p| (upstream.trim(), p))
+        }) {
+            Some((upstream, protocol)) => {
+                info!("Upstream {} uses {}", upstream, protocol.as_str());
+                forwarder = forwarder.with_upstream_protocol(upstream, protocol);
             }
-            Err(e) => {
-                warn!("Error accepting HTTPS connection: {}", e);
+            None => warn!("Ignoring invalid upstream protocol setting: {}", entry),
+        }
+    }
+    forwarder
+}
+
+/// Load connection pool settings from environment variables
+///
+/// Pool settings use the VPCService connectionPool field names:
+/// maxIdlePerHost, idleTimeoutSeconds, tcpKeepaliveSeconds, and maxConnections.
+///
+/// Environment variables:
+/// - ROUTER_POOL_MAX_IDLE_PER_HOST: Idle connections kept per upstream host (default: unlimited)
+/// - ROUTER_POOL_IDLE_TIMEOUT_SECS: How long idle connections are kept (default: 90)
+/// - ROUTER_POOL_TCP_KEEPALIVE_SECS: TCP keepalive interval, 0 to disable (default: 30)
+/// - ROUTER_UPSTREAM_POOLS: Semicolon-separated `upstream name=value ...` entries giving
+///   a VPCService (namespace/name), or the configured backend's host:port, its own pool,
+///   e.g. `default/backend maxConnections=100 idleTimeoutSeconds=30`
+fn load_connection_pools(mut forwarder: RequestForwarder) -> RequestForwarder {
+    let env = |var: &str| config::var(var).ok().and_then(|v| v.parse().ok());
+    let shared = ConnectionPoolConfig {
+        max_idle_per_host: env("ROUTER_POOL_MAX_IDLE_PER_HOST"),
+        idle_timeout_seconds: env("ROUTER_POOL_IDLE_TIMEOUT_SECS"),
+        tcp_keepalive_seconds: env("ROUTER_POOL_TCP_KEEPALIVE_SECS"),
+        max_connections: None,
+    };
+    let shared = PoolConfig::from_spec(&shared);
+    if shared != PoolConfig::default() {
+        info!("Upstream connection pool: {:?}", shared);
+        forwarder = forwarder.with_pool_config(shared);
+    }
+
+    let Ok(pools) = config::var("ROUTER_UPSTREAM_POOLS") else {
+        return forwarder;
+    };
+    for entry in pools.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut fields = entry.split_whitespace();
+        let Some(upstream) = fields.next() else {
+            continue;
+        };
+        let mut spec = ConnectionPoolConfig::default();
+        let mut valid = true;
+        for field in fields {
+            let value = field.split_once('=').and_then(|(name, value)| Some((name, value.parse().ok()?)));
+            match value {
+                Some(("maxIdlePerHost", value)) => spec.max_idle_per_host = Some(value),
+                Some(("idleTimeoutSeconds", value)) => spec.idle_timeout_seconds = Some(value),
+                Some(("tcpKeepaliveSeconds", value)) => spec.tcp_keepalive_seconds = Some(value),
+                Some(("maxConnections", value)) => spec.max_connections = Some(value),
+                _ => valid = false,
             }
         }
+        if !valid {
+            warn!("Ignoring invalid upstream pool setting: {}", entry);
+            continue;
+        }
+        let config = PoolConfig::from_spec(&spec);
+        info!("Upstream {} uses its own connection pool: {:?}", upstream, config);
+        forwarder = forwarder.with_upstream_pool(upstream, config);
     }
+    forwarder
 }
 
-async fn handle_request(
-    req: Request<hyper::body::Incoming>,
-    _proxy: Arc<HttpProxy>,
-    _router: Arc<Router>,
-    forwarder: Arc<RequestForwarder>,
-    middleware: Arc<MiddlewareChain>,
-    metrics_collector: Arc<MetricsCollector>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    use router_proxy::MiddlewareContext;
+/// Load in-flight request limits per upstream from environment variables
+///
+/// Requests over an upstream's limit get a 503 with Retry-After.
+///
+/// Environment variables:
+/// - ROUTER_UPSTREAM_CONCURRENCY: Semicolon-separated `upstream name=value ...` entries for a
+///   VPCService (namespace/name), or the configured backend's host:port,
+///   e.g. `default/backend maxConcurrentRequests=50 queueLength=10 queueTimeoutMs=250`
+fn load_upstream_concurrency(mut forwarder: RequestForwarder) -> RequestForwarder {
+    let Ok(limits) = config::var("ROUTER_UPSTREAM_CONCURRENCY") else {
+        return forwarder;
+    };
+    for entry in limits.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut fields = entry.split_whitespace();
+        let Some(upstream) = fields.next() else {
+            continue;
+        };
+        let mut config = ConcurrencyConfig::default();
+        let mut valid = true;
+        for field in fields {
+            let value = field.split_once('=').and_then(|(name, value)| Some((name, value.parse().ok()?)));
+            match value {
+                Some(("maxConcurrentRequests", value)) => config.max_concurrent_requests = value,
+                Some(("queueLength", value)) => config.queue_length = value,
+                Some(("queueTimeoutMs", value)) => config.queue_timeout = Duration::from_millis(value as u64),
+                _ => valid = false,
+            }
+        }
+        if !valid {
+            warn!("Ignoring invalid upstream concurrency setting: {}", entry);
+            continue;
+        }
+        info!(
+            "Upstream {} limited to {} requests in flight, queue of {} for up to {:?}",
+            upstream, config.max_concurrent_requests, config.queue_length, config.queue_timeout
+        );
+        forwarder = forwarder.with_upstream_concurrency(upstream, config);
+    }
+    forwarder
+}
 
-    let method = req.method().clone();
-    let path = req.uri().path().to_string();
+/// Load TCP proxy listeners from environment variables
+///
+/// Each service is registered in the gateway's registry with protocol TCP,
+/// so its endpoints are balanced and health checked like any other.
+///
+/// Environment variables:
+/// - ROUTER_TCP_PROXIES: Semicolon-separated `port namespace/name ip:port,ip:port` entries,
+///   e.g. `5432 default/postgres 10.0.0.1:5432,10.0.0.2:5432`; an endpoint suffixed
+///   `@N`, like `10.0.1.1:5432@1`, is in failover tier N
+/// - ROUTER_TCP_CONNECT_TIMEOUT_SECS: Timeout for connecting to an endpoint (default: 5)
+/// - ROUTER_TCP_SLOW_START_SECS: Window over which newly ready endpoints ramp up to their full share (default: 0, off)
+/// - ROUTER_TCP_FAILOVER_THRESHOLD: Ready fraction of a tier below which the next tier takes traffic too (default: 0.7)
+async fn load_tcp_proxies(registry: &Arc<ServiceRegistry>, metrics: &Arc<MetricsCollector>) -> Vec<(u16, TcpProxy)> {
+    let Ok(proxies) = config::var("ROUTER_TCP_PROXIES") else {
+        return Vec::new();
+    };
+    let mut config = TcpProxyConfig::default();
+    if let Some(secs) = config::var("ROUTER_TCP_CONNECT_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()) {
+        config.connect_timeout = Duration::from_secs(secs);
+    }
+    let slow_start = config::var("ROUTER_TCP_SLOW_START_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_default();
+    let failover_threshold = config::var("ROUTER_TCP_FAILOVER_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_FAILOVER_THRESHOLD);
 
-    debug!("{} {}", method, path);
+    let mut listeners = Vec::new();
+    for entry in proxies.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let fields = entry.split_whitespace().collect::<Vec<_>>();
+        let parsed = match fields.as_slice() {
+            [port, service, endpoints] => port.parse::<u16>().ok().and_then(|port| {
+                let (namespace, name) = service.split_once('/')?;
+                let endpoints = endpoints
+                    .split(',')
+                    .map(|endpoint| {
+                        let (addr, priority) = match endpoint.split_once('@') {
+                            Some((addr, priority)) => (addr, priority.parse().ok()?),
+                            None => (endpoint, 0),
+                        };
+                        let addr = addr.parse::<SocketAddr>().ok()?;
+                        Some(Endpoint { ip: addr.ip().to_string(), port: addr.port(), ready: true, priority })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((port, namespace, name, endpoints))
+            }),
+            _ => None,
+        };
+        let Some((port, namespace, name, endpoints)) = parsed else {
+            warn!("Ignoring invalid TCP proxy setting: {}", entry);
+            continue;
+        };
+        let service_port = endpoints.first().map_or(port, |e| e.port);
+        if let Err(e) = registry
+            .register_service(namespace.to_string(), name.to_string(), service_port, "TCP".to_string(), endpoints)
+            .await
+        {
+            warn!("Failed to register TCP service {}/{}: {}", namespace, name, e);
+            continue;
+        }
+        let load_balancer = LoadBalancer::new(Default::default())
+            .with_slow_start(slow_start)
+            .with_failover_threshold(failover_threshold)
+            .with_metrics(metrics.clone(), &format!("{}/{}", namespace, name));
+        let proxy = TcpProxy::new(registry.clone(), format!("{}/{}", namespace, name), load_balancer)
+            .with_config(config.clone())
+            .with_metrics(metrics.clone());
+        listeners.push((port, proxy));
+    }
+    listeners
+}
 
-    // Create middleware context
-    let context = MiddlewareContext::from_request(&req);
+/// Load OAuth2 client-credentials token injection from environment variables
+///
+/// Environment variables:
+/// - ROUTER_OAUTH2_TOKEN_URL: Token endpoint URL
+/// - ROUTER_OAUTH2_CLIENT_ID: OAuth2 client ID
+/// - ROUTER_OAUTH2_CLIENT_SECRET: OAuth2 client secret
+/// - ROUTER_OAUTH2_BACKENDS: Comma-separated VPCServices (namespace/name) to inject tokens for;
+///   the configured backend is named by its host:port
+/// - ROUTER_OAUTH2_SCOPES: Optional space-separated scopes
+/// - ROUTER_OAUTH2_FAIL_OPEN: "true" to forward without a token on failure (default: false)
+fn load_oauth2_injector() -> OAuth2TokenInjector {
+    let token_url = config::var("ROUTER_OAUTH2_TOKEN_URL").ok();
+    let client_id = config::var("ROUTER_OAUTH2_CLIENT_ID").ok();
+    let client_secret = config::var("ROUTER_OAUTH2_CLIENT_SECRET").ok();
+    let backends = config::var("ROUTER_OAUTH2_BACKENDS").ok();
 
-    // Call on_request middleware hooks
-    if let Err(e) = middleware.on_request(&context).await {
-        debug!("Middleware on_request error: {}", e);
+    match (token_url, client_id, client_secret, backends) {
+        (Some(token_url), Some(client_id), Some(client_secret), Some(backends)) => {
+            let mut config = OAuth2ClientConfig::new(token_url, client_id, client_secret);
+            config.scopes = config::var("ROUTER_OAUTH2_SCOPES")
+                .map(|s| s.split_whitespace().map(|s| s.to_string()).collect())
+                .unwrap_or_default();
+            config.fail_open = config::var("ROUTER_OAUTH2_FAIL_OPEN")
+                .map(|v| v.to_lowercase() == "true")
+                .unwrap_or(false);
+
+            let provider = match OAuth2TokenProvider::new(config) {
+                Ok(provider) => Arc::new(provider),
+                Err(e) => {
+                    warn!("Failed to initialize OAuth2 token provider: {}", e);
+                    return OAuth2TokenInjector::new();
+                }
+            };
+
+            let mut injector = OAuth2TokenInjector::new();
+            for backend in backends.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                info!("OAuth2 token injection enabled for backend {}", backend);
+                injector = injector.with_backend(backend.to_string(), provider.clone());
+            }
+            injector
+        }
+        _ => {
+            debug!("OAuth2 token injection not configured");
+            OAuth2TokenInjector::new()
+        }
     }
+}
 
-    // Metrics endpoint
-    if path == "/metrics" && method == "GET" {
-        let metrics_text = metrics_collector
-            .gather()
-            .unwrap_or_else(|_| "Failed to gather metrics\n".to_string());
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "text/plain; version=0.0.4")
-            .body(Full::new(Bytes::from(metrics_text)))
-            .unwrap();
+/// Load RFC 8693 token exchange configuration from environment variables
+///
+/// Environment variables:
+/// - ROUTER_TOKEN_EXCHANGE_URL: Security token service endpoint URL
+/// - ROUTER_TOKEN_EXCHANGE_CLIENT_ID: Client ID the router authenticates with
+/// - ROUTER_TOKEN_EXCHANGE_CLIENT_SECRET: Client secret the router authenticates with
+/// - ROUTER_TOKEN_EXCHANGE_TARGETS: Comma-separated `upstream=audience` pairs, where upstream
+///   is a VPCService (namespace/name), or the configured backend's host:port
+/// - ROUTER_TOKEN_EXCHANGE_REQUIRE_TOKEN: "true" to reject requests without a bearer token
+fn load_token_exchanger() -> Option<TokenExchanger> {
+    let token_url = config::var("ROUTER_TOKEN_EXCHANGE_URL").ok()?;
+    let client_id = config::var("ROUTER_TOKEN_EXCHANGE_CLIENT_ID").ok()?;
+    let client_secret = config::var("ROUTER_TOKEN_EXCHANGE_CLIENT_SECRET").ok()?;
+    let targets = config::var("ROUTER_TOKEN_EXCHANGE_TARGETS").ok()?;
 
-        if let Err(e) = middleware.on_response(&context, 200).await {
-            debug!("Middleware on_response error: {}", e);
+    let mut config = TokenExchangeConfig::new(token_url, client_id, client_secret);
+    config.require_subject_token = config::var("ROUTER_TOKEN_EXCHANGE_REQUIRE_TOKEN")
+        .map(|v| v.to_lowercase() == "true")
+        .unwrap_or(false);
+
+    let mut exchanger = match TokenExchanger::new(config) {
+        Ok(exchanger) => exchanger,
+        Err(e) => {
+            warn!("Failed to initialize token exchange: {}", e);
+            return None;
         }
+    };
 
-        return Ok(response);
+    for entry in targets.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (backend, audience) = match entry.split_once('=') {
+            Some((backend, audience)) => (backend, Some(audience.to_string())),
+            None => (entry, None),
+        };
+        info!("Token exchange enabled for backend {}", backend);
+        exchanger = exchanger.with_target(
+            backend.to_string(),
+            ExchangeTarget {
+                audience,
+                ..Default::default()
+            },
+        );
     }
 
-    // Health check endpoint
-    if path == "/healthz" {
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .body(Full::new(Bytes::from("OK\n")))
-            .unwrap();
+    Some(exchanger)
+}
 
-        if let Err(e) = middleware.on_response(&context, 200).await {
-            debug!("Middleware on_response error: {}", e);
-        }
+/// Load the concurrency limit for requests whose VPCRoute sets none from
+/// environment variables
+///
+/// Environment variables:
+/// - ROUTER_MAX_CONCURRENT_REQUESTS: Maximum requests in flight (unset disables the limit)
+/// - ROUTER_QUEUE_LENGTH: Maximum requests waiting for a slot (default: 0)
+/// - ROUTER_QUEUE_TIMEOUT_MS: Maximum time a request waits in the queue (default: 1000)
+fn load_concurrency_limiter() -> Option<ConcurrencyLimiter> {
+    let max_concurrent_requests = config::var("ROUTER_MAX_CONCURRENT_REQUESTS")
+        .ok()
+        .and_then(|v| v.parse().ok())?;
 
-        return Ok(response);
+    let mut config = ConcurrencyConfig {
+        max_concurrent_requests,
+        ..Default::default()
+    };
+    if let Some(queue_length) = config::var("ROUTER_QUEUE_LENGTH").ok().and_then(|v| v.parse().ok()) {
+        config.queue_length = queue_length;
+    }
+    if let Some(timeout_ms) = config::var("ROUTER_QUEUE_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()) {
+        config.queue_timeout = Duration::from_millis(timeout_ms);
     }
 
-    // Route the request based on VPCRoute rules
-    // Phase 2: Basic routing is available in Router module
-    // Phase 3: Health checks and policies are ready
-    // Phase 4.2: Using RequestForwarder for actual HTTP forwarding
-    // Phase 4.4: Middleware hooks integrated
+    info!(
+        "Concurrency limit enabled: {} in flight, queue of {} for up to {:?}",
+        config.max_concurrent_requests, config.queue_length, config.queue_timeout
+    );
+    Some(ConcurrencyLimiter::new(config))
+}
 
-    debug!("Processing request: {} {}", method, path);
+/// Load gateway replica ownership from environment variables
+///
+/// Environment variables:
+/// - ROUTER_REPLICA_ID: This replica's identifier
+/// - ROUTER_REPLICAS: Comma-separated `id=url` pairs for every replica, including this one
+/// - ROUTER_REPLICA_KEY: Optional `header:<name>` or `cookie:<name>` client key (default: client IP)
+fn load_replica_ring() -> Option<(ReplicaRing, Option<AffinityKeyExtractor>)> {
+    let self_id = config::var("ROUTER_REPLICA_ID").ok()?;
+    let replicas = config::var("ROUTER_REPLICAS").ok()?;
 
-    // Use forwarder to forward the request
-    let result = match forwarder.forward("http://backend-service:8080", req).await {
-        Ok(response) => {
-            // Convert response body to Full<Bytes>
-            let (parts, body) = response.into_parts();
-            let status = parts.status.as_u16();
-            let response = Response::from_parts(parts, Full::new(body));
+    let replicas: Vec<Replica> = replicas
+        .split(',')
+        .filter_map(|entry| entry.trim().split_once('='))
+        .map(|(id, url)| Replica {
+            id: id.trim().to_string(),
+            url: url.trim().to_string(),
+        })
+        .collect();
 
-            // Call on_response middleware hooks
-            if let Err(e) = middleware.on_response(&context, status).await {
-                debug!("Middleware on_response error: {}", e);
-            }
+    let key = parse_client_key("ROUTER_REPLICA_KEY");
 
-            Ok(response)
+    match ReplicaRing::new(self_id, replicas) {
+        Ok(ring) => {
+            info!(
+                "Replica ownership enabled for {} of {} replicas",
+                ring.self_id(),
+                ring.replicas().len()
+            );
+            Some((ring, key))
         }
         Err(e) => {
-            debug!("Forwarder error: {}", e);
-            let error_response = Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Full::new(Bytes::from("Internal Server Error\n")))
-                .unwrap();
+            warn!("Failed to configure replica ownership: {}", e);
+            None
+        }
+    }
+}
 
-            // Call on_error middleware hooks
-            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
-                debug!("Middleware on_error error: {}", mw_err);
-            }
+/// Load the validator store shared by routes with an ETag policy from
+/// environment variables
+///
+/// Routes add ETags and answer conditional requests when their VPCRoute
+/// sets an `etag` policy.
+///
+/// Environment variables:
+/// - ROUTER_ETAG_CAPACITY: Maximum number of URLs whose validators are remembered, 0 to turn it off (default: 10000)
+fn load_conditional_responder() -> Option<ConditionalResponder> {
+    let capacity = config::var("ROUTER_ETAG_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10_000);
+    if capacity == 0 {
+        info!("Conditional requests disabled");
+        return None;
+    }
 
-            // Call on_response middleware hooks for error response
-            if let Err(e) = middleware.on_response(&context, 500).await {
-                debug!("Middleware on_response error: {}", e);
-            }
+    match ConditionalResponder::new(capacity) {
+        Ok(responder) => {
+            info!("Conditional requests remember validators for up to {} URLs", responder.capacity());
+            Some(responder)
+        }
+        Err(e) => {
+            warn!("Failed to initialize conditional requests: {}", e);
+            None
+        }
+    }
+}
 
-            Ok(error_response)
+/// Load the metrics backend from environment variables
+///
+/// Prometheus metrics are always served on /metrics; the other backends
+/// receive the same measurements as they are recorded.
+///
+/// Environment variables:
+/// - ROUTER_METRICS_BACKEND: "prometheus" (default), "statsd", or "otlp"
+/// - ROUTER_STATSD_ADDR: StatsD agent address (default: 127.0.0.1:8125)
+/// - ROUTER_STATSD_PREFIX: Prefix for StatsD metric names (default: router)
+/// - ROUTER_OTLP_METRICS_ENDPOINT: OTLP/gRPC collector endpoint (default: http://localhost:4317)
+/// - ROUTER_OTLP_METRICS_INTERVAL_SECS: Export interval in seconds (default: 60)
+fn load_metrics_sink() -> Option<Arc<dyn MetricsSink>> {
+    let value = config::var("ROUTER_METRICS_BACKEND").ok()?;
+    let sink: anyhow::Result<Arc<dyn MetricsSink>> = match MetricsBackend::parse(&value) {
+        Some(MetricsBackend::Prometheus) => return None,
+        Some(MetricsBackend::Statsd) => {
+            let addr = config::var("ROUTER_STATSD_ADDR").unwrap_or_else(|_| "127.0.0.1:8125".to_string());
+            let prefix = config::var("ROUTER_STATSD_PREFIX").unwrap_or_else(|_| "router".to_string());
+            info!("Sending metrics to StatsD at {}", addr);
+            StatsdSink::new(&addr, &prefix).map(|s| Arc::new(s) as Arc<dyn MetricsSink>)
+        }
+        Some(MetricsBackend::Otlp) => {
+            let endpoint = config::var("ROUTER_OTLP_METRICS_ENDPOINT")
+                .unwrap_or_else(|_| "http://localhost:4317".to_string());
+            let interval = config::var("ROUTER_OTLP_METRICS_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60);
+            info!("Exporting metrics over OTLP to {} every {}s", endpoint, interval);
+            OtlpSink::new(&endpoint, Duration::from_secs(interval)).map(|s| Arc::new(s) as Arc<dyn MetricsSink>)
+        }
+        None => {
+            warn!("Unknown ROUTER_METRICS_BACKEND {:?}, using Prometheus only", value);
+            return None;
         }
     };
+    sink.map_err(|e| warn!("Failed to start {} metrics backend: {}", value, e)).ok()
+}
 
-    result
+/// Load upstream timeouts from environment variables
+///
+/// Environment variables:
+/// - ROUTER_CONNECT_TIMEOUT_SECS: Time to connect to a backend (default: 10)
+/// - ROUTER_FIRST_BYTE_TIMEOUT_SECS: Time from sending a request to its response headers (default: 30)
+/// - ROUTER_IDLE_TIMEOUT_SECS: Longest gap between response body chunks, 0 for unlimited (default: 60)
+/// - ROUTER_TOTAL_TIMEOUT_SECS: Time for the whole response, body included, 0 for unlimited (default: unlimited)
+fn load_timeout_policy() -> TimeoutPolicy {
+    let secs = |var: &str| config::var(var).ok().and_then(|v| v.parse::<u64>().ok()).map(Duration::from_secs);
+    let defaults = TimeoutPolicy::default();
+    TimeoutPolicy {
+        connect_timeout: secs("ROUTER_CONNECT_TIMEOUT_SECS").unwrap_or(defaults.connect_timeout),
+        first_byte_timeout: secs("ROUTER_FIRST_BYTE_TIMEOUT_SECS").unwrap_or(defaults.first_byte_timeout),
+        idle_timeout: secs("ROUTER_IDLE_TIMEOUT_SECS").map_or(defaults.idle_timeout, |d| Some(d).filter(|d| !d.is_zero())),
+        total_timeout: secs("ROUTER_TOTAL_TIMEOUT_SECS").map_or(defaults.total_timeout, |d| Some(d).filter(|d| !d.is_zero())),
+    }
+}
+
+/// Apply the request body size limit from environment variables
+///
+/// Environment variables:
+/// - ROUTER_MAX_REQUEST_BODY_BYTES: Largest request body accepted; larger bodies get 413 (default: unlimited)
+fn load_max_request_body(forwarder: RequestForwarder) -> RequestForwarder {
+    match config::var("ROUTER_MAX_REQUEST_BODY_BYTES").ok().and_then(|v| v.parse().ok()) {
+        Some(bytes) => {
+            info!("Request bodies limited to {} bytes", bytes);
+            forwarder.with_max_request_body(bytes)
+        }
+        None => forwarder,
+    }
+}
+
+/// Apply cached backend DNS resolution from environment variables
+///
+/// Environment variables:
+/// - ROUTER_DNS_CACHE: "true" to resolve backend hostnames through the cache (default: false)
+/// - ROUTER_DNS_MIN_TTL_SECS: Shortest time an answer is kept (default: 5)
+/// - ROUTER_DNS_MAX_TTL_SECS: Longest time an answer is kept (default: 300)
+/// - ROUTER_DNS_NEGATIVE_TTL_SECS: How long a failed lookup is remembered (default: 5)
+fn load_dns_cache(forwarder: RequestForwarder, metrics: &Arc<MetricsCollector>) -> RequestForwarder {
+    let enabled = config::var("ROUTER_DNS_CACHE")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if !enabled {
+        return forwarder;
+    }
+
+    let secs = |var: &str, default: Duration| {
+        config::var(var)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    };
+    let defaults = DnsCacheConfig::default();
+    let config = DnsCacheConfig {
+        min_ttl: secs("ROUTER_DNS_MIN_TTL_SECS", defaults.min_ttl),
+        max_ttl: secs("ROUTER_DNS_MAX_TTL_SECS", defaults.max_ttl),
+        negative_ttl: secs("ROUTER_DNS_NEGATIVE_TTL_SECS", defaults.negative_ttl),
+    };
+    match CachingResolver::from_system_conf(config.clone()) {
+        Ok(resolver) => {
+            info!("Backend DNS cache enabled: {:?}", config);
+            forwarder.with_resolver(resolver.with_metrics(metrics.clone()))
+        }
+        Err(e) => {
+            warn!("Failed to start backend DNS cache: {}, using system resolver", e);
+            forwarder
+        }
+    }
+}
+
+/// Load the prefix translated addresses of overlapping VPCs are allocated from
+///
+/// Environment variables:
+/// - ROUTER_NAT_PREFIX: IPv6 /48 routed to the VPCs by their identifiers (default: fd7a:7472::/48)
+fn load_nat_prefix() -> Ipv6Network {
+    let default = || NatTable::parse_prefix(DEFAULT_NAT_PREFIX).expect("valid default NAT prefix");
+    match config::var("ROUTER_NAT_PREFIX") {
+        Ok(value) => NatTable::parse_prefix(&value).unwrap_or_else(|e| {
+            warn!("Ignoring ROUTER_NAT_PREFIX: {}", e);
+            default()
+        }),
+        Err(_) => default(),
+    }
+}
+
+/// Load the key sticky session cookies are signed with
+///
+/// Without a key, a random one is generated and each replica only honors
+/// the cookies it set itself.
+///
+/// Environment variables:
+/// - ROUTER_STICKY_COOKIE_KEY: Secret shared by all replicas
+fn load_sticky_cookie_key() -> Vec<u8> {
+    match config::var("ROUTER_STICKY_COOKIE_KEY") {
+        Ok(key) if !key.is_empty() => key.into_bytes(),
+        _ => {
+            info!("ROUTER_STICKY_COOKIE_KEY unset, sticky cookies only hold on this replica");
+            rand::random::<[u8; 32]>().to_vec()
+        }
+    }
+}
+
+/// Load forwarded header handling from environment variables
+///
+/// Environment variables:
+/// - ROUTER_TRUSTED_PROXIES: Comma-separated addresses or CIDRs whose X-Forwarded-* headers are kept
+/// - ROUTER_FORWARDED_HEADER: "true" to also send the RFC 7239 Forwarded header (default: false)
+fn load_forwarded_headers() -> ForwardedHeaders {
+    let trusted_proxies = match config::var("ROUTER_TRUSTED_PROXIES") {
+        Ok(value) => ForwardedConfig::parse_trusted_proxies(&value).unwrap_or_else(|e| {
+            warn!("Ignoring ROUTER_TRUSTED_PROXIES: {}", e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    let forwarded_header = config::var("ROUTER_FORWARDED_HEADER")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    if !trusted_proxies.is_empty() {
+        info!("Trusting forwarded headers from {:?}", trusted_proxies);
+    }
+    ForwardedHeaders::new(ForwardedConfig {
+        trusted_proxies,
+        forwarded_header,
+    })
+}
+
+/// Load response compression settings from environment variables
+///
+/// Environment variables:
+/// - ROUTER_COMPRESSION: "true" to compress eligible responses (default: false)
+/// - ROUTER_COMPRESSION_MIN_SIZE: Smallest body compressed, in bytes (default: 1024)
+/// - ROUTER_COMPRESSION_GZIP_LEVEL: gzip level 0-9 (default: 6)
+/// - ROUTER_COMPRESSION_BROTLI_QUALITY: Brotli quality 0-11 (default: 4)
+fn load_response_compressor() -> Option<ResponseCompressor> {
+    let enabled = config::var("ROUTER_COMPRESSION")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if !enabled {
+        return None;
+    }
+
+    let defaults = CompressionConfig::default();
+    let config = CompressionConfig {
+        min_size: config::var("ROUTER_COMPRESSION_MIN_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.min_size),
+        gzip_level: config::var("ROUTER_COMPRESSION_GZIP_LEVEL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.gzip_level),
+        brotli_quality: config::var("ROUTER_COMPRESSION_BROTLI_QUALITY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.brotli_quality),
+        ..defaults
+    };
+
+    info!(
+        "Response compression enabled (min size {} bytes, gzip level {}, brotli quality {})",
+        config.min_size, config.gzip_level, config.brotli_quality
+    );
+    Some(ResponseCompressor::new(config))
+}
+
+/// Load how request metrics are labelled by path from environment variables
+///
+/// Environment variables:
+/// - ROUTER_METRICS_PATH_TEMPLATES: Comma-separated templates paths are
+///   collapsed onto, e.g. "/users/:id,/static/*"
+/// - ROUTER_METRICS_EXCLUDED_PATHS: Comma-separated path prefixes with no
+///   per-path metrics
+/// - ROUTER_METRICS_UNMATCHED_AS_OTHER: Label paths matching no template
+///   as "other" (default: false)
+/// - ROUTER_METRICS_MAX_PATHS: Most distinct path labels before the rest
+///   are labelled "other" (default: 1000)
+fn load_path_labels() -> PathLabeler {
+    let list = |var: &str| -> Vec<String> {
+        config::var(var)
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let defaults = PathLabelConfig::default();
+    let config = PathLabelConfig {
+        templates: list("ROUTER_METRICS_PATH_TEMPLATES"),
+        excluded: list("ROUTER_METRICS_EXCLUDED_PATHS"),
+        unmatched_as_other: config::var("ROUTER_METRICS_UNMATCHED_AS_OTHER")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(defaults.unmatched_as_other),
+        max_labels: config::var("ROUTER_METRICS_MAX_PATHS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.max_labels),
+    };
+    if !config.templates.is_empty() || !config.excluded.is_empty() {
+        info!(
+            "Metrics paths: {} templates, {} excluded prefixes, at most {} labels",
+            config.templates.len(),
+            config.excluded.len(),
+            config.max_labels
+        );
+    }
+    PathLabeler::new(config)
+}
+
+/// Load the connection draining deadline from environment variables
+///
+/// Environment variables:
+/// - ROUTER_DRAIN_TIMEOUT_SECS: How long open connections get to finish after
+///   SIGTERM before the gateway exits (default: 25)
+fn load_drain() -> Drain {
+    let secs = config::var("ROUTER_DRAIN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(25);
+    Drain::new(Duration::from_secs(secs))
+}
+
+/// Load the format of errors the gateway answers itself
+///
+/// Environment variables:
+/// - ROUTER_ERROR_FORMAT: "text" (default) or "problem+json" for RFC 7807 documents
+fn load_error_format() -> ErrorFormat {
+    match config::var("ROUTER_ERROR_FORMAT") {
+        Ok(value) => ErrorFormat::from_string(&value).unwrap_or_else(|| {
+            warn!("Ignoring invalid ROUTER_ERROR_FORMAT: {}", value);
+            ErrorFormat::default()
+        }),
+        Err(_) => ErrorFormat::default(),
+    }
+}
+
+/// Load request ID handling from environment variables
+///
+/// Environment variables:
+/// - ROUTER_REQUEST_ID_HEADER: Header carrying request IDs (default: x-request-id)
+/// - ROUTER_TRUST_REQUEST_ID: Keep valid IDs sent by clients (default: true)
+fn load_request_ids() -> RequestIdMiddleware {
+    let mut request_ids = RequestIdMiddleware::new();
+    if let Ok(value) = config::var("ROUTER_REQUEST_ID_HEADER") {
+        match hyper::header::HeaderName::from_bytes(value.as_bytes()) {
+            Ok(header) => request_ids = request_ids.with_header(header),
+            Err(_) => warn!("Ignoring invalid ROUTER_REQUEST_ID_HEADER: {}", value),
+        }
+    }
+    if let Ok(value) = config::var("ROUTER_TRUST_REQUEST_ID") {
+        request_ids = request_ids.with_trust_incoming(!value.eq_ignore_ascii_case("false"));
+    }
+    request_ids
+}
+
+/// Load the access log from environment variables
+///
+/// Environment variables:
+/// - ROUTER_ACCESS_LOG: "stdout" or the path of a file to log requests to
+/// - ROUTER_ACCESS_LOG_FORMAT: "combined" (default) or "json"
+/// - ROUTER_ACCESS_LOG_MAX_BYTES: Size at which the file is rotated (default: 104857600)
+/// - ROUTER_ACCESS_LOG_MAX_FILES: Rotated files kept (default: 5)
+fn load_access_log() -> Option<AccessLogger> {
+    let target = config::var("ROUTER_ACCESS_LOG").ok()?;
+    let mut config = AccessLogConfig::default();
+    if target != "stdout" {
+        config.path = Some(target.into());
+    }
+    if let Ok(value) = config::var("ROUTER_ACCESS_LOG_FORMAT") {
+        match AccessLogFormat::from_string(&value) {
+            Some(format) => config.format = format,
+            None => warn!("Ignoring invalid ROUTER_ACCESS_LOG_FORMAT: {}", value),
+        }
+    }
+    if let Some(max_bytes) = config::var("ROUTER_ACCESS_LOG_MAX_BYTES").ok().and_then(|v| v.parse().ok()) {
+        config.max_bytes = max_bytes;
+    }
+    if let Some(max_files) = config::var("ROUTER_ACCESS_LOG_MAX_FILES").ok().and_then(|v| v.parse().ok()) {
+        config.max_files = max_files;
+    }
+
+    let destination = config.path.as_ref().map_or_else(|| "stdout".to_string(), |p| p.display().to_string());
+    match AccessLogger::new(config) {
+        Ok(logger) => {
+            info!("Access log enabled ({:?} to {})", logger.format(), destination);
+            Some(logger)
+        }
+        Err(e) => {
+            warn!("Failed to initialize access log: {}", e);
+            None
+        }
+    }
+}
+
+/// Load WebAssembly plugins from environment variables
+///
+/// Returns the plugins that run right after request IDs are assigned, then
+/// those that run after the built-in middleware.
+///
+/// Environment variables:
+/// - ROUTER_WASM_PLUGINS: Comma-separated plugin files; a file prefixed
+///   with "early:" runs before the built-in middleware, e.g.
+///   "early:/plugins/auth.wasm,/plugins/tag.wasm"
+/// - ROUTER_WASM_FUEL: Fuel each plugin callback may burn before it is
+///   aborted (default: 10000000)
+/// - ROUTER_WASM_FAIL_OPEN: Let requests through when a plugin fails
+///   instead of answering 500 (default: false)
+fn load_wasm_plugins() -> (Vec<WasmMiddleware>, Vec<WasmMiddleware>) {
+    let mut early = Vec::new();
+    let mut late = Vec::new();
+    let Ok(plugins) = config::var("ROUTER_WASM_PLUGINS") else {
+        return (early, late);
+    };
+
+    let defaults = WasmPluginConfig::default();
+    let fuel_per_call = config::var("ROUTER_WASM_FUEL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(defaults.fuel_per_call);
+    let fail_open = config::var("ROUTER_WASM_FAIL_OPEN")
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(defaults.fail_open);
+
+    for entry in plugins.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (is_early, path) = match entry.strip_prefix("early:") {
+            Some(path) => (true, path),
+            None => (false, entry),
+        };
+        let name = std::path::Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.to_string());
+        let config = WasmPluginConfig {
+            name,
+            fuel_per_call,
+            fail_open,
+        };
+        match WasmMiddleware::from_file(path, config) {
+            Ok(plugin) => {
+                info!("WebAssembly plugin {} runs {} in the middleware chain", plugin.plugin_name(), if is_early { "early" } else { "late" });
+                if is_early {
+                    early.push(plugin);
+                } else {
+                    late.push(plugin);
+                }
+            }
+            Err(e) => warn!("Failed to load WebAssembly plugin {}: {:#}", path, e),
+        }
+    }
+    (early, late)
+}
+
+/// Load external authorization from environment variables
+///
+/// Environment variables:
+/// - ROUTER_EXT_AUTHZ_URL: Base URL of the authorization service (enables checks)
+/// - ROUTER_EXT_AUTHZ_PATH_PREFIX: Prefix prepended to request paths in checks
+/// - ROUTER_EXT_AUTHZ_ROUTES: Comma-separated path prefixes to authorize (default: all)
+/// - ROUTER_EXT_AUTHZ_FORWARD_HEADERS: Comma-separated request headers sent
+///   with checks (default: authorization,cookie)
+/// - ROUTER_EXT_AUTHZ_UPSTREAM_HEADERS: Comma-separated headers copied from
+///   an allowing answer onto the upstream request
+/// - ROUTER_EXT_AUTHZ_TIMEOUT_MS: Check timeout (default: 200)
+/// - ROUTER_EXT_AUTHZ_FAIL_OPEN: Allow requests when the service fails (default: false)
+/// - ROUTER_EXT_AUTHZ_STATUS_ON_ERROR: Status answered when the service fails (default: 403)
+fn load_ext_authz() -> Option<ExtAuthorizer> {
+    let url = config::var("ROUTER_EXT_AUTHZ_URL").ok()?;
+    let list = |var: &str| -> Option<Vec<String>> {
+        config::var(var).ok().map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .map(|h| h.to_lowercase())
+                .collect()
+        })
+    };
+
+    let mut config = ExtAuthzConfig::new(url);
+    if let Ok(prefix) = config::var("ROUTER_EXT_AUTHZ_PATH_PREFIX") {
+        config.path_prefix = prefix;
+    }
+    if let Some(routes) = list("ROUTER_EXT_AUTHZ_ROUTES") {
+        config.route_prefixes = routes;
+    }
+    if let Some(headers) = list("ROUTER_EXT_AUTHZ_FORWARD_HEADERS") {
+        config.forward_headers = headers;
+    }
+    if let Some(headers) = list("ROUTER_EXT_AUTHZ_UPSTREAM_HEADERS") {
+        config.upstream_headers = headers;
+    }
+    if let Some(ms) = config::var("ROUTER_EXT_AUTHZ_TIMEOUT_MS").ok().and_then(|v| v.parse().ok()) {
+        config.timeout = Duration::from_millis(ms);
+    }
+    config.fail_open = config::var("ROUTER_EXT_AUTHZ_FAIL_OPEN").is_ok_and(|v| v.eq_ignore_ascii_case("true"));
+    if let Ok(value) = config::var("ROUTER_EXT_AUTHZ_STATUS_ON_ERROR") {
+        match value.parse().ok().and_then(|code| StatusCode::from_u16(code).ok()) {
+            Some(status) => config.status_on_error = status,
+            None => warn!("Ignoring invalid ROUTER_EXT_AUTHZ_STATUS_ON_ERROR: {}", value),
+        }
+    }
+
+    match ExtAuthorizer::new(config) {
+        Ok(authz) => {
+            info!(
+                "External authorization enabled via {} (timeout {:?}, fail {})",
+                authz.config().url,
+                authz.config().timeout,
+                if authz.config().fail_open { "open" } else { "closed" }
+            );
+            Some(authz)
+        }
+        Err(e) => {
+            warn!("Failed to initialize external authorization: {}", e);
+            None
+        }
+    }
+}
+
+/// Load embedded authorization policies from environment variables
+///
+/// Environment variables:
+/// - ROUTER_AUTHZ_POLICY_ENGINE: "cedar" to evaluate Cedar policies (enables
+///   checks; OPA Rego is not supported)
+/// - ROUTER_AUTHZ_POLICY_NAMESPACE: Namespace of the policy ConfigMaps
+///   (default: the replica's --namespace)
+/// - ROUTER_AUTHZ_POLICY_SELECTOR: Label selector of the policy ConfigMaps
+///   (default: router.datum.net/authz-policy=cedar)
+/// - ROUTER_AUTHZ_POLICY_ROUTES: Comma-separated path prefixes to authorize (default: all)
+/// - ROUTER_AUTHZ_POLICY_IDENTITY_HEADER: Header naming the authenticated
+///   client, the policies' principal
+/// - ROUTER_AUTHZ_POLICY_HEADERS: Comma-separated request headers available
+///   to policies in `context.headers`
+fn load_authz_policy(pod_namespace: Option<&str>) -> Option<(Arc<PolicyAuthorizer>, policies::PolicySource)> {
+    let engine = config::var("ROUTER_AUTHZ_POLICY_ENGINE").ok()?;
+    if !engine.eq_ignore_ascii_case("cedar") {
+        warn!("Ignoring unsupported ROUTER_AUTHZ_POLICY_ENGINE {:?}; only \"cedar\" is supported", engine);
+        return None;
+    }
+    let Some(namespace) = config::var("ROUTER_AUTHZ_POLICY_NAMESPACE").ok().or(pod_namespace.map(str::to_string)) else {
+        warn!("Authorization policies need ROUTER_AUTHZ_POLICY_NAMESPACE or --namespace; not enabling them");
+        return None;
+    };
+    let selector = config::var("ROUTER_AUTHZ_POLICY_SELECTOR").unwrap_or_else(|_| policies::DEFAULT_SELECTOR.to_string());
+    let list = |var: &str| -> Vec<String> {
+        config::var(var)
+            .map(|v| v.split(',').map(str::trim).filter(|h| !h.is_empty()).map(|h| h.to_string()).collect())
+            .unwrap_or_default()
+    };
+
+    let config = PolicyAuthzConfig {
+        identity_header: config::var("ROUTER_AUTHZ_POLICY_IDENTITY_HEADER").ok().map(|h| h.to_lowercase()),
+        context_headers: list("ROUTER_AUTHZ_POLICY_HEADERS").into_iter().map(|h| h.to_lowercase()).collect(),
+        route_prefixes: list("ROUTER_AUTHZ_POLICY_ROUTES"),
+    };
+    info!("Authorizing requests with Cedar policies from ConfigMaps in {} matching {}", namespace, selector);
+    Some((Arc::new(PolicyAuthorizer::new(config)), policies::PolicySource { namespace, selector }))
+}
+
+/// Load client protocol rules from environment variables
+///
+/// - ROUTER_MIN_HTTP_VERSION: Oldest HTTP version accepted, e.g. "1.1" to
+///   refuse HTTP/1.0 clients with 505 (default: "1.0")
+fn load_client_protocol() -> ClientProtocol {
+    let mut config = ClientProtocolConfig::default();
+    if let Ok(value) = config::var("ROUTER_MIN_HTTP_VERSION") {
+        match ClientProtocol::parse_version(&value) {
+            Some(version) => {
+                info!("Refusing clients older than {:?}", version);
+                config.min_version = version;
+            }
+            None => warn!("Ignoring invalid ROUTER_MIN_HTTP_VERSION {:?}", value),
+        }
+    }
+    ClientProtocol::new(config)
+}
+
+/// Load per-request debug mode from environment variables
+///
+/// Debug requests get forced trace sampling, trace-level logs, and timing
+/// headers on the response.
+/// - ROUTER_DEBUG_SECRET: Value the debug header must carry
+/// - ROUTER_DEBUG_HEADER: Debug header name (default: x-router-debug)
+/// - ROUTER_DEBUG_OPEN: Accept the header with any value (default: false)
+fn load_request_debugger() -> Option<RequestDebugger> {
+    let defaults = DebugConfig::default();
+    let config = DebugConfig {
+        header: config::var("ROUTER_DEBUG_HEADER").unwrap_or(defaults.header),
+        secret: config::var("ROUTER_DEBUG_SECRET").ok(),
+        open: config::var("ROUTER_DEBUG_OPEN")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(defaults.open),
+    };
+    if config.secret.is_none() && !config.open {
+        return None;
+    }
+
+    let header = config.header.clone();
+    match RequestDebugger::new(config) {
+        Ok(debugger) => {
+            if debugger.is_open() {
+                warn!("Debug header {} accepted without a secret", header);
+            } else {
+                info!("Debug header {} enabled", header);
+            }
+            Some(debugger)
+        }
+        Err(e) => {
+            warn!("Ignoring debug configuration: {}", e);
+            None
+        }
+    }
+}
+
+/// Initialize logging from RUST_LOG, with everything logged inside debug requests
+///
+/// With the `console` feature, tokio-console is also served.
+fn tracing_init(log: &LogArgs) {
+    use tracing_subscriber::prelude::*;
+
+    let mut filter = EnvFilter::from_default_env();
+    if let Ok(directive) = format!("[{}]=trace", DEBUG_SPAN).parse() {
+        filter = filter.add_directive(directive);
+    }
+    let registry = tracing_subscriber::registry().with(log.fmt_layer().with_filter(filter));
+
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+
+    registry.init();
+}
+
+/// Load the response cache shared by caching routes from environment variables
+///
+/// Routes cache their GET responses when the `cache` policy of their
+/// VPCRoute is enabled, within that policy's limits.
+///
+/// Environment variables:
+/// - ROUTER_CACHE_CAPACITY: Maximum number of cached responses, 0 to turn caching off (default: 1000)
+/// - ROUTER_CACHE_PURGE_KEY: API key, sent in X-API-Key, allowed to purge the cache
+///
+/// `DELETE /cache` purges cached responses; like other administrative
+/// requests it needs the ROUTER_CONFIG_TOKEN bearer token, unless it
+/// carries the purge key.
+fn load_response_cache(metrics: &Arc<MetricsCollector>) -> Option<ResponseCache> {
+    let capacity = config::var("ROUTER_CACHE_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1000);
+    if capacity == 0 {
+        info!("Response cache disabled");
+        return None;
+    }
+
+    match ResponseCache::new(capacity) {
+        Ok(cache) => {
+            info!("Response cache holds up to {} responses", cache.capacity());
+            Some(cache.with_metrics(metrics.clone()))
+        }
+        Err(e) => {
+            warn!("Failed to initialize response cache: {}", e);
+            None
+        }
+    }
+}
+
+/// Load the URLs to warm the response cache with at startup
+///
+/// Environment variables:
+/// - ROUTER_CACHE_PREFETCH: Comma-separated absolute URLs (`http://shop.example.com/catalog`)
+fn load_cache_prefetch() -> Vec<String> {
+    config::var("ROUTER_CACHE_PREFETCH")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Warm the response cache with `urls` once VPCRoutes are loaded
+///
+/// Each URL is requested through the gateway like a client request, so
+/// responses are only kept for routes whose cache policy is enabled, under
+/// the URL's host. A URL whose backend isn't ready yet is tried again a
+/// few times.
+async fn prefetch_cache(state: Arc<GatewayState>, urls: Vec<String>) {
+    while state.router.routes().is_empty() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    for url in urls {
+        for attempt in 1..=5 {
+            let req = match Request::get(url.as_str()).body(Full::new(Bytes::new())) {
+                Ok(req) => req,
+                Err(e) => {
+                    warn!("Not prefetching {}: {}", url, e);
+                    break;
+                }
+            };
+            match handle_request(req, ([127, 0, 0, 1], 0).into(), "http", state.clone(), false).await {
+                Ok(response) if response.status().is_success() => {
+                    info!("Prefetched {}", url);
+                    break;
+                }
+                Ok(response) => debug!("Prefetching {} answered {} (attempt {})", url, response.status(), attempt),
+                Err(e) => debug!("Prefetching {} failed: {} (attempt {})", url, e, attempt),
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// Parse a `header:<name>` or `cookie:<name>` client key setting
+fn parse_client_key(var: &str) -> Option<AffinityKeyExtractor> {
+    config::var(var).ok().and_then(|v| match v.split_once(':') {
+        Some(("header", name)) => Some(AffinityKeyExtractor::new(AffinitySource::Header, name)),
+        Some(("cookie", name)) => Some(AffinityKeyExtractor::new(AffinitySource::Cookie, name)),
+        _ => {
+            warn!("Ignoring invalid {} {:?}, using client IP", var, v);
+            None
+        }
+    })
+}
+
+/// Connect to the state store configured for a feature
+///
+/// `var` holds "memory" (the default), "redis://...", or "etcd://...".
+async fn load_state_store(var: &str) -> Option<Arc<dyn StateStore>> {
+    let value = config::var(var).unwrap_or_else(|_| "memory".to_string());
+    let store = match StateStoreConfig::parse(&value) {
+        Ok(config) => config.connect().await,
+        Err(e) => Err(e),
+    };
+    match store {
+        Ok(store) => {
+            info!("{} state store: {}", var, store.backend());
+            Some(store)
+        }
+        Err(e) => {
+            warn!("Failed to configure {}: {}", var, e);
+            None
+        }
+    }
+}
+
+/// Create per-upstream circuit breakers for the traffic policy
+///
+/// Environment variables:
+/// - ROUTER_CIRCUIT_BREAKER_STORE: Store for sharing circuit states between replicas (unset keeps them local)
+/// - ROUTER_CIRCUIT_BREAKER_STATE_TTL_SECS: How long a shared state is kept (default: 300)
+async fn load_circuit_breakers(policy: &TrafficPolicy) -> CircuitBreakerRegistry {
+    let breakers = CircuitBreakerRegistry::new(policy.circuit_breaker.clone());
+    if config::var("ROUTER_CIRCUIT_BREAKER_STORE").is_err() {
+        return breakers;
+    }
+    let Some(store) = load_state_store("ROUTER_CIRCUIT_BREAKER_STORE").await else {
+        return breakers;
+    };
+    let ttl = config::var("ROUTER_CIRCUIT_BREAKER_STATE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(300));
+    breakers.with_shared_state(SharedCircuitState::new(store, ttl))
+}
+
+/// Load shared session pins from environment variables
+///
+/// Pinned sessions stay on their endpoint while it is ready, even as
+/// endpoints come and go, and every replica sends them to the same one.
+///
+/// Environment variables:
+/// - ROUTER_AFFINITY_STORE: Store for sharing session pins between replicas (unset disables pins)
+/// - ROUTER_AFFINITY_PIN_TTL_SECS: How long an unused pin is kept (default: 3600)
+async fn load_session_pins() -> Option<SessionPins> {
+    config::var("ROUTER_AFFINITY_STORE").ok()?;
+    let store = load_state_store("ROUTER_AFFINITY_STORE").await?;
+    let ttl = config::var("ROUTER_AFFINITY_PIN_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(3600));
+    Some(SessionPins::new(store, ttl))
+}
+
+/// Load request rate limiting from environment variables
+///
+/// Environment variables:
+/// - ROUTER_RATE_LIMIT_RPS: Requests per second per client (unset disables rate limiting)
+/// - ROUTER_RATE_LIMIT_BURST: Extra requests allowed per second (default: 0)
+/// - ROUTER_RATE_LIMIT_KEY: Optional `header:<name>` or `cookie:<name>` client key (default: client IP)
+/// - ROUTER_RATE_LIMIT_STORE: Counter store shared by replicas (default: memory)
+async fn load_rate_limiter() -> Option<(RateLimiter, Option<AffinityKeyExtractor>)> {
+    let requests_per_second = config::var("ROUTER_RATE_LIMIT_RPS")
+        .ok()
+        .and_then(|v| v.parse().ok())?;
+    let burst_size = config::var("ROUTER_RATE_LIMIT_BURST")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let store = load_state_store("ROUTER_RATE_LIMIT_STORE").await?;
+    let config = RateLimitConfig {
+        requests_per_second,
+        burst_size,
+    };
+
+    info!("Rate limiting enabled: {} requests/s (burst {})", requests_per_second, burst_size);
+    Some((
+        RateLimiter::new(store, config, "router-gateway"),
+        parse_client_key("ROUTER_RATE_LIMIT_KEY"),
+    ))
+}
+
+/// Host a request is addressed to
+///
+/// HTTP/1.1 carries it in the Host header, HTTP/2 in the :authority pseudo-header.
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    req.headers()
+        .get(hyper::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()))
+}
+
+/// Build a GraphQL-style error response
+fn graphql_error_response(message: &str) -> Response<Full<Bytes>> {
+    let body = serde_json::json!({ "errors": [{ "message": message }] });
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+/// Accept HTTPS connections with TLS
+///
+/// With `alt_svc` set, every response advertises the HTTP/3 endpoint.
+async fn accept_https_connections(
+    listener: TcpListener,
+    state: Arc<GatewayState>,
+    tls_acceptor: TlsAcceptor,
+    alt_svc: Option<HeaderValue>,
+) {
+    let connections = GracefulShutdown::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = state.drain.started() => break,
+        };
+        match accepted {
+            Ok((stream, peer_addr)) => {
+                let tls_acceptor = tls_acceptor.clone();
+                let state = state.clone();
+                let connection_buckets = Arc::new(ConnectionBuckets::default());
+                let alt_svc = alt_svc.clone();
+                let watcher = connections.watcher();
+
+                tasks::spawn("https-connection", async move {
+                    match tls_acceptor.accept(stream).await {
+                        Ok(tls_stream) => {
+                            let io = TokioIo::new(tls_stream);
+                            let service = service_fn(move |req| {
+                                let response = serve_request(req, peer_addr, "https", state.clone(), connection_buckets.clone());
+                                let alt_svc = alt_svc.clone();
+                                async move {
+                                    let mut response = response.await?;
+                                    if let Some(alt_svc) = alt_svc {
+                                        response.headers_mut().insert(ALT_SVC, alt_svc);
+                                    }
+                                    Ok::<_, hyper::Error>(response)
+                                }
+                            });
+
+                            let builder = auto::Builder::new(TokioExecutor::new());
+                            if let Err(e) = watcher.watch(builder.serve_connection(io, service)).await {
+                                debug!("Error serving HTTPS connection from {}: {}", peer_addr, e);
+                            }
+                        }
+                        Err(e) => {
+                            debug!("TLS error from {}: {}", peer_addr, e);
+                        }
+                    }
+                });
+            }
+            Err(e) => {
+                warn!("Error accepting HTTPS connection: {}", e);
+            }
+        }
+    }
+
+    drop(listener);
+    state.drain.finish(connections, "HTTPS").await;
+}
+
+/// Handle a request and pace its response body by any bandwidth limits
+///
+/// `scheme` is the listener's scheme, "http" or "https".
+async fn serve_request<B>(
+    mut req: Request<B>,
+    peer_addr: SocketAddr,
+    scheme: &'static str,
+    state: Arc<GatewayState>,
+    connection_buckets: Arc<ConnectionBuckets>,
+) -> Result<Response<ThrottledBody<TrailersBody<Full<Bytes>>>>, hyper::Error>
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    // Every request carries an ID, forwarded upstream with its headers
+    let request_id = state.request_ids.ensure(req.headers_mut());
+    let request_id_header = state.request_ids.header().clone();
+
+    // Bandwidth limits and access log redaction follow the request's VPCRoute
+    let routes = state.router.routes();
+    let request = RequestContext::new(&state, &routes, peer_addr, &req);
+    let throttle = request.route.and_then(|route| {
+        let limiter = state.router.route_bandwidth_limiter(route)?;
+        let connection_bucket = connection_buckets.bucket(&route.id, &limiter);
+        Some((limiter, connection_bucket))
+    });
+    let redactor = route_redactor(request.route);
+
+    // Debug requests run in their own span so log filters can raise their verbosity
+    let debug = state.debugger.as_ref().is_some_and(|d| d.is_debug(req.headers()));
+    let span = if debug {
+        tracing::info_span!(DEBUG_SPAN, method = %req.method(), path = req.uri().path(), client = %peer_addr)
+    } else {
+        tracing::Span::none()
+    };
+
+    // Gateway errors become problem documents naming the request they failed
+    let problem_instance = (state.error_format == ErrorFormat::ProblemJson).then(|| req.uri().path().to_string());
+
+    // Request details for the access log, completed once the response is ready
+    let access_log = state.access_log.clone().map(|logger| {
+        let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let entry = AccessLogEntry {
+            timestamp: chrono::Utc::now(),
+            client_ip: peer_addr.ip(),
+            method: req.method().to_string(),
+            path: req.uri().path_and_query().map_or_else(|| req.uri().path().to_string(), |p| p.to_string()),
+            protocol: format!("{:?}", req.version()),
+            status: 0,
+            bytes: 0,
+            latency_ms: 0.0,
+            upstream: None,
+            request_id: Some(request_id.clone()),
+            trace_id: header("traceparent")
+                .and_then(|t| TracingMiddleware::parse_traceparent(&t))
+                .map(|(trace_id, _, _)| trace_id),
+            referer: header("referer"),
+            user_agent: header("user-agent"),
+        };
+        (logger, entry, redactor, Instant::now())
+    });
+
+    // HTTP/1.0 clients get a Content-Length and an explicit Connection header
+    let version = req.version();
+    let keep_alive = ClientProtocol::wants_keep_alive(version, req.headers());
+    let mut response = if state.client_protocol.is_allowed(version) {
+        handle_routed_request(req, peer_addr, scheme, state, debug, request).instrument(span).await?
+    } else {
+        debug!("Refusing {:?} request from {}", version, peer_addr);
+        let (parts, body) = ClientProtocol::version_not_supported_response().into_parts();
+        Response::from_parts(parts, Full::new(body))
+    };
+    let upstream = response.extensions_mut().remove::<AccessLogUpstream>().map(|u| u.0);
+    if let Some(instance) = problem_instance {
+        response = problem::render(response, |problem| {
+            problem.with_instance(instance).with_request_id(request_id.clone())
+        });
+    }
+    // Clients can quote the ID when reporting a failed request
+    if !response.headers().contains_key(&request_id_header) {
+        if let Ok(value) = hyper::header::HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert(request_id_header, value);
+        }
+    }
+    let body_len = response.body().size_hint().exact().unwrap_or_default();
+    ClientProtocol::prepare_response(version, keep_alive, response.headers_mut(), body_len);
+
+    if let Some((logger, mut entry, redactor, started)) = access_log {
+        entry.status = response.status().as_u16();
+        entry.bytes = body_len;
+        entry.upstream = upstream;
+        logger.log(&entry.with_latency(started.elapsed()).redacted(&redactor));
+    }
+
+    // Upstream trailers (gRPC status) follow the body; HTTP/1.0 has no way to send them
+    let trailers = response
+        .extensions_mut()
+        .remove::<UpstreamTrailers>()
+        .filter(|_| version > hyper::Version::HTTP_10);
+    if let Some(trailers) = trailers.as_ref().filter(|_| version == hyper::Version::HTTP_11) {
+        trailers.declare(response.headers_mut());
+    }
+    let response = response.map(|body| TrailersBody::new(body, trailers.map(|t| t.0)));
+
+    Ok(response.map(|body| match &throttle {
+        Some((limiter, connection_bucket)) => limiter.throttle(body, connection_bucket.as_ref()),
+        None => ThrottledBody::unlimited(body),
+    }))
+}
+
+/// The VPCRoute a request matched, selected once before it is handled
+struct RequestContext<'a> {
+    /// Whether any VPCRoutes were loaded; until they are, requests go to
+    /// the configured backend
+    routed: bool,
+    /// The VPCRoute the request matched
+    route: Option<&'a Route>,
+}
+
+impl<'a> RequestContext<'a> {
+    /// Match a request against `routes` by its normalized path
+    fn new<B>(state: &GatewayState, routes: &'a [Route], peer_addr: SocketAddr, req: &Request<B>) -> Self {
+        let path = normalize_path(req.uri().path()).unwrap_or_default();
+        let source = state.sources.resolve(peer_addr.ip());
+        let route = state.router.select_route(
+            routes,
+            source.as_ref(),
+            req.method().as_str(),
+            &path,
+            req.uri().query(),
+            req.headers(),
+        );
+        Self { routed: !routes.is_empty(), route }
+    }
+}
+
+/// Redaction rules for what middleware, traces and the access log record
+/// of a request on `route`: the defaults, and the route's own
+fn route_redactor(route: Option<&Route>) -> Arc<Redactor> {
+    match route.and_then(|route| route.spec.redaction.as_ref()) {
+        Some(policy) => Arc::new(Redactor::from_policy(policy)),
+        None => Arc::default(),
+    }
+}
+
+/// The header rewrite of a route with a header policy
+fn route_header_rewrite(route: &Route) -> Option<HeaderRewrite> {
+    match HeaderRewrite::from_policy(route.spec.headers.as_ref()?) {
+        Ok(rewrite) => Some(rewrite).filter(|rewrite| !rewrite.is_empty()),
+        Err(e) => {
+            debug!("Ignoring header rewrites of route {}: {}", route.id, e);
+            None
+        }
+    }
+}
+
+/// The path rewrite of a route with a path rewrite policy
+fn route_path_rewrite(route: &Route) -> Option<PathRewrite> {
+    match PathRewrite::from_policy(route.spec.path_rewrite.as_ref()?, &route.spec.r#match) {
+        Ok(rewrite) => rewrite,
+        Err(e) => {
+            debug!("Ignoring path rewrite of route {}: {}", route.id, e);
+            None
+        }
+    }
+}
+
+/// The redirect of a route that answers with one
+fn route_redirect(route: &Route) -> Option<RouteRedirect> {
+    match RouteRedirect::from_action(route.spec.redirect.as_ref()?, &route.spec.r#match) {
+        Ok(redirect) => Some(redirect),
+        Err(e) => {
+            debug!("Ignoring redirect of route {}: {}", route.id, e);
+            None
+        }
+    }
+}
+
+/// The Host header a route sends upstream, the client's unless its policy
+/// says otherwise
+fn route_upstream_host(route: &Route) -> UpstreamHost {
+    let Some(policy) = &route.spec.upstream_host else {
+        return UpstreamHost::Preserve;
+    };
+    UpstreamHost::from_policy(policy).unwrap_or_else(|e| {
+        debug!("Ignoring upstream host of route {}: {}", route.id, e);
+        UpstreamHost::Preserve
+    })
+}
+
+/// The fault injector of a route with a fault policy
+fn route_fault_injector(state: &GatewayState, route: &Route) -> Option<FaultInjector> {
+    match FaultInjector::from_policy(route.spec.fault.as_ref()?) {
+        Ok(injector) => Some(injector.with_metrics(state.metrics_collector.clone())),
+        Err(e) => {
+            debug!("Ignoring fault injection of route {}: {}", route.id, e);
+            None
+        }
+    }
+}
+
+/// Where a routed request goes
+enum RouteTarget {
+    /// Forward to the endpoint at this URL of the VPCService (namespace/name),
+    /// counted as one of its active connections until dropped, setting the
+    /// sticky cookie pinning the client to it, if any
+    Endpoint(String, String, ActiveConnection, Option<hyper::header::HeaderValue>),
+    /// Answer with the destination's fixed response
+    Direct(StaticResponse),
+}
+
+/// Choose the destination of `route` for a request, and the endpoint of its
+/// VPCService, or None when no destination is available
+async fn route_target(
+    state: &GatewayState,
+    route: &Route,
+    method: &hyper::Method,
+    headers: &hyper::HeaderMap,
+    source: Option<&SourceVpc>,
+) -> Option<RouteTarget> {
+    let router = &state.router;
+    let load_balancer = router.route_load_balancer(&route.id, &route.spec.load_balancing);
+    let destination = router
+        .select_destination(&route.spec, method.as_str(), &route.namespace, source, &load_balancer)
+        .await?;
+    if let Some(direct) = &destination.direct_response {
+        return match StaticResponse::from_destination(direct) {
+            Ok(response) => Some(RouteTarget::Direct(response)),
+            Err(e) => {
+                warn!("Ignoring direct response of {}: {}", route.id, e);
+                None
+            }
+        };
+    }
+
+    let service_ref = &destination.vpc_service_ref;
+    let service_id = format!("{}/{}", service_ref.namespace.as_deref().unwrap_or(&route.namespace), service_ref.name);
+    let (endpoint, sticky_cookie) = match router.sticky_cookie(&route.spec) {
+        Some(sticky) => router.select_sticky_endpoint(&service_id, &load_balancer, &sticky, headers).await?,
+        None => {
+            let affinity = route.spec.affinity.as_ref().map(AffinityKeyExtractor::from_policy);
+            let endpoint = router
+                .select_endpoint(&service_id, &load_balancer, affinity.as_ref(), state.session_pins.as_ref(), headers)
+                .await?;
+            (endpoint, None)
+        }
+    };
+    debug!("Forwarding {} on {} to {}:{} of {}", method, route.id, endpoint.ip, endpoint.port, service_id);
+    let port = destination.port.unwrap_or(endpoint.port);
+    let address = router.endpoint_address(&state.sources.nat(), &service_id, &endpoint, source).await;
+    let scheme = router.service_scheme(&service_id).await;
+    let connection = load_balancer.begin(&endpoint);
+    Some(RouteTarget::Endpoint(endpoint_url(scheme, &address, port), service_id, connection, sticky_cookie))
+}
+
+/// URL of an upstream at `address` and `port`, reached over `scheme`
+fn endpoint_url(scheme: &str, address: &str, port: u16) -> String {
+    match address.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(ip)) => format!("{}://[{}]:{}", scheme, ip, port),
+        _ => format!("{}://{}:{}", scheme, address, port),
+    }
+}
+
+/// Handle a request, matching it to a VPCRoute first
+async fn handle_request<B>(
+    req: Request<B>,
+    peer_addr: SocketAddr,
+    scheme: &'static str,
+    state: Arc<GatewayState>,
+    debug: bool,
+) -> Result<Response<Full<Bytes>>, hyper::Error>
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let routes = state.router.routes();
+    let request = RequestContext::new(&state, &routes, peer_addr, &req);
+    handle_routed_request(req, peer_addr, scheme, state, debug, request).await
+}
+
+/// Handle a request on the VPCRoute `request` matched
+async fn handle_routed_request<B>(
+    mut req: Request<B>,
+    peer_addr: SocketAddr,
+    scheme: &'static str,
+    state: Arc<GatewayState>,
+    debug: bool,
+    request: RequestContext<'_>,
+) -> Result<Response<Full<Bytes>>, hyper::Error>
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    use router_proxy::MiddlewareContext;
+
+    let started = Instant::now();
+    let middleware = &state.middleware;
+
+    // Authorization, routing and the backend all see one spelling of the
+    // path, so `/x/../admin` or `/%61dmin` can't get past a gate for /admin
+    match normalize_path(req.uri().path()) {
+        Some(normalized) if normalized != req.uri().path() => {
+            let path_and_query = match req.uri().query() {
+                Some(query) => format!("{}?{}", normalized, query),
+                None => normalized,
+            };
+            let mut parts = req.uri().clone().into_parts();
+            parts.path_and_query = path_and_query.parse().ok();
+            if let Ok(uri) = hyper::Uri::from_parts(parts) {
+                *req.uri_mut() = uri;
+            }
+        }
+        Some(_) => {}
+        None => {
+            debug!("Rejecting {} {}: path can't be normalized", req.method(), req.uri().path());
+            let (parts, body) = problem::error_response(StatusCode::BAD_REQUEST, "Invalid request path").into_parts();
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    debug!("{} {}", method, path);
+
+    // Create middleware context
+    let mut context = MiddlewareContext::from_request(&req);
+    context.redactor = route_redactor(request.route);
+    context.source_vpc = state.sources.resolve(peer_addr.ip());
+
+    // Call on_request middleware hooks
+    if let Err(e) = middleware.on_request(&context).await {
+        debug!("Middleware on_request error: {}", e);
+    }
+
+    // A plugin may answer the request itself
+    if let Some(local) = WasmMiddleware::local_response(&context) {
+        let status = StatusCode::from_u16(local.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if let Err(e) = middleware.on_response(&context, status.as_u16()).await {
+            debug!("Middleware on_response error: {}", e);
+        }
+        let response = Response::builder()
+            .status(status)
+            .header("Content-Type", "text/plain")
+            .body(Full::new(Bytes::from(local.body)))
+            .unwrap();
+        return Ok(response);
+    }
+
+    // Endpoints the gateway answers itself
+    if let Some(endpoint) = AdminEndpoint::from_request(&method, &path) {
+        let response = endpoint.respond(req, &state).await;
+
+        if let Err(e) = middleware.on_response(&context, response.status().as_u16()).await {
+            debug!("Middleware on_response error: {}", e);
+        }
+
+        return Ok(response);
+    }
+
+    // Match the request to a VPCRoute; until any are loaded, requests go
+    // to the configured backend
+    let route = if !request.routed {
+        None
+    } else {
+        let Some(route) = request.route else {
+            debug!("No route matches {} {}", method, path);
+            let (parts, body) = HttpProxy::not_found_response("No route matches the request").into_parts();
+
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        };
+        debug!("Routing {} {} by {}", method, path, route.id);
+        Some(route)
+    };
+
+    // Send clients to the canonical form of the path when the route asks for it
+    if let Some(canonical) = route.and_then(|route| state.router.canonical_path(&route.spec.r#match, &path)) {
+        let location = match req.uri().query() {
+            Some(query) => format!("{}?{}", canonical, query),
+            None => canonical,
+        };
+        debug!("Redirecting {} {} to canonical path {}", method, path, location);
+        let response = Response::builder()
+            .status(StatusCode::PERMANENT_REDIRECT)
+            .header(hyper::header::LOCATION, location)
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+
+        if let Err(e) = middleware.on_response(&context, 308).await {
+            debug!("Middleware on_response error: {}", e);
+        }
+
+        return Ok(response);
+    }
+
+    // Header changes of the route, made to the forwarded request and its response
+    let header_rewrite = route.and_then(route_header_rewrite);
+
+    // Listed at /inflight until the request is handled
+    let request_id = context.request_id();
+    let inflight = state.inflight.begin(method.as_str(), &path, request_id.as_deref());
+    req.extensions_mut().insert(inflight.handle());
+    // The forwarder holds backend responses to the route's size limit
+    let response_limit = route.and_then(|route| route.spec.response_limit.as_ref()).filter(|p| p.max_bytes > 0);
+    if let Some(policy) = response_limit {
+        req.extensions_mut().insert(ResponseLimit::from_policy(policy));
+    }
+
+    // Answer redirect routes without a backend
+    if let Some(redirect) = route.and_then(route_redirect) {
+        if let Some(location) = redirect.location(scheme, request_host(&req), req.uri()) {
+            debug!("Redirecting {} {} to {}", method, path, location);
+            let (parts, body) = redirect.response(&location).into_parts();
+
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    // Send session-sticky clients to the replica that owns their state
+    if let Some(ring) = &state.replica_ring {
+        let key = state
+            .replica_key
+            .as_ref()
+            .and_then(|k| k.extract(req.headers()))
+            .unwrap_or_else(|| peer_addr.ip().to_string());
+        let host = request_host(&req);
+        let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        if let Ownership::Redirect(location) = ring.check(&key, host, path_and_query) {
+            debug!("Redirecting {} {} to owning replica at {}", method, path, location);
+            let response = Response::builder()
+                .status(StatusCode::TEMPORARY_REDIRECT)
+                .header(hyper::header::LOCATION, location)
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+
+            if let Err(e) = middleware.on_response(&context, 307).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(response);
+        }
+    }
+
+    // Enforce per-client rate limits; a store outage fails open
+    if let Some(limiter) = &state.rate_limiter {
+        let key = state
+            .rate_limit_key
+            .as_ref()
+            .and_then(|k| k.extract(req.headers()))
+            .unwrap_or_else(|| peer_addr.ip().to_string());
+
+        match limiter.check(&key).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!("Rate limiting {} {} for {}", method, path, key);
+                let response = HttpProxy::too_many_requests_response("Rate limit exceeded");
+                let (parts, body) = response.into_parts();
+
+                if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                    debug!("Middleware on_response error: {}", e);
+                }
+
+                return Ok(Response::from_parts(parts, Full::new(body)));
+            }
+            Err(e) => warn!("Rate limit check failed, allowing request: {}", e),
+        }
+    }
+
+    // Ask the external authorization service; a denial is its answer
+    if let Some(authz) = state.ext_authz.as_ref().filter(|a| a.applies_to(&path)) {
+        let uri = req.uri().clone();
+        if let AuthzDecision::Deny(response) = authz.authorize(&method, &uri, req.headers_mut(), peer_addr.ip()).await {
+            debug!("Authorization denied {} {}", method, path);
+            let (parts, body) = response.into_parts();
+
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    // Evaluate authorization policies; anything they don't permit is denied
+    if let Some(authz) = state.authz_policy.as_ref().filter(|a| a.applies_to(&path)) {
+        let decision = authz.authorize(&method, req.uri(), req.headers(), peer_addr.ip(), context.source_vpc.as_ref());
+        if let AuthzDecision::Deny(response) = decision {
+            debug!("Policies denied {} {}", method, path);
+            let (parts, body) = response.into_parts();
+
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    // Answer revalidations of unchanged content without the backend on
+    // routes with an ETag policy
+    let conditional_config = route
+        .and_then(|route| route.spec.etag.as_ref())
+        .map(ConditionalConfig::from_policy);
+    let conditional = state.conditional.as_ref().zip(conditional_config.as_ref());
+    let validator_key = format!(
+        "{}{}",
+        request_host(&req).unwrap_or_default(),
+        req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/")
+    );
+    let request_headers = conditional.map(|_| req.headers().clone());
+    if let Some(validators) = conditional.and_then(|(c, _)| c.check_request(&validator_key, &method, req.headers())) {
+        debug!("Not modified at the edge: {} {}", method, path);
+        let (parts, body) = ConditionalResponder::not_modified_response(&validators).into_parts();
+
+        if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+            debug!("Middleware on_response error: {}", e);
+        }
+
+        return Ok(Response::from_parts(parts, Full::new(body)));
+    }
+
+    // Serve fresh responses from the cache on routes whose cache policy is
+    // enabled; stale ones are revalidated with the backend
+    let cache_config = route
+        .and_then(|route| route.spec.cache.as_ref())
+        .filter(|policy| policy.enabled)
+        .map(CacheConfig::from_policy);
+    let cache = state.response_cache.as_ref().zip(cache_config.as_ref());
+    let cache_request_headers = cache.map(|_| req.headers().clone());
+    let mut revalidating = None;
+    match cache.map(|(cache, _)| cache.lookup(&validator_key, &method, req.headers())) {
+        Some(CacheLookup::Hit(response)) => {
+            debug!("Served from cache: {} {}", method, path);
+            let accept_encoding = req.headers().get(hyper::header::ACCEPT_ENCODING).cloned();
+            let (mut parts, mut body) = response.into_parts();
+            if let Some(rewrite) = &header_rewrite {
+                rewrite.apply_response(&mut parts.headers);
+            }
+            if let Some(compressor) = &state.compressor {
+                body = compressor.compress(accept_encoding.as_ref(), parts.status, &mut parts.headers, body);
+            }
+
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+        Some(CacheLookup::Revalidate(validators)) => revalidating = Some(validators),
+        _ => {}
+    }
+
+    // Hold a slot of the route's concurrency limit, or the gateway's, for
+    // the rest of the request
+    let queue_started = Instant::now();
+    let limiter = route
+        .and_then(|route| state.router.route_concurrency_limiter(route))
+        .or_else(|| state.concurrency_limiter.clone());
+    let _permit = match &limiter {
+        Some(limiter) => match limiter.acquire().await {
+            Ok(permit) => Some(permit),
+            Err(e) => {
+                debug!("Shedding {} {}: {}", method, path, e);
+                let response = HttpProxy::service_unavailable_response("Too many concurrent requests");
+                let (parts, body) = response.into_parts();
+
+                if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                    debug!("Middleware on_error error: {}", mw_err);
+                }
+                if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                    debug!("Middleware on_response error: {}", e);
+                }
+
+                return Ok(Response::from_parts(parts, Full::new(body)));
+            }
+        },
+        None => None,
+    };
+    let queue_time = limiter.as_ref().map(|_| queue_started.elapsed());
+
+    // Forward to an endpoint of the route's destination, or answer for it
+    let (target_url, service, _connection, sticky_cookie) = match route {
+        None => (state.backend_url.clone(), None, None, None),
+        Some(route) => match route_target(&state, route, &method, req.headers(), context.source_vpc.as_ref()).await {
+            Some(RouteTarget::Endpoint(url, service, connection, cookie)) => {
+                (url, Some(service), Some(connection), cookie)
+            }
+            Some(RouteTarget::Direct(direct)) => {
+                debug!("Serving direct response of {} for {} {}", route.id, method, path);
+                let (parts, body) = direct.response().into_parts();
+
+                if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                    debug!("Middleware on_response error: {}", e);
+                }
+
+                return Ok(Response::from_parts(parts, Full::new(body)));
+            }
+            None => {
+                debug!("No available destination of {} for {} {}", route.id, method, path);
+                let response = HttpProxy::service_unavailable_response("No healthy upstream");
+                let (parts, body) = response.into_parts();
+
+                if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                    debug!("Middleware on_response error: {}", e);
+                }
+
+                return Ok(Response::from_parts(parts, Full::new(body)));
+            }
+        },
+    };
+    let target_url = target_url.as_str();
+
+    // Per-upstream settings are keyed by VPCService, or by the configured
+    // backend's authority
+    let upstream_key = service
+        .clone()
+        .or_else(|| target_url.parse::<hyper::Uri>().ok().and_then(|u| u.authority().map(|a| a.to_string())));
+
+    // Exchange the caller's token for a backend-scoped one, or inject an
+    // OAuth2 token for backends that require service-to-service auth
+    if let Some(upstream) = upstream_key.as_deref() {
+        let auth_result = match &state.token_exchanger {
+            Some(exchanger) if exchanger.has_backend(upstream) => exchanger.propagate(upstream, req.headers_mut()).await,
+            _ => state.token_injector.inject(upstream, req.headers_mut()).await,
+        };
+        if let Err(e) = auth_result {
+            warn!("{}", e);
+            let response = HttpProxy::bad_gateway_response("Upstream authentication unavailable");
+            let (parts, body) = response.into_parts();
+
+            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                debug!("Middleware on_error error: {}", mw_err);
+            }
+            if let Err(e) = middleware.on_response(&context, parts.status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    // Buffer the request body so it can be inspected before forwarding
+    let (mut parts, incoming) = req.into_parts();
+    let collected = if middleware.inspects_body() {
+        let incoming = middleware.clone().inspect_request_body(context.clone(), incoming);
+        state.forwarder.collect_request_body(&parts.headers, incoming).await
+    } else {
+        state.forwarder.collect_request_body(&parts.headers, incoming).await
+    };
+    let body = match collected {
+        Ok(body) => body,
+        Err(e @ RequestBodyError::TooLarge(_)) => {
+            warn!("Rejected {} {}: {}", method, path, e);
+            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                debug!("Middleware on_error error: {}", mw_err);
+            }
+            if let Err(e) = middleware.on_response(&context, 413).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+            let (parts, body) = RequestForwarder::payload_too_large_response().into_parts();
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+        Err(e @ RequestBodyError::Rejected(_)) => {
+            warn!("Rejected {} {}: {}", method, path, e);
+            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                debug!("Middleware on_error error: {}", mw_err);
+            }
+            if let Err(e) = middleware.on_response(&context, 403).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+            let (parts, body) = problem::error_response(StatusCode::FORBIDDEN, "Request body rejected").into_parts();
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+        Err(e) => {
+            debug!("Failed to read request body: {}", e);
+            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                debug!("Middleware on_error error: {}", mw_err);
+            }
+            if let Err(e) = middleware.on_response(&context, 400).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+            let (parts, body) = problem::error_response(StatusCode::BAD_REQUEST, "Failed to read request body").into_parts();
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    };
+
+    // Enforce the route's GraphQL limits before the query reaches the backend
+    let graphql_guard = route
+        .and_then(|route| route.spec.graphql.as_ref())
+        .map(|policy| GraphQLGuard::new(GraphQLLimits::from_route(policy)));
+    if let Some(guard) = &graphql_guard {
+        let inspection = if method == hyper::Method::GET {
+            guard.inspect_query_string(parts.uri.query().unwrap_or_default())
+        } else {
+            guard.inspect_body(&body)
+        };
+        match inspection {
+            Ok(analysis) => {
+                let operation = guard.operation_label(analysis.operation_name.as_deref());
+                state.metrics_collector.record_graphql_request(&operation, analysis.operation_type, "allowed");
+                debug!(
+                    "GraphQL {} {} (depth: {}, complexity: {})",
+                    analysis.operation_type, operation, analysis.depth, analysis.complexity
+                );
+            }
+            Err(e) => {
+                state.metrics_collector.record_graphql_request("unknown", "unknown", e.reason());
+                warn!("Rejected GraphQL request on {}: {}", path, e);
+                if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                    debug!("Middleware on_error error: {}", mw_err);
+                }
+                if let Err(e) = middleware.on_response(&context, 400).await {
+                    debug!("Middleware on_response error: {}", e);
+                }
+                return Ok(graphql_error_response(&e.to_string()));
+            }
+        }
+    }
+
+    // Tell the backend who the client is and how it connected
+    let host = parts
+        .headers
+        .get(hyper::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| parts.uri.authority().map(|a| a.as_str()))
+        .map(str::to_string);
+    state.forwarded_headers.apply(&mut parts.headers, peer_addr.ip(), scheme, host.as_deref());
+
+    if let Some(rewrite) = &header_rewrite {
+        rewrite.apply_request(&mut parts.headers);
+    }
+    let accept_encoding = parts.headers.get(hyper::header::ACCEPT_ENCODING).cloned();
+    if let Some(validators) = &revalidating {
+        validators.add_conditional_headers(&mut parts.headers);
+    }
+
+    // Debug requests are always sampled, and the debug header is not forwarded
+    let debug_trace_id = state
+        .debugger
+        .as_ref()
+        .filter(|_| debug)
+        .map(|debugger| debugger.prepare_request(&mut parts.headers));
+
+    // Forward the request's path, rewritten as the route asks, to the backend
+    let path_rewrite = route.and_then(route_path_rewrite);
+    let target = RequestForwarder::target_uri(target_url, &parts.uri, path_rewrite.as_ref());
+    if let Ok(target) = &target {
+        route.map_or(UpstreamHost::Preserve, route_upstream_host).apply(&mut parts.headers, target);
+    }
+    let routing_time = started.elapsed();
+
+    // Injected faults of the route stand in for a slow or failing backend
+    if let Some(injector) = route.and_then(|route| route_fault_injector(&state, route)) {
+        let fault = injector.decide();
+        if let Some(delay) = fault.delay {
+            debug!("Injecting {:?} delay into {} {}", delay, method, path);
+            tokio::time::sleep(delay).await;
+        }
+        if let Some(status) = fault.abort {
+            debug!("Injecting {} abort into {} {}", status, method, path);
+            if let Err(e) = middleware.on_response(&context, status.as_u16()).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+            let (parts, body) = FaultInjector::abort_response(status).into_parts();
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+    }
+
+    // Use forwarder to forward the request
+    let upstream = target_url.parse::<hyper::Uri>().ok().and_then(|u| u.authority().map(|a| a.to_string()));
+    let endpoint_request = upstream.as_deref().map(|address| state.endpoint_stats.begin(address));
+    let request_bytes = body.len() as u64;
+    if let Some(service) = service {
+        parts.extensions.insert(UpstreamService(service));
+    }
+    let result = match target {
+        Ok(target) => match state.forwarder.forward_bytes(&target.to_string(), Request::from_parts(parts, body)).await {
+            Ok(response) => Ok(response),
+            // Upstream failures still get a response, passed through the usual response handling
+            Err(e) => {
+                debug!("Upstream request failed ({}): {}", e.kind(), e);
+                if !matches!(e, ForwardError::UpstreamStatus { .. }) {
+                    if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                        debug!("Middleware on_error error: {}", mw_err);
+                    }
+                }
+                Ok(e.into_response())
+            }
+        },
+        Err(e) => Err(e),
+    };
+    if let Some(endpoint_request) = endpoint_request {
+        match &result {
+            Ok(response) => endpoint_request.finish(Some(response.status().as_u16()), request_bytes, response.body().len() as u64),
+            Err(_) => endpoint_request.finish(None, request_bytes, 0),
+        }
+    }
+    if let Ok(response) = &result {
+        let source = context.source_vpc.as_ref().map(|vpc| format!("{}/{}", vpc.namespace, vpc.name));
+        let destination = target_url
+            .parse::<hyper::Uri>()
+            .ok()
+            .and_then(|uri| uri.host()?.trim_matches(['[', ']']).parse().ok())
+            .and_then(|ip| state.sources.vpc(ip));
+        state.vpc_traffic.record(source.as_deref(), destination.as_deref(), request_bytes, response.body().len() as u64);
+    }
+
+    let result = match result {
+        Ok(response) => {
+            let upstream_timing = response.extensions().get::<UpstreamTiming>().copied();
+
+            // Convert response body to Full<Bytes>
+            let (mut parts, mut body) = response.into_parts();
+            if let Some(upstream) = &upstream {
+                parts.extensions.insert(AccessLogUpstream(upstream.clone()));
+            }
+
+            // A 304 to our revalidation refreshes the cached copy, which is sent instead
+            if let Some((cache, config)) = cache.filter(|_| revalidating.is_some() && parts.status == StatusCode::NOT_MODIFIED) {
+                if let Some(cached) = cache.revalidated(&validator_key, config, &parts.headers) {
+                    debug!("Revalidated cached response: {} {}", method, path);
+                    (parts, body) = cached.into_parts();
+                }
+            }
+
+            // Scan responses of routes with a PII policy; compressed ones are
+            // scanned decoded, and a block policy blocks ones that can't be
+            let pii_policy = route.and_then(|route| route.spec.pii_scan.as_ref()).map(PiiPolicy::from_route);
+            if let Some(policy) = pii_policy {
+                let content_type = parts.headers.get(hyper::header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+                if PiiScanner::is_scannable_content_type(content_type) {
+                    let content_encoding = parts.headers.get(hyper::header::CONTENT_ENCODING).and_then(|v| v.to_str().ok());
+                    let action = policy.action;
+                    let scan = state.pii_scanner.scan_encoded(&policy, content_encoding, &body);
+                    if scan.is_none() {
+                        warn!(
+                            "Cannot scan response for {} {} with Content-Encoding {:?} for PII",
+                            method, path, content_encoding
+                        );
+                        if action == PiiAction::Block {
+                            let response = HttpProxy::bad_gateway_response("Response blocked by data protection policy");
+                            let (blocked_parts, blocked_body) = response.into_parts();
+                            parts = blocked_parts;
+                            body = blocked_body;
+                        }
+                    }
+                    if let Some(scan) = scan.filter(|scan| scan.found()) {
+                        state.metrics_collector.record_pii_detections(&scan, action);
+                        warn!("PII detected in response for {} {} ({})", method, path, action.as_str());
+
+                        match (action, scan.masked_body) {
+                            (PiiAction::Block, _) => {
+                                let response = HttpProxy::bad_gateway_response("Response blocked by data protection policy");
+                                let (blocked_parts, blocked_body) = response.into_parts();
+                                parts = blocked_parts;
+                                body = blocked_body;
+                            }
+                            (PiiAction::Mask, Some(masked)) => {
+                                // Masked bodies are decoded and their length
+                                // changed, let hyper recompute it
+                                parts.headers.remove(hyper::header::CONTENT_LENGTH);
+                                parts.headers.remove(hyper::header::CONTENT_ENCODING);
+                                body = masked;
+                            }
+                            (PiiAction::Mask, None) => {}
+                        }
+                    }
+                }
+            }
+
+            // Body middleware sees the response as one chunk, since it is buffered
+            if middleware.inspects_body() {
+                match middleware.on_response_body(&context, body.clone()) {
+                    Ok(inspected) => {
+                        if inspected.len() != body.len() {
+                            parts.headers.remove(hyper::header::CONTENT_LENGTH);
+                        }
+                        body = inspected;
+                    }
+                    Err(e) => {
+                        warn!("Response for {} {} blocked: {}", method, path, e);
+                        let response = HttpProxy::bad_gateway_response("Response blocked by middleware");
+                        (parts, body) = response.into_parts();
+                    }
+                }
+            }
+
+            if let (Some((cache, config)), Some(request_headers)) = (cache, &cache_request_headers) {
+                cache.store(&validator_key, config, &method, request_headers, &parts, &body);
+            }
+
+            // Add validators and collapse matching revalidations to 304
+            if let (Some((conditional, config)), Some(request_headers)) = (conditional, &request_headers) {
+                let not_modified =
+                    conditional.process_response(&validator_key, config, &method, request_headers, &mut parts, &body);
+                if not_modified {
+                    parts.status = StatusCode::NOT_MODIFIED;
+                    ConditionalResponder::strip_for_not_modified(&mut parts.headers);
+                    body = Bytes::new();
+                }
+            }
+
+            if let Some(rewrite) = &header_rewrite {
+                rewrite.apply_response(&mut parts.headers);
+            }
+            if let Some(cookie) = sticky_cookie {
+                parts.headers.append(hyper::header::SET_COOKIE, cookie);
+            }
+            if let Some(compressor) = &state.compressor {
+                body = compressor.compress(accept_encoding.as_ref(), parts.status, &mut parts.headers, body);
+            }
+            if state.server_timing {
+                let route_time = routing_time.saturating_sub(queue_time.unwrap_or_default());
+                ServerTiming::gateway_phases(route_time, queue_time, upstream_timing.as_ref(), started.elapsed())
+                    .apply(&mut parts.headers);
+            }
+            if let Some(trace_id) = &debug_trace_id {
+                RequestDebugger::add_timing_headers(
+                    &mut parts.headers,
+                    trace_id,
+                    routing_time,
+                    upstream_timing.as_ref(),
+                    started.elapsed(),
+                );
+            }
+
+            let status = parts.status.as_u16();
+            let response = Response::from_parts(parts, Full::new(body));
+
+            // Call on_response middleware hooks
+            if let Err(e) = middleware.on_response(&context, status).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            Ok(response)
+        }
+        Err(e) => {
+            debug!("Forwarder error: {}", e);
+            let (parts, body) = problem::error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to forward request").into_parts();
+            let error_response = Response::from_parts(parts, Full::new(body));
+
+            // Call on_error middleware hooks
+            if let Err(mw_err) = middleware.on_error(&context, &e.to_string()).await {
+                debug!("Middleware on_error error: {}", mw_err);
+            }
+
+            // Call on_response middleware hooks for error response
+            if let Err(e) = middleware.on_response(&context, 500).await {
+                debug!("Middleware on_response error: {}", e);
+            }
+
+            Ok(error_response)
+        }
+    };
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use router_api::v1alpha1::vpc_route::{
+        AffinityPolicy, ClientVersionMatch, DarkLaunchMatch, ReadWriteSplit, RouteDestination, RouteMatch, SecretKeyRef,
+        TrailingSlashPolicy, VPCRouteSpec,
+    };
+
+    /// Serve every request with `name` as the body, returning the port
+    async fn upstream(name: &'static str) -> u16 {
+        upstream_on("127.0.0.1:0", name).await
+    }
+
+    /// Serve every request on `address` with `name` as the body, returning the port
+    async fn upstream_on(address: &str, name: &'static str) -> u16 {
+        let listener = TcpListener::bind(address).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = service_fn(move |_req| async move {
+                    Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(name))))
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        port
+    }
+
+    /// Serve every request with the JSON `body`, returning the port
+    async fn json_upstream(body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = service_fn(move |_req| async move {
+                    let response = Response::builder()
+                        .header(hyper::header::CONTENT_TYPE, "application/json")
+                        .body(Full::new(Bytes::from(body)))
+                        .unwrap();
+                    Ok::<_, hyper::Error>(response)
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        port
+    }
+
+    /// Serve every request with its path and query, then its headers, as
+    /// the body
+    async fn echo_upstream() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                    let mut echo = req.uri().path_and_query().map_or("/", |p| p.as_str()).to_string();
+                    for (name, value) in req.headers() {
+                        echo.push_str(&format!("\n{}: {}", name, value.to_str().unwrap_or_default()));
+                    }
+                    Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(echo))))
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        port
+    }
+
+    /// Serve every request with `name` as the body, cacheable for a minute,
+    /// returning the port and the number of requests served
+    async fn cacheable_upstream(name: &'static str) -> (u16, Arc<std::sync::atomic::AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = served.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let counter = counter.clone();
+                let service = service_fn(move |_req| {
+                    counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    async move {
+                        let response = Response::builder()
+                            .header(hyper::header::CACHE_CONTROL, "max-age=60")
+                            .body(Full::new(Bytes::from(name)))
+                            .unwrap();
+                        Ok::<_, hyper::Error>(response)
+                    }
+                });
+                tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            }
+        });
+        (port, served)
+    }
+
+    /// Register `name` in the default namespace with local endpoints on `ports`
+    async fn register(registry: &ServiceRegistry, name: &str, ports: &[u16]) {
+        let endpoints = ports
+            .iter()
+            .map(|port| Endpoint { ip: "127.0.0.1".to_string(), port: *port, ready: true, priority: 0 })
+            .collect();
+        registry
+            .register_service("default".to_string(), name.to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+    }
+
+    /// A gateway with every optional feature off, routing by `router`, with
+    /// a response cache for routes whose policy enables it
+    fn gateway(router: Router, backend_url: &str) -> Arc<GatewayState> {
+        Arc::new(GatewayState {
+            router: Arc::new(router),
+            forwarder: Arc::new(RequestForwarder::new(Duration::from_secs(5))),
+            middleware: Arc::new(MiddlewareChain::new()),
+            metrics_collector: Arc::new(MetricsCollector::new().unwrap()),
+            token_injector: Arc::new(OAuth2TokenInjector::new()),
+            token_exchanger: None,
+            pii_scanner: Arc::new(PiiScanner::new().unwrap()),
+            concurrency_limiter: None,
+            replica_ring: None,
+            replica_key: None,
+            session_pins: None,
+            rate_limiter: None,
+            rate_limit_key: None,
+            conditional: None,
+            response_cache: Some(ResponseCache::new(100).unwrap()),
+            cache_purge_key: None,
+            compressor: None,
+            client_protocol: ClientProtocol::new(ClientProtocolConfig::default()),
+            endpoint_stats: Arc::new(EndpointStatsRecorder::new()),
+            vpc_traffic: Arc::new(VpcTrafficRecorder::new()),
+            inflight: InflightTracker::new(),
+            forwarded_headers: ForwardedHeaders::new(ForwardedConfig::default()),
+            debugger: None,
+            server_timing: false,
+            error_format: ErrorFormat::default(),
+            access_log: None,
+            ext_authz: None,
+            authz_policy: None,
+            backend_url: backend_url.to_string(),
+            request_ids: RequestIdMiddleware::new(),
+            drain: Drain::new(Duration::from_secs(1)),
+            router_config: None,
+            config_token: None,
+            routes: Arc::new(routes::RouteTables::new()),
+            sources: Arc::new(sources::Sources::new(DEFAULT_NAT_PREFIX.parse().unwrap())),
+            // Nothing is drained in these tests
+            drains: Arc::new(drains::Drains::new(Arc::new(ServiceRegistry::new()), None)),
+        })
+    }
+
+    /// Send `method` `path` through the gateway, returning the status and body
+    async fn send(state: &Arc<GatewayState>, method: &str, path: &str) -> (StatusCode, String) {
+        send_with(state, Request::builder().method(method).uri(path)).await
+    }
+
+    /// Send the request `builder` makes through the gateway, returning the
+    /// status and body
+    async fn send_with(state: &Arc<GatewayState>, builder: hyper::http::request::Builder) -> (StatusCode, String) {
+        let req = builder.body(Full::new(Bytes::new())).unwrap();
+        let response = handle_request(req, ([127, 0, 0, 1], 40000).into(), "http", state.clone(), false)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    /// A route sending requests under `prefix` to `destinations`
+    fn route(name: &str, prefix: &str, destinations: Vec<RouteDestination>) -> Route {
+        let spec = VPCRouteSpec {
+            name: name.to_string(),
+            r#match: RouteMatch { path_prefix: Some(prefix.to_string()), ..Default::default() },
+            destinations,
+            ..Default::default()
+        };
+        Route::new("default", name, spec).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_follow_vpc_routes() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let api = upstream("api").await;
+        register(&registry, "api", &[api]).await;
+        let backend = upstream("backend").await;
+        let router = Router::new(registry.clone());
+        let state = gateway(router, &format!("http://127.0.0.1:{}", backend));
+
+        // Without routes everything goes to the configured backend
+        assert_eq!(send(&state, "GET", "/api/users").await, (StatusCode::OK, "backend".to_string()));
+
+        state.router.sync_routes(vec![
+            route("api", "/api", vec![RouteDestination::service("api")]),
+            route("maintenance", "/status", vec![RouteDestination::direct(503, "Down for maintenance")]),
+        ]);
+        assert_eq!(send(&state, "GET", "/api/users").await, (StatusCode::OK, "api".to_string()));
+        assert_eq!(send(&state, "GET", "/status").await, (StatusCode::SERVICE_UNAVAILABLE, "Down for maintenance".to_string()));
+        assert_eq!(send(&state, "GET", "/other").await.0, StatusCode::NOT_FOUND);
+
+        // A route whose service has no ready endpoint has nowhere to go
+        registry.set_endpoint_ready("default/api", "127.0.0.1", api, false).await.unwrap();
+        assert_eq!(send(&state, "GET", "/api/users").await.0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_destination_weights_split_traffic() {
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        let canary = upstream("api-canary").await;
+        register(&registry, "api-canary", &[canary]).await;
+        let state = gateway(Router::new(registry.clone()), "http://127.0.0.1:9");
+        state.router.sync_routes(vec![route(
+            "api",
+            "/api",
+            vec![RouteDestination::service("api").with_weight(90), RouteDestination::service("api-canary").with_weight(10)],
+        )]);
+
+        let mut canary_requests = 0;
+        for _ in 0..100 {
+            if send(&state, "GET", "/api/users").await.1 == "api-canary" {
+                canary_requests += 1;
+            }
+        }
+        assert_eq!(canary_requests, 10);
+
+        // A canary with no ready endpoint gives its share to the others
+        registry.set_endpoint_ready("default/api-canary", "127.0.0.1", canary, false).await.unwrap();
+        for _ in 0..20 {
+            assert_eq!(send(&state, "GET", "/api/users").await, (StatusCode::OK, "api".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_type_selects_route() {
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "orders", &[upstream("orders").await]).await;
+        register(&registry, "orders-grpc", &[upstream("orders-grpc").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut grpc = route("orders-grpc", "/orders", vec![RouteDestination::service("orders-grpc")]);
+        grpc.spec.r#match.content_types = vec!["application/grpc".to_string()];
+        state.router.sync_routes(vec![route("orders", "/orders", vec![RouteDestination::service("orders")]), grpc]);
+
+        let request = |content_type: &str| {
+            Request::builder().method("POST").uri("/orders/Create").header(hyper::header::CONTENT_TYPE, content_type)
+        };
+        assert_eq!(send_with(&state, request("application/grpc+proto")).await.1, "orders-grpc");
+        assert_eq!(send_with(&state, request("application/json")).await.1, "orders");
+    }
+
+    #[tokio::test]
+    async fn test_read_write_split() {
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "db-primary", &[upstream("db-primary").await]).await;
+        register(&registry, "db-replica", &[upstream("db-replica").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut db = route("db", "/records", vec![RouteDestination::service("db-primary")]);
+        db.spec.read_write_split = Some(ReadWriteSplit::new(
+            vec![RouteDestination::service("db-replica")],
+            vec![RouteDestination::service("db-primary")],
+        ));
+        state.router.sync_routes(vec![db]);
+
+        assert_eq!(send(&state, "GET", "/records/7").await.1, "db-replica");
+        assert_eq!(send(&state, "POST", "/records").await.1, "db-primary");
+        assert_eq!(send(&state, "DELETE", "/records/7").await.1, "db-primary");
+    }
+
+    #[tokio::test]
+    async fn test_client_selects_route() {
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        register(&registry, "api-legacy", &[upstream("api-legacy").await]).await;
+        register(&registry, "api-bots", &[upstream("api-bots").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut legacy = route("api-legacy", "/api", vec![RouteDestination::service("api-legacy")]).spec;
+        legacy.r#match.client_version =
+            Some(ClientVersionMatch { header: "x-app-version".to_string(), range: "<2.0.0".to_string() });
+        let mut bots = route("api-bots", "/api", vec![RouteDestination::service("api-bots")]).spec;
+        bots.r#match.user_agent = Some("(?i)bot".to_string());
+        let legacy = Route::new("default", "api-legacy", legacy).unwrap();
+        let bots = Route::new("default", "api-bots", bots).unwrap();
+        state.router.sync_routes(vec![route("api", "/api", vec![RouteDestination::service("api")]), legacy, bots]);
+
+        let request = |name: &str, value: &str| Request::builder().uri("/api/items").header(name, value);
+        assert_eq!(send_with(&state, request("x-app-version", "1.9")).await.1, "api-legacy");
+        assert_eq!(send_with(&state, request("x-app-version", "v2.1.0")).await.1, "api");
+        assert_eq!(send_with(&state, request("user-agent", "Googlebot/2.1")).await.1, "api-bots");
+        assert_eq!(send_with(&state, request("user-agent", "curl/8.5.0")).await.1, "api");
+    }
+
+    #[tokio::test]
+    async fn test_dark_launch_needs_token() {
+        let dir = std::env::temp_dir().join(format!("gateway-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("dark-launch")).unwrap();
+        std::fs::write(dir.join("dark-launch").join("token"), "s3cr3t\n").unwrap();
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        register(&registry, "api-next", &[upstream("api-next").await]).await;
+        let state = gateway(Router::new(registry).with_secrets_dir(&dir), "http://127.0.0.1:9");
+        let mut next = route("api-next", "/api", vec![RouteDestination::service("api-next")]);
+        next.spec.r#match.dark_launch = Some(DarkLaunchMatch {
+            header: "x-dark-launch".to_string(),
+            secret_ref: SecretKeyRef { name: "dark-launch".to_string(), key: "token".to_string() },
+        });
+        state.router.sync_routes(vec![route("api", "/api", vec![RouteDestination::service("api")]), next]);
+
+        let request = |token: &str| Request::builder().uri("/api/items").header("x-dark-launch", token);
+        assert_eq!(send(&state, "GET", "/api/items").await.1, "api");
+        assert_eq!(send_with(&state, request("wrong")).await.1, "api");
+        assert_eq!(send_with(&state, request("s3cr3t")).await.1, "api-next");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_redirects_to_canonical_path() {
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut api = route("api", "/api", vec![RouteDestination::service("api")]);
+        api.spec.r#match.trailing_slash = TrailingSlashPolicy::Redirect;
+        state.router.sync_routes(vec![api]);
+
+        let req = Request::builder().uri("/api/items/?page=2").body(Full::new(Bytes::new())).unwrap();
+        let response = handle_request(req, ([127, 0, 0, 1], 40000).into(), "http", state.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[hyper::header::LOCATION], "/api/items?page=2");
+        assert_eq!(send(&state, "GET", "/api/items").await, (StatusCode::OK, "api".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_affinity_key_keeps_sessions_on_one_endpoint() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let ports = [upstream("carts-1").await, upstream("carts-2").await, upstream("carts-3").await];
+        register(&registry, "carts", &ports).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut carts = route("carts", "/carts", vec![RouteDestination::service("carts")]);
+        carts.spec.affinity = Some(AffinityPolicy { name: "x-session-id".to_string(), ..Default::default() });
+        state.router.sync_routes(vec![carts]);
+
+        let session = |id: &str| Request::builder().uri("/carts/items").header("x-session-id", id);
+        let first = send_with(&state, session("alice")).await.1;
+        for _ in 0..5 {
+            assert_eq!(send_with(&state, session("alice")).await.1, first);
+        }
+
+        // Requests without the key are balanced as usual
+        let mut served = std::collections::HashSet::new();
+        for _ in 0..3 {
+            served.insert(send(&state, "GET", "/carts/items").await.1);
+        }
+        assert_eq!(served.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_session_pins_outlast_endpoint_changes() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let ports = [upstream("carts-1").await, upstream("carts-2").await, upstream("carts-3").await];
+        register(&registry, "carts", &ports).await;
+        let mut state = gateway(Router::new(registry.clone()), "http://127.0.0.1:9");
+        let store: Arc<dyn StateStore> = Arc::new(router_proxy::MemoryStateStore::new());
+        Arc::get_mut(&mut state).unwrap().session_pins = Some(SessionPins::new(store, Duration::from_secs(60)));
+        let mut carts = route("carts", "/carts", vec![RouteDestination::service("carts")]);
+        carts.spec.affinity = Some(AffinityPolicy { name: "x-session-id".to_string(), ..Default::default() });
+        state.router.sync_routes(vec![carts]);
+
+        let session = |id: &str| Request::builder().uri("/carts/items").header("x-session-id", id);
+        let mut pinned = Vec::new();
+        for id in ["alice", "bob", "carol", "dave", "erin"] {
+            pinned.push(send_with(&state, session(id)).await.1);
+        }
+
+        // A new endpoint would take some sessions over by hashing alone
+        let fourth = upstream("carts-4").await;
+        register(&registry, "carts", &[ports[0], ports[1], ports[2], fourth]).await;
+        for (id, endpoint) in ["alice", "bob", "carol", "dave", "erin"].iter().zip(&pinned) {
+            assert_eq!(&send_with(&state, session(id)).await.1, endpoint);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sticky_cookie_keeps_clients_on_one_endpoint() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let ports = [upstream("carts-1").await, upstream("carts-2").await, upstream("carts-3").await];
+        register(&registry, "carts", &ports).await;
+        let state = gateway(Router::new(registry).with_sticky_cookie_key(b"secret"), "http://127.0.0.1:9");
+        let mut carts = route("carts", "/carts", vec![RouteDestination::service("carts")]);
+        carts.spec.affinity = Some(AffinityPolicy {
+            source: AffinitySource::StickyCookie,
+            name: "carts-session".to_string(),
+            ttl_seconds: Some(600),
+        });
+        state.router.sync_routes(vec![carts]);
+
+        let req = Request::builder().uri("/carts/items").body(Full::new(Bytes::new())).unwrap();
+        let response = handle_request(req, ([127, 0, 0, 1], 40000).into(), "http", state.clone(), false)
+            .await
+            .unwrap();
+        let cookie = response.headers()[hyper::header::SET_COOKIE].to_str().unwrap().to_string();
+        let first = String::from_utf8_lossy(&response.into_body().collect().await.unwrap().to_bytes()).to_string();
+        let cookie = cookie.split(';').next().unwrap().to_string();
+
+        // Round robin would move the client, the cookie keeps it in place
+        for _ in 0..3 {
+            let request = Request::builder().uri("/carts/items").header(hyper::header::COOKIE, cookie.as_str());
+            assert_eq!(send_with(&state, request).await.1, first);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_visibility_limits_source_vpcs() {
+        use router_api::v1alpha1::vpc_service::{VPCReference, Visibility};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "ledger", &[upstream("ledger").await]).await;
+        register(&registry, "admin", &[upstream("admin").await]).await;
+        let only = |vpc: &str| Visibility {
+            vpcs: vec![VPCReference { name: vpc.to_string(), namespace: "default".to_string() }],
+            ..Default::default()
+        };
+        registry.set_visibility("default/ledger", Some(only("payments"))).await.unwrap();
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut admin = route("admin", "/admin", vec![RouteDestination::service("admin")]);
+        admin.spec.visibility = Some(only("ops"));
+        state.router.sync_routes(vec![route("ledger", "/ledger", vec![RouteDestination::service("ledger")]), admin]);
+
+        // Requests from unknown sources see neither
+        assert_eq!(send(&state, "GET", "/ledger").await.0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(send(&state, "GET", "/admin").await.0, StatusCode::NOT_FOUND);
+
+        let mut map = router_core::SourceMap::default();
+        let payments = SourceVpc { namespace: "default".to_string(), name: "payments".to_string(), ..Default::default() };
+        map.insert([127, 0, 0, 1].into(), payments);
+        state.sources.publish(map, state.sources.nat().as_ref().clone());
+        assert_eq!(send(&state, "GET", "/ledger").await, (StatusCode::OK, "ledger".to_string()));
+        assert_eq!(send(&state, "GET", "/admin").await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_vpcs_are_reached_through_nat() {
+        use router_api::galactic::vpc::{VPCSpec, VPCStatus, VPC};
+        use router_api::v1alpha1::vpc_service::VPCReference;
+        use router_galactic::NatTable;
+
+        // With the NAT prefix ::/48, 0.0.0.1 in the VPC with identifier 0
+        // translates to ::1, where the upstream listens
+        let port = upstream_on("[::1]:0", "ledger").await;
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoint = Endpoint { ip: "0.0.0.1".to_string(), port, ready: true, priority: 0 };
+        registry
+            .register_service("default".to_string(), "ledger".to_string(), port, "HTTP".to_string(), vec![endpoint])
+            .await
+            .unwrap();
+        let web = VPCReference { name: "web".to_string(), namespace: "default".to_string() };
+        registry.set_vpc("default/ledger", Some(web)).await.unwrap();
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        state.router.sync_routes(vec![route("ledger", "/ledger", vec![RouteDestination::service("ledger")])]);
+
+        let vpc = |name: &str, identifier: &str| {
+            let mut vpc = VPC::new(name, VPCSpec { networks: vec!["0.0.0.0/24".to_string()] });
+            vpc.status = Some(VPCStatus { identifier: Some(identifier.to_string()), ..Default::default() });
+            vpc
+        };
+        let mut map = router_core::SourceMap::default();
+        let payments = SourceVpc { namespace: "default".to_string(), name: "payments".to_string(), ..Default::default() };
+        map.insert([127, 0, 0, 1].into(), payments);
+        let nat = NatTable::from_vpcs("::/48".parse().unwrap(), &[vpc("payments", "1"), vpc("web", "0")]);
+        state.sources.publish(map, nat);
+
+        assert_eq!(send(&state, "GET", "/ledger").await, (StatusCode::OK, "ledger".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_standby_tier_takes_over_when_primary_fails() {
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = vec![
+            Endpoint { ip: "127.0.0.1".to_string(), port: upstream("primary-a").await, ready: true, priority: 0 },
+            Endpoint { ip: "127.0.0.1".to_string(), port: upstream("primary-b").await, ready: true, priority: 0 },
+            Endpoint { ip: "127.0.0.1".to_string(), port: upstream("standby").await, ready: true, priority: 1 },
+        ];
+        let primary_a = endpoints[0].port;
+        registry
+            .register_service("default".to_string(), "db".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+        let state = gateway(Router::new(registry.clone()), "http://127.0.0.1:9");
+        state.router.sync_routes(vec![route("db", "/db", vec![RouteDestination::service("db")])]);
+
+        for _ in 0..4 {
+            assert!(send(&state, "GET", "/db").await.1.starts_with("primary"));
+        }
+
+        // Half the primary tier is below the threshold, the standby joins
+        registry.set_endpoint_ready("default/db", "127.0.0.1", primary_a, false).await.unwrap();
+        let mut served = std::collections::HashSet::new();
+        for _ in 0..4 {
+            served.insert(send(&state, "GET", "/db").await.1);
+        }
+        assert_eq!(served, ["primary-b".to_string(), "standby".to_string()].into());
+    }
+
+    #[tokio::test]
+    async fn test_route_cache_policy_enables_caching() {
+        use router_api::v1alpha1::vpc_route::ResponseCachePolicy;
+        use std::sync::atomic::Ordering;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let (catalog_port, catalog_served) = cacheable_upstream("catalog").await;
+        let (cart_port, cart_served) = cacheable_upstream("cart").await;
+        register(&registry, "catalog", &[catalog_port]).await;
+        register(&registry, "cart", &[cart_port]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut catalog = route("catalog", "/catalog", vec![RouteDestination::service("catalog")]);
+        catalog.spec.cache = Some(ResponseCachePolicy { enabled: true, ..Default::default() });
+        state.router.sync_routes(vec![catalog, route("cart", "/cart", vec![RouteDestination::service("cart")])]);
+
+        for _ in 0..3 {
+            assert_eq!(send(&state, "GET", "/catalog/items").await.1, "catalog");
+            assert_eq!(send(&state, "GET", "/cart/items").await.1, "cart");
+        }
+        assert_eq!(catalog_served.load(Ordering::SeqCst), 1);
+        assert_eq!(cart_served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_route_redaction_policy_applies_to_its_requests() {
+        use router_api::v1alpha1::vpc_route::RedactionPolicy;
+
+        let state = gateway(Router::new(Arc::new(ServiceRegistry::new())), "http://127.0.0.1:9");
+        let mut payments = route("payments", "/payments", vec![RouteDestination::service("payments")]);
+        payments.spec.redaction = Some(RedactionPolicy {
+            headers: vec!["X-Card-Number".to_string()],
+            query_params: vec!["token".to_string()],
+            ..Default::default()
+        });
+        state.router.sync_routes(vec![payments, route("web", "/web", vec![RouteDestination::service("web")])]);
+
+        let routes = state.router.routes();
+        let redactor_of = |path: &str| {
+            let req = Request::get(path).body(()).unwrap();
+            route_redactor(RequestContext::new(&state, &routes, ([127, 0, 0, 1], 40000).into(), &req).route)
+        };
+        let redactor = redactor_of("/payments/charge");
+        assert!(redactor.is_redacted_header("x-card-number"));
+        assert_eq!(redactor.redact_query("token=secret"), "token=[REDACTED]");
+
+        // Other routes keep the defaults only
+        let redactor = redactor_of("/web/charge");
+        assert!(!redactor.is_redacted_header("x-card-number"));
+        assert!(redactor.is_redacted_header("authorization"));
+    }
+
+    #[tokio::test]
+    async fn test_route_bandwidth_policy_paces_responses() {
+        use router_api::v1alpha1::vpc_route::BandwidthPolicy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "downloads", &[upstream("downloads").await]).await;
+        register(&registry, "web", &[upstream("web").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut downloads = route("downloads", "/downloads", vec![RouteDestination::service("downloads")]);
+        downloads.spec.bandwidth =
+            Some(BandwidthPolicy { bytes_per_second: Some(100), burst_bytes: Some(1), ..Default::default() });
+        state.router.sync_routes(vec![downloads, route("web", "/web", vec![RouteDestination::service("web")])]);
+
+        let fetch = |path: &'static str| {
+            let state = state.clone();
+            async move {
+                let req = Request::get(path).body(Full::new(Bytes::new())).unwrap();
+                let peer = ([127, 0, 0, 1], 40000).into();
+                let response = serve_request(req, peer, "http", state, Arc::default()).await.unwrap();
+                let started = Instant::now();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (String::from_utf8_lossy(&body).to_string(), started.elapsed())
+            }
+        };
+
+        // Nine bytes at 100 bytes/s after a one byte burst
+        let (body, elapsed) = fetch("/downloads/big.iso").await;
+        assert_eq!(body, "downloads");
+        assert!(elapsed >= Duration::from_millis(60), "paced for {:?}", elapsed);
+        let (body, elapsed) = fetch("/web/index.html").await;
+        assert_eq!(body, "web");
+        assert!(elapsed < Duration::from_millis(60), "paced for {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_route_header_policy_rewrites_headers() {
+        use router_api::v1alpha1::vpc_route::{HeaderOperations, HeaderRewritePolicy};
+        use std::collections::BTreeMap;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[echo_upstream().await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut api = route("api", "/api", vec![RouteDestination::service("api")]);
+        api.spec.headers = Some(HeaderRewritePolicy {
+            request: HeaderOperations {
+                set: BTreeMap::from([("x-env".to_string(), "staging".to_string())]),
+                remove: vec!["x-internal".to_string()],
+                ..Default::default()
+            },
+            response: HeaderOperations {
+                add: BTreeMap::from([("x-served-by".to_string(), "edge".to_string())]),
+                ..Default::default()
+            },
+        });
+        state.router.sync_routes(vec![api, route("web", "/web", vec![RouteDestination::service("api")])]);
+
+        let request = |path: &str| Request::get(path).header("x-internal", "1").body(Full::new(Bytes::new())).unwrap();
+        let peer: SocketAddr = ([127, 0, 0, 1], 40000).into();
+        let response = handle_request(request("/api/items"), peer, "http", state.clone(), false).await.unwrap();
+        assert_eq!(response.headers()["x-served-by"], "edge");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let echo = String::from_utf8_lossy(&body);
+        assert!(echo.contains("x-env: staging"));
+        assert!(!echo.contains("x-internal"));
+
+        // Other routes forward headers as sent
+        let response = handle_request(request("/web/items"), peer, "http", state.clone(), false).await.unwrap();
+        assert!(!response.headers().contains_key("x-served-by"));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("x-internal: 1"));
+    }
+
+    #[tokio::test]
+    async fn test_route_path_rewrite_policy_rewrites_forwarded_paths() {
+        use router_api::v1alpha1::vpc_route::{PathRewritePolicy, RegexRewrite};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[echo_upstream().await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut stripped = route("stripped", "/api/v1", vec![RouteDestination::service("api")]);
+        stripped.spec.path_rewrite = Some(PathRewritePolicy { strip_prefix: true, ..Default::default() });
+        let mut legacy = route("legacy", "/legacy", vec![RouteDestination::service("api")]);
+        legacy.spec.path_rewrite = Some(PathRewritePolicy {
+            regex: Some(RegexRewrite { pattern: "^/legacy/(\\w+)".to_string(), substitution: "/v2/$1".to_string() }),
+            ..Default::default()
+        });
+        let plain = route("plain", "/web", vec![RouteDestination::service("api")]);
+        state.router.sync_routes(vec![stripped, legacy, plain]);
+
+        let forwarded_path = |path: &'static str| {
+            let state = state.clone();
+            async move { send(&state, "GET", path).await.1.lines().next().unwrap_or_default().to_string() }
+        };
+        assert_eq!(forwarded_path("/api/v1/users?page=2").await, "/users?page=2");
+        assert_eq!(forwarded_path("/legacy/orders").await, "/v2/orders");
+        assert_eq!(forwarded_path("/web/index.html").await, "/web/index.html");
+    }
+
+    #[tokio::test]
+    async fn test_route_redirect_answers_without_a_backend() {
+        use router_api::v1alpha1::vpc_route::RedirectAction;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "web", &[upstream("web").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut legacy = route("legacy", "/old", Vec::new());
+        legacy.spec.redirect = Some(RedirectAction {
+            status_code: 301,
+            replace_prefix: Some("/new".to_string()),
+            ..Default::default()
+        });
+        state.router.sync_routes(vec![legacy, route("web", "/new", vec![RouteDestination::service("web")])]);
+
+        let request = |path: &str| Request::get(path).header("host", "shop.example.com").body(Full::new(Bytes::new()));
+        let peer: SocketAddr = ([127, 0, 0, 1], 40000).into();
+        let response = handle_request(request("/old/cart?item=1").unwrap(), peer, "http", state.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()["location"], "http://shop.example.com/new/cart?item=1");
+        assert_eq!(send(&state, "GET", "/new/cart").await.1, "web");
+    }
+
+    #[tokio::test]
+    async fn test_route_upstream_host_policy_sets_forwarded_host() {
+        use router_api::v1alpha1::vpc_route::{UpstreamHostMode, UpstreamHostPolicy};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let port = echo_upstream().await;
+        register(&registry, "api", &[port]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let with_host = |name: &str, prefix: &str, mode: UpstreamHostMode, value: Option<&str>| {
+            let mut route = route(name, prefix, vec![RouteDestination::service("api")]);
+            route.spec.upstream_host = Some(UpstreamHostPolicy { mode, value: value.map(str::to_string) });
+            route
+        };
+        state.router.sync_routes(vec![
+            with_host("fixed", "/fixed", UpstreamHostMode::Fixed, Some("api.internal")),
+            with_host("backend", "/backend", UpstreamHostMode::Backend, None),
+            route("plain", "/plain", vec![RouteDestination::service("api")]),
+        ]);
+
+        let forwarded_host = |path: &'static str| {
+            let state = state.clone();
+            async move {
+                let (_, echo) = send_with(&state, Request::get(path).header("host", "shop.example.com")).await;
+                echo.lines().find_map(|line| line.strip_prefix("host: ")).unwrap_or_default().to_string()
+            }
+        };
+        assert_eq!(forwarded_host("/fixed/items").await, "api.internal");
+        assert_eq!(forwarded_host("/backend/items").await, format!("127.0.0.1:{}", port));
+        assert_eq!(forwarded_host("/plain/items").await, "shop.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_route_fault_policy_injects_aborts() {
+        use router_api::v1alpha1::vpc_route::{FaultAbort, FaultInjectionPolicy};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut chaos = route("chaos", "/chaos", vec![RouteDestination::service("api")]);
+        chaos.spec.fault = Some(FaultInjectionPolicy {
+            abort: Some(FaultAbort { status_code: 503, percentage: 100.0 }),
+            ..Default::default()
+        });
+        state.router.sync_routes(vec![chaos, route("api", "/api", vec![RouteDestination::service("api")])]);
+
+        assert_eq!(send(&state, "GET", "/chaos/items").await.0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(send(&state, "GET", "/api/items").await.1, "api");
+        assert!(state.metrics_collector.gather().unwrap().contains("faults_injected_total{kind=\"abort\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn test_route_response_limit_policy_bounds_bodies() {
+        use router_api::v1alpha1::vpc_route::{ResponseLimitAction, ResponseLimitPolicy};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "downloads", &[upstream("downloads").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let limited = |name: &str, prefix: &str, on_exceed: ResponseLimitAction| {
+            let mut route = route(name, prefix, vec![RouteDestination::service("downloads")]);
+            route.spec.response_limit = Some(ResponseLimitPolicy { max_bytes: 4, on_exceed });
+            route
+        };
+        state.router.sync_routes(vec![
+            limited("aborted", "/aborted", ResponseLimitAction::Abort),
+            limited("truncated", "/truncated", ResponseLimitAction::Truncate),
+            route("whole", "/whole", vec![RouteDestination::service("downloads")]),
+        ]);
+
+        assert_eq!(send(&state, "GET", "/aborted/big.iso").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&state, "GET", "/truncated/big.iso").await.1, "down");
+        assert_eq!(send(&state, "GET", "/whole/big.iso").await.1, "downloads");
+    }
+
+    #[tokio::test]
+    async fn test_route_pii_scan_policy_masks_or_blocks_responses() {
+        use router_api::v1alpha1::vpc_route::{PiiScanAction, PiiScanPolicy};
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "customers", &[json_upstream(r#"{"email":"alice@example.com"}"#).await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let scanned = |name: &str, prefix: &str, action: PiiScanAction| {
+            let mut route = route(name, prefix, vec![RouteDestination::service("customers")]);
+            route.spec.pii_scan = Some(PiiScanPolicy { action, kinds: vec![] });
+            route
+        };
+        state.router.sync_routes(vec![
+            scanned("masked", "/masked", PiiScanAction::Mask),
+            scanned("blocked", "/blocked", PiiScanAction::Block),
+            route("plain", "/plain", vec![RouteDestination::service("customers")]),
+        ]);
+
+        assert_eq!(send(&state, "GET", "/masked/42").await.1, r#"{"email":"a***@example.com"}"#);
+        assert_eq!(send(&state, "GET", "/blocked/42").await.0, StatusCode::BAD_GATEWAY);
+        assert_eq!(send(&state, "GET", "/plain/42").await.1, r#"{"email":"alice@example.com"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_route_graphql_policy_limits_queries() {
+        use router_api::v1alpha1::vpc_route::GraphQLPolicy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "graph", &[upstream("graph").await]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut limited = route("limited", "/graphql", vec![RouteDestination::service("graph")]);
+        limited.spec.graphql = Some(GraphQLPolicy { max_depth: Some(1), ..Default::default() });
+        state.router.sync_routes(vec![limited, route("open", "/open", vec![RouteDestination::service("graph")])]);
+
+        // { a { b } } is two levels deep
+        assert_eq!(send(&state, "GET", "/graphql?query=%7Ba%7Bb%7D%7D").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(send(&state, "GET", "/graphql?query=%7Ba%7D").await.1, "graph");
+        assert_eq!(send(&state, "GET", "/open?query=%7Ba%7Bb%7D%7D").await.1, "graph");
+    }
+
+    #[tokio::test]
+    async fn test_route_etag_policy_answers_revalidations() {
+        use router_api::v1alpha1::vpc_route::ETagPolicy;
+        use std::sync::atomic::Ordering;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let (port, served) = cacheable_upstream("app").await;
+        register(&registry, "assets", &[port]).await;
+        let mut state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        Arc::get_mut(&mut state).unwrap().conditional = Some(Arc::new(ConditionalResponder::new(100).unwrap()));
+        let mut tagged = route("tagged", "/assets", vec![RouteDestination::service("assets")]);
+        tagged.spec.etag = Some(ETagPolicy::default());
+        state.router.sync_routes(vec![tagged, route("plain", "/plain", vec![RouteDestination::service("assets")])]);
+
+        let req = Request::get("/assets/app.js").body(Full::new(Bytes::new())).unwrap();
+        let response = handle_request(req, ([127, 0, 0, 1], 40000).into(), "http", state.clone(), false).await.unwrap();
+        let etag = response.headers()[hyper::header::ETAG].to_str().unwrap().to_string();
+
+        // The revalidation is answered without the backend
+        let revalidation = Request::get("/assets/app.js").header(hyper::header::IF_NONE_MATCH, &etag);
+        assert_eq!(send_with(&state, revalidation).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+
+        let revalidation = Request::get("/plain/app.js").header(hyper::header::IF_NONE_MATCH, &etag);
+        assert_eq!(send_with(&state, revalidation).await, (StatusCode::OK, "app".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_route_concurrency_policy_sheds_excess_requests() {
+        use router_api::v1alpha1::vpc_route::ConcurrencyPolicy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        register(&registry, "api", &[upstream("api").await]).await;
+        register(&registry, "web", &[upstream("web").await]).await;
+        let mut state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let config =
+            ConcurrencyConfig { max_concurrent_requests: 1, queue_length: 0, queue_timeout: Duration::from_millis(10) };
+        let gateway_limiter = Arc::new(ConcurrencyLimiter::new(config));
+        Arc::get_mut(&mut state).unwrap().concurrency_limiter = Some(gateway_limiter.clone());
+        let mut api = route("api", "/api", vec![RouteDestination::service("api")]);
+        api.spec.concurrency = Some(ConcurrencyPolicy { max_concurrent_requests: 1, queue_length: 0, queue_timeout_ms: 10 });
+        state.router.sync_routes(vec![api.clone(), route("web", "/web", vec![RouteDestination::service("web")])]);
+
+        // With the route's only slot taken, its requests are shed; other routes are unaffected
+        let busy = state.router.route_concurrency_limiter(&api).unwrap().acquire().await.unwrap();
+        assert_eq!(send(&state, "GET", "/api/items").await.0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(send(&state, "GET", "/web/items").await.1, "web");
+        drop(busy);
+        assert_eq!(send(&state, "GET", "/api/items").await.1, "api");
+
+        // Routes without a policy share the gateway's limit
+        let busy = gateway_limiter.acquire().await.unwrap();
+        assert_eq!(send(&state, "GET", "/web/items").await.0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(send(&state, "GET", "/api/items").await.1, "api");
+        drop(busy);
+    }
+
+    #[tokio::test]
+    async fn test_cache_purge_by_route_and_pattern() {
+        use router_api::v1alpha1::vpc_route::ResponseCachePolicy;
+        use std::sync::atomic::Ordering;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let (catalog_port, catalog_served) = cacheable_upstream("catalog").await;
+        let (search_port, search_served) = cacheable_upstream("search").await;
+        register(&registry, "catalog", &[catalog_port]).await;
+        register(&registry, "search", &[search_port]).await;
+        let mut state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        Arc::get_mut(&mut state).unwrap().cache_purge_key = Some("purge-key".to_string());
+        let cached = |name: &str, prefix: &str| {
+            let mut route = route(name, prefix, vec![RouteDestination::service(name)]);
+            route.spec.cache = Some(ResponseCachePolicy { enabled: true, ..Default::default() });
+            route
+        };
+        state.router.sync_routes(vec![cached("catalog", "/catalog"), cached("search", "/search")]);
+        let warm = || async {
+            for path in ["/catalog/shoes/images", "/catalog/hats/images", "/catalog/shoes/reviews", "/search/shoes"] {
+                send(&state, "GET", path).await;
+            }
+        };
+        let purge = |query: &str, key: &str| {
+            Request::builder().method("DELETE").uri(format!("/cache?{}", query)).header("x-api-key", key)
+        };
+        warm().await;
+        assert_eq!(catalog_served.load(Ordering::SeqCst), 3);
+
+        // Purges need the API key, or the admin token
+        assert_eq!(send_with(&state, purge("pattern=/*", "wrong")).await.0, StatusCode::FORBIDDEN);
+
+        let (status, body) = send_with(&state, purge("pattern=/catalog/*/images", "purge-key")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, r#"{"purged":2}"#));
+        warm().await;
+        assert_eq!(catalog_served.load(Ordering::SeqCst), 5);
+
+        assert_eq!(send_with(&state, purge("route=default/search", "purge-key")).await.1, r#"{"purged":1}"#);
+        assert_eq!(send_with(&state, purge("route=default/gone", "purge-key")).await.0, StatusCode::NOT_FOUND);
+        warm().await;
+        assert_eq!((catalog_served.load(Ordering::SeqCst), search_served.load(Ordering::SeqCst)), (5, 2));
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_warms_the_cache() {
+        use router_api::v1alpha1::vpc_route::ResponseCachePolicy;
+        use std::sync::atomic::Ordering;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let (port, served) = cacheable_upstream("catalog").await;
+        register(&registry, "catalog", &[port]).await;
+        let state = gateway(Router::new(registry), "http://127.0.0.1:9");
+        let mut catalog = route("catalog", "/catalog", vec![RouteDestination::service("catalog")]);
+        catalog.spec.cache = Some(ResponseCachePolicy { enabled: true, ..Default::default() });
+        state.router.sync_routes(vec![catalog]);
+
+        prefetch_cache(state.clone(), vec!["http://shop.example.com/catalog/featured".to_string()]).await;
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+
+        let request = Request::builder().uri("/catalog/featured").header(hyper::header::HOST, "shop.example.com");
+        assert_eq!(send_with(&state, request).await.1, "catalog");
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,91 @@
+//! Authorization policies loaded from ConfigMaps
+//!
+//! Org-wide access rules are written as Cedar policies in ConfigMaps of
+//! one namespace, selected by label, so they are managed like any other
+//! configuration and reach every replica without a restart. Every data key
+//! ending in `.cedar` holds policies; the gateway watches the ConfigMaps
+//! and replaces its policies whenever one changes. Only the configured
+//! namespace is read, since anyone able to write a policy ConfigMap can
+//! grant access.
+
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client, ResourceExt};
+use router_core::watch::{LoggingObserver, WatchConfig, WatchObserver};
+use router_proxy::PolicyAuthorizer;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::sources;
+
+/// Label selecting policy ConfigMaps unless configured otherwise
+pub const DEFAULT_SELECTOR: &str = "router.datum.net/authz-policy=cedar";
+
+/// Where policy ConfigMaps are found
+#[derive(Clone, Debug)]
+pub struct PolicySource {
+    pub namespace: String,
+    pub selector: String,
+}
+
+/// Policy texts of `configmaps`, named namespace/name/key, in a stable order
+fn policy_texts(configmaps: &[Arc<ConfigMap>]) -> Vec<(String, String)> {
+    let mut texts: Vec<(String, String)> = configmaps
+        .iter()
+        .flat_map(|configmap| {
+            let prefix = format!("{}/{}", configmap.namespace().unwrap_or_default(), configmap.name_any());
+            configmap
+                .data
+                .iter()
+                .flatten()
+                .filter(|(key, _)| key.ends_with(".cedar"))
+                .map(move |(key, text)| (format!("{}/{}", prefix, key), text.clone()))
+        })
+        .collect();
+    texts.sort();
+    texts
+}
+
+/// Watch the policy ConfigMaps of `source`, loading their policies into
+/// `authorizer` on every change
+pub async fn watch(client: Client, source: PolicySource, authorizer: Arc<PolicyAuthorizer>) {
+    let config = WatchConfig {
+        label_selector: Some(source.selector.clone()),
+        ..Default::default()
+    };
+    let observer: Arc<dyn WatchObserver> = Arc::new(LoggingObserver);
+    let (configmaps, changes) = sources::changes(Api::<ConfigMap>::namespaced(client, &source.namespace), &config, observer);
+    let mut changes = changes.boxed();
+
+    while changes.next().await.is_some() {
+        let texts = policy_texts(&configmaps.state());
+        match authorizer.load(&texts) {
+            Ok(count) => info!("Loaded {} authorization policies from {} ConfigMap keys", count, texts.len()),
+            Err(e) => warn!("Keeping current authorization policies: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configmap(name: &str, data: &[(&str, &str)]) -> Arc<ConfigMap> {
+        let mut configmap = ConfigMap::default();
+        configmap.metadata.name = Some(name.to_string());
+        configmap.metadata.namespace = Some("datum-router".to_string());
+        configmap.data = Some(data.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect());
+        Arc::new(configmap)
+    }
+
+    #[test]
+    fn test_policy_texts() {
+        let texts = policy_texts(&[
+            configmap("teams", &[("payments.cedar", "permit(principal, action, resource);"), ("README", "notes")]),
+            configmap("org", &[("base.cedar", "forbid(principal, action, resource);")]),
+            Arc::new(ConfigMap::default()),
+        ]);
+        let names: Vec<&str> = texts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["datum-router/org/base.cedar", "datum-router/teams/payments.cedar"]);
+    }
+}
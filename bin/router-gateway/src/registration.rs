@@ -0,0 +1,208 @@
+//! Self-registration of the gateway replica as a RouterGateway
+//!
+//! Running in Kubernetes, each replica creates a RouterGateway named after
+//! its pod and owned by it, so it goes away with the pod, and reports its
+//! version, address, loaded RouterConfig generation, and readiness on every
+//! heartbeat. A new route table generation is reported right away, without
+//! waiting for the next heartbeat, so the controller sees route changes go
+//! live promptly. When the replica starts draining it reports itself not
+//! ready one last time.
+
+use chrono::{DateTime, Utc};
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client, Resource};
+use router_core::ApiObserver;
+use router_api::v1alpha1::router_gateway::{RouterGatewaySpec, RouterGatewayStatus};
+use router_api::RouterGateway;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::cli::Cli;
+use crate::config::{self, ConfigVersion};
+use crate::routes::RouteTable;
+use crate::GatewayState;
+
+/// Field manager of the gateway's applies
+const FIELD_MANAGER: &str = "router-gateway";
+
+/// Keeps this replica's RouterGateway current
+pub struct Registration {
+    api: Api<RouterGateway>,
+    name: String,
+    pod_uid: Option<String>,
+    replica: Replica,
+    interval: Duration,
+    registered: bool,
+}
+
+/// What a replica reports about itself
+struct Replica {
+    spec: RouterGatewaySpec,
+    address: Option<String>,
+    config: Option<ConfigVersion>,
+    start_time: DateTime<Utc>,
+}
+
+impl Registration {
+    /// Registration for this replica, if it runs in Kubernetes
+    ///
+    /// Without `--pod-name` and `--namespace` the replica isn't registered.
+    ///
+    /// Environment variables:
+    /// - POD_UID: Pod UID; the pod owns its RouterGateway so it is deleted with the pod
+    /// - POD_IP: Address reported for the replica
+    /// - NODE_NAME: Node reported for the replica
+    /// - ROUTER_GATEWAY_HEARTBEAT_SECS: Interval between status reports (default: 30)
+    ///
+    /// The replica's Kubernetes client, shared with its watches, is set up
+    /// from the `--kube-*` flags and reports its requests to `observer`.
+    pub async fn from_env(cli: &Cli, config: Option<ConfigVersion>, observer: Arc<dyn ApiObserver>) -> Option<Self> {
+        let name = cli.pod_name.clone()?;
+        let namespace = cli.namespace.clone()?;
+        let client = match cli.kube.client_config("router-gateway").build(observer).await {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Gateway registration disabled: {}", e);
+                return None;
+            }
+        };
+        let interval = config::var("ROUTER_GATEWAY_HEARTBEAT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+
+        Some(Self {
+            api: Api::namespaced(client, &namespace),
+            replica: Replica {
+                spec: RouterGatewaySpec { pod_name: name.clone(), node_name: config::var("NODE_NAME").ok() },
+                address: config::var("POD_IP").ok(),
+                config,
+                start_time: Utc::now(),
+            },
+            name,
+            pod_uid: config::var("POD_UID").ok(),
+            interval: Duration::from_secs(interval),
+            registered: false,
+        })
+    }
+
+    /// Client the registration reports with
+    pub fn client(&self) -> Client {
+        self.api.clone().into()
+    }
+
+    /// Report until the gateway starts draining, then report it not ready
+    pub async fn run(mut self, state: Arc<GatewayState>) {
+        info!("Registering as RouterGateway {}, heartbeat every {:?}", self.name, self.interval);
+        let mut ticker = tokio::time::interval(self.interval);
+        let mut routes = state.routes.subscribe();
+        loop {
+            let draining = tokio::select! {
+                _ = ticker.tick() => false,
+                Ok(()) = routes.changed() => false,
+                _ = state.drain.started() => true,
+            };
+            self.heartbeat(&state).await;
+            if draining {
+                break;
+            }
+        }
+    }
+
+    async fn heartbeat(&mut self, state: &GatewayState) {
+        if !self.registered {
+            match self.register().await {
+                Ok(()) => self.registered = true,
+                Err(e) => {
+                    warn!("Failed to register RouterGateway {}: {}", self.name, e);
+                    return;
+                }
+            }
+        }
+
+        let routes = state.routes.current();
+        let status = self.replica.status(!state.drain.is_draining(), state.inflight.len() as u64, &routes, Utc::now());
+        let patch = json!({
+            "apiVersion": RouterGateway::api_version(&()),
+            "kind": RouterGateway::kind(&()),
+            "status": status,
+        });
+        let params = PatchParams::apply(FIELD_MANAGER).force();
+        match self.api.patch_status(&self.name, &params, &Patch::Apply(&patch)).await {
+            Ok(_) => debug!("Reported RouterGateway {} status", self.name),
+            Err(e) => warn!("Failed to report RouterGateway {} status: {}", self.name, e),
+        }
+    }
+
+    /// Create or update the RouterGateway itself
+    async fn register(&self) -> kube::Result<()> {
+        let mut metadata = json!({ "name": self.name });
+        if let Some(uid) = &self.pod_uid {
+            metadata["ownerReferences"] = json!([{
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "name": self.replica.spec.pod_name,
+                "uid": uid,
+            }]);
+        }
+        let object = json!({
+            "apiVersion": RouterGateway::api_version(&()),
+            "kind": RouterGateway::kind(&()),
+            "metadata": metadata,
+            "spec": self.replica.spec,
+        });
+        let params = PatchParams::apply(FIELD_MANAGER).force();
+        self.api.patch(&self.name, &params, &Patch::Apply(&object)).await?;
+        Ok(())
+    }
+}
+
+impl Replica {
+    /// Status reported at `now`
+    fn status(&self, ready: bool, inflight_requests: u64, routes: &RouteTable, now: DateTime<Utc>) -> RouterGatewayStatus {
+        RouterGatewayStatus {
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            address: self.address.clone(),
+            config_name: self.config.as_ref().map(|c| c.name.clone()),
+            config_generation: self.config.as_ref().and_then(|c| c.generation),
+            route_generation: Some(routes.generation),
+            routes: routes.applied(),
+            ready,
+            inflight_requests,
+            start_time: Some(self.start_time.to_rfc3339()),
+            last_heartbeat_time: Some(now.to_rfc3339()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::RouteKey;
+
+    #[test]
+    fn test_status() {
+        let replica = Replica {
+            spec: RouterGatewaySpec { pod_name: "gateway-0".to_string(), node_name: None },
+            address: Some("10.0.0.5".to_string()),
+            config: Some(ConfigVersion { name: "default".to_string(), generation: Some(7) }),
+            start_time: "2026-01-01T00:00:00Z".parse().unwrap(),
+        };
+
+        let mut routes = RouteTable { generation: 4, ..Default::default() };
+        let key = RouteKey { kind: "VPCRoute", namespace: "default".to_string(), name: "api".to_string() };
+        routes.routes.insert(key, 2);
+
+        let status = replica.status(false, 3, &routes, "2026-01-01T00:05:00Z".parse().unwrap());
+        assert_eq!(status.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(status.address.as_deref(), Some("10.0.0.5"));
+        assert_eq!((status.config_name.as_deref(), status.config_generation), (Some("default"), Some(7)));
+        assert_eq!(status.route_generation, Some(4));
+        assert_eq!((status.routes[0].name.as_str(), status.routes[0].generation), ("api", 2));
+        assert!(!status.ready);
+        assert_eq!(status.inflight_requests, 3);
+        assert_eq!(status.last_heartbeat_time.as_deref(), Some("2026-01-01T00:05:00+00:00"));
+    }
+}
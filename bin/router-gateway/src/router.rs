@@ -1,39 +1,278 @@
 //! Router for matching requests to VPCRoutes and selecting backends
 
-use router_core::ServiceRegistry;
-use std::sync::Arc;
-use tracing::debug;
+use anyhow::{Context, Result};
+use hyper::header::{HeaderValue, CONTENT_TYPE, USER_AGENT};
+use hyper::HeaderMap;
+use regex::Regex;
+use router_api::v1alpha1::vpc_route::{
+    DarkLaunchMatch, LoadBalancingPolicy, RouteDestination, RouteMatch, SecretKeyRef,
+    TrailingSlashPolicy, VPCRouteSpec,
+};
+use semver::{Version, VersionReq};
+use router_core::{Endpoint, ServiceRegistry, SourceVpc};
+use router_galactic::NatTable;
+use router_proxy::{
+    AffinityKeyExtractor, BandwidthConfig, BandwidthLimiter, ConcurrencyConfig, ConcurrencyLimiter, LoadBalancer,
+    SessionPins, StickyCookie,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::{debug, warn};
 
 /// Router for matching HTTP requests to VPCRoutes
 pub struct Router {
     registry: Arc<ServiceRegistry>,
+    secrets_dir: Option<PathBuf>,
+    /// Values of the secret keys routes refer to, by name/key, as last read
+    /// from the secrets directory
+    secrets: RwLock<HashMap<String, String>>,
+    sticky_cookie_key: Arc<[u8]>,
+    /// VPCRoutes requests are matched against, most specific first
+    routes: RwLock<Arc<Vec<Route>>>,
+    /// Load balancer of each VPCRoute, by namespace/name, with the policy
+    /// it was built for
+    route_balancers: RwLock<HashMap<String, (LoadBalancingPolicy, Arc<LoadBalancer>)>>,
+    /// Concurrency limiter of each VPCRoute with a concurrency policy, by
+    /// namespace/name
+    route_limiters: RwLock<HashMap<String, Arc<ConcurrencyLimiter>>>,
+    /// Bandwidth limiter of each VPCRoute with a bandwidth policy, by
+    /// namespace/name
+    route_bandwidth: RwLock<HashMap<String, Arc<BandwidthLimiter>>>,
 }
 
 impl Router {
     /// Create a new router with a service registry
     pub fn new(registry: Arc<ServiceRegistry>) -> Self {
-        Self { registry }
+        Self {
+            registry,
+            secrets_dir: None,
+            secrets: RwLock::default(),
+            sticky_cookie_key: Arc::from(&[][..]),
+            routes: RwLock::default(),
+            route_balancers: RwLock::default(),
+            route_limiters: RwLock::default(),
+            route_bandwidth: RwLock::default(),
+        }
+    }
+
+    /// Resolve secret references from Secrets mounted under `dir`
+    ///
+    /// Each Secret is expected at `<dir>/<name>/<key>`, the layout of a
+    /// Secret volume mounted per name.
+    pub fn with_secrets_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.secrets_dir = Some(dir.into());
+        self
+    }
+
+    /// Whether secret references are resolved at all
+    pub fn has_secrets_dir(&self) -> bool {
+        self.secrets_dir.is_some()
+    }
+
+    /// Sign sticky cookies with `key`
+    ///
+    /// Replicas must share the key to honor each other's cookies.
+    pub fn with_sticky_cookie_key(mut self, key: &[u8]) -> Self {
+        self.sticky_cookie_key = key.into();
+        self
+    }
+
+    /// The sticky cookie of a route whose affinity uses one
+    pub fn sticky_cookie(&self, route: &VPCRouteSpec) -> Option<StickyCookie> {
+        StickyCookie::from_policy(route.affinity.as_ref()?, &self.sticky_cookie_key)
     }
 
-    /// Match a request path against route patterns
-    pub fn match_path(&self, path: &str, pattern: &str) -> bool {
-        // Exact match
-        if pattern == path {
-            return true;
+    /// The load balancer of the VPCRoute `route_id` (namespace/name), for
+    /// its load balancing `policy`
+    ///
+    /// Each route keeps its own balancer, so round-robin positions and
+    /// connection counts aren't shared between routes. A changed policy
+    /// replaces the balancer.
+    pub fn route_load_balancer(&self, route_id: &str, policy: &LoadBalancingPolicy) -> Arc<LoadBalancer> {
+        if let Some((built_for, balancer)) = self.route_balancers.read().unwrap().get(route_id) {
+            if built_for == policy {
+                return balancer.clone();
+            }
+        }
+        let mut balancers = self.route_balancers.write().unwrap();
+        let (built_for, balancer) = balancers
+            .entry(route_id.to_string())
+            .or_insert_with(|| (policy.clone(), Arc::new(LoadBalancer::new(policy.into()))));
+        if built_for != policy {
+            debug!("Load balancing of route {} changed to {:?}", route_id, policy);
+            *built_for = policy.clone();
+            *balancer = Arc::new(LoadBalancer::new(policy.into()));
         }
+        balancer.clone()
+    }
 
-        // Prefix match (pattern ends with / or /*)
-        if pattern.ends_with('/') && path.starts_with(pattern) {
-            return true;
+    /// The concurrency limiter of a route with a concurrency policy
+    ///
+    /// Requests of the route share the limiter, and its queue, until the
+    /// policy changes.
+    pub fn route_concurrency_limiter(&self, route: &Route) -> Option<Arc<ConcurrencyLimiter>> {
+        let config = ConcurrencyConfig::from_policy(route.spec.concurrency.as_ref()?);
+        if let Some(limiter) = self.route_limiters.read().unwrap().get(&route.id) {
+            if limiter.config() == &config {
+                return Some(limiter.clone());
+            }
+        }
+        let mut limiters = self.route_limiters.write().unwrap();
+        let limiter = limiters
+            .entry(route.id.clone())
+            .or_insert_with(|| Arc::new(ConcurrencyLimiter::new(config.clone())));
+        if limiter.config() != &config {
+            debug!("Concurrency limit of route {} changed to {:?}", route.id, config);
+            *limiter = Arc::new(ConcurrencyLimiter::new(config));
         }
+        Some(limiter.clone())
+    }
 
-        // Prefix match with wildcard
-        if pattern.ends_with("/*") {
-            let prefix = &pattern[..pattern.len() - 2];
-            return path == prefix || path.starts_with(&format!("{}/", prefix));
+    /// The bandwidth limiter of a route with a bandwidth policy
+    ///
+    /// Responses of the route share the limiter's bucket until the policy
+    /// changes.
+    pub fn route_bandwidth_limiter(&self, route: &Route) -> Option<Arc<BandwidthLimiter>> {
+        let config = BandwidthConfig::from_policy(route.spec.bandwidth.as_ref()?);
+        if config.bytes_per_second.is_none() && config.per_connection_bytes_per_second.is_none() {
+            return None;
+        }
+        if let Some(limiter) = self.route_bandwidth.read().unwrap().get(&route.id) {
+            if limiter.config() == &config {
+                return Some(limiter.clone());
+            }
         }
+        let mut limiters = self.route_bandwidth.write().unwrap();
+        let limiter = limiters
+            .entry(route.id.clone())
+            .or_insert_with(|| Arc::new(BandwidthLimiter::new(config.clone())));
+        if limiter.config() != &config {
+            debug!("Bandwidth limit of route {} changed to {:?}", route.id, config);
+            *limiter = Arc::new(BandwidthLimiter::new(config));
+        }
+        Some(limiter.clone())
+    }
+
+    /// The VPCRoutes requests are matched against
+    pub fn routes(&self) -> Arc<Vec<Route>> {
+        self.routes.read().unwrap().clone()
+    }
+
+    /// Replace the routes requests are matched against, bringing the route
+    /// load balancers, concurrency and bandwidth limiters in line with their
+    /// policies
+    ///
+    /// Routes are kept most specific first: exact paths, then longer
+    /// prefixes, then more request conditions, so a route narrowed to a
+    /// content type or client isn't shadowed by a catch-all for the same
+    /// prefix. Ties go by namespace and name.
+    pub fn sync_routes(&self, mut routes: Vec<Route>) {
+        routes.sort_by(|a, b| {
+            specificity(&b.spec.r#match).cmp(&specificity(&a.spec.r#match)).then_with(|| a.id.cmp(&b.id))
+        });
+        let policies = routes
+            .iter()
+            .map(|route| (route.id.clone(), route.spec.load_balancing.clone()))
+            .collect();
+        self.sync_route_balancers(&policies);
+        self.route_limiters.write().unwrap().retain(|route_id, _| {
+            routes.iter().any(|route| &route.id == route_id && route.spec.concurrency.is_some())
+        });
+        self.route_bandwidth.write().unwrap().retain(|route_id, _| {
+            routes.iter().any(|route| &route.id == route_id && route.spec.bandwidth.is_some())
+        });
+        for route in &routes {
+            self.route_concurrency_limiter(route);
+            self.route_bandwidth_limiter(route);
+        }
+        *self.routes.write().unwrap() = Arc::new(routes);
+        self.refresh_secrets();
+    }
+
+    /// Re-read the secret keys the routes refer to
+    ///
+    /// Requests are matched against the values read here, so the files
+    /// aren't read per request; calling this periodically makes rotated
+    /// Secrets take effect without a restart.
+    pub fn refresh_secrets(&self) {
+        let mut secrets = HashMap::new();
+        for route in self.routes().iter() {
+            let Some(dark_launch) = &route.spec.r#match.dark_launch else {
+                continue;
+            };
+            let secret_ref = &dark_launch.secret_ref;
+            if let Some(value) = self.read_secret(secret_ref) {
+                secrets.insert(format!("{}/{}", secret_ref.name, secret_ref.key), value);
+            }
+        }
+        *self.secrets.write().unwrap() = secrets;
+    }
+
+    /// Bring the route load balancers in line with `routes`, the policy of
+    /// every VPCRoute by namespace/name
+    ///
+    /// Balancers of routes no longer listed are dropped; those whose policy
+    /// is unchanged are kept along with their state.
+    pub fn sync_route_balancers(&self, routes: &BTreeMap<String, LoadBalancingPolicy>) {
+        self.route_balancers.write().unwrap().retain(|route_id, _| routes.contains_key(route_id));
+        for (route_id, policy) in routes {
+            self.route_load_balancer(route_id, policy);
+        }
+    }
+
+    /// Match a request path against a route's exact path or prefix
+    ///
+    /// Prefixes match whole segments: "/api" matches "/api" and "/api/users"
+    /// but not "/apis". Honors the route's trailing slash and
+    /// case-insensitivity options.
+    pub fn match_route_path(&self, route_match: &RouteMatch, path: &str) -> bool {
+        let normalize = |p: &str| {
+            let p = match route_match.trailing_slash {
+                TrailingSlashPolicy::Strict => p,
+                TrailingSlashPolicy::Ignore | TrailingSlashPolicy::Redirect => trim_trailing_slash(p),
+            };
+            if route_match.case_insensitive {
+                p.to_ascii_lowercase()
+            } else {
+                p.to_string()
+            }
+        };
+
+        match (&route_match.exact_path, &route_match.path_prefix) {
+            (Some(exact), _) => normalize(path) == normalize(exact),
+            (None, Some(prefix)) => {
+                let prefix = normalize(prefix);
+                normalize(path)
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+            }
+            (None, None) => true,
+        }
+    }
 
-        false
+    /// Canonical form of a matched path, when the route redirects to it
+    ///
+    /// With the redirect policy, exact routes use the trailing slash form of
+    /// `exactPath` and prefix routes drop the trailing slash. Returns None
+    /// when the path is already canonical.
+    pub fn canonical_path(&self, route_match: &RouteMatch, path: &str) -> Option<String> {
+        if route_match.trailing_slash != TrailingSlashPolicy::Redirect || !self.match_route_path(route_match, path) {
+            return None;
+        }
+        let trimmed = trim_trailing_slash(path);
+        let wants_slash = route_match
+            .exact_path
+            .as_deref()
+            .is_some_and(|exact| exact.len() > 1 && exact.ends_with('/'));
+        let canonical = if wants_slash && trimmed != "/" {
+            format!("{}/", trimmed)
+        } else {
+            trimmed.to_string()
+        };
+        (canonical != path).then_some(canonical)
     }
 
     /// Match HTTP method against allowed methods
@@ -45,9 +284,397 @@ impl Router {
         allowed_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
     }
 
-    /// Get the service registry
-    pub fn registry(&self) -> &Arc<ServiceRegistry> {
-        &self.registry
+    /// Match a request Content-Type against allowed content types
+    ///
+    /// Parameters are ignored, "type/*" matches any subtype, and a pattern
+    /// matches its structured-syntax variants ("application/grpc" matches
+    /// "application/grpc+proto").
+    pub fn match_content_type(&self, content_type: Option<&str>, allowed_content_types: &[String]) -> bool {
+        if allowed_content_types.is_empty() {
+            return true; // If no content types specified, match all
+        }
+
+        let Some(content_type) = content_type else {
+            return false;
+        };
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+
+        allowed_content_types.iter().any(|pattern| {
+            let pattern = pattern.trim().to_lowercase();
+            match pattern.strip_suffix("/*") {
+                Some(top_level) => media_type.split('/').next() == Some(top_level),
+                None => {
+                    media_type == pattern
+                        || media_type.strip_prefix(pattern.as_str()).is_some_and(|rest| rest.starts_with('+'))
+                }
+            }
+        })
+    }
+
+    /// Match the User-Agent header against a route's compiled pattern
+    pub fn match_user_agent(&self, headers: &HeaderMap, pattern: &Regex) -> bool {
+        headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|user_agent| pattern.is_match(user_agent))
+    }
+
+    /// Match the client version in `header` against a semver range
+    ///
+    /// Partial versions are padded ("2.3" is 2.3.0) and a leading "v" is
+    /// ignored. Missing or unparseable versions never match.
+    pub fn match_client_version(&self, headers: &HeaderMap, header: &str, range: &VersionReq) -> bool {
+        headers
+            .get(header)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_client_version)
+            .is_some_and(|version| range.matches(&version))
+    }
+
+    /// The value of a secret key a route refers to, as last refreshed
+    pub fn secret_value(&self, secret_ref: &SecretKeyRef) -> Option<String> {
+        let id = format!("{}/{}", secret_ref.name, secret_ref.key);
+        self.secrets.read().unwrap().get(&id).cloned()
+    }
+
+    /// Read the value of a secret key from the secrets directory
+    fn read_secret(&self, secret_ref: &SecretKeyRef) -> Option<String> {
+        let dir = self.secrets_dir.as_ref()?;
+        if [&secret_ref.name, &secret_ref.key]
+            .iter()
+            .any(|part| part.is_empty() || part.contains(['/', '\\']) || part.starts_with('.'))
+        {
+            warn!("Invalid secret reference {}/{}", secret_ref.name, secret_ref.key);
+            return None;
+        }
+
+        match std::fs::read_to_string(dir.join(&secret_ref.name).join(&secret_ref.key)) {
+            Ok(value) => Some(value.trim_end_matches(['\r', '\n']).to_string()),
+            Err(e) => {
+                warn!("Failed to read secret {}/{}: {}", secret_ref.name, secret_ref.key, e);
+                None
+            }
+        }
+    }
+
+    /// Check a request carries the dark launch token
+    ///
+    /// An unresolvable or empty secret never matches, so a misconfigured
+    /// dark launch stays hidden.
+    pub fn match_dark_launch(&self, headers: &HeaderMap, dark_launch: &DarkLaunchMatch) -> bool {
+        let Some(expected) = self.secret_value(&dark_launch.secret_ref).filter(|v| !v.is_empty()) else {
+            return false;
+        };
+        headers
+            .get_all(dark_launch.header.as_str())
+            .iter()
+            .any(|v| constant_time_eq(v.as_bytes(), expected.as_bytes()))
+    }
+
+    /// Match request headers against required header values
+    pub fn match_headers(&self, headers: &HeaderMap, required: &BTreeMap<String, String>) -> bool {
+        required.iter().all(|(name, value)| {
+            headers
+                .get_all(name.as_str())
+                .iter()
+                .any(|v| v.to_str().map(|v| v == value).unwrap_or(false))
+        })
+    }
+
+    /// Match a request query against required query parameter values
+    ///
+    /// Names and values are compared as sent; a parameter given more than
+    /// once matches if any of its values does.
+    pub fn match_query_params(&self, query: Option<&str>, required: &BTreeMap<String, String>) -> bool {
+        required.iter().all(|(name, value)| {
+            query.unwrap_or_default().split('&').any(|pair| {
+                let (n, v) = pair.split_once('=').unwrap_or((pair, ""));
+                n == name && v == value
+            })
+        })
+    }
+
+    /// Match a request against a route's path, query, method, header,
+    /// content type, and client conditions
+    pub fn match_request(
+        &self,
+        route: &Route,
+        method: &str,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+    ) -> bool {
+        let route_match = &route.spec.r#match;
+        let path_matches = self.match_route_path(route_match, path);
+        let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+
+        path_matches
+            && self.match_method(method, &route_match.methods)
+            && self.match_query_params(query, &route_match.query_params)
+            && self.match_headers(headers, &route_match.headers)
+            && self.match_content_type(content_type, &route_match.content_types)
+            && route.user_agent.as_ref().is_none_or(|p| self.match_user_agent(headers, p))
+            && route_match
+                .client_version
+                .as_ref()
+                .zip(route.client_version.as_ref())
+                .is_none_or(|(v, range)| self.match_client_version(headers, &v.header, range))
+            && route_match.dark_launch.as_ref().is_none_or(|d| self.match_dark_launch(headers, d))
+    }
+
+    /// Select the route for a request from `source`
+    ///
+    /// Dark-launched routes are tried first; requests without their token
+    /// fall through to the most specific other matching route. Routes not visible
+    /// to the source VPC, or restricted to another source attachment, are
+    /// skipped as if they didn't exist.
+    pub fn select_route<'a>(
+        &self,
+        routes: &'a [Route],
+        source: Option<&SourceVpc>,
+        method: &str,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Option<&'a Route> {
+        let (dark, visible): (Vec<_>, Vec<_>) = routes
+            .iter()
+            .filter(|r| SourceVpc::allowed(source, r.spec.visibility.as_ref()))
+            .filter(|r| SourceVpc::attached(source, r.spec.source_vpc_attachment.as_deref()))
+            .partition(|r| r.spec.r#match.dark_launch.is_some());
+        dark.into_iter()
+            .chain(visible)
+            .find(|route| self.match_request(route, method, path, query, headers))
+    }
+
+    /// Select one of a route's destinations in proportion to their weights
+    ///
+    /// A destination whose VPCService has no ready endpoints, is in planned
+    /// maintenance, or isn't visible to the `source` VPC is skipped and its
+    /// share goes to the others, so a failed canary stops taking traffic.
+    /// Direct responses always count. `load_balancer` keeps the split's
+    /// progress and must be the route's own; services are looked up in
+    /// `namespace` unless the destination names another. Routes with
+    /// blue/green sets choose from the active set, and routes with a
+    /// read/write split from the set for `method`.
+    pub async fn select_destination<'a>(
+        &self,
+        route: &'a VPCRouteSpec,
+        method: &str,
+        namespace: &str,
+        source: Option<&SourceVpc>,
+        load_balancer: &LoadBalancer,
+    ) -> Option<&'a RouteDestination> {
+        let destinations = route.destinations_for_method(method);
+        let mut weighted = Vec::with_capacity(destinations.len());
+        for destination in destinations {
+            let available = destination.is_direct() || {
+                let service_ref = &destination.vpc_service_ref;
+                let service_id = format!("{}/{}", service_ref.namespace.as_deref().unwrap_or(namespace), service_ref.name);
+                self.registry
+                    .get_service(&service_id)
+                    .await
+                    .is_ok_and(|service| {
+                        service.maintenance.is_none()
+                            && service.endpoints.iter().any(|e| e.ready)
+                            && SourceVpc::allowed(source, service.visibility.as_ref())
+                    })
+            };
+            weighted.push((destination, if available { destination.weight } else { 0 }));
+        }
+        load_balancer.select_weighted(&weighted, |(_, weight)| *weight).map(|(destination, _)| *destination)
+    }
+
+    /// Select an endpoint of a service, honoring the route's session affinity
+    ///
+    /// When the route has an affinity key and the request carries it, the
+    /// endpoint is chosen by consistent hashing on the key; otherwise the
+    /// load balancer's strategy applies. With shared session pins, a session
+    /// keeps its endpoint for as long as that endpoint stays ready. Services
+    /// in planned maintenance are drained and get no new requests.
+    pub async fn select_endpoint(
+        &self,
+        service_id: &str,
+        load_balancer: &LoadBalancer,
+        affinity: Option<&AffinityKeyExtractor>,
+        pins: Option<&SessionPins>,
+        headers: &HeaderMap,
+    ) -> Option<Endpoint> {
+        let service = self.registry.get_service(service_id).await.ok()?;
+        if service.maintenance.is_some() {
+            return None;
+        }
+        let endpoints = service.endpoints;
+        let key = affinity.and_then(|a| a.extract(headers));
+
+        let (Some(key), Some(pins)) = (key.as_deref(), pins) else {
+            return load_balancer.select_with_key(&endpoints, key.as_deref()).cloned();
+        };
+
+        let pinned = pins.get(key).await.unwrap_or_else(|e| {
+            warn!("Failed to read session pin: {}", e);
+            None
+        });
+        if let Some(endpoint) = pinned.and_then(|addr| {
+            endpoints.iter().find(|e| e.ready && format!("{}:{}", e.ip, e.port) == addr)
+        }) {
+            return Some(endpoint.clone());
+        }
+
+        let endpoint = load_balancer.select_with_key(&endpoints, Some(key)).cloned()?;
+        if let Err(e) = pins.pin(key, &format!("{}:{}", endpoint.ip, endpoint.port)).await {
+            warn!("Failed to pin session: {}", e);
+        }
+        Some(endpoint)
+    }
+
+    /// Select an endpoint of a service for a route with a sticky cookie
+    ///
+    /// A request whose cookie pins it to a ready endpoint goes back to that
+    /// endpoint. Otherwise the load balancer picks one, and the returned
+    /// Set-Cookie value, to add to the response, pins the client to it.
+    pub async fn select_sticky_endpoint(
+        &self,
+        service_id: &str,
+        load_balancer: &LoadBalancer,
+        sticky: &StickyCookie,
+        headers: &HeaderMap,
+    ) -> Option<(Endpoint, Option<HeaderValue>)> {
+        let service = self.registry.get_service(service_id).await.ok()?;
+        if service.maintenance.is_some() {
+            return None;
+        }
+        let now = SystemTime::now();
+        if let Some(pinned) = sticky.endpoint(headers, service_id, now) {
+            if let Some(endpoint) = service.endpoints.iter().find(|e| e.ready && format!("{}:{}", e.ip, e.port) == pinned) {
+                return Some((endpoint.clone(), None));
+            }
+            debug!("Sticky endpoint {} of {} is gone, picking another", pinned, service_id);
+        }
+
+        let endpoint = load_balancer.select(&service.endpoints).cloned()?;
+        let cookie = sticky.cookie(service_id, &format!("{}:{}", endpoint.ip, endpoint.port), now);
+        Some((endpoint, Some(cookie)))
+    }
+
+    /// Address to connect to `endpoint` of `service_id` on a request from `source`
+    ///
+    /// An endpoint in a VPC whose networks overlap the source VPC's can't
+    /// be told apart from the source's own addresses, so it is reached
+    /// through its address translated by `nat` instead. When the service's
+    /// VPC isn't known, the endpoint's own address is used.
+    pub async fn endpoint_address(
+        &self,
+        nat: &NatTable,
+        service_id: &str,
+        endpoint: &Endpoint,
+        source: Option<&SourceVpc>,
+    ) -> String {
+        let Ok(address) = endpoint.ip.parse::<IpAddr>() else {
+            return endpoint.ip.clone();
+        };
+        let Some(vpc) = self.registry.get_service(service_id).await.ok().and_then(|service| service.vpc) else {
+            return endpoint.ip.clone();
+        };
+        let source = source.map(|source| (source.namespace.as_str(), source.name.as_str()));
+        let translated = nat.destination(source, (&vpc.namespace, &vpc.name), address);
+        if translated != address {
+            debug!("Reaching {} of {} as {}", address, service_id, translated);
+        }
+        translated.to_string()
+    }
+
+    /// URL scheme of a service's endpoints: https for VPCServices with the
+    /// HTTPS protocol, otherwise http
+    pub async fn service_scheme(&self, service_id: &str) -> &'static str {
+        match self.registry.get_service(service_id).await {
+            Ok(service) if service.protocol.eq_ignore_ascii_case("HTTPS") => "https",
+            _ => "http",
+        }
+    }
+
+}
+
+/// A VPCRoute requests are matched against
+#[derive(Clone, Debug)]
+pub struct Route {
+    /// The VPCRoute, as namespace/name
+    pub id: String,
+    /// Namespace of the VPCRoute, where its destinations are looked up
+    /// unless they name another
+    pub namespace: String,
+    pub spec: VPCRouteSpec,
+    /// The match's `userAgent` pattern, compiled
+    user_agent: Option<Regex>,
+    /// The match's `clientVersion` range, parsed
+    client_version: Option<VersionReq>,
+}
+
+impl Route {
+    /// The VPCRoute `name` in `namespace`
+    ///
+    /// Fails when the match's `userAgent` pattern or `clientVersion` range
+    /// is invalid, so the route can be left out rather than never match.
+    pub fn new(namespace: &str, name: &str, spec: VPCRouteSpec) -> Result<Self> {
+        let id = format!("{}/{}", namespace, name);
+        let user_agent = spec
+            .r#match
+            .user_agent
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| format!("Invalid userAgent pattern in VPCRoute {}", id))?;
+        let client_version = spec
+            .r#match
+            .client_version
+            .as_ref()
+            .map(|v| VersionReq::parse(&v.range))
+            .transpose()
+            .with_context(|| format!("Invalid clientVersion range in VPCRoute {}", id))?;
+        Ok(Self { id, namespace: namespace.to_string(), spec, user_agent, client_version })
+    }
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// How narrowly a route matches requests, greater being narrower
+fn specificity(route_match: &RouteMatch) -> (bool, usize, usize) {
+    let conditions = route_match.headers.len()
+        + route_match.query_params.len()
+        + usize::from(!route_match.methods.is_empty())
+        + usize::from(!route_match.content_types.is_empty())
+        + usize::from(route_match.user_agent.is_some())
+        + usize::from(route_match.client_version.is_some());
+    let prefix = route_match.path_prefix.as_deref().map_or(0, str::len);
+    (route_match.exact_path.is_some(), prefix, conditions)
+}
+
+/// Parse a client-reported version leniently
+fn parse_client_version(value: &str) -> Option<Version> {
+    let value = value.trim();
+    let value = value.strip_prefix(['v', 'V']).unwrap_or(value);
+    if let Ok(version) = Version::parse(value) {
+        return Some(version);
+    }
+
+    let mut parts = value.split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Version::new(major, minor, patch))
+}
+
+/// Remove trailing slashes, keeping the root path
+fn trim_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
     }
 }
 
@@ -58,25 +685,70 @@ mod tests {
     #[test]
     fn test_exact_path_match() {
         let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
-        assert!(router.match_path("/api/v1/users", "/api/v1/users"));
-        assert!(!router.match_path("/api/v2/users", "/api/v1/users"));
+        let route_match = RouteMatch {
+            exact_path: Some("/api/v1/users".to_string()),
+            ..Default::default()
+        };
+        assert!(router.match_route_path(&route_match, "/api/v1/users"));
+        assert!(!router.match_route_path(&route_match, "/api/v2/users"));
+        assert!(!router.match_route_path(&route_match, "/api/v1/users/7"));
     }
 
     #[test]
     fn test_prefix_path_match() {
         let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
-        assert!(router.match_path("/api/v1/users", "/api/v1/"));
-        assert!(router.match_path("/api/v1/", "/api/v1/"));
-        assert!(!router.match_path("/api/v2/users", "/api/v1/"));
+        let route_match = RouteMatch {
+            path_prefix: Some("/api/v1/".to_string()),
+            ..Default::default()
+        };
+        assert!(router.match_route_path(&route_match, "/api/v1/users"));
+        assert!(router.match_route_path(&route_match, "/api/v1/"));
+        assert!(!router.match_route_path(&route_match, "/api/v2/users"));
+
+        // Prefixes without a trailing slash end at a segment boundary
+        let route_match = RouteMatch {
+            path_prefix: Some("/api".to_string()),
+            ..Default::default()
+        };
+        assert!(router.match_route_path(&route_match, "/api"));
+        assert!(router.match_route_path(&route_match, "/api/users"));
+        assert!(!router.match_route_path(&route_match, "/apis"));
+        assert!(!router.match_route_path(&route_match, "/api-internal/users"));
+
+        let root = RouteMatch {
+            path_prefix: Some("/".to_string()),
+            ..Default::default()
+        };
+        assert!(router.match_route_path(&root, "/anything"));
     }
 
     #[test]
-    fn test_wildcard_path_match() {
+    fn test_trailing_slash_and_case_options() {
         let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
-        assert!(router.match_path("/api/v1/users", "/api/v1/*"));
-        assert!(router.match_path("/api/v1/posts", "/api/v1/*"));
-        assert!(router.match_path("/api/v1/", "/api/v1/*"));
-        assert!(!router.match_path("/api/v2/users", "/api/v1/*"));
+        let mut route_match = RouteMatch {
+            exact_path: Some("/Users".to_string()),
+            ..Default::default()
+        };
+        assert!(router.match_route_path(&route_match, "/Users"));
+        assert!(!router.match_route_path(&route_match, "/Users/"));
+        assert!(!router.match_route_path(&route_match, "/users"));
+
+        route_match.case_insensitive = true;
+        route_match.trailing_slash = TrailingSlashPolicy::Ignore;
+        assert!(router.match_route_path(&route_match, "/users/"));
+        assert_eq!(router.canonical_path(&route_match, "/users/"), None);
+
+        route_match.trailing_slash = TrailingSlashPolicy::Redirect;
+        assert_eq!(router.canonical_path(&route_match, "/users/").as_deref(), Some("/users"));
+        assert_eq!(router.canonical_path(&route_match, "/users"), None);
+        assert_eq!(router.canonical_path(&route_match, "/orders/"), None);
+
+        let directory_match = RouteMatch {
+            exact_path: Some("/docs/".to_string()),
+            trailing_slash: TrailingSlashPolicy::Redirect,
+            ..Default::default()
+        };
+        assert_eq!(router.canonical_path(&directory_match, "/docs").as_deref(), Some("/docs/"));
     }
 
     #[test]
@@ -89,6 +761,628 @@ mod tests {
         assert!(!router.match_method("DELETE", &methods));
     }
 
+    #[tokio::test]
+    async fn test_select_endpoint_with_affinity() {
+        use hyper::header::HeaderValue;
+        use router_api::v1alpha1::vpc_route::AffinitySource;
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = (1..=4)
+            .map(|i| Endpoint { ip: format!("10.0.0.{}", i), port: 8080, ready: true, priority: 0 })
+            .collect();
+        registry
+            .register_service("default".to_string(), "carts".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+
+        let router = Router::new(registry);
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let affinity = AffinityKeyExtractor::new(AffinitySource::Header, "x-session-id");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-session-id", HeaderValue::from_static("user-7"));
+
+        let first = router.select_endpoint("default/carts", &lb, Some(&affinity), None, &headers).await.unwrap();
+        for _ in 0..5 {
+            let next = router.select_endpoint("default/carts", &lb, Some(&affinity), None, &headers).await.unwrap();
+            assert_eq!(next.ip, first.ip);
+        }
+
+        assert!(router.select_endpoint("default/missing", &lb, Some(&affinity), None, &headers).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_select_sticky_endpoint() {
+        use hyper::header::COOKIE;
+        use router_api::v1alpha1::vpc_route::{AffinityPolicy, AffinitySource};
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = (1..=4)
+            .map(|i| Endpoint { ip: format!("10.0.0.{}", i), port: 8080, ready: true, priority: 0 })
+            .collect();
+        registry
+            .register_service("default".to_string(), "carts".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+
+        let router = Router::new(registry.clone()).with_sticky_cookie_key(b"secret");
+        let route = VPCRouteSpec {
+            affinity: Some(AffinityPolicy {
+                source: AffinitySource::StickyCookie,
+                name: "carts-session".to_string(),
+                ttl_seconds: Some(600),
+            }),
+            ..Default::default()
+        };
+        let sticky = router.sticky_cookie(&route).unwrap();
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+
+        // The first request gets a cookie naming its endpoint
+        let (first, cookie) = router.select_sticky_endpoint("default/carts", &lb, &sticky, &HeaderMap::new()).await.unwrap();
+        let cookie = cookie.unwrap().to_str().unwrap().split(';').next().unwrap().to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_str(&cookie).unwrap());
+        for _ in 0..5 {
+            let (next, set_cookie) = router.select_sticky_endpoint("default/carts", &lb, &sticky, &headers).await.unwrap();
+            assert_eq!(next.ip, first.ip);
+            assert!(set_cookie.is_none());
+        }
+
+        // Once the endpoint stops being ready, the client is pinned elsewhere
+        registry.set_endpoint_ready("default/carts", &first.ip, 8080, false).await.unwrap();
+        let (moved, set_cookie) = router.select_sticky_endpoint("default/carts", &lb, &sticky, &headers).await.unwrap();
+        assert_ne!(moved.ip, first.ip);
+        assert!(set_cookie.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_select_destination_weighted_split() {
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        for (name, ip) in [("api", "10.0.0.1"), ("api-canary", "10.0.1.1")] {
+            let endpoints = vec![Endpoint { ip: ip.to_string(), port: 8080, ready: true, priority: 0 }];
+            registry
+                .register_service("default".to_string(), name.to_string(), 8080, "HTTP".to_string(), endpoints)
+                .await
+                .unwrap();
+        }
+        let route = VPCRouteSpec {
+            destinations: vec![
+                RouteDestination::service("api").with_weight(90),
+                RouteDestination::service("api-canary").with_weight(10),
+            ],
+            ..Default::default()
+        };
+
+        let router = Router::new(registry.clone());
+        let route_lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let endpoint_lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let headers = HeaderMap::new();
+        let mut canary = 0;
+        for _ in 0..100 {
+            let destination = router.select_destination(&route, "GET", "default", None, &route_lb).await.unwrap();
+            let service_id = format!("default/{}", destination.vpc_service_ref.name);
+            let endpoint = router.select_endpoint(&service_id, &endpoint_lb, None, None, &headers).await.unwrap();
+            if endpoint.ip == "10.0.1.1" {
+                canary += 1;
+            }
+        }
+        assert_eq!(canary, 10);
+
+        // A canary without ready endpoints gets no traffic
+        let down = vec![Endpoint { ip: "10.0.1.1".to_string(), port: 8080, ready: false, priority: 0 }];
+        registry
+            .register_service("default".to_string(), "api-canary".to_string(), 8080, "HTTP".to_string(), down)
+            .await
+            .unwrap();
+        for _ in 0..20 {
+            let destination = router.select_destination(&route, "GET", "default", None, &route_lb).await.unwrap();
+            assert_eq!(destination.vpc_service_ref.name, "api");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_visibility_restricts_source_vpcs() {
+        use router_api::v1alpha1::vpc_service::{VPCReference, Visibility};
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let payments = SourceVpc {
+            attachment: "default/payments-a".to_string(),
+            namespace: "default".to_string(),
+            name: "payments".to_string(),
+            ..Default::default()
+        };
+        let web = SourceVpc { name: "web".to_string(), ..payments.clone() };
+        let internal = Visibility {
+            vpcs: vec![VPCReference { name: "payments".to_string(), namespace: "default".to_string() }],
+            vpc_selector: None,
+        };
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = vec![Endpoint { ip: "10.0.0.1".to_string(), port: 8080, ready: true, priority: 0 }];
+        registry
+            .register_service("default".to_string(), "ledger".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+        registry.set_visibility("default/ledger", Some(internal.clone())).await.unwrap();
+        let router = Router::new(registry);
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+
+        // The route is open to every VPC, the service behind it isn't
+        let route = VPCRouteSpec {
+            name: "ledger".to_string(),
+            destinations: vec![RouteDestination::service("ledger")],
+            ..Default::default()
+        };
+        assert!(router.select_destination(&route, "GET", "default", Some(&payments), &lb).await.is_some());
+        assert!(router.select_destination(&route, "GET", "default", Some(&web), &lb).await.is_none());
+        assert!(router.select_destination(&route, "GET", "default", None, &lb).await.is_none());
+
+        let routes = vec![Route::new("default", "ledger", VPCRouteSpec { visibility: Some(internal), ..route }).unwrap()];
+        let headers = HeaderMap::new();
+        assert!(router.select_route(&routes, Some(&payments), "GET", "/", None, &headers).is_some());
+        assert!(router.select_route(&routes, Some(&web), "GET", "/", None, &headers).is_none());
+
+        // Restricted to one attachment, not just the VPC
+        let spec = VPCRouteSpec { source_vpc_attachment: Some("payments-b".to_string()), ..routes[0].spec.clone() };
+        let routes = vec![Route::new("default", "ledger", spec).unwrap()];
+        assert!(router.select_route(&routes, Some(&payments), "GET", "/", None, &headers).is_none());
+        let payments_b = SourceVpc { attachment: "default/payments-b".to_string(), ..payments.clone() };
+        assert!(router.select_route(&routes, Some(&payments_b), "GET", "/", None, &headers).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_address_translates_overlapping_vpcs() {
+        use router_api::galactic::vpc::{VPCSpec, VPCStatus};
+        use router_api::galactic::VPC;
+        use router_api::v1alpha1::vpc_service::VPCReference;
+
+        let vpc = |name: &str, identifier: &str| {
+            let mut vpc = VPC::new(name, VPCSpec { networks: vec!["10.0.0.0/16".to_string()] });
+            vpc.status = Some(VPCStatus { identifier: Some(identifier.to_string()), ..Default::default() });
+            vpc
+        };
+        let nat = NatTable::from_vpcs(
+            router_galactic::nat::DEFAULT_NAT_PREFIX.parse().unwrap(),
+            &[vpc("payments", "000000000001"), vpc("web", "000000000002")],
+        );
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoint = Endpoint { ip: "10.0.0.1".to_string(), port: 8080, ready: true, priority: 0 };
+        registry
+            .register_service("default".to_string(), "ledger".to_string(), 8080, "HTTP".to_string(), vec![endpoint.clone()])
+            .await
+            .unwrap();
+        let router = Router::new(registry.clone());
+        let web = SourceVpc { namespace: "default".to_string(), name: "web".to_string(), ..Default::default() };
+
+        // Without a known VPC the endpoint is reached directly
+        assert_eq!(router.endpoint_address(&nat, "default/ledger", &endpoint, Some(&web)).await, "10.0.0.1");
+
+        let payments = VPCReference { name: "payments".to_string(), namespace: "default".to_string() };
+        registry.set_vpc("default/ledger", Some(payments)).await.unwrap();
+        assert_eq!(router.endpoint_address(&nat, "default/ledger", &endpoint, Some(&web)).await, "fd7a:7472::1:a00:1");
+        let same = SourceVpc { name: "payments".to_string(), ..web };
+        assert_eq!(router.endpoint_address(&nat, "default/ledger", &endpoint, Some(&same)).await, "10.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn test_service_scheme_follows_protocol() {
+        let registry = Arc::new(ServiceRegistry::new());
+        for (name, protocol) in [("web", "HTTP"), ("secure", "HTTPS"), ("orders", "gRPC")] {
+            registry
+                .register_service("default".to_string(), name.to_string(), 8080, protocol.to_string(), vec![])
+                .await
+                .unwrap();
+        }
+        let router = Router::new(registry);
+
+        assert_eq!(router.service_scheme("default/web").await, "http");
+        assert_eq!(router.service_scheme("default/secure").await, "https");
+        assert_eq!(router.service_scheme("default/orders").await, "http");
+        assert_eq!(router.service_scheme("default/missing").await, "http");
+    }
+
+    #[tokio::test]
+    async fn test_select_endpoint_drains_maintenance() {
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = vec![Endpoint { ip: "10.0.0.1".to_string(), port: 8080, ready: true, priority: 0 }];
+        registry
+            .register_service("default".to_string(), "carts".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+
+        let router = Router::new(registry.clone());
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let headers = HeaderMap::new();
+        assert!(router.select_endpoint("default/carts", &lb, None, None, &headers).await.is_some());
+
+        registry.set_maintenance("default/carts", Some("Schema migration".to_string())).await.unwrap();
+        assert!(router.select_endpoint("default/carts", &lb, None, None, &headers).await.is_none());
+
+        registry.set_maintenance("default/carts", None).await.unwrap();
+        assert!(router.select_endpoint("default/carts", &lb, None, None, &headers).await.is_some());
+    }
+
+    #[test]
+    fn test_route_load_balancers_follow_policy() {
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+
+        let router = Router::new(Arc::new(ServiceRegistry::new()));
+        let least = router.route_load_balancer("default/api", &LoadBalancingPolicy::LeastConnections);
+        assert_eq!(least.strategy(), &LoadBalancingStrategy::LeastConnections);
+
+        // Routes keep their balancer, and its state, until the policy changes
+        let endpoints = vec![
+            Endpoint { ip: "10.0.0.1".to_string(), port: 8080, ready: true, priority: 0 },
+            Endpoint { ip: "10.0.0.2".to_string(), port: 8080, ready: true, priority: 0 },
+        ];
+        let _busy = least.begin(&endpoints[0]);
+        let again = router.route_load_balancer("default/api", &LoadBalancingPolicy::LeastConnections);
+        assert!(Arc::ptr_eq(&least, &again));
+        assert_eq!(again.select(&endpoints).unwrap().ip, "10.0.0.2");
+        let other = router.route_load_balancer("default/web", &LoadBalancingPolicy::LeastConnections);
+        assert!(!Arc::ptr_eq(&least, &other));
+
+        let policies = BTreeMap::from([("default/api".to_string(), LoadBalancingPolicy::ConsistentHash)]);
+        router.sync_route_balancers(&policies);
+        let hashed = router.route_load_balancer("default/api", &LoadBalancingPolicy::ConsistentHash);
+        assert_eq!(hashed.strategy(), &LoadBalancingStrategy::ConsistentHash);
+        assert!(!router.route_balancers.read().unwrap().contains_key("default/web"));
+    }
+
+    #[test]
+    fn test_route_concurrency_limiters_follow_policy() {
+        use router_api::v1alpha1::vpc_route::ConcurrencyPolicy;
+
+        let router = Router::new(Arc::new(ServiceRegistry::new()));
+        let policy = ConcurrencyPolicy { max_concurrent_requests: 2, queue_length: 1, queue_timeout_ms: 100 };
+        let mut api = Route::new("default", "api", VPCRouteSpec { concurrency: Some(policy), ..Default::default() }).unwrap();
+        let web = Route::new("default", "web", VPCRouteSpec::default()).unwrap();
+        assert!(router.route_concurrency_limiter(&web).is_none());
+
+        router.sync_routes(vec![api.clone(), web.clone()]);
+        let limiter = router.route_concurrency_limiter(&api).unwrap();
+        assert_eq!(limiter.config().max_concurrent_requests, 2);
+        assert!(Arc::ptr_eq(&limiter, &router.route_concurrency_limiter(&api).unwrap()));
+
+        api.spec.concurrency.as_mut().unwrap().max_concurrent_requests = 5;
+        router.sync_routes(vec![api.clone()]);
+        assert_eq!(router.route_concurrency_limiter(&api).unwrap().config().max_concurrent_requests, 5);
+
+        router.sync_routes(vec![web]);
+        assert!(router.route_limiters.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_route_bandwidth_limiters_follow_policy() {
+        use router_api::v1alpha1::vpc_route::BandwidthPolicy;
+
+        let router = Router::new(Arc::new(ServiceRegistry::new()));
+        let policy = BandwidthPolicy { bytes_per_second: Some(1000), ..Default::default() };
+        let spec = VPCRouteSpec { bandwidth: Some(policy), ..Default::default() };
+        let mut downloads = Route::new("default", "downloads", spec).unwrap();
+        let mut web = Route::new("default", "web", VPCRouteSpec::default()).unwrap();
+        assert!(router.route_bandwidth_limiter(&web).is_none());
+        web.spec.bandwidth = Some(BandwidthPolicy::default());
+        assert!(router.route_bandwidth_limiter(&web).is_none());
+
+        router.sync_routes(vec![downloads.clone(), web.clone()]);
+        let limiter = router.route_bandwidth_limiter(&downloads).unwrap();
+        assert!(Arc::ptr_eq(&limiter, &router.route_bandwidth_limiter(&downloads).unwrap()));
+
+        downloads.spec.bandwidth.as_mut().unwrap().bytes_per_second = Some(2000);
+        let changed = router.route_bandwidth_limiter(&downloads).unwrap();
+        assert_eq!(changed.config().bytes_per_second, Some(2000));
+
+        router.sync_routes(vec![web]);
+        assert!(router.route_bandwidth.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_select_endpoint_keeps_pinned_session() {
+        use hyper::header::HeaderValue;
+        use router_api::v1alpha1::vpc_route::AffinitySource;
+        use router_proxy::load_balancer::LoadBalancingStrategy;
+        use router_proxy::MemoryStateStore;
+        use std::time::Duration;
+
+        let registry = Arc::new(ServiceRegistry::new());
+        let endpoints = (1..=4)
+            .map(|i| Endpoint { ip: format!("10.0.0.{}", i), port: 8080, ready: true, priority: 0 })
+            .collect();
+        registry
+            .register_service("default".to_string(), "carts".to_string(), 8080, "HTTP".to_string(), endpoints)
+            .await
+            .unwrap();
+
+        let router = Router::new(registry);
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let affinity = AffinityKeyExtractor::new(AffinitySource::Header, "x-session-id");
+        let pins = SessionPins::new(Arc::new(MemoryStateStore::new()), Duration::from_secs(60));
+
+        // Another replica already pinned this session
+        pins.pin("user-7", "10.0.0.3:8080").await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-session-id", HeaderValue::from_static("user-7"));
+        let endpoint = router
+            .select_endpoint("default/carts", &lb, Some(&affinity), Some(&pins), &headers)
+            .await
+            .unwrap();
+        assert_eq!(endpoint.ip, "10.0.0.3");
+
+        // A pin to a vanished endpoint is replaced
+        pins.pin("user-8", "10.0.0.9:8080").await.unwrap();
+        headers.insert("x-session-id", HeaderValue::from_static("user-8"));
+        let endpoint = router
+            .select_endpoint("default/carts", &lb, Some(&affinity), Some(&pins), &headers)
+            .await
+            .unwrap();
+        assert_eq!(pins.get("user-8").await.unwrap(), Some(format!("{}:8080", endpoint.ip)));
+    }
+
+    #[test]
+    fn test_content_type_match() {
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let grpc = vec!["application/grpc".to_string()];
+        assert!(router.match_content_type(Some("application/grpc"), &grpc));
+        assert!(router.match_content_type(Some("application/grpc+proto"), &grpc));
+        assert!(!router.match_content_type(Some("application/grpc-web"), &grpc));
+        assert!(!router.match_content_type(Some("application/json"), &grpc));
+        assert!(!router.match_content_type(None, &grpc));
+
+        let json = vec!["Application/JSON".to_string()];
+        assert!(router.match_content_type(Some("application/json; charset=utf-8"), &json));
+
+        let any_text = vec!["text/*".to_string()];
+        assert!(router.match_content_type(Some("text/plain"), &any_text));
+        assert!(!router.match_content_type(Some("application/xml"), &any_text));
+
+        assert!(router.match_content_type(None, &[]));
+    }
+
+    #[test]
+    fn test_request_match_splits_by_content_type() {
+        use hyper::header::HeaderValue;
+
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let grpc_route = matching(RouteMatch {
+            path_prefix: Some("/orders".to_string()),
+            content_types: vec!["application/grpc".to_string()],
+            ..Default::default()
+        });
+        let json_route = matching(RouteMatch {
+            path_prefix: Some("/orders".to_string()),
+            methods: vec!["POST".to_string()],
+            content_types: vec!["application/json".to_string()],
+            ..Default::default()
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc+proto"));
+        assert!(router.match_request(&grpc_route, "POST", "/orders/Create", None, &headers));
+        assert!(!router.match_request(&json_route, "POST", "/orders/Create", None, &headers));
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        assert!(!router.match_request(&grpc_route, "POST", "/orders", None, &headers));
+        assert!(router.match_request(&json_route, "POST", "/orders", None, &headers));
+        assert!(!router.match_request(&json_route, "GET", "/orders", None, &headers));
+    }
+
+    #[test]
+    fn test_query_param_match() {
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let required = BTreeMap::from([("version".to_string(), "2".to_string())]);
+        assert!(router.match_query_params(Some("version=2"), &required));
+        assert!(router.match_query_params(Some("page=3&version=1&version=2"), &required));
+        assert!(!router.match_query_params(Some("version=20"), &required));
+        assert!(!router.match_query_params(Some("versions=2"), &required));
+        assert!(!router.match_query_params(None, &required));
+        assert!(router.match_query_params(None, &BTreeMap::new()));
+
+        let route = matching(RouteMatch {
+            path_prefix: Some("/api".to_string()),
+            query_params: required,
+            ..Default::default()
+        });
+        let headers = HeaderMap::new();
+        assert!(router.match_request(&route, "GET", "/api/items", Some("version=2"), &headers));
+        assert!(!router.match_request(&route, "GET", "/api/items", None, &headers));
+    }
+
+    #[test]
+    fn test_read_write_split_destinations() {
+        use router_api::v1alpha1::vpc_route::{ReadWriteSplit, RouteDestination, ServiceRef, VPCRouteSpec};
+
+        let destination = |name: &str| RouteDestination {
+            vpc_service_ref: ServiceRef { name: name.to_string(), namespace: None },
+            direct_response: None,
+            weight: 100,
+            port: None,
+        };
+        let spec = VPCRouteSpec {
+            destinations: vec![destination("db")],
+            read_write_split: Some(ReadWriteSplit {
+                read_methods: vec!["GET".to_string(), "HEAD".to_string()],
+                read: vec![destination("db-replica")],
+                write: vec![destination("db-primary")],
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(spec.destinations_for_method("get")[0].vpc_service_ref.name, "db-replica");
+        assert_eq!(spec.destinations_for_method("HEAD")[0].vpc_service_ref.name, "db-replica");
+        assert_eq!(spec.destinations_for_method("POST")[0].vpc_service_ref.name, "db-primary");
+        assert_eq!(spec.destinations_for_method("DELETE")[0].vpc_service_ref.name, "db-primary");
+    }
+
+    #[test]
+    fn test_user_agent_match() {
+        use hyper::header::HeaderValue;
+
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let pattern = |p: &str| Regex::new(p).unwrap();
+        let mut headers = HeaderMap::new();
+        assert!(!router.match_user_agent(&headers, &pattern("MyApp/")));
+
+        headers.insert(USER_AGENT, HeaderValue::from_static("MyApp/1.4.2 (iOS 17.1)"));
+        assert!(router.match_user_agent(&headers, &pattern(r"^MyApp/1\.")));
+        assert!(!router.match_user_agent(&headers, &pattern("Android")));
+    }
+
+    #[test]
+    fn test_invalid_client_conditions_reject_the_route() {
+        use router_api::v1alpha1::vpc_route::ClientVersionMatch;
+
+        let user_agent = RouteMatch { user_agent: Some("(unclosed".to_string()), ..Default::default() };
+        let spec = VPCRouteSpec { r#match: user_agent, ..Default::default() };
+        assert!(Route::new("default", "mobile", spec).is_err());
+
+        let version = ClientVersionMatch { header: "x-app-version".to_string(), range: "newer".to_string() };
+        let client_version = RouteMatch { client_version: Some(version), ..Default::default() };
+        let spec = VPCRouteSpec { r#match: client_version, ..Default::default() };
+        assert!(Route::new("default", "mobile", spec).is_err());
+    }
+
+    #[test]
+    fn test_client_version_match() {
+        use hyper::header::HeaderValue;
+
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let legacy = VersionReq::parse("<2.0.0").unwrap();
+        let current = VersionReq::parse(">=2.0.0").unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert!(!router.match_client_version(&headers, "x-app-version", &legacy));
+        assert!(!router.match_client_version(&headers, "x-app-version", &current));
+
+        headers.insert("x-app-version", HeaderValue::from_static("1.9"));
+        assert!(router.match_client_version(&headers, "x-app-version", &legacy));
+        assert!(!router.match_client_version(&headers, "x-app-version", &current));
+
+        headers.insert("x-app-version", HeaderValue::from_static("v2.1.0"));
+        assert!(!router.match_client_version(&headers, "x-app-version", &legacy));
+        assert!(router.match_client_version(&headers, "x-app-version", &current));
+
+        headers.insert("x-app-version", HeaderValue::from_static("latest"));
+        assert!(!router.match_client_version(&headers, "x-app-version", &current));
+    }
+
+    #[test]
+    fn test_parse_client_version() {
+        assert_eq!(parse_client_version("2"), Some(Version::new(2, 0, 0)));
+        assert_eq!(parse_client_version("2.3"), Some(Version::new(2, 3, 0)));
+        assert_eq!(parse_client_version("V2.3.4"), Some(Version::new(2, 3, 4)));
+        assert_eq!(parse_client_version("2.3.4-beta.1").unwrap().pre.as_str(), "beta.1");
+        assert_eq!(parse_client_version("2.3.4.5"), None);
+        assert_eq!(parse_client_version(""), None);
+    }
+
+    fn dark_launch_route(name: &str, secrets_dir: &std::path::Path) -> (Router, VPCRouteSpec) {
+        std::fs::create_dir_all(secrets_dir.join("dark-launch")).unwrap();
+        std::fs::write(secrets_dir.join("dark-launch").join("token"), "s3cr3t\n").unwrap();
+
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new())).with_secrets_dir(secrets_dir);
+        let route = VPCRouteSpec {
+            name: name.to_string(),
+            r#match: RouteMatch {
+                path_prefix: Some("/api".to_string()),
+                dark_launch: Some(DarkLaunchMatch {
+                    header: "x-dark-launch".to_string(),
+                    secret_ref: SecretKeyRef {
+                        name: "dark-launch".to_string(),
+                        key: "token".to_string(),
+                    },
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        (router, route)
+    }
+
+    #[test]
+    fn test_dark_launch_falls_through_without_token() {
+        use hyper::header::HeaderValue;
+
+        let dir = std::env::temp_dir().join(format!("router-secrets-{}", std::process::id()));
+        let (router, dark) = dark_launch_route("api-next", &dir);
+        let default = VPCRouteSpec {
+            name: "api".to_string(),
+            r#match: RouteMatch {
+                path_prefix: Some("/api".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Listed after the default route, but still preferred when unlocked
+        let routes = vec![
+            Route::new("default", "api", default).unwrap(),
+            Route::new("default", "api-next", dark).unwrap(),
+        ];
+        router.sync_routes(routes.clone());
+
+        let mut headers = HeaderMap::new();
+        let route = router.select_route(&routes, None, "GET", "/api/users", None, &headers).unwrap();
+        assert_eq!(route.spec.name, "api");
+
+        headers.insert("x-dark-launch", HeaderValue::from_static("wrong"));
+        let route = router.select_route(&routes, None, "GET", "/api/users", None, &headers).unwrap();
+        assert_eq!(route.spec.name, "api");
+
+        headers.insert("x-dark-launch", HeaderValue::from_static("s3cr3t"));
+        let route = router.select_route(&routes, None, "GET", "/api/users", None, &headers).unwrap();
+        assert_eq!(route.spec.name, "api-next");
+
+        // A rotated token takes effect on the next refresh, not before
+        std::fs::write(dir.join("dark-launch").join("token"), "r0tat3d\n").unwrap();
+        let route = router.select_route(&routes, None, "GET", "/api/users", None, &headers).unwrap();
+        assert_eq!(route.spec.name, "api-next");
+        router.refresh_secrets();
+        let route = router.select_route(&routes, None, "GET", "/api/users", None, &headers).unwrap();
+        assert_eq!(route.spec.name, "api");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dark_launch_without_secret_never_matches() {
+        use hyper::header::HeaderValue;
+
+        let dir = std::env::temp_dir().join(format!("router-secrets-missing-{}", std::process::id()));
+        let (_, mut route) = dark_launch_route("api-next", &dir);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-dark-launch", HeaderValue::from_static("s3cr3t"));
+
+        // No secrets directory configured
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
+        let dark = matching(route.r#match.clone());
+        router.sync_routes(vec![dark.clone()]);
+        assert!(!router.match_request(&dark, "GET", "/api", None, &headers));
+
+        // Secret references can't escape the secrets directory
+        let router = Router::new(Arc::new(router_core::ServiceRegistry::new())).with_secrets_dir(&dir);
+        if let Some(dark_launch) = route.r#match.dark_launch.as_mut() {
+            dark_launch.secret_ref.name = "..".to_string();
+        }
+        let dark = matching(route.r#match.clone());
+        router.sync_routes(vec![dark.clone()]);
+        assert!(!router.match_request(&dark, "GET", "/api", None, &headers));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A route matching requests by `route_match` alone
+    fn matching(route_match: RouteMatch) -> Route {
+        Route::new("default", "test", VPCRouteSpec { r#match: route_match, ..Default::default() }).unwrap()
+    }
+
     #[test]
     fn test_method_match_empty() {
         let router = Router::new(Arc::new(router_core::ServiceRegistry::new()));
@@ -0,0 +1,248 @@
+//! Versioned snapshots of the routing resources the gateway has applied
+//!
+//! Running in Kubernetes, the gateway watches VPCRoutes and VPCIngresses
+//! into a route table. Every change to a resource's spec publishes a new
+//! snapshot with the next generation; status-only updates, which don't
+//! change the resource's `metadata.generation`, leave the table alone. The
+//! table generation and the spec generation of every resource in it are
+//! reported in the replica's RouterGateway, which is how the controller
+//! knows a change is live.
+//!
+//! A second watch hands the VPCRoutes to the router, which matches requests
+//! against them and keeps a load balancer per route in line with each
+//! route's `loadBalancing` policy.
+
+use futures::{stream, StreamExt};
+use kube::runtime::watcher::Event;
+use kube::{Api, Client, Resource, ResourceExt};
+use router_api::v1alpha1::router_gateway::AppliedRoute;
+use router_api::{VPCIngress, VPCRoute};
+use router_core::watch::{self, LoggingObserver, WatchConfig, WatchObserver};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::watch as channel;
+use tracing::{debug, info, warn};
+
+use crate::router::{Route, Router};
+use crate::sources;
+
+/// A routing resource, by kind, namespace, and name
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RouteKey {
+    pub kind: &'static str,
+    pub namespace: String,
+    pub name: String,
+}
+
+/// One snapshot of the route table
+#[derive(Clone, Debug, Default)]
+pub struct RouteTable {
+    /// Increases by one with every applied change
+    pub generation: u64,
+    /// Spec generation of each resource in the table
+    pub routes: BTreeMap<RouteKey, i64>,
+}
+
+/// The current route table, shared with whatever reports it
+pub struct RouteTables {
+    current: channel::Sender<Arc<RouteTable>>,
+}
+
+/// Builds route table snapshots from watch events
+#[derive(Default)]
+struct Compiler {
+    table: RouteTable,
+    /// Resources seen so far by a re-list in progress, by kind
+    listing: BTreeMap<&'static str, BTreeMap<RouteKey, i64>>,
+}
+
+impl RouteTable {
+    /// The table as reported in RouterGateway status
+    pub fn applied(&self) -> Vec<AppliedRoute> {
+        self.routes
+            .iter()
+            .map(|(key, generation)| AppliedRoute {
+                kind: key.kind.to_string(),
+                namespace: key.namespace.clone(),
+                name: key.name.clone(),
+                generation: *generation,
+            })
+            .collect()
+    }
+}
+
+impl RouteTables {
+    pub fn new() -> Self {
+        Self { current: channel::Sender::new(Arc::new(RouteTable::default())) }
+    }
+
+    /// The latest snapshot
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.current.borrow().clone()
+    }
+
+    /// Receiver notified whenever a snapshot is published
+    pub fn subscribe(&self) -> channel::Receiver<Arc<RouteTable>> {
+        self.current.subscribe()
+    }
+
+    fn publish(&self, table: RouteTable) {
+        self.current.send_replace(Arc::new(table));
+    }
+}
+
+impl Compiler {
+    /// Apply one watch event for `kind`, returning whether the table changed
+    ///
+    /// A re-list replaces all of the kind's resources once it completes,
+    /// so resources deleted while the watch was down are dropped too.
+    fn apply(&mut self, kind: &'static str, event: Event<(RouteKey, i64)>) -> bool {
+        let changed = match event {
+            Event::Init => {
+                self.listing.insert(kind, BTreeMap::new());
+                false
+            }
+            Event::InitApply((key, generation)) => {
+                self.listing.entry(kind).or_default().insert(key, generation);
+                false
+            }
+            Event::InitDone => {
+                let listed = self.listing.remove(kind).unwrap_or_default();
+                let current = self.table.routes.iter().filter(|(key, _)| key.kind == kind);
+                let changed = !current.eq(listed.iter());
+                if changed {
+                    self.table.routes.retain(|key, _| key.kind != kind);
+                    self.table.routes.extend(listed);
+                }
+                changed
+            }
+            Event::Apply((key, generation)) => self.table.routes.insert(key, generation) != Some(generation),
+            Event::Delete((key, _)) => self.table.routes.remove(&key).is_some(),
+        };
+        if changed {
+            self.table.generation += 1;
+        }
+        changed
+    }
+}
+
+/// Watch VPCRoutes and VPCIngresses, publishing a snapshot on every change
+pub async fn watch(client: Client, tables: Arc<RouteTables>) {
+    let config = WatchConfig::default();
+    let observer: Arc<dyn WatchObserver> = Arc::new(LoggingObserver);
+    let vpc_routes = entries(Api::<VPCRoute>::all(client.clone()), "VPCRoute", &config, observer.clone());
+    let vpc_ingresses = entries(Api::<VPCIngress>::all(client), "VPCIngress", &config, observer);
+    let mut events = stream::select(vpc_routes, vpc_ingresses).boxed();
+
+    let mut compiler = Compiler::default();
+    while let Some((kind, event)) = events.next().await {
+        if compiler.apply(kind, event) {
+            info!("Applied route table generation {} ({} resources)", compiler.table.generation, compiler.table.routes.len());
+            tables.publish(compiler.table.clone());
+        } else {
+            debug!("{} change left route table generation {} unchanged", kind, compiler.table.generation);
+        }
+    }
+}
+
+/// Watch VPCRoutes, handing them to the router on every change
+pub async fn watch_router(client: Client, router: Arc<Router>) {
+    let observer: Arc<dyn WatchObserver> = Arc::new(LoggingObserver);
+    let (routes, changes) = sources::changes(Api::<VPCRoute>::all(client), &WatchConfig::default(), observer);
+    let mut changes = changes.boxed();
+
+    while changes.next().await.is_some() {
+        // Routes whose conditions don't compile are left out, not matched
+        let routes: Vec<Route> = routes
+            .state()
+            .iter()
+            .filter_map(|route| {
+                Route::new(&route.namespace().unwrap_or_default(), &route.name_any(), route.spec.clone())
+                    .inspect_err(|e| warn!("Ignoring VPCRoute: {:#}", e))
+                    .ok()
+            })
+            .collect();
+        debug!("Routing by {} routes", routes.len());
+        router.sync_routes(routes);
+    }
+}
+
+/// Watch events for a kind, reduced to each resource's key and spec generation
+fn entries<K>(
+    api: Api<K>,
+    kind: &'static str,
+    config: &WatchConfig,
+    observer: Arc<dyn WatchObserver>,
+) -> impl futures::Stream<Item = (&'static str, Event<(RouteKey, i64)>)> + Send
+where
+    K: Resource<DynamicType = ()> + Clone + std::fmt::Debug + serde::de::DeserializeOwned + Send + Sync + 'static,
+{
+    // Errors are logged by the watch, which retries on its own
+    watch::watch(api, config, observer).filter_map(move |event| async move {
+        let entry = |resource: K| {
+            let key = RouteKey {
+                kind,
+                namespace: resource.namespace().unwrap_or_default(),
+                name: resource.name_any(),
+            };
+            (key, resource.meta().generation.unwrap_or(0))
+        };
+        let event = match event.ok()? {
+            Event::Init => Event::Init,
+            Event::InitApply(resource) => Event::InitApply(entry(resource)),
+            Event::InitDone => Event::InitDone,
+            Event::Apply(resource) => Event::Apply(entry(resource)),
+            Event::Delete(resource) => Event::Delete(entry(resource)),
+        };
+        Some((kind, event))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(name: &str, generation: i64) -> (RouteKey, i64) {
+        let key = RouteKey { kind: "VPCRoute", namespace: "default".to_string(), name: name.to_string() };
+        (key, generation)
+    }
+
+    #[test]
+    fn test_generation_follows_spec_changes() {
+        let mut compiler = Compiler::default();
+        assert!(compiler.apply("VPCRoute", Event::Apply(route("api", 1))));
+        assert_eq!(compiler.table.generation, 1);
+
+        // Status updates keep the spec generation
+        assert!(!compiler.apply("VPCRoute", Event::Apply(route("api", 1))));
+        assert!(compiler.apply("VPCRoute", Event::Apply(route("api", 2))));
+        assert!(compiler.apply("VPCRoute", Event::Delete(route("api", 2))));
+        assert!(!compiler.apply("VPCRoute", Event::Delete(route("api", 2))));
+        assert_eq!(compiler.table.generation, 3);
+        assert!(compiler.table.routes.is_empty());
+    }
+
+    #[test]
+    fn test_relist_replaces_kind() {
+        let mut compiler = Compiler::default();
+        compiler.apply("VPCRoute", Event::Apply(route("api", 1)));
+        compiler.apply("VPCRoute", Event::Apply(route("web", 3)));
+        let ingress = RouteKey { kind: "VPCIngress", namespace: "default".to_string(), name: "public".to_string() };
+        compiler.apply("VPCIngress", Event::Apply((ingress.clone(), 1)));
+
+        // An unchanged re-list is not a new generation
+        compiler.apply("VPCRoute", Event::Init);
+        compiler.apply("VPCRoute", Event::InitApply(route("api", 1)));
+        compiler.apply("VPCRoute", Event::InitApply(route("web", 3)));
+        assert!(!compiler.apply("VPCRoute", Event::InitDone));
+        assert_eq!(compiler.table.generation, 3);
+
+        // "web" was deleted while the watch was down
+        compiler.apply("VPCRoute", Event::Init);
+        compiler.apply("VPCRoute", Event::InitApply(route("api", 2)));
+        assert!(compiler.apply("VPCRoute", Event::InitDone));
+        assert_eq!(compiler.table.generation, 4);
+        let routes: Vec<_> = compiler.table.routes.into_iter().collect();
+        assert_eq!(routes, vec![(ingress, 1), route("api", 2)]);
+    }
+}
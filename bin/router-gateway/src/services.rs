@@ -0,0 +1,148 @@
+//! VPCServices the gateway routes to
+//!
+//! Running in Kubernetes, the gateway watches VPCServices and the
+//! VPCAttachments they run on, and keeps the registry's services in line:
+//! the endpoints in each service's status, its visibility, and the VPC of
+//! its attachment, which decides whether its endpoints need address
+//! translation. Endpoints the registry already knows keep the readiness
+//! the gateway's health checks gave them. Deleted services are dropped;
+//! services registered some other way, like TCP proxies, are left alone.
+//!
+//! Drains and health check settings of the same services are applied
+//! right after each change, see [`crate::drains`].
+
+use futures::{stream, StreamExt};
+use kube::{Api, Client, ResourceExt};
+use router_api::galactic::VPCAttachment;
+use router_api::v1alpha1::vpc_service::VPCReference;
+use router_api::VPCService;
+use router_core::watch::{LoggingObserver, WatchConfig, WatchObserver};
+use router_core::{Endpoint, ServiceRegistry};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use crate::drains::Drains;
+use crate::sources;
+
+/// Watch VPCServices and VPCAttachments, registering the services on every
+/// change and applying their drains
+pub async fn watch(client: Client, registry: Arc<ServiceRegistry>, drains: Arc<Drains>) {
+    let config = WatchConfig::default();
+    let observer: Arc<dyn WatchObserver> = Arc::new(LoggingObserver);
+    let (services, service_changes) = sources::changes(Api::<VPCService>::all(client.clone()), &config, observer.clone());
+    let (attachments, attachment_changes) = sources::changes(Api::<VPCAttachment>::all(client), &config, observer);
+    let mut changes = stream::select(service_changes, attachment_changes).boxed();
+
+    let mut registered = HashSet::new();
+    while changes.next().await.is_some() {
+        let services = services.state();
+        registered = sync(&registry, &services, &attachments.state(), &registered).await;
+        drains.publish(&services).await;
+    }
+}
+
+/// Register `services`, and deregister those of `registered` no longer
+/// listed, returning the ids now registered
+async fn sync(
+    registry: &ServiceRegistry,
+    services: &[Arc<VPCService>],
+    attachments: &[Arc<VPCAttachment>],
+    registered: &HashSet<String>,
+) -> HashSet<String> {
+    let mut current = HashSet::new();
+    for service in services {
+        let namespace = service.namespace().unwrap_or_else(|| "default".to_string());
+        let service_id = format!("{}/{}", namespace, service.name_any());
+        let endpoints: Vec<Endpoint> = service
+            .status
+            .iter()
+            .flat_map(|status| &status.endpoints)
+            .map(|e| Endpoint { ip: e.ip.clone(), port: e.port, ready: e.ready, priority: e.priority })
+            .collect();
+
+        let registration = registry
+            .sync_service(namespace, service.name_any(), service.spec.port, service.spec.protocol.clone(), endpoints)
+            .await;
+        if let Err(e) = registration {
+            debug!("Failed to register {}: {}", service_id, e);
+            continue;
+        }
+        if let Err(e) = registry.set_visibility(&service_id, service.spec.visibility.clone()).await {
+            debug!("Failed to apply visibility of {}: {}", service_id, e);
+        }
+        if let Err(e) = registry.set_vpc(&service_id, attachment_vpc(service, attachments)).await {
+            debug!("Failed to apply VPC of {}: {}", service_id, e);
+        }
+        current.insert(service_id);
+    }
+
+    for service_id in registered.difference(&current) {
+        if registry.deregister_service(service_id).await.is_ok() {
+            info!("Stopped routing to deleted VPCService {}", service_id);
+        }
+    }
+    current
+}
+
+/// The VPC of a service's VPCAttachment, if the attachment is known
+fn attachment_vpc(service: &VPCService, attachments: &[Arc<VPCAttachment>]) -> Option<VPCReference> {
+    // VPCAttachments are cluster-scoped
+    let attachment_ref = &service.spec.vpc_attachment_ref;
+    let attachment = attachments.iter().find(|attachment| attachment.name_any() == attachment_ref.name)?;
+    Some(VPCReference {
+        name: attachment.spec.vpc.name.clone(),
+        namespace: attachment.spec.vpc.namespace.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::galactic::vpc_attachment::{VPCAttachmentSpec, VPCRef};
+    use router_api::v1alpha1::vpc_service::{EndpointStatus, VPCServiceSpec, VPCServiceStatus};
+
+    fn service(name: &str, ips: &[&str]) -> Arc<VPCService> {
+        let mut service = VPCService::new(name, VPCServiceSpec { port: 8080, ..Default::default() });
+        service.metadata.namespace = Some("default".to_string());
+        service.spec.vpc_attachment_ref.name = "payments-a".to_string();
+        service.status = Some(VPCServiceStatus {
+            endpoints: ips
+                .iter()
+                .map(|ip| EndpointStatus { ip: ip.to_string(), port: 8080, ready: true, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        });
+        Arc::new(service)
+    }
+
+    #[tokio::test]
+    async fn test_sync_registers_services() {
+        let registry = ServiceRegistry::new();
+        registry
+            .register_service("default".into(), "postgres".into(), 5432, "TCP".into(), Vec::new())
+            .await
+            .unwrap();
+        let attachment = VPCAttachment::new("payments-a", VPCAttachmentSpec {
+            vpc: VPCRef { name: "payments".to_string(), namespace: "default".to_string(), ..Default::default() },
+            ..Default::default()
+        });
+        let attachments = vec![Arc::new(attachment)];
+
+        let registered = sync(&registry, &[service("api", &["10.0.0.1"])], &attachments, &HashSet::new()).await;
+        let api = registry.get_service("default/api").await.unwrap();
+        assert_eq!(api.endpoints[0].ip, "10.0.0.1");
+        assert_eq!(api.vpc.unwrap().name, "payments");
+
+        // Health check results survive status updates
+        registry.set_endpoint_ready("default/api", "10.0.0.1", 8080, false).await.unwrap();
+        let registered = sync(&registry, &[service("api", &["10.0.0.1", "10.0.0.2"])], &attachments, &registered).await;
+        let ready: Vec<bool> = registry.get_endpoints("default/api").await.unwrap().iter().map(|e| e.ready).collect();
+        assert_eq!(ready, vec![false, true]);
+
+        // Deleted VPCServices go, services registered otherwise stay
+        sync(&registry, &[], &attachments, &registered).await;
+        assert!(registry.get_service("default/api").await.is_err());
+        assert!(registry.get_service("default/postgres").await.is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+//! Graceful shutdown with connection draining
+//!
+//! On SIGTERM or Ctrl-C the gateway reports not-ready, its listeners stop
+//! accepting connections, and open connections get until a deadline to
+//! finish their in-flight requests before the process exits.
+
+use hyper_util::server::graceful::GracefulShutdown;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Coordinates draining between the gateway's listeners
+pub struct Drain {
+    deadline: Duration,
+    draining: watch::Sender<bool>,
+}
+
+impl Drain {
+    /// Create a drain that waits up to `deadline` for connections to finish
+    pub fn new(deadline: Duration) -> Self {
+        let (draining, _) = watch::channel(false);
+        Self { deadline, draining }
+    }
+
+    /// Whether shutdown has started
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Start shutting down
+    pub fn start(&self) {
+        self.draining.send_replace(true);
+    }
+
+    /// Wait until shutdown starts
+    pub async fn started(&self) {
+        let mut draining = self.draining.subscribe();
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+
+    /// Let a listener's open connections finish, up to the deadline
+    ///
+    /// Idle connections close right away; busy ones close once their
+    /// current requests are answered.
+    pub async fn finish(&self, connections: GracefulShutdown, listener: &str) {
+        info!("Draining {} open {} connections", connections.count(), listener);
+        if tokio::time::timeout(self.deadline, connections.shutdown()).await.is_err() {
+            warn!("{} connections still open after {:?}, closing them", listener, self.deadline);
+        }
+    }
+}
+
+/// Wait for SIGTERM or Ctrl-C
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use hyper::body::{Bytes, Incoming};
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response};
+    use hyper_util::rt::TokioIo;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Serve slow responses until the drain starts, then drain
+    async fn serve(listener: TcpListener, drain: Arc<Drain>) {
+        let connections = GracefulShutdown::new();
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => accepted.unwrap().0,
+                _ = drain.started() => break,
+            };
+            let service = service_fn(|_req: Request<Incoming>| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("done"))))
+            });
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            tokio::spawn(connections.watch(connection));
+        }
+        drain.finish(connections, "test").await;
+    }
+
+    #[tokio::test]
+    async fn test_drain_finishes_in_flight_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let drain = Arc::new(Drain::new(Duration::from_secs(5)));
+        let server = tokio::spawn(serve(listener, drain.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(!drain.is_draining());
+        let started = Instant::now();
+        drain.start();
+        assert!(drain.is_draining());
+
+        // The request in flight is answered, then the connection closes
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("done"));
+
+        server.await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+}
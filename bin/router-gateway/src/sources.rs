@@ -0,0 +1,108 @@
+//! Which VPC each request comes from, and how to reach the others
+//!
+//! Running in Kubernetes, the gateway watches Galactic VPCs and
+//! VPCAttachments and maps every attachment address to its VPC. A
+//! request's peer address is resolved through that map, so requests from
+//! workloads that reach the gateway over their attachment carry their
+//! source VPC; anything else, including traffic through a proxy, has none.
+//! The same watch keeps the NAT table for VPCs with overlapping networks.
+
+use futures::{stream, StreamExt};
+use ipnetwork::Ipv6Network;
+use kube::runtime::reflector::{self, Store};
+use kube::runtime::watcher::Event;
+use kube::runtime::WatchStreamExt;
+use kube::{Api, Client, Resource};
+use router_api::galactic::{VPCAttachment, VPC};
+use router_core::watch::{self, LoggingObserver, WatchConfig, WatchObserver};
+use router_core::{SourceMap, SourceVpc};
+use router_galactic::{NatTable, VPCDiscovery};
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// The current source address map and NAT table
+pub struct Sources {
+    current: RwLock<Arc<SourceMap>>,
+    nat: RwLock<Arc<NatTable>>,
+}
+
+impl Sources {
+    /// Sources translating into `nat_prefix`
+    pub fn new(nat_prefix: Ipv6Network) -> Self {
+        Self {
+            current: RwLock::default(),
+            nat: RwLock::new(Arc::new(NatTable::new(nat_prefix))),
+        }
+    }
+
+    /// The VPC a request from `address` came from
+    pub fn resolve(&self, address: IpAddr) -> Option<SourceVpc> {
+        self.current.read().unwrap().resolve(address).cloned()
+    }
+
+    /// The VPC, as namespace/name, an upstream at `address` is in
+    ///
+    /// Translated addresses are in the VPC they were translated for.
+    pub fn vpc(&self, address: IpAddr) -> Option<String> {
+        if let IpAddr::V6(translated) = address {
+            if let Some((namespace, name, _)) = self.nat().original(translated) {
+                return Some(format!("{}/{}", namespace, name));
+            }
+        }
+        self.resolve(address).map(|vpc| format!("{}/{}", vpc.namespace, vpc.name))
+    }
+
+    /// The current NAT table
+    pub fn nat(&self) -> Arc<NatTable> {
+        self.nat.read().unwrap().clone()
+    }
+
+    pub(crate) fn publish(&self, map: SourceMap, nat: NatTable) {
+        *self.current.write().unwrap() = Arc::new(map);
+        *self.nat.write().unwrap() = Arc::new(nat);
+    }
+}
+
+/// Watch VPCs and VPCAttachments, rebuilding the map and NAT table on
+/// every change
+pub async fn watch(client: Client, sources: Arc<Sources>) {
+    let config = WatchConfig::default();
+    let observer: Arc<dyn WatchObserver> = Arc::new(LoggingObserver);
+    let (vpcs, vpc_changes) = changes(Api::<VPC>::all(client.clone()), &config, observer.clone());
+    let (attachments, attachment_changes) = changes(Api::<VPCAttachment>::all(client), &config, observer);
+    let mut changes = stream::select(vpc_changes, attachment_changes).boxed();
+    let nat_prefix = sources.nat().prefix();
+
+    while changes.next().await.is_some() {
+        let vpcs = vpcs.state();
+        let map = VPCDiscovery::source_map(&vpcs, &attachments.state());
+        info!("Resolving source VPCs for {} attachment addresses", map.len());
+        sources.publish(map, NatTable::from_vpcs(nat_prefix, &vpcs));
+    }
+}
+
+/// Cache of a resource's objects, and a stream that yields whenever the
+/// cache changes
+pub(crate) fn changes<K>(
+    api: Api<K>,
+    config: &WatchConfig,
+    observer: Arc<dyn WatchObserver>,
+) -> (Store<K>, impl futures::Stream<Item = ()> + Send)
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + serde::de::DeserializeOwned + Send + Sync + 'static,
+{
+    let (reader, writer) = reflector::store();
+    // Errors are logged by the watch, which retries on its own; a re-list
+    // only shows in the cache once it completes
+    let changes = watch::watch(api, config, observer)
+        .reflect(writer)
+        .filter_map(|event| async move {
+            match event.ok()? {
+                Event::Apply(_) | Event::Delete(_) | Event::InitDone => Some(()),
+                Event::Init | Event::InitApply(_) => None,
+            }
+        });
+    (reader, changes)
+}
@@ -0,0 +1,29 @@
+//! Named tasks for runtime debugging
+//!
+//! With the `console` feature and `--cfg tokio_unstable`, the gateway
+//! serves tokio-console on its default port (6669, see TOKIO_CONSOLE_BIND)
+//! and its tasks show up under their names. Otherwise tasks are spawned
+//! as usual and the names are dropped.
+
+use std::future::Future;
+use tokio::task::JoinHandle;
+
+/// Spawn a task named `name`
+pub fn spawn<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(tokio_unstable)]
+    {
+        tokio::task::Builder::new()
+            .name(name)
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(tokio_unstable))]
+    {
+        let _ = name;
+        tokio::task::spawn(future)
+    }
+}
@@ -1,22 +1,65 @@
 use anyhow::Result;
+use clap::Parser;
+use futures::StreamExt;
+use kube::Api;
+use kube::runtime::watcher::Event;
+use router_api::galactic::VPCAttachment;
+use router_core::cli::{KubeArgs, LogArgs};
+use router_core::kube_client::LoggingApiObserver;
+use router_core::watch::{self, LoggingObserver, WatchConfig};
 use router_core::ServiceRegistry;
 use router_galactic::VPCDiscovery;
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{info, error, debug};
-use tracing_subscriber::fmt::init as tracing_init;
+
+/// Discovers services on Galactic VPC attachments
+#[derive(Debug, Parser)]
+#[command(name = "service-discovery", version, long_version = router_core::long_version!())]
+struct Cli {
+    /// Seconds between full rediscoveries; attachment changes also trigger one
+    #[arg(long, env = "ROUTER_DISCOVERY_RESYNC_SECS", value_name = "SECS", default_value_t = 30,
+          value_parser = clap::value_parser!(u64).range(1..))]
+    resync_interval_secs: u64,
+    #[command(flatten)]
+    kube: KubeArgs,
+    #[command(flatten)]
+    log: LogArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_init();
+    let cli = Cli::parse();
+    cli.log.init();
 
-    info!("Starting service-discovery daemon...");
+    info!("Starting service-discovery daemon {}...", router_core::build_info!());
 
     let registry = Arc::new(ServiceRegistry::new());
-    let discovery = VPCDiscovery::new().await?;
+    let client = cli
+        .kube
+        .client_config("service-discovery")
+        .build(Arc::new(LoggingApiObserver))
+        .await?;
+    let discovery = VPCDiscovery::new(client);
+
+    // Rediscover whenever attachments change, and periodically as a fallback
+    let attachments: Api<VPCAttachment> = Api::all(discovery.client().clone());
+    let mut changes = watch::watch(attachments, &WatchConfig::default(), Arc::new(LoggingObserver)).boxed();
+    let mut resync = tokio::time::interval(Duration::from_secs(cli.resync_interval_secs));
 
-    // Periodic discovery loop
     loop {
+        tokio::select! {
+            _ = resync.tick() => {}
+            event = changes.next() => match event {
+                // The initial list is covered once it completes
+                Some(Ok(Event::Init | Event::InitApply(_))) => continue,
+                Some(Ok(_)) => debug!("VPCAttachments changed, rediscovering"),
+                // Errors are logged and backed off by the watch
+                Some(Err(_)) => continue,
+                None => return Ok(()),
+            },
+        }
+
         match discover_services(&discovery, &registry).await {
             Ok(count) => {
                 info!("Discovered and registered {} services", count);
@@ -25,9 +68,6 @@ async fn main() -> Result<()> {
                 error!("Error discovering services: {}", e);
             }
         }
-
-        // Wait before next discovery cycle
-        tokio::time::sleep(Duration::from_secs(30)).await;
     }
 }
 
@@ -53,8 +93,8 @@ async fn discover_services(
         let _vpc_key = format!("{}/{}", attachment.spec.vpc.namespace, attachment.spec.vpc.name);
 
         let ipv4_addrs = VPCDiscovery::attachment_ipv4_addresses(&attachment);
-        let name = attachment.metadata.name.as_ref().map(|n| n.as_str()).unwrap_or("unknown");
-        let namespace = attachment.metadata.namespace.as_ref().map(|n| n.as_str()).unwrap_or("default");
+        let name = attachment.metadata.name.as_deref().unwrap_or("unknown");
+        let namespace = attachment.metadata.namespace.as_deref().unwrap_or("default");
         debug!(
             "Attachment {}/{} has {} IPv4 addresses",
             namespace,
@@ -1,12 +1,22 @@
 use anyhow::Result;
-use tracing_subscriber;
+use clap::Parser;
+use router_core::cli::LogArgs;
+
+/// Tunnels traffic between router gateways
+#[derive(Debug, Parser)]
+#[command(name = "tunnel-gateway", version, long_version = router_core::long_version!())]
+struct Cli {
+    #[command(flatten)]
+    log: LogArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    
-    println!("tunnel-gateway starting...");
-    
+    let cli = Cli::parse();
+    cli.log.init();
+
+    println!("tunnel-gateway {} starting...", router_core::build_info!());
+
     // TODO: Implement tunnel gateway
     Ok(())
 }
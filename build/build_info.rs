@@ -0,0 +1,66 @@
+//! Build script shared by the binaries
+//!
+//! Sets the `ROUTER_BUILD_*` variables read by `router_core::build_info!`.
+//! Builds outside a git checkout (container images built from a source
+//! archive) can pass the commit in `ROUTER_GIT_SHA`; `SOURCE_DATE_EPOCH`
+//! pins the build date for reproducible builds.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=ROUTER_GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_sha = env::var("ROUTER_GIT_SHA")
+        .ok()
+        .filter(|sha| !sha.is_empty())
+        .or_else(git_head)
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = command_output(Command::new(rustc).arg("--version")).unwrap_or_else(|| "unknown".to_string());
+
+    let build_date = match env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.parse().ok()) {
+        Some(epoch) => chrono::DateTime::from_timestamp(epoch, 0).unwrap_or_default(),
+        None => chrono::Utc::now(),
+    };
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| Some(name.strip_prefix("CARGO_FEATURE_")?.to_lowercase().replace('_', "-")))
+        .filter(|feature| feature != "default")
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=ROUTER_BUILD_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=ROUTER_BUILD_RUSTC={}", rustc);
+    println!(
+        "cargo:rustc-env=ROUTER_BUILD_DATE={}",
+        build_date.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+    println!("cargo:rustc-env=ROUTER_BUILD_FEATURES={}", features.join(","));
+}
+
+/// The checked out commit, rebuilding when it changes
+fn git_head() -> Option<String> {
+    let git_dir = command_output(Command::new("git").args(["rev-parse", "--absolute-git-dir"]))?;
+    let git_dir = Path::new(&git_dir);
+    println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+    if let Some(reference) = command_output(Command::new("git").args(["symbolic-ref", "-q", "HEAD"])) {
+        println!("cargo:rerun-if-changed={}", git_dir.join(reference).display());
+    }
+    println!("cargo:rerun-if-changed={}", git_dir.join("packed-refs").display());
+
+    command_output(Command::new("git").args(["rev-parse", "--short=12", "HEAD"]))
+}
+
+/// Trimmed stdout of a command that succeeded
+fn command_output(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string())
+}
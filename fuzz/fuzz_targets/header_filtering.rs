@@ -0,0 +1,52 @@
+#![no_main]
+//! Header filtering on arbitrary request headers
+//!
+//! Whatever a client sends, headers a route removes are gone, headers it
+//! sets have exactly one value, and an untrusted client can't spoof the
+//! address or scheme the backend sees.
+//!
+//! Input: `<name>: <value>` lines.
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, FORWARDED, HOST};
+use libfuzzer_sys::fuzz_target;
+use router_proxy::forwarded::{X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO};
+use router_proxy::header_rewrite::HeaderChanges;
+use router_proxy::{ForwardedConfig, ForwardedHeaders};
+use std::net::IpAddr;
+
+fuzz_target!(|data: &[u8]| {
+    let mut headers = HeaderMap::new();
+    for line in data.split(|b| *b == b'\n') {
+        let Some(colon) = line.iter().position(|b| *b == b':') else {
+            continue;
+        };
+        let name = HeaderName::from_bytes(&line[..colon]);
+        let value = HeaderValue::from_bytes(line[colon + 1..].trim_ascii());
+        if let (Ok(name), Ok(value)) = (name, value) {
+            headers.append(name, value);
+        }
+    }
+    let host = headers.get(HOST).and_then(|v| v.to_str().ok()).map(str::to_string);
+
+    let changes = HeaderChanges::default()
+        .remove("x-internal-token")
+        .and_then(|c| c.set("x-env", "prod"))
+        .unwrap();
+    changes.apply(&mut headers);
+    assert!(!headers.contains_key("x-internal-token"));
+    assert_eq!(headers.get_all("x-env").iter().count(), 1);
+
+    let peer = IpAddr::from([203, 0, 113, 9]);
+    let forwarded = ForwardedHeaders::new(ForwardedConfig {
+        trusted_proxies: Vec::new(),
+        forwarded_header: true,
+    });
+    forwarded.apply(&mut headers, peer, "http", host.as_deref());
+
+    let xff: Vec<_> = headers.get_all(X_FORWARDED_FOR).iter().collect();
+    assert!(xff.len() == 1 && xff[0] == "203.0.113.9", "spoofed X-Forwarded-For: {:?}", xff);
+    assert_eq!(headers[X_FORWARDED_PROTO], "http");
+    assert_eq!(headers.get(X_FORWARDED_HOST).and_then(|v| v.to_str().ok()), host.as_deref());
+    let element = headers[FORWARDED].to_str().unwrap();
+    assert!(element.starts_with("for=203.0.113.9;proto=http"), "spoofed Forwarded: {:?}", element);
+});
@@ -0,0 +1,77 @@
+#![no_main]
+//! Route path matching on arbitrary request paths
+//!
+//! A strict route never matches a path outside its pattern, a canonical
+//! redirect lands on a path the route still matches without redirecting
+//! again, and a forwarded path never leaves the backend it was sent to.
+//!
+//! Input: one options byte, then `<pattern>\n<request target>`.
+
+use hyper::Uri;
+use libfuzzer_sys::fuzz_target;
+use router_api::v1alpha1::vpc_route::{RouteMatch, TrailingSlashPolicy};
+use router_core::ServiceRegistry;
+use router_proxy::{PathRewrite, RequestForwarder};
+use std::sync::Arc;
+
+#[allow(dead_code)]
+#[path = "../../bin/router-gateway/src/router.rs"]
+mod router;
+
+use router::Router;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&options, input)) = data.split_first() else {
+        return;
+    };
+    let Ok(input) = std::str::from_utf8(input) else {
+        return;
+    };
+    let Some((pattern, target)) = input.split_once('\n') else {
+        return;
+    };
+    let Ok(uri) = target.parse::<Uri>() else {
+        return;
+    };
+    let path = uri.path();
+    let router = Router::new(Arc::new(ServiceRegistry::new()));
+
+    let exact = options & 1 == 1;
+    let route_match = RouteMatch {
+        exact_path: exact.then(|| pattern.to_string()),
+        path_prefix: (!exact).then(|| pattern.to_string()),
+        trailing_slash: match (options >> 1) % 3 {
+            0 => TrailingSlashPolicy::Strict,
+            1 => TrailingSlashPolicy::Ignore,
+            _ => TrailingSlashPolicy::Redirect,
+        },
+        case_insensitive: options & 8 != 0,
+        ..Default::default()
+    };
+    let matched = router.match_route_path(&route_match, path);
+    if matched && route_match.trailing_slash == TrailingSlashPolicy::Strict && !route_match.case_insensitive {
+        assert!(if exact { path == pattern } else { path.starts_with(pattern) });
+    }
+    if let Some(canonical) = router.canonical_path(&route_match, path) {
+        assert!(router.match_route_path(&route_match, &canonical));
+        assert_eq!(router.canonical_path(&route_match, &canonical), None);
+    }
+
+    let rewrites = [
+        None,
+        Some(PathRewrite::strip_prefix(pattern)),
+        Some(PathRewrite::replace_prefix("/api", "/v2")),
+    ];
+    for rewrite in &rewrites {
+        let Ok(forwarded) = RequestForwarder::target_uri("http://backend:8080/base", &uri, rewrite.as_ref()) else {
+            continue;
+        };
+        assert_eq!(forwarded.scheme_str(), Some("http"));
+        assert_eq!(forwarded.authority().map(|a| a.as_str()), Some("backend:8080"));
+        // Asterisk-form targets ("OPTIONS *") have no path to keep under the base
+        if path.starts_with('/') {
+            assert!(forwarded.path().starts_with("/base/"));
+        }
+        assert_eq!(forwarded.query(), uri.query());
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+//! W3C trace context parsing on arbitrary traceparent values
+//!
+//! Parsing never panics, and a parsed context re-encodes to a header that
+//! parses back to the same trace, span, and flags.
+
+use libfuzzer_sys::fuzz_target;
+use router_proxy::TracingMiddleware;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Some((trace_id, span_id, flags)) = TracingMiddleware::parse_traceparent(value) else {
+        return;
+    };
+    assert!(![&trace_id, &span_id, &flags].iter().any(|field| field.contains('-')));
+
+    let header = TracingMiddleware::create_w3c_trace_context(&trace_id, &span_id, &flags);
+    assert_eq!(TracingMiddleware::parse_traceparent(&header), Some((trace_id, span_id, flags)));
+});
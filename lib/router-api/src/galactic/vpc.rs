@@ -1,18 +1,19 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// VPC from galactic-operator - Represents a virtual private cloud network
 /// that spans multiple Kubernetes clusters
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "galactic.datumapis.com",
     version = "v1alpha",
     kind = "VPC",
     plural = "vpcs",
     derive = "Default",
     status = "VPCStatus",
-)]
+))]
 pub struct VPCSpec {
     /// Networks (CIDRs) associated with this VPC
     /// Can include both IPv4 and IPv6 networks
@@ -1,18 +1,19 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// VPCAttachment from galactic-operator - Defines how pods attach to a Galactic VPC
 /// and receive network interfaces within the VPC
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "galactic.datumapis.com",
     version = "v1alpha",
     kind = "VPCAttachment",
     plural = "vpcattachments",
     derive = "Default",
     status = "VPCAttachmentStatus",
-)]
+))]
 pub struct VPCAttachmentSpec {
     /// Reference to the VPC
     pub vpc: VPCRef,
@@ -6,8 +6,14 @@
 //! - VPCIngress: External ingress into VPC networks
 //! - ServiceBinding: Binds Kubernetes Services to VPCServices
 //! - VPCEgress: Controls outbound traffic from VPCs
+//! - RouterConfig: Cluster-wide settings for the router gateways
+//! - RouterGateway: A running gateway replica, reported by the replica itself
+//!
+//! The resource types need the `kube` feature (on by default); without it
+//! only the spec and status types are available, for static configuration.
 
 pub mod v1alpha1;
 pub mod galactic;
 
-pub use v1alpha1::{VPCService, VPCRoute, VPCIngress, ServiceBinding, VPCEgress};
+#[cfg(feature = "kube")]
+pub use v1alpha1::{VPCService, VPCRoute, VPCIngress, ServiceBinding, VPCEgress, RouterConfig, RouterGateway};
@@ -0,0 +1,1110 @@
+//! Validating builders for v1alpha1 specs
+//!
+//! Struct literals make it easy to build specs the API server accepts but
+//! the router can't use: a route without destinations, a `Default`
+//! destination with weight 0, a port of 0. The builders start from the
+//! same defaults the CRD schema applies and check the result in `build()`,
+//! reporting every problem at once:
+//!
+//! ```
+//! use router_api::v1alpha1::vpc_route::{RouteDestination, VPCRouteSpec};
+//!
+//! let spec = VPCRouteSpec::builder()
+//!     .name("api")
+//!     .path_prefix("/api")
+//!     .method("GET")
+//!     .destination(RouteDestination::service("api").with_port(8080))
+//!     .timeout_seconds(30)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(spec.destinations[0].weight, 100);
+//! ```
+//!
+//! The same checks are available on existing specs through `validate()`.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+
+use chrono::DateTime;
+use thiserror::Error;
+
+use super::router_config::RouterConfigSpec;
+use super::service_binding::{
+    KubernetesServiceRef, PodSelector, PortMapping, ServiceBindingSpec, VPCServiceRef,
+};
+use super::vpc_egress::{EgressDestination, RateLimitConfig, VPCEgressSpec};
+use super::vpc_ingress::{IngressRule, ServiceBackend, TlsConfig, VPCIngressSpec};
+use super::vpc_route::{
+    AffinityPolicy, BandwidthPolicy, BlueGreenConfig, ConcurrencyPolicy, CorsPolicy, ETagPolicy,
+    FaultInjectionPolicy, GraphQLPolicy, HeaderRewritePolicy, LoadBalancingPolicy,
+    PathRewritePolicy, PiiScanPolicy, ReadWriteSplit, RedactionPolicy, RedirectAction,
+    ResponseCachePolicy, ResponseLimitPolicy, RetryPolicy, RouteDestination, RouteSchedule,
+    TrailingSlashPolicy, UpstreamHostMode, UpstreamHostPolicy, VPCRouteSpec,
+};
+use super::vpc_service::{
+    ConnectionPoolConfig, DiscoveryConfig, HealthCheckConfig, MaintenanceWindow,
+    VPCAttachmentRef, VPCServiceSpec, Visibility, PROTOCOLS,
+};
+
+/// A spec that failed validation
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("invalid {kind}: {}", .problems.join("; "))]
+pub struct ValidationError {
+    /// Kind of spec, e.g. "VPCRouteSpec"
+    pub kind: &'static str,
+    /// Every problem found
+    pub problems: Vec<String>,
+}
+
+/// Problems collected while validating a spec
+struct Problems {
+    kind: &'static str,
+    problems: Vec<String>,
+}
+
+impl Problems {
+    fn new(kind: &'static str) -> Self {
+        Self { kind, problems: Vec::new() }
+    }
+
+    /// Record `problem` unless `ok`
+    fn require(&mut self, ok: bool, problem: impl Into<String>) {
+        if !ok {
+            self.problems.push(problem.into());
+        }
+    }
+
+    fn finish(self) -> Result<(), ValidationError> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { kind: self.kind, problems: self.problems })
+        }
+    }
+}
+
+fn is_path(path: &str) -> bool {
+    path.starts_with('/')
+}
+
+fn is_percentage(value: f64) -> bool {
+    (0.0..=100.0).contains(&value)
+}
+
+fn is_cidr(cidr: &str) -> bool {
+    let Some((addr, len)) = cidr.split_once('/') else {
+        return false;
+    };
+    match (addr.parse::<IpAddr>(), len.parse::<u8>()) {
+        (Ok(IpAddr::V4(_)), Ok(len)) => len <= 32,
+        (Ok(IpAddr::V6(_)), Ok(len)) => len <= 128,
+        _ => false,
+    }
+}
+
+fn check_destinations(problems: &mut Problems, field: &str, destinations: &[RouteDestination]) {
+    for (i, destination) in destinations.iter().enumerate() {
+        if !destination.is_direct() {
+            problems.require(
+                !destination.vpc_service_ref.name.is_empty(),
+                format!("{}[{}] needs a VPCService name or a direct response", field, i),
+            );
+        }
+        problems.require(destination.weight <= 100, format!("{}[{}].weight must be 0-100", field, i));
+        problems.require(destination.port != Some(0), format!("{}[{}].port must not be 0", field, i));
+    }
+    if !destinations.is_empty() {
+        problems.require(
+            destinations.iter().any(|d| d.weight > 0),
+            format!("{} weights must not all be 0", field),
+        );
+    }
+}
+
+fn check_selector(problems: &mut Problems, selector: &PodSelector) {
+    for expression in &selector.match_expressions {
+        let needs_values = match expression.operator.as_str() {
+            "In" | "NotIn" => true,
+            "Exists" | "DoesNotExist" => false,
+            other => {
+                problems.require(
+                    false,
+                    format!("label operator {:?} must be In, NotIn, Exists, or DoesNotExist", other),
+                );
+                continue;
+            }
+        };
+        problems.require(
+            needs_values != expression.values.is_empty(),
+            format!("label expression on {:?}: values are required for In/NotIn only", expression.key),
+        );
+    }
+}
+
+fn check_visibility(problems: &mut Problems, visibility: &Visibility) {
+    for (i, vpc) in visibility.vpcs.iter().enumerate() {
+        problems.require(!vpc.name.is_empty(), format!("visibility.vpcs[{}] needs a name", i));
+    }
+    if let Some(selector) = &visibility.vpc_selector {
+        check_selector(problems, selector);
+    }
+}
+
+impl VPCRouteSpec {
+    /// Builder for a route spec
+    pub fn builder() -> VPCRouteSpecBuilder {
+        VPCRouteSpecBuilder::default()
+    }
+
+    /// Check the spec for mistakes the CRD schema doesn't catch
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("VPCRouteSpec");
+        let m = &self.r#match;
+
+        problems.require(
+            m.path_prefix.is_none() || m.exact_path.is_none(),
+            "set at most one of pathPrefix and exactPath",
+        );
+        for path in m.path_prefix.iter().chain(&m.exact_path) {
+            problems.require(is_path(path), format!("path {:?} must start with '/'", path));
+        }
+        for method in &m.methods {
+            problems.require(
+                !method.is_empty() && method.bytes().all(|b| b.is_ascii_alphabetic()),
+                format!("method {:?} is not an HTTP method", method),
+            );
+        }
+        problems.require(
+            m.grpc_method.is_none() || m.grpc_service.is_some(),
+            "grpcMethod needs grpcService",
+        );
+
+        problems.require(
+            !self.destinations.is_empty()
+                || self.blue_green.is_some()
+                || self.read_write_split.is_some()
+                || self.redirect.is_some(),
+            "route needs destinations, a blue/green or read/write split, or a redirect",
+        );
+        check_destinations(&mut problems, "destinations", &self.destinations);
+        if let Some(blue_green) = &self.blue_green {
+            problems.require(
+                !blue_green.destinations(&blue_green.active).is_empty(),
+                format!("active {} destination set is empty", blue_green.active.as_str()),
+            );
+            check_destinations(&mut problems, "blueGreen.blue", &blue_green.blue);
+            check_destinations(&mut problems, "blueGreen.green", &blue_green.green);
+        }
+        if let Some(split) = &self.read_write_split {
+            problems.require(!split.read.is_empty(), "readWriteSplit.read is empty");
+            problems.require(!split.write.is_empty(), "readWriteSplit.write is empty");
+            check_destinations(&mut problems, "readWriteSplit.read", &split.read);
+            check_destinations(&mut problems, "readWriteSplit.write", &split.write);
+        }
+
+        problems.require(self.timeout_seconds != Some(0), "timeoutSeconds must not be 0");
+        if let Some(affinity) = &self.affinity {
+            problems.require(!affinity.name.is_empty(), "affinity needs a key name");
+        }
+        if let Some(backoff) = self.retries.as_ref().and_then(|r| r.backoff.as_ref()) {
+            problems.require(backoff.initial_ms <= backoff.max_ms, "retry backoff initialMs exceeds maxMs");
+        }
+        if let Some(concurrency) = &self.concurrency {
+            problems.require(
+                concurrency.max_concurrent_requests > 0,
+                "concurrency.maxConcurrentRequests must not be 0",
+            );
+        }
+        if let Some(limit) = &self.response_limit {
+            problems.require(limit.max_bytes > 0, "responseLimit.maxBytes must not be 0");
+        }
+        if let Some(graphql) = &self.graphql {
+            problems.require(graphql.max_depth != Some(0), "graphql.maxDepth must not be 0");
+            problems.require(graphql.max_complexity != Some(0), "graphql.maxComplexity must not be 0");
+        }
+        if let Some(rewrite) = &self.path_rewrite {
+            let rewrites = [rewrite.strip_prefix, rewrite.replace_prefix.is_some(), rewrite.regex.is_some()];
+            problems.require(
+                rewrites.iter().filter(|set| **set).count() <= 1,
+                "set at most one of stripPrefix, replacePrefix, and regex",
+            );
+            problems.require(
+                !(rewrite.strip_prefix || rewrite.replace_prefix.is_some()) || m.path_prefix.is_some(),
+                "prefix rewrites need a pathPrefix match",
+            );
+        }
+        if let Some(host) = &self.upstream_host {
+            problems.require(
+                host.mode != UpstreamHostMode::Fixed || host.value.as_deref().is_some_and(|v| !v.is_empty()),
+                "upstreamHost mode fixed needs a value",
+            );
+        }
+        if let Some(redirect) = &self.redirect {
+            problems.require(
+                [301, 302, 303, 307, 308].contains(&redirect.status_code),
+                format!("redirect status {} is not a redirect", redirect.status_code),
+            );
+            problems.require(
+                redirect.path.is_none() || redirect.replace_prefix.is_none(),
+                "set at most one of redirect path and replacePrefix",
+            );
+        }
+        if let Some(fault) = &self.fault {
+            if let Some(delay) = &fault.delay {
+                problems.require(is_percentage(delay.percentage), "fault.delay.percentage must be 0-100");
+                problems.require(
+                    delay.max_delay_ms.is_none_or(|max| max >= delay.fixed_delay_ms),
+                    "fault.delay.maxDelayMs is below fixedDelayMs",
+                );
+            }
+            if let Some(abort) = &fault.abort {
+                problems.require(is_percentage(abort.percentage), "fault.abort.percentage must be 0-100");
+                problems.require(
+                    (100..=599).contains(&abort.status_code),
+                    format!("fault.abort.statusCode {} is not an HTTP status", abort.status_code),
+                );
+            }
+        }
+
+        if let Some(visibility) = &self.visibility {
+            check_visibility(&mut problems, visibility);
+        }
+
+        problems.finish()
+    }
+}
+
+/// Builder for [`VPCRouteSpec`]
+#[derive(Clone, Debug, Default)]
+pub struct VPCRouteSpecBuilder {
+    spec: VPCRouteSpec,
+}
+
+impl VPCRouteSpecBuilder {
+    /// Set the route name
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.spec.name = name.into();
+        self
+    }
+
+    /// Match requests whose path starts with `prefix`
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.spec.r#match.path_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Match requests for exactly `path`
+    pub fn exact_path(mut self, path: impl Into<String>) -> Self {
+        self.spec.r#match.exact_path = Some(path.into());
+        self
+    }
+
+    /// Set how a trailing slash affects path matching
+    pub fn trailing_slash(mut self, policy: TrailingSlashPolicy) -> Self {
+        self.spec.r#match.trailing_slash = policy;
+        self
+    }
+
+    /// Match paths case-insensitively
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.spec.r#match.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Match an HTTP method; may be called more than once
+    pub fn method(mut self, method: impl Into<String>) -> Self {
+        self.spec.r#match.methods.push(method.into().to_ascii_uppercase());
+        self
+    }
+
+    /// Match a request header value
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.spec.r#match.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Match a query parameter value
+    pub fn query_param(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.spec.r#match.query_params.insert(name.into(), value.into());
+        self
+    }
+
+    /// Match a request content type; may be called more than once
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.spec.r#match.content_types.push(content_type.into());
+        self
+    }
+
+    /// Match a gRPC service, and optionally one of its methods
+    pub fn grpc(mut self, service: impl Into<String>, method: Option<&str>) -> Self {
+        self.spec.r#match.grpc_service = Some(service.into());
+        self.spec.r#match.grpc_method = method.map(str::to_string);
+        self
+    }
+
+    /// Add a destination
+    pub fn destination(mut self, destination: RouteDestination) -> Self {
+        self.spec.destinations.push(destination);
+        self
+    }
+
+    /// Use blue/green destination sets
+    pub fn blue_green(mut self, blue_green: BlueGreenConfig) -> Self {
+        self.spec.blue_green = Some(blue_green);
+        self
+    }
+
+    /// Split reads and writes across destination sets
+    pub fn read_write_split(mut self, split: ReadWriteSplit) -> Self {
+        self.spec.read_write_split = Some(split);
+        self
+    }
+
+    /// Set the load balancing policy
+    pub fn load_balancing(mut self, policy: LoadBalancingPolicy) -> Self {
+        self.spec.load_balancing = policy;
+        self
+    }
+
+    /// Set session affinity
+    pub fn affinity(mut self, affinity: AffinityPolicy) -> Self {
+        self.spec.affinity = Some(affinity);
+        self
+    }
+
+    /// Set the request timeout
+    pub fn timeout_seconds(mut self, seconds: u32) -> Self {
+        self.spec.timeout_seconds = Some(seconds);
+        self
+    }
+
+    /// Set the retry policy
+    pub fn retries(mut self, retries: RetryPolicy) -> Self {
+        self.spec.retries = Some(retries);
+        self
+    }
+
+    /// Set concurrency limits
+    pub fn concurrency(mut self, concurrency: ConcurrencyPolicy) -> Self {
+        self.spec.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Set bandwidth limits
+    pub fn bandwidth(mut self, bandwidth: BandwidthPolicy) -> Self {
+        self.spec.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Set the CORS policy
+    pub fn cors(mut self, cors: CorsPolicy) -> Self {
+        self.spec.cors = Some(cors);
+        self
+    }
+
+    /// Restrict the route to a source VPC attachment
+    pub fn source_vpc_attachment(mut self, attachment: impl Into<String>) -> Self {
+        self.spec.source_vpc_attachment = Some(attachment.into());
+        self
+    }
+
+    /// Restrict which source VPCs may use the route
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.spec.visibility = Some(visibility);
+        self
+    }
+
+    /// Set redaction rules
+    pub fn redaction(mut self, redaction: RedactionPolicy) -> Self {
+        self.spec.redaction = Some(redaction);
+        self
+    }
+
+    /// Set header rewrites
+    pub fn headers(mut self, headers: HeaderRewritePolicy) -> Self {
+        self.spec.headers = Some(headers);
+        self
+    }
+
+    /// Set the path rewrite
+    pub fn path_rewrite(mut self, rewrite: PathRewritePolicy) -> Self {
+        self.spec.path_rewrite = Some(rewrite);
+        self
+    }
+
+    /// Set the upstream Host header policy
+    pub fn upstream_host(mut self, host: UpstreamHostPolicy) -> Self {
+        self.spec.upstream_host = Some(host);
+        self
+    }
+
+    /// Answer with a redirect instead of forwarding
+    pub fn redirect(mut self, redirect: RedirectAction) -> Self {
+        self.spec.redirect = Some(redirect);
+        self
+    }
+
+    /// Inject faults
+    pub fn fault(mut self, fault: FaultInjectionPolicy) -> Self {
+        self.spec.fault = Some(fault);
+        self
+    }
+
+    /// Set response caching
+    pub fn cache(mut self, cache: ResponseCachePolicy) -> Self {
+        self.spec.cache = Some(cache);
+        self
+    }
+
+    /// Limit the size of response bodies
+    pub fn response_limit(mut self, limit: ResponseLimitPolicy) -> Self {
+        self.spec.response_limit = Some(limit);
+        self
+    }
+
+    /// Scan responses for PII
+    pub fn pii_scan(mut self, policy: PiiScanPolicy) -> Self {
+        self.spec.pii_scan = Some(policy);
+        self
+    }
+
+    /// Limit GraphQL requests
+    pub fn graphql(mut self, policy: GraphQLPolicy) -> Self {
+        self.spec.graphql = Some(policy);
+        self
+    }
+
+    /// Add ETags and answer conditional requests
+    pub fn etag(mut self, policy: ETagPolicy) -> Self {
+        self.spec.etag = Some(policy);
+        self
+    }
+
+    /// Limit the route to a time window
+    pub fn schedule(mut self, schedule: RouteSchedule) -> Self {
+        self.spec.schedule = Some(schedule);
+        self
+    }
+
+    /// Validate and return the spec
+    pub fn build(self) -> Result<VPCRouteSpec, ValidationError> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+impl VPCServiceSpec {
+    /// Builder for a service running on `port` in a VPC attachment
+    pub fn builder(vpc_attachment: VPCAttachmentRef, port: u16) -> VPCServiceSpecBuilder {
+        VPCServiceSpecBuilder {
+            spec: VPCServiceSpec {
+                vpc_attachment_ref: vpc_attachment,
+                protocol: "HTTP".to_string(),
+                port,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Check the spec for mistakes the CRD schema doesn't catch
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("VPCServiceSpec");
+        problems.require(!self.vpc_attachment_ref.name.is_empty(), "vpcAttachmentRef needs a name");
+        problems.require(
+            PROTOCOLS.contains(&self.protocol.as_str()),
+            format!("protocol {:?} must be HTTP, HTTPS, gRPC, or TCP", self.protocol),
+        );
+        problems.require(self.port != 0, "port must not be 0");
+        problems.require(self.target_port != Some(0), "targetPort must not be 0");
+        if let Some(check) = &self.health_check {
+            if let Some(path) = &check.http_path {
+                problems.require(is_path(path), format!("healthCheck.httpPath {:?} must start with '/'", path));
+            }
+            problems.require(check.interval_seconds > 0, "healthCheck.intervalSeconds must not be 0");
+            problems.require(check.timeout_seconds > 0, "healthCheck.timeoutSeconds must not be 0");
+            problems.require(
+                check.unhealthy_threshold > 0 && check.healthy_threshold > 0,
+                "healthCheck thresholds must not be 0",
+            );
+        }
+        if let Some(until) = self.maintenance.as_ref().and_then(|m| m.until.as_deref()) {
+            problems.require(
+                DateTime::parse_from_rfc3339(until).is_ok(),
+                format!("maintenance.until {:?} is not an RFC 3339 time", until),
+            );
+        }
+        if let Some(visibility) = &self.visibility {
+            check_visibility(&mut problems, visibility);
+        }
+        problems.finish()
+    }
+}
+
+/// Builder for [`VPCServiceSpec`]
+#[derive(Clone, Debug)]
+pub struct VPCServiceSpecBuilder {
+    spec: VPCServiceSpec,
+}
+
+impl VPCServiceSpecBuilder {
+    /// Set the protocol: HTTP, HTTPS, gRPC, or TCP
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.spec.protocol = protocol.into();
+        self
+    }
+
+    /// Forward to a different port than the one the service is reached on
+    pub fn target_port(mut self, port: u16) -> Self {
+        self.spec.target_port = Some(port);
+        self
+    }
+
+    /// Set the health check
+    pub fn health_check(mut self, health_check: HealthCheckConfig) -> Self {
+        self.spec.health_check = Some(health_check);
+        self
+    }
+
+    /// Set discovery settings
+    pub fn discovery(mut self, discovery: DiscoveryConfig) -> Self {
+        self.spec.discovery = Some(discovery);
+        self
+    }
+
+    /// Add a label
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.spec.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Put the service in maintenance
+    pub fn maintenance(mut self, maintenance: MaintenanceWindow) -> Self {
+        self.spec.maintenance = Some(maintenance);
+        self
+    }
+
+    /// Set connection pool settings
+    pub fn connection_pool(mut self, pool: ConnectionPoolConfig) -> Self {
+        self.spec.connection_pool = Some(pool);
+        self
+    }
+
+    /// Restrict which source VPCs may route to the service
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.spec.visibility = Some(visibility);
+        self
+    }
+
+    /// Validate and return the spec
+    pub fn build(self) -> Result<VPCServiceSpec, ValidationError> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+impl VPCIngressSpec {
+    /// Builder for an ingress serving `host`
+    pub fn builder(host: impl Into<String>) -> VPCIngressSpecBuilder {
+        VPCIngressSpecBuilder {
+            spec: VPCIngressSpec { host: host.into(), ..Default::default() },
+        }
+    }
+
+    /// Check the spec for mistakes the CRD schema doesn't catch
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("VPCIngressSpec");
+        problems.require(!self.host.is_empty(), "host must not be empty");
+        problems.require(
+            !self.host.contains(['/', ':', ' ']),
+            format!("host {:?} must be a bare hostname", self.host),
+        );
+        problems.require(!self.rules.is_empty(), "ingress needs at least one rule");
+        for (i, rule) in self.rules.iter().enumerate() {
+            if let Some(path) = &rule.path {
+                problems.require(is_path(path), format!("rules[{}].path {:?} must start with '/'", i, path));
+            }
+            problems.require(!rule.service.name.is_empty(), format!("rules[{}].service needs a name", i));
+            problems.require(!rule.service.namespace.is_empty(), format!("rules[{}].service needs a namespace", i));
+            problems.require(rule.service.port != 0, format!("rules[{}].service.port must not be 0", i));
+        }
+        if let Some(tls) = &self.tls {
+            problems.require(!tls.secret_name.is_empty(), "tls needs a secretName");
+            problems.require(
+                ["passthrough", "terminate"].contains(&tls.mode.as_str()),
+                format!("tls.mode {:?} must be passthrough or terminate", tls.mode),
+            );
+            problems.require(
+                ["1.2", "1.3"].contains(&tls.min_version.as_str()),
+                format!("tls.minVersion {:?} must be 1.2 or 1.3", tls.min_version),
+            );
+        }
+        problems.finish()
+    }
+}
+
+/// Builder for [`VPCIngressSpec`]
+#[derive(Clone, Debug)]
+pub struct VPCIngressSpecBuilder {
+    spec: VPCIngressSpec,
+}
+
+impl VPCIngressSpecBuilder {
+    /// Route requests under `path` to a backend
+    pub fn rule(mut self, path: impl Into<String>, service: ServiceBackend) -> Self {
+        self.spec.rules.push(IngressRule { path: Some(path.into()), service, vpc_attachment_name: None });
+        self
+    }
+
+    /// Add a rule as-is
+    pub fn raw_rule(mut self, rule: IngressRule) -> Self {
+        self.spec.rules.push(rule);
+        self
+    }
+
+    /// Set TLS
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.spec.tls = Some(tls);
+        self
+    }
+
+    /// Set the target VPC attachment
+    pub fn vpc_attachment_name(mut self, name: impl Into<String>) -> Self {
+        self.spec.vpc_attachment_name = Some(name.into());
+        self
+    }
+
+    /// Add an annotation
+    pub fn annotation(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.spec.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    /// Validate and return the spec
+    pub fn build(self) -> Result<VPCIngressSpec, ValidationError> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+impl ServiceBindingSpec {
+    /// Builder binding a Kubernetes Service to a VPCService
+    pub fn builder(service: KubernetesServiceRef, vpc_service: VPCServiceRef) -> ServiceBindingSpecBuilder {
+        ServiceBindingSpecBuilder {
+            spec: ServiceBindingSpec {
+                service_ref: service,
+                vpc_service_ref: vpc_service,
+                sync_interval_seconds: 30,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Check the spec for mistakes the CRD schema doesn't catch
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("ServiceBindingSpec");
+        problems.require(
+            !self.service_ref.name.is_empty() && !self.service_ref.namespace.is_empty(),
+            "serviceRef needs a name and namespace",
+        );
+        problems.require(
+            !self.vpc_service_ref.name.is_empty() && !self.vpc_service_ref.namespace.is_empty(),
+            "vpcServiceRef needs a name and namespace",
+        );
+        for (i, mapping) in self.port_mappings.iter().enumerate() {
+            problems.require(mapping.service_port != 0, format!("portMappings[{}].servicePort must not be 0", i));
+            problems.require(mapping.vpc_port != Some(0), format!("portMappings[{}].vpcPort must not be 0", i));
+            problems.require(
+                ["TCP", "UDP"].contains(&mapping.protocol.as_str()),
+                format!("portMappings[{}].protocol {:?} must be TCP or UDP", i, mapping.protocol),
+            );
+        }
+        if let Some(selector) = &self.pod_selector {
+            check_selector(&mut problems, selector);
+        }
+        problems.require(self.sync_interval_seconds > 0, "syncIntervalSeconds must not be 0");
+        problems.finish()
+    }
+}
+
+/// Builder for [`ServiceBindingSpec`]
+#[derive(Clone, Debug)]
+pub struct ServiceBindingSpecBuilder {
+    spec: ServiceBindingSpec,
+}
+
+impl ServiceBindingSpecBuilder {
+    /// Add a port mapping
+    pub fn port_mapping(mut self, mapping: PortMapping) -> Self {
+        self.spec.port_mappings.push(mapping);
+        self
+    }
+
+    /// Select pods by labels instead of the Service's selector
+    pub fn match_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.spec.pod_selector.get_or_insert_with(PodSelector::default).match_labels = labels;
+        self
+    }
+
+    /// Set the pod selector
+    pub fn pod_selector(mut self, selector: PodSelector) -> Self {
+        self.spec.pod_selector = Some(selector);
+        self
+    }
+
+    /// Sync endpoints automatically
+    pub fn auto_sync(mut self, auto_sync: bool) -> Self {
+        self.spec.auto_sync = auto_sync;
+        self
+    }
+
+    /// Set the endpoint sync interval
+    pub fn sync_interval_seconds(mut self, seconds: u32) -> Self {
+        self.spec.sync_interval_seconds = seconds;
+        self
+    }
+
+    /// Validate and return the spec
+    pub fn build(self) -> Result<ServiceBindingSpec, ValidationError> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+impl VPCEgressSpec {
+    /// Builder for egress from a VPC attachment, allowed by default
+    pub fn builder(source_vpc_attachment: impl Into<String>) -> VPCEgressSpecBuilder {
+        VPCEgressSpecBuilder {
+            spec: VPCEgressSpec {
+                source_vpc_attachment: source_vpc_attachment.into(),
+                policy: "Allow".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Check the spec for mistakes the CRD schema doesn't catch
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("VPCEgressSpec");
+        problems.require(!self.source_vpc_attachment.is_empty(), "sourceVpcAttachment must not be empty");
+        problems.require(
+            ["Allow", "Deny"].contains(&self.policy.as_str()),
+            format!("policy {:?} must be Allow or Deny", self.policy),
+        );
+        for cidr in &self.r#match.destination_cidrs {
+            problems.require(is_cidr(cidr), format!("destination CIDR {:?} is invalid", cidr));
+        }
+        problems.require(
+            !self.r#match.destination_ports.contains(&0),
+            "destination ports must not be 0",
+        );
+        problems.require(
+            !self.destinations.is_empty() || !self.r#match.destination_cidrs.is_empty(),
+            "egress needs destinations or destination CIDRs",
+        );
+        for (i, destination) in self.destinations.iter().enumerate() {
+            problems.require(!destination.endpoint.is_empty(), format!("destinations[{}] needs an endpoint", i));
+            problems.require(destination.port != Some(0), format!("destinations[{}].port must not be 0", i));
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            problems.require(rate_limit.requests_per_second > 0, "rateLimit.requestsPerSecond must not be 0");
+        }
+        problems.finish()
+    }
+}
+
+/// Builder for [`VPCEgressSpec`]
+#[derive(Clone, Debug)]
+pub struct VPCEgressSpecBuilder {
+    spec: VPCEgressSpec,
+}
+
+impl VPCEgressSpecBuilder {
+    /// Deny matching traffic instead of allowing it
+    pub fn deny(mut self) -> Self {
+        self.spec.policy = "Deny".to_string();
+        self
+    }
+
+    /// Match a destination CIDR
+    pub fn destination_cidr(mut self, cidr: impl Into<String>) -> Self {
+        self.spec.r#match.destination_cidrs.push(cidr.into());
+        self
+    }
+
+    /// Match a destination port
+    pub fn destination_port(mut self, port: u16) -> Self {
+        self.spec.r#match.destination_ports.push(port);
+        self
+    }
+
+    /// Match a protocol
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.spec.r#match.protocols.push(protocol.into());
+        self
+    }
+
+    /// Match a source pod label
+    pub fn source_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.spec.r#match.source_labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a destination
+    pub fn destination(mut self, destination: EgressDestination) -> Self {
+        self.spec.destinations.push(destination);
+        self
+    }
+
+    /// Add a destination endpoint, optionally on a port
+    pub fn endpoint(self, endpoint: impl Into<String>, port: Option<u16>) -> Self {
+        self.destination(EgressDestination { endpoint: endpoint.into(), port, tls: None })
+    }
+
+    /// Rate limit matching traffic
+    pub fn rate_limit(mut self, requests_per_second: u32, burst_size: u32) -> Self {
+        self.spec.rate_limit = Some(RateLimitConfig { requests_per_second, burst_size });
+        self
+    }
+
+    /// Validate and return the spec
+    pub fn build(self) -> Result<VPCEgressSpec, ValidationError> {
+        self.spec.validate()?;
+        Ok(self.spec)
+    }
+}
+
+impl RouterConfigSpec {
+    /// Check the settings for values the gateways would reject
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut problems = Problems::new("RouterConfigSpec");
+        let listeners = &self.listeners;
+        for (field, port) in [
+            ("listeners.httpPort", listeners.http_port),
+            ("listeners.httpsPort", listeners.https_port),
+            ("listeners.http3Port", listeners.http3_port),
+        ] {
+            problems.require(port != Some(0), format!("{} must not be 0", field));
+        }
+        problems.require(
+            listeners.http_port.is_none() || listeners.http_port != listeners.https_port,
+            "listeners.httpPort and listeners.httpsPort must differ",
+        );
+        problems.require(
+            listeners.tls_cert_file.is_some() == listeners.tls_key_file.is_some(),
+            "listeners.tlsCertFile and listeners.tlsKeyFile must be set together",
+        );
+        for (i, tcp) in listeners.tcp.iter().enumerate() {
+            problems.require(tcp.port != 0, format!("listeners.tcp[{}].port must not be 0", i));
+            problems.require(
+                tcp.service.split_once('/').is_some_and(|(ns, name)| !ns.is_empty() && !name.is_empty()),
+                format!("listeners.tcp[{}].service {:?} must be namespace/name", i, tcp.service),
+            );
+            problems.require(!tcp.endpoints.is_empty(), format!("listeners.tcp[{}] needs endpoints", i));
+            for endpoint in &tcp.endpoints {
+                problems.require(
+                    endpoint.parse::<SocketAddr>().is_ok(),
+                    format!("listeners.tcp[{}] endpoint {:?} must be ip:port", i, endpoint),
+                );
+            }
+        }
+
+        let defaults = &self.defaults;
+        problems.require(defaults.rate_limit_rps != Some(0), "defaults.rateLimitRps must not be 0");
+        problems.require(
+            defaults.rate_limit_burst.is_none() || defaults.rate_limit_rps.is_some(),
+            "defaults.rateLimitBurst needs defaults.rateLimitRps",
+        );
+        problems.require(
+            (defaults.queue_length.is_none() && defaults.queue_timeout_ms.is_none())
+                || defaults.max_concurrent_requests.is_some(),
+            "defaults.queueLength and defaults.queueTimeoutMs need defaults.maxConcurrentRequests",
+        );
+        if let Some(format) = &defaults.error_format {
+            problems.require(
+                ["text", "problem+json"].contains(&format.to_ascii_lowercase().as_str()),
+                format!("defaults.errorFormat {:?} must be text or problem+json", format),
+            );
+        }
+
+        if let Some(header) = &self.middleware.request_id_header {
+            problems.require(
+                !header.is_empty() && header.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'),
+                format!("middleware.requestIdHeader {:?} is not a valid header name", header),
+            );
+        }
+
+        let metrics = &self.metrics;
+        if let Some(backend) = &metrics.backend {
+            problems.require(
+                ["prometheus", "statsd", "otlp"].contains(&backend.to_ascii_lowercase().as_str()),
+                format!("metrics.backend {:?} must be prometheus, statsd, or otlp", backend),
+            );
+        }
+        problems.require(metrics.otlp_interval_seconds != Some(0), "metrics.otlpIntervalSeconds must not be 0");
+        problems.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_builder_defaults() {
+        let spec = VPCRouteSpec::builder()
+            .path_prefix("/api")
+            .method("get")
+            .destination(RouteDestination::service("api").with_weight(80))
+            .destination(RouteDestination::service("api-canary").with_weight(20))
+            .build()
+            .unwrap();
+        assert_eq!(spec.r#match.methods, vec!["GET"]);
+        assert!(matches!(spec.load_balancing, LoadBalancingPolicy::RoundRobin));
+
+        let split = VPCRouteSpec::builder()
+            .read_write_split(ReadWriteSplit::new(
+                vec![RouteDestination::service("replica")],
+                vec![RouteDestination::service("primary")],
+            ))
+            .build()
+            .unwrap();
+        assert!(split.read_write_split.unwrap().is_read("HEAD"));
+    }
+
+    #[test]
+    fn test_route_builder_reports_every_problem() {
+        let err = VPCRouteSpec::builder()
+            .path_prefix("api")
+            .exact_path("/api")
+            .timeout_seconds(0)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind, "VPCRouteSpec");
+        assert_eq!(err.problems.len(), 4, "{}", err);
+        assert!(err.to_string().starts_with("invalid VPCRouteSpec: "));
+
+        // A Default destination has weight 0 and no service
+        let err = VPCRouteSpec::builder().destination(RouteDestination::default()).build().unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+
+        let err = VPCRouteSpec::builder()
+            .destination(RouteDestination::service("api"))
+            .path_rewrite(PathRewritePolicy { strip_prefix: true, ..Default::default() })
+            .build()
+            .unwrap_err();
+        assert_eq!(err.problems, vec!["prefix rewrites need a pathPrefix match"]);
+    }
+
+    #[test]
+    fn test_service_and_ingress_builders() {
+        let service = VPCServiceSpec::builder(VPCAttachmentRef::new("vpc-a"), 8080)
+            .protocol("gRPC")
+            .health_check(HealthCheckConfig::http("/healthz"))
+            .build()
+            .unwrap();
+        assert_eq!(service.vpc_attachment_ref.namespace, "default");
+        assert_eq!(service.health_check.unwrap().interval_seconds, 10);
+        assert!(VPCServiceSpec::builder(VPCAttachmentRef::new("vpc-a"), 0).protocol("http").build().is_err());
+
+        let ingress = VPCIngressSpec::builder("api.example.com")
+            .rule("/", ServiceBackend::new("api", "default", 80))
+            .tls(TlsConfig::terminate("api-cert"))
+            .build()
+            .unwrap();
+        assert_eq!(ingress.tls.unwrap().min_version, "1.2");
+        assert!(VPCIngressSpec::builder("https://api.example.com").build().is_err());
+    }
+
+    #[test]
+    fn test_binding_and_egress_builders() {
+        let binding = ServiceBindingSpec::builder(
+            KubernetesServiceRef { name: "api".to_string(), namespace: "default".to_string() },
+            VPCServiceRef { name: "api".to_string(), namespace: "default".to_string() },
+        )
+        .port_mapping(PortMapping::new(8080))
+        .build()
+        .unwrap();
+        assert_eq!(binding.sync_interval_seconds, 30);
+        assert_eq!(binding.port_mappings[0].protocol, "TCP");
+
+        let egress = VPCEgressSpec::builder("vpc-a")
+            .destination_cidr("10.0.0.0/8")
+            .destination_port(443)
+            .build()
+            .unwrap();
+        assert_eq!(egress.policy, "Allow");
+
+        let err = VPCEgressSpec::builder("vpc-a").destination_cidr("10.0.0.0/33").destination_port(0).build().unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+    }
+
+    #[test]
+    fn test_router_config_validation() {
+        use crate::v1alpha1::router_config::{DefaultPolicies, ListenerSettings, TcpListenerSettings};
+
+        assert!(RouterConfigSpec::default().validate().is_ok());
+
+        let spec = RouterConfigSpec {
+            listeners: ListenerSettings {
+                http_port: Some(8080),
+                https_port: Some(8080),
+                tls_cert_file: Some("/etc/router/tls.crt".to_string()),
+                tcp: vec![TcpListenerSettings {
+                    port: 5432,
+                    service: "postgres".to_string(),
+                    endpoints: vec!["10.0.0.1:5432".to_string(), "db:5432".to_string()],
+                }],
+                ..Default::default()
+            },
+            defaults: DefaultPolicies {
+                queue_length: Some(10),
+                error_format: Some("xml".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = spec.validate().unwrap_err();
+        assert_eq!(err.kind, "RouterConfigSpec");
+        assert_eq!(err.problems.len(), 6, "{}", err);
+    }
+
+    #[test]
+    fn test_visibility() {
+        use crate::v1alpha1::service_binding::LabelExpression;
+        use crate::v1alpha1::vpc_service::VPCReference;
+
+        let visibility = Visibility {
+            vpcs: vec![VPCReference { name: "payments".to_string(), namespace: "default".to_string() }],
+            vpc_selector: Some(PodSelector {
+                match_labels: BTreeMap::from([("tier".to_string(), "internal".to_string())]),
+                match_expressions: vec![LabelExpression {
+                    key: "env".to_string(),
+                    operator: "NotIn".to_string(),
+                    values: vec!["dev".to_string()],
+                }],
+            }),
+        };
+        let labels = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert!(visibility.allows("default", "payments", &BTreeMap::new()));
+        assert!(visibility.allows("team-a", "orders", &labels(&[("tier", "internal")])));
+        assert!(!visibility.allows("team-a", "orders", &labels(&[("tier", "internal"), ("env", "dev")])));
+        assert!(!visibility.allows("team-b", "payments", &labels(&[("tier", "public")])));
+
+        let route = VPCRouteSpec::builder()
+            .destination(RouteDestination::service("api"))
+            .visibility(visibility)
+            .build()
+            .unwrap();
+        assert!(route.visibility.is_some());
+
+        let invalid = Visibility {
+            vpcs: vec![VPCReference::default()],
+            vpc_selector: Some(PodSelector {
+                match_expressions: vec![LabelExpression { key: "env".to_string(), operator: "Exists".to_string(), values: vec!["prod".to_string()] }],
+                ..Default::default()
+            }),
+        };
+        let err = VPCServiceSpec::builder(VPCAttachmentRef::new("vpc-a"), 8080).visibility(invalid).build().unwrap_err();
+        assert_eq!(err.problems.len(), 2, "{}", err);
+    }
+}
@@ -0,0 +1,111 @@
+//! Spec values v1alpha1 accepts but v1alpha2 won't
+//!
+//! Some v1alpha1 fields are free-form strings the router interprets
+//! loosely, such as protocols and HTTP methods in any case. v1alpha2 turns
+//! them into enums, so specs relying on the loose spellings will stop
+//! validating. The controllers report each such value in a `Deprecated`
+//! status condition and a Warning event, so users can migrate ahead of the
+//! new version.
+
+use std::fmt;
+
+use super::vpc_route::VPCRouteSpec;
+use super::vpc_service::{VPCServiceSpec, PROTOCOLS};
+
+/// Condition type reporting deprecated spec values
+pub const DEPRECATED_CONDITION: &str = "Deprecated";
+
+/// A deprecated value in a spec
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Path of the field, e.g. `spec.protocol`
+    pub field: String,
+    /// What to change, and why
+    pub message: String,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// One message for all of a resource's deprecations
+pub fn summarize(deprecations: &[Deprecation]) -> String {
+    deprecations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+impl VPCServiceSpec {
+    /// Values of this spec that v1alpha2 won't accept
+    pub fn deprecations(&self) -> Vec<Deprecation> {
+        let mut deprecations = Vec::new();
+        if !PROTOCOLS.contains(&self.protocol.as_str()) {
+            let message = match PROTOCOLS.iter().find(|p| p.eq_ignore_ascii_case(&self.protocol)) {
+                Some(canonical) => format!("write {:?} as {:?}; v1alpha2 protocols are case-sensitive", self.protocol, canonical),
+                None => format!(
+                    "{:?} is not a known protocol; v1alpha2 only accepts {}",
+                    self.protocol,
+                    PROTOCOLS.join(", ")
+                ),
+            };
+            deprecations.push(Deprecation { field: "spec.protocol".to_string(), message });
+        }
+        deprecations
+    }
+}
+
+impl VPCRouteSpec {
+    /// Values of this spec that v1alpha2 won't accept
+    pub fn deprecations(&self) -> Vec<Deprecation> {
+        let mut deprecations = Vec::new();
+        let mut methods = |field: &str, methods: &[String]| {
+            for (i, method) in methods.iter().enumerate() {
+                if *method != method.to_ascii_uppercase() {
+                    deprecations.push(Deprecation {
+                        field: format!("{}[{}]", field, i),
+                        message: format!(
+                            "write {:?} as {:?}; v1alpha2 methods are case-sensitive",
+                            method,
+                            method.to_ascii_uppercase()
+                        ),
+                    });
+                }
+            }
+        };
+        methods("spec.match.methods", &self.r#match.methods);
+        if let Some(split) = &self.read_write_split {
+            methods("spec.readWriteSplit.readMethods", &split.read_methods);
+        }
+        deprecations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_protocol() {
+        let spec = |protocol: &str| VPCServiceSpec { protocol: protocol.to_string(), ..Default::default() };
+        assert!(spec("gRPC").deprecations().is_empty());
+
+        let deprecations = spec("grpc").deprecations();
+        assert_eq!(deprecations.len(), 1);
+        assert_eq!(deprecations[0].field, "spec.protocol");
+        assert!(deprecations[0].message.contains("\"gRPC\""));
+        assert!(spec("h2c").deprecations()[0].message.contains("not a known protocol"));
+    }
+
+    #[test]
+    fn test_route_methods() {
+        let mut spec = VPCRouteSpec::default();
+        spec.r#match.methods = vec!["GET".to_string(), "post".to_string()];
+        let deprecations = spec.deprecations();
+        assert_eq!(deprecations.len(), 1);
+        assert_eq!(deprecations[0].field, "spec.match.methods[1]");
+        assert_eq!(
+            summarize(&deprecations),
+            "spec.match.methods[1]: write \"post\" as \"POST\"; v1alpha2 methods are case-sensitive"
+        );
+    }
+}
@@ -1,16 +1,31 @@
-/// API version v1alpha1 for Datum Router CRDs
+//! API version v1alpha1 for Datum Router CRDs
 
 pub mod vpc_service;
 pub mod vpc_route;
 pub mod vpc_ingress;
 pub mod service_binding;
 pub mod vpc_egress;
+pub mod router_config;
+pub mod router_gateway;
+pub mod builder;
+pub mod deprecation;
 
+pub use builder::ValidationError;
+
+#[cfg(feature = "kube")]
 pub use vpc_service::VPCService;
+#[cfg(feature = "kube")]
 pub use vpc_route::VPCRoute;
+#[cfg(feature = "kube")]
 pub use vpc_ingress::VPCIngress;
+#[cfg(feature = "kube")]
 pub use service_binding::ServiceBinding;
+#[cfg(feature = "kube")]
 pub use vpc_egress::VPCEgress;
+#[cfg(feature = "kube")]
+pub use router_config::RouterConfig;
+#[cfg(feature = "kube")]
+pub use router_gateway::RouterGateway;
 
 /// API group for Datum Router resources
 pub const API_GROUP: &str = "router.datum.net";
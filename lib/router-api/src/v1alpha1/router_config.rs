@@ -0,0 +1,203 @@
+#[cfg(feature = "kube")]
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// RouterConfig holds the settings shared by every router-gateway replica
+///
+/// Gateways use the RouterConfig named "default" (see the controller's and
+/// gateway's `ROUTER_CONFIG_NAME`). Every field is optional; unset fields
+/// keep the gateway's built-in default, and environment variables set on
+/// a gateway take precedence over this resource.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
+    group = "router.datum.net",
+    version = "v1alpha1",
+    kind = "RouterConfig",
+    plural = "routerconfigs",
+    derive = "Default",
+    status = "RouterConfigStatus",
+    printcolumn = r#"{"name":"Synced","type":"integer","jsonPath":".status.gatewaysSynced"}"#,
+    printcolumn = r#"{"name":"Gateways","type":"integer","jsonPath":".status.gateways"}"#,
+))]
+#[serde(rename_all = "camelCase")]
+pub struct RouterConfigSpec {
+    /// Ports and protocols the gateways listen on
+    #[serde(default)]
+    pub listeners: ListenerSettings,
+
+    /// Policies applied to every request
+    #[serde(default)]
+    pub defaults: DefaultPolicies,
+
+    /// Optional request processing stages
+    #[serde(default)]
+    pub middleware: MiddlewareSettings,
+
+    /// Where metrics are sent
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+}
+
+/// Gateway listeners
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerSettings {
+    /// Plaintext HTTP port (default: 8080)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_port: Option<u16>,
+
+    /// HTTPS port, served when a certificate is configured (default: 8443)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub https_port: Option<u16>,
+
+    /// PEM certificate file for HTTPS, as mounted in the gateway pods
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_cert_file: Option<String>,
+
+    /// PEM private key file for HTTPS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_key_file: Option<String>,
+
+    /// Accept prior-knowledge HTTP/2 on the plaintext port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub h2c: Option<bool>,
+
+    /// Serve HTTP/3 next to HTTPS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http3: Option<bool>,
+
+    /// UDP port for HTTP/3 (default: 8443)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http3_port: Option<u16>,
+
+    /// Layer 4 listeners proxying to TCP services
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tcp: Vec<TcpListenerSettings>,
+}
+
+/// A TCP proxy listener
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TcpListenerSettings {
+    /// Port the gateway listens on
+    pub port: u16,
+
+    /// Service proxied to, as namespace/name
+    pub service: String,
+
+    /// Endpoints of the service ("ip:port")
+    pub endpoints: Vec<String>,
+}
+
+/// Policies applied to every request
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DefaultPolicies {
+    /// Largest request body accepted (bytes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_request_body_bytes: Option<u64>,
+
+    /// Most requests proxied at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<u32>,
+
+    /// Requests waiting once the concurrency limit is reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_length: Option<u32>,
+
+    /// Longest time a request waits in the queue (ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_timeout_ms: Option<u32>,
+
+    /// Requests per second allowed per client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_rps: Option<u32>,
+
+    /// Extra requests per second allowed per client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_burst: Option<u32>,
+
+    /// Error body format: "text" or "problem+json"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_format: Option<String>,
+
+    /// Time open connections get to finish when a gateway shuts down (seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drain_timeout_seconds: Option<u32>,
+}
+
+/// Optional request processing stages
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MiddlewareSettings {
+    /// Access log destination: "stdout" or a file path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<String>,
+
+    /// Compress eligible responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<bool>,
+
+    /// Add Server-Timing headers to responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_timing: Option<bool>,
+
+    /// Header carrying request IDs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id_header: Option<String>,
+}
+
+/// Metrics export
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSettings {
+    /// Backend: "prometheus", "statsd", or "otlp"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+
+    /// StatsD address (host:port)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statsd_address: Option<String>,
+
+    /// Prefix of StatsD metric names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statsd_prefix: Option<String>,
+
+    /// OTLP collector endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp_endpoint: Option<String>,
+
+    /// OTLP export interval (seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp_interval_seconds: Option<u32>,
+
+    /// Most distinct path labels before the rest are grouped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_path_labels: Option<u32>,
+}
+
+/// Status of a RouterConfig
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RouterConfigStatus {
+    /// Generation of the spec the status describes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+
+    /// Gateway replicas found
+    #[serde(default)]
+    pub gateways: u32,
+
+    /// Gateway replicas running the observed generation
+    #[serde(default)]
+    pub gateways_synced: u32,
+
+    /// Last time the config was pushed to a gateway (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_push_time: Option<String>,
+
+    /// Rollout progress, or why the config can't be applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
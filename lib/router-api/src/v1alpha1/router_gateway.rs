@@ -0,0 +1,99 @@
+#[cfg(feature = "kube")]
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// RouterGateway describes one running router-gateway replica
+///
+/// Each replica creates its own RouterGateway, named after its pod and
+/// owned by it, and keeps the status current with periodic heartbeats, so
+/// `kubectl get routergateways` shows the whole fleet: which version each
+/// replica runs, which config generation it loaded, and which route
+/// changes it has applied.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
+    group = "router.datum.net",
+    version = "v1alpha1",
+    kind = "RouterGateway",
+    plural = "routergateways",
+    namespaced,
+    derive = "Default",
+    status = "RouterGatewayStatus",
+    printcolumn = r#"{"name":"Address","type":"string","jsonPath":".status.address"}"#,
+    printcolumn = r#"{"name":"Version","type":"string","jsonPath":".status.version"}"#,
+    printcolumn = r#"{"name":"Config","type":"integer","jsonPath":".status.configGeneration"}"#,
+    printcolumn = r#"{"name":"Routes","type":"integer","jsonPath":".status.routeGeneration"}"#,
+    printcolumn = r#"{"name":"Ready","type":"boolean","jsonPath":".status.ready"}"#,
+    printcolumn = r#"{"name":"Heartbeat","type":"date","jsonPath":".status.lastHeartbeatTime"}"#,
+))]
+#[serde(rename_all = "camelCase")]
+pub struct RouterGatewaySpec {
+    /// Pod running the replica
+    pub pod_name: String,
+
+    /// Node the pod runs on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+}
+
+/// State reported by a gateway replica
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RouterGatewayStatus {
+    /// router-gateway version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Pod IP the replica serves on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    /// RouterConfig the replica loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_name: Option<String>,
+
+    /// Generation of the RouterConfig spec the replica loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_generation: Option<i64>,
+
+    /// Whether the replica accepts traffic; false while draining
+    #[serde(default)]
+    pub ready: bool,
+
+    /// Generation of the replica's route table; increases with every route change it applies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_generation: Option<u64>,
+
+    /// Routing resources in the route table
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<AppliedRoute>,
+
+    /// Requests being handled at the last heartbeat
+    #[serde(default)]
+    pub inflight_requests: u64,
+
+    /// When the replica started (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+
+    /// Last status report (RFC 3339); a stale heartbeat means the replica is gone or stuck
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat_time: Option<String>,
+}
+
+/// A routing resource a gateway replica has applied
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedRoute {
+    /// Kind of the resource (VPCRoute or VPCIngress)
+    pub kind: String,
+
+    /// Namespace of the resource; empty for cluster-scoped kinds
+    pub namespace: String,
+
+    /// Name of the resource
+    pub name: String,
+
+    /// Spec generation that was applied
+    pub generation: i64,
+}
@@ -1,19 +1,21 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-
+use std::collections::BTreeMap;
 
 /// ServiceBinding binds a Kubernetes Service to a VPCService
 /// for automatic endpoint synchronization across VPCs
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "router.datum.net",
     version = "v1alpha1",
     kind = "ServiceBinding",
     plural = "servicebindings",
     derive = "Default",
     status = "ServiceBindingStatus",
-)]
+))]
+#[serde(rename_all = "camelCase")]
 pub struct ServiceBindingSpec {
     /// Reference to a Kubernetes Service
     pub service_ref: KubernetesServiceRef,
@@ -63,6 +65,7 @@ pub struct VPCServiceRef {
 
 /// Port mapping configuration
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct PortMapping {
     /// Port on the Kubernetes Service
@@ -77,11 +80,20 @@ pub struct PortMapping {
     pub protocol: String,
 }
 
+impl PortMapping {
+    /// TCP mapping of a Service port to the same VPCService port
+    pub fn new(service_port: u16) -> Self {
+        Self { service_port, vpc_port: None, protocol: default_protocol() }
+    }
+}
+
 /// Pod selector for endpoint discovery
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct PodSelector {
     /// Label selectors for matching pods
+    #[serde(default)]
     pub match_labels: std::collections::BTreeMap<String, String>,
 
     /// Label expressions for pod selection
@@ -89,6 +101,25 @@ pub struct PodSelector {
     pub match_expressions: Vec<LabelExpression>,
 }
 
+impl PodSelector {
+    /// Whether `labels` satisfy every label and expression
+    ///
+    /// Expressions with an unknown operator never match.
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.match_labels.iter().all(|(key, value)| labels.get(key) == Some(value))
+            && self.match_expressions.iter().all(|expression| {
+                let value = labels.get(&expression.key);
+                match expression.operator.as_str() {
+                    "In" => value.is_some_and(|v| expression.values.contains(v)),
+                    "NotIn" => value.is_none_or(|v| !expression.values.contains(v)),
+                    "Exists" => value.is_some(),
+                    "DoesNotExist" => value.is_none(),
+                    _ => false,
+                }
+            })
+    }
+}
+
 /// Label expression for pod matching
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[derive(Default)]
@@ -106,6 +137,7 @@ pub struct LabelExpression {
 
 /// Status of a ServiceBinding
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct ServiceBindingStatus {
     /// Whether this binding is active
     #[serde(default)]
@@ -130,6 +162,7 @@ pub struct ServiceBindingStatus {
 
 /// Condition for ServiceBinding status
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct BindingCondition {
     /// Type of condition
@@ -1,17 +1,19 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// VPCEgress controls outbound traffic from VPCs to external services
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "router.datum.net",
     version = "v1alpha1",
     kind = "VPCEgress",
     plural = "vpcegresses",
     derive = "Default",
     status = "VPCEgressStatus",
-)]
+))]
+#[serde(rename_all = "camelCase")]
 pub struct VPCEgressSpec {
     /// VPC attachment where traffic originates
     pub source_vpc_attachment: String,
@@ -33,6 +35,7 @@ pub struct VPCEgressSpec {
 
 /// Match conditions for egress traffic
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct EgressMatch {
     /// Destination IP ranges (CIDR)
@@ -87,6 +90,7 @@ pub struct OutboundTls {
 
 /// Rate limiting configuration
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct RateLimitConfig {
     /// Requests per second
@@ -99,6 +103,7 @@ pub struct RateLimitConfig {
 
 /// Status of a VPCEgress
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct VPCEgressStatus {
     /// Whether this egress rule is active
     #[serde(default)]
@@ -1,17 +1,19 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// VPCIngress defines external ingress into VPC networks via the router-gateway
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "router.datum.net",
     version = "v1alpha1",
     kind = "VPCIngress",
     plural = "vpcingresses",
     derive = "Default",
     status = "VPCIngressStatus",
-)]
+))]
+#[serde(rename_all = "camelCase")]
 pub struct VPCIngressSpec {
     /// Hostname for this ingress (e.g., api.example.com)
     pub host: String,
@@ -34,6 +36,7 @@ pub struct VPCIngressSpec {
 
 /// Ingress rule for path-based routing
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct IngressRule {
     /// HTTP path prefix (e.g., "/api/v1")
@@ -62,8 +65,16 @@ pub struct ServiceBackend {
     pub port: u16,
 }
 
+impl ServiceBackend {
+    /// Backend on a VPCService port
+    pub fn new(name: impl Into<String>, namespace: impl Into<String>, port: u16) -> Self {
+        Self { name: name.into(), namespace: namespace.into(), port }
+    }
+}
+
 /// TLS configuration for HTTPS
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct TlsConfig {
     /// TLS certificate secret name (in same namespace as ingress)
@@ -86,8 +97,22 @@ pub struct TlsConfig {
     pub cipher_suites: Vec<String>,
 }
 
+impl TlsConfig {
+    /// TLS terminated at the gateway with the certificate in `secret_name`
+    pub fn terminate(secret_name: impl Into<String>) -> Self {
+        Self {
+            secret_name: secret_name.into(),
+            secret_version: None,
+            mode: default_tls_mode(),
+            min_version: default_min_tls_version(),
+            cipher_suites: Vec::new(),
+        }
+    }
+}
+
 /// Status of a VPCIngress
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct VPCIngressStatus {
     /// Whether this ingress is ready
     #[serde(default)]
@@ -104,6 +129,14 @@ pub struct VPCIngressStatus {
     /// Current ingress addresses
     #[serde(default)]
     pub ingress_addresses: Vec<IngressAddress>,
+
+    /// Spec generation every ready gateway has applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programmed_generation: Option<i64>,
+
+    /// Ready gateways running the current spec generation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programmed_gateways: Option<u32>,
 }
 
 /// Ingress address information
@@ -1,18 +1,23 @@
+#[cfg(feature = "kube")]
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::vpc_service::{Condition, Visibility};
+
 /// VPCRoute defines Layer 7 routing rules for traffic between VPCs
 /// or from external clients to VPCServices
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "router.datum.net",
     version = "v1alpha1",
     kind = "VPCRoute",
     plural = "vpcroutes",
+    namespaced,
     derive = "Default",
     status = "VPCRouteStatus",
-)]
+))]
+#[serde(rename_all = "camelCase")]
 pub struct VPCRouteSpec {
     /// Name of this route (for reference)
     pub name: String,
@@ -23,10 +28,22 @@ pub struct VPCRouteSpec {
     /// Destination service(s)
     pub destinations: Vec<RouteDestination>,
 
+    /// Blue/green destination sets; when set, the active set replaces `destinations`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blue_green: Option<BlueGreenConfig>,
+
+    /// Read/write splitting: reads and writes go to separate destination sets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_write_split: Option<ReadWriteSplit>,
+
     /// Load balancing strategy
     #[serde(default = "default_load_balancing")]
     pub load_balancing: LoadBalancingPolicy,
 
+    /// Session affinity: where the consistent-hash key comes from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<AffinityPolicy>,
+
     /// Request timeout (seconds)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_seconds: Option<u32>,
@@ -35,6 +52,14 @@ pub struct VPCRouteSpec {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retries: Option<RetryPolicy>,
 
+    /// Capacity limits: concurrent requests and queueing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ConcurrencyPolicy>,
+
+    /// Bandwidth limits for response bodies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<BandwidthPolicy>,
+
     /// CORS configuration
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cors: Option<CorsPolicy>,
@@ -42,6 +67,82 @@ pub struct VPCRouteSpec {
     /// Optional: restrict this route to specific source VPC
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_vpc_attachment: Option<String>,
+
+    /// Source VPCs allowed to use this route; unset allows every VPC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<Visibility>,
+
+    /// Redaction rules applied to access logs and traces for this route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<RedactionPolicy>,
+
+    /// Headers added, set, or removed on requests and responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HeaderRewritePolicy>,
+
+    /// Path rewrite applied before forwarding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_rewrite: Option<PathRewritePolicy>,
+
+    /// Host header sent to the backend (default: the client's)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_host: Option<UpstreamHostPolicy>,
+
+    /// Answer matching requests with a redirect instead of forwarding them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<RedirectAction>,
+
+    /// Faults injected into requests for resilience testing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<FaultInjectionPolicy>,
+
+    /// Serve cacheable GET responses from the gateway's response cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<ResponseCachePolicy>,
+
+    /// Largest response body the gateway accepts from the backend
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_limit: Option<ResponseLimitPolicy>,
+
+    /// Scan responses for PII and mask or block what is found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pii_scan: Option<PiiScanPolicy>,
+
+    /// Depth, complexity, and operation limits for a GraphQL endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<GraphQLPolicy>,
+
+    /// Add ETags to responses and answer conditional requests at the gateway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<ETagPolicy>,
+
+    /// Time window during which this route is active
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<RouteSchedule>,
+}
+
+impl VPCRouteSpec {
+    /// Destinations traffic is currently sent to
+    ///
+    /// For blue/green routes this is the active color's set.
+    pub fn effective_destinations(&self) -> &[RouteDestination] {
+        match &self.blue_green {
+            Some(blue_green) => blue_green.destinations(&blue_green.active),
+            None => &self.destinations,
+        }
+    }
+
+    /// Destinations for a request method
+    ///
+    /// With read/write splitting, read methods go to the read set and all
+    /// other methods to the write set.
+    pub fn destinations_for_method(&self, method: &str) -> &[RouteDestination] {
+        match &self.read_write_split {
+            Some(split) if split.is_read(method) => &split.read,
+            Some(split) => &split.write,
+            None => self.effective_destinations(),
+        }
+    }
 }
 
 /// Route matching conditions
@@ -57,6 +158,14 @@ pub struct RouteMatch {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub exact_path: Option<String>,
 
+    /// How a trailing slash affects path matching
+    #[serde(default)]
+    pub trailing_slash: TrailingSlashPolicy,
+
+    /// Match paths case-insensitively
+    #[serde(default)]
+    pub case_insensitive: bool,
+
     /// HTTP headers to match
     #[serde(default)]
     pub headers: std::collections::BTreeMap<String, String>,
@@ -69,6 +178,22 @@ pub struct RouteMatch {
     #[serde(default)]
     pub methods: Vec<String>,
 
+    /// Request content types (e.g., "application/grpc", "application/*")
+    #[serde(default)]
+    pub content_types: Vec<String>,
+
+    /// Regular expression the User-Agent header must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Client version header and the semver range it must satisfy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<ClientVersionMatch>,
+
+    /// Dark launch gate: the route only matches requests carrying the secret token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dark_launch: Option<DarkLaunchMatch>,
+
     /// gRPC service name (for gRPC routes)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub grpc_service: Option<String>,
@@ -78,13 +203,71 @@ pub struct RouteMatch {
     pub grpc_method: Option<String>,
 }
 
+/// Trailing slash handling for path matching
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrailingSlashPolicy {
+    /// "/users" and "/users/" are different paths
+    #[default]
+    Strict,
+    /// A trailing slash is ignored when matching
+    Ignore,
+    /// Match as with `ignore`, then redirect to the route's canonical form
+    Redirect,
+}
+
+/// Match on a client-reported version
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct ClientVersionMatch {
+    /// Header carrying the client version (e.g., "x-app-version")
+    pub header: String,
+
+    /// Semver range the version must satisfy (e.g., "<2.0.0", ">=3.1, <4")
+    pub range: String,
+}
+
+/// Header gate for dark-launched routes
+///
+/// Requests without the token fall through to the next matching route.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct DarkLaunchMatch {
+    /// Header carrying the token (e.g., "x-dark-launch")
+    pub header: String,
+
+    /// Secret key holding the expected token
+    pub secret_ref: SecretKeyRef,
+}
+
+/// Reference to a key in a Kubernetes Secret
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Default)]
+pub struct SecretKeyRef {
+    /// Name of the Secret (in the same namespace as the route)
+    pub name: String,
+
+    /// Key within the Secret
+    pub key: String,
+}
+
 /// Destination for a route
+///
+/// Either a VPCService or a response served by the gateway itself.
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct RouteDestination {
     /// Reference to a VPCService
+    #[serde(default)]
     pub vpc_service_ref: ServiceRef,
 
+    /// Response served by the gateway instead of a VPCService
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct_response: Option<DirectResponse>,
+
     /// Weight for weighted load balancing (0-100)
     #[serde(default = "default_weight")]
     pub weight: u32,
@@ -94,6 +277,152 @@ pub struct RouteDestination {
     pub port: Option<u16>,
 }
 
+impl RouteDestination {
+    /// Destination sending traffic to a VPCService, at full weight
+    pub fn service(name: impl Into<String>) -> Self {
+        Self {
+            vpc_service_ref: ServiceRef { name: name.into(), namespace: None },
+            weight: default_weight(),
+            ..Default::default()
+        }
+    }
+
+    /// Destination answered by the gateway with a fixed response
+    pub fn direct(status_code: u16, body: impl Into<String>) -> Self {
+        Self {
+            direct_response: Some(DirectResponse { status_code, body: body.into(), headers: Default::default() }),
+            weight: default_weight(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the VPCService namespace
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.vpc_service_ref.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Set the weight (0-100)
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Set the port override
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Whether the gateway answers this destination itself
+    pub fn is_direct(&self) -> bool {
+        self.direct_response.is_some()
+    }
+}
+
+/// Fixed response served by the gateway (maintenance pages, robots.txt, stubs)
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct DirectResponse {
+    /// Response status code
+    #[serde(default = "default_direct_response_status")]
+    pub status_code: u16,
+
+    /// Response body
+    #[serde(default)]
+    pub body: String,
+
+    /// Response headers (Content-Type defaults to text/plain)
+    #[serde(default)]
+    pub headers: std::collections::BTreeMap<String, String>,
+}
+
+/// Blue/green deployment: two prepared destination sets, one active
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct BlueGreenConfig {
+    /// Color currently receiving traffic
+    #[serde(default)]
+    pub active: DeploymentColor,
+
+    /// Blue destination set
+    #[serde(default)]
+    pub blue: Vec<RouteDestination>,
+
+    /// Green destination set
+    #[serde(default)]
+    pub green: Vec<RouteDestination>,
+}
+
+impl BlueGreenConfig {
+    /// Destination set for a color
+    pub fn destinations(&self, color: &DeploymentColor) -> &[RouteDestination] {
+        match color {
+            DeploymentColor::Blue => &self.blue,
+            DeploymentColor::Green => &self.green,
+        }
+    }
+}
+
+/// Method-based destination sets (e.g., read replicas and a primary)
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct ReadWriteSplit {
+    /// Methods sent to the read set
+    #[serde(default = "default_read_methods")]
+    pub read_methods: Vec<String>,
+
+    /// Destinations for read requests
+    pub read: Vec<RouteDestination>,
+
+    /// Destinations for all other requests
+    pub write: Vec<RouteDestination>,
+}
+
+impl ReadWriteSplit {
+    /// Split with the default read methods (GET, HEAD)
+    pub fn new(read: Vec<RouteDestination>, write: Vec<RouteDestination>) -> Self {
+        Self { read_methods: default_read_methods(), read, write }
+    }
+
+    /// Whether a method is sent to the read set
+    pub fn is_read(&self, method: &str) -> bool {
+        self.read_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+}
+
+/// Blue/green deployment color
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentColor {
+    /// Blue destination set
+    #[default]
+    Blue,
+    /// Green destination set
+    Green,
+}
+
+impl DeploymentColor {
+    /// The other color
+    pub fn other(&self) -> Self {
+        match self {
+            DeploymentColor::Blue => DeploymentColor::Green,
+            DeploymentColor::Green => DeploymentColor::Blue,
+        }
+    }
+
+    /// Lowercase name of the color
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentColor::Blue => "blue",
+            DeploymentColor::Green => "green",
+        }
+    }
+}
+
 /// Reference to a VPCService
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[derive(Default)]
@@ -106,10 +435,11 @@ pub struct ServiceRef {
 }
 
 /// Load balancing policy
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum LoadBalancingPolicy {
     /// Round-robin distribution
+    #[default]
     RoundRobin,
     /// Send to least-connections endpoint
     LeastConnections,
@@ -117,12 +447,46 @@ pub enum LoadBalancingPolicy {
     SourceIp,
     /// Consistent hashing (for stateful services)
     ConsistentHash,
+    /// Less busy of two random endpoints; near least-connections for large services
+    PowerOfTwoChoices,
 }
 
-impl Default for LoadBalancingPolicy {
-    fn default() -> Self {
-        LoadBalancingPolicy::RoundRobin
-    }
+/// Session affinity configuration
+///
+/// Requests carrying the same key value are consistently hashed onto the
+/// same endpoint. Requests without the key fall back to the route's
+/// load balancing policy.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct AffinityPolicy {
+    /// Where the key is read from
+    #[serde(default)]
+    pub source: AffinitySource,
+
+    /// Header name, cookie name, or gRPC metadata key (e.g., "x-session-id")
+    pub name: String,
+
+    /// Lifetime of a sticky cookie set by the gateway; unset makes it a
+    /// browser session cookie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_seconds: Option<u32>,
+}
+
+/// Source of a session affinity key
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum AffinitySource {
+    /// HTTP request header
+    #[default]
+    Header,
+    /// HTTP cookie
+    Cookie,
+    /// gRPC metadata key
+    GrpcMetadata,
+    /// Signed cookie the gateway sets on the first response, naming the
+    /// endpoint that served it
+    StickyCookie,
 }
 
 /// Retry policy
@@ -137,11 +501,50 @@ pub struct RetryPolicy {
     #[serde(default)]
     pub retry_on_status: Vec<u16>,
 
+    /// Retry on these gRPC status codes, by name (e.g. UNAVAILABLE) or number
+    #[serde(default)]
+    pub retry_on_grpc_status: Vec<String>,
+
     /// Backoff configuration
     #[serde(skip_serializing_if = "Option::is_none")]
     pub backoff: Option<BackoffConfig>,
 }
 
+/// Concurrency and queueing limits for a route
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct ConcurrencyPolicy {
+    /// Maximum requests in flight to this route
+    pub max_concurrent_requests: u32,
+
+    /// Maximum requests waiting once the limit is reached (0 sheds immediately)
+    #[serde(default)]
+    pub queue_length: u32,
+
+    /// Maximum time a request waits in the queue (ms)
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout_ms: u32,
+}
+
+/// Bandwidth limits for a route's response bodies
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct BandwidthPolicy {
+    /// Bytes per second shared by all of the route's responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_per_second: Option<u64>,
+
+    /// Bytes per second for each client connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_connection_bytes_per_second: Option<u64>,
+
+    /// Burst allowance in bytes (defaults to one second of traffic)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst_bytes: Option<u64>,
+}
+
 /// Exponential backoff configuration
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -158,6 +561,7 @@ pub struct BackoffConfig {
 
 /// CORS policy
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct CorsPolicy {
     /// Allowed origins (use "*" for all)
@@ -181,8 +585,345 @@ pub struct CorsPolicy {
     pub max_age_seconds: Option<u32>,
 }
 
+/// Redaction rules for sensitive request data in observability pipelines
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct RedactionPolicy {
+    /// Header names whose values are masked (case-insensitive)
+    #[serde(default)]
+    pub headers: Vec<String>,
+
+    /// Query parameter names whose values are masked
+    #[serde(default)]
+    pub query_params: Vec<String>,
+
+    /// JSON body field names whose values are masked at any depth
+    #[serde(default)]
+    pub json_fields: Vec<String>,
+}
+
+/// Header rewrites for a route
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Default)]
+pub struct HeaderRewritePolicy {
+    /// Changes to requests before they are forwarded
+    #[serde(default)]
+    pub request: HeaderOperations,
+
+    /// Changes to responses before they are returned
+    #[serde(default)]
+    pub response: HeaderOperations,
+}
+
+/// Header changes, applied as remove, then set, then add
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Default)]
+pub struct HeaderOperations {
+    /// Headers appended, keeping any existing values
+    #[serde(default)]
+    pub add: std::collections::BTreeMap<String, String>,
+
+    /// Headers set, replacing any existing values
+    #[serde(default)]
+    pub set: std::collections::BTreeMap<String, String>,
+
+    /// Header names removed
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Path rewrite for a route
+///
+/// Prefix rewrites apply to the route's `pathPrefix`; set at most one of
+/// `stripPrefix`, `replacePrefix`, and `regex`.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct PathRewritePolicy {
+    /// Remove the matched prefix (e.g., "/api/users/1" -> "/users/1")
+    #[serde(default)]
+    pub strip_prefix: bool,
+
+    /// Replace the matched prefix (e.g., "/v2" turns "/api/users" into "/v2/users")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replace_prefix: Option<String>,
+
+    /// Rewrite the whole path with a regular expression
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<RegexRewrite>,
+}
+
+/// Regular expression path rewrite
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Default)]
+pub struct RegexRewrite {
+    /// Pattern matched against the path
+    pub pattern: String,
+
+    /// Replacement, which may refer to capture groups (e.g., "/items/$1")
+    pub substitution: String,
+}
+
+/// Host header sent upstream
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct UpstreamHostPolicy {
+    /// Where the Host header comes from
+    #[serde(default)]
+    pub mode: UpstreamHostMode,
+
+    /// Host sent in `fixed` mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Source of the upstream Host header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpstreamHostMode {
+    /// Forward the client's Host header
+    #[default]
+    Preserve,
+    /// Use the backend's authority (host:port)
+    Backend,
+    /// Use a fixed value
+    Fixed,
+}
+
+/// Redirect answered by the gateway
+///
+/// Unset parts of the location are taken from the request. Set at most
+/// one of `path` and `replacePrefix`.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct RedirectAction {
+    /// Status code: 301, 302, 303, 307, or 308
+    #[serde(default = "default_redirect_status")]
+    pub status_code: u16,
+
+    /// Scheme to redirect to (e.g., "https")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+
+    /// Host to redirect to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Port to redirect to; the scheme's default port is omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// Replace the whole path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// Replace the matched path prefix
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replace_prefix: Option<String>,
+
+    /// Drop the query string
+    #[serde(default)]
+    pub strip_query: bool,
+}
+
+/// Faults injected into a route's requests
+///
+/// A request can be both delayed and aborted; the delay comes first.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Default)]
+pub struct FaultInjectionPolicy {
+    /// Delay requests before forwarding
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay: Option<FaultDelay>,
+
+    /// Answer requests with an error instead of forwarding them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abort: Option<FaultAbort>,
+}
+
+/// Injected delay
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct FaultDelay {
+    /// Delay (ms); with `maxDelayMs`, the shortest random delay
+    pub fixed_delay_ms: u32,
+
+    /// Longest random delay (ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_delay_ms: Option<u32>,
+
+    /// Share of requests delayed (0-100)
+    #[serde(default = "default_fault_percentage")]
+    pub percentage: f64,
+}
+
+/// Injected abort
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct FaultAbort {
+    /// Status code returned
+    pub status_code: u16,
+
+    /// Share of requests aborted (0-100)
+    #[serde(default = "default_fault_percentage")]
+    pub percentage: f64,
+}
+
+/// Response caching for a route
+///
+/// Only GET responses the backend marks fresh with Cache-Control max-age
+/// or s-maxage are stored; stale entries with an ETag or Last-Modified are
+/// revalidated with the backend.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct ResponseCachePolicy {
+    /// Cache the route's responses
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Longest time a response is served from cache (s), whatever its max-age
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ttl_seconds: Option<u32>,
+
+    /// Largest response body cached (bytes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<u64>,
+}
+
+/// Response body size limit for a route
+///
+/// Responses are buffered in the gateway before they are sent, so one
+/// backend answering with a huge body can exhaust its memory.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseLimitPolicy {
+    /// Largest response body accepted (bytes)
+    pub max_bytes: u64,
+
+    /// What happens to responses over the limit
+    #[serde(default)]
+    pub on_exceed: ResponseLimitAction,
+}
+
+/// Handling of responses over a route's size limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseLimitAction {
+    /// Answer 502 instead of the response
+    #[default]
+    Abort,
+    /// Send the first `maxBytes` of the body, marked as truncated
+    Truncate,
+}
+
+/// PII scanning of a route's responses
+///
+/// Text responses (text/*, JSON, XML) are scanned after they are decoded.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PiiScanPolicy {
+    /// What happens to responses containing PII
+    #[serde(default)]
+    pub action: PiiScanAction,
+
+    /// Kinds of PII detected; empty detects every kind
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<PiiScanKind>,
+}
+
+/// Handling of responses containing PII
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PiiScanAction {
+    /// Mask the detected values
+    #[default]
+    Mask,
+    /// Answer 502 instead of the response
+    Block,
+}
+
+/// Kind of PII a route's responses are scanned for
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum PiiScanKind {
+    /// Email addresses
+    Email,
+    /// Luhn-valid payment card numbers
+    CreditCard,
+}
+
+/// Limits on GraphQL requests to a route
+///
+/// Queries are parsed at the gateway and rejected before they reach the
+/// backend when they exceed a limit.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLPolicy {
+    /// Maximum selection set nesting depth (default: 15)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>,
+
+    /// Maximum number of fields selected, fragments expanded (default: 1000)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_complexity: Option<u32>,
+
+    /// Operation names that may be executed; empty allows every operation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_operations: Vec<String>,
+}
+
+/// ETags and conditional requests for a route
+///
+/// GET responses without validators get an ETag. Revalidations of a
+/// response that is still fresh are answered with 304 without reaching
+/// the backend.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ETagPolicy {
+    /// Generate weak (W/"...") rather than strong ETags
+    #[serde(default)]
+    pub weak: bool,
+
+    /// Longest time validators answer revalidations without the backend (s, default: 10)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_validator_age_seconds: Option<u32>,
+}
+
+/// Time window for a scheduled route
+///
+/// All configured conditions must hold for the route to be active. With no
+/// conditions the route is always active.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct RouteSchedule {
+    /// Route becomes active at this time (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_from: Option<String>,
+
+    /// Route stops being active at this time (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_until: Option<String>,
+
+    /// Recurring window start as a cron expression with seconds
+    /// (e.g., "0 0 2 * * Sun" for Sundays at 02:00 UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+
+    /// Length of each recurring window (seconds, required with `cron`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<u64>,
+}
+
 /// Status of a VPCRoute
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct VPCRouteStatus {
     /// Whether this route is ready
     #[serde(default)]
@@ -191,12 +932,52 @@ pub struct VPCRouteStatus {
     /// Number of active destination endpoints
     #[serde(default)]
     pub active_destinations: u32,
+
+    /// Whether the route's schedule currently allows traffic
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule_active: Option<bool>,
+
+    /// Next time the schedule flips between active and inactive (RFC 3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_schedule_transition: Option<String>,
+
+    /// Why the route is not ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// Spec generation every ready gateway has applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programmed_generation: Option<i64>,
+
+    /// Ready gateways running the current spec generation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programmed_gateways: Option<u32>,
+
+    /// Conditions describing the status
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
 }
 
 fn default_load_balancing() -> LoadBalancingPolicy {
     LoadBalancingPolicy::RoundRobin
 }
 
+fn default_read_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_fault_percentage() -> f64 {
+    100.0
+}
+
+fn default_direct_response_status() -> u16 {
+    200
+}
+
+fn default_redirect_status() -> u16 {
+    302
+}
+
 fn default_weight() -> u32 {
     100
 }
@@ -208,3 +989,7 @@ fn default_initial_backoff() -> u32 {
 fn default_max_backoff() -> u32 {
     10000
 }
+
+fn default_queue_timeout() -> u32 {
+    1000
+}
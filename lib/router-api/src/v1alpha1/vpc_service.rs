@@ -1,20 +1,43 @@
+use chrono::{DateTime, Utc};
+#[cfg(feature = "kube")]
 use kube::CustomResource;
+#[cfg(feature = "kube")]
+use kube::ResourceExt;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::service_binding::PodSelector;
+
+/// Annotation that puts a VPCService in planned maintenance
+///
+/// The value is the reason; "false" or an empty value is ignored.
+pub const MAINTENANCE_ANNOTATION: &str = "router.datum.net/maintenance";
+
+/// Annotation listing endpoints of a VPCService drained by an administrator
+///
+/// The value is a comma-separated list of `ip:port` endpoints, which get no
+/// new traffic until they are removed from the list.
+pub const DRAIN_ANNOTATION: &str = "router.datum.net/drained-endpoints";
+
+/// Protocols a VPCService can speak, as spelled in the spec
+pub const PROTOCOLS: [&str; 4] = ["HTTP", "HTTPS", "gRPC", "TCP"];
 
 /// VPCService represents a service running inside a Galactic VPC
 /// that should be discoverable and routable across VPCs
-#[derive(CustomResource, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
-#[kube(
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "kube", derive(CustomResource), kube(
     group = "router.datum.net",
     version = "v1alpha1",
     kind = "VPCService",
     plural = "vpcservices",
+    namespaced,
     derive = "Default",
     status = "VPCServiceStatus",
     printcolumn = r#"{"name":"Ready","type":"string","jsonPath":".status.ready"}"#,
     printcolumn = r#"{"name":"Endpoints","type":"integer","jsonPath":".status.endpointCount"}"#,
-)]
+))]
+#[serde(rename_all = "camelCase")]
 pub struct VPCServiceSpec {
     /// Reference to the VPCAttachment where this service runs
     pub vpc_attachment_ref: VPCAttachmentRef,
@@ -41,10 +64,60 @@ pub struct VPCServiceSpec {
     /// Labels for selecting this service
     #[serde(default)]
     pub labels: std::collections::BTreeMap<String, String>,
+
+    /// Planned maintenance; health checks pause and endpoints drain while set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<MaintenanceWindow>,
+
+    /// Connection pool settings for gateway connections to this service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_pool: Option<ConnectionPoolConfig>,
+
+    /// Source VPCs allowed to route to this service; unset allows every VPC
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<Visibility>,
+}
+
+#[cfg(feature = "kube")]
+impl VPCService {
+    /// Reason the service is in planned maintenance at `now`, if it is
+    ///
+    /// The spec field takes precedence over the annotation.
+    pub fn maintenance_reason(&self, now: DateTime<Utc>) -> Option<String> {
+        if let Some(window) = &self.spec.maintenance {
+            if window.is_active(now) {
+                return Some(window.reason.clone().unwrap_or_else(|| "Planned maintenance".to_string()));
+            }
+        }
+
+        self.annotations()
+            .get(MAINTENANCE_ANNOTATION)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("false"))
+            .map(|v| if v.eq_ignore_ascii_case("true") { "Planned maintenance" } else { v }.to_string())
+    }
+
+    /// Endpoints, as `ip:port`, drained with the drain annotation
+    pub fn drained_endpoints(&self) -> Vec<String> {
+        parse_drained_endpoints(self.annotations().get(DRAIN_ANNOTATION).map(String::as_str).unwrap_or_default())
+    }
+}
+
+/// Parse the value of the drain annotation
+pub fn parse_drained_endpoints(value: &str) -> Vec<String> {
+    let mut endpoints: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(str::to_string)
+        .collect();
+    endpoints.sort();
+    endpoints.dedup();
+    endpoints
 }
 
 /// Status of a VPCService
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct VPCServiceStatus {
     /// Whether this service is ready for traffic
@@ -68,18 +141,6 @@ pub struct VPCServiceStatus {
     pub conditions: Vec<Condition>,
 }
 
-impl Default for VPCServiceStatus {
-    fn default() -> Self {
-        Self {
-            ready: false,
-            endpoint_count: 0,
-            last_update_time: None,
-            endpoints: Vec::new(),
-            conditions: Vec::new(),
-        }
-    }
-}
-
 /// Reference to a VPCAttachment
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[derive(Default)]
@@ -91,6 +152,48 @@ pub struct VPCAttachmentRef {
     pub namespace: String,
 }
 
+impl VPCAttachmentRef {
+    /// Reference to an attachment in the "default" namespace
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), namespace: default_namespace() }
+    }
+}
+
+/// Source VPCs allowed to route to a VPCService or VPCRoute
+///
+/// A VPC is allowed when it is listed in `vpcs` or its labels match
+/// `vpcSelector`. A visibility with neither allows no VPC at all, and
+/// requests whose source VPC is unknown are never allowed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Visibility {
+    /// VPCs allowed by reference
+    #[serde(default)]
+    pub vpcs: Vec<VPCReference>,
+
+    /// Labels of the VPCs allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpc_selector: Option<PodSelector>,
+}
+
+/// Reference to a Galactic VPC
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VPCReference {
+    /// Name of the VPC
+    pub name: String,
+    /// Namespace of the VPC (defaults to "default")
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+}
+
+impl Visibility {
+    /// Whether the VPC `namespace/name` with `labels` is allowed
+    pub fn allows(&self, namespace: &str, name: &str, labels: &BTreeMap<String, String>) -> bool {
+        self.vpcs.iter().any(|vpc| vpc.namespace == namespace && vpc.name == name)
+            || self.vpc_selector.as_ref().is_some_and(|selector| selector.matches(labels))
+    }
+}
+
 /// Health check configuration
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -115,6 +218,90 @@ pub struct HealthCheckConfig {
     /// Consecutive successes before marking healthy
     #[serde(default = "default_healthy_threshold")]
     pub healthy_threshold: u32,
+
+    /// Warm-up requests sent to a newly healthy endpoint before it takes traffic
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<WarmupPolicy>,
+}
+
+impl HealthCheckConfig {
+    /// HTTP health check of `path` with the default interval and thresholds
+    pub fn http(path: impl Into<String>) -> Self {
+        Self {
+            http_path: Some(path.into()),
+            interval_seconds: default_health_check_interval(),
+            timeout_seconds: default_health_check_timeout(),
+            unhealthy_threshold: default_unhealthy_threshold(),
+            healthy_threshold: default_healthy_threshold(),
+            warmup: None,
+        }
+    }
+}
+
+/// Warm-up requests for newly healthy endpoints
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct WarmupPolicy {
+    /// Path requested during warm-up
+    #[serde(default = "default_warmup_path")]
+    pub path: String,
+
+    /// Number of warm-up requests
+    #[serde(default = "default_warmup_requests")]
+    pub requests: u32,
+
+    /// Timeout for each warm-up request (seconds)
+    #[serde(default = "default_health_check_timeout")]
+    pub timeout_seconds: u32,
+}
+
+/// Connection pool settings
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct ConnectionPoolConfig {
+    /// Idle connections kept per endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_idle_per_host: Option<u32>,
+
+    /// How long an idle connection is kept (seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_seconds: Option<u32>,
+
+    /// TCP keepalive interval (seconds); 0 disables keepalive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_seconds: Option<u32>,
+
+    /// Most requests in flight to the service at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+}
+
+/// Planned maintenance window
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct MaintenanceWindow {
+    /// Why the service is in maintenance (shown in status and alerts)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// When maintenance ends (RFC 3339); open-ended if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+}
+
+impl MaintenanceWindow {
+    /// Whether the window is still open at `now`
+    ///
+    /// An unparseable end time keeps the window open.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        match self.until.as_deref().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(until)) => now < until,
+            _ => true,
+        }
+    }
 }
 
 /// Service discovery configuration
@@ -133,6 +320,7 @@ pub struct DiscoveryConfig {
 
 /// Status of an endpoint
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct EndpointStatus {
     /// IP address of the endpoint (VPC IP)
@@ -145,13 +333,48 @@ pub struct EndpointStatus {
     #[serde(default = "bool::default")]
     pub ready: bool,
 
+    /// Failover tier: 0 is primary; higher tiers are standbys that only
+    /// take traffic while the tiers before them are degraded
+    #[serde(default)]
+    pub priority: u32,
+
     /// Last heartbeat/update time
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_heartbeat: Option<String>,
+
+    /// Live traffic statistics aggregated from the gateways
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<EndpointStats>,
+}
+
+/// Live traffic statistics for an endpoint
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[derive(Default)]
+pub struct EndpointStats {
+    /// Requests currently in flight across all gateways
+    pub active_connections: u64,
+
+    /// Requests completed since the gateways started
+    pub total_requests: u64,
+
+    /// Fraction of requests that failed during the last collection interval
+    pub error_rate: f64,
+
+    /// Request bytes sent to the endpoint
+    pub bytes_received: u64,
+
+    /// Response bytes received from the endpoint
+    pub bytes_sent: u64,
+
+    /// When the statistics were collected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_update_time: Option<String>,
 }
 
 /// Condition for VPCService status
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 #[derive(Default)]
 pub struct Condition {
     /// Type of condition
@@ -198,6 +421,14 @@ fn default_healthy_threshold() -> u32 {
     2
 }
 
+fn default_warmup_path() -> String {
+    "/".to_string()
+}
+
+fn default_warmup_requests() -> u32 {
+    10
+}
+
 fn default_discovery_method() -> String {
     "manual".to_string()
 }
@@ -0,0 +1,152 @@
+//! Serde round-trip and golden-file tests for the CRD types
+//!
+//! Each fixture in `tests/fixtures` is a resource as users write it. It is
+//! deserialized into its type and serialized to JSON, which must match
+//! `tests/golden/<fixture>.json` and must carry every field the fixture
+//! set; reading the JSON back must give the same JSON again. A failure
+//! means the wire format changed: a renamed field, a new default, a field
+//! silently dropped. If the change is intended, regenerate the golden
+//! files with `UPDATE_GOLDEN=1 cargo test -p router-api --test golden`.
+//!
+//! The examples in `manifests/examples` are checked for dropped fields too.
+
+#![cfg(feature = "kube")]
+
+use router_api::{RouterConfig, RouterGateway, ServiceBinding, VPCEgress, VPCIngress, VPCRoute, VPCService};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+fn crate_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Typed JSON for a YAML resource, failing if the type drops any field
+fn typed_json<K: DeserializeOwned + Serialize>(yaml: serde_yaml::Value, source: &str) -> Value {
+    let input: Value = serde_json::to_value(&yaml).unwrap();
+    let resource: K = serde_yaml::from_value(yaml).unwrap_or_else(|e| panic!("{}: {}", source, e));
+    let output = serde_json::to_value(&resource).unwrap();
+    assert_contains(&output, &input, source, "");
+    output
+}
+
+/// Assert that every field and value of `expected` appears in `actual`
+fn assert_contains(actual: &Value, expected: &Value, source: &str, path: &str) {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => {
+            for (key, value) in expected {
+                let field = format!("{}.{}", path, key);
+                let Some(found) = actual.get(key) else {
+                    panic!("{}: {} was dropped on deserialization", source, field);
+                };
+                assert_contains(found, value, source, &field);
+            }
+        }
+        (Value::Array(actual), Value::Array(expected)) => {
+            assert_eq!(actual.len(), expected.len(), "{}: {} changed length", source, path);
+            for (i, (found, value)) in actual.iter().zip(expected).enumerate() {
+                assert_contains(found, value, source, &format!("{}[{}]", path, i));
+            }
+        }
+        (Value::Number(actual), Value::Number(expected)) => {
+            assert_eq!(actual.as_f64(), expected.as_f64(), "{}: {} changed", source, path);
+        }
+        _ => assert_eq!(actual, expected, "{}: {} changed", source, path),
+    }
+}
+
+/// Check a fixture against its golden file and round-trip it
+fn check_fixture<K: DeserializeOwned + Serialize>(name: &str) {
+    let fixture = crate_dir().join("tests/fixtures").join(format!("{}.yaml", name));
+    let golden = crate_dir().join("tests/golden").join(format!("{}.json", name));
+
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(&fixture).unwrap()).unwrap();
+    let json = typed_json::<K>(yaml, name);
+
+    let reread: K = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&reread).unwrap(), json, "{}: round trip changed the resource", name);
+
+    // Golden files list keys sorted, whether or not another crate in the
+    // build turns on serde_json's preserve_order
+    let mut sorted = json;
+    sorted.sort_all_objects();
+    let pretty = serde_json::to_string_pretty(&sorted).unwrap() + "\n";
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden, pretty).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("{}: {} (set UPDATE_GOLDEN=1 to create it)", golden.display(), e));
+    assert_eq!(pretty, expected, "{}: serialized form differs from {}", name, golden.display());
+}
+
+#[test]
+fn test_vpc_service_golden() {
+    check_fixture::<VPCService>("vpcservice");
+}
+
+#[test]
+fn test_vpc_route_golden() {
+    check_fixture::<VPCRoute>("vpcroute");
+}
+
+#[test]
+fn test_vpc_ingress_golden() {
+    check_fixture::<VPCIngress>("vpcingress");
+}
+
+#[test]
+fn test_service_binding_golden() {
+    check_fixture::<ServiceBinding>("servicebinding");
+}
+
+#[test]
+fn test_vpc_egress_golden() {
+    check_fixture::<VPCEgress>("vpcegress");
+}
+
+#[test]
+fn test_router_config_golden() {
+    check_fixture::<RouterConfig>("routerconfig");
+}
+
+#[test]
+fn test_router_gateway_golden() {
+    check_fixture::<RouterGateway>("routergateway");
+}
+
+#[test]
+fn test_manifest_examples() {
+    let examples = crate_dir().join("../../manifests/examples");
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&examples).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "yaml") {
+            checked += check_example(&path);
+        }
+    }
+    assert!(checked > 0, "no examples found in {}", examples.display());
+}
+
+/// Check every router resource in a multi-document YAML file
+fn check_example(path: &Path) -> usize {
+    let text = std::fs::read_to_string(path).unwrap();
+    let mut checked = 0;
+    for document in serde_yaml::Deserializer::from_str(&text) {
+        let yaml = serde_yaml::Value::deserialize(document).unwrap();
+        let source = format!("{} ({})", path.display(), yaml["metadata"]["name"].as_str().unwrap_or("?"));
+        match yaml["kind"].as_str() {
+            Some("VPCService") => typed_json::<VPCService>(yaml, &source),
+            Some("VPCRoute") => typed_json::<VPCRoute>(yaml, &source),
+            Some("VPCIngress") => typed_json::<VPCIngress>(yaml, &source),
+            Some("ServiceBinding") => typed_json::<ServiceBinding>(yaml, &source),
+            Some("VPCEgress") => typed_json::<VPCEgress>(yaml, &source),
+            Some("RouterConfig") => typed_json::<RouterConfig>(yaml, &source),
+            Some("RouterGateway") => typed_json::<RouterGateway>(yaml, &source),
+            _ => continue,
+        };
+        checked += 1;
+    }
+    checked
+}
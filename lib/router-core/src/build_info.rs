@@ -0,0 +1,103 @@
+//! Build information embedded at compile time
+//!
+//! Each binary's build script (`build/build_info.rs` at the workspace root)
+//! sets the `ROUTER_BUILD_*` variables read by [`build_info!`], so the values
+//! describe the binary that expands the macro rather than this library.
+//! Binaries export them as a `build_info` metric and at `/version` to tie
+//! fleet behavior to specific builds.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Version, source, and toolchain a binary was built from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: &'static str,
+    /// Commit the binary was built from, or "unknown" outside a git checkout
+    pub git_sha: &'static str,
+    /// `rustc --version` of the compiler
+    pub rustc: &'static str,
+    /// When the build script ran, as RFC 3339 in UTC
+    pub build_date: &'static str,
+    /// Cargo features enabled on the binary, comma separated
+    pub features: &'static str,
+}
+
+impl BuildInfo {
+    /// Label names and values for a `build_info` metric
+    pub fn labels(&self) -> [(&'static str, &'static str); 5] {
+        [
+            ("version", self.version),
+            ("git_sha", self.git_sha),
+            ("rustc", self.rustc),
+            ("build_date", self.build_date),
+            ("features", self.features),
+        ]
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (git {}, built {} with {}", self.version, self.git_sha, self.build_date, self.rustc)?;
+        if !self.features.is_empty() {
+            write!(f, ", features {}", self.features)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The [`BuildInfo`] of the binary expanding the macro
+///
+/// The binary must use the shared build script, which sets the variables.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::build_info::BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("ROUTER_BUILD_GIT_SHA"),
+            rustc: env!("ROUTER_BUILD_RUSTC"),
+            build_date: env!("ROUTER_BUILD_DATE"),
+            features: env!("ROUTER_BUILD_FEATURES"),
+        }
+    };
+}
+
+/// The `--version` text of the binary expanding the macro, for clap's
+/// `long_version`
+#[macro_export]
+macro_rules! long_version {
+    () => {
+        concat!(
+            env!("CARGO_PKG_VERSION"),
+            "\ngit: ",
+            env!("ROUTER_BUILD_GIT_SHA"),
+            "\nrustc: ",
+            env!("ROUTER_BUILD_RUSTC"),
+            "\nbuilt: ",
+            env!("ROUTER_BUILD_DATE"),
+            "\nfeatures: ",
+            env!("ROUTER_BUILD_FEATURES"),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let mut info = BuildInfo {
+            version: "0.1.0",
+            git_sha: "0123456789ab",
+            rustc: "rustc 1.82.0",
+            build_date: "2024-11-01T00:00:00Z",
+            features: "",
+        };
+        assert_eq!(info.to_string(), "0.1.0 (git 0123456789ab, built 2024-11-01T00:00:00Z with rustc 1.82.0)");
+        info.features = "console";
+        assert!(info.to_string().ends_with(", features console)"));
+        assert_eq!(info.labels()[1], ("git_sha", "0123456789ab"));
+    }
+}
@@ -0,0 +1,167 @@
+//! Command-line flags shared by the binaries
+//!
+//! Each binary flattens these into its own clap parser. Every flag can also
+//! be set through the environment variable named in its `--help` entry, so
+//! existing deployments configured through the environment keep working.
+
+use clap::{Args, ValueEnum};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[cfg(feature = "kube")]
+use crate::kube_client::KubeClientConfig;
+#[cfg(feature = "kube")]
+use std::path::PathBuf;
+#[cfg(feature = "kube")]
+use std::time::Duration;
+
+/// Format of log lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors
+    Json,
+}
+
+/// Logging flags
+#[derive(Clone, Debug, Default, Args)]
+pub struct LogArgs {
+    /// Format of log lines; levels are set with RUST_LOG
+    #[arg(long, env = "ROUTER_LOG_FORMAT", value_name = "FORMAT", value_enum, default_value_t)]
+    pub log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// Layer writing log lines in the selected format
+    pub fn fmt_layer<S>(&self) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        match self.log_format {
+            LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+            LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+        }
+    }
+
+    /// Log in the selected format, filtered by RUST_LOG
+    pub fn init(&self) {
+        tracing_subscriber::registry()
+            .with(self.fmt_layer().with_filter(EnvFilter::from_default_env()))
+            .init();
+    }
+}
+
+/// Kubernetes API client flags
+#[cfg(feature = "kube")]
+#[derive(Clone, Debug, Args)]
+pub struct KubeArgs {
+    /// Kubeconfig file [default: KUBECONFIG, the in-cluster service account, or ~/.kube/config]
+    #[arg(long, value_name = "PATH")]
+    pub kubeconfig: Option<PathBuf>,
+    /// Kubeconfig context to use [default: the current context]
+    #[arg(long, env = "ROUTER_KUBE_CONTEXT", value_name = "NAME")]
+    pub kube_context: Option<String>,
+    /// Sustained API requests per second
+    #[arg(long, env = "ROUTER_KUBE_QPS", value_name = "QPS", default_value_t = 20.0, value_parser = positive_rate)]
+    pub kube_qps: f64,
+    /// API requests allowed at once after an idle period
+    #[arg(long, env = "ROUTER_KUBE_BURST", value_name = "REQUESTS", default_value_t = 30,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub kube_burst: u32,
+    /// Most API requests in flight, watches excluded [default: unlimited]
+    #[arg(long, env = "ROUTER_KUBE_MAX_IN_FLIGHT", value_name = "REQUESTS",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub kube_max_in_flight: Option<u64>,
+    /// Seconds to wait connecting to the API server [default: the kubeconfig's]
+    #[arg(long, env = "ROUTER_KUBE_CONNECT_TIMEOUT_SECS", value_name = "SECS")]
+    pub kube_connect_timeout_secs: Option<u64>,
+    /// Longest wait in seconds for data from the API server [default: the kubeconfig's]
+    #[arg(long, env = "ROUTER_KUBE_READ_TIMEOUT_SECS", value_name = "SECS")]
+    pub kube_read_timeout_secs: Option<u64>,
+    /// HTTP proxy for the API server [default: the kubeconfig's]
+    #[arg(long, env = "ROUTER_KUBE_PROXY_URL", value_name = "URL")]
+    pub kube_proxy_url: Option<String>,
+}
+
+#[cfg(feature = "kube")]
+impl KubeArgs {
+    /// Client settings for the binary `component`
+    pub fn client_config(&self, component: &str) -> KubeClientConfig {
+        KubeClientConfig {
+            qps: self.kube_qps,
+            burst: self.kube_burst,
+            max_in_flight: self.kube_max_in_flight.map(|n| n as usize),
+            connect_timeout: self.kube_connect_timeout_secs.map(Duration::from_secs),
+            read_timeout: self.kube_read_timeout_secs.map(Duration::from_secs),
+            proxy_url: self.kube_proxy_url.clone(),
+            kubeconfig: self.kubeconfig.clone(),
+            context: self.kube_context.clone(),
+            ..KubeClientConfig::new(component)
+        }
+    }
+}
+
+/// Parse a rate that must be above zero, for flags such as `--kube-qps`
+pub fn positive_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(all(test, feature = "kube"))]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        kube: KubeArgs,
+        #[command(flatten)]
+        log: LogArgs,
+    }
+
+    #[test]
+    fn test_kube_args() {
+        let cli = Cli::try_parse_from(["test"]).unwrap();
+        assert_eq!(cli.log.log_format, LogFormat::Text);
+        assert_eq!(cli.kube.client_config("router-controller"), KubeClientConfig::new("router-controller"));
+
+        let cli = Cli::try_parse_from([
+            "test",
+            "--kubeconfig",
+            "/etc/kube/config",
+            "--kube-context",
+            "staging",
+            "--kube-qps",
+            "2.5",
+            "--kube-max-in-flight",
+            "8",
+            "--kube-read-timeout-secs",
+            "330",
+            "--log-format",
+            "json",
+        ])
+        .unwrap();
+        assert_eq!(cli.log.log_format, LogFormat::Json);
+        let config = cli.kube.client_config("router-gateway");
+        assert_eq!(config.kubeconfig, Some(PathBuf::from("/etc/kube/config")));
+        assert_eq!(config.context.as_deref(), Some("staging"));
+        assert_eq!(config.qps, 2.5);
+        assert_eq!(config.burst, 30);
+        assert_eq!(config.max_in_flight, Some(8));
+        assert_eq!(config.read_timeout, Some(Duration::from_secs(330)));
+
+        // Errors name the flag
+        for args in [["test", "--kube-qps", "0"], ["test", "--kube-burst", "0"], ["test", "--log-format", "xml"]] {
+            let error = Cli::try_parse_from(args).unwrap_err().to_string();
+            assert!(error.contains(args[1]), "{}", error);
+        }
+    }
+}
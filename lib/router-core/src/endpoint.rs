@@ -6,4 +6,8 @@ pub struct Endpoint {
     pub ip: String,
     pub port: u16,
     pub ready: bool,
+    /// Failover tier: 0 is the primary tier, higher tiers are backups that
+    /// only take traffic while the tiers before them are degraded
+    #[serde(default)]
+    pub priority: u32,
 }
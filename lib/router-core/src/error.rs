@@ -13,6 +13,7 @@ pub enum CoreError {
     #[error("Invalid service configuration: {0}")]
     InvalidConfiguration(String),
 
+    #[cfg(feature = "kube")]
     #[error("Kubernetes error: {0}")]
     KubernetesError(#[from] kube::error::Error),
 
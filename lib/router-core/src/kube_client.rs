@@ -0,0 +1,351 @@
+//! Kubernetes API clients shared by the controllers, discovery, and gateway
+//!
+//! Every client is built from a [`KubeClientConfig`] with the binary's user
+//! agent, kubeconfig, timeouts, and proxy; binaries fill one in from their
+//! `--kube-*` flags (see [`crate::cli::KubeArgs`]). Requests pass through [`ApiLayer`], which
+//! holds them to a QPS and burst budget shared by every caller of the
+//! client, optionally caps requests in flight, and reports each request to
+//! an [`ApiObserver`] so binaries can export them as metrics. Many
+//! replicas watching and writing at once would otherwise overwhelm the API
+//! server.
+
+use http::{Method, Request, Response, Uri};
+use kube::client::ClientBuilder;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::Client;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+use tower::{BoxError, Layer, Service};
+use tracing::debug;
+
+/// Settings for a Kubernetes API client
+#[derive(Clone, Debug, PartialEq)]
+pub struct KubeClientConfig {
+    /// User-Agent sent with every request, identifying the binary
+    pub user_agent: String,
+    /// Sustained requests per second
+    pub qps: f64,
+    /// Requests allowed at once after an idle period
+    pub burst: u32,
+    /// Most requests in flight at once, watches excluded; unlimited when unset
+    pub max_in_flight: Option<usize>,
+    /// Time to connect to the API server; the kubeconfig's when unset
+    pub connect_timeout: Option<Duration>,
+    /// Longest wait for data on a connection; the kubeconfig's when unset
+    ///
+    /// Watches stay open for minutes without data, so this must exceed
+    /// the watch timeout.
+    pub read_timeout: Option<Duration>,
+    /// HTTP proxy to reach the API server through; the kubeconfig's when unset
+    pub proxy_url: Option<String>,
+    /// Kubeconfig file to read; inferred from `KUBECONFIG`, the in-cluster
+    /// service account, or `~/.kube/config` when unset
+    pub kubeconfig: Option<PathBuf>,
+    /// Kubeconfig context to use; the current context when unset
+    pub context: Option<String>,
+}
+
+impl KubeClientConfig {
+    /// Default settings for the binary `component`
+    pub fn new(component: &str) -> Self {
+        Self {
+            user_agent: format!("{}/{}", component, env!("CARGO_PKG_VERSION")),
+            qps: 20.0,
+            burst: 30,
+            max_in_flight: None,
+            connect_timeout: None,
+            read_timeout: None,
+            proxy_url: None,
+            kubeconfig: None,
+            context: None,
+        }
+    }
+
+    /// Build a client with these settings against the configured cluster
+    pub async fn build(&self, observer: Arc<dyn ApiObserver>) -> anyhow::Result<Client> {
+        let options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..Default::default()
+        };
+        let mut config = match (&self.kubeconfig, &self.context) {
+            (Some(path), _) => kube::Config::from_custom_kubeconfig(Kubeconfig::read_from(path)?, &options).await?,
+            (None, Some(_)) => kube::Config::from_kubeconfig(&options).await?,
+            (None, None) => kube::Config::infer().await?,
+        };
+        config.headers.push((http::header::USER_AGENT, self.user_agent.parse()?));
+        if self.connect_timeout.is_some() {
+            config.connect_timeout = self.connect_timeout;
+        }
+        if self.read_timeout.is_some() {
+            config.read_timeout = self.read_timeout;
+        }
+        if let Some(proxy_url) = &self.proxy_url {
+            config.proxy_url = Some(proxy_url.parse()?);
+        }
+
+        debug!(
+            "Kubernetes client {} limited to {} requests/s (burst {})",
+            self.user_agent, self.qps, self.burst
+        );
+        Ok(ClientBuilder::try_from(config)?.with_layer(&ApiLayer::new(self, observer)).build())
+    }
+}
+
+/// Receives Kubernetes API request events
+pub trait ApiObserver: Send + Sync {
+    /// A request was sent
+    fn request_started(&self, verb: &str, resource: &str);
+
+    /// A request finished with `code`, its response status or "error" when
+    /// no response arrived
+    fn request_finished(&self, verb: &str, resource: &str, code: &str, duration: Duration);
+
+    /// A request waited `wait` for the rate limit before it was sent
+    fn throttled(&self, wait: Duration);
+}
+
+/// Observer that only logs
+pub struct LoggingApiObserver;
+
+impl ApiObserver for LoggingApiObserver {
+    fn request_started(&self, _verb: &str, _resource: &str) {}
+
+    fn request_finished(&self, verb: &str, resource: &str, code: &str, duration: Duration) {
+        debug!("Kubernetes API {} {}: {} in {:?}", verb, resource, code, duration);
+    }
+
+    fn throttled(&self, wait: Duration) {
+        debug!("Kubernetes API request rate limited for {:?}", wait);
+    }
+}
+
+/// Verb and resource of an API request, as Kubernetes names them
+///
+/// The resource includes any subresource (`vpcroutes/status`); requests
+/// outside the resource API, such as discovery, have an empty resource.
+pub fn describe_request(method: &Method, uri: &Uri) -> (&'static str, String) {
+    let segments: Vec<&str> = uri.path().split('/').filter(|s| !s.is_empty()).collect();
+    let mut rest = match segments.first() {
+        Some(&"api") => segments.get(2..).unwrap_or_default(),
+        Some(&"apis") => segments.get(3..).unwrap_or_default(),
+        _ => &[],
+    };
+    if rest.len() >= 3 && rest[0] == "namespaces" {
+        rest = &rest[2..];
+    }
+    let resource = match rest {
+        [resource, _, subresource, ..] => format!("{}/{}", resource, subresource),
+        [resource, ..] => resource.to_string(),
+        [] => String::new(),
+    };
+    let named = rest.len() >= 2;
+    let watch = uri
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair == "watch=true" || pair == "watch=1"));
+
+    let verb = match *method {
+        Method::GET if watch => "watch",
+        Method::GET if named => "get",
+        Method::GET => "list",
+        Method::POST => "create",
+        Method::PUT => "update",
+        Method::PATCH => "patch",
+        Method::DELETE if named => "delete",
+        Method::DELETE => "deletecollection",
+        _ => "other",
+    };
+    (verb, resource)
+}
+
+/// Request budget shared by every clone of a client
+struct ApiLimits {
+    interval: Duration,
+    burst: u32,
+    /// Earliest time the next request may be sent
+    next_slot: Mutex<Instant>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl ApiLimits {
+    /// Wait for a request slot, returning how long the wait was
+    async fn acquire(&self) -> Duration {
+        let wait = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            // Unused slots accumulate up to the burst size
+            let earliest = now.checked_sub(self.interval * (self.burst - 1)).unwrap_or(now);
+            let slot = (*next_slot).max(earliest);
+            *next_slot = slot + self.interval;
+            slot.saturating_duration_since(now)
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        wait
+    }
+}
+
+/// Layer rate limiting and observing Kubernetes API requests
+#[derive(Clone)]
+pub struct ApiLayer {
+    limits: Arc<ApiLimits>,
+    observer: Arc<dyn ApiObserver>,
+}
+
+impl ApiLayer {
+    /// Create a layer enforcing the limits in `config`
+    pub fn new(config: &KubeClientConfig, observer: Arc<dyn ApiObserver>) -> Self {
+        let interval = Duration::from_secs_f64(1.0 / config.qps.max(0.001));
+        let burst = config.burst.max(1);
+        // Start with the whole burst available
+        let now = Instant::now();
+        let limits = ApiLimits {
+            interval,
+            burst,
+            next_slot: Mutex::new(now.checked_sub(interval * (burst - 1)).unwrap_or(now)),
+            in_flight: config.max_in_flight.map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        };
+        Self { limits: Arc::new(limits), observer }
+    }
+}
+
+impl<S> Layer<S> for ApiLayer {
+    type Service = ApiService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiService { inner, layer: self.clone() }
+    }
+}
+
+/// Service produced by [`ApiLayer`]
+pub struct ApiService<S> {
+    inner: S,
+    layer: ApiLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ApiService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    /// Wait for the rate limit, then send the request
+    ///
+    /// The inner stack doesn't send anything until its future is polled,
+    /// so the request leaves only once it has a slot.
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let (verb, resource) = describe_request(request.method(), request.uri());
+        let response = self.inner.call(request);
+        let ApiLayer { limits, observer } = self.layer.clone();
+        Box::pin(async move {
+            let wait = limits.acquire().await;
+            if !wait.is_zero() {
+                observer.throttled(wait);
+            }
+            let _permit = match (&limits.in_flight, verb) {
+                (Some(in_flight), verb) if verb != "watch" => Some(in_flight.clone().acquire_owned().await?),
+                _ => None,
+            };
+
+            observer.request_started(verb, &resource);
+            let started = Instant::now();
+            let result = response.await.map_err(Into::into);
+            let code = match &result {
+                Ok(response) => response.status().as_u16().to_string(),
+                Err(_) => "error".to_string(),
+            };
+            observer.request_finished(verb, &resource, &code, started.elapsed());
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    #[test]
+    fn test_describe_request() {
+        let describe = |method: Method, uri: &str| describe_request(&method, &uri.parse().unwrap());
+        assert_eq!(
+            describe(Method::GET, "/apis/networking.datumapis.com/v1alpha1/vpcroutes?watch=true&timeoutSeconds=290"),
+            ("watch", "vpcroutes".to_string())
+        );
+        assert_eq!(
+            describe(Method::GET, "/apis/networking.datumapis.com/v1alpha1/namespaces/default/vpcroutes"),
+            ("list", "vpcroutes".to_string())
+        );
+        assert_eq!(
+            describe(Method::PATCH, "/apis/networking.datumapis.com/v1alpha1/namespaces/default/vpcroutes/web/status"),
+            ("patch", "vpcroutes/status".to_string())
+        );
+        assert_eq!(describe(Method::GET, "/api/v1/namespaces/default"), ("get", "namespaces".to_string()));
+        assert_eq!(describe(Method::DELETE, "/api/v1/namespaces/default/pods"), ("deletecollection", "pods".to_string()));
+        assert_eq!(describe(Method::GET, "/version"), ("list", String::new()));
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        finished: StdMutex<Vec<(String, String, String)>>,
+        throttled: StdMutex<Vec<Duration>>,
+    }
+
+    impl ApiObserver for Recorder {
+        fn request_started(&self, _verb: &str, _resource: &str) {}
+
+        fn request_finished(&self, verb: &str, resource: &str, code: &str, _duration: Duration) {
+            self.finished
+                .lock()
+                .unwrap()
+                .push((verb.to_string(), resource.to_string(), code.to_string()));
+        }
+
+        fn throttled(&self, wait: Duration) {
+            self.throttled.lock().unwrap().push(wait);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_rate_limited_and_observed() {
+        let recorder = Arc::new(Recorder::default());
+        let config = KubeClientConfig {
+            qps: 10.0,
+            burst: 2,
+            ..KubeClientConfig::new("test")
+        };
+        let mut service = ApiLayer::new(&config, recorder.clone()).layer(tower::service_fn(
+            |request: Request<()>| async move {
+                let status = if request.uri().path().ends_with("/missing") { 404 } else { 200 };
+                Ok::<_, BoxError>(Response::builder().status(status).body(()).unwrap())
+            },
+        ));
+
+        let started = Instant::now();
+        for path in ["/api/v1/namespaces/default/pods", "/api/v1/namespaces/default/pods/missing", "/api/v1/nodes"] {
+            let request = Request::get(path).body(()).unwrap();
+            service.call(request).await.unwrap();
+        }
+
+        // The burst goes out at once; the third request waits a slot
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(recorder.throttled.lock().unwrap().len(), 1);
+        let finished = recorder.finished.lock().unwrap();
+        assert_eq!(finished[1], ("get".to_string(), "pods".to_string(), "404".to_string()));
+        assert_eq!(finished[2], ("list".to_string(), "nodes".to_string(), "200".to_string()));
+    }
+}
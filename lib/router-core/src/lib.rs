@@ -4,11 +4,36 @@
 //! - Service registry for managing VPCServices and their endpoints
 //! - Endpoint discovery and synchronization
 //! - Traffic policy engine
+//! - Scheduled route evaluation
+//! - Per-endpoint traffic counters
+//! - Source VPC identity and visibility checks
+//! - Resilient resource watches (`kube` feature)
+//! - Rate-limited, instrumented Kubernetes API clients (`kube` feature)
+//! - Command-line flags shared by the binaries (`cli` feature)
+//! - Build information embedded in the binaries
 
+pub mod build_info;
 pub mod registry;
 pub mod endpoint;
 pub mod error;
+pub mod schedule;
+pub mod source;
+pub mod stats;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "kube")]
+pub mod kube_client;
+#[cfg(feature = "kube")]
+pub mod watch;
 
+pub use build_info::BuildInfo;
 pub use registry::ServiceRegistry;
 pub use endpoint::Endpoint;
 pub use error::{CoreError, Result};
+pub use schedule::ScheduleState;
+pub use source::{SourceMap, SourceVpc};
+pub use stats::{EndpointCounters, EndpointCounterMap};
+#[cfg(feature = "kube")]
+pub use kube_client::{ApiObserver, KubeClientConfig};
+#[cfg(feature = "kube")]
+pub use watch::{WatchConfig, WatchObserver};
@@ -1,7 +1,8 @@
 //! Service registry for managing VPCServices and endpoints
 
 use crate::{Endpoint, Result, CoreError};
-use std::collections::HashMap;
+use router_api::v1alpha1::vpc_service::{HealthCheckConfig, VPCReference, Visibility};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::debug;
@@ -21,6 +22,32 @@ pub struct ServiceInfo {
     pub port: u16,
     pub protocol: String,
     pub endpoints: Vec<Endpoint>,
+    /// Reason the service is in planned maintenance, if it is
+    pub maintenance: Option<String>,
+    /// Source VPCs allowed to route to the service; unset allows every VPC
+    pub visibility: Option<Visibility>,
+    /// VPC the service's endpoints are in, if known
+    pub vpc: Option<VPCReference>,
+    /// Health check settings from the service's spec; unset uses the
+    /// checker's defaults
+    pub health_check: Option<HealthCheckConfig>,
+    /// Endpoints, as `ip:port`, drained by an administrator; they are
+    /// reported not ready whatever their health
+    pub drained: HashSet<String>,
+}
+
+impl ServiceInfo {
+    /// The service with its drained endpoints marked not ready
+    fn with_drains(mut self) -> Self {
+        if !self.drained.is_empty() {
+            for endpoint in &mut self.endpoints {
+                if self.drained.contains(&format!("{}:{}", endpoint.ip, endpoint.port)) {
+                    endpoint.ready = false;
+                }
+            }
+        }
+        self
+    }
 }
 
 impl ServiceRegistry {
@@ -51,6 +78,11 @@ impl ServiceRegistry {
                 port,
                 protocol,
                 endpoints,
+                maintenance: None,
+                visibility: None,
+                vpc: None,
+                health_check: None,
+                drained: HashSet::new(),
             },
         );
 
@@ -58,10 +90,45 @@ impl ServiceRegistry {
         Ok(())
     }
 
+    /// Register a service, or update the port, protocol and endpoints of a
+    /// registered one
+    ///
+    /// Unlike `register_service`, an existing service keeps its other
+    /// settings, and endpoints it already had keep their readiness, which
+    /// health checks own from then on.
+    pub async fn sync_service(
+        &self,
+        namespace: String,
+        name: String,
+        port: u16,
+        protocol: String,
+        mut endpoints: Vec<Endpoint>,
+    ) -> Result<()> {
+        let service_id = format!("{}/{}", namespace, name);
+
+        let mut services = self.services.write().await;
+        let Some(service) = services.get_mut(&service_id) else {
+            drop(services);
+            return self.register_service(namespace, name, port, protocol, endpoints).await;
+        };
+        for endpoint in &mut endpoints {
+            if let Some(known) = service.endpoints.iter().find(|e| e.ip == endpoint.ip && e.port == endpoint.port) {
+                endpoint.ready = known.ready;
+            }
+        }
+        service.port = port;
+        service.protocol = protocol;
+        service.endpoints = endpoints;
+        debug!("Synced service: {}", service_id);
+        Ok(())
+    }
+
     /// Get service information
+    ///
+    /// Drained endpoints are reported not ready.
     pub async fn get_service(&self, service_id: &str) -> Result<ServiceInfo> {
         let services = self.services.read().await;
-        services.get(service_id).cloned().ok_or_else(|| {
+        services.get(service_id).cloned().map(ServiceInfo::with_drains).ok_or_else(|| {
             CoreError::ServiceNotFound(service_id.to_string())
         })
     }
@@ -88,10 +155,98 @@ impl ServiceRegistry {
         }
     }
 
-    /// List all services
+    /// Set the readiness of one endpoint of a service
+    pub async fn set_endpoint_ready(&self, service_id: &str, ip: &str, port: u16, ready: bool) -> Result<()> {
+        let mut services = self.services.write().await;
+        let service = services
+            .get_mut(service_id)
+            .ok_or_else(|| CoreError::ServiceNotFound(service_id.to_string()))?;
+        let endpoint = service
+            .endpoints
+            .iter_mut()
+            .find(|e| e.ip == ip && e.port == port)
+            .ok_or_else(|| CoreError::EndpointNotFound(format!("{}:{}", ip, port)))?;
+        endpoint.ready = ready;
+        debug!("Endpoint {}:{} of {} ready: {}", ip, port, service_id, ready);
+        Ok(())
+    }
+
+    /// Put a service in planned maintenance, or take it out with `None`
+    ///
+    /// Endpoints of a service in maintenance are drained: they keep their
+    /// readiness but receive no new traffic.
+    pub async fn set_maintenance(&self, service_id: &str, reason: Option<String>) -> Result<()> {
+        let mut services = self.services.write().await;
+        if let Some(service) = services.get_mut(service_id) {
+            if service.maintenance != reason {
+                debug!("Maintenance for service {}: {:?}", service_id, reason);
+            }
+            service.maintenance = reason;
+            Ok(())
+        } else {
+            Err(CoreError::ServiceNotFound(service_id.to_string()))
+        }
+    }
+
+    /// Restrict which source VPCs may route to a service, or lift the
+    /// restriction with `None`
+    pub async fn set_visibility(&self, service_id: &str, visibility: Option<Visibility>) -> Result<()> {
+        let mut services = self.services.write().await;
+        let service = services
+            .get_mut(service_id)
+            .ok_or_else(|| CoreError::ServiceNotFound(service_id.to_string()))?;
+        service.visibility = visibility;
+        Ok(())
+    }
+
+    /// Record the VPC a service's endpoints are in, or forget it with `None`
+    pub async fn set_vpc(&self, service_id: &str, vpc: Option<VPCReference>) -> Result<()> {
+        let mut services = self.services.write().await;
+        let service = services
+            .get_mut(service_id)
+            .ok_or_else(|| CoreError::ServiceNotFound(service_id.to_string()))?;
+        service.vpc = vpc;
+        Ok(())
+    }
+
+    /// Set how a service's endpoints are health checked, or return it to
+    /// the checker's defaults with `None`
+    pub async fn set_health_check(&self, service_id: &str, health_check: Option<HealthCheckConfig>) -> Result<()> {
+        let mut services = self.services.write().await;
+        let service = services
+            .get_mut(service_id)
+            .ok_or_else(|| CoreError::ServiceNotFound(service_id.to_string()))?;
+        service.health_check = health_check;
+        Ok(())
+    }
+
+    /// Drain the listed endpoints (`ip:port`) of a service, re-enabling any
+    /// others that were drained
+    ///
+    /// Drained endpoints keep their health but get no new traffic, for
+    /// maintenance of the nodes they run on.
+    pub async fn set_drained(&self, service_id: &str, drained: HashSet<String>) -> Result<()> {
+        let mut services = self.services.write().await;
+        let service = services
+            .get_mut(service_id)
+            .ok_or_else(|| CoreError::ServiceNotFound(service_id.to_string()))?;
+        if service.drained != drained {
+            debug!("Drained endpoints of service {}: {:?}", service_id, drained);
+        }
+        service.drained = drained;
+        Ok(())
+    }
+
+    /// Check whether a service is in planned maintenance
+    pub async fn in_maintenance(&self, service_id: &str) -> bool {
+        let services = self.services.read().await;
+        services.get(service_id).is_some_and(|s| s.maintenance.is_some())
+    }
+
+    /// List all services, with drained endpoints reported not ready
     pub async fn list_services(&self) -> Result<Vec<ServiceInfo>> {
         let services = self.services.read().await;
-        Ok(services.values().cloned().collect())
+        Ok(services.values().cloned().map(ServiceInfo::with_drains).collect())
     }
 
     /// Deregister a service
@@ -102,6 +257,20 @@ impl ServiceRegistry {
         Ok(())
     }
 
+    /// Remove services whose id is not in `live`
+    ///
+    /// Returns the ids removed. Used by the controller's periodic resync to
+    /// drop entries whose VPCService was deleted without a delete event.
+    pub async fn prune_services(&self, live: &HashSet<String>) -> Vec<String> {
+        let mut services = self.services.write().await;
+        let orphans: Vec<String> = services.keys().filter(|id| !live.contains(*id)).cloned().collect();
+        for service_id in &orphans {
+            services.remove(service_id);
+            debug!("Pruned orphaned service: {}", service_id);
+        }
+        orphans
+    }
+
     /// Get count of registered services
     pub async fn service_count(&self) -> usize {
         let services = self.services.read().await;
@@ -114,3 +283,70 @@ impl Default for ServiceRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prune_services() {
+        let registry = ServiceRegistry::new();
+        for name in ["api", "web", "deleted"] {
+            registry
+                .register_service("default".into(), name.into(), 80, "HTTP".into(), Vec::new())
+                .await
+                .unwrap();
+        }
+
+        let live = HashSet::from(["default/api".to_string(), "default/web".to_string()]);
+        assert_eq!(registry.prune_services(&live).await, vec!["default/deleted".to_string()]);
+        assert_eq!(registry.service_count().await, 2);
+        assert!(registry.prune_services(&live).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drained_endpoints_are_not_ready() {
+        let registry = ServiceRegistry::new();
+        let endpoints = vec![
+            Endpoint { ip: "10.0.0.1".into(), port: 80, ready: true, priority: 0 },
+            Endpoint { ip: "10.0.0.2".into(), port: 80, ready: true, priority: 0 },
+        ];
+        registry
+            .register_service("default".into(), "api".into(), 80, "HTTP".into(), endpoints)
+            .await
+            .unwrap();
+
+        registry.set_drained("default/api", HashSet::from(["10.0.0.2:80".to_string()])).await.unwrap();
+        let ready: Vec<bool> = registry.get_endpoints("default/api").await.unwrap().iter().map(|e| e.ready).collect();
+        assert_eq!(ready, vec![true, false]);
+
+        // Health changes don't re-enable a drained endpoint
+        registry.set_endpoint_ready("default/api", "10.0.0.2", 80, true).await.unwrap();
+        assert!(!registry.get_endpoints("default/api").await.unwrap()[1].ready);
+
+        registry.set_drained("default/api", HashSet::new()).await.unwrap();
+        assert!(registry.get_endpoints("default/api").await.unwrap()[1].ready);
+        assert!(registry.set_drained("default/missing", HashSet::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_service_keeps_health_and_settings() {
+        let registry = ServiceRegistry::new();
+        let endpoint = |ip: &str, ready: bool| Endpoint { ip: ip.into(), port: 80, ready, priority: 0 };
+        registry
+            .sync_service("default".into(), "api".into(), 80, "HTTP".into(), vec![endpoint("10.0.0.1", true)])
+            .await
+            .unwrap();
+        registry.set_endpoint_ready("default/api", "10.0.0.1", 80, false).await.unwrap();
+        registry.set_maintenance("default/api", Some("Upgrade".into())).await.unwrap();
+
+        let endpoints = vec![endpoint("10.0.0.1", true), endpoint("10.0.0.2", true)];
+        registry
+            .sync_service("default".into(), "api".into(), 80, "HTTP".into(), endpoints)
+            .await
+            .unwrap();
+        let ready: Vec<bool> = registry.get_endpoints("default/api").await.unwrap().iter().map(|e| e.ready).collect();
+        assert_eq!(ready, vec![false, true]);
+        assert!(registry.in_maintenance("default/api").await);
+    }
+}
@@ -0,0 +1,139 @@
+//! Evaluation of scheduled (time-based) routes
+
+use crate::{CoreError, Result};
+use chrono::{DateTime, Duration, Utc};
+use cron::Schedule;
+use router_api::v1alpha1::vpc_route::RouteSchedule;
+use std::str::FromStr;
+
+/// Result of evaluating a route schedule at a point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduleState {
+    /// Whether the route is active
+    pub active: bool,
+    /// Next time the route flips between active and inactive, if known
+    pub next_transition: Option<DateTime<Utc>>,
+}
+
+/// Evaluate a route schedule at `now`
+pub fn evaluate(schedule: &RouteSchedule, now: DateTime<Utc>) -> Result<ScheduleState> {
+    let from = schedule.active_from.as_deref().map(parse_time).transpose()?;
+    let until = schedule.active_until.as_deref().map(parse_time).transpose()?;
+
+    let mut active = true;
+    let mut transitions = Vec::new();
+
+    if let Some(from) = from {
+        active &= now >= from;
+        transitions.push(from);
+    }
+    if let Some(until) = until {
+        active &= now < until;
+        transitions.push(until);
+    }
+
+    if let Some(expression) = &schedule.cron {
+        let window = schedule.duration_seconds.ok_or_else(|| {
+            CoreError::InvalidConfiguration("schedule.durationSeconds is required with cron".to_string())
+        })?;
+        let window = Duration::seconds(window as i64);
+        let cron = Schedule::from_str(expression).map_err(|e| {
+            CoreError::InvalidConfiguration(format!("invalid schedule.cron {:?}: {}", expression, e))
+        })?;
+
+        // The latest window start at or before now
+        let last_start = cron.after(&(now + Duration::seconds(1))).next_back();
+        let in_window = last_start.map(|start| now < start + window).unwrap_or(false);
+        active &= in_window;
+
+        if in_window {
+            transitions.extend(last_start.map(|start| start + window));
+        } else {
+            transitions.extend(cron.after(&now).next());
+        }
+    }
+
+    Ok(ScheduleState {
+        active,
+        next_transition: transitions.into_iter().filter(|t| *t > now).min(),
+    })
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| CoreError::InvalidConfiguration(format!("invalid schedule time {:?}: {}", value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        parse_time(value).unwrap()
+    }
+
+    #[test]
+    fn test_empty_schedule_is_active() {
+        let state = evaluate(&RouteSchedule::default(), Utc::now()).unwrap();
+        assert!(state.active);
+        assert_eq!(state.next_transition, None);
+    }
+
+    #[test]
+    fn test_fixed_window() {
+        let schedule = RouteSchedule {
+            active_from: Some("2026-03-01T00:00:00Z".to_string()),
+            active_until: Some("2026-03-02T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+
+        let before = evaluate(&schedule, at("2026-02-28T12:00:00Z")).unwrap();
+        assert!(!before.active);
+        assert_eq!(before.next_transition, Some(at("2026-03-01T00:00:00Z")));
+
+        let during = evaluate(&schedule, at("2026-03-01T12:00:00Z")).unwrap();
+        assert!(during.active);
+        assert_eq!(during.next_transition, Some(at("2026-03-02T00:00:00Z")));
+
+        let after = evaluate(&schedule, at("2026-03-02T00:00:00Z")).unwrap();
+        assert!(!after.active);
+        assert_eq!(after.next_transition, None);
+    }
+
+    #[test]
+    fn test_cron_window() {
+        // Daily at 02:00 for one hour
+        let schedule = RouteSchedule {
+            cron: Some("0 0 2 * * *".to_string()),
+            duration_seconds: Some(3600),
+            ..Default::default()
+        };
+
+        let during = evaluate(&schedule, at("2026-03-01T02:30:00Z")).unwrap();
+        assert!(during.active);
+        assert_eq!(during.next_transition, Some(at("2026-03-01T03:00:00Z")));
+
+        let outside = evaluate(&schedule, at("2026-03-01T04:00:00Z")).unwrap();
+        assert!(!outside.active);
+        assert_eq!(outside.next_transition, Some(at("2026-03-02T02:00:00Z")));
+
+        let start = evaluate(&schedule, at("2026-03-01T02:00:00Z")).unwrap();
+        assert!(start.active);
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let missing_duration = RouteSchedule {
+            cron: Some("0 0 2 * * *".to_string()),
+            ..Default::default()
+        };
+        assert!(evaluate(&missing_duration, Utc::now()).is_err());
+
+        let bad_time = RouteSchedule {
+            active_from: Some("tomorrow".to_string()),
+            ..Default::default()
+        };
+        assert!(evaluate(&bad_time, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Identity of the VPC a request comes from
+//!
+//! Workloads reach the gateway through their VPCAttachment interfaces, so
+//! a request's source address identifies the attachment, and through it
+//! the VPC, it was sent from. A [`SourceMap`] holds those addresses.
+
+use router_api::v1alpha1::vpc_service::Visibility;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+/// The VPC, and attachment within it, a request was sent from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceVpc {
+    /// VPCAttachment the request came through, as namespace/name, or only
+    /// the name for cluster-scoped attachments
+    pub attachment: String,
+    /// Namespace of the VPC
+    pub namespace: String,
+    /// Name of the VPC
+    pub name: String,
+    /// Labels of the VPC
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Source VPCs by the addresses of their attachments
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    addresses: HashMap<IpAddr, SourceVpc>,
+}
+
+impl SourceVpc {
+    /// Whether the request came through `attachment`
+    ///
+    /// `attachment` is either namespace/name or only a name, which matches
+    /// an attachment of that name in any namespace.
+    pub fn is_attachment(&self, attachment: &str) -> bool {
+        if attachment.contains('/') {
+            self.attachment == attachment
+        } else {
+            self.attachment.rsplit('/').next() == Some(attachment)
+        }
+    }
+
+    /// Whether a route restricted to `attachment` may be used from `source`
+    ///
+    /// Unrestricted routes can be used from anywhere; restricted ones are
+    /// unusable when the source is unknown.
+    pub fn attached(source: Option<&SourceVpc>, attachment: Option<&str>) -> bool {
+        match (attachment, source) {
+            (None, _) => true,
+            (Some(attachment), Some(source)) => source.is_attachment(attachment),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Whether a resource with `visibility` may be reached from `source`
+    ///
+    /// Resources without a visibility are reachable from anywhere; ones
+    /// with a visibility are unreachable when the source is unknown.
+    pub fn allowed(source: Option<&SourceVpc>, visibility: Option<&Visibility>) -> bool {
+        match (visibility, source) {
+            (None, _) => true,
+            (Some(visibility), Some(source)) => visibility.allows(&source.namespace, &source.name, &source.labels),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl SourceMap {
+    /// Resolve `address` to `source`, replacing any earlier source
+    pub fn insert(&mut self, address: IpAddr, source: SourceVpc) {
+        self.addresses.insert(address.to_canonical(), source);
+    }
+
+    /// The VPC a request from `address` came from
+    ///
+    /// IPv4-mapped IPv6 addresses, as seen on dual-stack listeners, resolve
+    /// like the IPv4 address.
+    pub fn resolve(&self, address: IpAddr) -> Option<&SourceVpc> {
+        self.addresses.get(&address.to_canonical())
+    }
+
+    /// Number of addresses that resolve
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Whether no address resolves
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::v1alpha1::vpc_service::VPCReference;
+
+    #[test]
+    fn test_allowed() {
+        let source = SourceVpc {
+            attachment: "default/payments-a".to_string(),
+            namespace: "default".to_string(),
+            name: "payments".to_string(),
+            ..Default::default()
+        };
+        let visibility = Visibility {
+            vpcs: vec![VPCReference { name: "payments".to_string(), namespace: "default".to_string() }],
+            vpc_selector: None,
+        };
+
+        assert!(SourceVpc::allowed(None, None));
+        assert!(SourceVpc::allowed(Some(&source), Some(&visibility)));
+        assert!(!SourceVpc::allowed(None, Some(&visibility)));
+        assert!(!SourceVpc::allowed(Some(&source), Some(&Visibility::default())));
+    }
+
+    #[test]
+    fn test_attached() {
+        let source = SourceVpc { attachment: "default/payments-a".to_string(), ..Default::default() };
+
+        assert!(SourceVpc::attached(None, None));
+        assert!(SourceVpc::attached(Some(&source), Some("payments-a")));
+        assert!(SourceVpc::attached(Some(&source), Some("default/payments-a")));
+        assert!(!SourceVpc::attached(Some(&source), Some("other/payments-a")));
+        assert!(!SourceVpc::attached(Some(&source), Some("payments")));
+        assert!(!SourceVpc::attached(None, Some("payments-a")));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let source = SourceVpc { attachment: "default/payments-a".to_string(), ..Default::default() };
+        let mut sources = SourceMap::default();
+        sources.insert("10.1.0.5".parse().unwrap(), source.clone());
+
+        assert_eq!(sources.resolve("10.1.0.5".parse().unwrap()), Some(&source));
+        assert_eq!(sources.resolve("::ffff:10.1.0.5".parse().unwrap()), Some(&source));
+        assert_eq!(sources.resolve("10.1.0.6".parse().unwrap()), None);
+    }
+}
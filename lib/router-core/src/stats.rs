@@ -0,0 +1,105 @@
+//! Per-endpoint traffic counters reported by gateways
+//!
+//! Each gateway replica keeps running totals per backend address
+//! ("ip:port"). The controller sums the reports of all replicas and
+//! publishes them in VPCService status.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Traffic counters for one endpoint
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCounters {
+    /// Requests currently in flight
+    pub active_connections: u64,
+    /// Requests completed
+    pub requests: u64,
+    /// Requests that failed or got a 5xx response
+    pub errors: u64,
+    /// Request bytes sent to the endpoint
+    pub bytes_received: u64,
+    /// Response bytes received from the endpoint
+    pub bytes_sent: u64,
+}
+
+/// Counters keyed by endpoint address ("ip:port")
+pub type EndpointCounterMap = HashMap<String, EndpointCounters>;
+
+impl EndpointCounters {
+    /// Add another report's counters to these
+    pub fn add(&mut self, other: &EndpointCounters) {
+        self.active_connections += other.active_connections;
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+    }
+
+    /// Fraction of requests that failed since an earlier reading
+    ///
+    /// If the totals went backwards (a gateway restarted), the current
+    /// totals are used as the window.
+    pub fn error_rate_since(&self, previous: Option<&EndpointCounters>) -> f64 {
+        let (requests, errors) = match previous {
+            Some(prev) if self.requests >= prev.requests && self.errors >= prev.errors => {
+                (self.requests - prev.requests, self.errors - prev.errors)
+            }
+            _ => (self.requests, self.errors),
+        };
+        if requests == 0 {
+            0.0
+        } else {
+            errors as f64 / requests as f64
+        }
+    }
+}
+
+/// Sum the reports of several gateway replicas
+pub fn aggregate(reports: impl IntoIterator<Item = EndpointCounterMap>) -> EndpointCounterMap {
+    let mut totals = EndpointCounterMap::new();
+    for report in reports {
+        for (address, counters) in report {
+            totals.entry(address).or_default().add(&counters);
+        }
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(active: u64, requests: u64, errors: u64) -> EndpointCounters {
+        EndpointCounters {
+            active_connections: active,
+            requests,
+            errors,
+            bytes_received: requests * 100,
+            bytes_sent: requests * 1000,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_sums_replicas() {
+        let a = EndpointCounterMap::from([("10.0.0.1:8080".to_string(), counters(2, 10, 1))]);
+        let b = EndpointCounterMap::from([
+            ("10.0.0.1:8080".to_string(), counters(1, 5, 0)),
+            ("10.0.0.2:8080".to_string(), counters(0, 3, 3)),
+        ]);
+
+        let totals = aggregate([a, b]);
+        assert_eq!(totals["10.0.0.1:8080"], counters(3, 15, 1));
+        assert_eq!(totals["10.0.0.2:8080"].errors, 3);
+    }
+
+    #[test]
+    fn test_error_rate_since() {
+        let now = counters(0, 120, 12);
+        assert_eq!(now.error_rate_since(Some(&counters(0, 100, 2))), 0.5);
+        assert_eq!(now.error_rate_since(Some(&now)), 0.0);
+        // Totals went backwards after a restart
+        assert_eq!(now.error_rate_since(Some(&counters(0, 500, 0))), 0.1);
+        assert_eq!(now.error_rate_since(None), 0.1);
+    }
+}
@@ -0,0 +1,155 @@
+//! Resilient resource watches shared by the controllers and discovery
+//!
+//! Watches use bookmarks so a restart resumes from a recent resource
+//! version instead of re-listing. An expired resource version (410 Gone)
+//! triggers a fresh list, and API server errors back off exponentially
+//! before the watch is retried. Restarts and re-lists are reported to a
+//! [`WatchObserver`] so binaries can export them as metrics.
+
+use backoff::ExponentialBackoffBuilder;
+use futures::{Stream, StreamExt};
+use kube::runtime::reflector::{self, Store};
+use kube::runtime::watcher::{self, Event};
+use kube::runtime::WatchStreamExt;
+use kube::{Api, Resource};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Watch behaviour
+#[derive(Clone, Debug)]
+pub struct WatchConfig {
+    /// Delay before the first retry after an error
+    pub initial_backoff: Duration,
+    /// Longest delay between retries
+    pub max_backoff: Duration,
+    /// Objects per page when listing
+    pub page_size: u32,
+    /// Only watch objects matching this label selector
+    pub label_selector: Option<String>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            page_size: 500,
+            label_selector: None,
+        }
+    }
+}
+
+/// Receives watch lifecycle events
+pub trait WatchObserver: Send + Sync {
+    /// The watch failed and will be retried after a backoff
+    fn restarted(&self, resource: &str, reason: &str);
+
+    /// The watch started a full list of the resource
+    fn relisted(&self, resource: &str);
+}
+
+/// Observer that only logs
+pub struct LoggingObserver;
+
+impl WatchObserver for LoggingObserver {
+    fn restarted(&self, resource: &str, reason: &str) {
+        debug!("{} watch restarting: {}", resource, reason);
+    }
+
+    fn relisted(&self, resource: &str) {
+        debug!("{} watch listing all objects", resource);
+    }
+}
+
+/// Short label for why a watch failed
+pub fn restart_reason(error: &watcher::Error) -> &'static str {
+    match error {
+        watcher::Error::WatchError(response) if response.code == 410 => "gone",
+        watcher::Error::WatchError(_) => "api_error",
+        watcher::Error::InitialListFailed(_) => "list_failed",
+        watcher::Error::WatchStartFailed(_) => "watch_start_failed",
+        watcher::Error::WatchFailed(_) => "watch_failed",
+        watcher::Error::NoResourceVersion => "no_resource_version",
+    }
+}
+
+/// Watch events for a resource, retried with backoff
+///
+/// Errors are still yielded so callers can log them, but the stream
+/// never ends on its own.
+pub fn watch<K>(
+    api: Api<K>,
+    config: &WatchConfig,
+    observer: Arc<dyn WatchObserver>,
+) -> impl Stream<Item = Result<Event<K>, watcher::Error>> + Send
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + DeserializeOwned + Send + 'static,
+{
+    let resource = K::kind(&()).to_string();
+    let backoff = ExponentialBackoffBuilder::new()
+        .with_initial_interval(config.initial_backoff)
+        .with_max_interval(config.max_backoff)
+        .with_max_elapsed_time(None)
+        .build();
+    let mut watcher_config = watcher::Config::default().page_size(config.page_size);
+    if let Some(selector) = &config.label_selector {
+        watcher_config = watcher_config.labels(selector);
+    }
+
+    watcher::watcher(api, watcher_config)
+        .inspect(move |event| match event {
+            Ok(Event::Init) => observer.relisted(&resource),
+            Err(e) => {
+                let reason = restart_reason(e);
+                if reason == "gone" {
+                    debug!("{} watch expired, re-listing", resource);
+                } else {
+                    warn!("{} watch failed: {}", resource, e);
+                }
+                observer.restarted(&resource, reason);
+            }
+            Ok(_) => {}
+        })
+        .backoff(backoff)
+}
+
+/// Watch a resource into a cache, yielding each applied object
+///
+/// The store and stream suit `Controller::for_stream`.
+pub fn reflect<K>(
+    api: Api<K>,
+    config: &WatchConfig,
+    observer: Arc<dyn WatchObserver>,
+) -> (Store<K>, impl Stream<Item = Result<K, watcher::Error>> + Send)
+where
+    K: Resource<DynamicType = ()> + Clone + Debug + DeserializeOwned + Send + Sync + 'static,
+{
+    let (reader, writer) = reflector::store();
+    let stream = watch(api, config, observer).reflect(writer).applied_objects();
+    (reader, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::core::ErrorResponse;
+
+    fn api_error(code: u16) -> watcher::Error {
+        watcher::Error::WatchError(ErrorResponse {
+            status: "Failure".to_string(),
+            message: String::new(),
+            reason: String::new(),
+            code,
+        })
+    }
+
+    #[test]
+    fn test_restart_reason() {
+        assert_eq!(restart_reason(&api_error(410)), "gone");
+        assert_eq!(restart_reason(&api_error(500)), "api_error");
+        assert_eq!(restart_reason(&watcher::Error::NoResourceVersion), "no_resource_version");
+    }
+}
@@ -8,10 +8,9 @@ pub struct GalacticClient {
 }
 
 impl GalacticClient {
-    /// Create a new Galactic client
-    pub async fn new() -> anyhow::Result<Self> {
-        let client = Client::try_default().await?;
-        Ok(Self { client })
+    /// Create a Galactic client using `client`
+    pub fn new(client: Client) -> Self {
+        Self { client }
     }
 
     /// Get the underlying Kubernetes client
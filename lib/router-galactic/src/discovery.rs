@@ -1,8 +1,11 @@
 //! Service discovery across Galactic VPCs
 
-use kube::{Api, Client};
+use ipnetwork::IpNetwork;
+use kube::{Api, Client, ResourceExt};
 use router_api::galactic::{VPC, VPCAttachment};
+use router_core::{SourceMap, SourceVpc};
 use tracing::debug;
+use std::borrow::Borrow;
 use std::collections::HashMap;
 
 /// VPCDiscovery handles discovery of services across Galactic VPCs
@@ -11,10 +14,14 @@ pub struct VPCDiscovery {
 }
 
 impl VPCDiscovery {
-    /// Create a new VPC discovery client
-    pub async fn new() -> anyhow::Result<Self> {
-        let client = Client::try_default().await?;
-        Ok(Self { client })
+    /// Create a VPC discovery client using `client`
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Kubernetes client used for discovery
+    pub fn client(&self) -> &Client {
+        &self.client
     }
 
     /// Discover all VPCs in the cluster
@@ -62,7 +69,7 @@ impl VPCDiscovery {
 
         for attachment in attachments {
             let key = format!("{}/{}", attachment.spec.vpc.namespace, attachment.spec.vpc.name);
-            map.entry(key).or_insert_with(Vec::new).push(attachment);
+            map.entry(key).or_default().push(attachment);
         }
 
         Ok(map)
@@ -91,4 +98,73 @@ impl VPCDiscovery {
             .cloned()
             .collect()
     }
+
+    /// Map the addresses of `attachments` to the VPCs they belong to
+    ///
+    /// Addresses may carry a prefix length, which is ignored. VPC labels
+    /// come from `vpcs`; an attachment whose VPC isn't listed still
+    /// resolves, without labels.
+    pub fn source_map<V, A>(vpcs: &[V], attachments: &[A]) -> SourceMap
+    where
+        V: Borrow<VPC>,
+        A: Borrow<VPCAttachment>,
+    {
+        let mut map = SourceMap::default();
+        for attachment in attachments {
+            let attachment = attachment.borrow();
+            let vpc = &attachment.spec.vpc;
+            let labels = vpcs
+                .iter()
+                .map(Borrow::borrow)
+                .find(|v: &&VPC| v.name_any() == vpc.name && v.namespace().is_none_or(|ns| ns == vpc.namespace))
+                .map(|v| v.labels().clone())
+                .unwrap_or_default();
+            let source = SourceVpc {
+                attachment: match attachment.namespace() {
+                    Some(namespace) => format!("{}/{}", namespace, attachment.name_any()),
+                    None => attachment.name_any(),
+                },
+                namespace: vpc.namespace.clone(),
+                name: vpc.name.clone(),
+                labels,
+            };
+            for address in &attachment.spec.interface.addresses {
+                match address.parse::<IpNetwork>() {
+                    Ok(network) => map.insert(network.ip(), source.clone()),
+                    Err(e) => debug!("Ignoring address {:?} of attachment {}: {}", address, source.attachment, e),
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::galactic::vpc::VPCSpec;
+    use router_api::galactic::vpc_attachment::{InterfaceConfig, VPCAttachmentSpec, VPCRef};
+
+    #[test]
+    fn test_source_map() {
+        let mut vpc = VPC::new("payments", VPCSpec { networks: vec!["10.1.0.0/16".to_string()] });
+        vpc.metadata.labels = Some([("tier".to_string(), "internal".to_string())].into());
+        let mut attachment = VPCAttachment::new("payments-a", VPCAttachmentSpec {
+            vpc: VPCRef { name: "payments".to_string(), namespace: "default".to_string(), ..Default::default() },
+            interface: InterfaceConfig {
+                name: "galactic0".to_string(),
+                addresses: vec!["10.1.0.5/24".to_string(), "fd00::5".to_string(), "bogus".to_string()],
+            },
+            routes: vec![],
+        });
+        attachment.metadata.namespace = Some("apps".to_string());
+
+        let map = VPCDiscovery::source_map(&[vpc], &[attachment]);
+        assert_eq!(map.len(), 2);
+        let source = map.resolve("10.1.0.5".parse().unwrap()).unwrap();
+        assert_eq!(source.attachment, "apps/payments-a");
+        assert_eq!((source.namespace.as_str(), source.name.as_str()), ("default", "payments"));
+        assert_eq!(source.labels.get("tier").map(String::as_str), Some("internal"));
+        assert!(map.resolve("fd00::5".parse().unwrap()).is_some());
+    }
 }
@@ -1,6 +1,8 @@
 //! Galactic VPC integration
 pub mod discovery;
 pub mod client;
+pub mod nat;
 
 pub use discovery::VPCDiscovery;
 pub use client::GalacticClient;
+pub use nat::NatTable;
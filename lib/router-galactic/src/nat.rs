@@ -0,0 +1,219 @@
+//! Address translation between VPCs with overlapping networks
+//!
+//! Galactic VPCs pick their networks independently, so two VPCs may both
+//! use 10.0.0.0/16. A router attached to both can't tell their endpoints
+//! apart by address. When the source and destination VPCs of a request
+//! overlap, the destination endpoint is addressed by a translated IPv6
+//! address instead, made of the NAT prefix, the destination VPC's 48-bit
+//! identifier, and the endpoint's IPv4 address:
+//!
+//! ```text
+//! | NAT prefix (48) | VPC identifier (48) | IPv4 address (32) |
+//! ```
+//!
+//! The mapping is stateless, so whatever routes the NAT prefix into the
+//! destination VPC recovers the original address with
+//! [`NatTable::original`]. IPv6 endpoints are never translated.
+
+use ipnetwork::{IpNetwork, Ipv6Network};
+use kube::ResourceExt;
+use router_api::galactic::VPC;
+use std::borrow::Borrow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tracing::debug;
+
+/// Prefix translated addresses are allocated from unless configured
+pub const DEFAULT_NAT_PREFIX: &str = "fd7a:7472::/48";
+
+/// Length of a NAT prefix
+pub const NAT_PREFIX_LEN: u8 = 48;
+
+/// Largest VPC identifier
+const MAX_IDENTIFIER: u64 = (1 << 48) - 1;
+
+/// A VPC's identifier and networks
+#[derive(Clone, Debug)]
+struct NatVpc {
+    /// Namespace of the VPC; unset for cluster-scoped VPCs, which match any
+    namespace: Option<String>,
+    name: String,
+    identifier: Option<u64>,
+    networks: Vec<IpNetwork>,
+}
+
+/// Translation mappings for all known VPCs
+#[derive(Clone, Debug)]
+pub struct NatTable {
+    prefix: Ipv6Network,
+    vpcs: Vec<NatVpc>,
+}
+
+impl Default for NatTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_NAT_PREFIX.parse().expect("valid default NAT prefix"))
+    }
+}
+
+impl NatTable {
+    /// An empty table allocating from `prefix`, which must be a /48
+    pub fn new(prefix: Ipv6Network) -> Self {
+        let prefix = Ipv6Network::new(prefix.network(), NAT_PREFIX_LEN).expect("48 is a valid IPv6 prefix length");
+        Self { prefix, vpcs: Vec::new() }
+    }
+
+    /// Parse a NAT prefix, which must be an IPv6 /48
+    pub fn parse_prefix(value: &str) -> anyhow::Result<Ipv6Network> {
+        let prefix: Ipv6Network = value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid NAT prefix {:?}: {}", value, e))?;
+        if prefix.prefix() != NAT_PREFIX_LEN {
+            anyhow::bail!("NAT prefix {} must be a /{}", prefix, NAT_PREFIX_LEN);
+        }
+        Ok(prefix)
+    }
+
+    /// Table for `vpcs`, allocating from `prefix`
+    ///
+    /// VPCs without an identifier yet, or with networks that don't parse,
+    /// are kept with what could be read; they just can't be translated to.
+    pub fn from_vpcs<V: Borrow<VPC>>(prefix: Ipv6Network, vpcs: &[V]) -> Self {
+        let mut table = Self::new(prefix);
+        for vpc in vpcs {
+            let vpc = vpc.borrow();
+            let identifier = vpc
+                .status
+                .as_ref()
+                .and_then(|status| status.identifier.as_deref())
+                .and_then(|id| u64::from_str_radix(id.trim_start_matches("0x"), 16).ok())
+                .filter(|id| *id <= MAX_IDENTIFIER);
+            let networks = vpc
+                .spec
+                .networks
+                .iter()
+                .filter_map(|network| match network.parse() {
+                    Ok(network) => Some(network),
+                    Err(e) => {
+                        debug!("Ignoring network {:?} of VPC {}: {}", network, vpc.name_any(), e);
+                        None
+                    }
+                })
+                .collect();
+            table.vpcs.push(NatVpc { namespace: vpc.namespace(), name: vpc.name_any(), identifier, networks });
+        }
+        table
+    }
+
+    /// Prefix translated addresses are allocated from
+    pub fn prefix(&self) -> Ipv6Network {
+        self.prefix
+    }
+
+    fn vpc(&self, namespace: &str, name: &str) -> Option<&NatVpc> {
+        self.vpcs
+            .iter()
+            .find(|vpc| vpc.name == name && vpc.namespace.as_deref().is_none_or(|ns| ns == namespace))
+    }
+
+    /// Whether two different VPCs share any addresses
+    pub fn overlaps(&self, a: (&str, &str), b: (&str, &str)) -> bool {
+        if a == b {
+            return false;
+        }
+        let (Some(a), Some(b)) = (self.vpc(a.0, a.1), self.vpc(b.0, b.1)) else {
+            return false;
+        };
+        a.networks.iter().any(|x| b.networks.iter().any(|y| x.contains(y.ip()) || y.contains(x.ip())))
+    }
+
+    /// Translated address of `address` in the VPC `namespace/name`
+    ///
+    /// None if the VPC is unknown or has no identifier yet.
+    pub fn translate(&self, namespace: &str, name: &str, address: Ipv4Addr) -> Option<Ipv6Addr> {
+        let identifier = self.vpc(namespace, name)?.identifier?;
+        let prefix = u128::from(self.prefix.network()) >> 80 << 80;
+        let address = prefix | u128::from(identifier) << 32 | u128::from(u32::from(address));
+        Some(Ipv6Addr::from(address))
+    }
+
+    /// The address to reach `address`, in the VPC `destination`, from a
+    /// request sent from the VPC `source`
+    ///
+    /// Only IPv4 addresses in a VPC overlapping the source VPC are
+    /// translated; unknown sources and VPCs without an identifier get the
+    /// address unchanged.
+    pub fn destination(&self, source: Option<(&str, &str)>, destination: (&str, &str), address: IpAddr) -> IpAddr {
+        let IpAddr::V4(ipv4) = address else {
+            return address;
+        };
+        match source {
+            Some(source) if self.overlaps(source, destination) => self
+                .translate(destination.0, destination.1, ipv4)
+                .map(IpAddr::V6)
+                .unwrap_or(address),
+            _ => address,
+        }
+    }
+
+    /// The VPC, as namespace and name, and original address behind a
+    /// translated address
+    ///
+    /// The namespace is empty for cluster-scoped VPCs.
+    pub fn original(&self, address: Ipv6Addr) -> Option<(&str, &str, Ipv4Addr)> {
+        if !self.prefix.contains(address) {
+            return None;
+        }
+        let bits = u128::from(address);
+        let identifier = (bits >> 32) as u64 & MAX_IDENTIFIER;
+        let vpc = self.vpcs.iter().find(|vpc| vpc.identifier == Some(identifier))?;
+        Some((vpc.namespace.as_deref().unwrap_or_default(), vpc.name.as_str(), Ipv4Addr::from(bits as u32)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::galactic::vpc::{VPCSpec, VPCStatus};
+
+    fn vpc(name: &str, identifier: Option<&str>, networks: &[&str]) -> VPC {
+        let mut vpc = VPC::new(name, VPCSpec { networks: networks.iter().map(|n| n.to_string()).collect() });
+        vpc.status = Some(VPCStatus { identifier: identifier.map(str::to_string), ..Default::default() });
+        vpc
+    }
+
+    #[test]
+    fn test_translation_round_trips() {
+        let vpcs = [
+            vpc("payments", Some("0000000a0001"), &["10.0.0.0/16"]),
+            vpc("web", Some("0000000b0002"), &["10.0.128.0/17", "fd00::/64"]),
+            vpc("batch", Some("0000000c0003"), &["192.168.0.0/24"]),
+            vpc("new", None, &["10.0.0.0/8"]),
+        ];
+        let table = NatTable::from_vpcs(DEFAULT_NAT_PREFIX.parse().unwrap(), &vpcs);
+        let endpoint: IpAddr = "10.0.200.7".parse().unwrap();
+
+        assert!(table.overlaps(("default", "payments"), ("default", "web")));
+        assert!(!table.overlaps(("default", "payments"), ("default", "batch")));
+        assert!(!table.overlaps(("default", "web"), ("default", "web")));
+
+        let translated = table.destination(Some(("default", "payments")), ("default", "web"), endpoint);
+        assert_eq!(translated, "fd7a:7472::b:2:a00:c807".parse::<IpAddr>().unwrap());
+        let IpAddr::V6(translated) = translated else { unreachable!() };
+        assert_eq!(table.original(translated), Some(("", "web", "10.0.200.7".parse().unwrap())));
+
+        // No overlap, an unknown source, IPv6, or no identifier: unchanged
+        assert_eq!(table.destination(Some(("default", "batch")), ("default", "web"), endpoint), endpoint);
+        assert_eq!(table.destination(None, ("default", "web"), endpoint), endpoint);
+        let ipv6: IpAddr = "fd00::7".parse().unwrap();
+        assert_eq!(table.destination(Some(("default", "payments")), ("default", "web"), ipv6), ipv6);
+        assert_eq!(table.destination(Some(("default", "payments")), ("default", "new"), endpoint), endpoint);
+        assert_eq!(table.original("fd00::7".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        assert!(NatTable::parse_prefix("fd12:3456:789a::/48").is_ok());
+        assert!(NatTable::parse_prefix("fd12:3456::/32").is_err());
+        assert!(NatTable::parse_prefix("10.0.0.0/8").is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! Per-request access log
+//!
+//! One line per request with the client, request line, status, response
+//! size, latency, upstream, trace ID and request ID, in JSON or Apache
+//! combined format.
+//! Lines go to stdout or to a file rotated by size. Writing happens on a
+//! dedicated thread so a slow disk never holds up request handling; lines
+//! that don't fit in its buffer are dropped and counted.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::redaction::Redactor;
+
+/// Access log line format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// One JSON object per line
+    Json,
+    /// Apache combined format, followed by latency, upstream, trace ID and request ID
+    Combined,
+}
+
+impl AccessLogFormat {
+    /// Parse a format name, "json" or "combined"
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Some(AccessLogFormat::Json),
+            "combined" => Some(AccessLogFormat::Combined),
+            _ => None,
+        }
+    }
+}
+
+/// Access log configuration
+#[derive(Clone, Debug)]
+pub struct AccessLogConfig {
+    /// Line format
+    pub format: AccessLogFormat,
+    /// File to write to; stdout when unset
+    pub path: Option<PathBuf>,
+    /// Size at which the file is rotated
+    pub max_bytes: u64,
+    /// Rotated files kept, as `<path>.1` (newest) to `<path>.<max_files>`
+    pub max_files: usize,
+    /// Lines buffered for the writer before new ones are dropped
+    pub buffer_lines: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            format: AccessLogFormat::Combined,
+            path: None,
+            max_bytes: 100 * 1024 * 1024,
+            max_files: 5,
+            buffer_lines: 8192,
+        }
+    }
+}
+
+/// Response extension naming the upstream that answered the request
+#[derive(Clone, Debug)]
+pub struct AccessLogUpstream(pub String);
+
+/// One request's access log entry
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogEntry {
+    /// When the request was received
+    pub timestamp: DateTime<Utc>,
+    /// Address of the client connection
+    pub client_ip: IpAddr,
+    /// Request method
+    pub method: String,
+    /// Request path and query
+    pub path: String,
+    /// Request protocol version, e.g. "HTTP/1.1"
+    pub protocol: String,
+    /// Response status
+    pub status: u16,
+    /// Response body size
+    pub bytes: u64,
+    /// Time to produce the response, in milliseconds
+    pub latency_ms: f64,
+    /// Upstream that answered the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// Request ID assigned by the gateway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Trace ID of the request's traceparent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    /// Value of the Referer header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+    /// Value of the User-Agent header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Set the latency from a duration
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = latency.as_secs_f64() * 1000.0;
+        self
+    }
+
+    /// Mask sensitive query parameters and headers before the entry is logged
+    pub fn redacted(mut self, redactor: &Redactor) -> Self {
+        self.path = redactor.redact_uri(&self.path);
+        self.referer = self
+            .referer
+            .map(|referer| redactor.redact_uri(redactor.header_value("referer", &referer)));
+        self.user_agent = self
+            .user_agent
+            .map(|user_agent| redactor.header_value("user-agent", &user_agent).to_string());
+        self
+    }
+
+    /// Format the entry as a line, without the trailing newline
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            AccessLogFormat::Combined => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {:.3} {} {} {}",
+                self.client_ip,
+                self.timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                self.path,
+                self.protocol,
+                self.status,
+                self.bytes,
+                quoted(self.referer.as_deref()),
+                quoted(self.user_agent.as_deref()),
+                self.latency_ms / 1000.0,
+                self.upstream.as_deref().unwrap_or("-"),
+                self.trace_id.as_deref().unwrap_or("-"),
+                self.request_id.as_deref().unwrap_or("-"),
+            ),
+        }
+    }
+}
+
+/// Escape a header value for a quoted combined-format field
+fn quoted(value: Option<&str>) -> String {
+    match value {
+        Some(value) => value.replace('\\', "\\\\").replace('"', "\\\""),
+        None => "-".to_string(),
+    }
+}
+
+/// Writes access log lines on a background thread
+pub struct AccessLogger {
+    format: AccessLogFormat,
+    lines: SyncSender<String>,
+    dropped: AtomicU64,
+}
+
+impl AccessLogger {
+    /// Create a logger, opening its file if it has one
+    pub fn new(config: AccessLogConfig) -> Result<Self> {
+        let output = match &config.path {
+            Some(path) => Output::File(RotatingFile::open(path.clone(), config.max_bytes, config.max_files)?),
+            None => Output::Stdout,
+        };
+        let (lines, receiver) = mpsc::sync_channel(config.buffer_lines.max(1));
+        std::thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || write_lines(receiver, output))
+            .context("Failed to start access log writer")?;
+        Ok(Self {
+            format: config.format,
+            lines,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Line format
+    pub fn format(&self) -> AccessLogFormat {
+        self.format
+    }
+
+    /// Log a request
+    pub fn log(&self, entry: &AccessLogEntry) {
+        match self.lines.try_send(entry.format(self.format)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                    warn!("Access log writer is falling behind, dropping lines");
+                }
+            }
+        }
+    }
+
+    /// Number of lines dropped because the writer fell behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+enum Output {
+    Stdout,
+    File(RotatingFile),
+}
+
+/// Write lines until the logger is dropped, flushing whenever caught up
+fn write_lines(receiver: Receiver<String>, mut output: Output) {
+    let mut stdout = std::io::stdout();
+    while let Ok(mut line) = receiver.recv() {
+        loop {
+            line.push('\n');
+            let written = match &mut output {
+                Output::Stdout => stdout.write_all(line.as_bytes()),
+                Output::File(file) => file.write_line(line.as_bytes()),
+            };
+            if let Err(e) = written {
+                warn!("Failed to write access log: {}", e);
+            }
+            match receiver.try_recv() {
+                Ok(next) => line = next,
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        let flushed = match &mut output {
+            Output::Stdout => stdout.flush(),
+            Output::File(file) => file.flush(),
+        };
+        if let Err(e) = flushed {
+            warn!("Failed to flush access log: {}", e);
+        }
+    }
+}
+
+/// File rotated once it reaches a size
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> Result<Self> {
+        let file = Self::append(&path)?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file: BufWriter::new(file),
+            size,
+        })
+    }
+
+    fn append(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open access log {}", path.display()))
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            if let Err(e) = self.rotate() {
+                warn!("Failed to rotate access log {}: {}", self.path.display(), e);
+            }
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift `<path>.N` to `<path>.N+1`, dropping the oldest, and start a new file
+    fn rotate(&mut self) -> Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = BufWriter::new(Self::append(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: DateTime::parse_from_rfc3339("2024-03-05T13:55:36Z").unwrap().with_timezone(&Utc),
+            client_ip: "10.0.0.7".parse().unwrap(),
+            method: "GET".to_string(),
+            path: "/orders?page=2".to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: 512,
+            latency_ms: 0.0,
+            upstream: Some("backend-service:8080".to_string()),
+            request_id: Some("req-1".to_string()),
+            trace_id: None,
+            referer: None,
+            user_agent: Some("curl/8.0 \"test\"".to_string()),
+        }
+        .with_latency(Duration::from_millis(42))
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Combined),
+            "10.0.0.7 - - [05/Mar/2024:13:55:36 +0000] \"GET /orders?page=2 HTTP/1.1\" 200 512 \"-\" \
+             \"curl/8.0 \\\"test\\\"\" 0.042 backend-service:8080 - req-1"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&entry().format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["clientIp"], "10.0.0.7");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["latencyMs"], 42.0);
+        assert_eq!(json["upstream"], "backend-service:8080");
+        assert_eq!(json["requestId"], "req-1");
+        assert!(json.get("traceId").is_none());
+        assert_eq!(AccessLogFormat::from_string("JSON"), Some(AccessLogFormat::Json));
+        assert_eq!(AccessLogFormat::from_string("common"), None);
+    }
+
+    #[test]
+    fn test_redacted_entry() {
+        let redactor = Redactor::new()
+            .with_query_params(["token".to_string()])
+            .with_headers(["user-agent".to_string()]);
+        let mut entry = entry();
+        entry.path = "/login?token=s3cret&page=2".to_string();
+        entry.referer = Some("https://app.example.com/start?token=s3cret".to_string());
+        let entry = entry.redacted(&redactor);
+
+        for format in [AccessLogFormat::Combined, AccessLogFormat::Json] {
+            let line = entry.format(format);
+            assert!(!line.contains("s3cret"), "{}", line);
+            assert!(!line.contains("curl"), "{}", line);
+        }
+        assert_eq!(entry.path, "/login?token=[REDACTED]&page=2");
+        assert_eq!(entry.referer.as_deref(), Some("https://app.example.com/start?token=[REDACTED]"));
+    }
+
+    #[test]
+    fn test_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("access-log-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let mut file = RotatingFile::open(path.clone(), 20, 2).unwrap();
+        for line in ["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        // Each line overflows the limit, so each lands in its own file and the first is gone
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(std::fs::read_to_string(file.rotated(1)).unwrap(), "third line\n");
+        assert_eq!(std::fs::read_to_string(file.rotated(2)).unwrap(), "second line\n");
+        assert!(!file.rotated(3).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+//! Session affinity key extraction
+//!
+//! Reads the consistent-hash key for sticky routing from a named header,
+//! cookie, or gRPC metadata key, as configured on the VPCRoute. Session
+//! pins can be kept in a shared state store so every replica sends a
+//! session to the same endpoint, even as the endpoint set changes.
+//!
+//! Clients that carry no session key of their own can be pinned with a
+//! sticky cookie instead: the gateway names the endpoint that served the
+//! first request in a signed cookie, and later requests with the cookie go
+//! back to it.
+
+use crate::state_store::StateStore;
+use anyhow::Result;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::header::{HeaderMap, HeaderValue, COOKIE};
+use router_api::v1alpha1::vpc_route::{AffinityPolicy, AffinitySource};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Extracts session affinity keys from requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct AffinityKeyExtractor {
+    source: AffinitySource,
+    /// Lowercased header/metadata name, or cookie name as configured
+    name: String,
+}
+
+impl AffinityKeyExtractor {
+    /// Create an extractor reading `name` from `source`
+    pub fn new(source: AffinitySource, name: &str) -> Self {
+        let name = match source {
+            // Cookie names are case-sensitive; header names are not
+            AffinitySource::Cookie | AffinitySource::StickyCookie => name.to_string(),
+            AffinitySource::Header | AffinitySource::GrpcMetadata => name.to_lowercase(),
+        };
+        Self { source, name }
+    }
+
+    /// Create an extractor from a VPCRoute affinity policy
+    pub fn from_policy(policy: &AffinityPolicy) -> Self {
+        Self::new(policy.source.clone(), &policy.name)
+    }
+
+    /// Extract the affinity key from request headers
+    ///
+    /// Returns `None` when the request doesn't carry the key, in which case
+    /// the caller falls back to the route's load balancing policy.
+    pub fn extract(&self, headers: &HeaderMap) -> Option<String> {
+        match self.source {
+            // gRPC metadata travels as HTTP/2 headers
+            AffinitySource::Header | AffinitySource::GrpcMetadata => {
+                Self::header_value(headers, &self.name)
+            }
+            AffinitySource::Cookie | AffinitySource::StickyCookie => headers
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| *name == self.name)
+                .map(|(_, value)| value.trim_matches('"').to_string())
+                .filter(|value| !value.is_empty()),
+        }
+    }
+
+    fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Session-to-endpoint pins shared between gateway replicas
+pub struct SessionPins {
+    store: Arc<dyn StateStore>,
+    ttl: Duration,
+}
+
+impl SessionPins {
+    /// Create a pin table whose entries expire after `ttl` without use
+    pub fn new(store: Arc<dyn StateStore>, ttl: Duration) -> Self {
+        Self { store, ttl }
+    }
+
+    /// Endpoint address ("ip:port") a session is pinned to
+    pub async fn get(&self, session: &str) -> Result<Option<String>> {
+        let pinned = self.store.get(&Self::key(session)).await?;
+        Ok(pinned.and_then(|v| String::from_utf8(v).ok()))
+    }
+
+    /// Pin a session to an endpoint address, refreshing the expiry
+    pub async fn pin(&self, session: &str, endpoint: &str) -> Result<()> {
+        self.store.set(&Self::key(session), endpoint.as_bytes(), Some(self.ttl)).await
+    }
+
+    fn key(session: &str) -> String {
+        format!("affinity:{}", session)
+    }
+}
+
+/// Bytes of the HMAC-SHA256 kept in a sticky cookie's signature
+const SIGNATURE_LEN: usize = 16;
+
+/// Sticky sessions with a cookie the gateway sets
+///
+/// The cookie value is the endpoint, its expiry, and a signature over them
+/// and the service, so clients can't steer requests to an endpoint of
+/// their choosing and a cookie issued for one service is ignored by
+/// another. Replicas sharing the signing key honor each other's cookies.
+#[derive(Clone)]
+pub struct StickyCookie {
+    name: String,
+    ttl: Option<Duration>,
+    key: Arc<[u8]>,
+}
+
+impl StickyCookie {
+    /// Cookie `name` lasting `ttl`, or the browser session when unset,
+    /// signed with `key`
+    pub fn new(name: &str, ttl: Option<Duration>, key: &[u8]) -> Self {
+        Self { name: name.to_string(), ttl, key: key.into() }
+    }
+
+    /// Sticky cookie of a VPCRoute affinity policy, if it uses one
+    pub fn from_policy(policy: &AffinityPolicy, key: &[u8]) -> Option<Self> {
+        (policy.source == AffinitySource::StickyCookie).then(|| {
+            let ttl = policy.ttl_seconds.map(|secs| Duration::from_secs(secs.into()));
+            Self::new(&policy.name, ttl, key)
+        })
+    }
+
+    /// Endpoint ("ip:port") of `service_id` the request's cookie pins it to
+    ///
+    /// None when the request has no cookie, or one that is expired, was
+    /// issued for another service, or doesn't carry a valid signature.
+    pub fn endpoint(&self, headers: &HeaderMap, service_id: &str, now: SystemTime) -> Option<String> {
+        let value = AffinityKeyExtractor::new(AffinitySource::Cookie, &self.name).extract(headers)?;
+        let mut parts = value.splitn(3, '.');
+        let (endpoint, expires, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        let endpoint = String::from_utf8(URL_SAFE_NO_PAD.decode(endpoint).ok()?).ok()?;
+        let expires: u64 = expires.parse().ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        let expected = self.sign(service_id, &endpoint, expires);
+        if !constant_time_eq(&signature, &expected[..SIGNATURE_LEN]) {
+            return None;
+        }
+        if expires != 0 && expires <= unix_secs(now) {
+            return None;
+        }
+        Some(endpoint)
+    }
+
+    /// Set-Cookie value pinning the client to `endpoint` of `service_id`
+    pub fn cookie(&self, service_id: &str, endpoint: &str, now: SystemTime) -> HeaderValue {
+        // An expiry of 0 marks a session cookie, valid until the browser closes
+        let expires = self.ttl.map_or(0, |ttl| unix_secs(now) + ttl.as_secs());
+        let signature = self.sign(service_id, endpoint, expires);
+        let mut cookie = format!(
+            "{}={}.{}.{}; Path=/; HttpOnly; SameSite=Lax",
+            self.name,
+            URL_SAFE_NO_PAD.encode(endpoint),
+            expires,
+            URL_SAFE_NO_PAD.encode(&signature[..SIGNATURE_LEN])
+        );
+        if let Some(ttl) = self.ttl {
+            cookie.push_str(&format!("; Max-Age={}", ttl.as_secs()));
+        }
+        HeaderValue::from_str(&cookie).expect("cookie names are header-safe")
+    }
+
+    fn sign(&self, service_id: &str, endpoint: &str, expires: u64) -> [u8; 32] {
+        hmac_sha256(&self.key, format!("{}|{}|{}", service_id, endpoint, expires).as_bytes())
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// HMAC-SHA256 of `message` (RFC 2104)
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().into()
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn test_extract_header() {
+        let extractor = AffinityKeyExtractor::new(AffinitySource::Header, "X-Session-Id");
+        let mut headers = HeaderMap::new();
+        assert_eq!(extractor.extract(&headers), None);
+
+        headers.insert("x-session-id", HeaderValue::from_static("abc"));
+        assert_eq!(extractor.extract(&headers), Some("abc".to_string()));
+    }
+
+    #[test]
+    fn test_extract_grpc_metadata() {
+        let extractor = AffinityKeyExtractor::from_policy(&AffinityPolicy {
+            source: AffinitySource::GrpcMetadata,
+            name: "tenant-id".to_string(),
+            ttl_seconds: None,
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/grpc"));
+        headers.insert("tenant-id", HeaderValue::from_static("t-42"));
+        assert_eq!(extractor.extract(&headers), Some("t-42".to_string()));
+    }
+
+    #[test]
+    fn test_extract_cookie() {
+        let extractor = AffinityKeyExtractor::new(AffinitySource::Cookie, "session");
+        let mut headers = HeaderMap::new();
+        headers.append(COOKIE, HeaderValue::from_static("theme=dark"));
+        headers.append(COOKIE, HeaderValue::from_static("lang=en; session=\"s-1\""));
+        assert_eq!(extractor.extract(&headers), Some("s-1".to_string()));
+
+        let other = AffinityKeyExtractor::new(AffinitySource::Cookie, "Session");
+        assert_eq!(other.extract(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_session_pins_are_shared() {
+        use crate::state_store::MemoryStateStore;
+
+        let store: Arc<dyn StateStore> = Arc::new(MemoryStateStore::new());
+        let replica_a = SessionPins::new(store.clone(), Duration::from_secs(60));
+        let replica_b = SessionPins::new(store, Duration::from_secs(60));
+
+        assert_eq!(replica_a.get("s-1").await.unwrap(), None);
+        replica_a.pin("s-1", "10.0.0.3:8080").await.unwrap();
+        assert_eq!(replica_b.get("s-1").await.unwrap(), Some("10.0.0.3:8080".to_string()));
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(hex::encode(mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn test_sticky_cookie() {
+        let policy = AffinityPolicy {
+            source: AffinitySource::StickyCookie,
+            name: "route".to_string(),
+            ttl_seconds: Some(3600),
+        };
+        let sticky = StickyCookie::from_policy(&policy, b"secret").unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let set_cookie = sticky.cookie("default/carts", "10.0.0.3:8080", now);
+        assert!(set_cookie.to_str().unwrap().ends_with("; Max-Age=3600"));
+
+        let cookie = set_cookie.to_str().unwrap().split(';').next().unwrap().to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_str(&cookie).unwrap());
+        assert_eq!(sticky.endpoint(&headers, "default/carts", now), Some("10.0.0.3:8080".to_string()));
+
+        // Expired, for another service, or signed with another key
+        assert_eq!(sticky.endpoint(&headers, "default/carts", now + Duration::from_secs(3600)), None);
+        assert_eq!(sticky.endpoint(&headers, "default/orders", now), None);
+        let other = StickyCookie::new("route", Some(Duration::from_secs(3600)), b"other");
+        assert_eq!(other.endpoint(&headers, "default/carts", now), None);
+
+        // Tampering with the endpoint breaks the signature
+        let forged = cookie.replacen(&URL_SAFE_NO_PAD.encode("10.0.0.3:8080"), &URL_SAFE_NO_PAD.encode("10.0.0.4:8080"), 1);
+        headers.insert(COOKIE, HeaderValue::from_str(&forged).unwrap());
+        assert_eq!(sticky.endpoint(&headers, "default/carts", now), None);
+
+        assert!(StickyCookie::from_policy(&AffinityPolicy::default(), b"secret").is_none());
+    }
+}
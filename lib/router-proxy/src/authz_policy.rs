@@ -0,0 +1,313 @@
+//! Embedded authorization policies (Cedar)
+//!
+//! Evaluates each request against Cedar policies held by the gateway, so
+//! access rules need no round trip to an authorization service. A request
+//! is described to the policies as:
+//!
+//! - principal: `Router::Client::"<identity>"`, from the configured identity
+//!   header, or unspecified when there is none
+//! - action: `Router::Action::"<method>"`
+//! - resource: `Router::Host::"<host>"`
+//! - context: `method`, `host`, `path`, `query`, `clientIp` (an `ipaddr`),
+//!   `headers` with the configured request headers by lower-case name, and
+//!   `sourceVpc` (`namespace`, `name`, `attachment`, `labels`) when the
+//!   client's VPC is known
+//!
+//! The path is normalized first (see `path_normalize`), so route prefixes
+//! and policies see `/admin` however it was spelled; paths that can't be
+//! normalized are denied.
+//!
+//! Cedar denies a request unless a policy permits it and none forbids it,
+//! so every request is denied until policies are loaded. Policies are
+//! replaced all at once; a set that fails to parse leaves the current one
+//! in place. OPA Rego policies are not supported.
+
+use anyhow::{anyhow, Result};
+use cedar_policy::{
+    Authorizer, Context, Decision, Entities, EntityId, EntityTypeName, EntityUid, PolicyId, PolicySet, Request,
+};
+use hyper::header::{HeaderMap, HOST};
+use hyper::{Method, StatusCode, Uri};
+use router_core::SourceVpc;
+use serde_json::{json, Map};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tracing::debug;
+
+use crate::ext_authz::AuthzDecision;
+use crate::path_normalize::normalize_path;
+use crate::problem;
+
+/// Embedded authorization settings
+#[derive(Clone, Debug, Default)]
+pub struct PolicyAuthzConfig {
+    /// Header naming the authenticated client; it must be set by something
+    /// the gateway trusts, such as an external authorization service
+    pub identity_header: Option<String>,
+    /// Request headers made available to policies
+    pub context_headers: Vec<String>,
+    /// Path prefixes of routes requiring authorization; empty means all
+    pub route_prefixes: Vec<String>,
+}
+
+/// Authorizes requests against Cedar policies
+pub struct PolicyAuthorizer {
+    config: PolicyAuthzConfig,
+    authorizer: Authorizer,
+    policies: RwLock<Arc<PolicySet>>,
+}
+
+impl PolicyAuthorizer {
+    /// Create an authorizer with no policies
+    pub fn new(config: PolicyAuthzConfig) -> Self {
+        Self {
+            config,
+            authorizer: Authorizer::new(),
+            policies: RwLock::new(Arc::new(PolicySet::new())),
+        }
+    }
+
+    /// The authorizer's settings
+    pub fn config(&self) -> &PolicyAuthzConfig {
+        &self.config
+    }
+
+    /// Whether requests for `path` need authorization
+    ///
+    /// Paths that can't be normalized always do, and are denied.
+    pub fn applies_to(&self, path: &str) -> bool {
+        let Some(path) = normalize_path(path) else {
+            return true;
+        };
+        self.config.route_prefixes.is_empty()
+            || self.config.route_prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Replace the policies with those of `sources`, pairs of a source name
+    /// and Cedar policy text, returning how many policies were loaded
+    ///
+    /// Policies are identified by their source name and position, as in
+    /// `policies/org-rules/0`, in logs.
+    pub fn load(&self, sources: &[(String, String)]) -> Result<usize> {
+        let mut policies = PolicySet::new();
+        for (name, text) in sources {
+            let parsed = PolicySet::from_str(text).map_err(|e| anyhow!("Invalid policies in {}: {}", name, e))?;
+            if parsed.templates().next().is_some() {
+                return Err(anyhow!("Invalid policies in {}: templates are not supported", name));
+            }
+            for (i, policy) in parsed.policies().enumerate() {
+                let id = PolicyId::from_str(&format!("{}/{}", name, i)).map_err(|e| anyhow!("{}", e))?;
+                policies.add(policy.new_id(id)).map_err(|e| anyhow!("Invalid policies in {}: {}", name, e))?;
+            }
+        }
+        let count = policies.policies().count();
+        *self.policies.write().unwrap() = Arc::new(policies);
+        Ok(count)
+    }
+
+    /// Check a request against the current policies
+    pub fn authorize(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        client_ip: IpAddr,
+        source_vpc: Option<&SourceVpc>,
+    ) -> AuthzDecision {
+        let Some(path) = normalize_path(uri.path()) else {
+            debug!("Denying {} {}: path can't be normalized", method, uri.path());
+            return AuthzDecision::Deny(problem::error_response(StatusCode::BAD_REQUEST, "Invalid request path"));
+        };
+        let request = match self.request(method, uri, &path, headers, client_ip, source_vpc) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("Denying {} {}: {}", method, path, e);
+                return AuthzDecision::Deny(problem::error_response(StatusCode::FORBIDDEN, "Forbidden by policy"));
+            }
+        };
+        let policies = self.policies.read().unwrap().clone();
+        let response = self.authorizer.is_authorized(&request, &policies, &Entities::empty());
+        let diagnostics = response.diagnostics();
+        for error in diagnostics.errors() {
+            debug!("Policy error authorizing {} {}: {}", method, path, error);
+        }
+        let reasons: Vec<String> = diagnostics.reason().map(ToString::to_string).collect();
+        match response.decision() {
+            Decision::Allow => {
+                debug!("Policies {:?} allowed {} {}", reasons, method, path);
+                AuthzDecision::Allow
+            }
+            Decision::Deny => {
+                debug!("Policies {:?} denied {} {}", reasons, method, path);
+                AuthzDecision::Deny(problem::error_response(StatusCode::FORBIDDEN, "Forbidden by policy"))
+            }
+        }
+    }
+
+    /// Describe a request for the normalized `path` to the policies
+    fn request(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        path: &str,
+        headers: &HeaderMap,
+        client_ip: IpAddr,
+        source_vpc: Option<&SourceVpc>,
+    ) -> Result<Request> {
+        let host = headers
+            .get(HOST)
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| uri.host())
+            .unwrap_or_default();
+        let principal = self
+            .config
+            .identity_header
+            .as_ref()
+            .and_then(|name| headers.get(name.as_str()))
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| !id.is_empty())
+            .map(|id| entity("Router::Client", id))
+            .transpose()?;
+
+        let mut request_headers = Map::new();
+        for name in &self.config.context_headers {
+            if let Some(value) = headers.get(name.as_str()).and_then(|v| v.to_str().ok()) {
+                request_headers.insert(name.clone(), json!(value));
+            }
+        }
+        let mut context = json!({
+            "method": method.as_str(),
+            "host": host,
+            "path": path,
+            "query": uri.query().unwrap_or_default(),
+            "clientIp": { "__extn": { "fn": "ip", "arg": client_ip.to_string() } },
+            "headers": request_headers,
+        });
+        if let Some(vpc) = source_vpc {
+            context["sourceVpc"] = json!({
+                "namespace": vpc.namespace,
+                "name": vpc.name,
+                "attachment": vpc.attachment,
+                "labels": vpc.labels,
+            });
+        }
+        let context = Context::from_json_value(context, None)
+            .map_err(|e| anyhow!("Invalid request context: {}", e))?;
+
+        Ok(Request::new(
+            principal,
+            Some(entity("Router::Action", method.as_str())?),
+            Some(entity("Router::Host", host)?),
+            context,
+        ))
+    }
+}
+
+/// The entity of type `kind` named `id`
+fn entity(kind: &str, id: &str) -> Result<EntityUid> {
+    let kind = EntityTypeName::from_str(kind).map_err(|e| anyhow!("{}", e))?;
+    let id = EntityId::from_str(id).map_err(|e| anyhow!("{}", e))?;
+    Ok(EntityUid::from_type_name_and_id(kind, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    const POLICIES: &str = r#"
+        permit(principal, action == Router::Action::"GET", resource == Router::Host::"api.example.com")
+        when { context.path like "/public/*" };
+
+        permit(principal == Router::Client::"alice", action, resource);
+
+        permit(principal, action, resource)
+        when { context has sourceVpc && context.sourceVpc.labels has team && context.sourceVpc.labels.team == "payments" };
+
+        forbid(principal, action, resource)
+        when { context.clientIp.isInRange(ip("192.0.2.0/24")) };
+    "#;
+
+    fn authorizer() -> PolicyAuthorizer {
+        let authz = PolicyAuthorizer::new(PolicyAuthzConfig {
+            identity_header: Some("x-client-id".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(authz.load(&[("policies/org".to_string(), POLICIES.to_string())]).unwrap(), 4);
+        authz
+    }
+
+    fn allowed(authz: &PolicyAuthorizer, method: Method, uri: &str, client: Option<&str>, ip: [u8; 4], vpc: Option<&SourceVpc>) -> bool {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("api.example.com"));
+        if let Some(client) = client {
+            headers.insert("x-client-id", HeaderValue::from_str(client).unwrap());
+        }
+        let uri: Uri = uri.parse().unwrap();
+        matches!(authz.authorize(&method, &uri, &headers, ip.into(), vpc), AuthzDecision::Allow)
+    }
+
+    #[test]
+    fn test_policies_decide() {
+        let authz = authorizer();
+        assert!(allowed(&authz, Method::GET, "/public/docs", None, [10, 0, 0, 1], None));
+        assert!(!allowed(&authz, Method::POST, "/public/docs", None, [10, 0, 0, 1], None));
+        assert!(!allowed(&authz, Method::GET, "/admin", None, [10, 0, 0, 1], None));
+        assert!(allowed(&authz, Method::DELETE, "/admin", Some("alice"), [10, 0, 0, 1], None));
+
+        let vpc = SourceVpc {
+            attachment: "default/payments-a".to_string(),
+            namespace: "default".to_string(),
+            name: "payments".to_string(),
+            labels: [("team".to_string(), "payments".to_string())].into(),
+        };
+        assert!(allowed(&authz, Method::POST, "/admin", None, [10, 0, 0, 1], Some(&vpc)));
+
+        // Forbid wins over permit
+        assert!(!allowed(&authz, Method::DELETE, "/admin", Some("alice"), [192, 0, 2, 7], None));
+    }
+
+    #[test]
+    fn test_path_spellings_do_not_bypass_policies() {
+        let authz = authorizer();
+        for uri in ["/x/../admin", "//admin", "/%61dmin", "/public/../admin", "/public/%2e%2e/admin"] {
+            assert!(!allowed(&authz, Method::GET, uri, None, [10, 0, 0, 1], None), "{}", uri);
+        }
+        assert!(!allowed(&authz, Method::GET, "/public/..%2fadmin", None, [10, 0, 0, 1], None));
+        assert!(allowed(&authz, Method::GET, "/public//docs", None, [10, 0, 0, 1], None));
+
+        let forbid = r#"
+            permit(principal, action, resource);
+            forbid(principal, action, resource) when { context.path like "/admin*" };
+        "#;
+        authz.load(&[("policies/admin".to_string(), forbid.to_string())]).unwrap();
+        for uri in ["/x/../admin", "//admin", "/%61dmin"] {
+            assert!(!allowed(&authz, Method::GET, uri, None, [10, 0, 0, 1], None), "{}", uri);
+        }
+        assert!(allowed(&authz, Method::GET, "/docs", None, [10, 0, 0, 1], None));
+
+        let scoped = PolicyAuthorizer::new(PolicyAuthzConfig {
+            route_prefixes: vec!["/admin".to_string()],
+            ..Default::default()
+        });
+        for path in ["/x/../admin", "//admin", "/%61dmin", "/x%2f..%2fadmin"] {
+            assert!(scoped.applies_to(path), "{}", path);
+        }
+        assert!(!scoped.applies_to("/public/docs"));
+    }
+
+    #[test]
+    fn test_invalid_policies_keep_current_set() {
+        let authz = authorizer();
+        let err = authz
+            .load(&[("policies/broken".to_string(), "permit(principal, action".to_string())])
+            .unwrap_err();
+        assert!(err.to_string().contains("policies/broken"));
+        assert!(allowed(&authz, Method::GET, "/public/docs", None, [10, 0, 0, 1], None));
+
+        // No policies deny everything
+        authz.load(&[]).unwrap();
+        assert!(!allowed(&authz, Method::GET, "/public/docs", None, [10, 0, 0, 1], None));
+    }
+}
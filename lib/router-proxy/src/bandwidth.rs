@@ -0,0 +1,309 @@
+//! Bandwidth throttling for response bodies
+//!
+//! Token buckets measured in bytes pace response data per route and per
+//! client connection, so large downloads on one route can't saturate the
+//! edge link. Pacing happens as the body is written, one chunk at a time.
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use router_api::v1alpha1::vpc_route::BandwidthPolicy;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// Largest chunk written before pacing
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Bandwidth limit configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BandwidthConfig {
+    /// Bytes per second shared by all responses on the route
+    pub bytes_per_second: Option<u64>,
+    /// Bytes per second for each client connection
+    pub per_connection_bytes_per_second: Option<u64>,
+    /// Burst allowance in bytes (defaults to one second of traffic)
+    pub burst_bytes: Option<u64>,
+}
+
+impl BandwidthConfig {
+    /// Create a configuration from a VPCRoute bandwidth policy
+    pub fn from_policy(policy: &BandwidthPolicy) -> Self {
+        Self {
+            bytes_per_second: policy.bytes_per_second,
+            per_connection_bytes_per_second: policy.per_connection_bytes_per_second,
+            burst_bytes: policy.burst_bytes,
+        }
+    }
+}
+
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Byte-denominated token bucket
+///
+/// Reservations may overdraw the bucket; the caller waits out the debt,
+/// which spreads a shared rate fairly across concurrent transfers.
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    /// Create a full bucket refilling at `bytes_per_second`
+    pub fn new(bytes_per_second: u64, burst_bytes: u64) -> Self {
+        let burst = burst_bytes.max(1) as f64;
+        Self {
+            rate: bytes_per_second.max(1) as f64,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `bytes` tokens and return how long to wait before sending them
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.burst);
+        state.updated = now;
+
+        state.tokens -= bytes as f64;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+}
+
+/// Bandwidth limits of a route
+pub struct BandwidthLimiter {
+    config: BandwidthConfig,
+    route_bucket: Option<Arc<TokenBucket>>,
+}
+
+impl BandwidthLimiter {
+    /// Create a limiter for the responses of a route
+    pub fn new(config: BandwidthConfig) -> Self {
+        let route_bucket = config
+            .bytes_per_second
+            .map(|rate| Arc::new(TokenBucket::new(rate, config.burst_bytes.unwrap_or(rate))));
+        Self {
+            config,
+            route_bucket,
+        }
+    }
+
+    /// Get the limiter configuration
+    pub fn config(&self) -> &BandwidthConfig {
+        &self.config
+    }
+
+    /// Create the bucket for a new client connection, if limited per connection
+    pub fn connection_bucket(&self) -> Option<Arc<TokenBucket>> {
+        self.config
+            .per_connection_bytes_per_second
+            .map(|rate| Arc::new(TokenBucket::new(rate, self.config.burst_bytes.unwrap_or(rate))))
+    }
+
+    /// Pace a response body by the route bucket and a connection bucket
+    pub fn throttle<B>(&self, body: B, connection: Option<&Arc<TokenBucket>>) -> ThrottledBody<B> {
+        let buckets = self.route_bucket.iter().chain(connection).cloned().collect();
+        ThrottledBody::new(body, buckets)
+    }
+}
+
+/// Buckets of one client connection, one for each route it has used that
+/// limits bandwidth per connection
+#[derive(Default)]
+pub struct ConnectionBuckets {
+    buckets: Mutex<HashMap<String, Arc<TokenBucket>>>,
+}
+
+impl ConnectionBuckets {
+    /// The connection's bucket for `route`, created by its limiter on first use
+    pub fn bucket(&self, route: &str, limiter: &BandwidthLimiter) -> Option<Arc<TokenBucket>> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bucket) = buckets.get(route) {
+            return Some(bucket.clone());
+        }
+        let bucket = limiter.connection_bucket()?;
+        buckets.insert(route.to_string(), bucket.clone());
+        Some(bucket)
+    }
+}
+
+/// Response body paced by token buckets
+///
+/// With no buckets, frames pass through unchanged.
+pub struct ThrottledBody<B> {
+    inner: B,
+    buckets: Vec<Arc<TokenBucket>>,
+    /// Data not yet reserved against the buckets
+    pending: Bytes,
+    /// Reserved chunk to send once `sleep` completes
+    ready: Option<Bytes>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> ThrottledBody<B> {
+    /// Wrap a body, pacing it by `buckets`
+    pub fn new(inner: B, buckets: Vec<Arc<TokenBucket>>) -> Self {
+        Self {
+            inner,
+            buckets,
+            pending: Bytes::new(),
+            ready: None,
+            sleep: None,
+        }
+    }
+
+    /// Wrap a body without throttling it
+    pub fn unlimited(inner: B) -> Self {
+        Self::new(inner, Vec::new())
+    }
+}
+
+impl<B> Body for ThrottledBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(sleep) = this.sleep.as_mut() {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.sleep = None;
+            }
+
+            if let Some(chunk) = this.ready.take() {
+                return Poll::Ready(Some(Ok(Frame::data(chunk))));
+            }
+
+            if !this.pending.is_empty() {
+                let chunk = this.pending.split_to(this.pending.len().min(CHUNK_SIZE));
+                let wait = this
+                    .buckets
+                    .iter()
+                    .map(|bucket| bucket.reserve(chunk.len()))
+                    .max()
+                    .unwrap_or_default();
+                if !wait.is_zero() {
+                    this.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+                }
+                this.ready = Some(chunk);
+                continue;
+            }
+
+            match Pin::new(&mut this.inner).poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                    Ok(data) if this.buckets.is_empty() => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                    Ok(data) => this.pending = data,
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return other,
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_empty() && self.ready.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        let buffered = (self.pending.len() + self.ready.as_ref().map_or(0, Bytes::len)) as u64;
+        hint.set_lower(hint.lower() + buffered);
+        if let Some(upper) = hint.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+
+    #[test]
+    fn test_bucket_allows_burst_then_paces() {
+        let bucket = TokenBucket::new(1000, 1000);
+        assert_eq!(bucket.reserve(1000), Duration::ZERO);
+
+        let wait = bucket.reserve(500);
+        assert!(wait > Duration::from_millis(450) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_limiter_buckets() {
+        let limiter = BandwidthLimiter::new(BandwidthConfig {
+            bytes_per_second: Some(1_000_000),
+            per_connection_bytes_per_second: None,
+            burst_bytes: None,
+        });
+        assert!(limiter.connection_bucket().is_none());
+        assert!(ConnectionBuckets::default().bucket("default/downloads", &limiter).is_none());
+    }
+
+    #[test]
+    fn test_connection_buckets_per_route() {
+        let limiter = BandwidthLimiter::new(BandwidthConfig {
+            per_connection_bytes_per_second: Some(1000),
+            ..Default::default()
+        });
+        let connection = ConnectionBuckets::default();
+        let downloads = connection.bucket("default/downloads", &limiter).unwrap();
+        assert!(Arc::ptr_eq(&downloads, &connection.bucket("default/downloads", &limiter).unwrap()));
+        assert!(!Arc::ptr_eq(&downloads, &connection.bucket("default/media", &limiter).unwrap()));
+        assert!(!Arc::ptr_eq(&downloads, &ConnectionBuckets::default().bucket("default/downloads", &limiter).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_body_passes_through() {
+        let body = ThrottledBody::unlimited(Full::new(Bytes::from("hello")));
+        let collected = body.collect().await.unwrap().to_bytes();
+        assert_eq!(collected, Bytes::from("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_throttled_body_is_paced() {
+        // 3000 bytes at 10 KB/s with a 1000 byte burst: ~200ms of pacing
+        let data = Bytes::from(vec![7u8; 3000]);
+        let bucket = Arc::new(TokenBucket::new(10_000, 1000));
+        let body = ThrottledBody::new(Full::new(data.clone()), vec![bucket]);
+
+        let started = Instant::now();
+        let collected = body.collect().await.unwrap().to_bytes();
+        assert_eq!(collected, data);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_config_from_policy() {
+        let config = BandwidthConfig::from_policy(&BandwidthPolicy {
+            bytes_per_second: Some(1024),
+            per_connection_bytes_per_second: Some(256),
+            burst_bytes: None,
+        });
+        assert_eq!(config.bytes_per_second, Some(1024));
+        assert_eq!(config.per_connection_bytes_per_second, Some(256));
+    }
+}
@@ -0,0 +1,506 @@
+//! In-memory HTTP response cache
+//!
+//! GET responses on caching routes are stored in a bounded LRU cache,
+//! shared by all routes, for as long as their Cache-Control max-age (or
+//! s-maxage) allows, capped by the route's maximum TTL. Responses that are private, set cookies, or
+//! vary by request header are never stored, and requests carrying
+//! credentials or asking to bypass caches always reach the backend. A
+//! stale entry with validators is revalidated: the backend's 304 refreshes
+//! it instead of sending the body again.
+
+use crate::etag::{cache_control, ConditionalResponder, Validators};
+use crate::metrics::MetricsCollector;
+use anyhow::{Result, anyhow};
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderValue, AGE, AUTHORIZATION, CACHE_CONTROL, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED, SET_COOKIE, VARY,
+};
+use hyper::http::response;
+use hyper::{Method, Response, StatusCode};
+use lru::LruCache;
+use router_api::v1alpha1::vpc_route::ResponseCachePolicy;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Caching limits of a route
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheConfig {
+    /// Largest response body cached, in bytes
+    pub max_body_bytes: usize,
+    /// Longest time a response is served from cache
+    pub max_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 1024 * 1024,
+            max_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Create a configuration from a VPCRoute cache policy
+    ///
+    /// Limits the policy leaves out keep their defaults.
+    pub fn from_policy(policy: &ResponseCachePolicy) -> Self {
+        let mut config = Self::default();
+        if let Some(secs) = policy.max_ttl_seconds {
+            config.max_ttl = Duration::from_secs(secs as u64);
+        }
+        if let Some(bytes) = policy.max_body_bytes {
+            config.max_body_bytes = bytes as usize;
+        }
+        config
+    }
+}
+
+/// Result of looking a request up in the cache
+#[derive(Debug)]
+pub enum CacheLookup {
+    /// A fresh response, or a 304 if it matches the request's conditionals
+    Hit(Response<Bytes>),
+    /// A stale response; ask the backend whether these validators are current
+    Revalidate(Validators),
+    /// Nothing usable cached
+    Miss,
+}
+
+/// A cached response
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+    fresh_until: Instant,
+}
+
+impl CachedResponse {
+    fn validators(&self) -> Validators {
+        Validators::from_headers(&self.headers)
+    }
+
+    fn response(&self, now: Instant) -> Response<Bytes> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        let age = now.duration_since(self.stored).as_secs();
+        response.headers_mut().insert(AGE, HeaderValue::from(age));
+        response
+    }
+}
+
+/// Bounded LRU cache of backend responses
+pub struct ResponseCache {
+    entries: Mutex<LruCache<String, CachedResponse>>,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl ResponseCache {
+    /// Create a response cache holding up to `capacity` responses
+    pub fn new(capacity: usize) -> Result<Self> {
+        let capacity =
+            NonZeroUsize::new(capacity).ok_or_else(|| anyhow!("Response cache capacity must be greater than 0"))?;
+        // Grow as responses are stored rather than reserving every slot up front
+        let mut entries = LruCache::unbounded();
+        entries.resize(capacity);
+        Ok(Self {
+            entries: Mutex::new(entries),
+            metrics: None,
+        })
+    }
+
+    /// Count lookups by result through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Maximum number of cached responses
+    pub fn capacity(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).cap().get()
+    }
+
+    /// Number of cached responses
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Whether nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Look up the response for a request
+    ///
+    /// Stale entries are only offered for revalidation when the request
+    /// has no conditionals of its own, so a backend 304 is always ours.
+    pub fn lookup(&self, key: &str, method: &Method, headers: &HeaderMap) -> CacheLookup {
+        if !Self::is_cacheable_request(method, headers) {
+            self.record("bypass");
+            return CacheLookup::Miss;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = entries.get(key) else {
+            self.record("miss");
+            return CacheLookup::Miss;
+        };
+        let now = Instant::now();
+        if now < entry.fresh_until {
+            self.record("hit");
+            let validators = entry.validators();
+            if validators.not_modified(headers) {
+                return CacheLookup::Hit(ConditionalResponder::not_modified_response(&validators));
+            }
+            return CacheLookup::Hit(entry.response(now));
+        }
+
+        let validators = entry.validators();
+        if validators.is_empty() || headers.contains_key(IF_NONE_MATCH) || headers.contains_key(IF_MODIFIED_SINCE) {
+            entries.pop(key);
+            self.record("miss");
+            return CacheLookup::Miss;
+        }
+        self.record("stale");
+        CacheLookup::Revalidate(validators)
+    }
+
+    /// Store a backend response if it may be served to other clients,
+    /// within the limits of the route's `config`
+    ///
+    /// Returns whether the response was stored.
+    pub fn store(
+        &self,
+        key: &str,
+        config: &CacheConfig,
+        method: &Method,
+        request_headers: &HeaderMap,
+        response: &response::Parts,
+        body: &Bytes,
+    ) -> bool {
+        let headers = &response.headers;
+        if !Self::is_cacheable_request(method, request_headers)
+            || response.status != StatusCode::OK
+            || body.len() > config.max_body_bytes
+        {
+            return false;
+        }
+        let Some(fresh_for) = Self::freshness(config, headers) else {
+            return false;
+        };
+        // Entries that are never fresh are only worth keeping to revalidate
+        if fresh_for.is_zero() && Validators::from_headers(headers).is_empty() {
+            return false;
+        }
+
+        let now = Instant::now();
+        let entry = CachedResponse {
+            status: response.status,
+            headers: headers.clone(),
+            body: body.clone(),
+            stored: now,
+            fresh_until: now + fresh_for,
+        };
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .put(key.to_string(), entry);
+        true
+    }
+
+    /// Refresh a stale entry from the backend's 304 and return it
+    ///
+    /// Returns None if the entry was evicted in the meantime.
+    pub fn revalidated(&self, key: &str, config: &CacheConfig, not_modified: &HeaderMap) -> Option<Response<Bytes>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = entries.get_mut(key)?;
+        for name in [CACHE_CONTROL, DATE, ETAG, EXPIRES, LAST_MODIFIED] {
+            if let Some(value) = not_modified.get(&name) {
+                entry.headers.insert(name, value.clone());
+            }
+        }
+        let now = Instant::now();
+        entry.stored = now;
+        entry.fresh_until = now + Self::freshness(config, &entry.headers).unwrap_or_default();
+        self.record("revalidated");
+        Some(entry.response(now))
+    }
+
+    /// Remove cached responses whose path starts with `prefix`, or all of them
+    ///
+    /// Returns how many were removed.
+    pub fn purge(&self, prefix: Option<&str>) -> usize {
+        let Some(prefix) = prefix else {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            let purged = entries.len();
+            entries.clear();
+            return purged;
+        };
+        self.purge_where(|path| path.starts_with(prefix))
+    }
+
+    /// Remove cached responses whose path, without the query, matches
+    /// `pattern`, in which `*` stands for any part of a segment
+    /// ("/catalog/*/images" matches "/catalog/shoes/images")
+    ///
+    /// Returns how many were removed.
+    pub fn purge_matching(&self, pattern: &str) -> usize {
+        self.purge_where(|path| path_matches(pattern, path.split('?').next().unwrap_or_default()))
+    }
+
+    /// Remove cached responses whose path, with the query, passes `matches`
+    ///
+    /// Returns how many were removed.
+    pub fn purge_where(&self, matches: impl Fn(&str) -> bool) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let purged: Vec<String> = entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| matches(key_path(key)))
+            .cloned()
+            .collect();
+        for key in &purged {
+            entries.pop(key);
+        }
+        purged.len()
+    }
+
+    /// Whether a request may be answered from, and stored in, a shared cache
+    fn is_cacheable_request(method: &Method, headers: &HeaderMap) -> bool {
+        if *method != Method::GET || headers.contains_key(AUTHORIZATION) {
+            return false;
+        }
+        !cache_control(headers).iter().any(|d| d == "no-store" || d == "no-cache")
+    }
+
+    /// How long a response may be served to any client, or None if it can't be stored
+    fn freshness(config: &CacheConfig, headers: &HeaderMap) -> Option<Duration> {
+        let directives = cache_control(headers);
+        let private = directives
+            .iter()
+            .any(|d| d == "no-store" || d == "private" || d.starts_with("private="));
+        if private || headers.contains_key(SET_COOKIE) || headers.contains_key(VARY) {
+            return None;
+        }
+        if directives.iter().any(|d| d == "no-cache") {
+            return Some(Duration::ZERO);
+        }
+
+        let max_age = ["s-maxage", "max-age"].iter().find_map(|name| {
+            directives
+                .iter()
+                .find_map(|d| d.strip_prefix(name)?.strip_prefix('=')?.parse::<u64>().ok())
+        })?;
+        let age = headers
+            .get(AGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+        Some(Duration::from_secs(max_age.saturating_sub(age)).min(config.max_ttl))
+    }
+
+    fn record(&self, result: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_cache_lookup(result);
+        }
+    }
+}
+
+/// Path part of a cache key ("host/path?query")
+fn key_path(key: &str) -> &str {
+    key.find('/').map_or("", |i| &key[i..])
+}
+
+/// Whether `path` matches `pattern` segment by segment
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut patterns = pattern.split('/');
+    let mut segments = path.split('/');
+    loop {
+        match (patterns.next(), segments.next()) {
+            (None, None) => return true,
+            (Some(pattern), Some(segment)) if segment_matches(pattern.as_bytes(), segment.as_bytes()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Whether `segment` matches `pattern`, in which `*` stands for any run of bytes
+fn segment_matches(pattern: &[u8], segment: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Position after the last `*`, and where in the segment it resumes
+    let mut star = None;
+    while s < segment.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            star = Some((p, s));
+        } else if p < pattern.len() && pattern[p] == segment[s] {
+            p += 1;
+            s += 1;
+        } else if let Some((star_p, star_s)) = star {
+            p = star_p;
+            s = star_s + 1;
+            star = Some((star_p, s));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> ResponseCache {
+        ResponseCache::new(100).unwrap()
+    }
+
+    fn config() -> CacheConfig {
+        CacheConfig {
+            max_ttl: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn store(cache: &ResponseCache, key: &str, response_headers: &HeaderMap) -> bool {
+        let (mut response, _) = Response::new(()).into_parts();
+        response.headers = response_headers.clone();
+        cache.store(key, &config(), &Method::GET, &HeaderMap::new(), &response, &Bytes::from("body"))
+    }
+
+    #[test]
+    fn test_fresh_responses_are_served() {
+        let cache = cache();
+        let response_headers = headers(&[("cache-control", "public, max-age=30"), ("etag", "\"v1\"")]);
+        assert!(store(&cache, "example.com/api/items", &response_headers));
+
+        let CacheLookup::Hit(response) = cache.lookup("example.com/api/items", &Method::GET, &HeaderMap::new()) else {
+            panic!("expected a hit");
+        };
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &Bytes::from("body"));
+        assert_eq!(response.headers()[AGE], "0");
+
+        // A client holding the current copy gets a 304
+        let revalidation = headers(&[("if-none-match", "\"v1\"")]);
+        let CacheLookup::Hit(response) = cache.lookup("example.com/api/items", &Method::GET, &revalidation) else {
+            panic!("expected a hit");
+        };
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // Other methods and authorized requests bypass the cache
+        assert!(matches!(cache.lookup("example.com/api/items", &Method::POST, &HeaderMap::new()), CacheLookup::Miss));
+        let authorized = headers(&[("authorization", "Bearer token")]);
+        assert!(matches!(cache.lookup("example.com/api/items", &Method::GET, &authorized), CacheLookup::Miss));
+    }
+
+    #[test]
+    fn test_uncacheable_responses() {
+        let cache = cache();
+        assert!(!store(&cache, "h/a", &headers(&[("cache-control", "no-store")])));
+        assert!(!store(&cache, "h/b", &headers(&[("cache-control", "private, max-age=60")])));
+        assert!(!store(&cache, "h/c", &headers(&[("cache-control", "max-age=60"), ("set-cookie", "id=1")])));
+        assert!(!store(&cache, "h/d", &headers(&[("cache-control", "max-age=60"), ("vary", "accept-language")])));
+        // No freshness information: nothing to go on
+        assert!(!store(&cache, "h/e", &HeaderMap::new()));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_stale_entries_are_revalidated() {
+        let cache = cache();
+        let response_headers = headers(&[("cache-control", "no-cache"), ("etag", "\"v1\"")]);
+        assert!(store(&cache, "h/api/doc", &response_headers));
+
+        let CacheLookup::Revalidate(validators) = cache.lookup("h/api/doc", &Method::GET, &HeaderMap::new()) else {
+            panic!("expected revalidation");
+        };
+        let mut upstream_headers = HeaderMap::new();
+        validators.add_conditional_headers(&mut upstream_headers);
+        assert_eq!(upstream_headers[IF_NONE_MATCH], "\"v1\"");
+
+        let not_modified = headers(&[("cache-control", "max-age=30"), ("etag", "\"v1\"")]);
+        let response = cache.revalidated("h/api/doc", &config(), &not_modified).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &Bytes::from("body"));
+        assert!(matches!(cache.lookup("h/api/doc", &Method::GET, &HeaderMap::new()), CacheLookup::Hit(_)));
+    }
+
+    #[test]
+    fn test_capacity_evicts_least_recently_used() {
+        let cache = ResponseCache::new(2).unwrap();
+        let response_headers = headers(&[("cache-control", "max-age=30")]);
+        for key in ["h/a", "h/b", "h/c"] {
+            assert!(store(&cache, key, &response_headers));
+        }
+        assert_eq!((cache.len(), cache.capacity()), (2, 2));
+        assert!(matches!(cache.lookup("h/a", &Method::GET, &HeaderMap::new()), CacheLookup::Miss));
+        assert!(matches!(cache.lookup("h/c", &Method::GET, &HeaderMap::new()), CacheLookup::Hit(_)));
+        assert!(ResponseCache::new(0).is_err());
+    }
+
+    #[test]
+    fn test_purge() {
+        let cache = cache();
+        let response_headers = headers(&[("cache-control", "max-age=30")]);
+        for key in ["h/api/a", "h/api/b", "h/static/c"] {
+            store(&cache, key, &response_headers);
+        }
+        assert_eq!(cache.purge(Some("/api/")), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.purge(None), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_purge_matching() {
+        let cache = cache();
+        let response_headers = headers(&[("cache-control", "max-age=30")]);
+        let keys = [
+            "h/catalog/shoes/images",
+            "h/catalog/hats/images?size=2",
+            "h/catalog/shoes/reviews",
+            "h/catalog/a/b/images",
+        ];
+        for key in keys {
+            store(&cache, key, &response_headers);
+        }
+        assert_eq!(cache.purge_matching("/catalog/*/images"), 2);
+        assert_eq!(cache.purge_matching("/catalog/sh*s/rev*"), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_path_matches() {
+        assert!(path_matches("/api/*", "/api/items"));
+        assert!(path_matches("/api/*", "/api/"));
+        assert!(!path_matches("/api/*", "/api/items/7"));
+        assert!(path_matches("/*/items/*.json", "/v2/items/7.json"));
+        assert!(!path_matches("/*/items/*.json", "/v2/items/7.xml"));
+        assert!(path_matches("/a*b*c", "/aXXbYbc"));
+        assert!(!path_matches("/a*b*c", "/aXXbYb"));
+    }
+
+    #[test]
+    fn test_config_from_policy() {
+        let config = CacheConfig::from_policy(&ResponseCachePolicy {
+            enabled: true,
+            max_ttl_seconds: Some(10),
+            max_body_bytes: None,
+        });
+        assert_eq!(config.max_ttl, Duration::from_secs(10));
+        assert_eq!(config.max_body_bytes, CacheConfig::default().max_body_bytes);
+    }
+}
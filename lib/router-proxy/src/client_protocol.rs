@@ -0,0 +1,229 @@
+//! Client protocol version handling
+//!
+//! HTTP/1.0 clients (often legacy health checkers and load balancer
+//! probes) don't understand chunked transfer coding and only keep a
+//! connection open when they ask to. Responses to them are sent with a
+//! Content-Length and an explicit Connection header. Versions older than a
+//! configured minimum can be refused with 505.
+
+use crate::problem::ProblemDetails;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderValue, CONNECTION, CONTENT_LENGTH};
+use hyper::{Response, StatusCode, Version};
+
+/// Hop-by-hop response headers that must not reach an HTTP/1.0 client
+const HOP_BY_HOP: [&str; 6] = ["connection", "keep-alive", "proxy-authenticate", "trailer", "transfer-encoding", "upgrade"];
+
+/// Client protocol configuration
+#[derive(Clone, Debug)]
+pub struct ClientProtocolConfig {
+    /// Oldest HTTP version accepted from clients
+    pub min_version: Version,
+}
+
+impl Default for ClientProtocolConfig {
+    fn default() -> Self {
+        Self {
+            min_version: Version::HTTP_10,
+        }
+    }
+}
+
+/// Applies client protocol rules to requests and responses
+#[derive(Clone, Debug, Default)]
+pub struct ClientProtocol {
+    config: ClientProtocolConfig,
+}
+
+impl ClientProtocol {
+    /// Create a handler
+    pub fn new(config: ClientProtocolConfig) -> Self {
+        Self { config }
+    }
+
+    /// Parse a version setting such as "1.0", "HTTP/1.1", or "2"
+    pub fn parse_version(value: &str) -> Option<Version> {
+        let value = value.trim();
+        let value = value
+            .strip_prefix("HTTP/")
+            .or_else(|| value.strip_prefix("http/"))
+            .unwrap_or(value);
+        match value {
+            "0.9" => Some(Version::HTTP_09),
+            "1.0" => Some(Version::HTTP_10),
+            "1.1" => Some(Version::HTTP_11),
+            "2" | "2.0" => Some(Version::HTTP_2),
+            "3" | "3.0" => Some(Version::HTTP_3),
+            _ => None,
+        }
+    }
+
+    /// Whether a client's HTTP version is accepted
+    pub fn is_allowed(&self, version: Version) -> bool {
+        version >= self.config.min_version
+    }
+
+    /// 505 response for refused protocol versions
+    pub fn version_not_supported_response() -> Response<Bytes> {
+        let status = StatusCode::HTTP_VERSION_NOT_SUPPORTED;
+        Response::builder()
+            .status(status)
+            .header(CONNECTION, "close")
+            .extension(ProblemDetails::new(status, "HTTP version not supported"))
+            .body(Bytes::from("HTTP Version Not Supported\n"))
+            .unwrap()
+    }
+
+    /// Whether the client expects the connection to stay open
+    ///
+    /// HTTP/1.0 connections close unless the client sent
+    /// `Connection: keep-alive`; HTTP/1.1 connections stay open unless it
+    /// sent `Connection: close`.
+    pub fn wants_keep_alive(version: Version, request_headers: &HeaderMap) -> bool {
+        let has_token = |token: &str| {
+            request_headers
+                .get_all(CONNECTION)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+        match version {
+            Version::HTTP_09 => false,
+            Version::HTTP_10 => has_token("keep-alive"),
+            _ => !has_token("close"),
+        }
+    }
+
+    /// Make a buffered response safe for an HTTP/1.0 client
+    ///
+    /// Removes hop-by-hop headers left by the upstream, sets Content-Length
+    /// from the body, and states whether the connection stays open.
+    /// Responses to newer clients are left alone.
+    pub fn prepare_response(version: Version, keep_alive: bool, headers: &mut HeaderMap, body_len: u64) {
+        if version > Version::HTTP_10 {
+            return;
+        }
+        for name in HOP_BY_HOP {
+            headers.remove(name);
+        }
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        headers.insert(CONNECTION, HeaderValue::from_static(connection));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use hyper::header::TRANSFER_ENCODING;
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::Request;
+    use hyper_util::rt::TokioIo;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_min_version() {
+        let protocol = ClientProtocol::new(ClientProtocolConfig {
+            min_version: ClientProtocol::parse_version("HTTP/1.1").unwrap(),
+        });
+        assert!(!protocol.is_allowed(Version::HTTP_10));
+        assert!(protocol.is_allowed(Version::HTTP_11));
+        assert!(protocol.is_allowed(Version::HTTP_2));
+        assert!(ClientProtocol::default().is_allowed(Version::HTTP_10));
+        assert_eq!(ClientProtocol::parse_version("1.2"), None);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let mut headers = HeaderMap::new();
+        assert!(!ClientProtocol::wants_keep_alive(Version::HTTP_10, &headers));
+        assert!(ClientProtocol::wants_keep_alive(Version::HTTP_11, &headers));
+
+        headers.insert(CONNECTION, HeaderValue::from_static("Keep-Alive"));
+        assert!(ClientProtocol::wants_keep_alive(Version::HTTP_10, &headers));
+
+        headers.insert(CONNECTION, HeaderValue::from_static("close"));
+        assert!(!ClientProtocol::wants_keep_alive(Version::HTTP_11, &headers));
+    }
+
+    #[test]
+    fn test_prepare_response_for_http10() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        ClientProtocol::prepare_response(Version::HTTP_10, false, &mut headers, 3);
+        assert!(!headers.contains_key(TRANSFER_ENCODING));
+        assert!(!headers.contains_key("keep-alive"));
+        assert_eq!(headers[CONTENT_LENGTH], "3");
+        assert_eq!(headers[CONNECTION], "close");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        ClientProtocol::prepare_response(Version::HTTP_11, true, &mut headers, 3);
+        assert!(headers.contains_key(TRANSFER_ENCODING));
+    }
+
+    /// Serve one connection, answering like the gateway's health endpoint
+    async fn serve_health(listener: TcpListener, protocol: ClientProtocol) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let service = service_fn(move |req: Request<Incoming>| {
+            let protocol = protocol.clone();
+            async move {
+                let version = req.version();
+                let keep_alive = ClientProtocol::wants_keep_alive(version, req.headers());
+                let response = if protocol.is_allowed(version) {
+                    let mut response = Response::new(Bytes::from("OK\n"));
+                    response.headers_mut().insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+                    response
+                } else {
+                    ClientProtocol::version_not_supported_response()
+                };
+                let (mut parts, body) = response.into_parts();
+                ClientProtocol::prepare_response(version, keep_alive, &mut parts.headers, body.len() as u64);
+                Ok::<_, hyper::Error>(Response::from_parts(parts, Full::new(body)))
+            }
+        });
+        let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+    }
+
+    async fn legacy_request(protocol: ClientProtocol) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_health(listener, protocol));
+
+        // A bare HTTP/1.0 probe: no Host, no keep-alive
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /healthz HTTP/1.0\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        // The server must close the connection for this to finish
+        tokio::time::timeout(std::time::Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .expect("connection left open for HTTP/1.0 client")
+            .unwrap();
+        response.to_ascii_lowercase()
+    }
+
+    #[tokio::test]
+    async fn test_legacy_health_check_client() {
+        let response = legacy_request(ClientProtocol::default()).await;
+        assert!(response.contains(" 200 ok"));
+        assert!(response.contains("content-length: 3"));
+        assert!(response.contains("connection: close"));
+        assert!(!response.contains("transfer-encoding"));
+        assert!(response.ends_with("ok\n"));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_client_rejected() {
+        let protocol = ClientProtocol::new(ClientProtocolConfig {
+            min_version: Version::HTTP_11,
+        });
+        let response = legacy_request(protocol).await;
+        assert!(response.contains(" 505 http version not supported"));
+    }
+}
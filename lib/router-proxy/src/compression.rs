@@ -0,0 +1,309 @@
+//! Response compression at the edge
+//!
+//! Compresses eligible responses with brotli or gzip according to the
+//! client's Accept-Encoding. Small bodies, already-encoded responses, and
+//! content types that don't compress well are passed through unchanged.
+
+use crate::metrics::MetricsCollector;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, VARY,
+};
+use hyper::StatusCode;
+use std::io::Write;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Response compression configuration
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    /// Smallest body worth compressing, in bytes
+    pub min_size: usize,
+    /// gzip level (0-9)
+    pub gzip_level: u32,
+    /// Brotli quality (0-11)
+    pub brotli_quality: u32,
+    /// Content type prefixes eligible for compression
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            gzip_level: 6,
+            brotli_quality: 4,
+            content_types: [
+                "text/",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "application/graphql-response+json",
+                "image/svg+xml",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        }
+    }
+}
+
+/// Content coding applied to a response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Brotli (br)
+    Brotli,
+    /// gzip
+    Gzip,
+}
+
+impl Encoding {
+    /// Content-Encoding token
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Choose the preferred supported coding from an Accept-Encoding value
+    ///
+    /// Brotli wins ties. Codings with q=0 are refused, and `*` stands for
+    /// any coding not listed.
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut brotli: Option<f32> = None;
+        let mut gzip: Option<f32> = None;
+        let mut wildcard: Option<f32> = None;
+
+        for item in accept_encoding.split(',') {
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match coding.as_str() {
+                "br" => brotli = Some(q),
+                "gzip" | "x-gzip" => gzip = Some(q),
+                "*" => wildcard = Some(q),
+                _ => {}
+            }
+        }
+
+        let brotli = brotli.or(wildcard).unwrap_or(0.0);
+        let gzip = gzip.or(wildcard).unwrap_or(0.0);
+        if brotli <= 0.0 && gzip <= 0.0 {
+            None
+        } else if brotli >= gzip {
+            Some(Encoding::Brotli)
+        } else {
+            Some(Encoding::Gzip)
+        }
+    }
+}
+
+/// Compresses responses for clients that accept it
+pub struct ResponseCompressor {
+    config: CompressionConfig,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl ResponseCompressor {
+    /// Create a compressor
+    pub fn new(config: CompressionConfig) -> Self {
+        Self { config, metrics: None }
+    }
+
+    /// Record bytes saved through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Whether a content type is eligible for compression
+    pub fn is_compressible(&self, content_type: Option<&str>) -> bool {
+        let Some(content_type) = content_type else {
+            return false;
+        };
+        let content_type = content_type.to_ascii_lowercase();
+        self.config
+            .content_types
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix.as_str()))
+    }
+
+    /// Compress a response body if the client and response allow it
+    ///
+    /// Returns the body to send; headers are updated to match. Strong
+    /// ETags become weak, since the bytes no longer match the original.
+    pub fn compress(
+        &self,
+        accept_encoding: Option<&HeaderValue>,
+        status: StatusCode,
+        headers: &mut HeaderMap,
+        body: Bytes,
+    ) -> Bytes {
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || headers.contains_key(CONTENT_ENCODING)
+            || !self.is_compressible(headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()))
+        {
+            return body;
+        }
+        if headers
+            .get(CACHE_CONTROL)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.to_ascii_lowercase().contains("no-transform"))
+        {
+            return body;
+        }
+
+        // The representation depends on Accept-Encoding from here on
+        headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+        if body.len() < self.config.min_size {
+            return body;
+        }
+        let Some(encoding) = accept_encoding
+            .and_then(|v| v.to_str().ok())
+            .and_then(Encoding::negotiate)
+        else {
+            return body;
+        };
+
+        let compressed = match self.encode(encoding, &body) {
+            Ok(compressed) if compressed.len() < body.len() => compressed,
+            Ok(_) => return body,
+            Err(e) => {
+                debug!("Failed to {} compress response: {}", encoding.as_str(), e);
+                return body;
+            }
+        };
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_compression(encoding.as_str(), body.len(), compressed.len());
+        }
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+        headers.remove(CONTENT_LENGTH);
+        if let Some(etag) = headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                    headers.insert(ETAG, weak);
+                }
+            }
+        }
+        Bytes::from(compressed)
+    }
+
+    fn encode(&self, encoding: Encoding, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::new(self.config.gzip_level.min(9)));
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, self.config.brotli_quality.min(11), 22);
+                encoder.write_all(body)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn json_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("4000"));
+        headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+        headers
+    }
+
+    fn body() -> Bytes {
+        Bytes::from("{\"items\": [1, 2, 3]}".repeat(200))
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("gzip;q=1.0, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("br;q=0, gzip;q=0"), None);
+        assert_eq!(Encoding::negotiate("*;q=0.5, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("identity"), None);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let compressor = ResponseCompressor::new(CompressionConfig::default()).with_metrics(metrics.clone());
+        let mut headers = json_headers();
+        let accept = HeaderValue::from_static("gzip");
+
+        let compressed = compressor.compress(Some(&accept), StatusCode::OK, &mut headers, body());
+        assert_eq!(headers[CONTENT_ENCODING], "gzip");
+        assert_eq!(headers[VARY], "Accept-Encoding");
+        assert_eq!(headers[ETAG], "W/\"abc\"");
+        assert!(!headers.contains_key(CONTENT_LENGTH));
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(&compressed[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body());
+
+        let saved = metrics.compression_bytes_saved_total.with_label_values(&["gzip"]).get();
+        assert_eq!(saved, (body().len() - compressed.len()) as f64);
+    }
+
+    #[test]
+    fn test_brotli_round_trip() {
+        let compressor = ResponseCompressor::new(CompressionConfig::default());
+        let mut headers = json_headers();
+        let accept = HeaderValue::from_static("gzip, br");
+
+        let compressed = compressor.compress(Some(&accept), StatusCode::OK, &mut headers, body());
+        assert_eq!(headers[CONTENT_ENCODING], "br");
+
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, body());
+    }
+
+    #[test]
+    fn test_ineligible_responses_unchanged() {
+        let compressor = ResponseCompressor::new(CompressionConfig::default());
+        let accept = HeaderValue::from_static("gzip");
+
+        // Too small
+        let mut headers = json_headers();
+        let small = Bytes::from_static(b"{}");
+        assert_eq!(compressor.compress(Some(&accept), StatusCode::OK, &mut headers, small.clone()), small);
+        assert!(!headers.contains_key(CONTENT_ENCODING));
+        assert_eq!(headers[VARY], "Accept-Encoding");
+
+        // Not a compressible type
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        compressor.compress(Some(&accept), StatusCode::OK, &mut headers, body());
+        assert!(!headers.contains_key(CONTENT_ENCODING));
+
+        // Already encoded
+        let mut headers = json_headers();
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+        assert_eq!(compressor.compress(Some(&accept), StatusCode::OK, &mut headers, body()), body());
+
+        // Client doesn't accept any supported coding
+        let mut headers = json_headers();
+        assert_eq!(compressor.compress(None, StatusCode::OK, &mut headers, body()), body());
+        assert_eq!(headers[ETAG], "\"abc\"");
+    }
+}
@@ -0,0 +1,211 @@
+//! Concurrency limiting with bounded queueing
+//!
+//! Caps in-flight requests for a route. Requests beyond the limit wait in a
+//! bounded queue for up to a timeout; requests beyond the queue are shed
+//! immediately.
+
+use router_api::v1alpha1::vpc_route::ConcurrencyPolicy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Concurrency limit configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConcurrencyConfig {
+    /// Maximum requests in flight
+    pub max_concurrent_requests: usize,
+    /// Maximum requests waiting for a slot
+    pub queue_length: usize,
+    /// Maximum time a request waits in the queue
+    pub queue_timeout: Duration,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 100,
+            queue_length: 0,
+            queue_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ConcurrencyConfig {
+    /// Create a configuration from a VPCRoute concurrency policy
+    pub fn from_policy(policy: &ConcurrencyPolicy) -> Self {
+        Self {
+            max_concurrent_requests: policy.max_concurrent_requests as usize,
+            queue_length: policy.queue_length as usize,
+            queue_timeout: Duration::from_millis(policy.queue_timeout_ms as u64),
+        }
+    }
+}
+
+/// Why a request was not admitted
+#[derive(Debug, Error, PartialEq)]
+pub enum ConcurrencyError {
+    #[error("Concurrency limit reached and queue is full")]
+    QueueFull,
+
+    #[error("Timed out waiting in queue after {0:?}")]
+    QueueTimeout(Duration),
+}
+
+/// Admission slot held for the duration of a request
+pub struct ConcurrencyPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Limits in-flight requests with a bounded wait queue
+pub struct ConcurrencyLimiter {
+    config: ConcurrencyConfig,
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+impl ConcurrencyLimiter {
+    /// Create a new concurrency limiter
+    pub fn new(config: ConcurrencyConfig) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+            queued: AtomicUsize::new(0),
+            config,
+        }
+    }
+
+    /// Get the limiter configuration
+    pub fn config(&self) -> &ConcurrencyConfig {
+        &self.config
+    }
+
+    /// Number of requests currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.config.max_concurrent_requests - self.semaphore.available_permits()
+    }
+
+    /// Number of requests currently waiting in the queue
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Admit a request, waiting in the queue if the limit is reached
+    pub async fn acquire(&self) -> Result<ConcurrencyPermit, ConcurrencyError> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(ConcurrencyPermit { _permit: permit });
+        }
+
+        // Reserve a queue slot, or shed the request if the queue is full
+        let reserved = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < self.config.queue_length).then_some(queued + 1)
+            });
+        if reserved.is_err() {
+            return Err(ConcurrencyError::QueueFull);
+        }
+        // Released however the wait ends, including the caller giving up
+        let _slot = QueueSlot(&self.queued);
+
+        let result = tokio::time::timeout(self.config.queue_timeout, self.semaphore.clone().acquire_owned()).await;
+        match result {
+            Ok(Ok(permit)) => Ok(ConcurrencyPermit { _permit: permit }),
+            // The semaphore is never closed, so only the timeout can fail
+            _ => Err(ConcurrencyError::QueueTimeout(self.config.queue_timeout)),
+        }
+    }
+}
+
+/// A reserved place in the wait queue, given back on drop
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max: usize, queue: usize, timeout_ms: u64) -> Arc<ConcurrencyLimiter> {
+        Arc::new(ConcurrencyLimiter::new(ConcurrencyConfig {
+            max_concurrent_requests: max,
+            queue_length: queue,
+            queue_timeout: Duration::from_millis(timeout_ms),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_admits_up_to_limit() {
+        let limiter = limiter(2, 0, 10);
+        let _a = limiter.acquire().await.unwrap();
+        let _b = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.in_flight(), 2);
+        assert_eq!(limiter.acquire().await.err(), Some(ConcurrencyError::QueueFull));
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_gets_released_slot() {
+        let limiter = limiter(1, 1, 1000);
+        let first = limiter.acquire().await.unwrap();
+
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limiter.queued(), 1);
+
+        drop(first);
+        assert!(waiter.await.unwrap());
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let limiter = limiter(1, 1, 20);
+        let _held = limiter.acquire().await.unwrap();
+        assert_eq!(
+            limiter.acquire().await.err(),
+            Some(ConcurrencyError::QueueTimeout(Duration::from_millis(20)))
+        );
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter_leaves_queue() {
+        let limiter = limiter(1, 1, 1000);
+        let _held = limiter.acquire().await.unwrap();
+
+        // The client goes away while its request is queued
+        let waiter = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(limiter.queued(), 1);
+        waiter.abort();
+        assert!(waiter.await.unwrap_err().is_cancelled());
+        assert_eq!(limiter.queued(), 0);
+
+        // The queue slot can be used again
+        let next = tokio::time::timeout(Duration::from_millis(20), limiter.acquire()).await;
+        assert!(next.is_err(), "should wait in the queue, not be shed");
+        assert_eq!(limiter.queued(), 0);
+    }
+
+    #[test]
+    fn test_config_from_policy() {
+        let config = ConcurrencyConfig::from_policy(&ConcurrencyPolicy {
+            max_concurrent_requests: 50,
+            queue_length: 10,
+            queue_timeout_ms: 250,
+        });
+        assert_eq!(config.max_concurrent_requests, 50);
+        assert_eq!(config.queue_length, 10);
+        assert_eq!(config.queue_timeout, Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,221 @@
+//! Per-request debug mode
+//!
+//! A request carrying the debug header with the shared secret (or any
+//! value, while the admin toggle is on) is traced with the sampled flag
+//! forced on, logged verbosely, and answered with timing headers. The
+//! debug header itself is never forwarded.
+
+use crate::timing::UpstreamTiming;
+use crate::tracing::TracingMiddleware;
+use anyhow::{Result, anyhow};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Name of the tracing span debug requests run in
+///
+/// Log filters can raise verbosity inside it, e.g. `[router_debug]=trace`.
+pub const DEBUG_SPAN: &str = "router_debug";
+
+/// Debug mode configuration
+#[derive(Clone, Debug)]
+pub struct DebugConfig {
+    /// Header that requests debug mode
+    pub header: String,
+    /// Value the header must carry; without one, only the admin toggle enables debugging
+    pub secret: Option<String>,
+    /// Admin toggle: accept the header with any value
+    pub open: bool,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-router-debug".to_string(),
+            secret: None,
+            open: false,
+        }
+    }
+}
+
+/// Decides which requests get debug handling and decorates them
+pub struct RequestDebugger {
+    header: HeaderName,
+    secret: Option<String>,
+    open: AtomicBool,
+}
+
+impl RequestDebugger {
+    /// Create a debugger
+    pub fn new(config: DebugConfig) -> Result<Self> {
+        let header = HeaderName::from_bytes(config.header.as_bytes())
+            .map_err(|_| anyhow!("Invalid debug header name {:?}", config.header))?;
+        Ok(Self {
+            header,
+            secret: config.secret.filter(|s| !s.is_empty()),
+            open: AtomicBool::new(config.open),
+        })
+    }
+
+    /// Turn the admin toggle on or off
+    pub fn set_open(&self, open: bool) {
+        self.open.store(open, Ordering::Relaxed);
+    }
+
+    /// Whether the admin toggle is on
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Whether a request asked for debug handling and is allowed it
+    pub fn is_debug(&self, headers: &HeaderMap) -> bool {
+        let Some(value) = headers.get(&self.header) else {
+            return false;
+        };
+        if self.is_open() {
+            return true;
+        }
+        self.secret
+            .as_ref()
+            .is_some_and(|secret| constant_time_eq(value.as_bytes(), secret.as_bytes()))
+    }
+
+    /// Prepare a debug request for forwarding
+    ///
+    /// Removes the debug header and marks the trace as sampled, starting a
+    /// trace if the request has none. Returns the trace ID.
+    pub fn prepare_request(&self, headers: &mut HeaderMap) -> String {
+        headers.remove(&self.header);
+
+        let (trace_id, span_id) = headers
+            .get("traceparent")
+            .and_then(|v| v.to_str().ok())
+            .and_then(TracingMiddleware::parse_traceparent)
+            .map(|(trace_id, span_id, _)| (trace_id, span_id))
+            .unwrap_or_else(|| (TracingMiddleware::generate_trace_id(), TracingMiddleware::generate_span_id()));
+        let traceparent = TracingMiddleware::create_w3c_trace_context(&trace_id, &span_id, "01");
+        if let Ok(value) = HeaderValue::from_str(&traceparent) {
+            headers.insert("traceparent", value);
+        }
+        trace_id
+    }
+
+    /// Add timing headers to a debug response
+    ///
+    /// `routing` is the time spent before the request was forwarded and
+    /// `total` the time spent handling it overall, in the gateway.
+    pub fn add_timing_headers(
+        headers: &mut HeaderMap,
+        trace_id: &str,
+        routing: Duration,
+        upstream: Option<&UpstreamTiming>,
+        total: Duration,
+    ) {
+        let mut set = |name: &'static str, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        };
+        set("x-router-debug-trace-id", trace_id.to_string());
+        set("x-router-debug-routing-ms", millis(routing));
+        if let Some(upstream) = upstream {
+            let connect = upstream.connect.map(millis).unwrap_or_else(|| "reused".to_string());
+            set("x-router-debug-connect-ms", connect);
+            set("x-router-debug-ttfb-ms", millis(upstream.ttfb));
+            set("x-router-debug-upstream-ms", millis(upstream.total));
+            set("x-router-debug-attempts", upstream.attempts.to_string());
+        }
+        set("x-router-debug-total-ms", millis(total));
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// Compare secrets without leaking the position of the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger(secret: Option<&str>) -> RequestDebugger {
+        RequestDebugger::new(DebugConfig {
+            secret: secret.map(str::to_string),
+            ..DebugConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_secret_required_unless_open() {
+        let debugger = debugger(Some("s3cret"));
+        let mut headers = HeaderMap::new();
+        assert!(!debugger.is_debug(&headers));
+
+        headers.insert("x-router-debug", HeaderValue::from_static("guess"));
+        assert!(!debugger.is_debug(&headers));
+        debugger.set_open(true);
+        assert!(debugger.is_debug(&headers));
+        debugger.set_open(false);
+
+        headers.insert("x-router-debug", HeaderValue::from_static("s3cret"));
+        assert!(debugger.is_debug(&headers));
+    }
+
+    #[test]
+    fn test_no_secret_means_toggle_only() {
+        let debugger = debugger(None);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-router-debug", HeaderValue::from_static(""));
+        assert!(!debugger.is_debug(&headers));
+    }
+
+    #[test]
+    fn test_prepare_request_forces_sampling() {
+        let debugger = debugger(Some("s3cret"));
+        let mut headers = HeaderMap::new();
+        headers.insert("x-router-debug", HeaderValue::from_static("s3cret"));
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"),
+        );
+
+        let trace_id = debugger.prepare_request(&mut headers);
+        assert_eq!(trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(headers["traceparent"], "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        assert!(!headers.contains_key("x-router-debug"));
+
+        // A trace is started when the request has none
+        let mut headers = HeaderMap::new();
+        let trace_id = debugger.prepare_request(&mut headers);
+        assert_eq!(trace_id.len(), 32);
+        assert!(headers["traceparent"].to_str().unwrap().ends_with("-01"));
+    }
+
+    #[test]
+    fn test_timing_headers() {
+        let mut headers = HeaderMap::new();
+        let upstream = UpstreamTiming {
+            connect: None,
+            ttfb: Duration::from_millis(12),
+            total: Duration::from_millis(15),
+            attempts: 2,
+        };
+        RequestDebugger::add_timing_headers(
+            &mut headers,
+            "abc",
+            Duration::from_micros(250),
+            Some(&upstream),
+            Duration::from_millis(16),
+        );
+        assert_eq!(headers["x-router-debug-routing-ms"], "0.250");
+        assert_eq!(headers["x-router-debug-connect-ms"], "reused");
+        assert_eq!(headers["x-router-debug-ttfb-ms"], "12.000");
+        assert_eq!(headers["x-router-debug-attempts"], "2");
+        assert_eq!(headers["x-router-debug-total-ms"], "16.000");
+    }
+}
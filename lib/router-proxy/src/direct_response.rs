@@ -0,0 +1,111 @@
+//! Responses served by the gateway
+//!
+//! A route destination can be a fixed response instead of a VPCService,
+//! for maintenance pages, robots.txt, or stubbing endpoints while they
+//! migrate. No backend is contacted.
+
+use anyhow::{Result, anyhow};
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use router_api::v1alpha1::vpc_route::DirectResponse;
+
+/// Validated fixed response
+#[derive(Clone, Debug)]
+pub struct StaticResponse {
+    status: StatusCode,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Bytes,
+}
+
+impl StaticResponse {
+    /// Create a response with a text/plain body
+    pub fn new(status: StatusCode, body: impl Into<Bytes>) -> Self {
+        Self {
+            status,
+            headers: vec![(CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"))],
+            body: body.into(),
+        }
+    }
+
+    /// Create a response from a VPCRoute direct response destination
+    pub fn from_destination(direct: &DirectResponse) -> Result<Self> {
+        let status = StatusCode::from_u16(direct.status_code)
+            .map_err(|_| anyhow!("Invalid direct response status {}", direct.status_code))?;
+        let mut response = Self::new(status, direct.body.clone());
+        for (name, value) in &direct.headers {
+            response = response.with_header(name, value)?;
+        }
+        Ok(response)
+    }
+
+    /// Set a header, replacing the default Content-Type if given
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self> {
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| anyhow!("Invalid header name {:?}", name))?;
+        let value = HeaderValue::from_str(value).map_err(|_| anyhow!("Invalid value for header {}", name))?;
+        self.headers.retain(|(existing, _)| *existing != name);
+        self.headers.push((name, value));
+        Ok(self)
+    }
+
+    /// Status code served
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Build the response
+    pub fn response(&self) -> Response<Bytes> {
+        let mut builder = Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(self.body.clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_from_destination() {
+        let direct = DirectResponse {
+            status_code: 503,
+            body: "<h1>Down for maintenance</h1>".to_string(),
+            headers: BTreeMap::from([
+                ("Content-Type".to_string(), "text/html".to_string()),
+                ("Retry-After".to_string(), "600".to_string()),
+            ]),
+        };
+        let response = StaticResponse::from_destination(&direct).unwrap().response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get_all(CONTENT_TYPE).iter().count(), 1);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/html");
+        assert_eq!(response.headers()["retry-after"], "600");
+        assert_eq!(response.body(), "<h1>Down for maintenance</h1>");
+    }
+
+    #[test]
+    fn test_default_content_type() {
+        let response = StaticResponse::new(StatusCode::OK, "User-agent: *\nDisallow: /\n").response();
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn test_invalid_destination_rejected() {
+        let direct = DirectResponse {
+            status_code: 1000,
+            ..Default::default()
+        };
+        assert!(StaticResponse::from_destination(&direct).is_err());
+
+        let direct = DirectResponse {
+            status_code: 200,
+            headers: BTreeMap::from([("bad header".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        assert!(StaticResponse::from_destination(&direct).is_err());
+    }
+}
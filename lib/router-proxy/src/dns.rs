@@ -0,0 +1,342 @@
+//! Cached DNS resolution for hostname backends
+//!
+//! Without a resolver the forwarder's connector resolves backend hostnames
+//! with a blocking getaddrinfo call on every new connection. A
+//! [`CachingResolver`] resolves asynchronously and keeps answers for their
+//! TTL, clamped to configured bounds. Failed lookups are cached briefly so
+//! an unresolvable name doesn't reach DNS on every request, and a failed
+//! connection evicts the host so the next request resolves it again.
+
+use crate::metrics::MetricsCollector;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use hickory_resolver::system_conf::read_system_conf;
+use hickory_resolver::TokioAsyncResolver;
+use hyper_util::client::legacy::connect::dns::{GaiResolver, Name};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::Service;
+use tracing::debug;
+
+/// DNS cache configuration
+#[derive(Clone, Debug)]
+pub struct DnsCacheConfig {
+    /// Shortest time an answer is kept, whatever its TTL
+    pub min_ttl: Duration,
+    /// Longest time an answer is kept, whatever its TTL
+    pub max_ttl: Duration,
+    /// How long a failed lookup is remembered
+    pub negative_ttl: Duration,
+}
+
+impl Default for DnsCacheConfig {
+    fn default() -> Self {
+        Self {
+            min_ttl: Duration::from_secs(5),
+            max_ttl: Duration::from_secs(300),
+            negative_ttl: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Source of DNS answers for the cache
+#[async_trait]
+pub trait DnsLookup: Send + Sync {
+    /// Resolve a hostname, returning its addresses and their TTL
+    async fn lookup(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)>;
+}
+
+/// Lookups through hickory using the system resolver configuration
+pub struct HickoryLookup {
+    resolver: TokioAsyncResolver,
+}
+
+impl HickoryLookup {
+    /// Create a lookup from /etc/resolv.conf
+    pub fn from_system_conf() -> Result<Self> {
+        let (config, mut options) =
+            read_system_conf().map_err(|e| anyhow!("Failed to read system DNS configuration: {}", e))?;
+        // Answers are cached by CachingResolver, which must be able to evict them
+        options.cache_size = 0;
+        Ok(Self {
+            resolver: TokioAsyncResolver::tokio(config, options),
+        })
+    }
+}
+
+#[async_trait]
+impl DnsLookup for HickoryLookup {
+    async fn lookup(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let lookup = self.resolver.lookup_ip(host).await?;
+        let ttl = lookup.valid_until().saturating_duration_since(Instant::now());
+        Ok((lookup.iter().collect(), ttl))
+    }
+}
+
+/// Cached answer for a hostname
+#[derive(Clone, Debug)]
+enum CacheEntry {
+    Resolved { addrs: Vec<IpAddr>, expires: Instant },
+    Failed { error: String, expires: Instant },
+}
+
+impl CacheEntry {
+    fn expires(&self) -> Instant {
+        match self {
+            CacheEntry::Resolved { expires, .. } | CacheEntry::Failed { expires, .. } => *expires,
+        }
+    }
+}
+
+/// Async resolver with TTL-aware positive and negative caching
+///
+/// Clones share one cache.
+#[derive(Clone)]
+pub struct CachingResolver {
+    lookup: Arc<dyn DnsLookup>,
+    config: DnsCacheConfig,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl CachingResolver {
+    /// Create a resolver caching answers from `lookup`
+    pub fn new(lookup: impl DnsLookup + 'static, config: DnsCacheConfig) -> Self {
+        Self {
+            lookup: Arc::new(lookup),
+            config,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            metrics: None,
+        }
+    }
+
+    /// Create a resolver using the system DNS configuration
+    pub fn from_system_conf(config: DnsCacheConfig) -> Result<Self> {
+        Ok(Self::new(HickoryLookup::from_system_conf()?, config))
+    }
+
+    /// Count lookups and cache hits through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Resolve a hostname, from the cache when possible
+    pub async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let host = host.to_ascii_lowercase();
+        let cached = self.cache().get(&host).filter(|e| e.expires() > Instant::now()).cloned();
+        match cached {
+            Some(CacheEntry::Resolved { addrs, .. }) => {
+                self.record("hit");
+                return Ok(addrs);
+            }
+            Some(CacheEntry::Failed { error, .. }) => {
+                self.record("negative_hit");
+                return Err(anyhow!(error));
+            }
+            None => {}
+        }
+
+        let config = &self.config;
+        let entry = match self.lookup.lookup(&host).await {
+            Ok((addrs, _)) if addrs.is_empty() => {
+                self.record("error");
+                CacheEntry::Failed {
+                    error: format!("No addresses found for {}", host),
+                    expires: Instant::now() + config.negative_ttl,
+                }
+            }
+            Ok((addrs, ttl)) => {
+                self.record("miss");
+                let ttl = ttl.clamp(config.min_ttl, config.max_ttl);
+                debug!("Resolved {} to {:?}, caching for {:?}", host, addrs, ttl);
+                CacheEntry::Resolved {
+                    addrs,
+                    expires: Instant::now() + ttl,
+                }
+            }
+            Err(e) => {
+                self.record("error");
+                debug!("Failed to resolve {}: {}", host, e);
+                CacheEntry::Failed {
+                    error: format!("Failed to resolve {}: {}", host, e),
+                    expires: Instant::now() + config.negative_ttl,
+                }
+            }
+        };
+        self.cache().insert(host, entry.clone());
+        match entry {
+            CacheEntry::Resolved { addrs, .. } => Ok(addrs),
+            CacheEntry::Failed { error, .. } => Err(anyhow!(error)),
+        }
+    }
+
+    /// Forget a hostname so the next request resolves it again
+    pub fn invalidate(&self, host: &str) {
+        if self.cache().remove(&host.to_ascii_lowercase()).is_some() {
+            debug!("Evicted {} from the DNS cache", host);
+        }
+    }
+
+    /// Number of cached hostnames, including expired ones
+    pub fn len(&self) -> usize {
+        self.cache().len()
+    }
+
+    /// Whether no hostnames are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry>> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record(&self, result: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_dns_lookup(result);
+        }
+    }
+}
+
+/// Resolver plugged into the forwarder's connector
+#[derive(Clone)]
+pub(crate) enum ConnectorResolver {
+    /// getaddrinfo on a blocking thread, hyper's default
+    System(GaiResolver),
+    /// Cached async resolution
+    Cached(CachingResolver),
+}
+
+type ResolveFuture = Pin<Box<dyn Future<Output = Result<std::vec::IntoIter<SocketAddr>, io::Error>> + Send>>;
+
+impl Service<Name> for ConnectorResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = ResolveFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self {
+            ConnectorResolver::System(resolver) => resolver.poll_ready(cx),
+            ConnectorResolver::Cached(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        match self {
+            ConnectorResolver::System(resolver) => {
+                let future = resolver.call(name);
+                Box::pin(async move { Ok(future.await?.collect::<Vec<_>>().into_iter()) })
+            }
+            ConnectorResolver::Cached(resolver) => {
+                let resolver = resolver.clone();
+                Box::pin(async move {
+                    // The connector fills in the port
+                    let addrs = resolver
+                        .resolve(name.as_str())
+                        .await
+                        .map_err(|e| io::Error::other(e.to_string()))?;
+                    Ok(addrs
+                        .into_iter()
+                        .map(|ip| SocketAddr::new(ip, 0))
+                        .collect::<Vec<_>>()
+                        .into_iter())
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers from a fixed table, counting lookups
+    struct StaticLookup {
+        answers: HashMap<&'static str, (Vec<IpAddr>, Duration)>,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DnsLookup for StaticLookup {
+        async fn lookup(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.answers.get(host).cloned().ok_or_else(|| anyhow!("NXDOMAIN"))
+        }
+    }
+
+    fn caching_resolver(ttl: Duration, config: DnsCacheConfig) -> (CachingResolver, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let lookup = StaticLookup {
+            answers: HashMap::from([("backend.internal", (vec!["10.0.0.7".parse().unwrap()], ttl))]),
+            lookups: lookups.clone(),
+        };
+        (CachingResolver::new(lookup, config), lookups)
+    }
+
+    #[tokio::test]
+    async fn test_answers_cached_for_ttl() {
+        let config = DnsCacheConfig {
+            min_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+        let (resolver, lookups) = caching_resolver(Duration::from_secs(60), config);
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let resolver = resolver.with_metrics(metrics.clone());
+
+        assert_eq!(resolver.resolve("backend.internal").await.unwrap(), vec!["10.0.0.7".parse::<IpAddr>().unwrap()]);
+        assert!(resolver.resolve("Backend.Internal").await.is_ok());
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+        assert_eq!(metrics.dns_lookups_total.with_label_values(&["hit"]).get(), 1.0);
+
+        // Re-resolved once evicted, e.g. after a failed connection
+        resolver.invalidate("backend.internal");
+        assert!(resolver.resolve("backend.internal").await.is_ok());
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_answers_resolved_again() {
+        let config = DnsCacheConfig {
+            min_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+        let (resolver, lookups) = caching_resolver(Duration::ZERO, config);
+        resolver.resolve("backend.internal").await.unwrap();
+        resolver.resolve("backend.internal").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failures_cached() {
+        let (resolver, lookups) = caching_resolver(Duration::from_secs(60), DnsCacheConfig::default());
+        assert!(resolver.resolve("missing.internal").await.is_err());
+        assert!(resolver.resolve("missing.internal").await.is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        let config = DnsCacheConfig {
+            negative_ttl: Duration::ZERO,
+            ..Default::default()
+        };
+        let (resolver, lookups) = caching_resolver(Duration::from_secs(60), config);
+        assert!(resolver.resolve("missing.internal").await.is_err());
+        assert!(resolver.resolve("missing.internal").await.is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_connector_resolution() {
+        let (resolver, _) = caching_resolver(Duration::from_secs(60), DnsCacheConfig::default());
+        let mut connector_resolver = ConnectorResolver::Cached(resolver);
+        let name: Name = "backend.internal".parse().unwrap();
+        let addrs: Vec<_> = connector_resolver.call(name).await.unwrap().collect();
+        assert_eq!(addrs, vec!["10.0.0.7:0".parse::<SocketAddr>().unwrap()]);
+    }
+}
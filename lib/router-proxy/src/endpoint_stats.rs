@@ -0,0 +1,128 @@
+//! Live traffic counters per backend endpoint
+//!
+//! The gateway records every forwarded request against its upstream
+//! address. The controller periodically collects the totals from each
+//! replica and publishes them in VPCService status.
+
+use router_core::stats::{EndpointCounterMap, EndpointCounters};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct AtomicCounters {
+    active: AtomicU64,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+/// Records requests per endpoint address
+#[derive(Default)]
+pub struct EndpointStatsRecorder {
+    endpoints: Mutex<HashMap<String, Arc<AtomicCounters>>>,
+}
+
+impl EndpointStatsRecorder {
+    /// Create an empty recorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start recording a request to `address` ("ip:port")
+    ///
+    /// The request counts as active until the returned guard is finished
+    /// or dropped; a guard dropped without finishing counts as an error.
+    pub fn begin(&self, address: &str) -> EndpointRequest {
+        let counters = self
+            .endpoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(address.to_string())
+            .or_default()
+            .clone();
+        counters.active.fetch_add(1, Ordering::Relaxed);
+        EndpointRequest {
+            counters,
+            finished: false,
+        }
+    }
+
+    /// Current totals for every endpoint seen
+    pub fn snapshot(&self) -> EndpointCounterMap {
+        self.endpoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(address, c)| {
+                let counters = EndpointCounters {
+                    active_connections: c.active.load(Ordering::Relaxed),
+                    requests: c.requests.load(Ordering::Relaxed),
+                    errors: c.errors.load(Ordering::Relaxed),
+                    bytes_received: c.bytes_received.load(Ordering::Relaxed),
+                    bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+                };
+                (address.clone(), counters)
+            })
+            .collect()
+    }
+}
+
+/// A request in flight to an endpoint
+pub struct EndpointRequest {
+    counters: Arc<AtomicCounters>,
+    finished: bool,
+}
+
+impl EndpointRequest {
+    /// Record the request's outcome
+    ///
+    /// `status` is `None` when no response was received.
+    pub fn finish(mut self, status: Option<u16>, bytes_received: u64, bytes_sent: u64) {
+        self.finished = true;
+        let c = &self.counters;
+        c.requests.fetch_add(1, Ordering::Relaxed);
+        if status.is_none_or(|s| s >= 500) {
+            c.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        c.bytes_received.fetch_add(bytes_received, Ordering::Relaxed);
+        c.bytes_sent.fetch_add(bytes_sent, Ordering::Relaxed);
+    }
+}
+
+impl Drop for EndpointRequest {
+    fn drop(&mut self) {
+        let c = &self.counters;
+        c.active.fetch_sub(1, Ordering::Relaxed);
+        if !self.finished {
+            c.requests.fetch_add(1, Ordering::Relaxed);
+            c.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_requests() {
+        let recorder = EndpointStatsRecorder::new();
+        let ok = recorder.begin("10.0.0.1:8080");
+        let failed = recorder.begin("10.0.0.1:8080");
+        assert_eq!(recorder.snapshot()["10.0.0.1:8080"].active_connections, 2);
+
+        ok.finish(Some(200), 100, 2048);
+        failed.finish(Some(503), 50, 10);
+        let abandoned = recorder.begin("10.0.0.1:8080");
+        drop(abandoned);
+
+        let counters = recorder.snapshot()["10.0.0.1:8080"];
+        assert_eq!(counters.active_connections, 0);
+        assert_eq!(counters.requests, 3);
+        assert_eq!(counters.errors, 2);
+        assert_eq!(counters.bytes_received, 150);
+        assert_eq!(counters.bytes_sent, 2058);
+    }
+}
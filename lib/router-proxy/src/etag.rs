@@ -0,0 +1,421 @@
+//! ETag generation and conditional request handling at the edge
+//!
+//! Adds an ETag to cacheable responses that lack validators and answers
+//! If-None-Match/If-Modified-Since. Validators seen recently for a URL are
+//! remembered, so a revalidation that is still within the response's
+//! freshness lifetime gets a 304 without reaching the backend.
+
+use anyhow::{Result, anyhow};
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, SET_COOKIE, VARY,
+};
+use hyper::http::response;
+use hyper::{Method, Response, StatusCode};
+use lru::LruCache;
+use router_api::v1alpha1::vpc_route::ETagPolicy;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Conditional request handling of a route
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionalConfig {
+    /// Generate weak (W/"...") rather than strong ETags
+    pub weak: bool,
+    /// Longest time remembered validators answer revalidations without the backend
+    pub max_validator_age: Duration,
+}
+
+impl Default for ConditionalConfig {
+    fn default() -> Self {
+        Self {
+            weak: false,
+            max_validator_age: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ConditionalConfig {
+    /// Conditional request handling of a route's `etag` policy
+    pub fn from_policy(policy: &ETagPolicy) -> Self {
+        let defaults = Self::default();
+        Self {
+            weak: policy.weak,
+            max_validator_age: policy
+                .max_validator_age_seconds
+                .map_or(defaults.max_validator_age, |seconds| Duration::from_secs(seconds.into())),
+        }
+    }
+}
+
+/// Response validators
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validators {
+    /// Entity tag, including quotes and any W/ prefix
+    pub etag: Option<String>,
+    /// Last modification time
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Read validators from response headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            etag: headers.get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string),
+            last_modified: headers
+                .get(LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| httpdate::parse_http_date(v).ok()),
+        }
+    }
+
+    /// Set conditional headers asking whether a copy with these validators is current
+    pub fn add_conditional_headers(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = self.etag.as_deref().and_then(|e| HeaderValue::from_str(e).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = self.last_modified {
+            if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+                headers.insert(IF_MODIFIED_SINCE, value);
+            }
+        }
+    }
+
+    /// Whether there is anything to revalidate with
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Check whether a request's conditional headers match these validators
+    ///
+    /// If-None-Match takes precedence over If-Modified-Since (RFC 9110 13.2.2).
+    pub fn not_modified(&self, request_headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request_headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+            let Some(etag) = &self.etag else {
+                return false;
+            };
+            return if_none_match.trim() == "*"
+                || if_none_match.split(',').any(|tag| weak_eq(tag.trim(), etag));
+        }
+
+        let if_modified_since = request_headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+        match (if_modified_since, self.last_modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+/// Remembered validators for a URL
+struct Remembered {
+    validators: Validators,
+    fresh_until: Instant,
+}
+
+/// Generates ETags and answers conditional requests
+///
+/// Shared by every route with an `etag` policy; each response is processed
+/// with the [`ConditionalConfig`] of its route.
+pub struct ConditionalResponder {
+    remembered: Mutex<LruCache<String, Remembered>>,
+}
+
+impl ConditionalResponder {
+    /// Create a conditional responder remembering validators for up to `capacity` URLs
+    pub fn new(capacity: usize) -> Result<Self> {
+        let capacity =
+            NonZeroUsize::new(capacity).ok_or_else(|| anyhow!("Validator cache capacity must be greater than 0"))?;
+        // Grow as validators are remembered rather than reserving every slot up front
+        let mut remembered = LruCache::unbounded();
+        remembered.resize(capacity);
+        Ok(Self {
+            remembered: Mutex::new(remembered),
+        })
+    }
+
+    /// Maximum number of URLs whose validators are remembered
+    pub fn capacity(&self) -> usize {
+        self.remembered.lock().unwrap_or_else(|e| e.into_inner()).cap().get()
+    }
+
+    /// Compute an ETag for a response body
+    pub fn compute_etag(body: &[u8], weak: bool) -> String {
+        let digest = Sha256::digest(body);
+        let tag = hex::encode(&digest[..16]);
+        if weak {
+            format!("W/\"{}\"", tag)
+        } else {
+            format!("\"{}\"", tag)
+        }
+    }
+
+    /// Answer a conditional request from remembered validators
+    ///
+    /// Returns the validators to send in a 304 when the client's copy is
+    /// known to be current, or `None` if the request must go to the backend.
+    pub fn check_request(&self, key: &str, method: &Method, headers: &HeaderMap) -> Option<Validators> {
+        if !is_conditional(method, headers) {
+            return None;
+        }
+
+        let mut remembered = self.remembered.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = remembered.get(key)?;
+        if Instant::now() >= entry.fresh_until {
+            remembered.pop(key);
+            return None;
+        }
+        entry.validators.not_modified(headers).then(|| entry.validators.clone())
+    }
+
+    /// Add validators to a backend response and evaluate the request's conditionals
+    ///
+    /// Returns `true` if the response should be turned into a 304.
+    pub fn process_response(
+        &self,
+        key: &str,
+        config: &ConditionalConfig,
+        method: &Method,
+        request_headers: &HeaderMap,
+        response: &mut response::Parts,
+        body: &Bytes,
+    ) -> bool {
+        if !matches!(*method, Method::GET | Method::HEAD) || response.status != StatusCode::OK {
+            return false;
+        }
+        let response_headers = &mut response.headers;
+        let directives = cache_control(response_headers);
+        if directives.iter().any(|d| d == "no-store") {
+            return false;
+        }
+
+        // A HEAD response has no body to derive a tag from
+        if *method == Method::GET && !response_headers.contains_key(ETAG) {
+            let weak = config.weak || response_headers.contains_key(CONTENT_ENCODING);
+            if let Ok(value) = HeaderValue::from_str(&Self::compute_etag(body, weak)) {
+                response_headers.insert(ETAG, value);
+            }
+        }
+
+        let validators = Validators::from_headers(response_headers);
+        if let Some(fresh_for) = shared_freshness(config, &directives, response_headers) {
+            let mut remembered = self.remembered.lock().unwrap_or_else(|e| e.into_inner());
+            remembered.put(
+                key.to_string(),
+                Remembered {
+                    validators: validators.clone(),
+                    fresh_until: Instant::now() + fresh_for,
+                },
+            );
+        }
+
+        is_conditional(method, request_headers) && validators.not_modified(request_headers)
+    }
+
+    /// Build a 304 Not Modified response carrying the validators
+    pub fn not_modified_response(validators: &Validators) -> Response<Bytes> {
+        let mut builder = Response::builder().status(StatusCode::NOT_MODIFIED);
+        if let Some(etag) = &validators.etag {
+            builder = builder.header(ETAG, etag);
+        }
+        if let Some(modified) = validators.last_modified {
+            builder = builder.header(LAST_MODIFIED, httpdate::fmt_http_date(modified));
+        }
+        builder.body(Bytes::new()).unwrap()
+    }
+
+    /// Strip representation headers from a response being turned into a 304
+    pub fn strip_for_not_modified(headers: &mut HeaderMap) {
+        headers.remove(CONTENT_LENGTH);
+        headers.remove(CONTENT_TYPE);
+        headers.remove(CONTENT_ENCODING);
+    }
+}
+
+/// How long the gateway may answer revalidations for a response itself
+fn shared_freshness(config: &ConditionalConfig, directives: &[String], headers: &HeaderMap) -> Option<Duration> {
+    // Per-user or varying responses must not be shared between clients
+    let private = directives
+        .iter()
+        .any(|d| d == "private" || d == "no-cache" || d.starts_with("private="));
+    if private || headers.contains_key(SET_COOKIE) || headers.contains_key(VARY) {
+        return None;
+    }
+
+    let max_age = ["s-maxage", "max-age"].iter().find_map(|name| {
+        directives
+            .iter()
+            .find_map(|d| d.strip_prefix(name)?.strip_prefix('=')?.parse::<u64>().ok())
+    });
+    let fresh_for = match max_age {
+        Some(seconds) => Duration::from_secs(seconds).min(config.max_validator_age),
+        None => config.max_validator_age,
+    };
+    (!fresh_for.is_zero()).then_some(fresh_for)
+}
+
+/// Whether a request carries conditional headers the gateway evaluates
+fn is_conditional(method: &Method, headers: &HeaderMap) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+        && (headers.contains_key(IF_NONE_MATCH) || headers.contains_key(IF_MODIFIED_SINCE))
+}
+
+/// Lowercased Cache-Control directives
+pub(crate) fn cache_control(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|d| d.trim().to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// Weak comparison of entity tags (RFC 9110 8.8.3.2)
+fn weak_eq(a: &str, b: &str) -> bool {
+    let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+    opaque(a) == opaque(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder() -> ConditionalResponder {
+        ConditionalResponder::new(100).unwrap()
+    }
+
+    fn config(max_age_secs: u64) -> ConditionalConfig {
+        ConditionalConfig {
+            max_validator_age: Duration::from_secs(max_age_secs),
+            ..Default::default()
+        }
+    }
+
+    fn response(status: StatusCode, headers: HeaderMap) -> response::Parts {
+        let (mut parts, _) = Response::new(()).into_parts();
+        parts.status = status;
+        parts.headers = headers;
+        parts
+    }
+
+    fn conditional(header: hyper::header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_compute_etag() {
+        let strong = ConditionalResponder::compute_etag(b"hello", false);
+        assert!(strong.starts_with('"') && strong.ends_with('"'));
+        assert_eq!(strong, ConditionalResponder::compute_etag(b"hello", false));
+        assert_ne!(strong, ConditionalResponder::compute_etag(b"world", false));
+        assert_eq!(ConditionalResponder::compute_etag(b"hello", true), format!("W/{}", strong));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let validators = Validators {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+        };
+        assert!(validators.not_modified(&conditional(IF_NONE_MATCH, "\"xyz\", W/\"abc\"")));
+        assert!(validators.not_modified(&conditional(IF_NONE_MATCH, "*")));
+        assert!(!validators.not_modified(&conditional(IF_NONE_MATCH, "\"xyz\"")));
+        assert!(!validators.not_modified(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let modified = httpdate::parse_http_date("Sun, 01 Mar 2026 10:00:00 GMT").unwrap();
+        let validators = Validators {
+            etag: None,
+            last_modified: Some(modified),
+        };
+        assert!(validators.not_modified(&conditional(IF_MODIFIED_SINCE, "Sun, 01 Mar 2026 10:00:00 GMT")));
+        assert!(!validators.not_modified(&conditional(IF_MODIFIED_SINCE, "Sun, 01 Mar 2026 09:00:00 GMT")));
+
+        // If-None-Match wins over If-Modified-Since
+        let mut headers = conditional(IF_MODIFIED_SINCE, "Sun, 01 Mar 2026 10:00:00 GMT");
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!validators.not_modified(&headers));
+    }
+
+    #[test]
+    fn test_response_gets_etag_and_revalidation_skips_backend() {
+        let responder = responder();
+        let body = Bytes::from("console.log(1)");
+        let mut parts = response(StatusCode::OK, HeaderMap::new());
+
+        let request_headers = HeaderMap::new();
+        let not_modified =
+            responder.process_response("/assets/app.js", &config(60), &Method::GET, &request_headers, &mut parts, &body);
+        assert!(!not_modified);
+        let etag = parts.headers.get(ETAG).unwrap().to_str().unwrap().to_string();
+
+        let revalidation = conditional(IF_NONE_MATCH, &etag);
+        let validators = responder.check_request("/assets/app.js", &Method::GET, &revalidation).unwrap();
+        assert_eq!(validators.etag.as_deref(), Some(etag.as_str()));
+
+        let response = ConditionalResponder::not_modified_response(&validators);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), etag.as_str());
+
+        // Unconditional requests and other URLs still go to the backend
+        assert!(responder.check_request("/assets/app.js", &Method::GET, &HeaderMap::new()).is_none());
+        assert!(responder.check_request("/assets/other.js", &Method::GET, &revalidation).is_none());
+    }
+
+    #[test]
+    fn test_backend_response_becomes_not_modified() {
+        let responder = responder();
+        let body = Bytes::from("data");
+        let etag = ConditionalResponder::compute_etag(&body, false);
+
+        let mut parts = response(StatusCode::OK, HeaderMap::new());
+        let revalidation = conditional(IF_NONE_MATCH, &etag);
+        let not_modified =
+            responder.process_response("/assets/data.json", &config(0), &Method::GET, &revalidation, &mut parts, &body);
+        assert!(not_modified);
+
+        // Zero validator age: nothing remembered
+        assert!(responder
+            .check_request("/assets/data.json", &Method::GET, &conditional(IF_NONE_MATCH, &etag))
+            .is_none());
+    }
+
+    #[test]
+    fn test_uncacheable_responses() {
+        let responder = responder();
+        let config = config(60);
+        let body = Bytes::from("secret");
+
+        let mut no_store = response(StatusCode::OK, conditional(CACHE_CONTROL, "no-store"));
+        responder.process_response("/a", &config, &Method::GET, &HeaderMap::new(), &mut no_store, &body);
+        assert!(!no_store.headers.contains_key(ETAG));
+
+        // Private responses get an ETag but are not answered at the edge
+        let mut private = response(StatusCode::OK, conditional(CACHE_CONTROL, "private, max-age=60"));
+        responder.process_response("/b", &config, &Method::GET, &HeaderMap::new(), &mut private, &body);
+        let etag = private.headers.get(ETAG).unwrap().to_str().unwrap().to_string();
+        assert!(responder.check_request("/b", &Method::GET, &conditional(IF_NONE_MATCH, &etag)).is_none());
+
+        let mut error = response(StatusCode::NOT_FOUND, HeaderMap::new());
+        responder.process_response("/c", &config, &Method::GET, &HeaderMap::new(), &mut error, &body);
+        assert!(!error.headers.contains_key(ETAG));
+    }
+
+    #[test]
+    fn test_config_from_policy() {
+        assert_eq!(ConditionalConfig::from_policy(&ETagPolicy::default()), ConditionalConfig::default());
+        let policy = ETagPolicy { weak: true, max_validator_age_seconds: Some(30) };
+        assert_eq!(ConditionalConfig::from_policy(&policy), ConditionalConfig { weak: true, ..config(30) });
+    }
+}
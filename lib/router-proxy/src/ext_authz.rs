@@ -0,0 +1,324 @@
+//! External authorization (ext_authz style)
+//!
+//! Asks an external HTTP authorization service whether each request may
+//! proceed. The check mirrors the original request's method and path
+//! (under an optional prefix) with no body, carrying only the selected
+//! headers. A 2xx answer allows the request, and configured headers from
+//! the answer are set on the request sent upstream. Any other answer is
+//! returned to the client as the response. When the service can't be
+//! reached or times out, the request is denied unless the authorizer is
+//! configured to fail open.
+//!
+//! Paths are normalized (see `path_normalize`) before route prefixes are
+//! matched and the check is sent, so the service sees `/admin` however the
+//! client spelled it. Paths that can't be normalized are denied without a
+//! check.
+//!
+//! Only HTTP authorization services are supported; gRPC ones (Envoy's
+//! `envoy.service.auth.v3.Authorization`) need an HTTP front end.
+
+use anyhow::{anyhow, Result};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use hyper::{Method, Response, StatusCode, Uri};
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::path_normalize::normalize_path;
+use crate::problem;
+
+/// External authorization settings
+#[derive(Clone, Debug)]
+pub struct ExtAuthzConfig {
+    /// Base URL of the authorization service
+    pub url: String,
+    /// Prefix prepended to the original path in checks
+    pub path_prefix: String,
+    /// Request headers sent along with the check
+    pub forward_headers: Vec<String>,
+    /// Headers from an allowing answer set on the upstream request
+    pub upstream_headers: Vec<String>,
+    /// Path prefixes of routes requiring authorization; empty means all
+    pub route_prefixes: Vec<String>,
+    /// Timeout for checks
+    pub timeout: Duration,
+    /// Allow requests when the service fails instead of denying them
+    pub fail_open: bool,
+    /// Status answered when the service fails and the authorizer fails closed
+    pub status_on_error: StatusCode,
+}
+
+impl ExtAuthzConfig {
+    /// Create a configuration with default headers and timeout
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            path_prefix: String::new(),
+            forward_headers: vec!["authorization".to_string(), "cookie".to_string()],
+            upstream_headers: Vec::new(),
+            route_prefixes: Vec::new(),
+            timeout: Duration::from_millis(200),
+            fail_open: false,
+            status_on_error: StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+/// Outcome of an authorization check
+#[derive(Debug)]
+pub enum AuthzDecision {
+    /// Forward the request; the headers have been updated
+    Allow,
+    /// Answer the client with this response instead
+    Deny(Response<Bytes>),
+}
+
+/// Client of an external authorization service
+pub struct ExtAuthorizer {
+    config: ExtAuthzConfig,
+    http: reqwest::Client,
+}
+
+impl ExtAuthorizer {
+    /// Create an authorizer
+    pub fn new(config: ExtAuthzConfig) -> Result<Self> {
+        reqwest::Url::parse(&config.url).map_err(|e| anyhow!("Invalid authorization service URL {}: {}", config.url, e))?;
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok(Self { config, http })
+    }
+
+    /// The authorizer's settings
+    pub fn config(&self) -> &ExtAuthzConfig {
+        &self.config
+    }
+
+    /// Whether requests for `path` need authorization
+    ///
+    /// Paths that can't be normalized always do, and are denied.
+    pub fn applies_to(&self, path: &str) -> bool {
+        let Some(path) = normalize_path(path) else {
+            return true;
+        };
+        self.config.route_prefixes.is_empty()
+            || self.config.route_prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Check a request, updating `headers` with the service's mutations if allowed
+    pub async fn authorize(&self, method: &Method, uri: &Uri, headers: &mut HeaderMap, client_ip: IpAddr) -> AuthzDecision {
+        let Some(path) = normalize_path(uri.path()) else {
+            debug!("Denying {} {}: path can't be normalized", method, uri.path());
+            return AuthzDecision::Deny(problem::error_response(StatusCode::BAD_REQUEST, "Invalid request path"));
+        };
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        match self.check(method, &path_and_query, headers, client_ip).await {
+            Ok(decision) => decision,
+            Err(e) if self.config.fail_open => {
+                warn!("Authorization check failed, allowing request: {}", e);
+                AuthzDecision::Allow
+            }
+            Err(e) => {
+                warn!("Authorization check failed, denying request: {}", e);
+                AuthzDecision::Deny(problem::error_response(self.config.status_on_error, "Authorization unavailable"))
+            }
+        }
+    }
+
+    /// Call the authorization service about the normalized `path_and_query`
+    async fn check(&self, method: &Method, path_and_query: &str, headers: &mut HeaderMap, client_ip: IpAddr) -> Result<AuthzDecision> {
+        let url = format!("{}{}{}", self.config.url.trim_end_matches('/'), self.config.path_prefix, path_and_query);
+        let method = reqwest::Method::from_bytes(method.as_str().as_bytes())?;
+
+        let mut request = self.http.request(method, &url).header("x-forwarded-for", client_ip.to_string());
+        if let Some(host) = headers.get(HOST).and_then(|v| v.to_str().ok()) {
+            request = request.header("x-forwarded-host", host);
+        }
+        for name in &self.config.forward_headers {
+            for value in headers.get_all(name.as_str()) {
+                request = request.header(name.as_str(), value.as_bytes());
+            }
+        }
+
+        let response = request.send().await?;
+        let status = StatusCode::from_u16(response.status().as_u16())?;
+        let answer_headers: Vec<(String, Vec<u8>)> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+            .collect();
+
+        if status.is_success() {
+            for name in &self.config.upstream_headers {
+                let values: Vec<&[u8]> = answer_headers
+                    .iter()
+                    .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| v.as_slice())
+                    .collect();
+                if values.is_empty() {
+                    continue;
+                }
+                let header = HeaderName::from_bytes(name.as_bytes())?;
+                headers.remove(&header);
+                for value in values {
+                    headers.append(header.clone(), HeaderValue::from_bytes(value)?);
+                }
+            }
+            debug!("Authorized {} {}", url, status);
+            return Ok(AuthzDecision::Allow);
+        }
+
+        debug!("Authorization denied {} with {}", url, status);
+        let body = response.bytes().await?;
+        let mut denied = Response::builder().status(status).body(Bytes::from(body.to_vec()))?;
+        for (name, value) in answer_headers {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+            if [CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING].contains(&name) {
+                continue;
+            }
+            denied.headers_mut().append(name, HeaderValue::from_bytes(&value)?);
+        }
+        Ok(AuthzDecision::Deny(denied))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::Request;
+    use hyper_util::rt::TokioIo;
+    use tokio::net::TcpListener;
+
+    /// Authorization service allowing requests with "Bearer good"
+    async fn spawn_authz() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { return };
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let allowed = req.headers().get("authorization").is_some_and(|v| v == "Bearer good");
+                        let response = if req.uri().path() == "/check/slow" {
+                            tokio::time::sleep(Duration::from_secs(5)).await;
+                            Response::new(Full::new(Bytes::new()))
+                        } else if allowed {
+                            Response::builder()
+                                .header("x-user-id", "alice")
+                                .header("x-ignored", "1")
+                                .header("x-checked-path", req.uri().to_string())
+                                .body(Full::new(Bytes::new()))
+                                .unwrap()
+                        } else {
+                            Response::builder()
+                                .status(401)
+                                .header("www-authenticate", "Bearer")
+                                .body(Full::new(Bytes::from("who are you")))
+                                .unwrap()
+                        };
+                        Ok::<_, hyper::Error>(response)
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn authorizer(url: String) -> ExtAuthzConfig {
+        let mut config = ExtAuthzConfig::new(url);
+        config.path_prefix = "/check".to_string();
+        config.upstream_headers = vec!["x-user-id".to_string()];
+        config
+    }
+
+    #[tokio::test]
+    async fn test_allow_applies_upstream_headers() {
+        let authz = ExtAuthorizer::new(authorizer(spawn_authz().await)).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer good"));
+        headers.insert("x-user-id", HeaderValue::from_static("spoofed"));
+
+        let uri: Uri = "/api/items?page=2".parse().unwrap();
+        let decision = authz.authorize(&Method::GET, &uri, &mut headers, [10, 0, 0, 1].into()).await;
+        assert!(matches!(decision, AuthzDecision::Allow));
+        assert_eq!(headers["x-user-id"], "alice");
+        assert!(headers.get("x-ignored").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deny_returns_service_answer() {
+        let authz = ExtAuthorizer::new(authorizer(spawn_authz().await)).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer bad"));
+
+        let uri: Uri = "/api/items".parse().unwrap();
+        let AuthzDecision::Deny(response) = authz.authorize(&Method::POST, &uri, &mut headers, [10, 0, 0, 1].into()).await else {
+            panic!("expected a denial");
+        };
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        assert_eq!(response.body(), "who are you");
+    }
+
+    #[tokio::test]
+    async fn test_failure_policy() {
+        let url = spawn_authz().await;
+        let uri: Uri = "/slow".parse().unwrap();
+        let mut config = authorizer(url);
+        config.timeout = Duration::from_millis(50);
+
+        let closed = ExtAuthorizer::new(config.clone()).unwrap();
+        let decision = closed.authorize(&Method::GET, &uri, &mut HeaderMap::new(), [10, 0, 0, 1].into()).await;
+        assert!(matches!(decision, AuthzDecision::Deny(r) if r.status() == StatusCode::FORBIDDEN));
+
+        config.fail_open = true;
+        let open = ExtAuthorizer::new(config).unwrap();
+        let decision = open.authorize(&Method::GET, &uri, &mut HeaderMap::new(), [10, 0, 0, 1].into()).await;
+        assert!(matches!(decision, AuthzDecision::Allow));
+    }
+
+    #[tokio::test]
+    async fn test_checks_normalized_path() {
+        let mut config = authorizer(spawn_authz().await);
+        config.upstream_headers.push("x-checked-path".to_string());
+        config.route_prefixes = vec!["/admin".to_string()];
+        let authz = ExtAuthorizer::new(config).unwrap();
+
+        for uri in ["/x/../admin?q=1", "//admin?q=1", "/%61dmin?q=1"] {
+            assert!(authz.applies_to(uri.split('?').next().unwrap()), "{}", uri);
+            let mut headers = HeaderMap::new();
+            headers.insert("authorization", HeaderValue::from_static("Bearer good"));
+            let uri: Uri = uri.parse().unwrap();
+            let decision = authz.authorize(&Method::GET, &uri, &mut headers, [10, 0, 0, 1].into()).await;
+            assert!(matches!(decision, AuthzDecision::Allow));
+            assert_eq!(headers["x-checked-path"], "/check/admin?q=1");
+        }
+
+        let uri: Uri = "/x%2F..%2Fadmin".parse().unwrap();
+        assert!(authz.applies_to(uri.path()));
+        let decision = authz.authorize(&Method::GET, &uri, &mut HeaderMap::new(), [10, 0, 0, 1].into()).await;
+        assert!(matches!(decision, AuthzDecision::Deny(r) if r.status() == StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let mut config = ExtAuthzConfig::new("http://authz:9000".to_string());
+        assert!(ExtAuthorizer::new(config.clone()).unwrap().applies_to("/anything"));
+
+        config.route_prefixes = vec!["/admin".to_string()];
+        let authz = ExtAuthorizer::new(config).unwrap();
+        assert!(authz.applies_to("/admin/users"));
+        assert!(!authz.applies_to("/public"));
+
+        assert!(ExtAuthorizer::new(ExtAuthzConfig::new("not a url".to_string())).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Fault injection for resilience testing
+//!
+//! Routes can delay a share of requests or answer them with an error
+//! status, so teams can see how their clients cope with a slow or failing
+//! dependency without touching the backend.
+
+use crate::metrics::MetricsCollector;
+use anyhow::{Result, anyhow, bail};
+use hyper::body::Bytes;
+use hyper::{Response, StatusCode};
+use rand::Rng;
+use router_api::v1alpha1::vpc_route::FaultInjectionPolicy;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Injected delay
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelayFault {
+    /// Shortest delay
+    pub min: Duration,
+    /// Longest delay; equal to `min` for a fixed delay
+    pub max: Duration,
+    /// Share of requests delayed (0-100)
+    pub percentage: f64,
+}
+
+/// Injected abort
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbortFault {
+    /// Status code returned
+    pub status: StatusCode,
+    /// Share of requests aborted (0-100)
+    pub percentage: f64,
+}
+
+/// Faults chosen for one request
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultDecision {
+    /// Wait this long before continuing
+    pub delay: Option<Duration>,
+    /// Answer with this status instead of forwarding
+    pub abort: Option<StatusCode>,
+}
+
+/// Decides which requests get faults
+pub struct FaultInjector {
+    delay: Option<DelayFault>,
+    abort: Option<AbortFault>,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl FaultInjector {
+    /// Create an injector
+    pub fn new(delay: Option<DelayFault>, abort: Option<AbortFault>) -> Result<Self> {
+        if let Some(delay) = &delay {
+            check_percentage(delay.percentage)?;
+            if delay.max < delay.min {
+                bail!("Fault delay maximum is shorter than its minimum");
+            }
+        }
+        if let Some(abort) = &abort {
+            check_percentage(abort.percentage)?;
+        }
+        Ok(Self {
+            delay,
+            abort,
+            metrics: None,
+        })
+    }
+
+    /// Create an injector from a VPCRoute fault policy
+    pub fn from_policy(policy: &FaultInjectionPolicy) -> Result<Self> {
+        let delay = policy.delay.as_ref().map(|delay| {
+            let min = Duration::from_millis(delay.fixed_delay_ms as u64);
+            DelayFault {
+                min,
+                max: delay.max_delay_ms.map_or(min, |max| Duration::from_millis(max as u64)),
+                percentage: delay.percentage,
+            }
+        });
+        let abort = policy
+            .abort
+            .as_ref()
+            .map(|abort| {
+                let status = StatusCode::from_u16(abort.status_code)
+                    .map_err(|_| anyhow!("Invalid fault abort status {}", abort.status_code))?;
+                Ok::<_, anyhow::Error>(AbortFault {
+                    status,
+                    percentage: abort.percentage,
+                })
+            })
+            .transpose()?;
+        Self::new(delay, abort)
+    }
+
+    /// Count injected faults through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Choose the faults for a request
+    pub fn decide(&self) -> FaultDecision {
+        self.decide_with(&mut rand::thread_rng())
+    }
+
+    /// Choose the faults for a request using `rng`
+    pub fn decide_with<R: Rng>(&self, rng: &mut R) -> FaultDecision {
+        let mut decision = FaultDecision::default();
+        if let Some(delay) = self.delay.as_ref().filter(|d| hit(rng, d.percentage)) {
+            decision.delay = Some(if delay.max > delay.min {
+                rng.gen_range(delay.min..=delay.max)
+            } else {
+                delay.min
+            });
+        }
+        if let Some(abort) = self.abort.as_ref().filter(|a| hit(rng, a.percentage)) {
+            decision.abort = Some(abort.status);
+        }
+
+        if let Some(metrics) = &self.metrics {
+            if decision.delay.is_some() {
+                metrics.record_fault("delay");
+            }
+            if decision.abort.is_some() {
+                metrics.record_fault("abort");
+            }
+        }
+        decision
+    }
+
+    /// Response sent for an injected abort
+    pub fn abort_response(status: StatusCode) -> Response<Bytes> {
+        Response::builder()
+            .status(status)
+            .header("x-router-fault", "abort")
+            .body(Bytes::from("Fault injected\n"))
+            .unwrap()
+    }
+}
+
+fn check_percentage(percentage: f64) -> Result<()> {
+    if !(0.0..=100.0).contains(&percentage) {
+        bail!("Fault percentage {} is outside 0-100", percentage);
+    }
+    Ok(())
+}
+
+fn hit<R: Rng>(rng: &mut R, percentage: f64) -> bool {
+    percentage >= 100.0 || (percentage > 0.0 && rng.gen_range(0.0..100.0) < percentage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use router_api::v1alpha1::vpc_route::{FaultAbort, FaultDelay};
+
+    #[test]
+    fn test_fixed_delay_and_abort() {
+        let policy = FaultInjectionPolicy {
+            delay: Some(FaultDelay {
+                fixed_delay_ms: 250,
+                max_delay_ms: None,
+                percentage: 100.0,
+            }),
+            abort: Some(FaultAbort {
+                status_code: 503,
+                percentage: 100.0,
+            }),
+        };
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let injector = FaultInjector::from_policy(&policy).unwrap().with_metrics(metrics.clone());
+
+        let decision = injector.decide();
+        assert_eq!(decision.delay, Some(Duration::from_millis(250)));
+        assert_eq!(decision.abort, Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(metrics.faults_injected_total.with_label_values(&["abort"]).get(), 1.0);
+    }
+
+    #[test]
+    fn test_random_delay_within_bounds() {
+        let delay = DelayFault {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+            percentage: 100.0,
+        };
+        let injector = FaultInjector::new(Some(delay), None).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let delay = injector.decide_with(&mut rng).delay.unwrap();
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn test_percentage() {
+        let abort = AbortFault {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            percentage: 25.0,
+        };
+        let injector = FaultInjector::new(None, Some(abort)).unwrap();
+        let mut rng = StdRng::seed_from_u64(42);
+        let aborted = (0..10_000).filter(|_| injector.decide_with(&mut rng).abort.is_some()).count();
+        assert!((2_000..3_000).contains(&aborted), "aborted {} of 10000", aborted);
+
+        let never = AbortFault {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            percentage: 0.0,
+        };
+        let injector = FaultInjector::new(None, Some(never)).unwrap();
+        assert_eq!(injector.decide(), FaultDecision::default());
+    }
+
+    #[test]
+    fn test_invalid_policy_rejected() {
+        let abort = AbortFault {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            percentage: 150.0,
+        };
+        assert!(FaultInjector::new(None, Some(abort)).is_err());
+
+        let policy = FaultInjectionPolicy {
+            delay: Some(FaultDelay {
+                fixed_delay_ms: 100,
+                max_delay_ms: Some(10),
+                percentage: 100.0,
+            }),
+            abort: None,
+        };
+        assert!(FaultInjector::from_policy(&policy).is_err());
+    }
+}
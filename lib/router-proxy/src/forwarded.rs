@@ -0,0 +1,169 @@
+//! X-Forwarded-* and Forwarded headers for upstream requests
+//!
+//! Backends see the original client address, scheme, and host. Headers
+//! that arrive from untrusted peers are replaced, since any client could
+//! have set them; those from trusted proxies are extended.
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, FORWARDED};
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+use tracing::debug;
+
+/// X-Forwarded-For header
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+/// X-Forwarded-Proto header
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+/// X-Forwarded-Host header
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Forwarded header configuration
+#[derive(Clone, Debug, Default)]
+pub struct ForwardedConfig {
+    /// Peers whose forwarding headers are kept and extended
+    pub trusted_proxies: Vec<IpNetwork>,
+    /// Also send the standard Forwarded header (RFC 7239)
+    pub forwarded_header: bool,
+}
+
+impl ForwardedConfig {
+    /// Parse a comma-separated list of trusted proxy networks
+    ///
+    /// Plain addresses are accepted as single-host networks.
+    pub fn parse_trusted_proxies(value: &str) -> anyhow::Result<Vec<IpNetwork>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<IpNetwork>()
+                    .map_err(|e| anyhow::anyhow!("Invalid trusted proxy {:?}: {}", s, e))
+            })
+            .collect()
+    }
+}
+
+/// Sets forwarding headers on requests sent upstream
+#[derive(Clone, Debug, Default)]
+pub struct ForwardedHeaders {
+    config: ForwardedConfig,
+}
+
+impl ForwardedHeaders {
+    /// Create forwarding header handling
+    pub fn new(config: ForwardedConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether `peer` is a trusted proxy
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        self.config.trusted_proxies.iter().any(|network| network.contains(peer))
+    }
+
+    /// Rewrite the forwarding headers of a request
+    ///
+    /// `peer` is the connecting address, `proto` the listener's scheme
+    /// ("http" or "https"), and `host` the host the client addressed.
+    pub fn apply(&self, headers: &mut HeaderMap, peer: IpAddr, proto: &str, host: Option<&str>) {
+        if !self.is_trusted(peer) {
+            for name in [X_FORWARDED_FOR, X_FORWARDED_PROTO, X_FORWARDED_HOST, FORWARDED] {
+                if headers.remove(&name).is_some() {
+                    debug!("Dropped {} from untrusted peer {}", name, peer);
+                }
+            }
+        }
+
+        // Append the peer to the chain, joining repeated headers into one
+        let peer_text = peer.to_string();
+        let mut chain: Vec<&str> = headers
+            .get_all(&X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        chain.push(&peer_text);
+        let xff = chain.join(", ");
+        set(headers, X_FORWARDED_FOR, &xff);
+
+        // A trusted proxy's view of the original request wins
+        if !headers.contains_key(&X_FORWARDED_PROTO) {
+            set(headers, X_FORWARDED_PROTO, proto);
+        }
+        if let Some(host) = host.filter(|_| !headers.contains_key(&X_FORWARDED_HOST)) {
+            set(headers, X_FORWARDED_HOST, host);
+        }
+
+        if self.config.forwarded_header {
+            let mut element = format!("for={};proto={}", forwarded_node(peer), proto);
+            if let Some(host) = host {
+                element.push_str(&format!(";host=\"{}\"", host.replace('"', "")));
+            }
+            let value = match headers.get(FORWARDED).and_then(|v| v.to_str().ok()) {
+                Some(existing) => format!("{}, {}", existing, element),
+                None => element,
+            };
+            set(headers, FORWARDED, &value);
+        }
+    }
+}
+
+/// Node identifier for the Forwarded header; IPv6 must be quoted
+fn forwarded_node(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("\"[{}]\"", v6),
+    }
+}
+
+fn set(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(_) => debug!("Skipping invalid {} value {:?}", name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(trusted: &str) -> ForwardedHeaders {
+        ForwardedHeaders::new(ForwardedConfig {
+            trusted_proxies: ForwardedConfig::parse_trusted_proxies(trusted).unwrap(),
+            forwarded_header: true,
+        })
+    }
+
+    #[test]
+    fn test_untrusted_peer_headers_replaced() {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, HeaderValue::from_static("6.6.6.6"));
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static("https"));
+
+        forwarded("10.0.0.0/8").apply(&mut headers, "203.0.113.7".parse().unwrap(), "http", Some("api.example.com"));
+        assert_eq!(headers[&X_FORWARDED_FOR], "203.0.113.7");
+        assert_eq!(headers[&X_FORWARDED_PROTO], "http");
+        assert_eq!(headers[&X_FORWARDED_HOST], "api.example.com");
+        assert_eq!(headers[FORWARDED], "for=203.0.113.7;proto=http;host=\"api.example.com\"");
+    }
+
+    #[test]
+    fn test_trusted_proxy_chain_extended() {
+        let mut headers = HeaderMap::new();
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("198.51.100.1"));
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("198.51.100.2"));
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static("https"));
+        headers.insert(FORWARDED, HeaderValue::from_static("for=198.51.100.1"));
+
+        forwarded("10.0.0.0/8, 2001:db8::/32").apply(&mut headers, "2001:db8::5".parse().unwrap(), "http", None);
+        assert_eq!(headers[&X_FORWARDED_FOR], "198.51.100.1, 198.51.100.2, 2001:db8::5");
+        assert_eq!(headers[&X_FORWARDED_PROTO], "https");
+        assert!(!headers.contains_key(&X_FORWARDED_HOST));
+        assert_eq!(headers[FORWARDED], "for=198.51.100.1, for=\"[2001:db8::5]\";proto=http");
+    }
+
+    #[test]
+    fn test_parse_trusted_proxies() {
+        assert_eq!(ForwardedConfig::parse_trusted_proxies("10.0.0.1, fd00::/8").unwrap().len(), 2);
+        assert!(ForwardedConfig::parse_trusted_proxies("not-an-ip").is_err());
+    }
+}
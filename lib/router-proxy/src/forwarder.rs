@@ -1,65 +1,634 @@
 //! HTTP/HTTPS request/response body forwarding with actual client forwarding
-//! Supports mTLS (mutual TLS) for service-to-service authentication
+//! Supports HTTPS upstreams and mTLS (mutual TLS) for service-to-service authentication
+//! Supports HTTP/1.1 and HTTP/2 (h2c or ALPN-negotiated) upstreams
+//!
+//! Each upstream attempt runs in its own `upstream_attempt` span; retries
+//! follow from the attempt before them, and a traced request's
+//! traceparent names the attempt's span so backend spans attach to it.
 
 use hyper::{Request, Response, StatusCode, body::Bytes, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::dns::GaiResolver;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::rt::tokio::TokioExecutor;
-use http_body_util::{BodyExt, Full};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use std::collections::HashMap;
 use std::time::Duration;
 use std::sync::Arc;
-use tokio::time::timeout as tokio_timeout;
-use tracing::{debug, warn, info};
+use tokio::sync::Semaphore;
+use tokio::time::{timeout as tokio_timeout, timeout_at, Instant};
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
 use anyhow::Result;
+use thiserror::Error;
+use crate::concurrency::{ConcurrencyConfig, ConcurrencyLimiter};
+use crate::dns::{CachingResolver, ConnectorResolver};
+use crate::inflight::{self, InflightHandle, InflightState};
+use crate::metrics::MetricsCollector;
+use crate::middleware::BodyHookError;
 use crate::mtls::TlsClientConfig;
+use crate::path_rewrite::PathRewrite;
+use crate::policy::{is_idempotent, CircuitBreakerRegistry, RetryPolicy, TimeoutPolicy};
+use crate::pool::PoolConfig;
+use crate::problem::ProblemDetails;
+use crate::timing::{measure_connect, TimedConnector, UpstreamTiming};
+use crate::tracing::TracingMiddleware;
+use crate::trailers::{GrpcStatus, UpstreamTrailers};
+use router_api::v1alpha1::vpc_route::{ResponseLimitAction, ResponseLimitPolicy};
+
+/// Response header marking a body cut at its route's size limit; the
+/// value is the limit in bytes
+pub const RESPONSE_TRUNCATED: &str = "x-router-response-truncated";
+
+/// VPCService (namespace/name) a request is sent to, set as a request
+/// extension
+///
+/// Per-upstream pools, concurrency limits and protocols are looked up
+/// under it; requests without one use the authority (host:port) of their
+/// target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpstreamService(pub String);
+
+/// Size limit for the response to a request, set as a request extension
+///
+/// Responses are buffered before they are sent on, so the limit bounds the
+/// memory a single backend response can take.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResponseLimit {
+    /// Largest response body accepted, in bytes
+    pub max_bytes: usize,
+    /// What happens to a larger body
+    pub action: ResponseLimitAction,
+}
+
+impl ResponseLimit {
+    /// Create a limit from a VPCRoute response limit policy
+    pub fn from_policy(policy: &ResponseLimitPolicy) -> Self {
+        Self {
+            max_bytes: usize::try_from(policy.max_bytes).unwrap_or(usize::MAX),
+            action: policy.on_exceed,
+        }
+    }
+}
+
+/// Protocol used to talk to an upstream
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UpstreamProtocol {
+    /// HTTP/1.1 over plaintext; HTTP/2 when negotiated via ALPN over TLS
+    #[default]
+    Auto,
+    /// HTTP/1.1 only
+    Http1,
+    /// HTTP/2 with prior knowledge (h2c over plaintext)
+    Http2,
+}
+
+impl UpstreamProtocol {
+    /// Get the protocol name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpstreamProtocol::Auto => "auto",
+            UpstreamProtocol::Http1 => "http1",
+            UpstreamProtocol::Http2 => "http2",
+        }
+    }
+
+    /// Parse a protocol from a configuration string
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Some(UpstreamProtocol::Auto),
+            "http1" | "http/1.1" => Some(UpstreamProtocol::Http1),
+            "http2" | "h2" | "h2c" => Some(UpstreamProtocol::Http2),
+            _ => None,
+        }
+    }
+
+    /// Protocol for a VPCService's declared protocol
+    ///
+    /// gRPC requires HTTP/2; everything else negotiates.
+    pub fn for_service_protocol(protocol: &str) -> Self {
+        if protocol.eq_ignore_ascii_case("grpc") {
+            UpstreamProtocol::Http2
+        } else {
+            UpstreamProtocol::Auto
+        }
+    }
+}
+
+/// Why a request could not be forwarded
+///
+/// Returned by the forwarder so retries, circuit breakers, metrics, and
+/// error pages can react to the specific failure. Every error still maps
+/// to the response a client should see with [`ForwardError::into_response`].
+#[derive(Debug, Error)]
+pub enum ForwardError {
+    /// The target URL could not be parsed
+    #[error("Invalid upstream URL: {0}")]
+    InvalidTarget(String),
+
+    /// The request body is over the configured limit
+    #[error("Request body exceeds the {0} byte limit")]
+    RequestTooLarge(usize),
+
+    /// The upstream's circuit breaker is open
+    #[error("Circuit open for {0}")]
+    CircuitOpen(String),
+
+    /// The upstream has as many requests in flight as it is allowed
+    #[error("Too many requests in flight to {0}")]
+    Saturated(String),
+
+    /// No connection, or free connection slot, within the timeout
+    #[error("Timed out connecting to {0}")]
+    ConnectTimeout(String),
+
+    /// The connection was refused or could not be opened
+    #[error("Failed to connect to {upstream}: {reason}")]
+    Refused { upstream: String, reason: String },
+
+    /// The connection failed after the request may have been sent
+    #[error("Connection to {upstream} failed: {reason}")]
+    Reset { upstream: String, reason: String },
+
+    /// The TLS handshake with the upstream failed
+    #[error("TLS handshake with {upstream} failed: {reason}")]
+    TlsFailure { upstream: String, reason: String },
+
+    /// The upstream sent no response headers within the first byte timeout,
+    /// or no complete response within the total timeout
+    #[error("No response from {0} within the request timeout")]
+    Timeout(String),
+
+    /// The upstream stopped sending the response body for longer than the
+    /// idle timeout
+    #[error("Response body from {0} stalled past the idle timeout")]
+    IdleTimeout(String),
+
+    /// The upstream answered with a server error status
+    ///
+    /// The response is kept so it can be passed on to the client.
+    #[error("Upstream {upstream} responded with {}", .response.status())]
+    UpstreamStatus { upstream: String, response: Box<Response<Bytes>> },
+
+    /// A request or response body could not be read
+    #[error("Failed to read body: {0}")]
+    BodyError(String),
+
+    /// The response body is over the request's [`ResponseLimit`]
+    #[error("Response from {upstream} exceeds the {limit} byte limit")]
+    ResponseTooLarge { upstream: String, limit: usize },
+}
+
+impl ForwardError {
+    /// Classify a hyper client error by where the request failed
+    ///
+    /// Connection setup failures (refused, unreachable, TLS handshake)
+    /// happen before anything is written. Any other failure, such as a
+    /// reset mid-response, may come after the upstream acted on the request.
+    fn from_client_error(upstream: &str, error: &hyper_util::client::legacy::Error) -> Self {
+        let upstream = upstream.to_string();
+        let mut reason = error.to_string();
+        let mut source = std::error::Error::source(error);
+        let mut tls = false;
+        let mut timed_out = false;
+        while let Some(cause) = source {
+            reason = format!("{}: {}", reason, cause);
+            if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+                timed_out |= io.kind() == std::io::ErrorKind::TimedOut;
+            }
+            tls |= is_tls_error(cause);
+            source = cause.source();
+        }
+
+        if !error.is_connect() {
+            ForwardError::Reset { upstream, reason }
+        } else if tls {
+            ForwardError::TlsFailure { upstream, reason }
+        } else if timed_out {
+            ForwardError::ConnectTimeout(upstream)
+        } else {
+            ForwardError::Refused { upstream, reason }
+        }
+    }
+
+    /// Short name of the failure, used in metrics and spans
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ForwardError::InvalidTarget(_) => "invalid_target",
+            ForwardError::RequestTooLarge(_) => "request_too_large",
+            ForwardError::CircuitOpen(_) => "circuit_open",
+            ForwardError::Saturated(_) => "saturated",
+            ForwardError::ConnectTimeout(_) => "connect_timeout",
+            ForwardError::Refused { .. } => "refused",
+            ForwardError::Reset { .. } => "reset",
+            ForwardError::TlsFailure { .. } => "tls_failure",
+            ForwardError::Timeout(_) => "timeout",
+            ForwardError::IdleTimeout(_) => "idle_timeout",
+            ForwardError::UpstreamStatus { .. } => "upstream_status",
+            ForwardError::BodyError(_) => "body_error",
+            ForwardError::ResponseTooLarge { .. } => "response_too_large",
+        }
+    }
+
+    /// Whether the request failed before it was sent, so retrying it is safe
+    /// whatever its method
+    pub fn is_connect(&self) -> bool {
+        matches!(
+            self,
+            ForwardError::ConnectTimeout(_) | ForwardError::Refused { .. } | ForwardError::TlsFailure { .. }
+        )
+    }
+
+    /// Status code of the response sent to the client
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ForwardError::InvalidTarget(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ForwardError::RequestTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ForwardError::CircuitOpen(_) | ForwardError::Saturated(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ForwardError::ConnectTimeout(_) | ForwardError::Timeout(_) | ForwardError::IdleTimeout(_) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ForwardError::UpstreamStatus { response, .. } => response.status(),
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// Response sent to the client for this error
+    pub fn into_response(self) -> Response<Bytes> {
+        let status = self.status();
+        let message = match self {
+            ForwardError::UpstreamStatus { response, .. } => return *response,
+            ForwardError::RequestTooLarge(_) => return RequestForwarder::payload_too_large_response(),
+            ForwardError::InvalidTarget(_) => "Internal Server Error\n",
+            ForwardError::CircuitOpen(_) => "Backend service unavailable (circuit open)\n",
+            ForwardError::Saturated(_) => {
+                let mut response = RequestForwarder::error_response(status, "Backend service busy\n");
+                response
+                    .headers_mut()
+                    .insert(hyper::header::RETRY_AFTER, hyper::header::HeaderValue::from_static("1"));
+                return response;
+            }
+            ForwardError::ConnectTimeout(_) | ForwardError::Timeout(_) | ForwardError::IdleTimeout(_) => {
+                "Backend service request timeout\n"
+            }
+            ForwardError::ResponseTooLarge { .. } => "Backend response too large\n",
+            _ => "Error communicating with backend service\n",
+        };
+        RequestForwarder::error_response(status, message)
+    }
+}
+
+/// Whether an error is, or wraps, a rustls error
+///
+/// TLS handshake failures arrive as I/O errors wrapping the rustls error,
+/// sometimes more than once, and an I/O error's `source` skips what it wraps.
+fn is_tls_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    if error.is::<rustls::Error>() {
+        return true;
+    }
+    match error.downcast_ref::<std::io::Error>().and_then(|io| io.get_ref()) {
+        Some(inner) => is_tls_error(inner),
+        None => false,
+    }
+}
+
+/// Outcome recorded on an attempt's span
+fn attempt_outcome(attempt: &std::result::Result<Response<Bytes>, ForwardError>) -> &'static str {
+    match attempt {
+        Ok(response) if response.status().is_server_error() => "server_error",
+        Ok(_) => "response",
+        Err(e) => e.kind(),
+    }
+}
+
+/// Why a request body could not be buffered
+#[derive(Debug, Error)]
+pub enum RequestBodyError {
+    #[error("Request body exceeds the {0} byte limit")]
+    TooLarge(usize),
+
+    #[error("Failed to read request body: {0}")]
+    Read(String),
+
+    #[error("Request body rejected: {0}")]
+    Rejected(String),
+}
+
+/// W3C trace context header
+const TRACEPARENT: &str = "traceparent";
+
+/// Pooled hyper client used for upstream requests
+type UpstreamClient = Client<TimedConnector<HttpsConnector<HttpConnector<ConnectorResolver>>>, Full<Bytes>>;
+
+/// Clients for each upstream protocol, built from one pool configuration
+#[derive(Clone)]
+struct ClientSet {
+    auto: UpstreamClient,
+    /// HTTP/1.1 client kept separate from negotiated connections
+    http1: UpstreamClient,
+    /// HTTP/2 prior-knowledge client
+    http2: UpstreamClient,
+}
+
+impl ClientSet {
+    /// Client for an upstream protocol
+    fn get(&self, protocol: UpstreamProtocol) -> &UpstreamClient {
+        match protocol {
+            UpstreamProtocol::Auto => &self.auto,
+            UpstreamProtocol::Http1 => &self.http1,
+            UpstreamProtocol::Http2 => &self.http2,
+        }
+    }
+}
+
+/// A request's slot in a pool with a connection limit
+struct PoolSlot {
+    _permit: tokio::sync::OwnedSemaphorePermit,
+    slots: Arc<Semaphore>,
+    max: usize,
+    upstream: String,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl PoolSlot {
+    /// Report the slots in use, leaving this one out if it is being released
+    fn report(&self, releasing: bool) {
+        if let Some(metrics) = &self.metrics {
+            let in_use = self.max.saturating_sub(self.slots.available_permits());
+            metrics.set_upstream_pool_in_use(&self.upstream, in_use.saturating_sub(releasing as usize));
+        }
+    }
+}
+
+impl Drop for PoolSlot {
+    fn drop(&mut self) {
+        // The permit is released after this runs
+        self.report(true);
+    }
+}
+
+/// Dedicated pool for one upstream
+struct UpstreamPool {
+    config: PoolConfig,
+    clients: ClientSet,
+    /// Slots for requests in flight when connections are limited
+    slots: Option<Arc<Semaphore>>,
+}
 
 /// HTTP/HTTPS request forwarder for proxying requests to backend services
 /// with connection pooling and timeout support.
 ///
 /// Supports optional mTLS (mutual TLS) for service-to-service authentication
-/// when configured with a TlsClientConfig.
+/// when configured with a TlsClientConfig. HTTP/2 upstreams share one
+/// multiplexed connection per backend for concurrent requests.
 pub struct RequestForwarder {
-    client: Client<HttpConnector, Full<Bytes>>,
-    timeout: Duration,
+    clients: ClientSet,
+    /// Pool settings of the shared clients
+    pool_config: PoolConfig,
+    /// Pools for upstream authorities (host:port) with their own settings
+    pools: HashMap<String, UpstreamPool>,
+    /// In-flight request limits per upstream authority (host:port)
+    concurrency: HashMap<String, Arc<ConcurrencyLimiter>>,
+    timeouts: TimeoutPolicy,
     /// Optional TLS configuration for HTTPS/mTLS requests
     tls_config: Option<Arc<TlsClientConfig>>,
+    /// Protocol per upstream authority (host:port)
+    protocols: HashMap<String, UpstreamProtocol>,
+    /// Retry policy; requests are sent once when unset
+    retry_policy: Option<RetryPolicy>,
+    /// Metrics for retry attempts
+    metrics: Option<Arc<MetricsCollector>>,
+    /// Circuit breakers per upstream authority
+    circuit_breakers: Option<Arc<CircuitBreakerRegistry>>,
+    /// Largest request body accepted, in bytes
+    max_request_body: Option<usize>,
+    /// TLS settings the clients were built with
+    client_tls: rustls::ClientConfig,
+    /// Cached resolver for backend hostnames; getaddrinfo when unset
+    resolver: Option<CachingResolver>,
 }
 
 impl RequestForwarder {
     /// Create a new HTTP request forwarder with connection pooling
     ///
-    /// For HTTPS/mTLS support, use `with_tls()` instead.
+    /// Connecting and waiting for response headers are each limited to
+    /// `timeout`; use `with_timeout_policy()` to limit the body too.
+    /// HTTPS backends are verified against the system trust roots and no
+    /// client certificate is sent; use `with_tls()` for a custom CA or mTLS.
     pub fn new(timeout: Duration) -> Self {
-        // Configure HTTP connector with connection pooling
-        let mut connector = HttpConnector::new();
-        connector.set_connect_timeout(Some(timeout));
-        connector.set_keepalive(Some(Duration::from_secs(30)));
-
-        // Create hyper client with the connector and tokio executor
-        let client = Client::builder(TokioExecutor::new())
-            .build::<_, Full<Bytes>>(connector);
+        let tls = TlsClientConfig::new(vec![], vec![], None, true)
+            .client_config()
+            .expect("TLS settings without a client certificate are always valid");
+        let pool_config = PoolConfig::default();
+        let timeouts = TimeoutPolicy::uniform(timeout);
+        let clients = Self::build_clients(&timeouts, tls.clone(), None, &pool_config);
 
         Self {
-            client,
-            timeout,
+            clients,
+            pool_config,
+            pools: HashMap::new(),
+            concurrency: HashMap::new(),
+            timeouts,
             tls_config: None,
+            protocols: HashMap::new(),
+            retry_policy: None,
+            metrics: None,
+            circuit_breakers: None,
+            max_request_body: None,
+            client_tls: tls,
+            resolver: None,
         }
     }
 
+    /// Build pooled clients for each upstream protocol
+    ///
+    /// Each client speaks plaintext HTTP and HTTPS. Over TLS, the ALPN
+    /// offer matches the protocol: both for Auto, one for the others.
+    fn build_clients(
+        timeouts: &TimeoutPolicy,
+        tls: rustls::ClientConfig,
+        resolver: Option<CachingResolver>,
+        pool: &PoolConfig,
+    ) -> ClientSet {
+        let resolver = match resolver {
+            Some(resolver) => ConnectorResolver::Cached(resolver),
+            None => ConnectorResolver::System(GaiResolver::new()),
+        };
+
+        // Configure HTTP connector with connection pooling
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_connect_timeout(Some(timeouts.connect_timeout));
+        connector.set_keepalive(pool.tcp_keepalive);
+        connector.enforce_http(false);
+
+        let auto_connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls.clone())
+            .https_or_http()
+            .enable_all_versions()
+            .wrap_connector(connector.clone());
+        let http1_connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls.clone())
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(connector.clone());
+        let http2_connector = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_or_http()
+            .enable_http2()
+            .wrap_connector(connector);
+
+        // Create hyper clients with the connector and tokio executor
+        let builder = || {
+            let mut builder = Client::builder(TokioExecutor::new());
+            builder
+                .pool_max_idle_per_host(pool.max_idle_per_host)
+                .pool_idle_timeout(pool.idle_timeout);
+            builder
+        };
+        ClientSet {
+            auto: builder().build::<_, Full<Bytes>>(TimedConnector::new(auto_connector)),
+            http1: builder().build::<_, Full<Bytes>>(TimedConnector::new(http1_connector)),
+            http2: builder()
+                .http2_only(true)
+                .build::<_, Full<Bytes>>(TimedConnector::new(http2_connector)),
+        }
+    }
+
+    /// Build a dedicated pool for one upstream
+    fn build_pool(&self, config: PoolConfig) -> UpstreamPool {
+        let clients = Self::build_clients(&self.timeouts, self.client_tls.clone(), self.resolver.clone(), &config);
+        let slots = config.max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        UpstreamPool { config, clients, slots }
+    }
+
+    /// Use `config` for the pool shared by upstreams without their own
+    pub fn with_pool_config(mut self, config: PoolConfig) -> Self {
+        self.clients = Self::build_clients(&self.timeouts, self.client_tls.clone(), self.resolver.clone(), &config);
+        self.pool_config = config;
+        self
+    }
+
+    /// Give `upstream` its own connection pool
+    ///
+    /// `upstream` is the [`UpstreamService`] of requests, or the authority
+    /// (host:port) of requests without one. With `max_connections` set,
+    /// requests beyond the limit wait for a slot within the connect timeout.
+    pub fn with_upstream_pool(mut self, upstream: &str, config: PoolConfig) -> Self {
+        let pool = self.build_pool(config);
+        self.pools.insert(upstream.to_string(), pool);
+        self
+    }
+
+    /// Limit the requests in flight to `upstream`, an [`UpstreamService`] or
+    /// an authority (host:port)
+    ///
+    /// A request holds its slot across retries. Requests that find no slot
+    /// within the queue settings fail with [`ForwardError::Saturated`], a
+    /// 503 with Retry-After, so a slow upstream can't tie up the gateway.
+    pub fn with_upstream_concurrency(mut self, upstream: &str, config: ConcurrencyConfig) -> Self {
+        self.concurrency
+            .insert(upstream.to_string(), Arc::new(ConcurrencyLimiter::new(config)));
+        self
+    }
+
+    /// Use `protocol` for requests to `upstream`, an [`UpstreamService`] or
+    /// an authority (host:port)
+    pub fn with_upstream_protocol(mut self, upstream: &str, protocol: UpstreamProtocol) -> Self {
+        self.protocols.insert(upstream.to_string(), protocol);
+        self
+    }
+
+    /// Retry failed requests according to `policy`
+    ///
+    /// Connection errors are always retried. Failures after the request may
+    /// have been sent, including timeouts, are only retried for idempotent
+    /// methods; timeouts also need 504 to be a retryable status code. gRPC
+    /// responses are also retried on the policy's gRPC statuses, read from
+    /// their trailers.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Fail fast with 503 for upstreams whose circuit is open
+    ///
+    /// Connection errors, timeouts, 5xx responses, and gRPC responses with
+    /// one of the configured failure statuses count as failures.
+    pub fn with_circuit_breakers(mut self, circuit_breakers: Arc<CircuitBreakerRegistry>) -> Self {
+        self.circuit_breakers = Some(circuit_breakers);
+        self
+    }
+
+    /// Resolve backend hostnames through `resolver`
+    ///
+    /// A connection error evicts the upstream's host from the cache so the
+    /// next attempt resolves it again.
+    pub fn with_resolver(mut self, resolver: CachingResolver) -> Self {
+        self.resolver = Some(resolver);
+        self.clients =
+            Self::build_clients(&self.timeouts, self.client_tls.clone(), self.resolver.clone(), &self.pool_config);
+        let pools = std::mem::take(&mut self.pools);
+        for (authority, pool) in pools {
+            let pool = self.build_pool(pool.config);
+            self.pools.insert(authority, pool);
+        }
+        self
+    }
+
+    /// Limit each phase of upstream exchanges according to `policy`
+    ///
+    /// Responses whose body stalls past the idle timeout, or that don't
+    /// complete within the total timeout, fail with 504 like responses
+    /// whose headers don't arrive in time.
+    pub fn with_timeout_policy(mut self, policy: TimeoutPolicy) -> Self {
+        self.timeouts = policy;
+        self.clients =
+            Self::build_clients(&self.timeouts, self.client_tls.clone(), self.resolver.clone(), &self.pool_config);
+        let pools = std::mem::take(&mut self.pools);
+        for (authority, pool) in pools {
+            let pool = self.build_pool(pool.config);
+            self.pools.insert(authority, pool);
+        }
+        self
+    }
+
+    /// Timeouts applied to upstream exchanges
+    pub fn timeout_policy(&self) -> &TimeoutPolicy {
+        &self.timeouts
+    }
+
+    /// Record retry attempts in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Reject request bodies larger than `bytes` with 413
+    pub fn with_max_request_body(mut self, bytes: usize) -> Self {
+        self.max_request_body = Some(bytes);
+        self
+    }
+
+    /// Largest request body accepted, if limited
+    pub fn max_request_body(&self) -> Option<usize> {
+        self.max_request_body
+    }
+
+    /// Protocol used for an upstream service or authority
+    pub fn upstream_protocol(&self, upstream: &str) -> UpstreamProtocol {
+        self.protocols.get(upstream).copied().unwrap_or_default()
+    }
+
     /// Create a new request forwarder with TLS/mTLS support
     ///
     /// This forwarder can authenticate to HTTPS backends using client certificates.
     /// The TlsClientConfig contains the client certificate, key, and optional CA cert
     /// for verifying the backend server's certificate.
     pub fn with_tls(timeout: Duration, tls_config: TlsClientConfig) -> Result<Self> {
-        // Configure HTTP connector with connection pooling
-        let mut connector = HttpConnector::new();
-        connector.set_connect_timeout(Some(timeout));
-        connector.set_keepalive(Some(Duration::from_secs(30)));
-
-        // Create hyper client with the connector and tokio executor
-        let client = Client::builder(TokioExecutor::new())
-            .build::<_, Full<Bytes>>(connector);
+        let client_tls = tls_config.client_config()?;
+        let pool_config = PoolConfig::default();
+        let timeouts = TimeoutPolicy::uniform(timeout);
+        let clients = Self::build_clients(&timeouts, client_tls.clone(), None, &pool_config);
 
         info!(
             "RequestForwarder initialized with mTLS support (client cert verification: {})",
@@ -67,9 +636,19 @@ impl RequestForwarder {
         );
 
         Ok(Self {
-            client,
-            timeout,
+            clients,
+            pool_config,
+            pools: HashMap::new(),
+            concurrency: HashMap::new(),
+            timeouts,
             tls_config: Some(Arc::new(tls_config)),
+            protocols: HashMap::new(),
+            retry_policy: None,
+            metrics: None,
+            circuit_breakers: None,
+            max_request_body: None,
+            client_tls,
+            resolver: None,
         })
     }
 
@@ -85,34 +664,51 @@ impl RequestForwarder {
 
     /// Forward a request to a target URL and return the response
     ///
-    /// Supports both HTTP and HTTPS URLs. HTTPS requests require TLS configuration
-    /// to be set via `with_tls()` and will return a 502 Bad Gateway error if HTTPS
-    /// is used without TLS configuration.
+    /// Supports both HTTP and HTTPS URLs. HTTPS requests present a client
+    /// certificate only when one was configured via `with_tls()`. Server
+    /// error responses are returned as [`ForwardError::UpstreamStatus`].
     pub async fn forward(
         &self,
         target_url: &str,
         request: Request<hyper::body::Incoming>,
-    ) -> Result<Response<Bytes>> {
-        debug!("Forwarding request to: {}", target_url);
+    ) -> std::result::Result<Response<Bytes>, ForwardError> {
+        // Collect request body
+        let (parts, incoming) = request.into_parts();
+        let body_bytes = Self::collect_body(incoming)
+            .await
+            .map_err(|e| ForwardError::BodyError(e.to_string()))?;
 
-        let uri: Uri = target_url.parse()?;
+        self.forward_bytes(target_url, Request::from_parts(parts, body_bytes)).await
+    }
 
-        // Check if URL is HTTPS and warn if not configured
-        if uri.scheme_str() == Some("https") && !self.has_tls() {
-            warn!("HTTPS URL requested but TLS not configured: {}", target_url);
-            return Ok(Self::error_response(
-                StatusCode::BAD_GATEWAY,
-                "Backend HTTPS not configured - use with_tls() to enable\n",
-            ));
-        }
+    /// Forward a request whose body has already been buffered
+    ///
+    /// Used when the body must be inspected before forwarding. A request
+    /// carrying an [`InflightHandle`] extension reports its backend and state.
+    pub async fn forward_bytes(
+        &self,
+        target_url: &str,
+        request: Request<Bytes>,
+    ) -> std::result::Result<Response<Bytes>, ForwardError> {
+        debug!("Forwarding request to: {}", target_url);
+
+        let uri: Uri = target_url
+            .parse()
+            .map_err(|e| ForwardError::InvalidTarget(format!("{}: {}", target_url, e)))?;
 
         if uri.scheme_str() == Some("https") {
             debug!("Using TLS/mTLS for HTTPS request");
         }
 
-        // Collect request body
-        let (mut parts, incoming) = request.into_parts();
-        let body_bytes = Self::collect_body(incoming).await?;
+        let (mut parts, body_bytes) = request.into_parts();
+
+        if let Some(limit) = self.max_request_body.filter(|limit| body_bytes.len() > *limit) {
+            debug!("Request body of {} bytes exceeds {} byte limit", body_bytes.len(), limit);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_request_body_too_large();
+            }
+            return Err(ForwardError::RequestTooLarge(limit));
+        }
 
         debug!(
             "Request details - method: {}, headers: {}",
@@ -132,6 +728,21 @@ impl RequestForwarder {
             removed_count
         );
 
+        // Per-upstream settings are keyed by service, or the target's authority
+        let upstream_key = match parts.extensions.get::<UpstreamService>() {
+            Some(service) => service.0.clone(),
+            None => uri.authority().map(|a| a.to_string()).unwrap_or_default(),
+        };
+        let protocol = self.upstream_protocol(&upstream_key);
+
+        // gRPC over HTTP/2 requires "te: trailers" to reach the backend
+        let te_trailers = protocol == UpstreamProtocol::Http2
+            && parts
+                .headers
+                .get(hyper::header::TE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.eq_ignore_ascii_case("trailers"));
+
         // Remove hop-by-hop headers from the request
         let mut filtered_headers = hyper::header::HeaderMap::new();
         for (k, v) in parts.headers.iter() {
@@ -139,58 +750,490 @@ impl RequestForwarder {
                 filtered_headers.insert(k.clone(), v.clone());
             }
         }
+        if te_trailers {
+            filtered_headers.insert(hyper::header::TE, hyper::header::HeaderValue::from_static("trailers"));
+        }
         parts.headers = filtered_headers;
 
         // Update the URI to the target URL
         parts.uri = uri;
 
-        // Build the forwarded request with the collected body
-        let forwarded_request = Request::from_parts(parts, Full::new(body_bytes.clone()));
+        // The downstream protocol doesn't carry over to the upstream connection
+        parts.version = match (protocol, parts.version) {
+            (UpstreamProtocol::Http2, _) => hyper::Version::HTTP_2,
+            (_, hyper::Version::HTTP_10) => hyper::Version::HTTP_10,
+            _ => hyper::Version::HTTP_11,
+        };
+
+        debug!(
+            "Sending request to backend over {} with {:?} first byte timeout",
+            protocol.as_str(),
+            self.timeouts.first_byte_timeout
+        );
+
+        let upstream = parts.uri.authority().map(|a| a.to_string()).unwrap_or_default();
+        let inflight = parts.extensions.get::<InflightHandle>().cloned();
+        let response_limit = parts.extensions.get::<ResponseLimit>().copied();
+        if let Some(inflight) = &inflight {
+            inflight.set_backend(&upstream);
+        }
+        let pool = self.pools.get(&upstream_key);
+        let client = pool.map_or(&self.clients, |p| &p.clients).get(protocol);
+        let max_retries = self.retry_policy.as_ref().map_or(0, |p| p.max_retries);
+        let idempotent = is_idempotent(&parts.method);
+        let trace_context = parts
+            .headers
+            .get(TRACEPARENT)
+            .and_then(|v| v.to_str().ok())
+            .and_then(TracingMiddleware::parse_traceparent);
+
+        let _concurrency_permit = match self.concurrency.get(&upstream_key) {
+            Some(limiter) => match limiter.acquire().await {
+                Ok(permit) => Some(permit),
+                Err(e) => {
+                    debug!("Rejecting request to {}: {}", upstream, e);
+                    let error = ForwardError::Saturated(upstream.clone());
+                    self.record_error(&upstream, &error);
+                    return Err(error);
+                }
+            },
+            None => None,
+        };
+
+        let mut attempt = 0;
+        let mut previous_span: Option<Span> = None;
+        loop {
+            if let Some(breakers) = &self.circuit_breakers {
+                if !breakers.can_attempt(&upstream).await {
+                    debug!("Circuit open for {}, rejecting request", upstream);
+                    let error = ForwardError::CircuitOpen(upstream.clone());
+                    self.record_error(&upstream, &error);
+                    return Err(error);
+                }
+            }
+
+            // Rebuild the request for each attempt; the buffered body is cheap to clone
+            let mut forwarded_request = Request::new(Full::new(body_bytes.clone()));
+            *forwarded_request.method_mut() = parts.method.clone();
+            *forwarded_request.uri_mut() = parts.uri.clone();
+            *forwarded_request.version_mut() = parts.version;
+            *forwarded_request.headers_mut() = parts.headers.clone();
+
+            let span = info_span!(
+                "upstream_attempt",
+                upstream = %upstream,
+                attempt = attempt + 1,
+                span_id = field::Empty,
+                outcome = field::Empty,
+                status = field::Empty,
+                grpc_status = field::Empty,
+                retry_reason = field::Empty,
+            );
+            if let Some(previous) = &previous_span {
+                span.follows_from(previous);
+            }
+            if let Some((trace_id, _, flags)) = &trace_context {
+                let span_id = TracingMiddleware::generate_span_id();
+                span.record("span_id", span_id.as_str());
+                let traceparent = TracingMiddleware::create_w3c_trace_context(trace_id, &span_id, flags);
+                if let Ok(value) = hyper::header::HeaderValue::from_str(&traceparent) {
+                    forwarded_request.headers_mut().insert(TRACEPARENT, value);
+                }
+            }
+
+            let outcome = inflight::scope(
+                inflight.clone(),
+                self.send_once(client, &upstream, pool, response_limit, forwarded_request).instrument(span.clone()),
+            )
+            .await;
+            span.record("outcome", attempt_outcome(&outcome));
+            let grpc_status = match &outcome {
+                Ok(response) => {
+                    span.record("status", response.status().as_u16());
+                    GrpcStatus::from_response(response.headers(), response.extensions().get())
+                }
+                _ => None,
+            };
+            if let Some(grpc_status) = grpc_status {
+                span.record("grpc_status", grpc_status.name());
+            }
+            if let (Err(ForwardError::Refused { .. } | ForwardError::ConnectTimeout(_)), Some(resolver)) =
+                (&outcome, &self.resolver)
+            {
+                if let Some(host) = parts.uri.host() {
+                    resolver.invalidate(host);
+                }
+            }
+            if let Some(breakers) = &self.circuit_breakers {
+                match &outcome {
+                    Ok(response) if !breakers.is_failure(response.status(), grpc_status) => {
+                        breakers.record_success(&upstream).await
+                    }
+                    _ => breakers.record_failure(&upstream).await,
+                }
+            }
+            let retry_reason = match (&outcome, &self.retry_policy) {
+                (_, None) => None,
+                // Never sent, so safe to retry whatever the method
+                (Err(e), Some(_)) if e.is_connect() => Some("connect_error".to_string()),
+                // May have been processed; only repeat requests that can be repeated
+                (Err(ForwardError::Reset { .. } | ForwardError::BodyError(_)), Some(_)) if idempotent => {
+                    Some("request_error".to_string())
+                }
+                (Err(ForwardError::Timeout(_) | ForwardError::IdleTimeout(_)), Some(policy))
+                    if idempotent && policy.should_retry(504) =>
+                {
+                    Some("timeout".to_string())
+                }
+                (Ok(response), Some(policy)) if policy.should_retry(response.status().as_u16()) => {
+                    Some(response.status().as_u16().to_string())
+                }
+                (Ok(_), Some(policy)) if grpc_status.is_some_and(|s| policy.should_retry_grpc(s)) => {
+                    grpc_status.map(|s| format!("grpc_{}", s.name().to_ascii_lowercase()))
+                }
+                _ => None,
+            };
+
+            match (retry_reason, &self.retry_policy) {
+                (Some(reason), Some(policy)) if attempt < max_retries => {
+                    span.record("retry_reason", reason.as_str());
+                    previous_span = Some(span);
+                    let backoff = policy.backoff_duration(attempt);
+                    attempt += 1;
+                    debug!(
+                        "Retrying request to {} ({}), attempt {}/{} after {:?}",
+                        upstream, reason, attempt, max_retries, backoff
+                    );
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_retry(&upstream, &reason);
+                    }
+                    tokio::time::sleep(backoff).await;
+                }
+                _ => {
+                    if let (Some(grpc_status), Some(metrics)) = (grpc_status, &self.metrics) {
+                        metrics.record_grpc_response(&upstream, grpc_status.name());
+                    }
+                    let mut response = match outcome {
+                        Ok(response) => response,
+                        Err(error) => {
+                            self.record_error(&upstream, &error);
+                            return Err(error);
+                        }
+                    };
+                    if let Some(timing) = response.extensions_mut().get_mut::<UpstreamTiming>() {
+                        timing.attempts = attempt + 1;
+                    }
+                    if response.status().is_server_error() {
+                        let error = ForwardError::UpstreamStatus {
+                            upstream: upstream.clone(),
+                            response: Box::new(response),
+                        };
+                        self.record_error(&upstream, &error);
+                        return Err(error);
+                    }
+                    return Ok(response);
+                }
+            }
+        }
+    }
 
-        debug!("Sending request to backend with {}s timeout", self.timeout.as_secs());
+    /// Send one attempt of a request with timeout protection
+    ///
+    /// Responses carry the attempt's [`UpstreamTiming`] as an extension,
+    /// along with any [`UpstreamTrailers`]. Waiting for a slot in a limited
+    /// pool counts against the connect timeout, and everything from the
+    /// start of the attempt against the first byte and total timeouts.
+    async fn send_once(
+        &self,
+        client: &UpstreamClient,
+        upstream: &str,
+        pool: Option<&UpstreamPool>,
+        response_limit: Option<ResponseLimit>,
+        request: Request<Full<Bytes>>,
+    ) -> std::result::Result<Response<Bytes>, ForwardError> {
+        let started = std::time::Instant::now();
+        let deadline = self.timeouts.total_timeout.map(|total| Instant::now() + total);
+        inflight::report(InflightState::Connecting);
+        let limit = pool.and_then(|p| p.slots.as_ref().zip(p.config.max_connections));
+        let _slot = match limit {
+            Some((slots, max)) => match self.acquire_slot(upstream, slots, max).await {
+                Some(slot) => Some(slot),
+                None => {
+                    warn!("No free connection to {} after {:?}", upstream, self.timeouts.connect_timeout);
+                    return Err(ForwardError::ConnectTimeout(upstream.to_string()));
+                }
+            },
+            None => None,
+        };
 
-        // Send the request with timeout protection
-        match tokio_timeout(self.timeout, self.client.request(forwarded_request)).await {
+        // The connector reports Connecting while it opens a new connection
+        inflight::report(InflightState::Waiting);
+        let first_byte = Instant::now() + self.timeouts.first_byte_timeout.saturating_sub(started.elapsed());
+        let headers_by = deadline.map_or(first_byte, |deadline| deadline.min(first_byte));
+        let (result, connect) = measure_connect(timeout_at(headers_by, client.request(request))).await;
+        if let (Some(_), Some(metrics)) = (connect, &self.metrics) {
+            metrics.record_upstream_connection(upstream);
+        }
+        match result {
             Ok(Ok(response)) => {
-                debug!("Backend responded with status: {}", response.status());
+                let ttfb = started.elapsed();
+                debug!("Backend responded with status: {} after {:?}", response.status(), ttfb);
+                inflight::report(InflightState::Streaming);
 
                 // Collect response body
-                let (response_parts, body) = response.into_parts();
-                let response_bytes = Self::collect_body(body).await?;
+                let (mut response_parts, body) = response.into_parts();
+                let (response_bytes, trailers) =
+                    self.read_body(upstream, &mut response_parts, body, response_limit, deadline).await?;
 
                 debug!("Response body size: {} bytes", response_bytes.len());
 
+                response_parts.extensions.insert(UpstreamTiming {
+                    connect,
+                    ttfb,
+                    total: started.elapsed(),
+                    attempts: 1,
+                });
+                if let Some(trailers) = trailers {
+                    response_parts.extensions.insert(UpstreamTrailers(trailers));
+                }
                 Ok(Response::from_parts(response_parts, response_bytes))
             }
             Ok(Err(e)) => {
                 warn!("Backend request error: {}", e);
-                Ok(Self::error_response(
-                    StatusCode::BAD_GATEWAY,
-                    "Error communicating with backend service\n",
-                ))
+                Err(ForwardError::from_client_error(upstream, &e))
+            }
+            Err(_) => {
+                warn!("Backend request timeout after {:?}", started.elapsed());
+                Err(ForwardError::Timeout(upstream.to_string()))
+            }
+        }
+    }
+
+    /// Read a response body, holding it to `limit`
+    ///
+    /// A body over the limit fails the attempt, or with the truncate action
+    /// is cut at the limit and marked with [`RESPONSE_TRUNCATED`]; the rest
+    /// is never read. Truncated bodies lose their trailers. Each frame must
+    /// arrive within the idle timeout, and the whole body before `deadline`.
+    async fn read_body(
+        &self,
+        upstream: &str,
+        parts: &mut hyper::http::response::Parts,
+        mut body: hyper::body::Incoming,
+        limit: Option<ResponseLimit>,
+        deadline: Option<Instant>,
+    ) -> std::result::Result<(Bytes, Option<hyper::HeaderMap>), ForwardError> {
+        let body_error = |e: hyper::Error| {
+            warn!("Backend response body error: {}", e);
+            ForwardError::BodyError(format!("response from {}: {}", upstream, e))
+        };
+        let too_large = |limit: ResponseLimit| {
+            warn!("Response from {} exceeds the {} byte limit, aborting", upstream, limit.max_bytes);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_response_body_too_large("abort");
+            }
+            ForwardError::ResponseTooLarge { upstream: upstream.to_string(), limit: limit.max_bytes }
+        };
+
+        // Refuse bodies declared too large without reading them
+        if let Some(limit) = limit.filter(|limit| limit.action == ResponseLimitAction::Abort) {
+            let declared = parts
+                .headers
+                .get(hyper::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if declared.is_some_and(|length| length > limit.max_bytes as u64) {
+                return Err(too_large(limit));
+            }
+        }
+
+        let mut data = Vec::new();
+        let mut trailers = None;
+        while let Some(frame) = self.next_frame(upstream, &mut body, deadline).await? {
+            let frame = match frame.map_err(body_error)?.into_data() {
+                Ok(chunk) => chunk,
+                Err(frame) => {
+                    trailers = frame.into_trailers().ok().or(trailers);
+                    continue;
+                }
+            };
+            let Some(limit) = limit.filter(|limit| data.len() + frame.len() > limit.max_bytes) else {
+                data.extend_from_slice(&frame);
+                continue;
+            };
+            if limit.action == ResponseLimitAction::Abort {
+                return Err(too_large(limit));
+            }
+            warn!("Response from {} exceeds the {} byte limit, truncating", upstream, limit.max_bytes);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_response_body_too_large("truncate");
+            }
+            data.extend_from_slice(&frame[..limit.max_bytes - data.len()]);
+            parts.headers.remove(hyper::header::CONTENT_LENGTH);
+            parts.headers.insert(RESPONSE_TRUNCATED, hyper::header::HeaderValue::from(limit.max_bytes));
+            return Ok((Bytes::from(data), None));
+        }
+        Ok((Bytes::from(data), trailers))
+    }
+
+    /// Wait for the next frame of a response body, within the idle timeout
+    /// and before `deadline`
+    async fn next_frame(
+        &self,
+        upstream: &str,
+        body: &mut hyper::body::Incoming,
+        deadline: Option<Instant>,
+    ) -> std::result::Result<Option<std::result::Result<hyper::body::Frame<Bytes>, hyper::Error>>, ForwardError> {
+        let idle = self.timeouts.idle_timeout.map(|idle| Instant::now() + idle);
+        let wait_until = match (idle, deadline) {
+            (Some(idle), Some(deadline)) => Some(idle.min(deadline)),
+            (idle, deadline) => idle.or(deadline),
+        };
+        let Some(wait_until) = wait_until else {
+            return Ok(body.frame().await);
+        };
+        match timeout_at(wait_until, body.frame()).await {
+            Ok(frame) => Ok(frame),
+            Err(_) if Some(wait_until) == deadline => {
+                warn!("Response from {} did not complete within {:?}", upstream, self.timeouts.total_timeout);
+                Err(ForwardError::Timeout(upstream.to_string()))
             }
             Err(_) => {
-                warn!("Backend request timeout after {}s", self.timeout.as_secs());
-                Ok(Self::error_response(
-                    StatusCode::GATEWAY_TIMEOUT,
-                    "Backend service request timeout\n",
-                ))
+                warn!("Response body from {} stalled for {:?}", upstream, self.timeouts.idle_timeout);
+                Err(ForwardError::IdleTimeout(upstream.to_string()))
             }
         }
     }
 
+    /// Count a request that failed for good
+    fn record_error(&self, upstream: &str, error: &ForwardError) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_upstream_error(upstream, error.kind());
+        }
+    }
+
+    /// Take a slot in a pool with a connection limit
+    ///
+    /// Returns None if no slot freed up within the timeout.
+    async fn acquire_slot(&self, upstream: &str, slots: &Arc<Semaphore>, max: usize) -> Option<PoolSlot> {
+        let permit = match slots.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                debug!("Waiting for a free connection to {}", upstream);
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_upstream_pool_wait(upstream);
+                }
+                tokio_timeout(self.timeouts.connect_timeout, slots.clone().acquire_owned()).await.ok()?.ok()?
+            }
+        };
+        let slot = PoolSlot {
+            _permit: permit,
+            slots: slots.clone(),
+            max,
+            upstream: upstream.to_string(),
+            metrics: self.metrics.clone(),
+        };
+        slot.report(false);
+        Some(slot)
+    }
+
+    /// Build the URI a request is forwarded to
+    ///
+    /// Joins the backend's scheme, authority, and any base path from
+    /// `target_url` with the request's path, rewritten if a rewrite is
+    /// given, and its query string.
+    pub fn target_uri(target_url: &str, request_uri: &Uri, rewrite: Option<&PathRewrite>) -> Result<Uri> {
+        let target: Uri = target_url.parse()?;
+        let path = request_uri.path();
+        let path = match rewrite {
+            Some(rewrite) => rewrite.apply(path),
+            None => path.into(),
+        };
+        let base = target.path().trim_end_matches('/');
+
+        let mut path_and_query = format!("{}{}", base, path);
+        if let Some(query) = request_uri.query() {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+        if rewrite.is_some() {
+            debug!("Rewrote path {} to {}", request_uri.path(), path_and_query);
+        }
+
+        let mut builder = Uri::builder().path_and_query(path_and_query);
+        if let Some(scheme) = target.scheme() {
+            builder = builder.scheme(scheme.clone());
+        }
+        if let Some(authority) = target.authority() {
+            builder = builder.authority(authority.clone());
+        }
+        Ok(builder.build()?)
+    }
+
     /// Collect the entire request body into Bytes
-    pub async fn collect_body(body: hyper::body::Incoming) -> Result<Bytes> {
+    pub async fn collect_body<B>(body: B) -> Result<Bytes>
+    where
+        B: hyper::body::Body,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
         let collected = body.collect().await?;
         Ok(collected.to_bytes())
     }
 
+    /// Buffer a request body, enforcing the configured size limit
+    ///
+    /// A Content-Length over the limit is rejected before anything is
+    /// read; otherwise reading stops as soon as the limit is passed.
+    pub async fn collect_request_body<B>(
+        &self,
+        headers: &hyper::header::HeaderMap,
+        body: B,
+    ) -> std::result::Result<Bytes, RequestBodyError>
+    where
+        B: hyper::body::Body,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let Some(limit) = self.max_request_body else {
+            return Self::collect_body(body).await.map_err(|e| match e.downcast_ref::<BodyHookError>() {
+                Some(rejected @ BodyHookError::Rejected { .. }) => RequestBodyError::Rejected(rejected.to_string()),
+                _ => RequestBodyError::Read(e.to_string()),
+            });
+        };
+
+        let declared = headers
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        let result = match declared {
+            Some(length) if length > limit as u64 => Err(RequestBodyError::TooLarge(limit)),
+            _ => match Limited::new(body, limit).collect().await {
+                Ok(collected) => Ok(collected.to_bytes()),
+                Err(e) if e.is::<LengthLimitError>() => Err(RequestBodyError::TooLarge(limit)),
+                Err(e) => match e.downcast_ref::<BodyHookError>() {
+                    Some(rejected @ BodyHookError::Rejected { .. }) => Err(RequestBodyError::Rejected(rejected.to_string())),
+                    _ => Err(RequestBodyError::Read(e.to_string())),
+                },
+            },
+        };
+        if let (Err(RequestBodyError::TooLarge(_)), Some(metrics)) = (&result, &self.metrics) {
+            metrics.record_request_body_too_large();
+        }
+        result
+    }
+
+    /// 413 response for request bodies over the limit
+    pub fn payload_too_large_response() -> Response<Bytes> {
+        Self::error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large\n")
+    }
+
     /// Create an error response
     fn error_response(status: StatusCode, message: &str) -> Response<Bytes> {
-        Response::builder()
+        let mut response = Response::builder()
             .status(status)
             .body(Bytes::from(format!("{}\n", message)))
-            .unwrap()
+            .unwrap();
+        response.extensions_mut().insert(ProblemDetails::new(status, message));
+        response
     }
 
     /// Check if header is hop-by-hop (should not be forwarded)
@@ -216,7 +1259,7 @@ mod tests {
     #[test]
     fn test_forwarder_creation() {
         let forwarder = RequestForwarder::new(Duration::from_secs(30));
-        assert_eq!(forwarder.timeout, Duration::from_secs(30));
+        assert_eq!(forwarder.timeouts.first_byte_timeout, Duration::from_secs(30));
     }
 
     #[test]
@@ -224,6 +1267,7 @@ mod tests {
         let forwarder = RequestForwarder::new(Duration::from_secs(30));
         assert!(!forwarder.has_tls());
         assert_eq!(forwarder.tls_config(), None);
+        assert!(!forwarder.client_tls.client_auth_cert_resolver.has_certs());
     }
 
     #[test]
@@ -240,6 +1284,17 @@ mod tests {
         assert!(forwarder.tls_config().is_some());
     }
 
+    #[test]
+    fn test_target_uri_keeps_path_and_query() {
+        let request_uri: Uri = "/api/users?page=2".parse().unwrap();
+        let target = RequestForwarder::target_uri("http://backend:8080", &request_uri, None).unwrap();
+        assert_eq!(target, "http://backend:8080/api/users?page=2");
+
+        let rewrite = PathRewrite::strip_prefix("/api");
+        let target = RequestForwarder::target_uri("http://backend:8080/v1/", &request_uri, Some(&rewrite)).unwrap();
+        assert_eq!(target, "http://backend:8080/v1/users?page=2");
+    }
+
     #[test]
     fn test_forwarder_tls_config_access() {
         let tls_config = TlsClientConfig::new(
@@ -255,6 +1310,518 @@ mod tests {
         assert!(!config.verify_server_cert);
     }
 
+    #[tokio::test]
+    async fn test_retries_retryable_status() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Fails the first two requests with 503
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |_req: Request<hyper::body::Incoming>| {
+                        let status = if seen.fetch_add(1, Ordering::SeqCst) < 2 {
+                            StatusCode::SERVICE_UNAVAILABLE
+                        } else {
+                            StatusCode::OK
+                        };
+                        async move {
+                            let mut response = Response::new(Full::new(Bytes::new()));
+                            *response.status_mut() = status;
+                            Ok::<_, hyper::Error>(response)
+                        }
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_retry_policy(policy.clone())
+            .with_metrics(metrics.clone());
+        let target = format!("http://{}/", addr);
+
+        let response = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        let timing = response.extensions().get::<UpstreamTiming>().unwrap();
+        assert_eq!(timing.attempts, 3);
+        assert!(timing.ttfb <= timing.total);
+        assert_eq!(
+            metrics.upstream_retries_total.with_label_values(&[&addr.to_string(), "503"]).get(),
+            2.0
+        );
+
+        // Connection errors are retried until the budget runs out
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let error = forwarder
+            .forward_bytes(&format!("http://{}/", closed), Request::new(Bytes::new()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::Refused { .. }));
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            metrics.upstream_retries_total.with_label_values(&[&closed.to_string(), "connect_error"]).get(),
+            3.0
+        );
+        assert_eq!(
+            metrics.upstream_errors_total.with_label_values(&[&closed.to_string(), "refused"]).get(),
+            1.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mid_request_failures_retried_only_when_idempotent() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::AsyncReadExt;
+
+        // Reads each request, then drops the connection without answering
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let seen = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                seen.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+            }
+        });
+
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_retry_policy(policy)
+            .with_metrics(metrics.clone());
+        let target = format!("http://{}/", addr);
+
+        let error = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap_err();
+        assert!(matches!(error, ForwardError::Reset { .. }));
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            metrics.upstream_retries_total.with_label_values(&[&addr.to_string(), "request_error"]).get(),
+            2.0
+        );
+
+        // The upstream may have acted on the POST, so it isn't repeated
+        accepted.store(0, Ordering::SeqCst);
+        let post = Request::post("/").body(Bytes::from("order")).unwrap();
+        let error = forwarder.forward_bytes(&target, post).await.unwrap_err();
+        assert!(matches!(error, ForwardError::Reset { .. }));
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        // A refused connection never reached the upstream, so even a POST is retried
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let post = Request::post("/").body(Bytes::from("order")).unwrap();
+        forwarder.forward_bytes(&format!("http://{}/", closed), post).await.unwrap_err();
+        assert_eq!(
+            metrics.upstream_retries_total.with_label_values(&[&closed.to_string(), "connect_error"]).get(),
+            2.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_attempts_get_their_own_trace_parent() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use std::sync::Mutex;
+
+        // Records each traceparent and fails the first attempt
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let parents = Arc::new(Mutex::new(Vec::new()));
+        let seen = parents.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let mut seen = seen.lock().unwrap();
+                        seen.push(req.headers()[TRACEPARENT].to_str().unwrap().to_string());
+                        let status = if seen.len() == 1 { StatusCode::BAD_GATEWAY } else { StatusCode::OK };
+                        async move {
+                            let mut response = Response::new(Full::new(Bytes::new()));
+                            *response.status_mut() = status;
+                            Ok::<_, hyper::Error>(response)
+                        }
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let forwarder = RequestForwarder::new(Duration::from_secs(5)).with_retry_policy(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        });
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let mut request = Request::new(Bytes::new());
+        request.headers_mut().insert(
+            TRACEPARENT,
+            format!("00-{}-00f067aa0ba902b7-01", trace_id).parse().unwrap(),
+        );
+
+        let response = forwarder.forward_bytes(&format!("http://{}/", addr), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let parents = parents.lock().unwrap();
+        assert_eq!(parents.len(), 2);
+        let parsed: Vec<_> = parents.iter().map(|p| TracingMiddleware::parse_traceparent(p).unwrap()).collect();
+        assert!(parsed.iter().all(|(t, _, flags)| t == trace_id && flags == "01"));
+        assert_ne!(parsed[0].1, parsed[1].1);
+        assert_ne!(parsed[0].1, "00f067aa0ba902b7");
+    }
+
+    #[tokio::test]
+    async fn test_hostname_backend_through_dns_cache() {
+        use crate::dns::{DnsCacheConfig, DnsLookup};
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use std::net::IpAddr;
+
+        struct Loopback;
+
+        #[async_trait::async_trait]
+        impl DnsLookup for Loopback {
+            async fn lookup(&self, _host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+                Ok((vec!["127.0.0.1".parse().unwrap()], Duration::from_secs(60)))
+            }
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("ok"))))
+            });
+            let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+        });
+
+        let resolver = CachingResolver::new(Loopback, DnsCacheConfig::default());
+        let forwarder = RequestForwarder::new(Duration::from_secs(5)).with_resolver(resolver.clone());
+
+        let target = format!("http://backend.internal:{}/", port);
+        let response = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(resolver.len(), 1);
+
+        // A refused connection evicts the host so it is resolved again
+        let error = forwarder
+            .forward_bytes("http://gone.internal:1/", Request::new(Bytes::new()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(resolver.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_body_limit() {
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_max_request_body(8)
+            .with_metrics(metrics.clone());
+
+        let mut headers = hyper::header::HeaderMap::new();
+        let body = forwarder.collect_request_body(&headers, Full::new(Bytes::from("12345678"))).await.unwrap();
+        assert_eq!(body, Bytes::from("12345678"));
+
+        // Streamed past the limit
+        let error = forwarder
+            .collect_request_body(&headers, Full::new(Bytes::from("123456789")))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RequestBodyError::TooLarge(8)));
+
+        // Declared too large up front
+        headers.insert(hyper::header::CONTENT_LENGTH, "1000".parse().unwrap());
+        let error = forwarder.collect_request_body(&headers, Full::new(Bytes::new())).await.unwrap_err();
+        assert!(matches!(error, RequestBodyError::TooLarge(8)));
+
+        // Already-buffered bodies are checked before forwarding
+        let error = forwarder
+            .forward_bytes("http://127.0.0.1:1/", Request::new(Bytes::from("123456789")))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::RequestTooLarge(8)));
+        assert_eq!(error.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(metrics.request_body_too_large_total.get(), 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_response_body_limit() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        // Answers with a 16 byte body
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("0123456789abcdef"))))
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let forwarder = RequestForwarder::new(Duration::from_secs(5)).with_metrics(metrics.clone());
+        let target = format!("http://{}/", addr);
+        let limited = |max_bytes, action| {
+            let mut request = Request::new(Bytes::new());
+            request.extensions_mut().insert(ResponseLimit { max_bytes, action });
+            request
+        };
+
+        // Within the limit
+        let response = forwarder.forward_bytes(&target, limited(16, ResponseLimitAction::Abort)).await.unwrap();
+        assert_eq!(response.body(), &Bytes::from("0123456789abcdef"));
+        assert!(response.headers().get(RESPONSE_TRUNCATED).is_none());
+
+        let error = forwarder
+            .forward_bytes(&target, limited(8, ResponseLimitAction::Abort))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::ResponseTooLarge { limit: 8, .. }));
+        assert_eq!(error.kind(), "response_too_large");
+        assert_eq!(error.into_response().status(), StatusCode::BAD_GATEWAY);
+
+        let response = forwarder.forward_bytes(&target, limited(8, ResponseLimitAction::Truncate)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &Bytes::from("01234567"));
+        assert_eq!(response.headers()[RESPONSE_TRUNCATED], "8");
+        assert!(response.headers().get(hyper::header::CONTENT_LENGTH).is_none());
+
+        assert_eq!(metrics.response_body_too_large_total.with_label_values(&["abort"]).get(), 1.0);
+        assert_eq!(metrics.response_body_too_large_total.with_label_values(&["truncate"]).get(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_request_body_rejected_by_middleware() {
+        use crate::middleware::{Middleware, MiddlewareChain, MiddlewareContext};
+
+        struct RejectAll;
+
+        #[async_trait::async_trait]
+        impl Middleware for RejectAll {
+            fn inspects_body(&self) -> bool {
+                true
+            }
+
+            fn on_request_body(&self, _context: &MiddlewareContext, _chunk: Bytes) -> Result<Bytes> {
+                anyhow::bail!("no bodies allowed")
+            }
+        }
+
+        let chain = Arc::new(MiddlewareChain::new().add(RejectAll));
+        let headers = hyper::header::HeaderMap::new();
+        for forwarder in [RequestForwarder::new(Duration::from_secs(5)), RequestForwarder::new(Duration::from_secs(5)).with_max_request_body(8)] {
+            let body = chain.clone().inspect_request_body(MiddlewareContext::default(), Full::new(Bytes::from("data")));
+            let error = forwarder.collect_request_body(&headers, body).await.unwrap_err();
+            assert!(matches!(error, RequestBodyError::Rejected(_)), "{:?}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_fails_fast() {
+        use crate::policy::CircuitBreakerConfig;
+
+        let breakers = Arc::new(CircuitBreakerRegistry::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            success_threshold: 1,
+            timeout: Duration::from_secs(60),
+            ..Default::default()
+        }));
+        let forwarder = RequestForwarder::new(Duration::from_secs(5)).with_circuit_breakers(breakers.clone());
+
+        // Nothing listens here, so each attempt fails to connect
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let target = format!("http://{}/", closed);
+        for _ in 0..2 {
+            let error = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap_err();
+            assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        }
+
+        let error = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap_err();
+        assert!(matches!(error, ForwardError::CircuitOpen(_)));
+        assert_eq!(error.into_response().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(breakers.state(&closed.to_string()), crate::policy::CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_mtls_upstream_round_trip() {
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use hyper_util::server::conn::auto;
+        use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+        // CA issuing both the server and the client certificate
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let issue = |usage: ExtendedKeyUsagePurpose| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        };
+        let (server_cert, server_key) = issue(ExtendedKeyUsagePurpose::ServerAuth);
+        let (client_cert, client_key) = issue(ExtendedKeyUsagePurpose::ClientAuth);
+
+        let server_tls = crate::tls::TlsServerConfig::from_pem_with_client_auth(
+            server_cert.as_bytes(),
+            server_key.as_bytes(),
+            Some(ca.pem().as_bytes()),
+            true,
+            None,
+            None,
+        )
+        .unwrap()
+        .with_http2();
+        let acceptor = tokio_rustls::TlsAcceptor::from(server_tls.config.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(stream).await else { return };
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let version = format!("{:?}", req.version());
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(version))))
+                    });
+                    let _ = auto::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let target = format!("https://localhost:{}/", port);
+        let tls_config = TlsClientConfig::new(
+            client_cert.into_bytes(),
+            client_key.into_bytes(),
+            Some(ca.pem().into_bytes()),
+            true,
+        );
+        let forwarder = RequestForwarder::with_tls(Duration::from_secs(5), tls_config).unwrap();
+        let response = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &Bytes::from("HTTP/2.0"));
+
+        // Without a client certificate the handshake is rejected
+        let anonymous = TlsClientConfig::new(Vec::new(), Vec::new(), Some(ca.pem().into_bytes()), true);
+        let forwarder = RequestForwarder::with_tls(Duration::from_secs(5), anonymous).unwrap();
+        let error = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_forward_error_kinds() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use tokio::io::AsyncWriteExt;
+
+        // Answers everything with a plaintext 500
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                        let mut response = Response::new(Full::new(Bytes::from("boom")));
+                        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                        Ok::<_, hyper::Error>(response)
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(&mut stream), service).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        // Accepts connections but never answers
+        let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_addr = silent.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            loop {
+                held.push(silent.accept().await.unwrap());
+            }
+        });
+
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let tls = TlsClientConfig::new(Vec::new(), Vec::new(), None, true);
+        let forwarder = RequestForwarder::with_tls(Duration::from_millis(200), tls)
+            .unwrap()
+            .with_metrics(metrics.clone());
+
+        // Server errors are returned with the upstream's response intact
+        let error = forwarder
+            .forward_bytes(&format!("http://{}/", addr), Request::new(Bytes::new()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "upstream_status");
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.body(), &Bytes::from("boom"));
+
+        // A plaintext server can't complete a TLS handshake
+        let error = forwarder
+            .forward_bytes(&format!("https://{}/", addr), Request::new(Bytes::new()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::TlsFailure { .. }), "{:?}", error);
+        assert!(error.is_connect());
+
+        let error = forwarder
+            .forward_bytes(&format!("http://{}/", silent_addr), Request::new(Bytes::new()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::Timeout(_)));
+        assert_eq!(error.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            metrics.upstream_errors_total.with_label_values(&[&addr.to_string(), "tls_failure"]).get(),
+            1.0
+        );
+
+        let error = forwarder.forward_bytes("http://bad host/", Request::new(Bytes::new())).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
     #[test]
     fn test_hop_by_hop_headers() {
         assert!(RequestForwarder::is_hop_by_hop_header("connection"));
@@ -264,13 +1831,412 @@ mod tests {
         assert!(!RequestForwarder::is_hop_by_hop_header("authorization"));
     }
 
+    #[tokio::test]
+    async fn test_body_timeouts() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Sends headers at once, then a body chunk every 150ms
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n1\r\na\r\n")
+                        .await;
+                    for chunk in [&b"1\r\nb\r\n"[..], b"1\r\nc\r\n0\r\n\r\n"] {
+                        tokio::time::sleep(Duration::from_millis(150)).await;
+                        let _ = stream.write_all(chunk).await;
+                    }
+                });
+            }
+        });
+        let target = format!("http://{}/", addr);
+        let forward = |policy: TimeoutPolicy| {
+            let forwarder = RequestForwarder::new(Duration::from_secs(5)).with_timeout_policy(policy);
+            let target = target.clone();
+            async move { forwarder.forward_bytes(&target, Request::new(Bytes::new())).await }
+        };
+        let policy = TimeoutPolicy {
+            connect_timeout: Duration::from_secs(1),
+            first_byte_timeout: Duration::from_millis(100),
+            idle_timeout: Some(Duration::from_millis(300)),
+            total_timeout: None,
+        };
+
+        // A body that keeps streaming outlasts the first byte timeout
+        let response = forward(policy.clone()).await.unwrap();
+        assert_eq!(response.body(), &Bytes::from("abc"));
+
+        let error = forward(TimeoutPolicy { idle_timeout: Some(Duration::from_millis(50)), ..policy.clone() })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::IdleTimeout(_)), "{:?}", error);
+        assert_eq!(error.kind(), "idle_timeout");
+        assert_eq!(error.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let error = forward(TimeoutPolicy { total_timeout: Some(Duration::from_millis(200)), ..policy })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ForwardError::Timeout(_)), "{:?}", error);
+    }
+
     #[test]
     fn test_forwarder_creation_with_different_timeouts() {
         let forwarder_5s = RequestForwarder::new(Duration::from_secs(5));
-        assert_eq!(forwarder_5s.timeout, Duration::from_secs(5));
+        assert_eq!(forwarder_5s.timeouts.first_byte_timeout, Duration::from_secs(5));
 
         let forwarder_60s = RequestForwarder::new(Duration::from_secs(60));
-        assert_eq!(forwarder_60s.timeout, Duration::from_secs(60));
+        assert_eq!(forwarder_60s.timeouts.first_byte_timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_upstream_protocol_selection() {
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_upstream_protocol("grpc-backend:50051", UpstreamProtocol::Http2)
+            .with_upstream_protocol("legacy:8080", UpstreamProtocol::Http1);
+
+        assert_eq!(forwarder.upstream_protocol("grpc-backend:50051"), UpstreamProtocol::Http2);
+        assert_eq!(forwarder.upstream_protocol("legacy:8080"), UpstreamProtocol::Http1);
+        assert_eq!(forwarder.upstream_protocol("other:80"), UpstreamProtocol::Auto);
+
+        assert_eq!(UpstreamProtocol::from_string("h2c"), Some(UpstreamProtocol::Http2));
+        assert_eq!(UpstreamProtocol::from_string("HTTP1"), Some(UpstreamProtocol::Http1));
+        assert_eq!(UpstreamProtocol::from_string("spdy"), None);
+        assert_eq!(UpstreamProtocol::for_service_protocol("gRPC"), UpstreamProtocol::Http2);
+        assert_eq!(UpstreamProtocol::for_service_protocol("HTTP"), UpstreamProtocol::Auto);
+    }
+
+    #[tokio::test]
+    async fn test_h2c_requests_share_one_connection() {
+        use hyper::server::conn::http2;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let version = format!("{:?}", req.version());
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(version))))
+                    });
+                    let _ = http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let authority = addr.to_string();
+        let forwarder = Arc::new(
+            RequestForwarder::new(Duration::from_secs(5))
+                .with_upstream_protocol(&authority, UpstreamProtocol::Http2),
+        );
+        let target = format!("http://{}/", authority);
+
+        let requests = (0..4).map(|_| {
+            let forwarder = forwarder.clone();
+            let target = target.clone();
+            tokio::spawn(async move {
+                forwarder
+                    .forward_bytes(&target, Request::new(Bytes::new()))
+                    .await
+                    .unwrap()
+            })
+        });
+        for request in requests {
+            let response = request.await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body(), &Bytes::from("HTTP/2.0"));
+        }
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_protocol_keyed_by_service() {
+        use hyper::server::conn::http2;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                let version = format!("{:?}", req.version());
+                Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(version))))
+            });
+            let _ = http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_upstream_protocol("default/grpc", UpstreamProtocol::Http2);
+        let mut request = Request::new(Bytes::new());
+        request.extensions_mut().insert(UpstreamService("default/grpc".to_string()));
+
+        let response = forwarder.forward_bytes(&format!("http://{}/", addr), request).await.unwrap();
+        assert_eq!(response.body(), &Bytes::from("HTTP/2.0"));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_trailers_preserved() {
+        use crate::trailers::{TrailersBody, GRPC_STATUS};
+        use hyper::server::conn::http2;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                let mut trailers = hyper::header::HeaderMap::new();
+                trailers.insert(GRPC_STATUS, "14".parse().unwrap());
+                let body = TrailersBody::new(Full::new(Bytes::from_static(b"\0\0\0\0\0")), Some(trailers));
+                Ok::<_, hyper::Error>(Response::new(body))
+            });
+            let _ = http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let authority = addr.to_string();
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_upstream_protocol(&authority, UpstreamProtocol::Http2)
+            .with_metrics(metrics.clone());
+        let mut request = Request::new(Bytes::new());
+        request.headers_mut().insert(hyper::header::TE, "trailers".parse().unwrap());
+
+        let response = forwarder
+            .forward_bytes(&format!("http://{}/pkg.Service/Call", authority), request)
+            .await
+            .unwrap();
+        let trailers = response.extensions().get::<UpstreamTrailers>().unwrap();
+        assert_eq!(trailers.0[GRPC_STATUS], "14");
+        assert_eq!(
+            metrics.grpc_responses_total.with_label_values(&[&authority, "UNAVAILABLE"]).get(),
+            1.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grpc_status_retries_and_trips_circuit() {
+        use crate::policy::{CircuitBreakerConfig, CircuitState};
+        use crate::trailers::{GrpcStatus, TrailersBody, GRPC_STATUS};
+        use hyper::server::conn::http2;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Answers UNAVAILABLE to the first two calls, then OK
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = calls.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |_req: Request<hyper::body::Incoming>| {
+                let code = if seen.fetch_add(1, Ordering::SeqCst) < 2 { "14" } else { "0" };
+                async move {
+                    let mut trailers = hyper::header::HeaderMap::new();
+                    trailers.insert(GRPC_STATUS, code.parse().unwrap());
+                    Ok::<_, hyper::Error>(Response::new(TrailersBody::new(Full::new(Bytes::new()), Some(trailers))))
+                }
+            });
+            let _ = http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let authority = addr.to_string();
+        let breakers = Arc::new(CircuitBreakerRegistry::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            ..Default::default()
+        }));
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            retryable_grpc_statuses: vec![GrpcStatus::UNAVAILABLE],
+            ..RetryPolicy::default()
+        };
+        let forwarder = RequestForwarder::new(Duration::from_secs(5))
+            .with_upstream_protocol(&authority, UpstreamProtocol::Http2)
+            .with_retry_policy(policy)
+            .with_circuit_breakers(breakers.clone());
+        let target = format!("http://{}/pkg.Service/Call", authority);
+
+        let response = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap();
+        let trailers = response.extensions().get::<UpstreamTrailers>().unwrap();
+        assert_eq!(trailers.0[GRPC_STATUS], "0");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(response.extensions().get::<UpstreamTiming>().unwrap().attempts, 3);
+
+        // Both UNAVAILABLE answers counted against the circuit; the OK reset it
+        assert_eq!(breakers.state(&authority), CircuitState::Closed);
+        assert!(breakers.is_failure(StatusCode::OK, Some(GrpcStatus::RESOURCE_EXHAUSTED)));
+        assert!(!breakers.is_failure(StatusCode::OK, Some(GrpcStatus::OK)));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_pool_connection_limit() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        // Slow backend so concurrent requests overlap
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("ok"))))
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let authority = addr.to_string();
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let forwarder = Arc::new(
+            RequestForwarder::new(Duration::from_secs(5))
+                .with_metrics(metrics.clone())
+                .with_upstream_pool(
+                    &authority,
+                    PoolConfig {
+                        max_connections: Some(1),
+                        ..Default::default()
+                    },
+                ),
+        );
+        let target = format!("http://{}/", authority);
+
+        let requests = (0..3).map(|_| {
+            let forwarder = forwarder.clone();
+            let target = target.clone();
+            tokio::spawn(async move { forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap() })
+        });
+        let requests = requests.collect::<Vec<_>>();
+        for request in requests {
+            assert_eq!(request.await.unwrap().status(), StatusCode::OK);
+        }
+
+        // One request at a time, all over one reused connection
+        assert_eq!(metrics.upstream_pool_waits_total.with_label_values(&[&authority]).get(), 2.0);
+        assert_eq!(metrics.upstream_connections_opened_total.with_label_values(&[&authority]).get(), 1.0);
+        assert_eq!(metrics.upstream_pool_in_use.with_label_values(&[&authority]).get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_concurrency_limit() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("ok"))))
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let authority = addr.to_string();
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let forwarder = Arc::new(
+            RequestForwarder::new(Duration::from_secs(5))
+                .with_metrics(metrics.clone())
+                .with_upstream_concurrency(
+                    &authority,
+                    ConcurrencyConfig {
+                        max_concurrent_requests: 1,
+                        ..Default::default()
+                    },
+                ),
+        );
+        let target = format!("http://{}/", authority);
+
+        let slow = {
+            let forwarder = forwarder.clone();
+            let target = target.clone();
+            tokio::spawn(async move { forwarder.forward_bytes(&target, Request::new(Bytes::new())).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The upstream's only slot is taken, so the next request is shed
+        let error = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap_err();
+        assert!(matches!(error, ForwardError::Saturated(_)));
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[hyper::header::RETRY_AFTER], "1");
+        assert_eq!(metrics.upstream_errors_total.with_label_values(&[&authority, "saturated"]).get(), 1.0);
+
+        // Limits follow the request's VPCService, not the address it's sent to
+        let mut request = Request::new(Bytes::new());
+        request.extensions_mut().insert(UpstreamService("default/other".to_string()));
+        assert_eq!(forwarder.forward_bytes(&target, request).await.unwrap().status(), StatusCode::OK);
+
+        // The slot is released once the request completes
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+        let response = forwarder.forward_bytes(&target, Request::new(Bytes::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_http2_downstream_request_to_http1_upstream() {
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper_util::rt::tokio::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                let version = format!("{:?}", req.version());
+                Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(version))))
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let forwarder = RequestForwarder::new(Duration::from_secs(5));
+        let request = Request::builder()
+            .version(hyper::Version::HTTP_2)
+            .body(Bytes::new())
+            .unwrap();
+        let response = forwarder
+            .forward_bytes(&format!("http://{}/", addr), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &Bytes::from("HTTP/1.1"));
     }
 
     #[test]
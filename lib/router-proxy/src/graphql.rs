@@ -0,0 +1,435 @@
+//! GraphQL request inspection and cost limits
+//!
+//! Parses GraphQL queries on routes with a `graphql` policy and enforces
+//! depth and complexity limits plus an optional operation allow-list
+//! before the request reaches the backend.
+
+use async_graphql_parser::types::{
+    DocumentOperations, ExecutableDocument, OperationType, Selection, SelectionSet,
+};
+use async_graphql_parser::parse_query;
+use router_api::v1alpha1::vpc_route::GraphQLPolicy;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Metrics label for operations without a name
+pub const ANONYMOUS_OPERATION: &str = "anonymous";
+
+/// Metrics label for named operations outside the allow-list
+pub const UNLISTED_OPERATION: &str = "unlisted";
+
+/// GraphQL limits for a route
+#[derive(Clone, Debug)]
+pub struct GraphQLLimits {
+    /// Maximum selection set nesting depth
+    pub max_depth: usize,
+    /// Maximum number of fields selected (fragments expanded)
+    pub max_complexity: usize,
+    /// Operation names that may be executed (empty allows all)
+    pub allowed_operations: Vec<String>,
+}
+
+impl Default for GraphQLLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 15,
+            max_complexity: 1000,
+            allowed_operations: Vec::new(),
+        }
+    }
+}
+
+impl GraphQLLimits {
+    /// Limits of a route's `graphql` policy, with defaults for those it leaves unset
+    pub fn from_route(policy: &GraphQLPolicy) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_depth: policy.max_depth.map_or(defaults.max_depth, |depth| depth as usize),
+            max_complexity: policy.max_complexity.map_or(defaults.max_complexity, |complexity| complexity as usize),
+            allowed_operations: policy.allowed_operations.clone(),
+        }
+    }
+}
+
+/// Reasons a GraphQL request is rejected
+#[derive(Debug, Error, PartialEq)]
+pub enum GraphQLError {
+    #[error("Invalid GraphQL request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Unknown operation: {0}")]
+    UnknownOperation(String),
+
+    #[error("Operation not allowed: {0}")]
+    OperationNotAllowed(String),
+
+    #[error("Query depth {depth} exceeds limit of {limit}")]
+    DepthExceeded { depth: usize, limit: usize },
+
+    #[error("Query complexity {complexity} exceeds limit of {limit}")]
+    ComplexityExceeded { complexity: usize, limit: usize },
+}
+
+impl GraphQLError {
+    /// Short rejection reason used as a metrics label
+    pub fn reason(&self) -> &'static str {
+        match self {
+            GraphQLError::InvalidRequest(_) => "invalid",
+            GraphQLError::UnknownOperation(_) => "unknown_operation",
+            GraphQLError::OperationNotAllowed(_) => "not_allowed",
+            GraphQLError::DepthExceeded { .. } => "depth_exceeded",
+            GraphQLError::ComplexityExceeded { .. } => "complexity_exceeded",
+        }
+    }
+}
+
+/// GraphQL-over-HTTP request body
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQLRequestBody {
+    query: String,
+    #[serde(default)]
+    operation_name: Option<String>,
+}
+
+/// Result of analyzing a GraphQL operation
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphQLAnalysis {
+    /// Operation name, if the operation is named
+    pub operation_name: Option<String>,
+    /// Operation type ("query", "mutation", "subscription")
+    pub operation_type: &'static str,
+    /// Maximum selection set nesting depth
+    pub depth: usize,
+    /// Number of fields selected
+    pub complexity: usize,
+}
+
+/// Enforces a route's GraphQL limits
+pub struct GraphQLGuard {
+    limits: GraphQLLimits,
+    allowed: HashSet<String>,
+}
+
+impl GraphQLGuard {
+    /// Create a new GraphQL guard
+    pub fn new(limits: GraphQLLimits) -> Self {
+        let allowed = limits.allowed_operations.iter().cloned().collect();
+        Self { limits, allowed }
+    }
+
+    /// Get the configured limits
+    pub fn limits(&self) -> &GraphQLLimits {
+        &self.limits
+    }
+
+    /// Metrics label for an operation, bounded by the allow-list
+    pub fn operation_label(&self, operation_name: Option<&str>) -> String {
+        match operation_name {
+            None => ANONYMOUS_OPERATION.to_string(),
+            Some(name) if self.allowed.is_empty() || self.allowed.contains(name) => name.to_string(),
+            Some(_) => UNLISTED_OPERATION.to_string(),
+        }
+    }
+
+    /// Inspect a JSON GraphQL request body and enforce limits
+    pub fn inspect_body(&self, body: &[u8]) -> Result<GraphQLAnalysis, GraphQLError> {
+        let request: GraphQLRequestBody = serde_json::from_slice(body)
+            .map_err(|e| GraphQLError::InvalidRequest(e.to_string()))?;
+        self.inspect(&request.query, request.operation_name.as_deref())
+    }
+
+    /// Inspect a GraphQL GET request's query string and enforce limits
+    pub fn inspect_query_string(&self, query_string: &str) -> Result<GraphQLAnalysis, GraphQLError> {
+        let request: GraphQLRequestBody = serde_urlencoded::from_str(query_string)
+            .map_err(|e| GraphQLError::InvalidRequest(e.to_string()))?;
+        self.inspect(&request.query, request.operation_name.as_deref())
+    }
+
+    /// Inspect a query and enforce limits
+    ///
+    /// Measuring stops once the complexity limit is passed, so the
+    /// complexity reported for a rejected query is a lower bound.
+    pub fn inspect(&self, query: &str, operation_name: Option<&str>) -> Result<GraphQLAnalysis, GraphQLError> {
+        let analysis = Self::analyze_within(query, operation_name, self.limits.max_complexity)?;
+
+        if !self.allowed.is_empty() {
+            let name = analysis.operation_name.as_deref().unwrap_or(ANONYMOUS_OPERATION);
+            if !self.allowed.contains(name) {
+                return Err(GraphQLError::OperationNotAllowed(name.to_string()));
+            }
+        }
+
+        if analysis.depth > self.limits.max_depth {
+            return Err(GraphQLError::DepthExceeded {
+                depth: analysis.depth,
+                limit: self.limits.max_depth,
+            });
+        }
+
+        if analysis.complexity > self.limits.max_complexity {
+            return Err(GraphQLError::ComplexityExceeded {
+                complexity: analysis.complexity,
+                limit: self.limits.max_complexity,
+            });
+        }
+
+        Ok(analysis)
+    }
+
+    /// Parse a query and measure the selected operation
+    pub fn analyze(query: &str, operation_name: Option<&str>) -> Result<GraphQLAnalysis, GraphQLError> {
+        Self::analyze_within(query, operation_name, usize::MAX)
+    }
+
+    /// Parse a query and measure the selected operation, stopping once its
+    /// complexity passes `max_complexity`
+    fn analyze_within(
+        query: &str,
+        operation_name: Option<&str>,
+        max_complexity: usize,
+    ) -> Result<GraphQLAnalysis, GraphQLError> {
+        let document = parse_query(query).map_err(|e| GraphQLError::InvalidRequest(e.to_string()))?;
+
+        let (name, operation) = match (&document.operations, operation_name) {
+            (DocumentOperations::Single(op), _) => (None, op),
+            (DocumentOperations::Multiple(ops), Some(wanted)) => {
+                let (name, op) = ops
+                    .iter()
+                    .find(|(name, _)| name.as_str() == wanted)
+                    .ok_or_else(|| GraphQLError::UnknownOperation(wanted.to_string()))?;
+                (Some(name.to_string()), op)
+            }
+            (DocumentOperations::Multiple(ops), None) if ops.len() == 1 => {
+                let (name, op) = ops.iter().next().unwrap();
+                (Some(name.to_string()), op)
+            }
+            (DocumentOperations::Multiple(_), None) => {
+                return Err(GraphQLError::InvalidRequest(
+                    "operationName is required for documents with multiple operations".to_string(),
+                ));
+            }
+        };
+
+        let operation_type = match operation.node.ty {
+            OperationType::Query => "query",
+            OperationType::Mutation => "mutation",
+            OperationType::Subscription => "subscription",
+        };
+
+        let mut measurer = Measurer {
+            document: &document,
+            fragments: HashMap::new(),
+            visiting: Vec::new(),
+            max_complexity,
+        };
+        let (depth, complexity) = measurer.measure(&operation.node.selection_set.node);
+
+        Ok(GraphQLAnalysis {
+            operation_name: name,
+            operation_type,
+            depth,
+            complexity,
+        })
+    }
+}
+
+/// Measures depth and field count of selection sets, expanding fragments
+///
+/// Each fragment is measured once and its result reused at every spread, so
+/// fragments spreading each other many times can't make the work grow
+/// exponentially. `visiting` holds the fragments on the current path so
+/// cyclic fragment spreads (invalid GraphQL) can't recurse forever.
+struct Measurer<'a> {
+    document: &'a ExecutableDocument,
+    fragments: HashMap<String, (usize, usize)>,
+    visiting: Vec<String>,
+    /// Measuring stops once the complexity passes this
+    max_complexity: usize,
+}
+
+impl Measurer<'_> {
+    fn measure(&mut self, selection_set: &SelectionSet) -> (usize, usize) {
+        let mut depth = 0;
+        let mut complexity: usize = 0;
+
+        for selection in &selection_set.items {
+            let (d, c) = match &selection.node {
+                Selection::Field(field) => {
+                    let (d, c) = self.measure(&field.node.selection_set.node);
+                    (d + 1, c.saturating_add(1))
+                }
+                Selection::InlineFragment(fragment) => self.measure(&fragment.node.selection_set.node),
+                Selection::FragmentSpread(spread) => self.measure_fragment(spread.node.fragment_name.node.as_str()),
+            };
+            depth = depth.max(d);
+            complexity = complexity.saturating_add(c);
+            if complexity > self.max_complexity {
+                break;
+            }
+        }
+
+        (depth, complexity)
+    }
+
+    fn measure_fragment(&mut self, name: &str) -> (usize, usize) {
+        if let Some(measured) = self.fragments.get(name) {
+            return *measured;
+        }
+        let document = self.document;
+        match document.fragments.get(name) {
+            Some(fragment) if !self.visiting.iter().any(|v| v == name) => {
+                self.visiting.push(name.to_string());
+                let measured = self.measure(&fragment.node.selection_set.node);
+                self.visiting.pop();
+                self.fragments.insert(name.to_string(), measured);
+                measured
+            }
+            _ => (0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_depth_and_complexity() {
+        let analysis = GraphQLGuard::analyze(
+            "query GetUser { user(id: 1) { name friends { name } } }",
+            None,
+        )
+        .unwrap();
+        assert_eq!(analysis.operation_name, Some("GetUser".to_string()));
+        assert_eq!(analysis.operation_type, "query");
+        assert_eq!(analysis.depth, 3);
+        assert_eq!(analysis.complexity, 4);
+    }
+
+    #[test]
+    fn test_analyze_expands_fragments() {
+        let query = r#"
+            query { user { ...UserFields ... on User { email } } }
+            fragment UserFields on User { name address { city } }
+        "#;
+        let analysis = GraphQLGuard::analyze(query, None).unwrap();
+        assert_eq!(analysis.operation_name, None);
+        assert_eq!(analysis.depth, 3);
+        assert_eq!(analysis.complexity, 5);
+    }
+
+    #[test]
+    fn test_analyze_selects_named_operation() {
+        let query = "query A { a } mutation B { b { c } }";
+        let analysis = GraphQLGuard::analyze(query, Some("B")).unwrap();
+        assert_eq!(analysis.operation_type, "mutation");
+        assert_eq!(analysis.depth, 2);
+
+        assert_eq!(
+            GraphQLGuard::analyze(query, Some("C")),
+            Err(GraphQLError::UnknownOperation("C".to_string()))
+        );
+        assert!(GraphQLGuard::analyze(query, None).is_err());
+    }
+
+    #[test]
+    fn test_inspect_enforces_limits() {
+        let guard = GraphQLGuard::new(GraphQLLimits {
+            max_depth: 2,
+            max_complexity: 3,
+            ..Default::default()
+        });
+
+        assert!(guard.inspect("{ a { b } }", None).is_ok());
+        assert_eq!(
+            guard.inspect("{ a { b { c } } }", None),
+            Err(GraphQLError::DepthExceeded { depth: 3, limit: 2 })
+        );
+        assert_eq!(
+            guard.inspect("{ a b c d }", None),
+            Err(GraphQLError::ComplexityExceeded { complexity: 4, limit: 3 })
+        );
+    }
+
+    #[test]
+    fn test_fragment_chain_is_measured_once() {
+        // F0 spreads F1 twice, F1 spreads F2 twice, ...: 2^40 fields expanded
+        let mut query = "query { ...F0 }\n".to_string();
+        for i in 0..40 {
+            query.push_str(&format!("fragment F{} on T {{ a {{ ...F{} }} b {{ ...F{} }} }}\n", i, i + 1, i + 1));
+        }
+        query.push_str("fragment F40 on T { leaf }\n");
+
+        let started = std::time::Instant::now();
+        let analysis = GraphQLGuard::analyze(&query, None).unwrap();
+        assert_eq!(analysis.depth, 41);
+        assert_eq!(analysis.complexity, 3 * (1usize << 40) - 2);
+
+        let guard = GraphQLGuard::new(GraphQLLimits { max_depth: 100, ..Default::default() });
+        assert!(matches!(
+            guard.inspect(&query, None),
+            Err(GraphQLError::ComplexityExceeded { limit: 1000, .. })
+        ));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_inspect_allow_list() {
+        let guard = GraphQLGuard::new(GraphQLLimits {
+            allowed_operations: vec!["GetUser".to_string()],
+            ..Default::default()
+        });
+
+        assert!(guard.inspect("query GetUser { user { name } }", None).is_ok());
+        assert_eq!(
+            guard.inspect("query DumpAll { users { name } }", None),
+            Err(GraphQLError::OperationNotAllowed("DumpAll".to_string()))
+        );
+        assert!(guard.inspect("{ users { name } }", None).is_err());
+
+        assert_eq!(guard.operation_label(Some("GetUser")), "GetUser");
+        assert_eq!(guard.operation_label(Some("DumpAll")), UNLISTED_OPERATION);
+        assert_eq!(guard.operation_label(None), ANONYMOUS_OPERATION);
+    }
+
+    #[test]
+    fn test_inspect_body() {
+        let guard = GraphQLGuard::new(GraphQLLimits::default());
+        let body = br#"{"query":"query Q { a }","operationName":"Q"}"#;
+        let analysis = guard.inspect_body(body).unwrap();
+        assert_eq!(analysis.operation_name, Some("Q".to_string()));
+
+        assert!(matches!(guard.inspect_body(b"not json"), Err(GraphQLError::InvalidRequest(_))));
+        assert!(matches!(
+            guard.inspect_body(br#"{"query":"{ a "}"#),
+            Err(GraphQLError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_inspect_query_string() {
+        let guard = GraphQLGuard::new(GraphQLLimits::default());
+        let analysis = guard
+            .inspect_query_string("query=query%20Q%20%7B%20a%20%7D&operationName=Q")
+            .unwrap();
+        assert_eq!(analysis.operation_name, Some("Q".to_string()));
+        assert!(guard.inspect_query_string("foo=bar").is_err());
+    }
+
+    #[test]
+    fn test_limits_from_route() {
+        let defaults = GraphQLLimits::from_route(&GraphQLPolicy::default());
+        assert_eq!((defaults.max_depth, defaults.max_complexity), (15, 1000));
+        assert!(defaults.allowed_operations.is_empty());
+
+        let limits = GraphQLLimits::from_route(&GraphQLPolicy {
+            max_depth: Some(2),
+            max_complexity: None,
+            allowed_operations: vec!["GetUser".to_string()],
+        });
+        assert_eq!((limits.max_depth, limits.max_complexity), (2, 1000));
+        assert_eq!(limits.allowed_operations, vec!["GetUser".to_string()]);
+    }
+}
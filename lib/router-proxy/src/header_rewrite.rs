@@ -0,0 +1,245 @@
+//! Request and response header rewrites
+//!
+//! Routes can inject headers (e.g., X-Env) into forwarded requests and
+//! strip internal headers from responses. Each direction removes, then
+//! sets, then adds, so a set replaces whatever the client or backend sent.
+//!
+//! The Host header sent upstream is chosen separately by [`UpstreamHost`].
+
+use anyhow::{Result, anyhow};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, HOST};
+use hyper::Uri;
+use router_api::v1alpha1::vpc_route::{HeaderOperations, HeaderRewritePolicy, UpstreamHostMode, UpstreamHostPolicy};
+
+/// Validated header changes for one direction
+#[derive(Clone, Debug, Default)]
+pub struct HeaderChanges {
+    remove: Vec<HeaderName>,
+    set: Vec<(HeaderName, HeaderValue)>,
+    add: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderChanges {
+    /// Validate header operations from a route
+    pub fn from_operations(operations: &HeaderOperations) -> Result<Self> {
+        Ok(Self {
+            remove: operations.remove.iter().map(|name| parse_name(name)).collect::<Result<_>>()?,
+            set: operations.set.iter().map(|(name, value)| parse_header(name, value)).collect::<Result<_>>()?,
+            add: operations.add.iter().map(|(name, value)| parse_header(name, value)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Remove a header
+    pub fn remove(mut self, name: &str) -> Result<Self> {
+        self.remove.push(parse_name(name)?);
+        Ok(self)
+    }
+
+    /// Set a header, replacing existing values
+    pub fn set(mut self, name: &str, value: &str) -> Result<Self> {
+        self.set.push(parse_header(name, value)?);
+        Ok(self)
+    }
+
+    /// Append a header, keeping existing values
+    pub fn add(mut self, name: &str, value: &str) -> Result<Self> {
+        self.add.push(parse_header(name, value)?);
+        Ok(self)
+    }
+
+    /// Whether there is nothing to change
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.set.is_empty() && self.add.is_empty()
+    }
+
+    /// Apply the changes to a header map
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+        for (name, value) in &self.add {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+/// Header rewrites for requests and responses on a route
+#[derive(Clone, Debug, Default)]
+pub struct HeaderRewrite {
+    /// Changes to forwarded requests
+    pub request: HeaderChanges,
+    /// Changes to returned responses
+    pub response: HeaderChanges,
+}
+
+impl HeaderRewrite {
+    /// Create a rewrite from a VPCRoute header policy
+    pub fn from_policy(policy: &HeaderRewritePolicy) -> Result<Self> {
+        Ok(Self {
+            request: HeaderChanges::from_operations(&policy.request)?,
+            response: HeaderChanges::from_operations(&policy.response)?,
+        })
+    }
+
+    /// Whether the rewrite changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.request.is_empty() && self.response.is_empty()
+    }
+
+    /// Rewrite request headers before forwarding
+    pub fn apply_request(&self, headers: &mut HeaderMap) {
+        self.request.apply(headers);
+    }
+
+    /// Rewrite response headers before returning them
+    pub fn apply_response(&self, headers: &mut HeaderMap) {
+        self.response.apply(headers);
+    }
+}
+
+/// Host header sent to the backend
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum UpstreamHost {
+    /// Forward the client's Host header
+    #[default]
+    Preserve,
+    /// Use the backend's authority
+    Backend,
+    /// Use a fixed value
+    Fixed(HeaderValue),
+}
+
+impl UpstreamHost {
+    /// Create from a VPCRoute upstream host policy
+    pub fn from_policy(policy: &UpstreamHostPolicy) -> Result<Self> {
+        match policy.mode {
+            UpstreamHostMode::Preserve => Ok(Self::Preserve),
+            UpstreamHostMode::Backend => Ok(Self::Backend),
+            UpstreamHostMode::Fixed => {
+                let value = policy
+                    .value
+                    .as_deref()
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| anyhow!("Fixed upstream host requires a value"))?;
+                Self::fixed(value)
+            }
+        }
+    }
+
+    /// Always send `host`
+    pub fn fixed(host: &str) -> Result<Self> {
+        let value = HeaderValue::from_str(host).map_err(|_| anyhow!("Invalid upstream host {:?}", host))?;
+        Ok(Self::Fixed(value))
+    }
+
+    /// Set the Host header for a request to `target`
+    pub fn apply(&self, headers: &mut HeaderMap, target: &Uri) {
+        let value = match self {
+            UpstreamHost::Preserve => return,
+            UpstreamHost::Backend => match target.authority().and_then(|a| HeaderValue::from_str(a.as_str()).ok()) {
+                Some(value) => value,
+                None => return,
+            },
+            UpstreamHost::Fixed(value) => value.clone(),
+        };
+        headers.insert(HOST, value);
+    }
+}
+
+fn parse_name(name: &str) -> Result<HeaderName> {
+    HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| anyhow!("Invalid header name {:?}", name))
+}
+
+fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue)> {
+    let value = HeaderValue::from_str(value).map_err(|_| anyhow!("Invalid value for header {}", name))?;
+    Ok((parse_name(name)?, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_remove_set_add_order() {
+        let operations = HeaderOperations {
+            add: BTreeMap::from([("x-env".to_string(), "staging".to_string())]),
+            set: BTreeMap::from([("x-env".to_string(), "prod".to_string())]),
+            remove: vec!["x-internal-token".to_string()],
+        };
+        let changes = HeaderChanges::from_operations(&operations).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-env", HeaderValue::from_static("client"));
+        headers.insert("x-internal-token", HeaderValue::from_static("secret"));
+        changes.apply(&mut headers);
+
+        let values: Vec<_> = headers.get_all("x-env").iter().collect();
+        assert_eq!(values, ["prod", "staging"]);
+        assert!(!headers.contains_key("x-internal-token"));
+    }
+
+    #[test]
+    fn test_policy_directions() {
+        let policy = HeaderRewritePolicy {
+            request: HeaderOperations {
+                set: BTreeMap::from([("X-Env".to_string(), "prod".to_string())]),
+                ..Default::default()
+            },
+            response: HeaderOperations {
+                remove: vec!["Server".to_string()],
+                ..Default::default()
+            },
+        };
+        let rewrite = HeaderRewrite::from_policy(&policy).unwrap();
+
+        let mut request = HeaderMap::new();
+        rewrite.apply_request(&mut request);
+        assert_eq!(request["x-env"], "prod");
+
+        let mut response = HeaderMap::new();
+        response.insert("server", HeaderValue::from_static("backend/1.0"));
+        rewrite.apply_response(&mut response);
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn test_upstream_host() {
+        let target: Uri = "http://orders.internal:8080/orders".parse().unwrap();
+        let client_headers = || {
+            let mut headers = HeaderMap::new();
+            headers.insert(HOST, HeaderValue::from_static("shop.example.com"));
+            headers
+        };
+
+        let mut headers = client_headers();
+        UpstreamHost::Preserve.apply(&mut headers, &target);
+        assert_eq!(headers[HOST], "shop.example.com");
+
+        UpstreamHost::Backend.apply(&mut headers, &target);
+        assert_eq!(headers[HOST], "orders.internal:8080");
+
+        let policy = UpstreamHostPolicy {
+            mode: UpstreamHostMode::Fixed,
+            value: Some("orders.example.com".to_string()),
+        };
+        let mut headers = client_headers();
+        UpstreamHost::from_policy(&policy).unwrap().apply(&mut headers, &target);
+        assert_eq!(headers[HOST], "orders.example.com");
+
+        let missing = UpstreamHostPolicy {
+            mode: UpstreamHostMode::Fixed,
+            value: None,
+        };
+        assert!(UpstreamHost::from_policy(&missing).is_err());
+    }
+
+    #[test]
+    fn test_invalid_headers_rejected() {
+        assert!(HeaderChanges::default().set("bad header", "x").is_err());
+        assert!(HeaderChanges::default().add("x-ok", "line\nbreak").is_err());
+    }
+}
@@ -1,9 +1,46 @@
 //! Health checking for service endpoints
+//!
+//! Endpoints are checked with an HTTP GET of the configured path. An
+//! endpoint is healthy when it answers within the timeout with a status in
+//! one of the expected ranges and, if a body substring is expected, a body
+//! containing it. Endpoints of TCP services, which may not speak HTTP,
+//! are healthy when they accept a connection.
+//!
+//! Services with health check settings in their VPCService spec are
+//! checked with those settings in place of the checker's own path,
+//! interval, timeout, and thresholds.
+//!
+//! A checker built with [`HealthChecker::with_tls`] probes endpoints over
+//! HTTPS instead, presenting a client certificate when one is configured so
+//! that mTLS-only services can be checked.
 
-use router_core::Endpoint;
+use crate::metrics::MetricsCollector;
+use crate::mtls::TlsClientConfig;
+use anyhow::Context;
+
+use http_body_util::{BodyExt, Empty, Limited};
+use hyper::body::Bytes;
+use hyper::header::USER_AGENT;
+use hyper::{Method, Request};
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use router_api::v1alpha1::vpc_service::HealthCheckConfig as ServiceHealthCheck;
+use router_core::registry::ServiceInfo;
+use router_core::{CoreError, Endpoint, ServiceRegistry};
+use rustls::pki_types::ServerName;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time;
-use tracing::{debug, warn};
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Most of a health check response body read looking for the expected text
+const MAX_BODY_BYTES: usize = 64 * 1024;
 
 /// Health check configuration
 #[derive(Clone, Debug)]
@@ -18,6 +55,13 @@ pub struct HealthCheckConfig {
     pub unhealthy_threshold: u32,
     /// Number of consecutive successes before marking healthy
     pub healthy_threshold: u32,
+    /// Response statuses counted as healthy
+    pub expected_statuses: Vec<RangeInclusive<u16>>,
+    /// Text the response body must contain, if any
+    pub expected_body: Option<String>,
+    /// Name verified against endpoint certificates over TLS, since
+    /// endpoints are addressed by IP
+    pub tls_server_name: Option<String>,
 }
 
 impl Default for HealthCheckConfig {
@@ -28,6 +72,49 @@ impl Default for HealthCheckConfig {
             timeout: Duration::from_secs(5),
             unhealthy_threshold: 3,
             healthy_threshold: 2,
+            expected_statuses: vec![200..=399],
+            expected_body: None,
+            tls_server_name: None,
+        }
+    }
+}
+
+impl HealthCheckConfig {
+    /// These settings with the path, interval, timeout, and thresholds set
+    /// in a VPCService's spec
+    ///
+    /// The expected statuses and body and the TLS server name are not part
+    /// of the spec and are kept.
+    pub fn for_service(&self, spec: &ServiceHealthCheck) -> Self {
+        Self {
+            http_path: spec.http_path.clone().unwrap_or_else(|| self.http_path.clone()),
+            check_interval: Duration::from_secs(spec.interval_seconds.max(1).into()),
+            timeout: Duration::from_secs(spec.timeout_seconds.max(1).into()),
+            unhealthy_threshold: spec.unhealthy_threshold.max(1),
+            healthy_threshold: spec.healthy_threshold.max(1),
+            ..self.clone()
+        }
+    }
+}
+
+/// Result of checking an endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// Endpoint answered its health check
+    Healthy,
+    /// Endpoint failed its health check
+    Unhealthy,
+    /// Service is in planned maintenance; the endpoint was not checked
+    Maintenance,
+}
+
+impl HealthStatus {
+    /// Metric label for the status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Unhealthy => "unhealthy",
+            HealthStatus::Maintenance => "maintenance",
         }
     }
 }
@@ -35,19 +122,83 @@ impl Default for HealthCheckConfig {
 /// Health checker for monitoring endpoint health
 pub struct HealthChecker {
     config: HealthCheckConfig,
+    /// Shared by every check, so connections to endpoints are reused
+    client: Client<HttpsConnector<HttpConnector>, Empty<Bytes>>,
+    /// URL scheme endpoints are checked with
+    scheme: &'static str,
 }
 
 impl HealthChecker {
-    /// Create a new health checker
+    /// Create a new health checker probing endpoints over plain HTTP
     pub fn new(config: HealthCheckConfig) -> Self {
-        Self { config }
+        // Only http URLs are built, so no trust roots are needed
+        let tls = rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let client = Self::build_client(tls, None);
+        Self { config, client, scheme: "http" }
+    }
+
+    /// Create a health checker probing endpoints over HTTPS
+    ///
+    /// The client certificate in `tls_config`, if any, is presented to
+    /// endpoints requiring one.
+    pub fn with_tls(config: HealthCheckConfig, tls_config: TlsClientConfig) -> anyhow::Result<Self> {
+        let tls = tls_config.client_config()?;
+        let server_name = match &config.tls_server_name {
+            Some(name) => Some(
+                ServerName::try_from(name.clone())
+                    .with_context(|| format!("invalid health check TLS server name {:?}", name))?,
+            ),
+            None => None,
+        };
+        let client = Self::build_client(tls, server_name);
+        Ok(Self { config, client, scheme: "https" })
+    }
+
+    /// Configuration the checker was created with
+    pub fn config(&self) -> &HealthCheckConfig {
+        &self.config
+    }
+
+    /// Configuration `service` is checked with: the checker's, with the
+    /// settings of the service's spec in place
+    pub fn service_config(&self, service: &ServiceInfo) -> HealthCheckConfig {
+        match &service.health_check {
+            Some(spec) => self.config.for_service(spec),
+            None => self.config.clone(),
+        }
+    }
+
+    fn build_client(
+        tls: rustls::ClientConfig,
+        server_name: Option<ServerName<'static>>,
+    ) -> Client<HttpsConnector<HttpConnector>, Empty<Bytes>> {
+        let mut connector = HttpConnector::new();
+        connector.enforce_http(false);
+        let builder = HttpsConnectorBuilder::new().with_tls_config(tls).https_or_http();
+        let builder = match server_name {
+            Some(name) => builder.with_server_name_resolver(FixedServerNameResolver::new(name)),
+            None => builder,
+        };
+        Client::builder(TokioExecutor::new()).build(builder.enable_http1().wrap_connector(connector))
     }
 
     /// Check if an endpoint is healthy by making an HTTP request
     pub async fn check_endpoint(&self, endpoint: &Endpoint) -> bool {
-        let url = format!("http://{}:{}{}", endpoint.ip, endpoint.port, self.config.http_path);
+        self.check_http(endpoint, &self.config).await
+    }
+
+    /// Check if an endpoint is healthy by opening a TCP connection to it
+    pub async fn check_tcp_endpoint(&self, endpoint: &Endpoint) -> bool {
+        self.check_tcp(endpoint, self.config.timeout).await
+    }
+
+    async fn check_http(&self, endpoint: &Endpoint, config: &HealthCheckConfig) -> bool {
+        let host = if endpoint.ip.contains(':') { format!("[{}]", endpoint.ip) } else { endpoint.ip.clone() };
+        let url = format!("{}://{}:{}{}", self.scheme, host, endpoint.port, config.http_path);
 
-        match time::timeout(self.config.timeout, self.check_single(url.clone())).await {
+        match time::timeout(config.timeout, self.check_single(&url, config)).await {
             Ok(Ok(healthy)) => {
                 if healthy {
                     debug!("Endpoint {}:{} is healthy", endpoint.ip, endpoint.port);
@@ -68,81 +219,273 @@ impl HealthChecker {
         }
     }
 
-    /// Check a single endpoint (internal)
-    async fn check_single(&self, url: String) -> Result<bool, String> {
-        // For now, we'll use a simple TCP connection check
-        // In Phase 3, this would make actual HTTP requests
-        // For Phase 3 MVP, we consider endpoints healthy if they're in the registry
-        match tokio::net::TcpStream::connect(
-            format!("{}:{}",
-                self.extract_host(&url),
-                self.extract_port(&url)
-            )
-        ).await {
-            Ok(_) => {
-                debug!("TCP connection to {} succeeded", url);
-                Ok(true)
+    async fn check_tcp(&self, endpoint: &Endpoint, timeout: Duration) -> bool {
+        let addr = (endpoint.ip.as_str(), endpoint.port);
+        match time::timeout(timeout, tokio::net::TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => {
+                debug!("TCP connection to {}:{} succeeded", endpoint.ip, endpoint.port);
+                true
             }
-            Err(e) => {
-                warn!("TCP connection to {} failed: {}", url, e);
-                Ok(false)
+            Ok(Err(e)) => {
+                warn!("TCP connection to {}:{} failed: {}", endpoint.ip, endpoint.port, e);
+                false
+            }
+            Err(_) => {
+                warn!("TCP connection to {}:{} timed out", endpoint.ip, endpoint.port);
+                false
             }
         }
     }
 
-    fn extract_host<'a>(&self, url: &'a str) -> &'a str {
-        // Extract host from "http://10.0.0.1:8080/healthz"
-        if let Some(start) = url.find("://") {
-            let rest = &url[start + 3..];
-            if let Some(colon) = rest.find(':') {
-                return &rest[..colon];
-            }
+    /// Check every endpoint of a service
+    ///
+    /// HTTP services are checked with a GET, TCP services with a connect,
+    /// using the service's configuration (see [`Self::service_config`]).
+    /// Checks pause while the service is in planned maintenance: its
+    /// endpoints are reported as draining rather than unhealthy.
+    pub async fn check_service(
+        &self,
+        registry: &ServiceRegistry,
+        service_id: &str,
+    ) -> router_core::Result<Vec<(Endpoint, HealthStatus)>> {
+        let service = registry.get_service(service_id).await?;
+        let config = self.service_config(&service);
+        Ok(self.check_endpoints(service, &config).await)
+    }
+
+    /// Check every endpoint of `service` with `config`
+    async fn check_endpoints(&self, service: ServiceInfo, config: &HealthCheckConfig) -> Vec<(Endpoint, HealthStatus)> {
+        if let Some(reason) = &service.maintenance {
+            debug!("Skipping health checks for {} in maintenance: {}", service.service_id, reason);
+            return service
+                .endpoints
+                .into_iter()
+                .map(|endpoint| (endpoint, HealthStatus::Maintenance))
+                .collect();
         }
-        "127.0.0.1"
+
+        let tcp = service.protocol.eq_ignore_ascii_case("TCP");
+        let mut results = Vec::with_capacity(service.endpoints.len());
+        for endpoint in service.endpoints {
+            let healthy = if tcp {
+                self.check_tcp(&endpoint, config.timeout).await
+            } else {
+                self.check_http(&endpoint, config).await
+            };
+            let status = if healthy {
+                HealthStatus::Healthy
+            } else {
+                HealthStatus::Unhealthy
+            };
+            results.push((endpoint, status));
+        }
+        results
     }
 
-    fn extract_port(&self, url: &str) -> u16 {
-        // Extract port from "http://10.0.0.1:8080/healthz" or "http://localhost:3000/health"
-        // Skip the scheme (http:// or https://)
-        let without_scheme = if let Some(pos) = url.find("://") {
-            &url[pos + 3..]
-        } else {
-            url
+    /// Check a single endpoint (internal)
+    async fn check_single(&self, url: &str, config: &HealthCheckConfig) -> Result<bool, String> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(url)
+            .header(USER_AGENT, "router-gateway-health-check")
+            .body(Empty::new())
+            .map_err(|e| e.to_string())?;
+        let response = self.client.request(request).await.map_err(|e| e.to_string())?;
+
+        let status = response.status().as_u16();
+        if !config.expected_statuses.iter().any(|range| range.contains(&status)) {
+            debug!("Health check of {} returned unexpected status {}", url, status);
+            return Ok(false);
+        }
+        let Some(expected) = &config.expected_body else {
+            return Ok(true);
         };
+        let body = Limited::new(response.into_body(), MAX_BODY_BYTES)
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        let found = String::from_utf8_lossy(&body).contains(expected.as_str());
+        if !found {
+            debug!("Health check of {} returned a body without {:?}", url, expected);
+        }
+        Ok(found)
+    }
+}
 
-        // Find the colon that separates host from port
-        if let Some(colon_pos) = without_scheme.find(':') {
-            let after_colon = &without_scheme[colon_pos + 1..];
-            // Extract until slash or end of string
-            if let Some(slash_pos) = after_colon.find('/') {
-                if let Ok(port) = after_colon[..slash_pos].parse::<u16>() {
-                    return port;
-                }
-            } else if let Ok(port) = after_colon.parse::<u16>() {
-                return port;
+/// Consecutive check results of one endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct EndpointHealth {
+    ready: bool,
+    successes: u32,
+    failures: u32,
+}
+
+impl EndpointHealth {
+    fn new(ready: bool) -> Self {
+        Self { ready, successes: 0, failures: 0 }
+    }
+
+    /// Count a check result, returning the new readiness when the healthy
+    /// or unhealthy threshold flips it
+    fn observe(&mut self, healthy: bool, config: &HealthCheckConfig) -> Option<bool> {
+        if healthy {
+            self.failures = 0;
+            self.successes = self.successes.saturating_add(1);
+            if !self.ready && self.successes >= config.healthy_threshold {
+                self.ready = true;
+                return Some(true);
+            }
+        } else {
+            self.successes = 0;
+            self.failures = self.failures.saturating_add(1);
+            if self.ready && self.failures >= config.unhealthy_threshold {
+                self.ready = false;
+                return Some(false);
             }
         }
-        8080
+        None
     }
 }
 
 /// Health check monitor for periodic checking
+///
+/// Every registered service gets a check loop of its own, run at the
+/// service's interval. An endpoint is marked not ready in the registry
+/// after `unhealthy_threshold` failed checks in a row and ready again after
+/// `healthy_threshold` passing ones, using the service's thresholds. A
+/// service's loop stops once it is deregistered.
 pub struct HealthCheckMonitor {
-    config: HealthCheckConfig,
+    checker: Arc<HealthChecker>,
+    registry: Arc<ServiceRegistry>,
+    metrics: Option<Arc<MetricsCollector>>,
 }
 
 impl HealthCheckMonitor {
-    /// Create a new health check monitor
-    pub fn new(config: HealthCheckConfig) -> Self {
-        Self { config }
+    /// Create a monitor checking the services of `registry`
+    pub fn new(checker: Arc<HealthChecker>, registry: Arc<ServiceRegistry>) -> Self {
+        Self {
+            checker,
+            registry,
+            metrics: None,
+        }
+    }
+
+    /// Count checks and ejections through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
 
     /// Start periodic health checking for endpoints
-    /// This would be called from the main gateway loop
-    pub fn start_monitoring(&self) {
-        debug!("Health check monitor started with interval: {:?}", self.config.check_interval);
-        // In Phase 3, this will spawn background tasks to periodically check endpoints
-        // and update the service registry with health status
+    ///
+    /// The returned task looks for new services every check interval and
+    /// spawns a check loop for each.
+    pub fn start_monitoring(self: &Arc<Self>) -> JoinHandle<()> {
+        let monitor = self.clone();
+        let interval = self.checker.config().check_interval;
+        debug!("Health check monitor started with interval: {:?}", interval);
+        tokio::spawn(async move {
+            let mut loops: HashMap<String, JoinHandle<()>> = HashMap::new();
+            let mut ticker = time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let live: HashSet<String> = match monitor.registry.list_services().await {
+                    Ok(services) => services.into_iter().map(|s| s.service_id).collect(),
+                    Err(e) => {
+                        warn!("Failed to list services for health checks: {}", e);
+                        continue;
+                    }
+                };
+                loops.retain(|service_id, task| {
+                    if !live.contains(service_id) {
+                        task.abort();
+                        return false;
+                    }
+                    !task.is_finished()
+                });
+                for service_id in live {
+                    if let Entry::Vacant(entry) = loops.entry(service_id) {
+                        debug!("Starting health checks for {}", entry.key());
+                        let service_id = entry.key().clone();
+                        entry.insert(tokio::spawn(monitor.clone().monitor_service(service_id)));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Check a service every interval until it is deregistered
+    ///
+    /// The interval follows changes to the service's configuration.
+    async fn monitor_service(self: Arc<Self>, service_id: String) {
+        let mut health = HashMap::new();
+        let mut interval = self.checker.config().check_interval;
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match self.check_service(&service_id, &mut health).await {
+                Ok(next) if next != interval => {
+                    debug!("Checking {} every {:?}", service_id, next);
+                    interval = next;
+                    ticker = time::interval_at(time::Instant::now() + interval, interval);
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                }
+                Ok(_) => {}
+                Err(CoreError::ServiceNotFound(_)) => {
+                    debug!("Stopping health checks for deregistered service {}", service_id);
+                    return;
+                }
+                Err(e) => warn!("Health checks for {} failed: {}", service_id, e),
+            }
+        }
+    }
+
+    /// Check a service's endpoints once, updating the readiness of those
+    /// crossing a threshold
+    ///
+    /// Returns the service's check interval.
+    async fn check_service(
+        &self,
+        service_id: &str,
+        health: &mut HashMap<String, EndpointHealth>,
+    ) -> router_core::Result<Duration> {
+        let service = self.registry.get_service(service_id).await?;
+        let config = self.checker.service_config(&service);
+        let results = self.checker.check_endpoints(service, &config).await;
+        let mut seen = HashSet::with_capacity(results.len());
+        for (endpoint, status) in results {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_health_check(service_id, status);
+            }
+            let key = format!("{}:{}", endpoint.ip, endpoint.port);
+            seen.insert(key.clone());
+            if status == HealthStatus::Maintenance {
+                continue;
+            }
+
+            let state = health.entry(key.clone()).or_insert_with(|| EndpointHealth::new(endpoint.ready));
+            let Some(ready) = state.observe(status == HealthStatus::Healthy, &config) else {
+                continue;
+            };
+            info!("Endpoint {} of {} is now {}", key, service_id, status.as_str());
+            match self.registry.set_endpoint_ready(service_id, &endpoint.ip, endpoint.port, ready).await {
+                // Endpoints changed since the check; the next one starts over
+                Err(CoreError::EndpointNotFound(_)) => {
+                    health.remove(&key);
+                    continue;
+                }
+                result => result?,
+            }
+            if let (Some(metrics), false) = (&self.metrics, ready) {
+                metrics.record_endpoint_ejection(service_id, &key);
+            }
+        }
+        // Forget endpoints that left the service
+        health.retain(|key, _| seen.contains(key));
+        Ok(config.check_interval)
     }
 }
 
@@ -161,16 +504,286 @@ mod tests {
     }
 
     #[test]
-    fn test_extract_host() {
-        let checker = HealthChecker::new(HealthCheckConfig::default());
-        assert_eq!(checker.extract_host("http://10.0.0.1:8080/healthz"), "10.0.0.1");
-        assert_eq!(checker.extract_host("http://localhost:3000/health"), "localhost");
+    fn test_thresholds() {
+        let config = HealthCheckConfig::default();
+        let mut health = EndpointHealth::new(true);
+        assert_eq!(health.observe(false, &config), None);
+        assert_eq!(health.observe(false, &config), None);
+        // A pass resets the failure count
+        assert_eq!(health.observe(true, &config), None);
+        assert_eq!(health.observe(false, &config), None);
+        assert_eq!(health.observe(false, &config), None);
+        assert_eq!(health.observe(false, &config), Some(false));
+        assert_eq!(health.observe(false, &config), None);
+
+        assert_eq!(health.observe(true, &config), None);
+        assert_eq!(health.observe(true, &config), Some(true));
+        assert_eq!(health.observe(true, &config), None);
     }
 
-    #[test]
-    fn test_extract_port() {
+    #[tokio::test]
+    async fn test_monitor_updates_registry() {
+        let healthy = Endpoint { ready: false, ..spawn_endpoint().await };
+        // Nothing listens on port 1
+        let failing = Endpoint {
+            ip: "127.0.0.1".to_string(),
+            port: 1,
+            ready: true,
+            priority: 0,
+        };
+        let registry = Arc::new(ServiceRegistry::new());
+        registry
+            .register_service(
+                "default".into(),
+                "api".into(),
+                80,
+                "HTTP".into(),
+                vec![healthy.clone(), failing.clone()],
+            )
+            .await
+            .unwrap();
+        let checker = Arc::new(HealthChecker::new(HealthCheckConfig {
+            check_interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        }));
+        let monitor = Arc::new(HealthCheckMonitor::new(checker, registry.clone()));
+        let task = monitor.start_monitoring();
+
+        let ready = || async {
+            let endpoints = registry.get_endpoints("default/api").await.unwrap();
+            endpoints.iter().map(|e| e.ready).collect::<Vec<_>>()
+        };
+        for _ in 0..100 {
+            if ready().await == [true, false] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(ready().await, [true, false]);
+
+        task.abort();
+
+        // A service's loop stops once it is deregistered
+        let service_loop = tokio::spawn(monitor.clone().monitor_service("default/api".to_string()));
+        registry.deregister_service("default/api").await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), service_loop).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_pauses_checks() {
+        let registry = ServiceRegistry::new();
+        // Nothing listens on port 1, so a real check would fail
+        let endpoint = Endpoint {
+            ip: "127.0.0.1".to_string(),
+            port: 1,
+            ready: true,
+            priority: 0,
+        };
+        registry
+            .register_service("default".into(), "api".into(), 1, "HTTP".into(), vec![endpoint])
+            .await
+            .unwrap();
         let checker = HealthChecker::new(HealthCheckConfig::default());
-        assert_eq!(checker.extract_port("http://10.0.0.1:8080/healthz"), 8080);
-        assert_eq!(checker.extract_port("http://localhost:3000/health"), 3000);
+
+        let results = checker.check_service(&registry, "default/api").await.unwrap();
+        assert_eq!(results[0].1, HealthStatus::Unhealthy);
+
+        registry
+            .set_maintenance("default/api", Some("Database upgrade".to_string()))
+            .await
+            .unwrap();
+        let results = checker.check_service(&registry, "default/api").await.unwrap();
+        assert_eq!(results[0].1, HealthStatus::Maintenance);
+        assert!(results[0].0.ready);
+    }
+
+    #[tokio::test]
+    async fn test_service_config() {
+        let registry = ServiceRegistry::new();
+        let endpoint = spawn_endpoint().await;
+        registry
+            .register_service("default".into(), "api".into(), 80, "HTTP".into(), vec![endpoint])
+            .await
+            .unwrap();
+        let checker = HealthChecker::new(HealthCheckConfig {
+            expected_body: Some("starting".to_string()),
+            ..Default::default()
+        });
+        let service = registry.get_service("default/api").await.unwrap();
+        assert_eq!(checker.service_config(&service).http_path, "/healthz");
+
+        let spec = ServiceHealthCheck {
+            interval_seconds: 30,
+            timeout_seconds: 0,
+            unhealthy_threshold: 1,
+            ..ServiceHealthCheck::http("/starting")
+        };
+        registry.set_health_check("default/api", Some(spec)).await.unwrap();
+        let service = registry.get_service("default/api").await.unwrap();
+        let config = checker.service_config(&service);
+        assert_eq!(config.http_path, "/starting");
+        assert_eq!(config.check_interval, Duration::from_secs(30));
+        assert_eq!(config.timeout, Duration::from_secs(1));
+        assert_eq!((config.unhealthy_threshold, config.healthy_threshold), (1, 2));
+        // Settings outside the spec are kept
+        assert_eq!(config.expected_body.as_deref(), Some("starting"));
+
+        // Checks use the service's path: 503 at /starting
+        let results = checker.check_service(&registry, "default/api").await.unwrap();
+        assert_eq!(results[0].1, HealthStatus::Unhealthy);
+
+        // The monitor ejects after the service's single failure
+        let monitor = HealthCheckMonitor::new(Arc::new(checker), Arc::new(registry));
+        let mut health = HashMap::new();
+        let interval = monitor.check_service("default/api", &mut health).await.unwrap();
+        assert_eq!(interval, Duration::from_secs(30));
+        assert!(!monitor.registry.get_endpoints("default/api").await.unwrap()[0].ready);
+    }
+
+    /// Endpoint answering /healthz with 200 "ok", /starting with 503, and
+    /// /slow after a delay
+    async fn spawn_endpoint() -> Endpoint {
+        use http_body_util::Full;
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper::Response;
+        use hyper_util::rt::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { return };
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        let response = match req.uri().path() {
+                            "/healthz" => Response::new(Full::new(Bytes::from("status: ok"))),
+                            "/slow" => {
+                                tokio::time::sleep(Duration::from_secs(5)).await;
+                                Response::new(Full::new(Bytes::new()))
+                            }
+                            _ => Response::builder().status(503).body(Full::new(Bytes::from("starting"))).unwrap(),
+                        };
+                        Ok::<_, hyper::Error>(response)
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        Endpoint {
+            ip: "127.0.0.1".to_string(),
+            port,
+            ready: true,
+            priority: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_checks() {
+        let endpoint = spawn_endpoint().await;
+        let checker = |path: &str, expected_body: Option<&str>| {
+            HealthChecker::new(HealthCheckConfig {
+                http_path: path.to_string(),
+                timeout: Duration::from_millis(200),
+                expected_body: expected_body.map(str::to_string),
+                ..Default::default()
+            })
+        };
+
+        assert!(checker("/healthz", None).check_endpoint(&endpoint).await);
+        assert!(checker("/healthz", Some("ok")).check_endpoint(&endpoint).await);
+        assert!(!checker("/healthz", Some("ready")).check_endpoint(&endpoint).await);
+        assert!(!checker("/starting", None).check_endpoint(&endpoint).await);
+        assert!(!checker("/slow", None).check_endpoint(&endpoint).await);
+
+        // Other statuses count as healthy when expected
+        let accepting = HealthChecker::new(HealthCheckConfig {
+            http_path: "/starting".to_string(),
+            expected_statuses: vec![200..=299, 503..=503],
+            ..Default::default()
+        });
+        assert!(accepting.check_endpoint(&endpoint).await);
+    }
+
+    #[tokio::test]
+    async fn test_https_checks() {
+        use http_body_util::Full;
+        use hyper::server::conn::http1;
+        use hyper::Response;
+        use hyper::service::service_fn;
+        use hyper_util::rt::TokioIo;
+        use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let issue = |usage: ExtendedKeyUsagePurpose| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec!["api.internal".to_string()]).unwrap();
+            params.extended_key_usages = vec![usage];
+            let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+            (cert.pem(), key.serialize_pem())
+        };
+        let (server_cert, server_key) = issue(ExtendedKeyUsagePurpose::ServerAuth);
+        let (client_cert, client_key) = issue(ExtendedKeyUsagePurpose::ClientAuth);
+
+        // Endpoint only accepting clients with a certificate
+        let server_tls = crate::tls::TlsServerConfig::from_pem_with_client_auth(
+            server_cert.as_bytes(),
+            server_key.as_bytes(),
+            Some(ca.pem().as_bytes()),
+            true,
+            None,
+            None,
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(server_tls.config.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { return };
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(stream).await else { return };
+                    let service = service_fn(|_req: Request<hyper::body::Incoming>| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("ok"))))
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        let endpoint = Endpoint {
+            ip: "127.0.0.1".to_string(),
+            port,
+            ready: true,
+            priority: 0,
+        };
+        let config = |server_name: Option<&str>| HealthCheckConfig {
+            timeout: Duration::from_secs(2),
+            tls_server_name: server_name.map(str::to_string),
+            ..Default::default()
+        };
+        let tls = |with_cert: bool| {
+            let (cert, key) = if with_cert {
+                (client_cert.clone().into_bytes(), client_key.clone().into_bytes())
+            } else {
+                (Vec::new(), Vec::new())
+            };
+            TlsClientConfig::new(cert, key, Some(ca.pem().into_bytes()), true)
+        };
+
+        let checker = HealthChecker::with_tls(config(Some("api.internal")), tls(true)).unwrap();
+        assert!(checker.check_endpoint(&endpoint).await);
+
+        // Plain HTTP, a missing client certificate, or a name the
+        // certificate doesn't cover all fail
+        assert!(!HealthChecker::new(config(None)).check_endpoint(&endpoint).await);
+        let anonymous = HealthChecker::with_tls(config(Some("api.internal")), tls(false)).unwrap();
+        assert!(!anonymous.check_endpoint(&endpoint).await);
+        let mismatched = HealthChecker::with_tls(config(Some("db.internal")), tls(true)).unwrap();
+        assert!(!mismatched.check_endpoint(&endpoint).await);
     }
 }
@@ -1,6 +1,7 @@
 //! HTTP proxy implementation with request forwarding
 
 use hyper::{Response, StatusCode, body::Bytes, Request};
+use crate::problem;
 use router_core::{ServiceRegistry, Endpoint};
 use std::sync::Arc;
 use tracing::debug;
@@ -79,45 +80,33 @@ impl HttpProxy {
 
     /// Create a 502 Bad Gateway response
     pub fn bad_gateway_response(reason: &str) -> Response<Bytes> {
-        let body = Bytes::from(format!("Bad Gateway: {}\n", reason));
-        Response::builder()
-            .status(StatusCode::BAD_GATEWAY)
-            .body(body)
-            .unwrap()
+        problem::error_response(StatusCode::BAD_GATEWAY, reason)
     }
 
     /// Create a 503 Service Unavailable response
     pub fn service_unavailable_response(reason: &str) -> Response<Bytes> {
-        let body = Bytes::from(format!("Service Unavailable: {}\n", reason));
-        Response::builder()
-            .status(StatusCode::SERVICE_UNAVAILABLE)
-            .body(body)
-            .unwrap()
+        problem::error_response(StatusCode::SERVICE_UNAVAILABLE, reason)
     }
 
     /// Create a 504 Gateway Timeout response
     pub fn gateway_timeout_response(reason: &str) -> Response<Bytes> {
-        let body = Bytes::from(format!("Gateway Timeout: {}\n", reason));
-        Response::builder()
-            .status(StatusCode::GATEWAY_TIMEOUT)
-            .body(body)
-            .unwrap()
+        problem::error_response(StatusCode::GATEWAY_TIMEOUT, reason)
+    }
+
+    /// Create a 429 Too Many Requests response
+    pub fn too_many_requests_response(reason: &str) -> Response<Bytes> {
+        problem::error_response(StatusCode::TOO_MANY_REQUESTS, reason)
     }
 
     /// Create a 404 Not Found response
     pub fn not_found_response(reason: &str) -> Response<Bytes> {
-        let body = Bytes::from(format!("Not Found: {}\n", reason));
-        Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(body)
-            .unwrap()
+        problem::error_response(StatusCode::NOT_FOUND, reason)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use router_core::ServiceRegistry;
 
     #[test]
     fn test_build_target_url() {
@@ -125,6 +114,7 @@ mod tests {
             ip: "10.0.0.1".to_string(),
             port: 8080,
             ready: true,
+            priority: 0,
         };
 
         let url = HttpProxy::build_target_url(&endpoint, "/api/v1/users");
@@ -137,6 +127,7 @@ mod tests {
             ip: "10.0.0.1".to_string(),
             port: 8080,
             ready: true,
+            priority: 0,
         };
 
         let url = HttpProxy::build_target_url(&endpoint, "/");
@@ -0,0 +1,251 @@
+//! HTTP/3 listener over QUIC
+//!
+//! Serves requests on a UDP port alongside the TCP listeners, reusing the
+//! HTTPS certificate. Clients learn about it from the Alt-Svc header on
+//! HTTPS responses and upgrade on their next connection.
+
+use anyhow::{Result, anyhow};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Buf, Bytes};
+use hyper::header::HeaderValue;
+use hyper::{Request, Response};
+use quinn::crypto::rustls::QuicServerConfig;
+use std::fmt::Display;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::tls::TlsServerConfig;
+
+/// HTTP/3 listener configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct Http3Config {
+    /// UDP port to listen on
+    pub port: u16,
+    /// How long clients may remember the HTTP/3 endpoint
+    pub alt_svc_max_age: Duration,
+}
+
+impl Default for Http3Config {
+    fn default() -> Self {
+        Self {
+            port: 8443,
+            alt_svc_max_age: Duration::from_secs(86400),
+        }
+    }
+}
+
+impl Http3Config {
+    /// Alt-Svc header value advertising the HTTP/3 endpoint
+    pub fn alt_svc(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!(
+            "h3=\":{}\"; ma={}",
+            self.port,
+            self.alt_svc_max_age.as_secs()
+        ))
+        .expect("Alt-Svc value is valid ASCII")
+    }
+}
+
+/// HTTP/3 server bound to a QUIC endpoint
+pub struct Http3Server {
+    endpoint: quinn::Endpoint,
+}
+
+impl Http3Server {
+    /// Bind a QUIC endpoint using the HTTPS listener's certificate
+    pub fn bind(tls: &TlsServerConfig, addr: SocketAddr) -> Result<Self> {
+        let mut config = (*tls.config).clone();
+        config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = QuicServerConfig::try_from(config)
+            .map_err(|e| anyhow!("TLS configuration unusable for QUIC: {}", e))?;
+        let endpoint = quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(crypto)), addr)?;
+        Ok(Self { endpoint })
+    }
+
+    /// Local address of the endpoint
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    /// Accept connections until the endpoint is closed
+    ///
+    /// `new_service` is called once per connection with the client address
+    /// and returns the handler for that connection's requests. Request
+    /// bodies are buffered before the handler runs.
+    pub async fn serve<S, H, Fut, B, E>(self, new_service: S)
+    where
+        S: Fn(SocketAddr) -> H,
+        H: Fn(Request<Full<Bytes>>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response<B>, E>> + Send,
+        B: Body<Data = Bytes> + Send + Unpin,
+        B::Error: Display + Send,
+        E: Display + Send,
+    {
+        info!("HTTP/3 server listening on {}", self.endpoint.local_addr().map(|a| a.to_string()).unwrap_or_default());
+        while let Some(incoming) = self.endpoint.accept().await {
+            let peer_addr = incoming.remote_address();
+            let service = new_service(peer_addr);
+            tokio::task::spawn(async move {
+                if let Err(e) = serve_connection(incoming, service).await {
+                    debug!("Error serving HTTP/3 connection from {}: {}", peer_addr, e);
+                }
+            });
+        }
+    }
+}
+
+async fn serve_connection<H, Fut, B, E>(incoming: quinn::Incoming, service: H) -> Result<()>
+where
+    H: Fn(Request<Full<Bytes>>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response<B>, E>> + Send,
+    B: Body<Data = Bytes> + Send + Unpin,
+    B::Error: Display + Send,
+    E: Display + Send,
+{
+    let connection = incoming.await?;
+    let mut h3 = h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(connection)).await?;
+
+    loop {
+        match h3.accept().await {
+            Ok(Some(resolver)) => {
+                let service = service.clone();
+                tokio::task::spawn(async move {
+                    let (req, mut stream) = match resolver.resolve_request().await {
+                        Ok(request) => request,
+                        Err(e) => {
+                            debug!("Failed to read HTTP/3 request: {}", e);
+                            return;
+                        }
+                    };
+
+                    let mut body = Vec::new();
+                    loop {
+                        match stream.recv_data().await {
+                            Ok(Some(mut chunk)) => body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining())),
+                            Ok(None) => break,
+                            Err(e) => {
+                                debug!("Failed to read HTTP/3 request body: {}", e);
+                                return;
+                            }
+                        }
+                    }
+                    let req = req.map(|_| Full::new(Bytes::from(body)));
+
+                    let response = match service(req).await {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("HTTP/3 request handler failed: {}", e);
+                            return;
+                        }
+                    };
+                    if let Err(e) = send_response(&mut stream, response).await {
+                        debug!("Failed to send HTTP/3 response: {}", e);
+                    }
+                });
+            }
+            Ok(None) => return Ok(()),
+            Err(e) if e.is_h3_no_error() => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Write a response's head, data, and trailers to a request stream
+async fn send_response<S, B>(stream: &mut h3::server::RequestStream<S, Bytes>, response: Response<B>) -> Result<()>
+where
+    S: h3::quic::BidiStream<Bytes>,
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Display + Send,
+{
+    let (parts, mut body) = response.into_parts();
+    stream.send_response(Response::from_parts(parts, ())).await?;
+
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| anyhow!("Response body error: {}", e))?;
+        match frame.into_data() {
+            Ok(data) => stream.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    stream.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    stream.finish().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alt_svc_value() {
+        assert_eq!(Http3Config::default().alt_svc(), "h3=\":8443\"; ma=86400");
+
+        let config = Http3Config {
+            port: 443,
+            alt_svc_max_age: Duration::from_secs(3600),
+        };
+        assert_eq!(config.alt_svc(), "h3=\":443\"; ma=3600");
+    }
+
+    #[tokio::test]
+    async fn test_request_round_trip() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let tls = TlsServerConfig::from_pem(
+            cert.cert.pem().as_bytes(),
+            cert.key_pair.serialize_pem().as_bytes(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        let server = Http3Server::bind(&tls, ([127, 0, 0, 1], 0).into()).unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.serve(|_peer| {
+            |req: Request<Full<Bytes>>| async move {
+                let path = req.uri().path().to_string();
+                let body = req.into_body().collect().await?.to_bytes();
+                Ok::<_, std::convert::Infallible>(Response::new(Full::new(Bytes::from(format!(
+                    "{} {}",
+                    path,
+                    String::from_utf8_lossy(&body)
+                )))))
+            }
+        }));
+
+        // Client trusting the self-signed certificate
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let mut client_tls = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_tls.alpn_protocols = vec![b"h3".to_vec()];
+        let client_config = quinn::ClientConfig::new(Arc::new(
+            quinn::crypto::rustls::QuicClientConfig::try_from(client_tls).unwrap(),
+        ));
+        let mut endpoint = quinn::Endpoint::client(([127, 0, 0, 1], 0).into()).unwrap();
+        endpoint.set_default_client_config(client_config);
+
+        let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+        let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(connection)).await.unwrap();
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+        let request = Request::post(format!("https://localhost:{}/echo", addr.port())).body(()).unwrap();
+        let mut stream = sender.send_request(request).await.unwrap();
+        stream.send_data(Bytes::from("hello")).await.unwrap();
+        stream.finish().await.unwrap();
+
+        let response = stream.recv_response().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let mut body = Vec::new();
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+        assert_eq!(body, b"/echo hello");
+    }
+}
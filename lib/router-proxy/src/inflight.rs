@@ -0,0 +1,244 @@
+//! Registry of requests in flight
+//!
+//! The gateway registers every proxied request for as long as it is being
+//! handled, so operators can list what is stuck and where: still
+//! connecting to an upstream, waiting for its response headers, or
+//! streaming its body. The forwarder updates a request's backend and
+//! state through the [`InflightHandle`] request extension.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Where a request in flight is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InflightState {
+    /// Being processed by the gateway before forwarding
+    Received,
+    /// Waiting for a connection to the upstream
+    Connecting,
+    /// Sent, waiting for the upstream's response headers
+    Waiting,
+    /// Reading the upstream's response body
+    Streaming,
+}
+
+impl InflightState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => InflightState::Connecting,
+            2 => InflightState::Waiting,
+            3 => InflightState::Streaming,
+            _ => InflightState::Received,
+        }
+    }
+}
+
+struct InflightEntry {
+    method: String,
+    path: String,
+    request_id: Option<String>,
+    started: Instant,
+    route: Mutex<Option<String>>,
+    backend: Mutex<Option<String>>,
+    state: AtomicU8,
+}
+
+/// One request in a snapshot
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InflightSnapshot {
+    /// Tracker-assigned request number
+    pub id: u64,
+    /// Request method
+    pub method: String,
+    /// Request path
+    pub path: String,
+    /// Value of the request's x-request-id header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Route that matched the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    /// Upstream the request is forwarded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Where the request is
+    pub state: InflightState,
+    /// Time since the request was received, in milliseconds
+    pub age_ms: u64,
+}
+
+type InflightMap = Arc<Mutex<HashMap<u64, Arc<InflightEntry>>>>;
+
+/// Tracks the requests the gateway is handling
+#[derive(Default)]
+pub struct InflightTracker {
+    next_id: AtomicU64,
+    requests: InflightMap,
+}
+
+impl InflightTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a request until the returned guard is dropped
+    pub fn begin(&self, method: &str, path: &str, request_id: Option<&str>) -> InflightRequest {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = Arc::new(InflightEntry {
+            method: method.to_string(),
+            path: path.to_string(),
+            request_id: request_id.map(String::from),
+            started: Instant::now(),
+            route: Mutex::new(None),
+            backend: Mutex::new(None),
+            state: AtomicU8::new(InflightState::Received as u8),
+        });
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, entry.clone());
+        InflightRequest {
+            id,
+            requests: self.requests.clone(),
+            handle: InflightHandle(entry),
+        }
+    }
+
+    /// Number of requests in flight
+    pub fn len(&self) -> usize {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Whether no requests are in flight
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Requests in flight, oldest first
+    pub fn snapshot(&self) -> Vec<InflightSnapshot> {
+        let now = Instant::now();
+        let mut snapshot: Vec<_> = self
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(id, entry)| InflightSnapshot {
+                id: *id,
+                method: entry.method.clone(),
+                path: entry.path.clone(),
+                request_id: entry.request_id.clone(),
+                route: entry.route.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+                backend: entry.backend.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+                state: InflightState::from_u8(entry.state.load(Ordering::Relaxed)),
+                age_ms: now.duration_since(entry.started).as_millis() as u64,
+            })
+            .collect();
+        snapshot.sort_by(|a, b| b.age_ms.cmp(&a.age_ms).then(a.id.cmp(&b.id)));
+        snapshot
+    }
+}
+
+/// A registered request, removed from the tracker when dropped
+pub struct InflightRequest {
+    id: u64,
+    requests: InflightMap,
+    handle: InflightHandle,
+}
+
+impl InflightRequest {
+    /// Handle for updating the request, to attach as a request extension
+    pub fn handle(&self) -> InflightHandle {
+        self.handle.clone()
+    }
+}
+
+impl Drop for InflightRequest {
+    fn drop(&mut self) {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+/// Updates a registered request's details
+#[derive(Clone)]
+pub struct InflightHandle(Arc<InflightEntry>);
+
+impl InflightHandle {
+    /// Set the route that matched the request
+    pub fn set_route(&self, route: &str) {
+        *self.0.route.lock().unwrap_or_else(|e| e.into_inner()) = Some(route.to_string());
+    }
+
+    /// Set the upstream the request is forwarded to
+    pub fn set_backend(&self, backend: &str) {
+        *self.0.backend.lock().unwrap_or_else(|e| e.into_inner()) = Some(backend.to_string());
+    }
+
+    /// Set where the request is
+    pub fn set_state(&self, state: InflightState) {
+        self.0.state.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+tokio::task_local! {
+    /// Request whose upstream attempt the current task is running
+    static CURRENT: Option<InflightHandle>;
+}
+
+/// Run an upstream attempt, reporting states from within it to `handle`
+pub(crate) async fn scope<F: Future>(handle: Option<InflightHandle>, future: F) -> F::Output {
+    CURRENT.scope(handle, future).await
+}
+
+/// Report the state of the request whose attempt is running, if any
+pub(crate) fn report(state: InflightState) {
+    let _ = CURRENT.try_with(|handle| {
+        if let Some(handle) = handle {
+            handle.set_state(state);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracks_requests_until_dropped() {
+        let tracker = InflightTracker::new();
+        let first = tracker.begin("GET", "/orders", Some("req-1"));
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let second = tracker.begin("POST", "/users", None);
+
+        first.handle().set_backend("backend:8080");
+        first.handle().set_route("orders");
+        scope(Some(second.handle()), async { report(InflightState::Streaming) }).await;
+        // Outside a scope there's nothing to report to
+        report(InflightState::Waiting);
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].path, "/orders");
+        assert_eq!(snapshot[0].backend.as_deref(), Some("backend:8080"));
+        assert_eq!(snapshot[0].state, InflightState::Received);
+        assert!(snapshot[0].age_ms >= 10);
+        assert_eq!(snapshot[1].state, InflightState::Streaming);
+
+        let json = serde_json::to_value(&snapshot[0]).unwrap();
+        assert_eq!(json["requestId"], "req-1");
+        assert_eq!(json["state"], "received");
+
+        drop(first);
+        drop(second);
+        assert!(tracker.is_empty());
+    }
+}
@@ -8,16 +8,57 @@ pub mod tls;
 pub mod mtls;
 pub mod middleware;
 pub mod metrics;
+pub mod metrics_sink;
 pub mod tracing;
+pub mod oauth2;
+pub mod token_exchange;
+pub mod redaction;
+pub mod pii;
+pub mod graphql;
+pub mod affinity;
+pub mod cache;
+pub mod concurrency;
+pub mod replica;
+pub mod state_store;
+pub mod rate_limit;
+pub mod etag;
+pub mod bandwidth;
+pub mod http3;
+pub mod warmup;
+pub mod endpoint_stats;
+pub mod vpc_traffic;
+pub mod forwarded;
+pub mod compression;
+pub mod header_rewrite;
+pub mod timing;
+pub mod debug;
+pub mod path_rewrite;
+pub mod path_normalize;
+pub mod redirect;
+pub mod direct_response;
+pub mod client_protocol;
+pub mod fault;
+pub mod dns;
+pub mod trailers;
+pub mod pool;
+pub mod tcp;
+pub mod problem;
+pub mod path_labels;
+pub mod inflight;
+pub mod access_log;
+pub mod request_id;
+pub mod wasm;
+pub mod ext_authz;
+pub mod authz_policy;
 
 pub use http::HttpProxy;
-pub use load_balancer::LoadBalancer;
-pub use health_check::{HealthChecker, HealthCheckConfig, HealthCheckMonitor};
+pub use load_balancer::{ActiveConnection, LoadBalancer};
+pub use health_check::{HealthChecker, HealthCheckConfig, HealthCheckMonitor, HealthStatus};
 pub use policy::{
     TimeoutPolicy, RetryPolicy, CircuitBreaker, CircuitBreakerConfig,
-    CircuitState, TrafficPolicy
+    CircuitBreakerRegistry, CircuitState, SharedCircuitState, TrafficPolicy
 };
-pub use forwarder::RequestForwarder;
+pub use forwarder::{ForwardError, RequestForwarder, RequestBodyError, ResponseLimit, UpstreamProtocol, UpstreamService};
 pub use tls::{TlsServerConfig, CertificateMaterial};
 pub use mtls::{
     ClientAuthMode, TlsClientConfig, MtlsClientVerifier,
@@ -25,6 +66,47 @@ pub use mtls::{
     calculate_cert_fingerprint,
     RevocationStatus, RevocationCache, OcspConfig, RevocationRequest, RevocationChecker
 };
-pub use middleware::{Middleware, MiddlewareChain, MiddlewareContext, LoggingMiddleware, HeaderInspectionMiddleware};
+pub use middleware::{Middleware, MiddlewareChain, MiddlewareContext, BodyHookError, InspectedBody, LoggingMiddleware, HeaderInspectionMiddleware};
 pub use metrics::{MetricsCollector, MetricsMiddleware};
+pub use metrics_sink::{MetricsBackend, MetricsSink, PrometheusSink, StatsdSink, OtlpSink};
 pub use tracing::TracingMiddleware;
+pub use oauth2::{OAuth2ClientConfig, OAuth2TokenProvider, OAuth2TokenInjector};
+pub use token_exchange::{TokenExchangeConfig, TokenExchanger, ExchangeTarget};
+pub use redaction::Redactor;
+pub use pii::{PiiAction, PiiKind, PiiPolicy, PiiScan, PiiScanner};
+pub use graphql::{GraphQLGuard, GraphQLLimits, GraphQLAnalysis, GraphQLError};
+pub use affinity::{AffinityKeyExtractor, SessionPins, StickyCookie};
+pub use cache::{CacheConfig, CacheLookup, ResponseCache};
+pub use concurrency::{ConcurrencyConfig, ConcurrencyLimiter, ConcurrencyPermit, ConcurrencyError};
+pub use replica::{Replica, ReplicaRing, Ownership};
+pub use state_store::{StateStore, StateStoreConfig, MemoryStateStore, RedisStateStore, EtcdStateStore};
+pub use rate_limit::RateLimiter;
+pub use etag::{ConditionalConfig, ConditionalResponder, Validators};
+pub use bandwidth::{BandwidthConfig, BandwidthLimiter, ConnectionBuckets, TokenBucket, ThrottledBody};
+pub use http3::{Http3Config, Http3Server};
+pub use warmup::{WarmupConfig, EndpointWarmer};
+pub use endpoint_stats::{EndpointStatsRecorder, EndpointRequest};
+pub use vpc_traffic::{VpcPairTraffic, VpcTrafficRecorder};
+pub use forwarded::{ForwardedConfig, ForwardedHeaders};
+pub use compression::{CompressionConfig, Encoding, ResponseCompressor};
+pub use header_rewrite::{HeaderChanges, HeaderRewrite, UpstreamHost};
+pub use timing::{ServerTiming, TimedConnector, UpstreamTiming};
+pub use debug::{DebugConfig, RequestDebugger, DEBUG_SPAN};
+pub use path_rewrite::PathRewrite;
+pub use path_normalize::normalize_path;
+pub use redirect::RouteRedirect;
+pub use direct_response::StaticResponse;
+pub use client_protocol::{ClientProtocol, ClientProtocolConfig};
+pub use fault::{FaultInjector, FaultDecision};
+pub use dns::{CachingResolver, DnsCacheConfig, DnsLookup, HickoryLookup};
+pub use trailers::{GrpcStatus, TrailersBody, UpstreamTrailers, GRPC_STATUS};
+pub use pool::PoolConfig;
+pub use tcp::{TcpProxy, TcpProxyConfig};
+pub use problem::{ErrorFormat, ProblemDetails, PROBLEM_JSON};
+pub use path_labels::{PathLabelConfig, PathLabeler};
+pub use inflight::{InflightHandle, InflightRequest, InflightSnapshot, InflightState, InflightTracker};
+pub use access_log::{AccessLogConfig, AccessLogEntry, AccessLogFormat, AccessLogUpstream, AccessLogger};
+pub use request_id::{RequestIdMiddleware, X_REQUEST_ID};
+pub use wasm::{LocalResponse, WasmMiddleware, WasmPluginConfig};
+pub use ext_authz::{AuthzDecision, ExtAuthorizer, ExtAuthzConfig};
+pub use authz_policy::{PolicyAuthorizer, PolicyAuthzConfig};
@@ -1,13 +1,19 @@
 //! Load balancing strategies for distributing traffic across endpoints
 
+use crate::metrics::MetricsCollector;
+use rand::Rng;
+use router_api::v1alpha1::vpc_route::LoadBalancingPolicy;
 use router_core::Endpoint;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Load balancing strategy
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum LoadBalancingStrategy {
     /// Round-robin: distribute requests evenly across endpoints
+    #[default]
     RoundRobin,
     /// Least connections: route to endpoint with fewest active connections
     LeastConnections,
@@ -15,18 +21,72 @@ pub enum LoadBalancingStrategy {
     SourceIpHash,
     /// Consistent hash: hash-based routing for consistent endpoint selection
     ConsistentHash,
+    /// Power of two choices: the less busy of two random ready endpoints
+    PowerOfTwoChoices,
 }
 
-impl Default for LoadBalancingStrategy {
-    fn default() -> Self {
-        LoadBalancingStrategy::RoundRobin
+impl From<&LoadBalancingPolicy> for LoadBalancingStrategy {
+    fn from(policy: &LoadBalancingPolicy) -> Self {
+        match policy {
+            LoadBalancingPolicy::RoundRobin => Self::RoundRobin,
+            LoadBalancingPolicy::LeastConnections => Self::LeastConnections,
+            LoadBalancingPolicy::SourceIp => Self::SourceIpHash,
+            LoadBalancingPolicy::ConsistentHash => Self::ConsistentHash,
+            LoadBalancingPolicy::PowerOfTwoChoices => Self::PowerOfTwoChoices,
+        }
     }
 }
 
+/// Random draws power of two choices makes looking for a ready endpoint
+/// before scanning for them instead
+const P2C_SAMPLES: usize = 8;
+
+/// Share of full traffic an endpoint gets as soon as it becomes ready
+/// during slow start
+const SLOW_START_MIN_SHARE: f64 = 0.1;
+
+/// Ready fraction below which a priority tier fails over to the next
+pub const DEFAULT_FAILOVER_THRESHOLD: f64 = 0.7;
+
 /// Load balancer for selecting endpoints based on a strategy
+///
+/// Callers mark the requests or connections they send to an endpoint with
+/// [`LoadBalancer::begin`]; the least-connections strategy picks the ready
+/// endpoint with the fewest of them, and power of two choices the less busy
+/// of two endpoints drawn at random. Weighted choices, such as a route's
+/// destinations, go through [`LoadBalancer::select_weighted`].
+///
+/// Endpoints are grouped into tiers by their priority. Only the primary
+/// tier (priority 0) is used while enough of it is ready; once its ready
+/// fraction drops below the failover threshold, the next tier is added,
+/// and so on, so standby endpoints take traffic only during an outage.
+///
+/// With [`LoadBalancer::with_metrics`], every [`LoadBalancer::begin`] is
+/// counted per endpoint, so imbalance and hot spots show up in metrics.
 pub struct LoadBalancer {
     strategy: LoadBalancingStrategy,
     round_robin_counter: Arc<AtomicUsize>,
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    /// Smooth weighted round-robin state, by item position
+    weighted: Mutex<Vec<i64>>,
+    slow_start: Option<SlowStart>,
+    failover_threshold: f64,
+    metrics: Option<EndpointMetrics>,
+}
+
+/// Where a load balancer reports per-endpoint metrics
+#[derive(Clone)]
+struct EndpointMetrics {
+    collector: Arc<MetricsCollector>,
+    service: Arc<str>,
+}
+
+/// Ramps up traffic to endpoints that just became ready
+struct SlowStart {
+    window: Duration,
+    /// When each ready endpoint became ready; unset until the first
+    /// selection, whose endpoints count as warm
+    ready_since: Mutex<Option<HashMap<String, Instant>>>,
 }
 
 impl LoadBalancer {
@@ -35,19 +95,113 @@ impl LoadBalancer {
         Self {
             strategy,
             round_robin_counter: Arc::new(AtomicUsize::new(0)),
+            active: Arc::new(Mutex::new(HashMap::new())),
+            weighted: Mutex::new(Vec::new()),
+            slow_start: None,
+            failover_threshold: DEFAULT_FAILOVER_THRESHOLD,
+            metrics: None,
+        }
+    }
+
+    /// The strategy endpoints are selected with
+    pub fn strategy(&self) -> &LoadBalancingStrategy {
+        &self.strategy
+    }
+
+    /// Fail over to the next priority tier once fewer than `threshold`
+    /// (0.0-1.0) of a tier's endpoints are ready
+    ///
+    /// At 0.0 a tier is only passed over when none of it is ready; at 1.0
+    /// any unready endpoint brings in the next tier.
+    pub fn with_failover_threshold(mut self, threshold: f64) -> Self {
+        self.failover_threshold = threshold.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Ramp traffic to newly ready endpoints up over `window`
+    ///
+    /// An endpoint that becomes ready starts with a tenth of its full share
+    /// of selections, growing linearly to the full share at the end of the
+    /// window, so a cold backend isn't sent a full share of requests before
+    /// its caches and connection pools warm up. Endpoints ready when the
+    /// load balancer first selects count as warm, and an endpoint that
+    /// stops being ready starts over. Hash-based selection ignores slow
+    /// start, since moving keys away from an endpoint would break affinity.
+    pub fn with_slow_start(mut self, window: Duration) -> Self {
+        self.slow_start = (!window.is_zero()).then(|| SlowStart { window, ready_since: Mutex::new(None) });
+        self
+    }
+
+    /// Report requests, active connections, and errors of each endpoint
+    /// of `service` to `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>, service: &str) -> Self {
+        self.metrics = Some(EndpointMetrics { collector: metrics, service: service.into() });
+        self
+    }
+
+    /// Count a request or connection to `endpoint` as active until the guard drops
+    pub fn begin(&self, endpoint: &Endpoint) -> ActiveConnection {
+        let address = endpoint_address(endpoint);
+        let active = {
+            let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+            let count = active.entry(address.clone()).or_default();
+            *count += 1;
+            *count
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.collector.record_endpoint_request(&metrics.service, &address);
+            metrics.collector.set_endpoint_active_connections(&metrics.service, &address, active);
+        }
+        ActiveConnection {
+            active: self.active.clone(),
+            address,
+            metrics: self.metrics.clone(),
         }
     }
 
+    /// Requests or connections currently active to `endpoint`
+    pub fn active_connections(&self, endpoint: &Endpoint) -> usize {
+        self.active
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&endpoint_address(endpoint))
+            .copied()
+            .unwrap_or(0)
+    }
+
     /// Select an endpoint from the list based on the configured strategy
     pub fn select<'a>(&self, endpoints: &'a [Endpoint]) -> Option<&'a Endpoint> {
+        self.select_at(endpoints, Instant::now(), &mut rand::thread_rng())
+    }
+
+    fn select_at<'a>(&self, endpoints: &'a [Endpoint], now: Instant, rng: &mut impl Rng) -> Option<&'a Endpoint> {
         if endpoints.is_empty() {
             return None;
         }
 
+        // Endpoints still warming up sit out some selections, and standby
+        // tiers until the tiers before them are degraded
+        let admitted = self.slow_start.as_ref().map(|slow_start| slow_start.admit(endpoints, now, rng));
+        let tier = self.failover_tier(endpoints);
+        let eligible = |i: usize| {
+            endpoints[i].priority <= tier
+                && match &admitted {
+                    Some(admitted) => admitted[i],
+                    None => endpoints[i].ready,
+                }
+        };
+
+        // Samples the full list, so selection doesn't grow with it
+        if self.strategy == LoadBalancingStrategy::PowerOfTwoChoices {
+            return self.select_power_of_two(endpoints, eligible, rng);
+        }
+
         // Filter to only ready endpoints
         let ready_endpoints: Vec<&'a Endpoint> = endpoints
             .iter()
-            .filter(|e| e.ready)
+            .enumerate()
+            .filter(|(i, _)| eligible(*i))
+            .map(|(_, e)| e)
             .collect();
 
         if ready_endpoints.is_empty() {
@@ -71,6 +225,7 @@ impl LoadBalancer {
                 // For now, fall back to round-robin
                 self.select_round_robin(&ready_endpoints)
             }
+            LoadBalancingStrategy::PowerOfTwoChoices => unreachable!("sampled before filtering"),
         }
     }
 
@@ -84,31 +239,143 @@ impl LoadBalancer {
         endpoints.get(current % endpoints.len()).copied()
     }
 
-    /// Select endpoint with least connections (simplified: just use first ready endpoint)
+    /// Select the endpoint with the fewest active connections
+    ///
+    /// Ties are broken round-robin so idle endpoints share the load instead
+    /// of the first one taking every request.
     fn select_least_connections<'a>(&self, endpoints: &[&'a Endpoint]) -> Option<&'a Endpoint> {
-        // Since we don't track active connections yet, just use the first ready endpoint
-        endpoints.first().copied()
-    }
-
-    /// Hash-based endpoint selection for sticky sessions
-    pub fn select_by_hash<'a>(&self, endpoints: &'a [Endpoint], hash_key: &str) -> Option<&'a Endpoint> {
         if endpoints.is_empty() {
             return None;
         }
 
-        // Filter to only ready endpoints
-        let ready_endpoints: Vec<&'a Endpoint> = endpoints
+        let start = self.round_robin_counter.fetch_add(1, Ordering::SeqCst) % endpoints.len();
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        endpoints[start..]
             .iter()
-            .filter(|e| e.ready)
-            .collect();
+            .chain(&endpoints[..start])
+            .min_by_key(|e| active.get(&endpoint_address(e)).copied().unwrap_or(0))
+            .copied()
+    }
 
-        if ready_endpoints.is_empty() {
+    /// Select the less busy of two distinct eligible endpoints drawn at random
+    ///
+    /// Gets close to least connections without looking at every endpoint:
+    /// draws from the full list skip ineligible endpoints, and only when
+    /// [`P2C_SAMPLES`] draws in a row miss does it scan for the eligible
+    /// ones. Ties go to the first endpoint drawn.
+    fn select_power_of_two<'a>(
+        &self,
+        endpoints: &'a [Endpoint],
+        eligible: impl Fn(usize) -> bool,
+        rng: &mut impl Rng,
+    ) -> Option<&'a Endpoint> {
+        let mut draw = |other: Option<usize>| {
+            (0..P2C_SAMPLES)
+                .map(|_| rng.gen_range(0..endpoints.len()))
+                .find(|&i| eligible(i) && Some(i) != other)
+        };
+        let first = draw(None);
+        let second = first.and_then(|first| draw(Some(first)));
+        let (first, second) = match (first, second) {
+            (Some(first), Some(second)) => (first, second),
+            _ => {
+                let ready: Vec<usize> = (0..endpoints.len()).filter(|&i| eligible(i)).collect();
+                match ready.len() {
+                    0 => return None,
+                    1 => return Some(&endpoints[ready[0]]),
+                    len => {
+                        let picked = rand::seq::index::sample(rng, len, 2);
+                        (ready[picked.index(0)], ready[picked.index(1)])
+                    }
+                }
+            }
+        };
+
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let load = |i: usize| active.get(&endpoint_address(&endpoints[i])).copied().unwrap_or(0);
+        Some(&endpoints[if load(second) < load(first) { second } else { first }])
+    }
+
+    /// Select one of `items` in proportion to its weight
+    ///
+    /// Uses smooth weighted round-robin: over any run of selections each
+    /// item's share follows its weight as closely as possible, and picks are
+    /// interleaved rather than bunched (weights 2 and 1 give a, b, a). Items
+    /// weighing 0 are never picked. Progress is remembered by position, so
+    /// pass the same items in the same order each time.
+    pub fn select_weighted<'a, T>(&self, items: &'a [T], weight: impl Fn(&T) -> u32) -> Option<&'a T> {
+        let weights: Vec<i64> = items.iter().map(|item| i64::from(weight(item))).collect();
+        let total: i64 = weights.iter().sum();
+        if total == 0 {
             return None;
         }
 
-        // Simple hash using string hash
-        let hash = Self::compute_hash(hash_key);
-        ready_endpoints.get(hash % ready_endpoints.len()).copied()
+        let mut current = self.weighted.lock().unwrap_or_else(|e| e.into_inner());
+        if current.len() != items.len() {
+            *current = vec![0; items.len()];
+        }
+        let mut best = None;
+        for (i, weight) in weights.iter().enumerate() {
+            if *weight == 0 {
+                current[i] = 0;
+                continue;
+            }
+            current[i] += weight;
+            if best.is_none_or(|b: usize| current[i] > current[b]) {
+                best = Some(i);
+            }
+        }
+        let best = best?;
+        current[best] -= total;
+        items.get(best)
+    }
+
+    /// Select an endpoint, preferring a session affinity key when present
+    ///
+    /// Requests with a key are consistently hashed; requests without one
+    /// use the configured strategy.
+    pub fn select_with_key<'a>(&self, endpoints: &'a [Endpoint], hash_key: Option<&str>) -> Option<&'a Endpoint> {
+        match hash_key {
+            Some(key) => self.select_by_hash(endpoints, key),
+            None => self.select(endpoints),
+        }
+    }
+
+    /// Hash-based endpoint selection for sticky sessions
+    ///
+    /// Uses rendezvous (highest random weight) hashing so that adding or
+    /// removing an endpoint only remaps the keys that hashed to it.
+    pub fn select_by_hash<'a>(&self, endpoints: &'a [Endpoint], hash_key: &str) -> Option<&'a Endpoint> {
+        let tier = self.failover_tier(endpoints);
+        endpoints
+            .iter()
+            .filter(|e| e.ready && e.priority <= tier)
+            .max_by_key(|e| Self::compute_hash(&format!("{}|{}:{}", hash_key, e.ip, e.port)))
+    }
+
+    /// Lowest priority, i.e. highest tier number, that takes traffic
+    ///
+    /// Tiers are walked from the primary one; each is passed over while
+    /// fewer than the failover threshold of its endpoints are ready.
+    fn failover_tier(&self, endpoints: &[Endpoint]) -> u32 {
+        let Some(first) = endpoints.first() else {
+            return 0;
+        };
+        if endpoints.iter().all(|e| e.priority == first.priority) {
+            return first.priority;
+        }
+
+        let mut tiers: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+        for endpoint in endpoints {
+            let (ready, total) = tiers.entry(endpoint.priority).or_default();
+            *ready += usize::from(endpoint.ready);
+            *total += 1;
+        }
+        let last = *tiers.keys().next_back().expect("endpoints are not empty");
+        tiers
+            .into_iter()
+            .find(|(_, (ready, total))| *ready > 0 && *ready as f64 >= self.failover_threshold * *total as f64)
+            .map_or(last, |(tier, _)| tier)
     }
 
     /// Compute hash for a string
@@ -125,3 +392,322 @@ impl LoadBalancer {
         hash
     }
 }
+
+impl SlowStart {
+    /// Which endpoints may be selected at `now`
+    ///
+    /// Records when endpoints become ready, then admits each warming
+    /// endpoint with a probability that grows over the window. When every
+    /// ready endpoint is left out, all of them are admitted.
+    fn admit(&self, endpoints: &[Endpoint], now: Instant, rng: &mut impl Rng) -> Vec<bool> {
+        let mut ready_since = self.ready_since.lock().unwrap_or_else(|e| e.into_inner());
+        let warm_start = now.checked_sub(self.window).unwrap_or(now);
+        let first = ready_since.is_none();
+        let ready_since = ready_since.get_or_insert_with(HashMap::new);
+        let ready: HashSet<String> = endpoints.iter().filter(|e| e.ready).map(endpoint_address).collect();
+        ready_since.retain(|address, _| ready.contains(address));
+
+        let admitted: Vec<bool> = endpoints
+            .iter()
+            .map(|endpoint| {
+                if !endpoint.ready {
+                    return false;
+                }
+                let since = *ready_since
+                    .entry(endpoint_address(endpoint))
+                    .or_insert(if first { warm_start } else { now });
+                let ramp = now.saturating_duration_since(since).as_secs_f64() / self.window.as_secs_f64();
+                ramp >= 1.0 || rng.gen_bool(ramp.max(SLOW_START_MIN_SHARE))
+            })
+            .collect();
+        if admitted.contains(&true) {
+            admitted
+        } else {
+            endpoints.iter().map(|e| e.ready).collect()
+        }
+    }
+}
+
+/// An active request or connection counted by a [`LoadBalancer`]
+pub struct ActiveConnection {
+    active: Arc<Mutex<HashMap<String, usize>>>,
+    address: String,
+    metrics: Option<EndpointMetrics>,
+}
+
+impl ActiveConnection {
+    /// Count the request or connection as failed against its endpoint
+    pub fn fail(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.collector.record_endpoint_error(&metrics.service, &self.address);
+        }
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        let remaining = {
+            let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(count) = active.get_mut(&self.address) else {
+                return;
+            };
+            *count -= 1;
+            let remaining = *count;
+            if remaining == 0 {
+                active.remove(&self.address);
+            }
+            remaining
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.collector.set_endpoint_active_connections(&metrics.service, &self.address, remaining);
+        }
+    }
+}
+
+fn endpoint_address(endpoint: &Endpoint) -> String {
+    format!("{}:{}", endpoint.ip, endpoint.port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(count: u8) -> Vec<Endpoint> {
+        (1..=count)
+            .map(|i| Endpoint {
+                ip: format!("10.0.0.{}", i),
+                port: 8080,
+                ready: true,
+                priority: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_by_hash_is_sticky() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::ConsistentHash);
+        let endpoints = endpoints(5);
+
+        let first = lb.select_by_hash(&endpoints, "session-1").unwrap();
+        for _ in 0..10 {
+            assert_eq!(lb.select_by_hash(&endpoints, "session-1").unwrap().ip, first.ip);
+        }
+    }
+
+    #[test]
+    fn test_select_by_hash_minimal_remapping() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::ConsistentHash);
+        let mut endpoints = endpoints(5);
+        let keys: Vec<String> = (0..200).map(|i| format!("user-{}", i)).collect();
+
+        let before: Vec<String> = keys
+            .iter()
+            .map(|k| lb.select_by_hash(&endpoints, k).unwrap().ip.clone())
+            .collect();
+
+        // Taking one endpoint out only moves the keys that were on it
+        endpoints[2].ready = false;
+        for (key, previous) in keys.iter().zip(&before) {
+            let now = &lb.select_by_hash(&endpoints, key).unwrap().ip;
+            if previous != "10.0.0.3" {
+                assert_eq!(now, previous);
+            } else {
+                assert_ne!(now, previous);
+            }
+        }
+    }
+
+    #[test]
+    fn test_select_weighted_follows_weights() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let destinations = [("stable", 90), ("canary", 10)];
+
+        let picks: Vec<&str> = (0..100)
+            .map(|_| lb.select_weighted(&destinations, |d| d.1).unwrap().0)
+            .collect();
+        assert_eq!(picks.iter().filter(|d| **d == "canary").count(), 10);
+        // Canary requests are spread out, not sent in one burst
+        assert_ne!(picks[..10], ["stable"; 10]);
+        assert!(picks.windows(2).all(|w| w != ["canary", "canary"]));
+
+        let interleaved = [("a", 2), ("b", 1)];
+        let picks: Vec<&str> = (0..3).map(|_| lb.select_weighted(&interleaved, |d| d.1).unwrap().0).collect();
+        assert_eq!(picks, ["a", "b", "a"]);
+
+        assert!(lb.select_weighted(&[("off", 0)], |d| d.1).is_none());
+        let drained = [("a", 0), ("b", 50)];
+        assert!((0..5).all(|_| lb.select_weighted(&drained, |d| d.1).unwrap().0 == "b"));
+    }
+
+    #[test]
+    fn test_least_connections_picks_least_busy() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastConnections);
+        let endpoints = endpoints(3);
+
+        let _a = lb.begin(&endpoints[0]);
+        let _b1 = lb.begin(&endpoints[1]);
+        let b2 = lb.begin(&endpoints[1]);
+        for _ in 0..5 {
+            assert_eq!(lb.select(&endpoints).unwrap().ip, "10.0.0.3");
+        }
+
+        let _c1 = lb.begin(&endpoints[2]);
+        let _c2 = lb.begin(&endpoints[2]);
+        assert_eq!(lb.select(&endpoints).unwrap().ip, "10.0.0.1");
+
+        drop(b2);
+        assert_eq!(lb.active_connections(&endpoints[1]), 1);
+        let _a2 = lb.begin(&endpoints[0]);
+        assert_eq!(lb.select(&endpoints).unwrap().ip, "10.0.0.2");
+    }
+
+    #[test]
+    fn test_least_connections_spreads_ties() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::LeastConnections);
+        let mut endpoints = endpoints(3);
+        endpoints[0].ready = false;
+
+        let picked: std::collections::HashSet<String> =
+            (0..4).map(|_| lb.select(&endpoints).unwrap().ip.clone()).collect();
+        assert_eq!(picked.len(), 2);
+        assert!(!picked.contains("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_power_of_two_avoids_busiest() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::PowerOfTwoChoices);
+        let mut rng = StdRng::seed_from_u64(7);
+        let endpoints = endpoints(5);
+        let _busy: Vec<_> = (0..3).map(|_| lb.begin(&endpoints[2])).collect();
+
+        let mut picked = std::collections::HashSet::new();
+        for _ in 0..200 {
+            picked.insert(lb.select_power_of_two(&endpoints, |i| endpoints[i].ready, &mut rng).unwrap().ip.clone());
+        }
+        assert_eq!(picked.len(), 4);
+        assert!(!picked.contains("10.0.0.3"));
+    }
+
+    #[test]
+    fn test_power_of_two_with_few_ready() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::PowerOfTwoChoices);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut endpoints = endpoints(100);
+        for endpoint in &mut endpoints {
+            endpoint.ready = false;
+        }
+        assert!(lb.select(&endpoints).is_none());
+
+        endpoints[40].ready = true;
+        assert_eq!(lb.select(&endpoints).unwrap().ip, "10.0.0.41");
+
+        // Mostly unready: the two ready endpoints are still compared
+        endpoints[90].ready = true;
+        let _busy = lb.begin(&endpoints[40]);
+        for _ in 0..20 {
+            assert_eq!(lb.select_power_of_two(&endpoints, |i| endpoints[i].ready, &mut rng).unwrap().ip, "10.0.0.91");
+        }
+    }
+
+    #[test]
+    fn test_slow_start_ramps_new_endpoints() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin).with_slow_start(Duration::from_secs(60));
+        let mut rng = StdRng::seed_from_u64(7);
+        let start = Instant::now();
+        let mut endpoints = endpoints(3);
+        endpoints[2].ready = false;
+        let picks = |lb: &LoadBalancer, endpoints: &[Endpoint], at: Instant, rng: &mut StdRng| {
+            (0..1000).filter(|_| lb.select_at(endpoints, at, rng).unwrap().ip == "10.0.0.3").count()
+        };
+
+        // Endpoints ready from the start are warm
+        assert_eq!(picks(&lb, &endpoints, start, &mut rng), 0);
+
+        endpoints[2].ready = true;
+        let cold = picks(&lb, &endpoints, start, &mut rng);
+        let halfway = picks(&lb, &endpoints, start + Duration::from_secs(30), &mut rng);
+        let warm = picks(&lb, &endpoints, start + Duration::from_secs(60), &mut rng);
+        assert!(cold < 100, "cold endpoint got {} of 1000", cold);
+        assert!(cold < halfway && halfway < warm, "{} {} {}", cold, halfway, warm);
+        assert!((300..=340).contains(&warm), "warm endpoint got {} of 1000", warm);
+
+        // Becoming unready starts the ramp over
+        endpoints[2].ready = false;
+        lb.select_at(&endpoints, start + Duration::from_secs(61), &mut rng);
+        endpoints[2].ready = true;
+        assert!(picks(&lb, &endpoints, start + Duration::from_secs(61), &mut rng) < 100);
+
+        // A lone cold endpoint still takes everything
+        let lone = &endpoints[2..];
+        assert_eq!(lb.select_at(lone, start + Duration::from_secs(61), &mut rng).unwrap().ip, "10.0.0.3");
+    }
+
+    #[test]
+    fn test_failover_to_standby_tier() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin).with_failover_threshold(0.5);
+        let mut endpoints = endpoints(6);
+        for endpoint in &mut endpoints[4..] {
+            endpoint.priority = 1;
+        }
+        let picked = |lb: &LoadBalancer, endpoints: &[Endpoint]| -> HashSet<String> {
+            (0..24).map(|_| lb.select(endpoints).unwrap().ip.clone()).collect()
+        };
+
+        // Standbys stay idle while the primary tier is healthy enough
+        endpoints[0].ready = false;
+        endpoints[1].ready = false;
+        assert!(picked(&lb, &endpoints).iter().all(|ip| ip == "10.0.0.3" || ip == "10.0.0.4"));
+
+        // Below the threshold the standby tier joins what's left of the primary one
+        endpoints[2].ready = false;
+        assert_eq!(picked(&lb, &endpoints).len(), 3);
+
+        endpoints[3].ready = false;
+        assert!(picked(&lb, &endpoints).iter().all(|ip| ip == "10.0.0.5" || ip == "10.0.0.6"));
+
+        // Nothing ready anywhere
+        endpoints[4].ready = false;
+        endpoints[5].ready = false;
+        assert!(lb.select(&endpoints).is_none());
+    }
+
+    #[test]
+    fn test_select_with_key_falls_back_without_key() {
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin);
+        let endpoints = endpoints(2);
+
+        let a = lb.select_with_key(&endpoints, None).unwrap().ip.clone();
+        let b = lb.select_with_key(&endpoints, None).unwrap().ip.clone();
+        assert_ne!(a, b);
+        assert!(lb.select_with_key(&[], Some("key")).is_none());
+    }
+
+    #[test]
+    fn test_endpoint_metrics() {
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let lb = LoadBalancer::new(LoadBalancingStrategy::RoundRobin).with_metrics(metrics.clone(), "default/db");
+        let endpoints = endpoints(2);
+        let labels = ["default/db", "10.0.0.1:8080"];
+
+        let first = lb.begin(&endpoints[0]);
+        let second = lb.begin(&endpoints[0]);
+        assert_eq!(metrics.endpoint_requests_total.with_label_values(&labels).get(), 2.0);
+        assert_eq!(metrics.endpoint_active_connections.with_label_values(&labels).get(), 2);
+
+        second.fail();
+        drop(second);
+        assert_eq!(metrics.endpoint_errors_total.with_label_values(&labels).get(), 1.0);
+        assert_eq!(metrics.endpoint_active_connections.with_label_values(&labels).get(), 1);
+        drop(first);
+        assert_eq!(metrics.endpoint_active_connections.with_label_values(&labels).get(), 0);
+    }
+}
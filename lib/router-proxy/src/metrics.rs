@@ -1,13 +1,20 @@
 //! Prometheus metrics middleware for observability
+//!
+//! Measurements can also be forwarded to a [`MetricsSink`] backend.
 
 use prometheus::{
-    Counter, CounterVec, HistogramVec, Registry, Encoder, TextEncoder,
+    Counter, CounterVec, HistogramVec, IntGaugeVec, Registry, Encoder, TextEncoder,
     Opts,
 };
 use std::sync::Arc;
 use anyhow::Result;
 use tracing::debug;
+use crate::health_check::HealthStatus;
+use crate::metrics_sink::{Labels, MetricsSink};
 use crate::middleware::{Middleware, MiddlewareContext};
+use crate::path_labels::PathLabeler;
+use crate::pii::{PiiAction, PiiScan};
+use router_core::BuildInfo;
 
 /// Prometheus metrics collector for HTTP requests
 pub struct MetricsCollector {
@@ -23,8 +30,70 @@ pub struct MetricsCollector {
     pub http_request_size_bytes: HistogramVec,
     /// Response body size in bytes
     pub http_response_size_bytes: HistogramVec,
+    /// PII detections in response bodies by kind and action taken
+    pub pii_detections_total: CounterVec,
+    /// GraphQL requests by operation name, type, and outcome
+    pub graphql_requests_total: CounterVec,
+    /// Endpoint health check results by service and status
+    pub health_checks_total: CounterVec,
+    /// Whether a service is in planned maintenance (1) or not (0)
+    pub service_maintenance: IntGaugeVec,
+    /// Upstream request retries by upstream and reason
+    pub upstream_retries_total: CounterVec,
+    /// Requests rejected because their body exceeded the size limit
+    pub request_body_too_large_total: Counter,
+    /// Responses over their route's size limit, by action taken
+    pub response_body_too_large_total: CounterVec,
+    /// Response bytes saved by compression, by encoding
+    pub compression_bytes_saved_total: CounterVec,
+    /// Faults injected into requests, by kind
+    pub faults_injected_total: CounterVec,
+    /// Backend hostname resolutions by result
+    pub dns_lookups_total: CounterVec,
+    /// gRPC responses by upstream and status
+    pub grpc_responses_total: CounterVec,
+    /// Upstream connections opened
+    pub upstream_connections_opened_total: CounterVec,
+    /// Requests that waited for an upstream pool slot
+    pub upstream_pool_waits_total: CounterVec,
+    /// Connection slots in use per pooled upstream
+    pub upstream_pool_in_use: IntGaugeVec,
+    /// TCP proxy connections by outcome
+    pub tcp_connections_total: CounterVec,
+    /// Bytes streamed by TCP proxies
+    pub tcp_bytes_total: CounterVec,
+    /// Failed upstream requests by failure kind
+    pub upstream_errors_total: CounterVec,
+    /// Response cache lookups by result
+    pub cache_lookups_total: CounterVec,
+    /// Requests by the VPC they were sent from
+    pub source_vpc_requests_total: CounterVec,
+    /// Forwarded requests by source and destination VPC
+    pub vpc_pair_requests_total: CounterVec,
+    /// Bytes forwarded by source and destination VPC
+    pub vpc_pair_bytes_total: CounterVec,
+    /// Requests and connections load balancers sent to each endpoint
+    pub endpoint_requests_total: CounterVec,
+    /// Requests and connections in progress to each endpoint
+    pub endpoint_active_connections: IntGaugeVec,
+    /// Requests and connections to each endpoint that failed
+    pub endpoint_errors_total: CounterVec,
+    /// Times each endpoint was taken out of rotation by health checks
+    pub endpoint_ejections_total: CounterVec,
+    /// Kubernetes API requests by verb, resource, and response code
+    pub kube_api_requests_total: CounterVec,
+    /// Kubernetes API request latency by verb
+    pub kube_api_request_duration_seconds: HistogramVec,
+    /// Kubernetes API requests in flight by verb
+    pub kube_api_requests_in_flight: IntGaugeVec,
+    /// Time Kubernetes API requests waited for the client rate limit
+    pub kube_api_rate_limit_wait_seconds_total: Counter,
+    /// Always 1, labeled with the version and build of the binary
+    pub build_info: IntGaugeVec,
     /// Prometheus registry for metrics
     pub registry: Arc<Registry>,
+    /// Backend that also receives every measurement
+    sink: Option<Arc<dyn MetricsSink>>,
 }
 
 impl MetricsCollector {
@@ -75,6 +144,148 @@ impl MetricsCollector {
             &["status"],
         )?;
 
+        let pii_detections_total = CounterVec::new(
+            Opts::new("pii_detections_total", "PII detections in response bodies"),
+            &["kind", "action"],
+        )?;
+
+        let graphql_requests_total = CounterVec::new(
+            Opts::new("graphql_requests_total", "GraphQL requests by operation"),
+            &["operation", "type", "outcome"],
+        )?;
+
+        let health_checks_total = CounterVec::new(
+            Opts::new("health_checks_total", "Endpoint health check results"),
+            &["service", "status"],
+        )?;
+
+        let service_maintenance = IntGaugeVec::new(
+            Opts::new("service_maintenance", "Whether a service is in planned maintenance"),
+            &["service"],
+        )?;
+
+        let upstream_retries_total = CounterVec::new(
+            Opts::new("upstream_retries_total", "Upstream request retries"),
+            &["upstream", "reason"],
+        )?;
+
+        let request_body_too_large_total = Counter::new(
+            "request_body_too_large_total",
+            "Requests rejected for exceeding the body size limit",
+        )?;
+
+        let response_body_too_large_total = CounterVec::new(
+            Opts::new("response_body_too_large_total", "Responses over their route's body size limit"),
+            &["action"],
+        )?;
+
+        let compression_bytes_saved_total = CounterVec::new(
+            Opts::new("compression_bytes_saved_total", "Response bytes saved by compression"),
+            &["encoding"],
+        )?;
+
+        let faults_injected_total = CounterVec::new(
+            Opts::new("faults_injected_total", "Faults injected for resilience testing"),
+            &["kind"],
+        )?;
+
+        let dns_lookups_total = CounterVec::new(
+            Opts::new("dns_lookups_total", "Backend hostname resolutions by cache result"),
+            &["result"],
+        )?;
+
+        let grpc_responses_total = CounterVec::new(
+            Opts::new("grpc_responses_total", "gRPC responses from upstreams by grpc-status"),
+            &["upstream", "grpc_status"],
+        )?;
+
+        let upstream_connections_opened_total = CounterVec::new(
+            Opts::new("upstream_connections_opened_total", "Connections opened to upstreams"),
+            &["upstream"],
+        )?;
+
+        let upstream_pool_waits_total = CounterVec::new(
+            Opts::new("upstream_pool_waits_total", "Requests that waited for a free upstream connection slot"),
+            &["upstream"],
+        )?;
+
+        let upstream_pool_in_use = IntGaugeVec::new(
+            Opts::new("upstream_pool_in_use", "Connection slots in use for upstreams with a connection limit"),
+            &["upstream"],
+        )?;
+
+        let tcp_connections_total = CounterVec::new(
+            Opts::new("tcp_connections_total", "Connections accepted by TCP proxy listeners"),
+            &["service", "result"],
+        )?;
+
+        let tcp_bytes_total = CounterVec::new(
+            Opts::new("tcp_bytes_total", "Bytes streamed through TCP proxy listeners"),
+            &["service", "direction"],
+        )?;
+
+        let kube_api_requests_total = CounterVec::new(
+            Opts::new("kube_api_requests_total", "Kubernetes API requests by response code"),
+            &["verb", "resource", "code"],
+        )?;
+        let kube_api_request_duration_seconds = HistogramVec::new(
+            Opts::new("kube_api_request_duration_seconds", "Kubernetes API request latency in seconds").into(),
+            &["verb"],
+        )?;
+        let kube_api_requests_in_flight = IntGaugeVec::new(
+            Opts::new("kube_api_requests_in_flight", "Kubernetes API requests in flight"),
+            &["verb"],
+        )?;
+        let kube_api_rate_limit_wait_seconds_total = Counter::new(
+            "kube_api_rate_limit_wait_seconds_total",
+            "Time Kubernetes API requests waited for the client rate limit",
+        )?;
+
+        let upstream_errors_total = CounterVec::new(
+            Opts::new("upstream_errors_total", "Upstream requests that failed after any retries"),
+            &["upstream", "kind"],
+        )?;
+
+        let cache_lookups_total = CounterVec::new(
+            Opts::new("cache_lookups_total", "Response cache lookups by result"),
+            &["result"],
+        )?;
+
+        let source_vpc_requests_total = CounterVec::new(
+            Opts::new("source_vpc_requests_total", "HTTP requests by source VPC"),
+            &["source_vpc"],
+        )?;
+
+        let vpc_pair_requests_total = CounterVec::new(
+            Opts::new("vpc_pair_requests_total", "Forwarded requests by source and destination VPC"),
+            &["source_vpc", "destination_vpc"],
+        )?;
+
+        let vpc_pair_bytes_total = CounterVec::new(
+            Opts::new("vpc_pair_bytes_total", "Bytes forwarded between source and destination VPCs"),
+            &["source_vpc", "destination_vpc", "direction"],
+        )?;
+
+        let endpoint_requests_total = CounterVec::new(
+            Opts::new("endpoint_requests_total", "Requests and connections load balancers sent to each endpoint"),
+            &["service", "endpoint"],
+        )?;
+
+        let endpoint_active_connections = IntGaugeVec::new(
+            Opts::new("endpoint_active_connections", "Requests and connections in progress to each endpoint"),
+            &["service", "endpoint"],
+        )?;
+
+        let endpoint_errors_total = CounterVec::new(
+            Opts::new("endpoint_errors_total", "Requests and connections to each endpoint that failed"),
+            &["service", "endpoint"],
+        )?;
+
+        let endpoint_ejections_total = CounterVec::new(
+            Opts::new("endpoint_ejections_total", "Times each endpoint was taken out of rotation by health checks"),
+            &["service", "endpoint"],
+        )?;
+
         // Register metrics
         registry.register(Box::new(http_requests_total.clone()))?;
         registry.register(Box::new(http_request_duration_seconds.clone()))?;
@@ -82,6 +293,41 @@ impl MetricsCollector {
         registry.register(Box::new(http_errors_total.clone()))?;
         registry.register(Box::new(http_request_size_bytes.clone()))?;
         registry.register(Box::new(http_response_size_bytes.clone()))?;
+        registry.register(Box::new(pii_detections_total.clone()))?;
+        registry.register(Box::new(graphql_requests_total.clone()))?;
+        registry.register(Box::new(health_checks_total.clone()))?;
+        registry.register(Box::new(service_maintenance.clone()))?;
+        registry.register(Box::new(upstream_retries_total.clone()))?;
+        registry.register(Box::new(request_body_too_large_total.clone()))?;
+        registry.register(Box::new(response_body_too_large_total.clone()))?;
+        registry.register(Box::new(compression_bytes_saved_total.clone()))?;
+        registry.register(Box::new(faults_injected_total.clone()))?;
+        registry.register(Box::new(dns_lookups_total.clone()))?;
+        registry.register(Box::new(grpc_responses_total.clone()))?;
+        registry.register(Box::new(upstream_connections_opened_total.clone()))?;
+        registry.register(Box::new(upstream_pool_waits_total.clone()))?;
+        registry.register(Box::new(upstream_pool_in_use.clone()))?;
+        registry.register(Box::new(kube_api_requests_total.clone()))?;
+        registry.register(Box::new(kube_api_request_duration_seconds.clone()))?;
+        registry.register(Box::new(kube_api_requests_in_flight.clone()))?;
+        registry.register(Box::new(kube_api_rate_limit_wait_seconds_total.clone()))?;
+        registry.register(Box::new(tcp_connections_total.clone()))?;
+        registry.register(Box::new(tcp_bytes_total.clone()))?;
+        registry.register(Box::new(upstream_errors_total.clone()))?;
+        registry.register(Box::new(cache_lookups_total.clone()))?;
+        registry.register(Box::new(source_vpc_requests_total.clone()))?;
+        registry.register(Box::new(vpc_pair_requests_total.clone()))?;
+        registry.register(Box::new(vpc_pair_bytes_total.clone()))?;
+        registry.register(Box::new(endpoint_requests_total.clone()))?;
+        registry.register(Box::new(endpoint_active_connections.clone()))?;
+        registry.register(Box::new(endpoint_errors_total.clone()))?;
+        registry.register(Box::new(endpoint_ejections_total.clone()))?;
+
+        let build_info = IntGaugeVec::new(
+            Opts::new("build_info", "Version and build of the binary"),
+            &["version", "git_sha", "rustc", "build_date", "features"],
+        )?;
+        registry.register(Box::new(build_info.clone()))?;
 
         Ok(Self {
             http_requests_total,
@@ -90,10 +336,68 @@ impl MetricsCollector {
             http_errors_total,
             http_request_size_bytes,
             http_response_size_bytes,
+            pii_detections_total,
+            graphql_requests_total,
+            health_checks_total,
+            service_maintenance,
+            upstream_retries_total,
+            request_body_too_large_total,
+            response_body_too_large_total,
+            compression_bytes_saved_total,
+            faults_injected_total,
+            dns_lookups_total,
+            grpc_responses_total,
+            upstream_connections_opened_total,
+            upstream_pool_waits_total,
+            upstream_pool_in_use,
+            kube_api_requests_total,
+            kube_api_request_duration_seconds,
+            kube_api_requests_in_flight,
+            kube_api_rate_limit_wait_seconds_total,
+            tcp_connections_total,
+            tcp_bytes_total,
+            upstream_errors_total,
+            cache_lookups_total,
+            source_vpc_requests_total,
+            vpc_pair_requests_total,
+            vpc_pair_bytes_total,
+            endpoint_requests_total,
+            endpoint_active_connections,
+            endpoint_errors_total,
+            endpoint_ejections_total,
+            build_info,
             registry,
+            sink: None,
         })
     }
 
+    /// Also send every measurement to `sink`
+    pub fn with_sink(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Forward a counter increment to the sink
+    pub(crate) fn sink_counter(&self, name: &str, labels: &Labels, value: f64) {
+        if let Some(sink) = &self.sink {
+            sink.counter(name, labels, value);
+        }
+    }
+
+    /// Forward a gauge value to the sink
+    pub(crate) fn sink_gauge(&self, name: &str, labels: &Labels, value: f64) {
+        if let Some(sink) = &self.sink {
+            sink.gauge(name, labels, value);
+        }
+    }
+
+    /// Forward a histogram observation to the sink
+    pub(crate) fn sink_histogram(&self, name: &str, labels: &Labels, value: f64) {
+        if let Some(sink) = &self.sink {
+            sink.histogram(name, labels, value);
+        }
+    }
+
     /// Gather all metrics in Prometheus text format
     pub fn gather(&self) -> Result<String> {
         let encoder = TextEncoder::new();
@@ -102,6 +406,220 @@ impl MetricsCollector {
         encoder.encode(&metric_families, &mut buffer)?;
         Ok(String::from_utf8(buffer)?)
     }
+
+    /// Export the binary's build information
+    pub fn set_build_info(&self, info: &BuildInfo) {
+        let labels = info.labels();
+        self.build_info
+            .with_label_values(&labels.map(|(_, value)| value))
+            .set(1);
+        self.sink_gauge("build_info", &labels, 1.0);
+    }
+
+    /// Record PII detections from a response scan
+    pub fn record_pii_detections(&self, scan: &PiiScan, action: PiiAction) {
+        for (kind, count) in &scan.detections {
+            if *count > 0 {
+                self.pii_detections_total
+                    .with_label_values(&[kind.as_str(), action.as_str()])
+                    .inc_by(*count as f64);
+                self.sink_counter(
+                    "pii_detections_total",
+                    &[("kind", kind.as_str()), ("action", action.as_str())],
+                    *count as f64,
+                );
+            }
+        }
+    }
+
+    /// Record a GraphQL request outcome ("allowed" or a rejection reason)
+    pub fn record_graphql_request(&self, operation: &str, operation_type: &str, outcome: &str) {
+        self.graphql_requests_total
+            .with_label_values(&[operation, operation_type, outcome])
+            .inc();
+        self.sink_counter(
+            "graphql_requests_total",
+            &[("operation", operation), ("type", operation_type), ("outcome", outcome)],
+            1.0,
+        );
+    }
+
+    /// Record an endpoint health check result
+    ///
+    /// Endpoints skipped for planned maintenance count as "maintenance",
+    /// not "unhealthy", so outage alerts stay quiet.
+    pub fn record_health_check(&self, service: &str, status: HealthStatus) {
+        self.health_checks_total
+            .with_label_values(&[service, status.as_str()])
+            .inc();
+        self.sink_counter("health_checks_total", &[("service", service), ("status", status.as_str())], 1.0);
+    }
+
+    /// Record whether a service is in planned maintenance
+    pub fn set_service_maintenance(&self, service: &str, in_maintenance: bool) {
+        self.service_maintenance
+            .with_label_values(&[service])
+            .set(in_maintenance as i64);
+        self.sink_gauge("service_maintenance", &[("service", service)], in_maintenance as i64 as f64);
+    }
+
+    /// Record a retry of an upstream request
+    ///
+    /// `reason` is "connect_error", "request_error", "timeout", `grpc_<status>`,
+    /// or the retried status code.
+    pub fn record_retry(&self, upstream: &str, reason: &str) {
+        self.upstream_retries_total
+            .with_label_values(&[upstream, reason])
+            .inc();
+        self.sink_counter("upstream_retries_total", &[("upstream", upstream), ("reason", reason)], 1.0);
+    }
+
+    /// Record a request rejected for an oversized body
+    pub fn record_request_body_too_large(&self) {
+        self.request_body_too_large_total.inc();
+        self.sink_counter("request_body_too_large_total", &[], 1.0);
+    }
+
+    /// Record a response over its size limit, aborted or truncated
+    pub fn record_response_body_too_large(&self, action: &str) {
+        self.response_body_too_large_total.with_label_values(&[action]).inc();
+        self.sink_counter("response_body_too_large_total", &[("action", action)], 1.0);
+    }
+
+    /// Record a compressed response
+    pub fn record_compression(&self, encoding: &str, original_bytes: usize, compressed_bytes: usize) {
+        let saved = original_bytes.saturating_sub(compressed_bytes) as f64;
+        self.compression_bytes_saved_total
+            .with_label_values(&[encoding])
+            .inc_by(saved);
+        self.sink_counter("compression_bytes_saved_total", &[("encoding", encoding)], saved);
+    }
+
+    /// Record an injected fault ("delay" or "abort")
+    pub fn record_fault(&self, kind: &str) {
+        self.faults_injected_total.with_label_values(&[kind]).inc();
+        self.sink_counter("faults_injected_total", &[("kind", kind)], 1.0);
+    }
+
+    /// Record a backend hostname resolution ("hit", "miss", "negative_hit", or "error")
+    pub fn record_dns_lookup(&self, result: &str) {
+        self.dns_lookups_total.with_label_values(&[result]).inc();
+        self.sink_counter("dns_lookups_total", &[("result", result)], 1.0);
+    }
+
+    /// Record a gRPC response by its status name
+    pub fn record_grpc_response(&self, upstream: &str, status: &str) {
+        self.grpc_responses_total.with_label_values(&[upstream, status]).inc();
+        self.sink_counter("grpc_responses_total", &[("upstream", upstream), ("grpc_status", status)], 1.0);
+    }
+
+    /// Record a new connection to an upstream
+    pub fn record_upstream_connection(&self, upstream: &str) {
+        self.upstream_connections_opened_total.with_label_values(&[upstream]).inc();
+        self.sink_counter("upstream_connections_opened_total", &[("upstream", upstream)], 1.0);
+    }
+
+    /// Record a request waiting for a connection slot to an upstream
+    pub fn record_upstream_pool_wait(&self, upstream: &str) {
+        self.upstream_pool_waits_total.with_label_values(&[upstream]).inc();
+        self.sink_counter("upstream_pool_waits_total", &[("upstream", upstream)], 1.0);
+    }
+
+    /// Set the connection slots in use for an upstream
+    pub fn set_upstream_pool_in_use(&self, upstream: &str, in_use: usize) {
+        self.upstream_pool_in_use.with_label_values(&[upstream]).set(in_use as i64);
+        self.sink_gauge("upstream_pool_in_use", &[("upstream", upstream)], in_use as f64);
+    }
+
+    /// Record a TCP proxy connection ("proxied", "no_endpoint", or "connect_error")
+    pub fn record_tcp_connection(&self, service: &str, result: &str) {
+        self.tcp_connections_total.with_label_values(&[service, result]).inc();
+        self.sink_counter("tcp_connections_total", &[("service", service), ("result", result)], 1.0);
+    }
+
+    /// Record bytes streamed through a TCP proxy ("upstream" or "downstream")
+    pub fn record_tcp_bytes(&self, service: &str, direction: &str, bytes: u64) {
+        self.tcp_bytes_total.with_label_values(&[service, direction]).inc_by(bytes as f64);
+        self.sink_counter("tcp_bytes_total", &[("service", service), ("direction", direction)], bytes as f64);
+    }
+
+    /// Record a request forwarded from the `source` to the `destination` VPC
+    ///
+    /// Request bytes count as "upstream", response bytes as "downstream".
+    pub fn record_vpc_pair(&self, source: &str, destination: &str, bytes_sent: u64, bytes_received: u64) {
+        let pair = [("source_vpc", source), ("destination_vpc", destination)];
+        self.vpc_pair_requests_total.with_label_values(&[source, destination]).inc();
+        self.sink_counter("vpc_pair_requests_total", &pair, 1.0);
+        for (direction, bytes) in [("upstream", bytes_sent), ("downstream", bytes_received)] {
+            self.vpc_pair_bytes_total
+                .with_label_values(&[source, destination, direction])
+                .inc_by(bytes as f64);
+            let labels = [pair[0], pair[1], ("direction", direction)];
+            self.sink_counter("vpc_pair_bytes_total", &labels, bytes as f64);
+        }
+    }
+
+    /// Record a request or connection a load balancer sent to `endpoint` (ip:port)
+    pub fn record_endpoint_request(&self, service: &str, endpoint: &str) {
+        self.endpoint_requests_total.with_label_values(&[service, endpoint]).inc();
+        self.sink_counter("endpoint_requests_total", &[("service", service), ("endpoint", endpoint)], 1.0);
+    }
+
+    /// Set the requests and connections in progress to `endpoint`
+    pub fn set_endpoint_active_connections(&self, service: &str, endpoint: &str, active: usize) {
+        self.endpoint_active_connections.with_label_values(&[service, endpoint]).set(active as i64);
+        self.sink_gauge("endpoint_active_connections", &[("service", service), ("endpoint", endpoint)], active as f64);
+    }
+
+    /// Record a failed request or connection to `endpoint`
+    pub fn record_endpoint_error(&self, service: &str, endpoint: &str) {
+        self.endpoint_errors_total.with_label_values(&[service, endpoint]).inc();
+        self.sink_counter("endpoint_errors_total", &[("service", service), ("endpoint", endpoint)], 1.0);
+    }
+
+    /// Record `endpoint` being taken out of rotation
+    pub fn record_endpoint_ejection(&self, service: &str, endpoint: &str) {
+        self.endpoint_ejections_total.with_label_values(&[service, endpoint]).inc();
+        self.sink_counter("endpoint_ejections_total", &[("service", service), ("endpoint", endpoint)], 1.0);
+    }
+
+    /// Record an upstream request that failed after any retries
+    pub fn record_upstream_error(&self, upstream: &str, kind: &str) {
+        self.upstream_errors_total.with_label_values(&[upstream, kind]).inc();
+        self.sink_counter("upstream_errors_total", &[("upstream", upstream), ("kind", kind)], 1.0);
+    }
+
+    /// Count a response cache lookup
+    ///
+    /// Results are "hit", "miss", "stale" (sent for revalidation),
+    /// "revalidated" (refreshed by a 304), and "bypass".
+    pub fn record_cache_lookup(&self, result: &str) {
+        self.cache_lookups_total.with_label_values(&[result]).inc();
+        self.sink_counter("cache_lookups_total", &[("result", result)], 1.0);
+    }
+}
+
+#[cfg(feature = "kube")]
+impl router_core::ApiObserver for MetricsCollector {
+    fn request_started(&self, verb: &str, _resource: &str) {
+        self.kube_api_requests_in_flight.with_label_values(&[verb]).inc();
+    }
+
+    fn request_finished(&self, verb: &str, resource: &str, code: &str, duration: std::time::Duration) {
+        self.kube_api_requests_in_flight.with_label_values(&[verb]).dec();
+        self.kube_api_requests_total.with_label_values(&[verb, resource, code]).inc();
+        self.kube_api_request_duration_seconds
+            .with_label_values(&[verb])
+            .observe(duration.as_secs_f64());
+        let labels = [("verb", verb), ("resource", resource), ("code", code)];
+        self.sink_counter("kube_api_requests_total", &labels, 1.0);
+        self.sink_histogram("kube_api_request_duration_seconds", &[("verb", verb)], duration.as_secs_f64());
+    }
+
+    fn throttled(&self, wait: std::time::Duration) {
+        self.kube_api_rate_limit_wait_seconds_total.inc_by(wait.as_secs_f64());
+        self.sink_counter("kube_api_rate_limit_wait_seconds_total", &[], wait.as_secs_f64());
+    }
 }
 
 impl Default for MetricsCollector {
@@ -120,7 +638,38 @@ impl Clone for MetricsCollector {
             http_errors_total: self.http_errors_total.clone(),
             http_request_size_bytes: self.http_request_size_bytes.clone(),
             http_response_size_bytes: self.http_response_size_bytes.clone(),
+            pii_detections_total: self.pii_detections_total.clone(),
+            graphql_requests_total: self.graphql_requests_total.clone(),
+            health_checks_total: self.health_checks_total.clone(),
+            service_maintenance: self.service_maintenance.clone(),
+            upstream_retries_total: self.upstream_retries_total.clone(),
+            request_body_too_large_total: self.request_body_too_large_total.clone(),
+            response_body_too_large_total: self.response_body_too_large_total.clone(),
+            compression_bytes_saved_total: self.compression_bytes_saved_total.clone(),
+            faults_injected_total: self.faults_injected_total.clone(),
+            dns_lookups_total: self.dns_lookups_total.clone(),
+            grpc_responses_total: self.grpc_responses_total.clone(),
+            upstream_connections_opened_total: self.upstream_connections_opened_total.clone(),
+            upstream_pool_waits_total: self.upstream_pool_waits_total.clone(),
+            upstream_pool_in_use: self.upstream_pool_in_use.clone(),
+            kube_api_requests_total: self.kube_api_requests_total.clone(),
+            kube_api_request_duration_seconds: self.kube_api_request_duration_seconds.clone(),
+            kube_api_requests_in_flight: self.kube_api_requests_in_flight.clone(),
+            kube_api_rate_limit_wait_seconds_total: self.kube_api_rate_limit_wait_seconds_total.clone(),
+            tcp_connections_total: self.tcp_connections_total.clone(),
+            tcp_bytes_total: self.tcp_bytes_total.clone(),
+            upstream_errors_total: self.upstream_errors_total.clone(),
+            cache_lookups_total: self.cache_lookups_total.clone(),
+            source_vpc_requests_total: self.source_vpc_requests_total.clone(),
+            vpc_pair_requests_total: self.vpc_pair_requests_total.clone(),
+            vpc_pair_bytes_total: self.vpc_pair_bytes_total.clone(),
+            endpoint_requests_total: self.endpoint_requests_total.clone(),
+            endpoint_active_connections: self.endpoint_active_connections.clone(),
+            endpoint_errors_total: self.endpoint_errors_total.clone(),
+            endpoint_ejections_total: self.endpoint_ejections_total.clone(),
+            build_info: self.build_info.clone(),
             registry: self.registry.clone(),
+            sink: self.sink.clone(),
         }
     }
 }
@@ -128,12 +677,23 @@ impl Clone for MetricsCollector {
 /// Prometheus metrics middleware
 pub struct MetricsMiddleware {
     pub collector: MetricsCollector,
+    /// Bounds the path labels of request metrics
+    path_labels: PathLabeler,
 }
 
 impl MetricsMiddleware {
     /// Create a new metrics middleware
     pub fn new(collector: MetricsCollector) -> Self {
-        Self { collector }
+        Self {
+            collector,
+            path_labels: PathLabeler::default(),
+        }
+    }
+
+    /// Label request metrics by path through `path_labels`
+    pub fn with_path_labels(mut self, path_labels: PathLabeler) -> Self {
+        self.path_labels = path_labels;
+        self
     }
 }
 
@@ -146,11 +706,35 @@ impl Middleware for MetricsMiddleware {
     async fn on_request(&self, context: &MiddlewareContext) -> Result<()> {
         debug!("Recording request metrics for {} {}", context.method, context.path);
 
+        // Excluded paths get no per-path metrics
+        let Some(path) = self.path_labels.label(&context.path) else {
+            return Ok(());
+        };
+
         // Increment total requests counter
         self.collector
             .http_requests_total
-            .with_label_values(&[&context.method, &context.path])
+            .with_label_values(&[&context.method, &path])
             .inc();
+        self.collector.sink_counter(
+            "http_requests_total",
+            &[("method", &context.method), ("path", &path)],
+            1.0,
+        );
+        context.set_metadata("metrics_path".to_string(), path);
+
+        // Requests whose source address didn't resolve count as "unknown"
+        let source_vpc = context
+            .source_vpc
+            .as_ref()
+            .map(|source| format!("{}/{}", source.namespace, source.name))
+            .unwrap_or_else(|| "unknown".to_string());
+        self.collector
+            .source_vpc_requests_total
+            .with_label_values(&[&source_vpc])
+            .inc();
+        self.collector
+            .sink_counter("source_vpc_requests_total", &[("source_vpc", &source_vpc)], 1.0);
 
         // Record start time for latency measurement
         context.set_metadata(
@@ -176,9 +760,13 @@ impl Middleware for MetricsMiddleware {
             .http_responses_total
             .with_label_values(&[&status.to_string()])
             .inc();
+        self.collector
+            .sink_counter("http_responses_total", &[("status", &status.to_string())], 1.0);
 
         // Calculate and record request duration
-        if let Some(start_time_str) = context.get_metadata("metrics_start_time") {
+        if let (Some(start_time_str), Some(path)) =
+            (context.get_metadata("metrics_start_time"), context.get_metadata("metrics_path"))
+        {
             if let Ok(start_time) = start_time_str.parse::<f64>() {
                 let now = std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)?
@@ -186,8 +774,13 @@ impl Middleware for MetricsMiddleware {
                 let duration = now - start_time;
                 self.collector
                     .http_request_duration_seconds
-                    .with_label_values(&[&context.method, &context.path])
+                    .with_label_values(&[&context.method, &path])
                     .observe(duration);
+                self.collector.sink_histogram(
+                    "http_request_duration_seconds",
+                    &[("method", &context.method), ("path", &path)],
+                    duration,
+                );
             }
         }
 
@@ -199,6 +792,7 @@ impl Middleware for MetricsMiddleware {
 
         // Increment error counter
         self.collector.http_errors_total.inc();
+        self.collector.sink_counter("http_errors_total", &[], 1.0);
 
         Ok(())
     }
@@ -234,6 +828,61 @@ mod tests {
         assert!(collector2.gather().is_ok());
     }
 
+    #[test]
+    fn test_set_build_info() {
+        let collector = MetricsCollector::new().expect("Failed to create collector");
+        collector.set_build_info(&BuildInfo {
+            version: "0.1.0",
+            git_sha: "0123456789ab",
+            rustc: "rustc 1.82.0",
+            build_date: "2024-11-01T00:00:00Z",
+            features: "console",
+        });
+
+        let metrics = collector.gather().unwrap();
+        assert!(metrics.contains(
+            "build_info{build_date=\"2024-11-01T00:00:00Z\",features=\"console\",git_sha=\"0123456789ab\",rustc=\"rustc 1.82.0\",version=\"0.1.0\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_record_pii_detections() {
+        use crate::pii::PiiKind;
+
+        let collector = MetricsCollector::new().expect("Failed to create collector");
+        let scan = PiiScan {
+            detections: vec![(PiiKind::Email, 2), (PiiKind::CreditCard, 0)],
+            masked_body: None,
+        };
+        collector.record_pii_detections(&scan, PiiAction::Mask);
+
+        let count = collector
+            .pii_detections_total
+            .with_label_values(&["email", "mask"])
+            .get();
+        assert_eq!(count, 2.0);
+        assert!(!collector.gather().unwrap().contains("credit_card"));
+    }
+
+    #[test]
+    fn test_record_health_checks() {
+        let collector = MetricsCollector::new().expect("Failed to create collector");
+        collector.record_health_check("default/api", HealthStatus::Maintenance);
+        collector.set_service_maintenance("default/api", true);
+
+        let maintenance = collector
+            .health_checks_total
+            .with_label_values(&["default/api", "maintenance"])
+            .get();
+        let unhealthy = collector
+            .health_checks_total
+            .with_label_values(&["default/api", "unhealthy"])
+            .get();
+        assert_eq!(maintenance, 1.0);
+        assert_eq!(unhealthy, 0.0);
+        assert_eq!(collector.service_maintenance.with_label_values(&["default/api"]).get(), 1);
+    }
+
     #[tokio::test]
     async fn test_metrics_middleware_creation() {
         let collector = MetricsCollector::new().expect("Failed to create collector");
@@ -253,6 +902,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_request(&context).await;
@@ -264,6 +914,25 @@ mod tests {
         assert!(metrics.contains("http_requests_total"));
     }
 
+    #[tokio::test]
+    async fn test_requests_by_source_vpc() {
+        let middleware = MetricsMiddleware::new(MetricsCollector::new().unwrap());
+        let source = router_core::SourceVpc {
+            attachment: "apps/payments-a".to_string(),
+            namespace: "default".to_string(),
+            name: "payments".to_string(),
+            ..Default::default()
+        };
+        for source_vpc in [Some(source.clone()), Some(source), None] {
+            let context = MiddlewareContext { path: "/".to_string(), source_vpc, ..Default::default() };
+            middleware.on_request(&context).await.unwrap();
+        }
+
+        let requests = &middleware.collector.source_vpc_requests_total;
+        assert_eq!(requests.with_label_values(&["default/payments"]).get(), 2.0);
+        assert_eq!(requests.with_label_values(&["unknown"]).get(), 1.0);
+    }
+
     #[tokio::test]
     async fn test_metrics_middleware_on_response() {
         let collector = MetricsCollector::new().expect("Failed to create collector");
@@ -276,6 +945,7 @@ mod tests {
             response_status: Some(200),
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         // Set start time
@@ -308,6 +978,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_error(&context, "Test error").await;
@@ -318,6 +989,52 @@ mod tests {
         assert!(metrics.contains("http_errors_total"));
     }
 
+    #[tokio::test]
+    async fn test_metrics_middleware_path_labels() {
+        use crate::path_labels::PathLabelConfig;
+
+        let middleware = MetricsMiddleware::new(MetricsCollector::new().unwrap()).with_path_labels(PathLabeler::new(
+            PathLabelConfig {
+                templates: vec!["/users/:id".into()],
+                excluded: vec!["/healthz".into()],
+                ..Default::default()
+            },
+        ));
+        for path in ["/users/1", "/users/2", "/healthz"] {
+            let context = MiddlewareContext {
+                path: path.to_string(),
+                method: "GET".to_string(),
+                ..Default::default()
+            };
+            middleware.on_request(&context).await.unwrap();
+            middleware.on_response(&context, 200).await.unwrap();
+        }
+
+        let requests = &middleware.collector.http_requests_total;
+        assert_eq!(requests.with_label_values(&["GET", "/users/:id"]).get(), 2.0);
+        let metrics = middleware.collector.gather().unwrap();
+        assert!(!metrics.contains("/users/1"));
+        assert!(!metrics.contains("/healthz"));
+        assert!(metrics.contains("http_responses_total{status=\"200\"} 3"));
+    }
+
+    #[tokio::test]
+    async fn test_measurements_forwarded_to_sink() {
+        use crate::metrics_sink::PrometheusSink;
+
+        let sink = Arc::new(PrometheusSink::new(Arc::new(Registry::new())));
+        let collector = MetricsCollector::new().unwrap().with_sink(sink.clone());
+        collector.record_retry("backend:8080", "timeout");
+        let middleware = MetricsMiddleware::new(collector.clone());
+        middleware.on_error(&MiddlewareContext::default(), "boom").await.unwrap();
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&sink.registry().gather(), &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("upstream_retries_total{reason=\"timeout\",upstream=\"backend:8080\"} 1"));
+        assert!(text.contains("http_errors_total 1"));
+    }
+
     #[test]
     fn test_metrics_text_format_structure() {
         let collector = MetricsCollector::new().expect("Failed to create collector");
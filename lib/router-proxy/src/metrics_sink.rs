@@ -0,0 +1,319 @@
+//! Pluggable metrics backends
+//!
+//! [`MetricsCollector`](crate::MetricsCollector) keeps its Prometheus
+//! registry for scraping and forwards every measurement to an optional
+//! [`MetricsSink`], so the same metrics can be pushed to StatsD or an
+//! OpenTelemetry collector instead.
+
+use anyhow::Result;
+use opentelemetry::metrics::{Counter, Gauge, Histogram, Meter, MeterProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use prometheus::{CounterVec, GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Metric labels as name/value pairs
+pub type Labels<'a> = [(&'a str, &'a str)];
+
+/// Destination for metric measurements
+pub trait MetricsSink: Send + Sync {
+    /// Add `value` to a counter
+    fn counter(&self, name: &str, labels: &Labels, value: f64);
+
+    /// Set a gauge to `value`
+    fn gauge(&self, name: &str, labels: &Labels, value: f64);
+
+    /// Record one observation in a histogram
+    fn histogram(&self, name: &str, labels: &Labels, value: f64);
+}
+
+/// Available metrics backends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricsBackend {
+    /// Scraped from /metrics (always available)
+    Prometheus,
+    /// Pushed to a StatsD agent over UDP
+    Statsd,
+    /// Pushed to an OpenTelemetry collector over OTLP/gRPC
+    Otlp,
+}
+
+impl MetricsBackend {
+    /// Parse a backend name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "prometheus" => Some(Self::Prometheus),
+            "statsd" | "dogstatsd" => Some(Self::Statsd),
+            "otlp" => Some(Self::Otlp),
+            _ => None,
+        }
+    }
+}
+
+/// Sink that registers metrics in a Prometheus registry as they appear
+///
+/// Label names are fixed by the first measurement of each metric.
+pub struct PrometheusSink {
+    registry: Arc<Registry>,
+    counters: Mutex<HashMap<String, CounterVec>>,
+    gauges: Mutex<HashMap<String, GaugeVec>>,
+    histograms: Mutex<HashMap<String, HistogramVec>>,
+}
+
+impl PrometheusSink {
+    /// Create a sink that registers into `registry`
+    pub fn new(registry: Arc<Registry>) -> Self {
+        Self {
+            registry,
+            counters: Mutex::new(HashMap::new()),
+            gauges: Mutex::new(HashMap::new()),
+            histograms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registry the metrics are registered in
+    pub fn registry(&self) -> &Arc<Registry> {
+        &self.registry
+    }
+
+    /// Look up a metric, creating and registering it on first use
+    fn metric<M, F>(&self, metrics: &Mutex<HashMap<String, M>>, name: &str, create: F) -> Option<M>
+    where
+        M: prometheus::core::Collector + Clone + 'static,
+        F: FnOnce() -> prometheus::Result<M>,
+    {
+        let mut metrics = metrics.lock().unwrap();
+        if let Some(metric) = metrics.get(name) {
+            return Some(metric.clone());
+        }
+        let metric = create()
+            .and_then(|m| self.registry.register(Box::new(m.clone())).map(|_| m))
+            .map_err(|e| warn!("Failed to register metric {}: {}", name, e))
+            .ok()?;
+        metrics.insert(name.to_string(), metric.clone());
+        Some(metric)
+    }
+}
+
+fn label_names<'a>(labels: &'a Labels) -> Vec<&'a str> {
+    labels.iter().map(|(name, _)| *name).collect()
+}
+
+fn label_values<'a>(labels: &'a Labels) -> Vec<&'a str> {
+    labels.iter().map(|(_, value)| *value).collect()
+}
+
+impl MetricsSink for PrometheusSink {
+    fn counter(&self, name: &str, labels: &Labels, value: f64) {
+        let metric = self.metric(&self.counters, name, || {
+            CounterVec::new(Opts::new(name, name), &label_names(labels))
+        });
+        if let Some(metric) = metric {
+            if let Ok(counter) = metric.get_metric_with_label_values(&label_values(labels)) {
+                counter.inc_by(value);
+            }
+        }
+    }
+
+    fn gauge(&self, name: &str, labels: &Labels, value: f64) {
+        let metric = self.metric(&self.gauges, name, || {
+            GaugeVec::new(Opts::new(name, name), &label_names(labels))
+        });
+        if let Some(metric) = metric {
+            if let Ok(gauge) = metric.get_metric_with_label_values(&label_values(labels)) {
+                gauge.set(value);
+            }
+        }
+    }
+
+    fn histogram(&self, name: &str, labels: &Labels, value: f64) {
+        let metric = self.metric(&self.histograms, name, || {
+            HistogramVec::new(HistogramOpts::new(name, name), &label_names(labels))
+        });
+        if let Some(metric) = metric {
+            if let Ok(histogram) = metric.get_metric_with_label_values(&label_values(labels)) {
+                histogram.observe(value);
+            }
+        }
+    }
+}
+
+/// Sink that sends DogStatsD-formatted measurements over UDP
+///
+/// Labels become tags. Sends never block; measurements are dropped if
+/// the agent can't keep up.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl StatsdSink {
+    /// Create a sink sending to `address` (host:port), prefixing metric names
+    pub fn new(address: &str, prefix: &str) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Format a measurement as a DogStatsD line
+    fn line(&self, name: &str, labels: &Labels, value: f64, kind: &str) -> String {
+        let mut line = if self.prefix.is_empty() {
+            format!("{}:{}|{}", name, value, kind)
+        } else {
+            format!("{}.{}:{}|{}", self.prefix, name, value, kind)
+        };
+        if !labels.is_empty() {
+            let tags: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}:{}", name, value.replace([',', '|', '#'], "_")))
+                .collect();
+            line.push_str("|#");
+            line.push_str(&tags.join(","));
+        }
+        line
+    }
+
+    fn send(&self, line: String) {
+        if let Err(e) = self.socket.send(line.as_bytes()) {
+            debug!("Dropped StatsD measurement: {}", e);
+        }
+    }
+}
+
+impl MetricsSink for StatsdSink {
+    fn counter(&self, name: &str, labels: &Labels, value: f64) {
+        self.send(self.line(name, labels, value, "c"));
+    }
+
+    fn gauge(&self, name: &str, labels: &Labels, value: f64) {
+        self.send(self.line(name, labels, value, "g"));
+    }
+
+    fn histogram(&self, name: &str, labels: &Labels, value: f64) {
+        self.send(self.line(name, labels, value, "h"));
+    }
+}
+
+/// Sink that exports measurements to an OpenTelemetry collector
+///
+/// Measurements are aggregated in process and exported over OTLP/gRPC
+/// every `interval`.
+pub struct OtlpSink {
+    provider: SdkMeterProvider,
+    meter: Meter,
+    counters: Mutex<HashMap<String, Counter<f64>>>,
+    gauges: Mutex<HashMap<String, Gauge<f64>>>,
+    histograms: Mutex<HashMap<String, Histogram<f64>>>,
+}
+
+impl OtlpSink {
+    /// Create a sink exporting to `endpoint`; must be called on a Tokio runtime
+    pub fn new(endpoint: &str, interval: Duration) -> Result<Self> {
+        let provider = opentelemetry_otlp::new_pipeline()
+            .metrics(opentelemetry_sdk::runtime::Tokio)
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+            .with_period(interval)
+            .build()?;
+        let meter = provider.meter("router-proxy");
+        Ok(Self {
+            provider,
+            meter,
+            counters: Mutex::new(HashMap::new()),
+            gauges: Mutex::new(HashMap::new()),
+            histograms: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Export pending measurements and stop the exporter
+    pub fn shutdown(&self) -> Result<()> {
+        self.provider.shutdown()?;
+        Ok(())
+    }
+
+    fn attributes(labels: &Labels) -> Vec<KeyValue> {
+        labels
+            .iter()
+            .map(|(name, value)| KeyValue::new(name.to_string(), value.to_string()))
+            .collect()
+    }
+}
+
+impl MetricsSink for OtlpSink {
+    fn counter(&self, name: &str, labels: &Labels, value: f64) {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters
+            .entry(name.to_string())
+            .or_insert_with(|| self.meter.f64_counter(name.to_string()).init());
+        counter.add(value, &Self::attributes(labels));
+    }
+
+    fn gauge(&self, name: &str, labels: &Labels, value: f64) {
+        let mut gauges = self.gauges.lock().unwrap();
+        let gauge = gauges
+            .entry(name.to_string())
+            .or_insert_with(|| self.meter.f64_gauge(name.to_string()).init());
+        gauge.record(value, &Self::attributes(labels));
+    }
+
+    fn histogram(&self, name: &str, labels: &Labels, value: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms
+            .entry(name.to_string())
+            .or_insert_with(|| self.meter.f64_histogram(name.to_string()).init());
+        histogram.record(value, &Self::attributes(labels));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Encoder, TextEncoder};
+
+    #[test]
+    fn test_prometheus_sink_registers_on_first_use() {
+        let sink = PrometheusSink::new(Arc::new(Registry::new()));
+        sink.counter("requests_total", &[("method", "GET")], 2.0);
+        sink.counter("requests_total", &[("method", "GET")], 1.0);
+        sink.gauge("in_flight", &[], 4.0);
+        sink.histogram("latency_seconds", &[("method", "GET")], 0.25);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&sink.registry().gather(), &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("requests_total{method=\"GET\"} 3"));
+        assert!(text.contains("in_flight 4"));
+        assert!(text.contains("latency_seconds_count{method=\"GET\"} 1"));
+    }
+
+    #[test]
+    fn test_statsd_sink_sends_tagged_lines() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let sink = StatsdSink::new(&agent.local_addr().unwrap().to_string(), "router").unwrap();
+
+        sink.counter("http_requests_total", &[("method", "GET"), ("path", "/a,b")], 1.0);
+        sink.histogram("http_request_duration_seconds", &[], 0.5);
+
+        let mut buffer = [0u8; 512];
+        let n = agent.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"router.http_requests_total:1|c|#method:GET,path:/a_b");
+        let n = agent.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"router.http_request_duration_seconds:0.5|h");
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        assert_eq!(MetricsBackend::parse("StatsD"), Some(MetricsBackend::Statsd));
+        assert_eq!(MetricsBackend::parse("otlp"), Some(MetricsBackend::Otlp));
+        assert_eq!(MetricsBackend::parse("graphite"), None);
+    }
+}
@@ -1,13 +1,19 @@
 //! Middleware framework for extensible request/response processing
 
+use hyper::body::{Body, Buf, Bytes, Frame};
 use hyper::Request;
 use std::collections::HashMap;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use anyhow::Result;
+use thiserror::Error;
 use tracing::{debug, span, Level};
+use crate::redaction::Redactor;
+use router_core::SourceVpc;
 
 /// Context passed through middleware chain
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct MiddlewareContext {
     /// Request path
     pub path: String,
@@ -21,11 +27,17 @@ pub struct MiddlewareContext {
     pub response_headers: HashMap<String, String>,
     /// Custom metadata for middleware
     pub metadata: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    /// Raw request query string
+    pub query: Option<String>,
+    /// Redaction rules for anything written to logs or traces
+    pub redactor: Arc<Redactor>,
+    /// VPC the request was sent from, if its source address resolved
+    pub source_vpc: Option<SourceVpc>,
 }
 
 impl MiddlewareContext {
     /// Create a new middleware context from a request
-    pub fn from_request(req: &Request<hyper::body::Incoming>) -> Self {
+    pub fn from_request<B>(req: &Request<B>) -> Self {
         let mut headers = HashMap::new();
         for (k, v) in req.headers() {
             if let Ok(v_str) = v.to_str() {
@@ -40,9 +52,25 @@ impl MiddlewareContext {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            query: req.uri().query().map(|q| q.to_string()),
+            redactor: Arc::new(Redactor::default()),
+            source_vpc: None,
         }
     }
 
+    /// Get the request path and query with sensitive parameters redacted
+    pub fn loggable_uri(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, self.redactor.redact_query(query)),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Get the request headers with sensitive values redacted
+    pub fn loggable_headers(&self) -> HashMap<String, String> {
+        self.redactor.redact_headers(&self.request_headers)
+    }
+
     /// Get a metadata value
     pub fn get_metadata(&self, key: &str) -> Option<String> {
         self.metadata
@@ -51,6 +79,11 @@ impl MiddlewareContext {
             .and_then(|m| m.get(key).cloned())
     }
 
+    /// Request ID assigned by [`crate::RequestIdMiddleware`]
+    pub fn request_id(&self) -> Option<String> {
+        self.get_metadata(crate::request_id::REQUEST_ID_METADATA)
+    }
+
     /// Set a metadata value
     pub fn set_metadata(&self, key: String, value: String) {
         if let Ok(mut m) = self.metadata.lock() {
@@ -85,6 +118,40 @@ pub trait Middleware: Send + Sync {
     async fn on_error(&self, _context: &MiddlewareContext, _error: &str) -> Result<()> {
         Ok(())
     }
+
+    /// Whether the body hooks do anything; bodies bypass them unless some middleware does
+    fn inspects_body(&self) -> bool {
+        false
+    }
+
+    /// Called with each chunk of the request body as it arrives
+    ///
+    /// Returns the chunk to forward in its place; an error rejects the request.
+    fn on_request_body(&self, _context: &MiddlewareContext, chunk: Bytes) -> Result<Bytes> {
+        Ok(chunk)
+    }
+
+    /// Called with each chunk of the response body
+    ///
+    /// Returns the chunk to send in its place; an error blocks the response.
+    fn on_response_body(&self, _context: &MiddlewareContext, chunk: Bytes) -> Result<Bytes> {
+        Ok(chunk)
+    }
+}
+
+/// Error reading a body through the body hooks
+#[derive(Debug, Error)]
+pub enum BodyHookError {
+    /// A middleware rejected the body
+    #[error("Body rejected by {middleware}: {reason}")]
+    Rejected {
+        middleware: &'static str,
+        reason: String,
+    },
+
+    /// The body itself failed
+    #[error(transparent)]
+    Body(Box<dyn std::error::Error + Send + Sync>),
 }
 
 /// Chain of middleware to execute in order
@@ -101,6 +168,7 @@ impl MiddlewareChain {
     }
 
     /// Add middleware to the chain
+    #[allow(clippy::should_implement_trait)]
     pub fn add<M: Middleware + 'static>(mut self, middleware: M) -> Self {
         self.middleware.push(Arc::new(middleware));
         self
@@ -142,6 +210,85 @@ impl MiddlewareChain {
         }
         Ok(())
     }
+
+    /// Whether any middleware inspects bodies
+    pub fn inspects_body(&self) -> bool {
+        self.middleware.iter().any(|mw| mw.inspects_body())
+    }
+
+    /// Pass a request body chunk through all middleware
+    pub fn on_request_body(&self, context: &MiddlewareContext, mut chunk: Bytes) -> std::result::Result<Bytes, BodyHookError> {
+        for mw in self.middleware.iter().filter(|mw| mw.inspects_body()) {
+            chunk = mw.on_request_body(context, chunk).map_err(|e| BodyHookError::Rejected {
+                middleware: mw.name(),
+                reason: e.to_string(),
+            })?;
+        }
+        Ok(chunk)
+    }
+
+    /// Pass a response body chunk through all middleware (in reverse order)
+    pub fn on_response_body(&self, context: &MiddlewareContext, mut chunk: Bytes) -> std::result::Result<Bytes, BodyHookError> {
+        for mw in self.middleware.iter().rev().filter(|mw| mw.inspects_body()) {
+            chunk = mw.on_response_body(context, chunk).map_err(|e| BodyHookError::Rejected {
+                middleware: mw.name(),
+                reason: e.to_string(),
+            })?;
+        }
+        Ok(chunk)
+    }
+
+    /// Run a request body through the request body hooks as it streams
+    pub fn inspect_request_body<B>(self: Arc<Self>, context: MiddlewareContext, body: B) -> InspectedBody<B>
+    where
+        B: Body,
+    {
+        InspectedBody {
+            chain: self,
+            context,
+            inner: Box::pin(body),
+        }
+    }
+}
+
+/// Request body whose data frames pass through the body hooks
+pub struct InspectedBody<B> {
+    chain: Arc<MiddlewareChain>,
+    context: MiddlewareContext,
+    inner: Pin<Box<B>>,
+}
+
+impl<B> Body for InspectedBody<B>
+where
+    B: Body,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Data = Bytes;
+    type Error = BodyHookError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = match self.inner.as_mut().poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(BodyHookError::Body(Box::new(e))))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let frame = match frame.into_data() {
+            Ok(mut data) => {
+                let chunk = data.copy_to_bytes(data.remaining());
+                self.chain.on_request_body(&self.context, chunk).map(Frame::data)
+            }
+            Err(frame) => Ok(frame.map_data(|mut data| data.copy_to_bytes(data.remaining()))),
+        };
+        Poll::Ready(Some(frame))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
 }
 
 impl Default for MiddlewareChain {
@@ -163,7 +310,7 @@ impl Middleware for LoggingMiddleware {
         debug!(
             "Request: {} {} (headers: {})",
             context.method,
-            context.path,
+            context.loggable_uri(),
             context.request_headers.len()
         );
         context.set_metadata("start_time".to_string(),
@@ -195,7 +342,7 @@ impl Middleware for LoggingMiddleware {
         debug!(
             "Response: {} {} -> {} (duration: {}ms)",
             context.method,
-            context.path,
+            context.loggable_uri(),
             status,
             duration
         );
@@ -203,7 +350,7 @@ impl Middleware for LoggingMiddleware {
     }
 
     async fn on_error(&self, context: &MiddlewareContext, error: &str) -> Result<()> {
-        debug!("Error: {} {} - {}", context.method, context.path, error);
+        debug!("Error: {} {} - {}", context.method, context.loggable_uri(), error);
         Ok(())
     }
 }
@@ -230,7 +377,7 @@ impl Middleware for HeaderInspectionMiddleware {
     async fn on_request(&self, context: &MiddlewareContext) -> Result<()> {
         for header in &self.headers_to_log {
             if let Some(value) = context.request_headers.get(header) {
-                debug!("Request header {}: {}", header, value);
+                debug!("Request header {}: {}", header, context.redactor.header_value(header, value));
             }
         }
         Ok(())
@@ -250,6 +397,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
         assert_eq!(context.path, "/test");
         assert_eq!(context.method, "GET");
@@ -264,6 +412,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         context.set_metadata("key1".to_string(), "value1".to_string());
@@ -281,6 +430,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = chain.on_request(&context).await;
@@ -300,6 +450,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_request(&context).await;
@@ -307,6 +458,72 @@ mod tests {
         assert!(context.get_metadata("start_time").is_some());
     }
 
+    #[test]
+    fn test_middleware_context_loggable_fields() {
+        let mut request_headers = HashMap::new();
+        request_headers.insert("authorization".to_string(), "Bearer secret".to_string());
+        request_headers.insert("accept".to_string(), "*/*".to_string());
+
+        let context = MiddlewareContext {
+            path: "/login".to_string(),
+            method: "POST".to_string(),
+            request_headers,
+            query: Some("user=bob&token=abc".to_string()),
+            redactor: Arc::new(Redactor::new().with_query_params(vec!["token".to_string()])),
+            ..Default::default()
+        };
+
+        assert_eq!(context.loggable_uri(), "/login?user=bob&token=[REDACTED]");
+        let headers = context.loggable_headers();
+        assert_eq!(headers["authorization"], crate::redaction::REDACTED);
+        assert_eq!(headers["accept"], "*/*");
+    }
+
+    /// Uppercases bodies and rejects any containing "forbidden"
+    struct ShoutingMiddleware;
+
+    #[async_trait::async_trait]
+    impl Middleware for ShoutingMiddleware {
+        fn name(&self) -> &'static str {
+            "ShoutingMiddleware"
+        }
+
+        fn inspects_body(&self) -> bool {
+            true
+        }
+
+        fn on_request_body(&self, _context: &MiddlewareContext, chunk: Bytes) -> Result<Bytes> {
+            if chunk.windows(9).any(|w| w == b"forbidden") {
+                anyhow::bail!("forbidden content");
+            }
+            Ok(Bytes::from(chunk.to_ascii_uppercase()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_body_hooks() {
+        use http_body_util::{BodyExt, StreamBody};
+
+        let plain = MiddlewareChain::new().add(LoggingMiddleware);
+        assert!(!plain.inspects_body());
+
+        let chain = Arc::new(MiddlewareChain::new().add(LoggingMiddleware).add(ShoutingMiddleware));
+        assert!(chain.inspects_body());
+        let context = MiddlewareContext::default();
+        assert_eq!(chain.on_response_body(&context, Bytes::from("pong")).unwrap(), "pong");
+
+        // Request bodies are transformed chunk by chunk as they stream
+        let chunks = ["hello ", "world"].map(|c| Ok::<_, std::io::Error>(Frame::data(Bytes::from(c))));
+        let body = StreamBody::new(futures::stream::iter(chunks));
+        let collected = chain.clone().inspect_request_body(context.clone(), body).collect().await.unwrap();
+        assert_eq!(collected.to_bytes(), "HELLO WORLD");
+
+        let chunks = ["fine", "forbidden"].map(|c| Ok::<_, std::io::Error>(Frame::data(Bytes::from(c))));
+        let body = StreamBody::new(futures::stream::iter(chunks));
+        let error = chain.inspect_request_body(context, body).collect().await.unwrap_err();
+        assert!(matches!(error, BodyHookError::Rejected { middleware: "ShoutingMiddleware", .. }));
+    }
+
     #[test]
     fn test_header_inspection_middleware_creation() {
         let middleware = HeaderInspectionMiddleware::new(vec![
@@ -2,7 +2,10 @@
 //!
 //! Phase 4.8: Advanced certificate validation with metadata extraction and pinning
 
-use rustls::pki_types::CertificateDer;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
 use rustls_pemfile::certs;
 use std::io::BufReader;
 use std::collections::HashMap;
@@ -92,6 +95,103 @@ impl TlsClientConfig {
             verify_server_cert,
         })
     }
+
+    /// Build the rustls client configuration for upstream connections
+    ///
+    /// Server certificates are verified against the CA certificate when
+    /// one is set, otherwise against the system trust store. A client
+    /// certificate is presented when the configuration has one.
+    pub fn client_config(&self) -> Result<ClientConfig> {
+        let builder = if self.verify_server_cert {
+            let mut roots = RootCertStore::empty();
+            match &self.ca_cert_pem {
+                Some(ca_pem) => {
+                    let ca_certs = load_certificates(ca_pem)?;
+                    if ca_certs.is_empty() {
+                        // Fail closed: no server certificate will verify
+                        warn!("No CA certificates found for upstream verification");
+                    }
+                    roots.add_parsable_certificates(ca_certs);
+                }
+                None => {
+                    let native = rustls_native_certs::load_native_certs();
+                    for e in &native.errors {
+                        warn!("Failed to load system CA certificate: {}", e);
+                    }
+                    roots.add_parsable_certificates(native.certs);
+                }
+            }
+            ClientConfig::builder().with_root_certificates(roots)
+        } else {
+            warn!("Upstream server certificate verification is disabled");
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoServerVerification::new()))
+        };
+
+        let cert_chain = load_certificates(&self.cert_pem)?;
+        if cert_chain.is_empty() {
+            return Ok(builder.with_no_client_auth());
+        }
+        let key = rustls_pemfile::private_key(&mut BufReader::new(self.key_pem.as_slice()))
+            .map_err(|e| anyhow!("Failed to parse client private key: {}", e))?
+            .ok_or_else(|| anyhow!("No client private key found in PEM data"))?;
+        builder
+            .with_client_auth_cert(cert_chain, key)
+            .map_err(|e| anyhow!("Invalid client certificate: {}", e))
+    }
+}
+
+/// Accepts any server certificate while still checking handshake signatures
+///
+/// Only used when server verification is explicitly turned off.
+#[derive(Debug)]
+struct NoServerVerification {
+    provider: Arc<CryptoProvider>,
+}
+
+impl NoServerVerification {
+    fn new() -> Self {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()));
+        Self { provider }
+    }
+}
+
+impl ServerCertVerifier for NoServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
 }
 
 /// Load certificates from PEM-encoded data
@@ -199,7 +299,7 @@ impl CertificatePinner {
         debug!("Added certificate pin for service: {}", service);
         self.pins
             .entry(service)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(fingerprint);
     }
 
@@ -299,7 +399,7 @@ impl CertificateValidationResult {
 pub fn calculate_cert_fingerprint(cert_der: &CertificateDer) -> String {
     use sha2::{Sha256, Digest};
     let mut hasher = Sha256::new();
-    hasher.update(&cert_der.as_ref());
+    hasher.update(cert_der.as_ref());
     let hash = hasher.finalize();
     hex::encode(hash)
 }
@@ -506,6 +606,11 @@ impl RevocationChecker {
         self.cache.stats()
     }
 
+    /// Get the configured maximum cache size
+    pub fn cache_capacity(&self) -> usize {
+        self.cache_capacity
+    }
+
     /// Clear the revocation cache
     pub fn clear_cache(&self) {
         self.cache.clear();
@@ -0,0 +1,366 @@
+//! OAuth2 client-credentials token injection for upstream requests
+//!
+//! Centralizes service-to-service authentication at the router: tokens are
+//! obtained from a configured token endpoint, cached until shortly before
+//! they expire, and injected as `Authorization` headers toward specific backends.
+
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use anyhow::{Result, anyhow};
+use tracing::{debug, warn};
+
+/// Lifetime assumed for tokens whose response omits `expires_in`
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// OAuth2 client-credentials configuration for a backend
+#[derive(Clone, Debug)]
+pub struct OAuth2ClientConfig {
+    /// Token endpoint URL
+    pub token_url: String,
+    /// OAuth2 client ID
+    pub client_id: String,
+    /// OAuth2 client secret
+    pub client_secret: String,
+    /// Scopes to request
+    pub scopes: Vec<String>,
+    /// Optional audience parameter (required by some providers)
+    pub audience: Option<String>,
+    /// Refresh tokens this long before they expire
+    pub refresh_skew: Duration,
+    /// Timeout for token endpoint requests
+    pub timeout: Duration,
+    /// Forward requests without a token when none can be obtained
+    pub fail_open: bool,
+}
+
+impl OAuth2ClientConfig {
+    /// Create a configuration with default refresh and timeout settings
+    pub fn new(token_url: String, client_id: String, client_secret: String) -> Self {
+        Self {
+            token_url,
+            client_id,
+            client_secret,
+            scopes: Vec::new(),
+            audience: None,
+            refresh_skew: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            fail_open: false,
+        }
+    }
+}
+
+/// Token endpoint response (RFC 6749 section 5.1)
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    token_type: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Cached access token
+#[derive(Clone, Debug)]
+struct CachedToken {
+    access_token: String,
+    token_type: String,
+    expires_at: Instant,
+}
+
+impl CachedToken {
+    fn from_response(response: TokenResponse, now: Instant) -> Self {
+        let lifetime = response
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        // "bearer" is case-insensitive on the wire but most backends expect "Bearer"
+        let token_type = match response.token_type {
+            Some(t) if !t.eq_ignore_ascii_case("bearer") => t,
+            _ => "Bearer".to_string(),
+        };
+
+        Self {
+            access_token: response.access_token,
+            token_type,
+            expires_at: now + lifetime,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    fn needs_refresh(&self, now: Instant, skew: Duration) -> bool {
+        now + skew >= self.expires_at
+    }
+
+    fn header_value(&self) -> String {
+        format!("{} {}", self.token_type, self.access_token)
+    }
+}
+
+/// Obtains and caches client-credentials tokens from a token endpoint
+pub struct OAuth2TokenProvider {
+    config: OAuth2ClientConfig,
+    http: reqwest::Client,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl OAuth2TokenProvider {
+    /// Create a new token provider
+    pub fn new(config: OAuth2ClientConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()?;
+
+        Ok(Self {
+            config,
+            http,
+            cached: Mutex::new(None),
+        })
+    }
+
+    /// Get the provider configuration
+    pub fn config(&self) -> &OAuth2ClientConfig {
+        &self.config
+    }
+
+    /// Get an `Authorization` header value, refreshing the token if needed
+    ///
+    /// If the refresh fails but the cached token has not yet expired, the
+    /// cached token is returned so a flaky token endpoint doesn't take
+    /// down traffic to the backend.
+    pub async fn authorization(&self) -> Result<String> {
+        // Holding the lock across the fetch prevents refresh stampedes
+        let mut cached = self.cached.lock().await;
+        let now = Instant::now();
+
+        if let Some(token) = cached.as_ref() {
+            if !token.needs_refresh(now, self.config.refresh_skew) {
+                return Ok(token.header_value());
+            }
+        }
+
+        match self.fetch().await {
+            Ok(token) => {
+                debug!("Obtained OAuth2 token from {}", self.config.token_url);
+                let value = token.header_value();
+                *cached = Some(token);
+                Ok(value)
+            }
+            Err(e) => match cached.as_ref() {
+                Some(token) if !token.is_expired(Instant::now()) => {
+                    warn!("OAuth2 token refresh failed, using cached token: {}", e);
+                    Ok(token.header_value())
+                }
+                _ => {
+                    *cached = None;
+                    Err(e)
+                }
+            },
+        }
+    }
+
+    /// Drop the cached token so the next request fetches a new one
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    /// Request a new token from the token endpoint
+    async fn fetch(&self) -> Result<CachedToken> {
+        let mut form = vec![("grant_type", "client_credentials".to_string())];
+        if !self.config.scopes.is_empty() {
+            form.push(("scope", self.config.scopes.join(" ")));
+        }
+        if let Some(audience) = &self.config.audience {
+            form.push(("audience", audience.clone()));
+        }
+
+        let response = self
+            .http
+            .post(&self.config.token_url)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Token endpoint {} returned {}",
+                self.config.token_url,
+                response.status()
+            ));
+        }
+
+        let body: TokenResponse = response.json().await?;
+        Ok(CachedToken::from_response(body, Instant::now()))
+    }
+}
+
+/// Injects OAuth2 tokens into requests toward configured backends
+#[derive(Default)]
+pub struct OAuth2TokenInjector {
+    /// Token providers keyed by backend authority (host:port)
+    providers: HashMap<String, Arc<OAuth2TokenProvider>>,
+}
+
+impl OAuth2TokenInjector {
+    /// Create an injector with no backends configured
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject tokens from `provider` into requests toward `backend`
+    pub fn with_backend(mut self, backend: String, provider: Arc<OAuth2TokenProvider>) -> Self {
+        self.providers.insert(backend, provider);
+        self
+    }
+
+    /// Check if a backend has token injection configured
+    pub fn has_backend(&self, backend: &str) -> bool {
+        self.providers.contains_key(backend)
+    }
+
+    /// Check if no backends are configured
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Set the `Authorization` header for a request toward `backend`
+    ///
+    /// Returns `Ok(true)` if a token was injected and `Ok(false)` if the
+    /// backend has no token provider (or the provider failed open).
+    pub async fn inject(&self, backend: &str, headers: &mut HeaderMap) -> Result<bool> {
+        let Some(provider) = self.providers.get(backend) else {
+            return Ok(false);
+        };
+
+        match provider.authorization().await {
+            Ok(value) => {
+                headers.insert(AUTHORIZATION, HeaderValue::from_str(&value)?);
+                Ok(true)
+            }
+            Err(e) if provider.config().fail_open => {
+                warn!("No OAuth2 token for {}, forwarding without one: {}", backend, e);
+                Ok(false)
+            }
+            Err(e) => Err(anyhow!("Failed to obtain OAuth2 token for {}: {}", backend, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(token_type: Option<&str>, expires_in: Option<u64>) -> TokenResponse {
+        TokenResponse {
+            access_token: "abc123".to_string(),
+            token_type: token_type.map(|t| t.to_string()),
+            expires_in,
+        }
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config = OAuth2ClientConfig::new(
+            "https://auth.example.com/token".to_string(),
+            "client".to_string(),
+            "secret".to_string(),
+        );
+        assert!(config.scopes.is_empty());
+        assert_eq!(config.refresh_skew, Duration::from_secs(30));
+        assert!(!config.fail_open);
+    }
+
+    #[test]
+    fn test_cached_token_header_value() {
+        let now = Instant::now();
+        let token = CachedToken::from_response(response(Some("bearer"), Some(60)), now);
+        assert_eq!(token.header_value(), "Bearer abc123");
+
+        let token = CachedToken::from_response(response(Some("MAC"), Some(60)), now);
+        assert_eq!(token.header_value(), "MAC abc123");
+    }
+
+    #[test]
+    fn test_cached_token_expiry() {
+        let now = Instant::now();
+        let token = CachedToken::from_response(response(None, Some(60)), now);
+
+        assert!(!token.is_expired(now));
+        assert!(!token.needs_refresh(now, Duration::from_secs(30)));
+        assert!(token.needs_refresh(now + Duration::from_secs(31), Duration::from_secs(30)));
+        assert!(token.is_expired(now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_cached_token_default_lifetime() {
+        let now = Instant::now();
+        let token = CachedToken::from_response(response(None, None), now);
+        assert_eq!(token.expires_at, now + DEFAULT_TOKEN_LIFETIME);
+    }
+
+    #[tokio::test]
+    async fn test_injector_skips_unconfigured_backend() {
+        let injector = OAuth2TokenInjector::new();
+        let mut headers = HeaderMap::new();
+
+        let injected = injector.inject("backend:8080", &mut headers).await.unwrap();
+        assert!(!injected);
+        assert!(headers.get(AUTHORIZATION).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_injector_uses_cached_token() {
+        let config = OAuth2ClientConfig::new(
+            "http://127.0.0.1:1/token".to_string(),
+            "client".to_string(),
+            "secret".to_string(),
+        );
+        let provider = Arc::new(OAuth2TokenProvider::new(config).unwrap());
+        *provider.cached.lock().await = Some(CachedToken::from_response(
+            response(None, Some(3600)),
+            Instant::now(),
+        ));
+
+        let injector = OAuth2TokenInjector::new()
+            .with_backend("backend:8080".to_string(), provider);
+        assert!(injector.has_backend("backend:8080"));
+
+        let mut headers = HeaderMap::new();
+        let injected = injector.inject("backend:8080", &mut headers).await.unwrap();
+        assert!(injected);
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer abc123");
+    }
+
+    #[tokio::test]
+    async fn test_injector_fail_open_and_closed() {
+        // Port 1 is never listening, so the token fetch fails immediately
+        let mut config = OAuth2ClientConfig::new(
+            "http://127.0.0.1:1/token".to_string(),
+            "client".to_string(),
+            "secret".to_string(),
+        );
+        let closed = OAuth2TokenInjector::new().with_backend(
+            "backend:8080".to_string(),
+            Arc::new(OAuth2TokenProvider::new(config.clone()).unwrap()),
+        );
+        let mut headers = HeaderMap::new();
+        assert!(closed.inject("backend:8080", &mut headers).await.is_err());
+
+        config.fail_open = true;
+        let open = OAuth2TokenInjector::new().with_backend(
+            "backend:8080".to_string(),
+            Arc::new(OAuth2TokenProvider::new(config).unwrap()),
+        );
+        let injected = open.inject("backend:8080", &mut headers).await.unwrap();
+        assert!(!injected);
+        assert!(headers.get(AUTHORIZATION).is_none());
+    }
+}
@@ -0,0 +1,187 @@
+//! Path labels for request metrics
+//!
+//! Request counters and latency histograms are labelled by path, so a
+//! scanner hitting random URLs would create a time series per URL. Paths
+//! can be collapsed onto templates such as `/users/:id`, left out of
+//! per-path metrics, or folded into "other" when they match no template.
+//! A hard cap on distinct labels bounds the series count regardless.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Label for paths folded together
+pub const OTHER_PATH: &str = "other";
+
+/// Path label configuration
+#[derive(Clone, Debug)]
+pub struct PathLabelConfig {
+    /// Templates paths are collapsed onto; `:name` matches one segment, a trailing `*` the rest
+    pub templates: Vec<String>,
+    /// Path prefixes left out of per-path metrics
+    pub excluded: Vec<String>,
+    /// Label paths that match no template as "other"
+    pub unmatched_as_other: bool,
+    /// Most distinct path labels; paths beyond it are labelled "other"
+    pub max_labels: usize,
+}
+
+impl Default for PathLabelConfig {
+    fn default() -> Self {
+        Self {
+            templates: Vec::new(),
+            excluded: Vec::new(),
+            unmatched_as_other: false,
+            max_labels: 1000,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    Param,
+    Rest,
+}
+
+/// Parsed path template
+#[derive(Debug)]
+struct PathTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl PathTemplate {
+    fn parse(template: &str) -> Self {
+        let segments = template
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| match s {
+                "*" => Segment::Rest,
+                s if s.starts_with(':') => Segment::Param,
+                s => Segment::Literal(s.to_string()),
+            })
+            .collect();
+        Self {
+            template: template.to_string(),
+            segments,
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let mut parts = path.split('/').filter(|s| !s.is_empty());
+        for segment in &self.segments {
+            match segment {
+                Segment::Rest => return true,
+                Segment::Param => {
+                    if parts.next().is_none() {
+                        return false;
+                    }
+                }
+                Segment::Literal(literal) => {
+                    if parts.next() != Some(literal.as_str()) {
+                        return false;
+                    }
+                }
+            }
+        }
+        parts.next().is_none()
+    }
+}
+
+/// Maps request paths to bounded metric labels
+#[derive(Debug)]
+pub struct PathLabeler {
+    templates: Vec<PathTemplate>,
+    excluded: Vec<String>,
+    unmatched_as_other: bool,
+    max_labels: usize,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl Default for PathLabeler {
+    fn default() -> Self {
+        Self::new(PathLabelConfig::default())
+    }
+}
+
+impl PathLabeler {
+    /// Create a labeler
+    pub fn new(config: PathLabelConfig) -> Self {
+        Self {
+            templates: config.templates.iter().map(|t| PathTemplate::parse(t)).collect(),
+            excluded: config.excluded,
+            unmatched_as_other: config.unmatched_as_other,
+            max_labels: config.max_labels,
+            seen: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Label for a request path
+    ///
+    /// None for excluded paths. The first matching template wins;
+    /// templates don't count against the cap since there are only as
+    /// many as configured.
+    pub fn label(&self, path: &str) -> Option<String> {
+        if self.excluded.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            return None;
+        }
+        if let Some(template) = self.templates.iter().find(|t| t.matches(path)) {
+            return Some(template.template.clone());
+        }
+        if self.unmatched_as_other {
+            return Some(OTHER_PATH.to_string());
+        }
+
+        let Ok(mut seen) = self.seen.lock() else {
+            return Some(OTHER_PATH.to_string());
+        };
+        if seen.contains(path) {
+            return Some(path.to_string());
+        }
+        if seen.len() >= self.max_labels {
+            return Some(OTHER_PATH.to_string());
+        }
+        seen.insert(path.to_string());
+        Some(path.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates_and_exclusions() {
+        let labeler = PathLabeler::new(PathLabelConfig {
+            templates: vec!["/users/:id".into(), "/users/:id/orders/:order".into(), "/static/*".into()],
+            excluded: vec!["/internal/".into()],
+            ..Default::default()
+        });
+        assert_eq!(labeler.label("/users/42").as_deref(), Some("/users/:id"));
+        assert_eq!(labeler.label("/users/42/orders/7").as_deref(), Some("/users/:id/orders/:order"));
+        assert_eq!(labeler.label("/static/css/site.css").as_deref(), Some("/static/*"));
+        assert_eq!(labeler.label("/users").as_deref(), Some("/users"));
+        assert_eq!(labeler.label("/internal/debug"), None);
+
+        let strict = PathLabeler::new(PathLabelConfig {
+            templates: vec!["/users/:id".into()],
+            unmatched_as_other: true,
+            ..Default::default()
+        });
+        assert_eq!(strict.label("/users/42").as_deref(), Some("/users/:id"));
+        assert_eq!(strict.label("/wp-admin.php").as_deref(), Some(OTHER_PATH));
+    }
+
+    #[test]
+    fn test_label_cap() {
+        let labeler = PathLabeler::new(PathLabelConfig {
+            max_labels: 2,
+            ..Default::default()
+        });
+        assert_eq!(labeler.label("/a").as_deref(), Some("/a"));
+        assert_eq!(labeler.label("/b").as_deref(), Some("/b"));
+        assert_eq!(labeler.label("/c").as_deref(), Some(OTHER_PATH));
+        // Paths seen before the cap keep their label
+        assert_eq!(labeler.label("/a").as_deref(), Some("/a"));
+    }
+}
@@ -0,0 +1,108 @@
+//! Request path normalization
+//!
+//! Authorization gates match route prefixes and policies against the request
+//! path, so a path spelled differently from the one the backend resolves
+//! (`/x/../admin`, `//admin`, `/%61dmin`) could slip past them. Paths are
+//! normalized before they are checked and forwarded:
+//!
+//! - percent-encoded unreserved characters are decoded, other escapes are
+//!   kept with upper-case hex digits
+//! - duplicate slashes are collapsed
+//! - `.` and `..` segments are removed, never climbing above the root
+//!
+//! Paths no normalization can make safe are rejected: encoded slashes,
+//! backslashes or NULs, and malformed escapes.
+
+/// Normalize a request path, or None if it must be rejected
+pub fn normalize_path(path: &str) -> Option<String> {
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    let raw_segments: Vec<&str> = path.split('/').skip(1).collect();
+    for (i, raw) in raw_segments.iter().enumerate() {
+        let last = i + 1 == raw_segments.len();
+        let segment = decode_unreserved(raw)?;
+        match segment.as_str() {
+            "." | ".." => {
+                if segment == ".." {
+                    segments.pop();
+                }
+                // A path ending in a dot segment names a directory
+                if last {
+                    segments.push(String::new());
+                }
+            }
+            "" if !last => {}
+            _ => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        return Some("/".to_string());
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// Decode the unreserved characters of a path segment
+fn decode_unreserved(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = String::with_capacity(segment.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = segment.get(i + 1..i + 3)?;
+                let byte = u8::from_str_radix(hex, 16).ok()?;
+                match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => decoded.push(byte as char),
+                    b'/' | b'\\' | 0 => return None,
+                    _ => {
+                        decoded.push('%');
+                        decoded.push_str(&hex.to_ascii_uppercase());
+                    }
+                }
+                i += 3;
+            }
+            b'\\' | 0 => return None,
+            _ => {
+                let c = segment[i..].chars().next()?;
+                decoded.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(normalize_path("/").as_deref(), Some("/"));
+        assert_eq!(normalize_path("/api/items").as_deref(), Some("/api/items"));
+        assert_eq!(normalize_path("/api/items/").as_deref(), Some("/api/items/"));
+        assert_eq!(normalize_path("/x/../admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize_path("/../../admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize_path("/./admin/.").as_deref(), Some("/admin/"));
+        assert_eq!(normalize_path("//admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize_path("/api//v1///items").as_deref(), Some("/api/v1/items"));
+        assert_eq!(normalize_path("/%61dmin").as_deref(), Some("/admin"));
+        assert_eq!(normalize_path("/x/%2e%2E/admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize_path("/files/a%20b%3f").as_deref(), Some("/files/a%20b%3F"));
+    }
+
+    #[test]
+    fn test_rejected_paths() {
+        assert_eq!(normalize_path("/x%2F..%2Fadmin"), None);
+        assert_eq!(normalize_path("/x%5c..%5cadmin"), None);
+        assert_eq!(normalize_path("/x\\..\\admin"), None);
+        assert_eq!(normalize_path("/admin%00"), None);
+        assert_eq!(normalize_path("/bad%zz"), None);
+        assert_eq!(normalize_path("/bad%4"), None);
+        assert_eq!(normalize_path("admin"), None);
+    }
+}
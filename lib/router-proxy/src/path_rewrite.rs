@@ -0,0 +1,165 @@
+//! Path rewriting on forward
+//!
+//! Routes matched by path prefix can strip or replace the prefix before
+//! the request reaches the backend, or rewrite the whole path with a
+//! regular expression. The query string is always preserved.
+
+use anyhow::{Result, anyhow, bail};
+use regex::Regex;
+use router_api::v1alpha1::vpc_route::{PathRewritePolicy, RouteMatch};
+use std::borrow::Cow;
+
+/// Validated path rewrite
+#[derive(Clone, Debug)]
+pub enum PathRewrite {
+    /// Replace a leading prefix; an empty replacement strips it
+    Prefix {
+        /// Prefix the route matched on
+        prefix: String,
+        /// Prefix sent to the backend instead
+        replacement: String,
+    },
+    /// Replace the first match of a pattern
+    Regex {
+        /// Pattern matched against the path
+        pattern: Regex,
+        /// Replacement, which may refer to capture groups
+        substitution: String,
+    },
+}
+
+impl PathRewrite {
+    /// Strip a prefix from matching paths
+    pub fn strip_prefix(prefix: &str) -> Self {
+        Self::replace_prefix(prefix, "")
+    }
+
+    /// Replace a prefix on matching paths
+    pub fn replace_prefix(prefix: &str, replacement: &str) -> Self {
+        Self::Prefix {
+            prefix: prefix.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    /// Rewrite paths matching a regular expression
+    pub fn regex(pattern: &str, substitution: &str) -> Result<Self> {
+        let pattern = Regex::new(pattern).map_err(|e| anyhow!("Invalid path rewrite pattern: {}", e))?;
+        Ok(Self::Regex {
+            pattern,
+            substitution: substitution.to_string(),
+        })
+    }
+
+    /// Create a rewrite from a VPCRoute policy and the route's match
+    ///
+    /// Returns None when the policy rewrites nothing.
+    pub fn from_policy(policy: &PathRewritePolicy, route_match: &RouteMatch) -> Result<Option<Self>> {
+        let configured = [policy.strip_prefix, policy.replace_prefix.is_some(), policy.regex.is_some()];
+        if configured.iter().filter(|c| **c).count() > 1 {
+            bail!("Path rewrite sets more than one of stripPrefix, replacePrefix, and regex");
+        }
+
+        if let Some(regex) = &policy.regex {
+            return Self::regex(&regex.pattern, &regex.substitution).map(Some);
+        }
+        if !policy.strip_prefix && policy.replace_prefix.is_none() {
+            return Ok(None);
+        }
+        let prefix = route_match
+            .path_prefix
+            .as_deref()
+            .ok_or_else(|| anyhow!("Prefix rewrite requires a pathPrefix match"))?;
+        Ok(Some(Self::replace_prefix(prefix, policy.replace_prefix.as_deref().unwrap_or_default())))
+    }
+
+    /// Rewrite a request path
+    ///
+    /// Paths the rewrite doesn't apply to are returned unchanged. The
+    /// result always starts with "/".
+    pub fn apply<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let rewritten = match self {
+            PathRewrite::Prefix { prefix, replacement } => {
+                let prefix = prefix.trim_end_matches('/');
+                let Some(rest) = path.strip_prefix(prefix) else {
+                    return Cow::Borrowed(path);
+                };
+                // "/api" matches "/api" and "/api/users" but not "/apix"
+                if !rest.is_empty() && !rest.starts_with('/') {
+                    return Cow::Borrowed(path);
+                }
+                let replacement = replacement.trim_end_matches('/');
+                format!("{}{}", replacement, rest)
+            }
+            PathRewrite::Regex { pattern, substitution } => match pattern.replace(path, substitution.as_str()) {
+                Cow::Borrowed(_) => return Cow::Borrowed(path),
+                Cow::Owned(rewritten) => rewritten,
+            },
+        };
+
+        if rewritten.starts_with('/') {
+            Cow::Owned(rewritten)
+        } else {
+            Cow::Owned(format!("/{}", rewritten))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use router_api::v1alpha1::vpc_route::RegexRewrite;
+
+    #[test]
+    fn test_strip_prefix() {
+        let rewrite = PathRewrite::strip_prefix("/api/");
+        assert_eq!(rewrite.apply("/api/users/1"), "/users/1");
+        assert_eq!(rewrite.apply("/api"), "/");
+        assert_eq!(rewrite.apply("/apix/users"), "/apix/users");
+        assert_eq!(rewrite.apply("/other"), "/other");
+    }
+
+    #[test]
+    fn test_replace_prefix() {
+        let rewrite = PathRewrite::replace_prefix("/api", "/v2");
+        assert_eq!(rewrite.apply("/api/users"), "/v2/users");
+        assert_eq!(rewrite.apply("/api"), "/v2");
+    }
+
+    #[test]
+    fn test_regex_rewrite() {
+        let rewrite = PathRewrite::regex(r"^/users/(\d+)/profile$", "/profiles/$1").unwrap();
+        assert_eq!(rewrite.apply("/users/42/profile"), "/profiles/42");
+        assert_eq!(rewrite.apply("/users/abc/profile"), "/users/abc/profile");
+        assert!(PathRewrite::regex("(", "/").is_err());
+    }
+
+    #[test]
+    fn test_from_policy() {
+        let route_match = RouteMatch {
+            path_prefix: Some("/orders".to_string()),
+            ..Default::default()
+        };
+        let policy = PathRewritePolicy {
+            strip_prefix: true,
+            ..Default::default()
+        };
+        let rewrite = PathRewrite::from_policy(&policy, &route_match).unwrap().unwrap();
+        assert_eq!(rewrite.apply("/orders/7"), "/7");
+
+        assert!(PathRewrite::from_policy(&PathRewritePolicy::default(), &route_match).unwrap().is_none());
+
+        // Prefix rewrites need a prefix to rewrite
+        assert!(PathRewrite::from_policy(&policy, &RouteMatch::default()).is_err());
+
+        let conflicting = PathRewritePolicy {
+            strip_prefix: true,
+            regex: Some(RegexRewrite {
+                pattern: "^/orders".to_string(),
+                substitution: "/".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert!(PathRewrite::from_policy(&conflicting, &route_match).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! PII detection and masking for response bodies
+//!
+//! Scans responses of routes with a `piiScan` policy for common PII patterns
+//! (email addresses, payment card numbers) and masks or blocks them.
+//! Compressed responses are decoded before scanning.
+
+use std::io::Read;
+
+use hyper::body::Bytes;
+use regex::{Captures, Regex};
+use anyhow::Result;
+use router_api::v1alpha1::vpc_route::{PiiScanAction, PiiScanKind, PiiScanPolicy};
+
+/// Largest decoded body scanned; larger ones are treated as unreadable
+const MAX_DECODED_BODY: u64 = 16 * 1024 * 1024;
+
+/// Kind of PII detected in a body
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PiiKind {
+    /// Email address
+    Email,
+    /// Payment card number (Luhn-valid)
+    CreditCard,
+}
+
+impl PiiKind {
+    /// Label used in metrics and logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PiiKind::Email => "email",
+            PiiKind::CreditCard => "credit_card",
+        }
+    }
+
+    /// The kind a route's `piiScan` policy names
+    pub fn from_route(kind: PiiScanKind) -> Self {
+        match kind {
+            PiiScanKind::Email => PiiKind::Email,
+            PiiScanKind::CreditCard => PiiKind::CreditCard,
+        }
+    }
+}
+
+/// What to do when PII is detected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PiiAction {
+    /// Replace detected values with masked versions
+    Mask,
+    /// Refuse to return the response
+    Block,
+}
+
+impl PiiAction {
+    /// Label used in metrics and logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PiiAction::Mask => "mask",
+            PiiAction::Block => "block",
+        }
+    }
+
+    /// The action a route's `piiScan` policy names
+    pub fn from_route(action: PiiScanAction) -> Self {
+        match action {
+            PiiScanAction::Mask => PiiAction::Mask,
+            PiiScanAction::Block => PiiAction::Block,
+        }
+    }
+}
+
+/// PII scanning policy
+#[derive(Clone, Debug)]
+pub struct PiiPolicy {
+    /// Kinds of PII to detect
+    pub kinds: Vec<PiiKind>,
+    /// Action taken on detection
+    pub action: PiiAction,
+}
+
+impl Default for PiiPolicy {
+    fn default() -> Self {
+        Self {
+            kinds: vec![PiiKind::Email, PiiKind::CreditCard],
+            action: PiiAction::Mask,
+        }
+    }
+}
+
+impl PiiPolicy {
+    /// Scanning policy of a route; a policy naming no kinds detects every kind
+    pub fn from_route(policy: &PiiScanPolicy) -> Self {
+        let mut scan = Self {
+            action: PiiAction::from_route(policy.action),
+            ..Default::default()
+        };
+        if !policy.kinds.is_empty() {
+            scan.kinds = policy.kinds.iter().copied().map(PiiKind::from_route).collect();
+        }
+        scan
+    }
+}
+
+/// Result of scanning a body
+#[derive(Clone, Debug, Default)]
+pub struct PiiScan {
+    /// Number of detections per kind
+    pub detections: Vec<(PiiKind, usize)>,
+    /// Masked body (only set when the action is Mask and PII was found),
+    /// never content-encoded
+    pub masked_body: Option<Bytes>,
+}
+
+impl PiiScan {
+    /// Check if any PII was detected
+    pub fn found(&self) -> bool {
+        self.detections.iter().any(|(_, count)| *count > 0)
+    }
+}
+
+/// Scanner that detects and masks PII in response bodies
+///
+/// The patterns are compiled once and shared by every route; each scan
+/// takes the policy of the route the response is for.
+pub struct PiiScanner {
+    email: Regex,
+    card: Regex,
+}
+
+impl PiiScanner {
+    /// Create a new PII scanner
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}")?,
+            card: Regex::new(r"\b(?:\d[ -]?){12,18}\d\b")?,
+        })
+    }
+
+    /// Check if a response content type carries scannable text
+    pub fn is_scannable_content_type(content_type: Option<&str>) -> bool {
+        match content_type {
+            Some(ct) => {
+                let ct = ct.to_lowercase();
+                ct.starts_with("text/") || ct.contains("json") || ct.contains("xml")
+            }
+            None => false,
+        }
+    }
+
+    /// Scan a body for PII, masking it if the policy says so
+    pub fn scan(&self, policy: &PiiPolicy, body: &[u8]) -> PiiScan {
+        let Ok(text) = std::str::from_utf8(body) else {
+            return PiiScan::default();
+        };
+
+        let mut scan = PiiScan::default();
+        let mut masked = text.to_string();
+
+        for kind in &policy.kinds {
+            let (count, replaced) = match kind {
+                PiiKind::Email => {
+                    let count = self.email.find_iter(&masked).count();
+                    (count, self.email.replace_all(&masked, |c: &Captures| mask_email(&c[0])).into_owned())
+                }
+                PiiKind::CreditCard => {
+                    let count = self.card.find_iter(&masked).filter(|m| luhn_valid(m.as_str())).count();
+                    let replaced = self.card.replace_all(&masked, |c: &Captures| {
+                        if luhn_valid(&c[0]) {
+                            mask_card(&c[0])
+                        } else {
+                            c[0].to_string()
+                        }
+                    });
+                    (count, replaced.into_owned())
+                }
+            };
+            scan.detections.push((*kind, count));
+            masked = replaced;
+        }
+
+        if scan.found() && policy.action == PiiAction::Mask {
+            scan.masked_body = Some(Bytes::from(masked));
+        }
+
+        scan
+    }
+
+    /// Scan a body sent with `content_encoding`, decoding it first
+    ///
+    /// Returns None if the body can't be read: an encoding other than gzip,
+    /// deflate or br, a corrupt body, or one that decodes to more than 16 MiB.
+    pub fn scan_encoded(&self, policy: &PiiPolicy, content_encoding: Option<&str>, body: &[u8]) -> Option<PiiScan> {
+        let encoding = content_encoding.map(|e| e.trim().to_lowercase()).unwrap_or_default();
+        let decoder: Box<dyn Read + '_> = match encoding.as_str() {
+            "" | "identity" => return Some(self.scan(policy, body)),
+            "gzip" | "x-gzip" => Box::new(flate2::read::GzDecoder::new(body)),
+            "deflate" => Box::new(flate2::read::ZlibDecoder::new(body)),
+            "br" => Box::new(brotli::Decompressor::new(body, 4096)),
+            _ => return None,
+        };
+
+        let mut decoded = Vec::new();
+        decoder.take(MAX_DECODED_BODY + 1).read_to_end(&mut decoded).ok()?;
+        if decoded.len() as u64 > MAX_DECODED_BODY {
+            return None;
+        }
+        Some(self.scan(policy, &decoded))
+    }
+}
+
+/// Mask an email address, keeping the first character and the domain
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().unwrap_or('*');
+            format!("{}***@{}", first, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// Mask a card number, keeping the last four digits
+fn mask_card(number: &str) -> String {
+    let digits: Vec<char> = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    let keep = digits.len().saturating_sub(4);
+    digits
+        .iter()
+        .enumerate()
+        .map(|(i, d)| if i < keep { '*' } else { *d })
+        .collect()
+}
+
+/// Validate a card number with the Luhn checksum
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() < 13 || digits.len() > 19 {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                *d
+            }
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(action: PiiAction) -> PiiPolicy {
+        PiiPolicy {
+            action,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_luhn() {
+        assert!(luhn_valid("4111 1111 1111 1111"));
+        assert!(luhn_valid("5500-0000-0000-0004"));
+        assert!(!luhn_valid("4111 1111 1111 1112"));
+        assert!(!luhn_valid("1234"));
+    }
+
+    #[test]
+    fn test_scannable_content_type() {
+        assert!(PiiScanner::is_scannable_content_type(Some("application/json")));
+        assert!(PiiScanner::is_scannable_content_type(Some("text/html; charset=utf-8")));
+        assert!(!PiiScanner::is_scannable_content_type(Some("image/png")));
+        assert!(!PiiScanner::is_scannable_content_type(None));
+    }
+
+    #[test]
+    fn test_scan_masks_pii() {
+        let scanner = PiiScanner::new().unwrap();
+        let body = br#"{"email":"alice@example.com","card":"4111 1111 1111 1111","order":"1234567890123"}"#;
+
+        let scan = scanner.scan(&policy(PiiAction::Mask), body);
+        assert!(scan.found());
+        assert_eq!(scan.detections, vec![(PiiKind::Email, 1), (PiiKind::CreditCard, 1)]);
+
+        let masked = String::from_utf8(scan.masked_body.unwrap().to_vec()).unwrap();
+        assert!(masked.contains("a***@example.com"));
+        assert!(masked.contains("************1111"));
+        // Not Luhn-valid, so left alone
+        assert!(masked.contains("1234567890123"));
+    }
+
+    #[test]
+    fn test_scan_block_does_not_mask() {
+        let scanner = PiiScanner::new().unwrap();
+        let scan = scanner.scan(&policy(PiiAction::Block), b"contact bob@example.org");
+        assert!(scan.found());
+        assert!(scan.masked_body.is_none());
+    }
+
+    #[test]
+    fn test_scan_clean_body() {
+        let scanner = PiiScanner::new().unwrap();
+        let scan = scanner.scan(&policy(PiiAction::Mask), b"{\"status\":\"ok\"}");
+        assert!(!scan.found());
+        assert!(scan.masked_body.is_none());
+    }
+
+    #[test]
+    fn test_scan_compressed_body() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let scanner = PiiScanner::new().unwrap();
+        let policy = policy(PiiAction::Mask);
+        let body = br#"{"email":"alice@example.com"}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // Undecoded, the compressed bytes hide the address
+        assert!(!scanner.scan(&policy, &compressed).found());
+
+        let scan = scanner.scan_encoded(&policy, Some("gzip"), &compressed).unwrap();
+        assert_eq!(scan.detections, vec![(PiiKind::Email, 1), (PiiKind::CreditCard, 0)]);
+        assert_eq!(scan.masked_body.unwrap(), &br#"{"email":"a***@example.com"}"#[..]);
+
+        assert!(scanner.scan_encoded(&policy, None, body).unwrap().found());
+        assert!(scanner.scan_encoded(&policy, Some("zstd"), &compressed).is_none());
+        assert!(scanner.scan_encoded(&policy, Some("gzip"), body).is_none());
+    }
+
+    #[test]
+    fn test_policy_from_route() {
+        let every_kind = PiiPolicy::from_route(&PiiScanPolicy::default());
+        assert_eq!(every_kind.action, PiiAction::Mask);
+        assert_eq!(every_kind.kinds, vec![PiiKind::Email, PiiKind::CreditCard]);
+
+        let cards = PiiPolicy::from_route(&PiiScanPolicy {
+            action: PiiScanAction::Block,
+            kinds: vec![PiiScanKind::CreditCard],
+        });
+        assert_eq!(cards.action, PiiAction::Block);
+        assert_eq!(cards.kinds, vec![PiiKind::CreditCard]);
+
+        let scan = PiiScanner::new().unwrap().scan(&cards, b"contact bob@example.org");
+        assert!(!scan.found());
+    }
+}
@@ -1,24 +1,54 @@
 //! Traffic policies for request handling
 
-use std::time::Duration;
+use crate::state_store::StateStore;
+use crate::trailers::GrpcStatus;
+use anyhow::{Result, anyhow};
+use hyper::{Method, StatusCode};
+use router_api::v1alpha1::vpc_route::RetryPolicy as RetrySpec;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
-use tracing::debug;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
 
 /// Timeout policy for requests
-#[derive(Clone, Debug)]
+///
+/// Each phase of an upstream exchange has its own limit, so a long download
+/// that keeps streaming isn't cut off by a timeout sized for the wait on
+/// response headers.
+#[derive(Clone, Debug, PartialEq)]
 pub struct TimeoutPolicy {
-    /// Total timeout for the request
-    pub request_timeout: Duration,
-    /// Connection timeout
+    /// Time to establish a connection, including waiting for a free one in
+    /// a limited pool
     pub connect_timeout: Duration,
+    /// Time from sending the request until the response headers arrive
+    pub first_byte_timeout: Duration,
+    /// Longest gap between chunks of the response body; unlimited when unset
+    pub idle_timeout: Option<Duration>,
+    /// Time for the whole exchange, body included; unlimited when unset
+    pub total_timeout: Option<Duration>,
+}
+
+impl TimeoutPolicy {
+    /// Policy giving connecting and waiting for headers `timeout` each, with
+    /// no limit on reading the body
+    pub fn uniform(timeout: Duration) -> Self {
+        Self {
+            connect_timeout: timeout,
+            first_byte_timeout: timeout,
+            idle_timeout: None,
+            total_timeout: None,
+        }
+    }
 }
 
 impl Default for TimeoutPolicy {
     fn default() -> Self {
         Self {
-            request_timeout: Duration::from_secs(30),
             connect_timeout: Duration::from_secs(10),
+            first_byte_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(60)),
+            total_timeout: None,
         }
     }
 }
@@ -30,6 +60,8 @@ pub struct RetryPolicy {
     pub max_retries: u32,
     /// HTTP status codes that trigger a retry
     pub retryable_status_codes: Vec<u16>,
+    /// gRPC statuses, read from response trailers, that trigger a retry
+    pub retryable_grpc_statuses: Vec<GrpcStatus>,
     /// Initial backoff duration
     pub initial_backoff: Duration,
     /// Maximum backoff duration
@@ -41,6 +73,7 @@ impl Default for RetryPolicy {
         Self {
             max_retries: 3,
             retryable_status_codes: vec![502, 503, 504], // Bad Gateway, Service Unavailable, Gateway Timeout
+            retryable_grpc_statuses: Vec::new(),
             initial_backoff: Duration::from_millis(100),
             max_backoff: Duration::from_secs(10),
         }
@@ -48,11 +81,36 @@ impl Default for RetryPolicy {
 }
 
 impl RetryPolicy {
+    /// Create a policy from a VPCRoute retry spec
+    pub fn from_spec(spec: &RetrySpec) -> Result<Self> {
+        let retryable_grpc_statuses = spec
+            .retry_on_grpc_status
+            .iter()
+            .map(|name| GrpcStatus::parse(name).ok_or_else(|| anyhow!("Unknown gRPC status {:?}", name)))
+            .collect::<Result<_>>()?;
+        let mut policy = Self {
+            max_retries: spec.max_retries,
+            retryable_status_codes: spec.retry_on_status.clone(),
+            retryable_grpc_statuses,
+            ..Self::default()
+        };
+        if let Some(backoff) = &spec.backoff {
+            policy.initial_backoff = Duration::from_millis(backoff.initial_ms as u64);
+            policy.max_backoff = Duration::from_millis(backoff.max_ms as u64);
+        }
+        Ok(policy)
+    }
+
     /// Check if a status code should trigger a retry
     pub fn should_retry(&self, status: u16) -> bool {
         self.retryable_status_codes.contains(&status)
     }
 
+    /// Check if a gRPC status should trigger a retry
+    pub fn should_retry_grpc(&self, status: GrpcStatus) -> bool {
+        self.retryable_grpc_statuses.contains(&status)
+    }
+
     /// Calculate backoff duration for the given retry count
     pub fn backoff_duration(&self, retry_count: u32) -> Duration {
         let base = self.initial_backoff.as_millis() as u64;
@@ -62,6 +120,16 @@ impl RetryPolicy {
     }
 }
 
+/// Whether repeating a request has the same effect as sending it once
+///
+/// Per RFC 9110, section 9.2.2.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
 /// Circuit breaker states
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CircuitState {
@@ -73,6 +141,27 @@ pub enum CircuitState {
     HalfOpen,
 }
 
+impl CircuitState {
+    /// Get the state name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half-open",
+        }
+    }
+
+    /// Parse a state name
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "closed" => Some(CircuitState::Closed),
+            "open" => Some(CircuitState::Open),
+            "half-open" => Some(CircuitState::HalfOpen),
+            _ => None,
+        }
+    }
+}
+
 /// Circuit breaker for preventing cascading failures
 pub struct CircuitBreaker {
     /// Current state
@@ -81,6 +170,8 @@ pub struct CircuitBreaker {
     failure_count: Arc<AtomicU32>,
     /// Success count (for half-open state)
     success_count: Arc<AtomicU32>,
+    /// When the circuit last opened
+    opened_at: Arc<Mutex<Option<Instant>>>,
     /// Configuration
     config: CircuitBreakerConfig,
 }
@@ -94,6 +185,8 @@ pub struct CircuitBreakerConfig {
     pub success_threshold: u32,
     /// Duration to wait before trying half-open
     pub timeout: Duration,
+    /// gRPC statuses counted as failures; gRPC errors arrive with HTTP 200
+    pub grpc_failure_statuses: Vec<GrpcStatus>,
 }
 
 impl Default for CircuitBreakerConfig {
@@ -102,6 +195,12 @@ impl Default for CircuitBreakerConfig {
             failure_threshold: 5,
             success_threshold: 2,
             timeout: Duration::from_secs(60),
+            grpc_failure_statuses: vec![
+                GrpcStatus::DEADLINE_EXCEEDED,
+                GrpcStatus::RESOURCE_EXHAUSTED,
+                GrpcStatus::INTERNAL,
+                GrpcStatus::UNAVAILABLE,
+            ],
         }
     }
 }
@@ -113,12 +212,29 @@ impl CircuitBreaker {
             state: Arc::new(AtomicU32::new(CircuitState::Closed as u32)),
             failure_count: Arc::new(AtomicU32::new(0)),
             success_count: Arc::new(AtomicU32::new(0)),
+            opened_at: Arc::new(Mutex::new(None)),
             config,
         }
     }
 
     /// Get the current state
+    ///
+    /// An open circuit becomes half-open once `config.timeout` has passed
+    /// since it opened.
     pub fn state(&self) -> CircuitState {
+        if self.state.load(Ordering::SeqCst) == CircuitState::Open as u32 && self.open_timeout_elapsed() {
+            let swapped = self.state.compare_exchange(
+                CircuitState::Open as u32,
+                CircuitState::HalfOpen as u32,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            if swapped.is_ok() {
+                debug!("Circuit breaker: Transitioning to half-open after {:?}", self.config.timeout);
+                self.success_count.store(0, Ordering::SeqCst);
+            }
+        }
+
         let state_u32 = self.state.load(Ordering::SeqCst);
         match state_u32 {
             0 => CircuitState::Closed,
@@ -156,13 +272,13 @@ impl CircuitBreaker {
                 let failure_count = self.failure_count.fetch_add(1, Ordering::SeqCst) + 1;
                 if failure_count >= self.config.failure_threshold {
                     debug!("Circuit breaker: Opening circuit after {} failures", failure_count);
-                    self.state.store(CircuitState::Open as u32, Ordering::SeqCst);
+                    self.open();
                     self.success_count.store(0, Ordering::SeqCst);
                 }
             }
             CircuitState::HalfOpen => {
                 debug!("Circuit breaker: Opening circuit - failure during half-open");
-                self.state.store(CircuitState::Open as u32, Ordering::SeqCst);
+                self.open();
                 self.failure_count.store(0, Ordering::SeqCst);
                 self.success_count.store(0, Ordering::SeqCst);
             }
@@ -182,24 +298,208 @@ impl CircuitBreaker {
             self.state.store(CircuitState::HalfOpen as u32, Ordering::SeqCst);
         }
     }
+
+    fn open(&self) {
+        *self.opened_at.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        self.state.store(CircuitState::Open as u32, Ordering::SeqCst);
+    }
+
+    fn open_timeout_elapsed(&self) -> bool {
+        self.opened_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some_and(|t| t.elapsed() >= self.config.timeout)
+    }
+
+    /// Adopt a state observed by another replica
+    ///
+    /// A half-open breaker ignores a shared open state, so the shared
+    /// entry can't keep restarting its timer and block trial requests.
+    pub fn adopt(&self, state: CircuitState) {
+        let current = self.state();
+        if current == CircuitState::HalfOpen && state == CircuitState::Open {
+            return;
+        }
+        if current != state {
+            debug!("Circuit breaker: Adopting shared state {}", state.as_str());
+            if state == CircuitState::Open {
+                self.open();
+            } else {
+                self.state.store(state as u32, Ordering::SeqCst);
+            }
+            self.failure_count.store(0, Ordering::SeqCst);
+            self.success_count.store(0, Ordering::SeqCst);
+        }
+    }
 }
 
-/// Complete traffic policy configuration
-#[derive(Clone, Debug)]
-pub struct TrafficPolicy {
-    pub timeout: TimeoutPolicy,
-    pub retry: RetryPolicy,
-    pub circuit_breaker: CircuitBreakerConfig,
+/// Circuit breaker states shared between gateway replicas
+///
+/// A replica that trips or closes a breaker publishes the new state; the
+/// others adopt it so they stop (or resume) sending traffic together.
+pub struct SharedCircuitState {
+    store: Arc<dyn StateStore>,
+    ttl: Duration,
 }
 
-impl Default for TrafficPolicy {
-    fn default() -> Self {
+impl SharedCircuitState {
+    /// Create shared circuit state whose entries expire after `ttl`
+    pub fn new(store: Arc<dyn StateStore>, ttl: Duration) -> Self {
+        Self { store, ttl }
+    }
+
+    /// Publish an upstream's circuit state
+    pub async fn publish(&self, upstream: &str, state: CircuitState) -> Result<()> {
+        self.store
+            .set(&Self::key(upstream), state.as_str().as_bytes(), Some(self.ttl))
+            .await
+    }
+
+    /// Fetch the last published circuit state of an upstream
+    pub async fn fetch(&self, upstream: &str) -> Result<Option<CircuitState>> {
+        let state = self.store.get(&Self::key(upstream)).await?;
+        Ok(state
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| CircuitState::parse(&v)))
+    }
+
+    /// Adopt the shared state of an upstream into a local breaker
+    pub async fn sync(&self, upstream: &str, breaker: &CircuitBreaker) -> Result<()> {
+        if let Some(state) = self.fetch(upstream).await? {
+            breaker.adopt(state);
+        }
+        Ok(())
+    }
+
+    fn key(upstream: &str) -> String {
+        format!("circuit:{}", upstream)
+    }
+}
+
+/// How often a breaker re-reads the shared state of its upstream
+const SHARED_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Circuit breaker for one upstream, with the bookkeeping around it
+struct UpstreamCircuit {
+    breaker: CircuitBreaker,
+    /// When the shared state was last read
+    synced_at: Mutex<Option<Instant>>,
+}
+
+/// Circuit breakers per upstream authority (host:port)
+pub struct CircuitBreakerRegistry {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<String, Arc<UpstreamCircuit>>>,
+    shared: Option<SharedCircuitState>,
+}
+
+impl CircuitBreakerRegistry {
+    /// Create a registry whose breakers use `config`
+    pub fn new(config: CircuitBreakerConfig) -> Self {
         Self {
-            timeout: TimeoutPolicy::default(),
-            retry: RetryPolicy::default(),
-            circuit_breaker: CircuitBreakerConfig::default(),
+            config,
+            circuits: Mutex::new(HashMap::new()),
+            shared: None,
+        }
+    }
+
+    /// Share circuit states with other replicas
+    pub fn with_shared_state(mut self, shared: SharedCircuitState) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Whether a response counts as a failure for its upstream's circuit
+    ///
+    /// 5xx responses and gRPC responses with a failure status do.
+    pub fn is_failure(&self, status: StatusCode, grpc_status: Option<GrpcStatus>) -> bool {
+        status.is_server_error()
+            || grpc_status.is_some_and(|s| self.config.grpc_failure_statuses.contains(&s))
+    }
+
+    /// Current state of an upstream's circuit
+    pub fn state(&self, upstream: &str) -> CircuitState {
+        self.circuit(upstream).breaker.state()
+    }
+
+    /// Check whether a request to `upstream` may be sent
+    pub async fn can_attempt(&self, upstream: &str) -> bool {
+        let circuit = self.circuit(upstream);
+        self.sync(upstream, &circuit).await;
+        circuit.breaker.can_attempt()
+    }
+
+    /// Record a successful request to `upstream`
+    pub async fn record_success(&self, upstream: &str) {
+        let circuit = self.circuit(upstream);
+        let before = circuit.breaker.state();
+        circuit.breaker.record_success();
+        self.published(upstream, &circuit, before).await;
+    }
+
+    /// Record a failed request to `upstream`
+    pub async fn record_failure(&self, upstream: &str) {
+        let circuit = self.circuit(upstream);
+        let before = circuit.breaker.state();
+        circuit.breaker.record_failure();
+        self.published(upstream, &circuit, before).await;
+    }
+
+    fn circuit(&self, upstream: &str) -> Arc<UpstreamCircuit> {
+        self.circuits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(upstream.to_string())
+            .or_insert_with(|| {
+                Arc::new(UpstreamCircuit {
+                    breaker: CircuitBreaker::new(self.config.clone()),
+                    synced_at: Mutex::new(None),
+                })
+            })
+            .clone()
+    }
+
+    /// Adopt the shared state, at most once per sync interval
+    async fn sync(&self, upstream: &str, circuit: &UpstreamCircuit) {
+        let Some(shared) = &self.shared else { return };
+        {
+            let mut synced_at = circuit.synced_at.lock().unwrap_or_else(|e| e.into_inner());
+            if synced_at.is_some_and(|t| t.elapsed() < SHARED_SYNC_INTERVAL) {
+                return;
+            }
+            *synced_at = Some(Instant::now());
+        }
+
+        if let Err(e) = shared.sync(upstream, &circuit.breaker).await {
+            debug!("Failed to read shared circuit state of {}: {}", upstream, e);
         }
     }
+
+    /// Log and publish a state change
+    async fn published(&self, upstream: &str, circuit: &UpstreamCircuit, before: CircuitState) {
+        let after = circuit.breaker.state();
+        if after == before {
+            return;
+        }
+        if after == CircuitState::Open {
+            warn!("Circuit opened for upstream {}", upstream);
+        } else if after == CircuitState::Closed {
+            info!("Circuit closed for upstream {}", upstream);
+        }
+        if let Some(shared) = &self.shared {
+            if let Err(e) = shared.publish(upstream, after).await {
+                debug!("Failed to publish circuit state of {}: {}", upstream, e);
+            }
+        }
+    }
+}
+
+/// Complete traffic policy configuration
+#[derive(Clone, Debug, Default)]
+pub struct TrafficPolicy {
+    pub timeout: TimeoutPolicy,
+    pub retry: RetryPolicy,
+    pub circuit_breaker: CircuitBreakerConfig,
 }
 
 #[cfg(test)]
@@ -216,6 +516,44 @@ mod tests {
         assert!(!policy.should_retry(404));
     }
 
+    #[test]
+    fn test_retry_policy_from_spec() {
+        use router_api::v1alpha1::vpc_route::BackoffConfig;
+
+        let spec = RetrySpec {
+            max_retries: 2,
+            retry_on_status: vec![503],
+            retry_on_grpc_status: vec!["UNAVAILABLE".to_string(), "resource_exhausted".to_string()],
+            backoff: Some(BackoffConfig {
+                initial_ms: 50,
+                max_ms: 1000,
+            }),
+        };
+        let policy = RetryPolicy::from_spec(&spec).unwrap();
+        assert_eq!(policy.max_retries, 2);
+        assert!(policy.should_retry(503));
+        assert!(!policy.should_retry(502));
+        assert!(policy.should_retry_grpc(GrpcStatus::UNAVAILABLE));
+        assert!(policy.should_retry_grpc(GrpcStatus::RESOURCE_EXHAUSTED));
+        assert!(!policy.should_retry_grpc(GrpcStatus::INTERNAL));
+        assert_eq!(policy.initial_backoff, Duration::from_millis(50));
+
+        let spec = RetrySpec {
+            retry_on_grpc_status: vec!["SOMETIMES".to_string()],
+            ..Default::default()
+        };
+        assert!(RetryPolicy::from_spec(&spec).is_err());
+    }
+
+    #[test]
+    fn test_idempotent_methods() {
+        assert!(is_idempotent(&Method::GET));
+        assert!(is_idempotent(&Method::PUT));
+        assert!(is_idempotent(&Method::DELETE));
+        assert!(!is_idempotent(&Method::POST));
+        assert!(!is_idempotent(&Method::PATCH));
+    }
+
     #[test]
     fn test_retry_policy_backoff() {
         let policy = RetryPolicy::default();
@@ -234,6 +572,7 @@ mod tests {
             failure_threshold: 3,
             success_threshold: 2,
             timeout: Duration::from_secs(60),
+            ..Default::default()
         };
         let cb = CircuitBreaker::new(config);
 
@@ -255,6 +594,7 @@ mod tests {
             failure_threshold: 1,
             success_threshold: 1,
             timeout: Duration::from_secs(60),
+            ..Default::default()
         };
         let cb = CircuitBreaker::new(config);
 
@@ -270,4 +610,67 @@ mod tests {
         cb.record_success();
         assert_eq!(cb.state(), CircuitState::Closed);
     }
+
+    #[test]
+    fn test_circuit_breaker_half_opens_after_timeout() {
+        let cb = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 1,
+            success_threshold: 1,
+            timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+
+        cb.record_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cb.state(), CircuitState::HalfOpen);
+        assert!(cb.can_attempt());
+
+        // A failed trial reopens the circuit and restarts the timer
+        cb.record_failure();
+        assert_eq!(cb.state(), CircuitState::Open);
+        std::thread::sleep(Duration::from_millis(30));
+        cb.record_success();
+        assert_eq!(cb.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_shared_circuit_state() {
+        use crate::state_store::MemoryStateStore;
+
+        let shared = SharedCircuitState::new(Arc::new(MemoryStateStore::new()), Duration::from_secs(60));
+        let cb = CircuitBreaker::new(CircuitBreakerConfig::default());
+
+        // Nothing published yet: local state is kept
+        shared.sync("orders:8080", &cb).await.unwrap();
+        assert_eq!(cb.state(), CircuitState::Closed);
+
+        shared.publish("orders:8080", CircuitState::Open).await.unwrap();
+        assert_eq!(shared.fetch("orders:8080").await.unwrap(), Some(CircuitState::Open));
+        shared.sync("orders:8080", &cb).await.unwrap();
+        assert!(!cb.can_attempt());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_registry() {
+        let registry = CircuitBreakerRegistry::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            success_threshold: 1,
+            timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+
+        registry.record_failure("orders:8080").await;
+        registry.record_failure("orders:8080").await;
+        assert!(!registry.can_attempt("orders:8080").await);
+        // Other upstreams are unaffected
+        assert!(registry.can_attempt("billing:8080").await);
+
+        // Half-open after the timeout, closed after a trial success
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(registry.can_attempt("orders:8080").await);
+        assert_eq!(registry.state("orders:8080"), CircuitState::HalfOpen);
+        registry.record_success("orders:8080").await;
+        assert_eq!(registry.state("orders:8080"), CircuitState::Closed);
+    }
 }
@@ -0,0 +1,78 @@
+//! Upstream connection pool settings
+//!
+//! The forwarder's default clients share one pool configuration. An
+//! upstream can get its own, set from its VPCService or gateway
+//! configuration, with a cap on the requests it has in flight. Over
+//! HTTP/1.1 each in-flight request holds its own connection, so the cap
+//! bounds the connections open to the upstream.
+
+use router_api::v1alpha1::vpc_service::ConnectionPoolConfig;
+use std::time::Duration;
+
+/// Connection pool configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolConfig {
+    /// Idle connections kept per host
+    pub max_idle_per_host: usize,
+    /// How long an idle connection is kept; forever when None
+    pub idle_timeout: Option<Duration>,
+    /// TCP keepalive interval for open connections
+    pub tcp_keepalive: Option<Duration>,
+    /// Most requests in flight at once; unlimited when None
+    pub max_connections: Option<usize>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: Some(Duration::from_secs(30)),
+            max_connections: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Create a configuration from a VPCService connection pool spec
+    ///
+    /// Settings the spec leaves out keep their defaults.
+    pub fn from_spec(spec: &ConnectionPoolConfig) -> Self {
+        let mut config = Self::default();
+        if let Some(max_idle) = spec.max_idle_per_host {
+            config.max_idle_per_host = max_idle as usize;
+        }
+        if let Some(secs) = spec.idle_timeout_seconds {
+            config.idle_timeout = Some(Duration::from_secs(secs as u64));
+        }
+        if let Some(secs) = spec.tcp_keepalive_seconds {
+            config.tcp_keepalive = Some(Duration::from_secs(secs as u64)).filter(|d| !d.is_zero());
+        }
+        if let Some(max) = spec.max_connections {
+            config.max_connections = Some(max as usize);
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_spec() {
+        let spec = ConnectionPoolConfig {
+            max_idle_per_host: Some(4),
+            idle_timeout_seconds: Some(15),
+            tcp_keepalive_seconds: Some(0),
+            max_connections: Some(64),
+        };
+        let config = PoolConfig::from_spec(&spec);
+        assert_eq!(config.max_idle_per_host, 4);
+        assert_eq!(config.idle_timeout, Some(Duration::from_secs(15)));
+        assert_eq!(config.tcp_keepalive, None);
+        assert_eq!(config.max_connections, Some(64));
+
+        assert_eq!(PoolConfig::from_spec(&ConnectionPoolConfig::default()), PoolConfig::default());
+    }
+}
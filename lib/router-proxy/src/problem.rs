@@ -0,0 +1,169 @@
+//! RFC 7807 problem details for gateway errors
+//!
+//! Errors the gateway answers itself carry a [`ProblemDetails`] response
+//! extension next to their plaintext body. With the problem+json format
+//! enabled, the body is replaced by the JSON document, completed with the
+//! request path, request ID, and route.
+
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use serde::Serialize;
+
+/// Media type of problem details documents
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// How gateway errors are written
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorFormat {
+    /// Short plaintext bodies
+    #[default]
+    Text,
+    /// application/problem+json documents
+    ProblemJson,
+}
+
+impl ErrorFormat {
+    /// Parse a format setting ("text" or "problem+json")
+    pub fn from_string(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "text" | "plain" => Some(ErrorFormat::Text),
+            "problem+json" | "problem-json" | "json" => Some(ErrorFormat::ProblemJson),
+            _ => None,
+        }
+    }
+}
+
+/// Problem details document
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemDetails {
+    /// URI identifying the problem type
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// Short summary of the problem type
+    pub title: String,
+    /// HTTP status code
+    pub status: u16,
+    /// Explanation of this occurrence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Request path the problem occurred on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// ID of the failed request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Route that matched the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+impl ProblemDetails {
+    /// Problem for a status code, titled with its reason phrase
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        let detail = detail.into();
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: Some(detail.trim().to_string()).filter(|d| !d.is_empty()),
+            instance: None,
+            request_id: None,
+            route: None,
+        }
+    }
+
+    /// Set the request path the problem occurred on
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Set the ID of the failed request
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Set the route that matched the request
+    pub fn with_route(mut self, route: impl Into<String>) -> Self {
+        self.route = Some(route.into());
+        self
+    }
+
+    /// JSON document
+    pub fn to_json(&self) -> Bytes {
+        Bytes::from(serde_json::to_vec(self).unwrap_or_default())
+    }
+}
+
+/// Plaintext error response carrying its problem details
+///
+/// The body reads "<reason phrase>: <detail>".
+pub fn error_response(status: StatusCode, detail: &str) -> Response<Bytes> {
+    let body = format!("{}: {}\n", status.canonical_reason().unwrap_or("Error"), detail);
+    let mut response = Response::builder().status(status).body(Bytes::from(body)).unwrap();
+    response.extensions_mut().insert(ProblemDetails::new(status, detail));
+    response
+}
+
+/// Replace the body of a gateway error with its problem details document
+///
+/// `complete` fills in the request-specific members. Responses without
+/// problem details, such as those from upstreams, are left alone.
+pub fn render<B, F>(response: Response<B>, complete: F) -> Response<B>
+where
+    B: From<Bytes>,
+    F: FnOnce(ProblemDetails) -> ProblemDetails,
+{
+    let (mut parts, body) = response.into_parts();
+    let Some(problem) = parts.extensions.remove::<ProblemDetails>() else {
+        return Response::from_parts(parts, body);
+    };
+    parts.headers.insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, B::from(complete(problem).to_json()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_document() {
+        let problem = ProblemDetails::new(StatusCode::GATEWAY_TIMEOUT, "Backend service request timeout\n")
+            .with_instance("/api/orders")
+            .with_request_id("req-42");
+        let json: serde_json::Value = serde_json::from_slice(&problem.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Gateway Timeout",
+                "status": 504,
+                "detail": "Backend service request timeout",
+                "instance": "/api/orders",
+                "requestId": "req-42",
+            })
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let response = error_response(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded");
+        assert_eq!(response.body(), &Bytes::from("Too Many Requests: Rate limit exceeded\n"));
+
+        let response = render(response, |p| p.with_route("orders"));
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        let json: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(json["status"], 429);
+        assert_eq!(json["detail"], "Rate limit exceeded");
+        assert_eq!(json["route"], "orders");
+
+        // Upstream responses have no problem details and pass through
+        let upstream = Response::new(Bytes::from("upstream error"));
+        assert_eq!(render(upstream, |p| p).body(), &Bytes::from("upstream error"));
+        assert_eq!(ErrorFormat::from_string("problem+json"), Some(ErrorFormat::ProblemJson));
+    }
+}
@@ -0,0 +1,94 @@
+//! Request rate limiting backed by a shared state store
+//!
+//! Counts requests per client key in one-second windows. With a Redis or
+//! etcd store, every gateway replica draws from the same budget.
+
+use crate::state_store::StateStore;
+use anyhow::Result;
+use router_api::v1alpha1::vpc_egress::RateLimitConfig;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Fixed-window rate limiter
+pub struct RateLimiter {
+    store: Arc<dyn StateStore>,
+    config: RateLimitConfig,
+    prefix: String,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter whose counters live under `prefix` in `store`
+    pub fn new(store: Arc<dyn StateStore>, config: RateLimitConfig, prefix: &str) -> Self {
+        Self {
+            store,
+            config,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Get the limiter configuration
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Requests allowed per window
+    pub fn limit(&self) -> u32 {
+        self.config.requests_per_second + self.config.burst_size
+    }
+
+    /// Count a request for `key` and return whether it is within the limit
+    pub async fn check(&self, key: &str) -> Result<bool> {
+        let window = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.check_window(key, window).await
+    }
+
+    async fn check_window(&self, key: &str, window: u64) -> Result<bool> {
+        let counter = format!("{}:ratelimit:{}:{}", self.prefix, key, window);
+        // Keep the counter a little past its window so replicas with slight
+        // clock skew still see it
+        let count = self.store.increment(&counter, 1, Some(Duration::from_secs(2))).await?;
+        Ok(count <= self.limit() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::MemoryStateStore;
+
+    fn limiter(store: Arc<dyn StateStore>) -> RateLimiter {
+        RateLimiter::new(
+            store,
+            RateLimitConfig {
+                requests_per_second: 2,
+                burst_size: 1,
+            },
+            "gw",
+        )
+    }
+
+    #[tokio::test]
+    async fn test_limits_per_window() {
+        let limiter = limiter(Arc::new(MemoryStateStore::new()));
+        for _ in 0..3 {
+            assert!(limiter.check_window("10.0.0.1", 100).await.unwrap());
+        }
+        assert!(!limiter.check_window("10.0.0.1", 100).await.unwrap());
+
+        // Other clients and later windows have their own budget
+        assert!(limiter.check_window("10.0.0.2", 100).await.unwrap());
+        assert!(limiter.check_window("10.0.0.1", 101).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_replicas_share_budget() {
+        let store: Arc<dyn StateStore> = Arc::new(MemoryStateStore::new());
+        let a = limiter(store.clone());
+        let b = limiter(store);
+
+        assert!(a.check_window("client", 7).await.unwrap());
+        assert!(b.check_window("client", 7).await.unwrap());
+        assert!(a.check_window("client", 7).await.unwrap());
+        assert!(!b.check_window("client", 7).await.unwrap());
+    }
+}
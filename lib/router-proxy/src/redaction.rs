@@ -0,0 +1,222 @@
+//! Redaction of sensitive request data before it reaches logs and traces
+//!
+//! A `Redactor` masks configured header values, query parameters, and JSON
+//! body fields. It is carried on the `MiddlewareContext` so every logging
+//! middleware applies the same rules.
+
+use router_api::v1alpha1::vpc_route::RedactionPolicy;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Replacement value for redacted data
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers that are always redacted
+const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Masks sensitive header values, query parameters, and JSON body fields
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redactor {
+    /// Lowercased header names to redact
+    headers: HashSet<String>,
+    /// Query parameter names to redact
+    query_params: HashSet<String>,
+    /// JSON object keys to redact at any depth
+    json_fields: HashSet<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self {
+            headers: DEFAULT_REDACTED_HEADERS.iter().map(|h| h.to_string()).collect(),
+            query_params: HashSet::new(),
+            json_fields: HashSet::new(),
+        }
+    }
+}
+
+impl Redactor {
+    /// Create a redactor with the default header rules
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a redactor from a VPCRoute redaction policy (on top of the defaults)
+    pub fn from_policy(policy: &RedactionPolicy) -> Self {
+        Self::new().merge_policy(policy)
+    }
+
+    /// Add the rules from a VPCRoute redaction policy
+    pub fn merge_policy(self, policy: &RedactionPolicy) -> Self {
+        self.with_headers(policy.headers.iter().cloned())
+            .with_query_params(policy.query_params.iter().cloned())
+            .with_json_fields(policy.json_fields.iter().cloned())
+    }
+
+    /// Redact additional headers
+    pub fn with_headers(mut self, headers: impl IntoIterator<Item = String>) -> Self {
+        self.headers.extend(headers.into_iter().map(|h| h.to_lowercase()));
+        self
+    }
+
+    /// Redact additional query parameters
+    pub fn with_query_params(mut self, params: impl IntoIterator<Item = String>) -> Self {
+        self.query_params.extend(params);
+        self
+    }
+
+    /// Redact additional JSON body fields
+    pub fn with_json_fields(mut self, fields: impl IntoIterator<Item = String>) -> Self {
+        self.json_fields.extend(fields);
+        self
+    }
+
+    /// Check if a header should be redacted
+    pub fn is_redacted_header(&self, name: &str) -> bool {
+        self.headers.contains(&name.to_lowercase())
+    }
+
+    /// Get a header value as it may be logged
+    pub fn header_value<'a>(&self, name: &str, value: &'a str) -> &'a str {
+        if self.is_redacted_header(name) {
+            REDACTED
+        } else {
+            value
+        }
+    }
+
+    /// Copy a header map with sensitive values masked
+    pub fn redact_headers(&self, headers: &HashMap<String, String>) -> HashMap<String, String> {
+        headers
+            .iter()
+            .map(|(k, v)| (k.clone(), self.header_value(k, v).to_string()))
+            .collect()
+    }
+
+    /// Mask sensitive parameters in a raw query string
+    pub fn redact_query(&self, query: &str) -> String {
+        query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, _)) if self.query_params.contains(name) => {
+                    format!("{}={}", name, REDACTED)
+                }
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Mask sensitive parameters in a path or URL with a query string
+    pub fn redact_uri(&self, uri: &str) -> String {
+        match uri.split_once('?') {
+            Some((path, query)) => format!("{}?{}", path, self.redact_query(query)),
+            None => uri.to_string(),
+        }
+    }
+
+    /// Mask sensitive fields in a JSON value, at any depth
+    pub fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    if self.json_fields.contains(key) {
+                        *field = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_json(field);
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.redact_json(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Mask sensitive fields in a JSON body
+    ///
+    /// Returns `None` if the body is not valid JSON.
+    pub fn redact_json_body(&self, body: &[u8]) -> Option<String> {
+        let mut value: Value = serde_json::from_slice(body).ok()?;
+        self.redact_json(&mut value);
+        Some(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_redacts_credentials() {
+        let redactor = Redactor::new();
+        assert!(redactor.is_redacted_header("Authorization"));
+        assert!(redactor.is_redacted_header("cookie"));
+        assert!(!redactor.is_redacted_header("content-type"));
+        assert_eq!(redactor.header_value("authorization", "Bearer abc"), REDACTED);
+        assert_eq!(redactor.header_value("accept", "*/*"), "*/*");
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let redactor = Redactor::new().with_headers(vec!["X-Api-Key".to_string()]);
+        let mut headers = HashMap::new();
+        headers.insert("x-api-key".to_string(), "secret".to_string());
+        headers.insert("user-agent".to_string(), "curl".to_string());
+
+        let redacted = redactor.redact_headers(&headers);
+        assert_eq!(redacted["x-api-key"], REDACTED);
+        assert_eq!(redacted["user-agent"], "curl");
+    }
+
+    #[test]
+    fn test_redact_query() {
+        let redactor = Redactor::new().with_query_params(vec!["token".to_string()]);
+        assert_eq!(
+            redactor.redact_query("page=2&token=abc&sort"),
+            "page=2&token=[REDACTED]&sort"
+        );
+        assert_eq!(redactor.redact_query("page=2"), "page=2");
+        assert_eq!(redactor.redact_uri("/login?token=abc"), "/login?token=[REDACTED]");
+        assert_eq!(redactor.redact_uri("/login"), "/login");
+    }
+
+    #[test]
+    fn test_redact_json_nested() {
+        let redactor = Redactor::new().with_json_fields(vec!["password".to_string(), "ssn".to_string()]);
+        let body = br#"{"user":"bob","password":"hunter2","profile":{"ssn":"123"},"items":[{"ssn":"456"}]}"#;
+
+        let redacted: Value = serde_json::from_str(&redactor.redact_json_body(body).unwrap()).unwrap();
+        assert_eq!(redacted["user"], "bob");
+        assert_eq!(redacted["password"], REDACTED);
+        assert_eq!(redacted["profile"]["ssn"], REDACTED);
+        assert_eq!(redacted["items"][0]["ssn"], REDACTED);
+    }
+
+    #[test]
+    fn test_redact_json_body_invalid() {
+        let redactor = Redactor::new();
+        assert_eq!(redactor.redact_json_body(b"not json"), None);
+    }
+
+    #[test]
+    fn test_from_policy() {
+        let policy = RedactionPolicy {
+            headers: vec!["X-Session".to_string()],
+            query_params: vec!["key".to_string()],
+            json_fields: vec!["card".to_string()],
+        };
+        let redactor = Redactor::from_policy(&policy);
+        assert!(redactor.is_redacted_header("x-session"));
+        assert!(redactor.is_redacted_header("authorization"));
+        assert_eq!(redactor.redact_query("key=1"), "key=[REDACTED]");
+    }
+}
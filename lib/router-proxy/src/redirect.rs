@@ -0,0 +1,254 @@
+//! Redirects answered at the edge
+//!
+//! Routes with a redirect action are answered by the gateway without a
+//! backend, e.g. for HTTP to HTTPS upgrades or legacy path moves. Parts of
+//! the location the action doesn't set are taken from the request.
+
+use crate::path_rewrite::PathRewrite;
+use anyhow::{Result, anyhow, bail};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_LENGTH, LOCATION};
+use hyper::http::uri::Authority;
+use hyper::{Response, StatusCode, Uri};
+use router_api::v1alpha1::vpc_route::{RedirectAction, RouteMatch};
+
+/// How the redirect location's path is built
+#[derive(Clone, Debug)]
+enum RedirectPath {
+    /// Keep the request path
+    Keep,
+    /// Use a fixed path
+    Replace(String),
+    /// Rewrite the request path
+    Rewrite(PathRewrite),
+}
+
+/// Validated redirect for a route
+#[derive(Clone, Debug)]
+pub struct RouteRedirect {
+    status: StatusCode,
+    scheme: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    path: RedirectPath,
+    strip_query: bool,
+}
+
+impl RouteRedirect {
+    /// Create a redirect to another scheme, host, or path
+    pub fn new(status: StatusCode) -> Result<Self> {
+        if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+            bail!("Unsupported redirect status {}", status.as_u16());
+        }
+        Ok(Self {
+            status,
+            scheme: None,
+            host: None,
+            port: None,
+            path: RedirectPath::Keep,
+            strip_query: false,
+        })
+    }
+
+    /// Create a redirect from a VPCRoute action and the route's match
+    pub fn from_action(action: &RedirectAction, route_match: &RouteMatch) -> Result<Self> {
+        let status = StatusCode::from_u16(action.status_code)
+            .map_err(|_| anyhow!("Invalid redirect status {}", action.status_code))?;
+        let mut redirect = Self::new(status)?;
+        if let Some(scheme) = &action.scheme {
+            redirect = redirect.with_scheme(scheme)?;
+        }
+        if let Some(host) = &action.host {
+            redirect = redirect.with_host(host);
+        }
+        if let Some(port) = action.port {
+            redirect = redirect.with_port(port);
+        }
+        match (&action.path, &action.replace_prefix) {
+            (Some(_), Some(_)) => bail!("Redirect sets both path and replacePrefix"),
+            (Some(path), None) => redirect = redirect.with_path(path),
+            (None, Some(replacement)) => {
+                let prefix = route_match
+                    .path_prefix
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Redirect replacePrefix requires a pathPrefix match"))?;
+                redirect = redirect.with_path_rewrite(PathRewrite::replace_prefix(prefix, replacement));
+            }
+            (None, None) => {}
+        }
+        if action.strip_query {
+            redirect = redirect.without_query();
+        }
+        Ok(redirect)
+    }
+
+    /// Redirect to a scheme ("http" or "https")
+    pub fn with_scheme(mut self, scheme: &str) -> Result<Self> {
+        let scheme = scheme.to_ascii_lowercase();
+        if scheme != "http" && scheme != "https" {
+            bail!("Unsupported redirect scheme {:?}", scheme);
+        }
+        self.scheme = Some(scheme);
+        Ok(self)
+    }
+
+    /// Redirect to a host
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Redirect to a port
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Redirect to a fixed path
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = RedirectPath::Replace(path.to_string());
+        self
+    }
+
+    /// Redirect to the rewritten request path
+    pub fn with_path_rewrite(mut self, rewrite: PathRewrite) -> Self {
+        self.path = RedirectPath::Rewrite(rewrite);
+        self
+    }
+
+    /// Drop the query string from the location
+    pub fn without_query(mut self) -> Self {
+        self.strip_query = true;
+        self
+    }
+
+    /// Status code sent with the redirect
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Location for a request
+    ///
+    /// `scheme` and `host` are the ones the client used. Returns None when
+    /// the location is the request's own URL, so a redirect can't loop.
+    pub fn location(&self, scheme: &str, host: Option<&str>, uri: &Uri) -> Option<String> {
+        let request_authority = host.and_then(|h| h.parse::<Authority>().ok());
+        let target_scheme = self.scheme.as_deref().unwrap_or(scheme);
+        let target_host = self
+            .host
+            .as_deref()
+            .or_else(|| request_authority.as_ref().map(|a| a.host()))?;
+        // A request's port belongs to its scheme and host; keep it only if neither changes
+        let port = self.port.or_else(|| {
+            let unchanged = self.scheme.as_deref().is_none_or(|s| s == scheme) && self.host.is_none();
+            request_authority.as_ref().and_then(|a| a.port_u16()).filter(|_| unchanged)
+        });
+        let default_port = match target_scheme {
+            "https" => 443,
+            _ => 80,
+        };
+
+        let mut origin = format!("{}://{}", target_scheme, target_host);
+        if let Some(port) = port.filter(|p| *p != default_port) {
+            origin.push_str(&format!(":{}", port));
+        }
+        let mut target = match &self.path {
+            RedirectPath::Keep => uri.path().to_string(),
+            RedirectPath::Replace(path) => path.clone(),
+            RedirectPath::Rewrite(rewrite) => rewrite.apply(uri.path()).into_owned(),
+        };
+        if let Some(query) = uri.query().filter(|_| !self.strip_query) {
+            target.push('?');
+            target.push_str(query);
+        }
+
+        let request_port = request_authority
+            .as_ref()
+            .and_then(|a| a.port_u16())
+            .unwrap_or(if scheme == "https" { 443 } else { 80 });
+        let same_origin = target_scheme == scheme
+            && request_authority.as_ref().is_some_and(|a| a.host().eq_ignore_ascii_case(target_host))
+            && port.unwrap_or(default_port) == request_port;
+        let request_target = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        if same_origin && target == request_target {
+            return None;
+        }
+        Some(format!("{}{}", origin, target))
+    }
+
+    /// Redirect response for a location
+    pub fn response(&self, location: &str) -> Response<Bytes> {
+        Response::builder()
+            .status(self.status)
+            .header(LOCATION, location)
+            .header(CONTENT_LENGTH, 0)
+            .body(Bytes::new())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(value: &str) -> Uri {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_https_upgrade() {
+        let redirect = RouteRedirect::new(StatusCode::PERMANENT_REDIRECT)
+            .unwrap()
+            .with_scheme("https")
+            .unwrap();
+        let location = redirect.location("http", Some("example.com:8080"), &uri("/a?b=1"));
+        assert_eq!(location.as_deref(), Some("https://example.com/a?b=1"));
+
+        // Already on HTTPS: no redirect
+        assert_eq!(redirect.location("https", Some("example.com"), &uri("/a")), None);
+    }
+
+    #[test]
+    fn test_legacy_prefix_redirect() {
+        let route_match = RouteMatch {
+            path_prefix: Some("/old".to_string()),
+            ..Default::default()
+        };
+        let action = RedirectAction {
+            status_code: 301,
+            replace_prefix: Some("/new".to_string()),
+            strip_query: true,
+            ..Default::default()
+        };
+        let redirect = RouteRedirect::from_action(&action, &route_match).unwrap();
+        let location = redirect.location("https", Some("example.com"), &uri("/old/page?x=1")).unwrap();
+        assert_eq!(location, "https://example.com/new/page");
+
+        let response = redirect.response(&location);
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[LOCATION], "https://example.com/new/page");
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        let redirect = RouteRedirect::new(StatusCode::FOUND)
+            .unwrap()
+            .with_host("new.example.com")
+            .with_port(8443);
+        let location = redirect.location("http", Some("old.example.com:8080"), &uri("/"));
+        assert_eq!(location.as_deref(), Some("http://new.example.com:8443/"));
+    }
+
+    #[test]
+    fn test_invalid_actions_rejected() {
+        assert!(RouteRedirect::new(StatusCode::OK).is_err());
+        assert!(RouteRedirect::new(StatusCode::FOUND).unwrap().with_scheme("ftp").is_err());
+
+        let action = RedirectAction {
+            status_code: 302,
+            replace_prefix: Some("/new".to_string()),
+            ..Default::default()
+        };
+        assert!(RouteRedirect::from_action(&action, &RouteMatch::default()).is_err());
+    }
+}
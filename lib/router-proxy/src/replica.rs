@@ -0,0 +1,152 @@
+//! Gateway replica ownership for session-sticky state
+//!
+//! When several gateway replicas sit behind an L4 load balancer, each
+//! client key (session ID or client IP) is owned by exactly one replica,
+//! chosen by rendezvous hashing over the replica set. A replica that
+//! receives a request it doesn't own redirects the client to the owner,
+//! so in-memory session state stays on one replica without shared storage.
+
+use anyhow::{Result, anyhow};
+
+/// A gateway replica
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replica {
+    /// Stable replica identifier (e.g., pod name)
+    pub id: String,
+    /// Base URL clients can reach this replica at (e.g., "https://gw-1.example.com")
+    pub url: String,
+}
+
+/// Where a request should be served
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ownership {
+    /// This replica owns the key
+    Local,
+    /// Another replica owns the key; redirect to this location
+    Redirect(String),
+}
+
+/// Consistent assignment of client keys to gateway replicas
+pub struct ReplicaRing {
+    self_id: String,
+    replicas: Vec<Replica>,
+}
+
+impl ReplicaRing {
+    /// Create a ring for the replica `self_id`
+    ///
+    /// `replicas` must include this replica.
+    pub fn new(self_id: String, replicas: Vec<Replica>) -> Result<Self> {
+        if !replicas.iter().any(|r| r.id == self_id) {
+            return Err(anyhow!("Replica {} is not in the replica set", self_id));
+        }
+        Ok(Self { self_id, replicas })
+    }
+
+    /// This replica's identifier
+    pub fn self_id(&self) -> &str {
+        &self.self_id
+    }
+
+    /// Replica set
+    pub fn replicas(&self) -> &[Replica] {
+        &self.replicas
+    }
+
+    /// Replica that owns a client key
+    pub fn owner(&self, key: &str) -> &Replica {
+        self.replicas
+            .iter()
+            .max_by_key(|r| fnv1a(&format!("{}|{}", key, r.id)))
+            .expect("replica set is never empty")
+    }
+
+    /// Decide whether to serve a request locally or redirect it
+    ///
+    /// `path_and_query` is appended to the owner's URL. Requests whose
+    /// `host` already matches the owner are served locally so diverging
+    /// replica-set views can't cause redirect loops.
+    pub fn check(&self, key: &str, host: Option<&str>, path_and_query: &str) -> Ownership {
+        let owner = self.owner(key);
+        if owner.id == self.self_id {
+            return Ownership::Local;
+        }
+
+        let owner_url = owner.url.trim_end_matches('/');
+        let owner_host = owner_url.split("://").nth(1).unwrap_or(owner_url);
+        if host.is_some_and(|h| h.eq_ignore_ascii_case(owner_host)) {
+            return Ownership::Local;
+        }
+
+        Ownership::Redirect(format!("{}{}", owner_url, path_and_query))
+    }
+}
+
+/// FNV-1a hash
+fn fnv1a(s: &str) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 14695981039346656037;
+    const FNV_PRIME: u64 = 1099511628211;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in s.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replicas() -> Vec<Replica> {
+        (1..=3)
+            .map(|i| Replica {
+                id: format!("gw-{}", i),
+                url: format!("https://gw-{}.example.com", i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_self_must_be_member() {
+        assert!(ReplicaRing::new("gw-9".to_string(), replicas()).is_err());
+        assert!(ReplicaRing::new("gw-1".to_string(), replicas()).is_ok());
+    }
+
+    #[test]
+    fn test_all_replicas_agree_on_owner() {
+        let rings: Vec<ReplicaRing> = (1..=3)
+            .map(|i| ReplicaRing::new(format!("gw-{}", i), replicas()).unwrap())
+            .collect();
+
+        for key in ["alice", "bob", "10.0.0.7"] {
+            let owner = &rings[0].owner(key).id;
+            let local: Vec<bool> = rings
+                .iter()
+                .map(|r| r.check(key, None, "/") == Ownership::Local)
+                .collect();
+            assert_eq!(local.iter().filter(|l| **l).count(), 1);
+            assert!(rings.iter().all(|r| &r.owner(key).id == owner));
+        }
+    }
+
+    #[test]
+    fn test_redirect_location() {
+        let ring = ReplicaRing::new("gw-1".to_string(), replicas()).unwrap();
+        let key = (0..100)
+            .map(|i| format!("user-{}", i))
+            .find(|k| ring.owner(k).id != "gw-1")
+            .unwrap();
+        let owner = ring.owner(&key).clone();
+
+        assert_eq!(
+            ring.check(&key, Some("gw-1.example.com"), "/cart?item=1"),
+            Ownership::Redirect(format!("{}/cart?item=1", owner.url))
+        );
+
+        // Already addressed to the owner: don't bounce it around
+        let owner_host = owner.url.trim_start_matches("https://");
+        assert_eq!(ring.check(&key, Some(owner_host), "/cart"), Ownership::Local);
+    }
+}
@@ -0,0 +1,159 @@
+//! Request IDs
+//!
+//! Every request gets an ID that follows it through the gateway's logs,
+//! access log, error responses, and the request sent upstream. A valid
+//! X-Request-ID from the client is kept so IDs assigned by an outer proxy
+//! carry through; otherwise a new one is generated.
+
+use crate::middleware::{Middleware, MiddlewareContext};
+use anyhow::Result;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::debug;
+
+/// Header carrying the request ID
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Metadata key the request ID is stored under in [`MiddlewareContext`]
+pub const REQUEST_ID_METADATA: &str = "request_id";
+
+/// Longest incoming request ID kept
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Assigns request IDs
+#[derive(Clone, Debug)]
+pub struct RequestIdMiddleware {
+    header: HeaderName,
+    trust_incoming: bool,
+}
+
+impl Default for RequestIdMiddleware {
+    fn default() -> Self {
+        Self {
+            header: X_REQUEST_ID,
+            trust_incoming: true,
+        }
+    }
+}
+
+impl RequestIdMiddleware {
+    /// Create request ID handling with the X-Request-ID header
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Carry request IDs in `header` instead
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Whether to keep IDs sent by clients (default: true)
+    pub fn with_trust_incoming(mut self, trust: bool) -> Self {
+        self.trust_incoming = trust;
+        self
+    }
+
+    /// Header carrying the request ID
+    pub fn header(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// Generate a new request ID
+    pub fn generate() -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    /// Whether an incoming request ID can be kept
+    pub fn is_valid(value: &str) -> bool {
+        !value.is_empty() && value.len() <= MAX_REQUEST_ID_LEN && value.bytes().all(|b| b.is_ascii_graphic())
+    }
+
+    /// The incoming ID if it can be kept
+    fn incoming<'a>(&self, value: Option<&'a str>) -> Option<&'a str> {
+        let value = value.filter(|_| self.trust_incoming)?;
+        if Self::is_valid(value) {
+            Some(value)
+        } else {
+            debug!("Replacing invalid request ID {:?}", value);
+            None
+        }
+    }
+
+    /// Give a request its ID, returning it
+    ///
+    /// The ID is written to the request's headers, so everything that
+    /// reads them afterwards, including the upstream, sees the same ID.
+    pub fn ensure(&self, headers: &mut HeaderMap) -> String {
+        let incoming = headers.get(&self.header).and_then(|v| v.to_str().ok());
+        if let Some(id) = self.incoming(incoming) {
+            return id.to_string();
+        }
+
+        let id = Self::generate();
+        // Generated IDs are always valid header values
+        if let Ok(value) = HeaderValue::from_str(&id) {
+            headers.insert(self.header.clone(), value);
+        }
+        id
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RequestIdMiddleware {
+    fn name(&self) -> &'static str {
+        "RequestIdMiddleware"
+    }
+
+    async fn on_request(&self, context: &MiddlewareContext) -> Result<()> {
+        let incoming = context.request_headers.get(self.header.as_str()).map(String::as_str);
+        let id = match self.incoming(incoming) {
+            Some(id) => id.to_string(),
+            None => Self::generate(),
+        };
+        context.set_metadata(REQUEST_ID_METADATA.to_string(), id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_honors_or_generates() {
+        let request_ids = RequestIdMiddleware::new();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("edge-abc-123"));
+        assert_eq!(request_ids.ensure(&mut headers), "edge-abc-123");
+
+        // Missing and malformed IDs are replaced, and the new ID is forwarded
+        for incoming in [None, Some("has spaces"), Some(&*"x".repeat(200))] {
+            let mut headers = HeaderMap::new();
+            if let Some(incoming) = incoming {
+                headers.insert(X_REQUEST_ID, HeaderValue::from_str(incoming).unwrap());
+            }
+            let id = request_ids.ensure(&mut headers);
+            assert_eq!(id.len(), 36);
+            assert_eq!(headers[X_REQUEST_ID], id.as_str());
+        }
+
+        let untrusting = RequestIdMiddleware::new().with_trust_incoming(false);
+        let mut headers = HeaderMap::new();
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("client-chosen"));
+        assert_ne!(untrusting.ensure(&mut headers), "client-chosen");
+    }
+
+    #[tokio::test]
+    async fn test_stores_id_in_context() {
+        let middleware = RequestIdMiddleware::new();
+        let mut context = MiddlewareContext::default();
+        context.request_headers.insert("x-request-id".to_string(), "req-42".to_string());
+        middleware.on_request(&context).await.unwrap();
+        assert_eq!(context.request_id().as_deref(), Some("req-42"));
+
+        let context = MiddlewareContext::default();
+        middleware.on_request(&context).await.unwrap();
+        assert!(context.request_id().is_some());
+    }
+}
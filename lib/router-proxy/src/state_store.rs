@@ -0,0 +1,538 @@
+//! Shared state backends for multi-replica gateways
+//!
+//! Rate limiting, session affinity, and circuit breaker state can be kept
+//! in a `StateStore` so every gateway replica sees the same counters and
+//! pins. Each feature picks its own backend: in-process memory (single
+//! replica), Redis, or etcd.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use redis::aio::ConnectionManager;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Key/value store shared between gateway replicas
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// Get a value
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Set a value, optionally expiring after `ttl`
+    async fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<()>;
+
+    /// Delete a value
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Atomically add `delta` to an integer counter and return the new value
+    ///
+    /// `ttl` applies only when the counter is created.
+    async fn increment(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64>;
+
+    /// Backend name for logging
+    fn backend(&self) -> &'static str;
+}
+
+/// Which backend a feature uses
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateStoreConfig {
+    /// In-process memory (not shared between replicas)
+    Memory,
+    /// Redis at the given URL (redis:// or rediss://)
+    Redis(String),
+    /// etcd v3 JSON gateway at the given base URL
+    Etcd(String),
+}
+
+impl StateStoreConfig {
+    /// Parse a backend URL: "memory", "redis://host:6379", "etcd://host:2379",
+    /// or "etcd+https://host:2379"
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("memory") {
+            Ok(StateStoreConfig::Memory)
+        } else if value.starts_with("redis://") || value.starts_with("rediss://") {
+            Ok(StateStoreConfig::Redis(value.to_string()))
+        } else if let Some(rest) = value.strip_prefix("etcd+https://") {
+            Ok(StateStoreConfig::Etcd(format!("https://{}", rest)))
+        } else if let Some(rest) = value.strip_prefix("etcd://") {
+            Ok(StateStoreConfig::Etcd(format!("http://{}", rest)))
+        } else {
+            Err(anyhow!("Unsupported state store: {}", value))
+        }
+    }
+
+    /// Connect to the configured backend
+    pub async fn connect(&self) -> Result<Arc<dyn StateStore>> {
+        Ok(match self {
+            StateStoreConfig::Memory => Arc::new(MemoryStateStore::new()),
+            StateStoreConfig::Redis(url) => Arc::new(RedisStateStore::connect(url).await?),
+            StateStoreConfig::Etcd(url) => Arc::new(EtcdStateStore::new(url)?),
+        })
+    }
+}
+
+/// Stored value and its expiry
+type MemoryEntry = (Vec<u8>, Option<Instant>);
+
+/// How often writes sweep expired entries out of a memory store
+const MEMORY_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// In-process state store
+///
+/// Expired entries are dropped when read, and swept out by the first write
+/// after each sweep interval, so keys that are never read again (such as
+/// per-window rate limit counters) don't accumulate.
+pub struct MemoryStateStore {
+    entries: Mutex<MemoryEntries>,
+}
+
+struct MemoryEntries {
+    map: HashMap<String, MemoryEntry>,
+    next_sweep: Instant,
+}
+
+impl MemoryEntries {
+    /// Drop expired entries if a sweep is due
+    fn sweep(&mut self, now: Instant) {
+        if now < self.next_sweep {
+            return;
+        }
+        self.map.retain(|_, (_, expires_at)| expires_at.is_none_or(|expires_at| now < expires_at));
+        self.next_sweep = now + MEMORY_SWEEP_INTERVAL;
+    }
+}
+
+impl Default for MemoryStateStore {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(MemoryEntries {
+                map: HashMap::new(),
+                next_sweep: Instant::now() + MEMORY_SWEEP_INTERVAL,
+            }),
+        }
+    }
+}
+
+impl MemoryStateStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, MemoryEntries>> {
+        self.entries.lock().map_err(|_| anyhow!("state store lock poisoned"))
+    }
+}
+
+#[async_trait]
+impl StateStore for MemoryStateStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut entries = self.lock()?;
+        match entries.map.get(key) {
+            Some((_, Some(expires_at))) if Instant::now() >= *expires_at => {
+                entries.map.remove(key);
+                Ok(None)
+            }
+            Some((value, _)) => Ok(Some(value.clone())),
+            None => Ok(None),
+        }
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<()> {
+        let mut entries = self.lock()?;
+        let now = Instant::now();
+        entries.sweep(now);
+        entries.map.insert(key.to_string(), (value.to_vec(), ttl.map(|t| now + t)));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.lock()?.map.remove(key);
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64> {
+        let mut entries = self.lock()?;
+        let now = Instant::now();
+        entries.sweep(now);
+
+        let current = match entries.map.get(key) {
+            Some((_, Some(expires_at))) if now >= *expires_at => None,
+            Some((value, expires_at)) => Some((parse_counter(value)?, *expires_at)),
+            None => None,
+        };
+        let (value, expires_at) = match current {
+            Some((value, expires_at)) => (value + delta, expires_at),
+            None => (delta, ttl.map(|t| now + t)),
+        };
+
+        entries.map.insert(key.to_string(), (value.to_string().into_bytes(), expires_at));
+        Ok(value)
+    }
+
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Redis-backed state store
+pub struct RedisStateStore {
+    connection: ConnectionManager,
+}
+
+impl RedisStateStore {
+    /// Connect to Redis
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(Self { connection })
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStateStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut connection = self.connection.clone();
+        Ok(redis::cmd("GET").arg(key).query_async(&mut connection).await?)
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+        }
+        cmd.query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("DEL").arg(key).query_async::<()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64> {
+        let mut connection = self.connection.clone();
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if let Some(ttl) = ttl {
+            // Create the counter with its expiry only if it doesn't exist yet
+            pipe.cmd("SET").arg(key).arg(0).arg("PX").arg(ttl.as_millis().max(1) as u64).arg("NX").ignore();
+        }
+        pipe.cmd("INCRBY").arg(key).arg(delta);
+        let (value,): (i64,) = pipe.query_async(&mut connection).await?;
+        Ok(value)
+    }
+
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+}
+
+/// etcd-backed state store using the etcd v3 JSON gateway
+///
+/// Keys with a TTL share leases: a lease granted for a TTL is reused by
+/// every key set with that TTL for a tenth of it (at least a second), and
+/// outlives the TTL by that long, so keys expire up to that much late
+/// rather than early.
+pub struct EtcdStateStore {
+    base_url: String,
+    http: reqwest::Client,
+    /// Lease ID and how long it may be reused, by TTL in seconds
+    leases: Mutex<HashMap<u64, (String, Instant)>>,
+}
+
+/// Attempts at a compare-and-swap increment before giving up
+const ETCD_CAS_ATTEMPTS: usize = 10;
+
+impl EtcdStateStore {
+    /// Create a store talking to the etcd gateway at `base_url`
+    pub fn new(base_url: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+            leases: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn call(&self, path: &str, body: Value) -> Result<Value> {
+        let response = self
+            .http
+            .post(format!("{}{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("etcd {} returned {}", path, response.status()));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Fetch a key's value and modification revision
+    async fn range(&self, key: &str) -> Result<Option<(Vec<u8>, String)>> {
+        let response = self.call("/v3/kv/range", json!({ "key": BASE64.encode(key) })).await?;
+        let Some(kv) = response["kvs"].as_array().and_then(|kvs| kvs.first()) else {
+            return Ok(None);
+        };
+        let value = BASE64.decode(kv["value"].as_str().unwrap_or_default())?;
+        let revision = json_int(&kv["mod_revision"]);
+        Ok(Some((value, revision)))
+    }
+
+    /// A lease for keys to expire after `ttl`, granting one if none can
+    /// be reused
+    async fn lease(&self, ttl: Duration) -> Result<String> {
+        let secs = ttl.as_secs().max(1);
+        let now = Instant::now();
+        {
+            let mut leases = self.leases.lock().map_err(|_| anyhow!("state store lock poisoned"))?;
+            leases.retain(|_, (_, reusable_until)| now < *reusable_until);
+            if let Some((id, _)) = leases.get(&secs) {
+                return Ok(id.clone());
+            }
+        }
+
+        let reuse = (secs / 10).max(1);
+        let response = self.call("/v3/lease/grant", json!({ "TTL": secs + reuse })).await?;
+        let id = json_int(&response["ID"]);
+        self.leases
+            .lock()
+            .map_err(|_| anyhow!("state store lock poisoned"))?
+            .insert(secs, (id.clone(), now + Duration::from_secs(reuse)));
+        Ok(id)
+    }
+
+    fn put_request(key: &str, value: &[u8], lease: Option<&str>) -> Value {
+        let mut put = json!({ "key": BASE64.encode(key), "value": BASE64.encode(value) });
+        if let Some(lease) = lease {
+            put["lease"] = json!(lease);
+        }
+        put
+    }
+}
+
+#[async_trait]
+impl StateStore for EtcdStateStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.range(key).await?.map(|(value, _)| value))
+    }
+
+    async fn set(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<()> {
+        let lease = match ttl {
+            Some(ttl) => Some(self.lease(ttl).await?),
+            None => None,
+        };
+        self.call("/v3/kv/put", Self::put_request(key, value, lease.as_deref())).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.call("/v3/kv/deleterange", json!({ "key": BASE64.encode(key) })).await?;
+        Ok(())
+    }
+
+    async fn increment(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64> {
+        for _ in 0..ETCD_CAS_ATTEMPTS {
+            let current = self.range(key).await?;
+            let (next, compare, lease) = match &current {
+                Some((value, revision)) => (
+                    parse_counter(value)? + delta,
+                    json!({ "key": BASE64.encode(key), "target": "MOD", "mod_revision": revision, "result": "EQUAL" }),
+                    None,
+                ),
+                None => (
+                    delta,
+                    json!({ "key": BASE64.encode(key), "target": "CREATE", "create_revision": "0", "result": "EQUAL" }),
+                    match ttl {
+                        Some(ttl) => Some(self.lease(ttl).await?),
+                        None => None,
+                    },
+                ),
+            };
+
+            // A put without a lease would detach an existing key from its lease
+            let put = match (&current, lease.as_deref()) {
+                (Some(_), _) => json!({ "key": BASE64.encode(key), "value": BASE64.encode(next.to_string()), "ignore_lease": true }),
+                (None, lease) => Self::put_request(key, next.to_string().as_bytes(), lease),
+            };
+
+            let response = self
+                .call("/v3/kv/txn", json!({ "compare": [compare], "success": [{ "request_put": put }] }))
+                .await?;
+            if response["succeeded"].as_bool().unwrap_or(false) {
+                return Ok(next);
+            }
+        }
+        Err(anyhow!("etcd increment of {} lost too many races", key))
+    }
+
+    fn backend(&self) -> &'static str {
+        "etcd"
+    }
+}
+
+/// Parse a stored integer counter
+fn parse_counter(value: &[u8]) -> Result<i64> {
+    std::str::from_utf8(value)?
+        .trim()
+        .parse()
+        .map_err(|e| anyhow!("State store value is not a counter: {}", e))
+}
+
+/// etcd's JSON gateway encodes int64 fields as strings
+fn json_int(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => "0".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(StateStoreConfig::parse("memory").unwrap(), StateStoreConfig::Memory);
+        assert_eq!(
+            StateStoreConfig::parse("redis://redis:6379").unwrap(),
+            StateStoreConfig::Redis("redis://redis:6379".to_string())
+        );
+        assert_eq!(
+            StateStoreConfig::parse("etcd://etcd:2379").unwrap(),
+            StateStoreConfig::Etcd("http://etcd:2379".to_string())
+        );
+        assert_eq!(
+            StateStoreConfig::parse("etcd+https://etcd:2379").unwrap(),
+            StateStoreConfig::Etcd("https://etcd:2379".to_string())
+        );
+        assert!(StateStoreConfig::parse("consul://x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_get_set_delete() {
+        let store = MemoryStateStore::new();
+        assert_eq!(store.get("k").await.unwrap(), None);
+
+        store.set("k", b"v", None).await.unwrap();
+        assert_eq!(store.get("k").await.unwrap(), Some(b"v".to_vec()));
+
+        store.delete("k").await.unwrap();
+        assert_eq!(store.get("k").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_expiry() {
+        let store = MemoryStateStore::new();
+        store.set("k", b"v", Some(Duration::from_millis(10))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(store.get("k").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_increment() {
+        let store = MemoryStateStore::new();
+        assert_eq!(store.increment("c", 1, Some(Duration::from_millis(10))).await.unwrap(), 1);
+        assert_eq!(store.increment("c", 2, None).await.unwrap(), 3);
+
+        // The TTL set on creation still applies
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(store.increment("c", 1, None).await.unwrap(), 1);
+
+        store.set("text", b"abc", None).await.unwrap();
+        assert!(store.increment("text", 1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_sweeps_expired_keys() {
+        let store = MemoryStateStore::new();
+        for window in 0..100 {
+            let key = format!("ratelimit:client:{}", window);
+            store.increment(&key, 1, Some(Duration::from_millis(10))).await.unwrap();
+        }
+        store.set("kept", b"v", None).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(store.lock().unwrap().map.len(), 101);
+
+        // The next write after the sweep interval drops the expired counters
+        store.lock().unwrap().next_sweep = Instant::now();
+        store.increment("ratelimit:client:100", 1, Some(Duration::from_secs(1))).await.unwrap();
+        let entries = store.lock().unwrap();
+        assert_eq!(entries.map.len(), 2);
+        assert!(entries.map.contains_key("kept"));
+    }
+
+    /// etcd JSON gateway answering puts and lease grants, counting grants
+    async fn spawn_etcd() -> (String, Arc<Mutex<Vec<Value>>>) {
+        use http_body_util::{BodyExt, Full};
+        use hyper::server::conn::http1;
+        use hyper::service::service_fn;
+        use hyper::Response;
+        use hyper_util::rt::TokioIo;
+
+        let grants = Arc::new(Mutex::new(Vec::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let recorded = grants.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { return };
+                let grants = recorded.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                        let grants = grants.clone();
+                        async move {
+                            let path = req.uri().path().to_string();
+                            let body = req.into_body().collect().await?.to_bytes();
+                            let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+                            let response = if path == "/v3/lease/grant" {
+                                let mut grants = grants.lock().unwrap();
+                                grants.push(request);
+                                json!({ "ID": grants.len().to_string() })
+                            } else {
+                                json!({})
+                            };
+                            Ok::<_, hyper::Error>(Response::new(Full::new(hyper::body::Bytes::from(response.to_string()))))
+                        }
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+        (url, grants)
+    }
+
+    #[tokio::test]
+    async fn test_etcd_reuses_leases() {
+        let (url, grants) = spawn_etcd().await;
+        let store = EtcdStateStore::new(&url).unwrap();
+        for key in ["a", "b", "c"] {
+            store.set(key, b"v", Some(Duration::from_secs(60))).await.unwrap();
+        }
+        store.set("d", b"v", Some(Duration::from_secs(5))).await.unwrap();
+        store.set("e", b"v", None).await.unwrap();
+
+        let grants = grants.lock().unwrap();
+        assert_eq!(grants.len(), 2);
+        // Leases outlive the TTL by the reuse window
+        assert_eq!(json_int(&grants[0]["TTL"]), "66");
+        assert_eq!(json_int(&grants[1]["TTL"]), "6");
+    }
+
+    #[test]
+    fn test_json_int() {
+        assert_eq!(json_int(&json!("42")), "42");
+        assert_eq!(json_int(&json!(42)), "42");
+        assert_eq!(json_int(&Value::Null), "0");
+    }
+}
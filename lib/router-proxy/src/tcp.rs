@@ -0,0 +1,285 @@
+//! Layer 4 proxying for TCP services
+//!
+//! VPCServices with `protocol: TCP` are served by a listener of their own.
+//! Each accepted connection goes to an endpoint chosen from the service
+//! registry by the load balancer, and bytes are streamed both ways until
+//! either side closes. Endpoints that refuse the connection are skipped,
+//! and periodic health checks take failing endpoints out of rotation.
+
+use crate::health_check::{HealthChecker, HealthStatus};
+use crate::load_balancer::{ActiveConnection, LoadBalancer};
+use crate::metrics::MetricsCollector;
+use anyhow::{Result, anyhow};
+use router_core::{Endpoint, ServiceRegistry};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// TCP proxy configuration
+#[derive(Clone, Debug)]
+pub struct TcpProxyConfig {
+    /// Timeout for connecting to an endpoint
+    pub connect_timeout: Duration,
+}
+
+impl Default for TcpProxyConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Proxies TCP connections to the endpoints of one service
+pub struct TcpProxy {
+    registry: Arc<ServiceRegistry>,
+    service_id: String,
+    load_balancer: LoadBalancer,
+    config: TcpProxyConfig,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl TcpProxy {
+    /// Create a proxy for a service (namespace/name)
+    pub fn new(registry: Arc<ServiceRegistry>, service_id: impl Into<String>, load_balancer: LoadBalancer) -> Self {
+        Self {
+            registry,
+            service_id: service_id.into(),
+            load_balancer,
+            config: TcpProxyConfig::default(),
+            metrics: None,
+        }
+    }
+
+    /// Use `config` instead of the defaults
+    pub fn with_config(mut self, config: TcpProxyConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Count connections and bytes through the metrics collector
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Service this proxy forwards to
+    pub fn service_id(&self) -> &str {
+        &self.service_id
+    }
+
+    /// Accept connections on `listener` and proxy each in its own task
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.proxy(stream, peer_addr).await {
+                    debug!("TCP connection from {} to {} ended: {}", peer_addr, proxy.service_id, e);
+                }
+            });
+        }
+    }
+
+    /// Stream a client connection to an endpoint until either side closes
+    pub async fn proxy(&self, mut client: TcpStream, peer_addr: SocketAddr) -> Result<()> {
+        let Some((endpoint, mut upstream, _active)) = self.connect().await else {
+            self.record_connection("no_endpoint");
+            return Err(anyhow!("No reachable endpoint for {}", self.service_id));
+        };
+        self.record_connection("proxied");
+        debug!(
+            "Proxying TCP connection from {} to {}:{} ({})",
+            peer_addr, endpoint.ip, endpoint.port, self.service_id
+        );
+
+        let (sent, received) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_tcp_bytes(&self.service_id, "upstream", sent);
+            metrics.record_tcp_bytes(&self.service_id, "downstream", received);
+        }
+        Ok(())
+    }
+
+    /// Connect to an endpoint chosen by the load balancer
+    ///
+    /// An endpoint that can't be reached is skipped for the next choice,
+    /// trying each ready endpoint at most once. Services in planned
+    /// maintenance get no new connections. The connection counts against
+    /// the endpoint until the returned guard drops.
+    async fn connect(&self) -> Option<(Endpoint, TcpStream, ActiveConnection)> {
+        let service = self.registry.get_service(&self.service_id).await.ok()?;
+        if service.maintenance.is_some() {
+            return None;
+        }
+        let mut endpoints = service.endpoints;
+
+        while let Some(endpoint) = self.load_balancer.select(&endpoints).cloned() {
+            let addr = format!("{}:{}", endpoint.ip, endpoint.port);
+            let active = self.load_balancer.begin(&endpoint);
+            match tokio::time::timeout(self.config.connect_timeout, TcpStream::connect(&addr)).await {
+                Ok(Ok(stream)) => return Some((endpoint, stream, active)),
+                Ok(Err(e)) => warn!("Failed to connect to {} for {}: {}", addr, self.service_id, e),
+                Err(_) => warn!("Timed out connecting to {} for {}", addr, self.service_id),
+            }
+            active.fail();
+            self.record_connection("connect_error");
+            endpoints.retain(|e| e.ip != endpoint.ip || e.port != endpoint.port);
+        }
+        None
+    }
+
+    /// Check the service's endpoints and update their readiness
+    pub async fn check_health(&self, checker: &HealthChecker) -> Result<()> {
+        for (endpoint, status) in checker.check_service(&self.registry, &self.service_id).await? {
+            let ready = match status {
+                HealthStatus::Healthy => true,
+                HealthStatus::Unhealthy => false,
+                HealthStatus::Maintenance => continue,
+            };
+            if ready != endpoint.ready {
+                info!(
+                    "Endpoint {}:{} of {} is now {}",
+                    endpoint.ip, endpoint.port, self.service_id, status.as_str()
+                );
+                self.registry
+                    .set_endpoint_ready(&self.service_id, &endpoint.ip, endpoint.port, ready)
+                    .await?;
+                if let (Some(metrics), false) = (&self.metrics, ready) {
+                    metrics.record_endpoint_ejection(&self.service_id, &format!("{}:{}", endpoint.ip, endpoint.port));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check endpoint health every `interval` in a background task
+    pub fn spawn_health_checks(self: &Arc<Self>, checker: Arc<HealthChecker>, interval: Duration) -> JoinHandle<()> {
+        let proxy = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = proxy.check_health(&checker).await {
+                    warn!("Health checks for {} failed: {}", proxy.service_id, e);
+                }
+            }
+        })
+    }
+
+    fn record_connection(&self, result: &str) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_tcp_connection(&self.service_id, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health_check::HealthCheckConfig;
+    use crate::load_balancer::LoadBalancingStrategy;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Echo server that prefixes replies with its name
+    async fn echo_server(name: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    while let Ok(n) = stream.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        let reply = [name.as_bytes(), b":", &buf[..n]].concat();
+                        stream.write_all(&reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn endpoint(addr: SocketAddr) -> Endpoint {
+        Endpoint {
+            ip: addr.ip().to_string(),
+            port: addr.port(),
+            ready: true,
+            priority: 0,
+        }
+    }
+
+    async fn start_proxy(endpoints: Vec<Endpoint>, metrics: Arc<MetricsCollector>) -> (Arc<TcpProxy>, SocketAddr) {
+        let registry = Arc::new(ServiceRegistry::new());
+        registry
+            .register_service("default".into(), "db".into(), 5432, "TCP".into(), endpoints)
+            .await
+            .unwrap();
+        let proxy = Arc::new(
+            TcpProxy::new(registry, "default/db", LoadBalancer::new(LoadBalancingStrategy::RoundRobin))
+                .with_metrics(metrics),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(proxy.clone().serve(listener));
+        (proxy, addr)
+    }
+
+    async fn round_trip(addr: SocketAddr, message: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(message).await.unwrap();
+        let mut buf = [0u8; 64];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_streams_to_balanced_endpoints() {
+        let a = echo_server("a").await;
+        let b = echo_server("b").await;
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let (_proxy, addr) = start_proxy(vec![endpoint(a), endpoint(b)], metrics.clone()).await;
+
+        let mut replies = vec![round_trip(addr, b"ping").await, round_trip(addr, b"ping").await];
+        replies.sort();
+        assert_eq!(replies, ["a:ping", "b:ping"]);
+        assert_eq!(metrics.tcp_connections_total.with_label_values(&["default/db", "proxied"]).get(), 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_skips_unreachable_endpoints() {
+        let live = echo_server("live").await;
+        let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let (proxy, addr) = start_proxy(vec![endpoint(dead), endpoint(live)], metrics.clone()).await;
+
+        for _ in 0..3 {
+            assert_eq!(round_trip(addr, b"ping").await, "live:ping");
+        }
+        assert!(metrics.tcp_connections_total.with_label_values(&["default/db", "connect_error"]).get() >= 1.0);
+
+        // Health checks take the dead endpoint out of rotation
+        let checker = HealthChecker::new(HealthCheckConfig::default());
+        proxy.check_health(&checker).await.unwrap();
+        let endpoints = proxy.registry.get_endpoints("default/db").await.unwrap();
+        assert!(!endpoints.iter().find(|e| e.port == dead.port()).unwrap().ready);
+        assert!(endpoints.iter().find(|e| e.port == live.port()).unwrap().ready);
+    }
+
+    #[tokio::test]
+    async fn test_no_endpoints_closes_connection() {
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let (_proxy, addr) = start_proxy(Vec::new(), metrics.clone()).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(metrics.tcp_connections_total.with_label_values(&["default/db", "no_endpoint"]).get(), 1.0);
+    }
+}
@@ -0,0 +1,227 @@
+//! Upstream request timing
+//!
+//! The forwarder attaches an [`UpstreamTiming`] to every upstream response
+//! as a response extension. Connection setup is measured by wrapping the
+//! connector; a request served on a pooled connection has no connect time.
+//!
+//! [`ServerTiming`] reports gateway phases to clients in a Server-Timing
+//! header, where browser devtools display them.
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::Uri;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::Service;
+use crate::inflight::{self, InflightState};
+
+/// Phase timings of an upstream request
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UpstreamTiming {
+    /// Time spent opening a new connection, including TLS; None when one was reused
+    pub connect: Option<Duration>,
+    /// Time from sending the request until the response headers arrived
+    pub ttfb: Duration,
+    /// Time until the response body was fully read
+    pub total: Duration,
+    /// Attempts made, including retries
+    pub attempts: u32,
+}
+
+/// Server-Timing response header
+pub const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Builds a Server-Timing header from named durations
+#[derive(Clone, Debug, Default)]
+pub struct ServerTiming {
+    metrics: Vec<(&'static str, &'static str, Duration)>,
+}
+
+impl ServerTiming {
+    /// Create an empty set of timings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Timings for each phase of a proxied request
+    ///
+    /// `route` is the gateway's own work before forwarding and `queue` the
+    /// time spent waiting for a concurrency slot. Connect time is only
+    /// reported when a new upstream connection was opened.
+    pub fn gateway_phases(
+        route: Duration,
+        queue: Option<Duration>,
+        upstream: Option<&UpstreamTiming>,
+        total: Duration,
+    ) -> Self {
+        let mut timing = Self::new();
+        timing.add("route", "Route match", route);
+        if let Some(queue) = queue {
+            timing.add("queue", "Queue wait", queue);
+        }
+        if let Some(upstream) = upstream {
+            if let Some(connect) = upstream.connect {
+                timing.add("connect", "Upstream connect", connect);
+            }
+            timing.add("ttfb", "Upstream TTFB", upstream.ttfb);
+        }
+        timing.add("total", "Total", total);
+        timing
+    }
+
+    /// Add a metric
+    pub fn add(&mut self, name: &'static str, description: &'static str, duration: Duration) -> &mut Self {
+        self.metrics.push((name, description, duration));
+        self
+    }
+
+    /// Header value listing the metrics, durations in milliseconds
+    pub fn header_value(&self) -> Option<HeaderValue> {
+        if self.metrics.is_empty() {
+            return None;
+        }
+        let value = self
+            .metrics
+            .iter()
+            .map(|(name, description, duration)| {
+                format!("{};desc=\"{}\";dur={:.3}", name, description, duration.as_secs_f64() * 1000.0)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).ok()
+    }
+
+    /// Append the header, keeping any Server-Timing the backend sent
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Some(value) = self.header_value() {
+            headers.append(SERVER_TIMING, value);
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Where the connector reports connection setup time for the current request
+    static CONNECT_TIME: Arc<Mutex<Option<Duration>>>;
+}
+
+/// Run a request future, returning how long any new connection took to open
+pub(crate) async fn measure_connect<F: Future>(future: F) -> (F::Output, Option<Duration>) {
+    let slot = Arc::new(Mutex::new(None));
+    let output = CONNECT_TIME.scope(slot.clone(), future).await;
+    let connect = *slot.lock().unwrap_or_else(|e| e.into_inner());
+    (output, connect)
+}
+
+/// Connector wrapper that reports connection setup time
+///
+/// A request's in-flight state shows Connecting while its connection opens.
+#[derive(Clone, Debug)]
+pub struct TimedConnector<C> {
+    inner: C,
+}
+
+impl<C> TimedConnector<C> {
+    /// Wrap a connector
+    pub fn new(inner: C) -> Self {
+        Self { inner }
+    }
+}
+
+impl<C> Service<Uri> for TimedConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<C::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let started = Instant::now();
+        let connecting = self.inner.call(uri);
+        inflight::report(InflightState::Connecting);
+        Box::pin(async move {
+            let result = connecting.await;
+            inflight::report(InflightState::Waiting);
+            // Only set when the connection is opened on behalf of a measured request
+            let _ = CONNECT_TIME.try_with(|slot| {
+                *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(started.elapsed());
+            });
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct SlowConnector;
+
+    impl Service<Uri> for SlowConnector {
+        type Response = ();
+        type Error = std::convert::Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<(), Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Uri) -> Self::Future {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn test_server_timing_header() {
+        let upstream = UpstreamTiming {
+            connect: Some(Duration::from_millis(3)),
+            ttfb: Duration::from_millis(20),
+            total: Duration::from_millis(25),
+            attempts: 1,
+        };
+        let timing = ServerTiming::gateway_phases(
+            Duration::from_micros(500),
+            None,
+            Some(&upstream),
+            Duration::from_millis(26),
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER_TIMING, HeaderValue::from_static("db;dur=12"));
+        timing.apply(&mut headers);
+
+        let values: Vec<_> = headers.get_all(SERVER_TIMING).iter().collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            values[1],
+            "route;desc=\"Route match\";dur=0.500, connect;desc=\"Upstream connect\";dur=3.000, \
+             ttfb;desc=\"Upstream TTFB\";dur=20.000, total;desc=\"Total\";dur=26.000"
+        );
+        assert!(ServerTiming::new().header_value().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_connect_time_reported_to_measured_request() {
+        let mut connector = TimedConnector::new(SlowConnector);
+        let uri: Uri = "http://backend:8080/".parse().unwrap();
+
+        let (_, connect) = measure_connect(connector.call(uri.clone())).await;
+        assert!(connect.unwrap() >= Duration::from_millis(20));
+
+        // Outside a measured request nothing is recorded
+        let (_, connect) = measure_connect(async {}).await;
+        assert_eq!(connect, None);
+        connector.call(uri).await.unwrap();
+    }
+}
@@ -171,6 +171,14 @@ impl TlsServerConfig {
         })
     }
 
+    /// Offer HTTP/2 and HTTP/1.1 via ALPN, preferring HTTP/2
+    pub fn with_http2(mut self) -> Self {
+        let mut config = (*self.config).clone();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        self.config = Arc::new(config);
+        self
+    }
+
     /// Validate that certificate is properly configured
     pub fn validate(&self) -> Result<()> {
         debug!("Validating TLS configuration");
@@ -0,0 +1,345 @@
+//! Identity propagation via OAuth2 token exchange (RFC 8693)
+//!
+//! Exchanges an inbound end-user token for a backend-scoped token before
+//! forwarding, so per-VPC trust domains don't have to accept each other's
+//! raw tokens.
+
+use hyper::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use lru::LruCache;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use tracing::debug;
+
+/// Token type URN for OAuth2 access tokens
+pub const TOKEN_TYPE_ACCESS_TOKEN: &str = "urn:ietf:params:oauth:token-type:access_token";
+/// Token type URN for JWTs
+pub const TOKEN_TYPE_JWT: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+const GRANT_TYPE_TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// Lifetime assumed for exchanged tokens whose response omits `expires_in`
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Token exchange configuration for a trust domain
+#[derive(Clone, Debug)]
+pub struct TokenExchangeConfig {
+    /// Security token service (STS) endpoint URL
+    pub token_url: String,
+    /// Client ID used to authenticate the router to the STS
+    pub client_id: String,
+    /// Client secret used to authenticate the router to the STS
+    pub client_secret: String,
+    /// Type of the inbound subject token
+    pub subject_token_type: String,
+    /// Requested type of the exchanged token
+    pub requested_token_type: String,
+    /// Maximum number of exchanged tokens to cache
+    pub cache_capacity: usize,
+    /// Timeout for STS requests
+    pub timeout: Duration,
+    /// Reject requests that carry no bearer token instead of forwarding them as-is
+    pub require_subject_token: bool,
+}
+
+impl TokenExchangeConfig {
+    /// Create a configuration exchanging access tokens for access tokens
+    pub fn new(token_url: String, client_id: String, client_secret: String) -> Self {
+        Self {
+            token_url,
+            client_id,
+            client_secret,
+            subject_token_type: TOKEN_TYPE_ACCESS_TOKEN.to_string(),
+            requested_token_type: TOKEN_TYPE_ACCESS_TOKEN.to_string(),
+            cache_capacity: 1024,
+            timeout: Duration::from_secs(10),
+            require_subject_token: false,
+        }
+    }
+}
+
+/// Target of a token exchange: the backend trust domain
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExchangeTarget {
+    /// Logical audience of the exchanged token
+    pub audience: Option<String>,
+    /// Resource URI of the backend
+    pub resource: Option<String>,
+    /// Scopes to request on the exchanged token
+    pub scopes: Vec<String>,
+}
+
+/// STS response (RFC 8693 section 2.2.1)
+#[derive(Deserialize)]
+struct ExchangeResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Clone)]
+struct ExchangedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+/// Performs RFC 8693 token exchanges and caches the results
+pub struct TokenExchanger {
+    config: TokenExchangeConfig,
+    http: reqwest::Client,
+    /// Exchanged tokens keyed by hash of (subject token, target)
+    cache: Mutex<LruCache<String, ExchangedToken>>,
+    /// Exchange targets keyed by backend authority (host:port)
+    targets: HashMap<String, ExchangeTarget>,
+}
+
+impl TokenExchanger {
+    /// Create a new token exchanger
+    pub fn new(config: TokenExchangeConfig) -> Result<Self> {
+        let capacity = NonZeroUsize::new(config.cache_capacity)
+            .ok_or_else(|| anyhow!("Token exchange cache capacity must be greater than 0"))?;
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()?;
+
+        Ok(Self {
+            config,
+            http,
+            cache: Mutex::new(LruCache::new(capacity)),
+            targets: HashMap::new(),
+        })
+    }
+
+    /// Exchange tokens for requests toward `backend`
+    pub fn with_target(mut self, backend: String, target: ExchangeTarget) -> Self {
+        self.targets.insert(backend, target);
+        self
+    }
+
+    /// Check if a backend has token exchange configured
+    pub fn has_backend(&self, backend: &str) -> bool {
+        self.targets.contains_key(backend)
+    }
+
+    /// Replace the inbound bearer token with one scoped to `backend`
+    ///
+    /// Returns `Ok(true)` if the token was exchanged and `Ok(false)` if the
+    /// backend has no exchange configured or the request carried no token.
+    pub async fn propagate(&self, backend: &str, headers: &mut HeaderMap) -> Result<bool> {
+        let Some(target) = self.targets.get(backend) else {
+            return Ok(false);
+        };
+
+        let Some(subject_token) = Self::bearer_token(headers) else {
+            if self.config.require_subject_token {
+                return Err(anyhow!("No bearer token to exchange for {}", backend));
+            }
+            return Ok(false);
+        };
+
+        let token = self.exchange(&subject_token, target).await?;
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token))?);
+        debug!("Propagated exchanged identity toward {}", backend);
+        Ok(true)
+    }
+
+    /// Exchange a subject token for a token scoped to `target`
+    pub async fn exchange(&self, subject_token: &str, target: &ExchangeTarget) -> Result<String> {
+        let key = Self::cache_key(subject_token, target);
+
+        if let Some(token) = self.cached(&key) {
+            return Ok(token);
+        }
+
+        let mut form = vec![
+            ("grant_type", GRANT_TYPE_TOKEN_EXCHANGE.to_string()),
+            ("subject_token", subject_token.to_string()),
+            ("subject_token_type", self.config.subject_token_type.clone()),
+            ("requested_token_type", self.config.requested_token_type.clone()),
+        ];
+        if let Some(audience) = &target.audience {
+            form.push(("audience", audience.clone()));
+        }
+        if let Some(resource) = &target.resource {
+            form.push(("resource", resource.clone()));
+        }
+        if !target.scopes.is_empty() {
+            form.push(("scope", target.scopes.join(" ")));
+        }
+
+        let response = self
+            .http
+            .post(&self.config.token_url)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Token exchange at {} failed with {}",
+                self.config.token_url,
+                response.status()
+            ));
+        }
+
+        let body: ExchangeResponse = response.json().await?;
+        let lifetime = body
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.put(
+                key,
+                ExchangedToken {
+                    access_token: body.access_token.clone(),
+                    expires_at: Instant::now() + lifetime,
+                },
+            );
+        }
+
+        Ok(body.access_token)
+    }
+
+    /// Look up an unexpired exchanged token
+    fn cached(&self, key: &str) -> Option<String> {
+        let mut cache = self.cache.lock().ok()?;
+        match cache.get(key) {
+            Some(token) if Instant::now() < token.expires_at => Some(token.access_token.clone()),
+            Some(_) => {
+                cache.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Extract the bearer token from an Authorization header
+    fn bearer_token(headers: &HeaderMap) -> Option<String> {
+        let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = value.split_once(' ')?;
+        if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+            Some(token.trim().to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Cache key that avoids keeping raw subject tokens in memory
+    fn cache_key(subject_token: &str, target: &ExchangeTarget) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(subject_token.as_bytes());
+        hasher.update([0]);
+        hasher.update(target.audience.as_deref().unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(target.resource.as_deref().unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(target.scopes.join(" ").as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchanger() -> TokenExchanger {
+        // Port 1 is never listening, so any STS call fails immediately
+        let config = TokenExchangeConfig::new(
+            "http://127.0.0.1:1/token".to_string(),
+            "router".to_string(),
+            "secret".to_string(),
+        );
+        TokenExchanger::new(config).unwrap()
+    }
+
+    fn target() -> ExchangeTarget {
+        ExchangeTarget {
+            audience: Some("billing".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bearer_token_extraction() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(TokenExchanger::bearer_token(&headers), None);
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer user-token"));
+        assert_eq!(TokenExchanger::bearer_token(&headers), Some("user-token".to_string()));
+
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        assert_eq!(TokenExchanger::bearer_token(&headers), None);
+    }
+
+    #[test]
+    fn test_cache_key_varies_by_target() {
+        let other = ExchangeTarget {
+            audience: Some("orders".to_string()),
+            ..Default::default()
+        };
+        let key1 = TokenExchanger::cache_key("token", &target());
+        let key2 = TokenExchanger::cache_key("token", &other);
+        assert_ne!(key1, key2);
+        assert!(!key1.contains("token"));
+    }
+
+    #[test]
+    fn test_zero_cache_capacity_rejected() {
+        let mut config = TokenExchangeConfig::new(
+            "http://127.0.0.1:1/token".to_string(),
+            "router".to_string(),
+            "secret".to_string(),
+        );
+        config.cache_capacity = 0;
+        assert!(TokenExchanger::new(config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_propagate_uses_cached_exchange() {
+        let exchanger = exchanger().with_target("billing:8080".to_string(), target());
+        let key = TokenExchanger::cache_key("user-token", &target());
+        exchanger.cache.lock().unwrap().put(
+            key,
+            ExchangedToken {
+                access_token: "scoped-token".to_string(),
+                expires_at: Instant::now() + Duration::from_secs(60),
+            },
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer user-token"));
+
+        let exchanged = exchanger.propagate("billing:8080", &mut headers).await.unwrap();
+        assert!(exchanged);
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer scoped-token");
+    }
+
+    #[tokio::test]
+    async fn test_propagate_without_subject_token() {
+        let exchanger = exchanger().with_target("billing:8080".to_string(), target());
+        let mut headers = HeaderMap::new();
+
+        let exchanged = exchanger.propagate("billing:8080", &mut headers).await.unwrap();
+        assert!(!exchanged);
+
+        let mut strict = exchanger;
+        strict.config.require_subject_token = true;
+        assert!(strict.propagate("billing:8080", &mut headers).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_propagate_fails_closed_on_sts_error() {
+        let exchanger = exchanger().with_target("billing:8080".to_string(), target());
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer user-token"));
+
+        assert!(exchanger.propagate("billing:8080", &mut headers).await.is_err());
+        assert!(!exchanger.has_backend("orders:8080"));
+    }
+}
@@ -27,15 +27,18 @@ impl TracingMiddleware {
     /// Extract W3C Trace Context from request headers
     /// Returns (trace_id, span_id, trace_flags) if present
     pub fn extract_w3c_trace_context(headers: &HashMap<String, String>) -> Option<(String, String, String)> {
+        headers.get("traceparent").and_then(|v| Self::parse_traceparent(v))
+    }
+
+    /// Parse a traceparent header value into (trace_id, span_id, trace_flags)
+    pub fn parse_traceparent(value: &str) -> Option<(String, String, String)> {
         // W3C Trace Context header format: version-trace_id-span_id-trace_flags
-        headers.get("traceparent").and_then(|v| {
-            let parts: Vec<&str> = v.split('-').collect();
-            if parts.len() >= 4 {
-                Some((parts[1].to_string(), parts[2].to_string(), parts[3].to_string()))
-            } else {
-                None
-            }
-        })
+        let parts: Vec<&str> = value.split('-').collect();
+        if parts.len() >= 4 {
+            Some((parts[1].to_string(), parts[2].to_string(), parts[3].to_string()))
+        } else {
+            None
+        }
     }
 
     /// Create W3C Trace Context header value
@@ -241,6 +244,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_request(&context).await;
@@ -267,6 +271,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_request(&context).await;
@@ -287,6 +292,7 @@ mod tests {
             response_status: Some(200),
             response_headers: HashMap::new(),
             metadata: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         // Set trace_id as would be set by on_request
@@ -306,6 +312,7 @@ mod tests {
             response_status: None,
             response_headers: HashMap::new(),
             metadata: std::sync::Arc::new(std::sync::Mutex::new(HashMap::new())),
+            ..Default::default()
         };
 
         let result = middleware.on_error(&context, "Test error").await;
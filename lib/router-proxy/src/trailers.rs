@@ -0,0 +1,246 @@
+//! Response trailers and gRPC status
+//!
+//! gRPC reports a call's outcome in `grpc-status` and `grpc-message`
+//! trailers sent after the response body. The forwarder keeps an
+//! upstream's trailers as an [`UpstreamTrailers`] response extension, and
+//! [`TrailersBody`] sends them on to the client after the buffered body.
+//! HTTP/1.1 clients only receive trailers they asked for with
+//! `TE: trailers`, over a chunked response.
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, TRAILER};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// gRPC status trailer
+pub const GRPC_STATUS: HeaderName = HeaderName::from_static("grpc-status");
+
+/// Trailers received from an upstream, attached to its response
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpstreamTrailers(pub HeaderMap);
+
+impl UpstreamTrailers {
+    /// Declare the trailer fields in a `Trailer` response header
+    ///
+    /// HTTP/1.1 only sends declared trailers, and only over chunked
+    /// encoding, so any Content-Length is removed.
+    pub fn declare(&self, headers: &mut HeaderMap) {
+        let names = self.0.keys().map(|name| name.as_str()).collect::<Vec<_>>().join(", ");
+        if let Ok(value) = HeaderValue::from_str(&names) {
+            headers.insert(TRAILER, value);
+            headers.remove(CONTENT_LENGTH);
+        }
+    }
+}
+
+/// gRPC status code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrpcStatus(u32);
+
+/// Canonical gRPC status code names, indexed by code
+const GRPC_STATUS_NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+impl GrpcStatus {
+    /// The call succeeded
+    pub const OK: Self = Self(0);
+    /// The deadline passed before the call completed
+    pub const DEADLINE_EXCEEDED: Self = Self(4);
+    /// A quota or resource ran out, e.g. the server is overloaded
+    pub const RESOURCE_EXHAUSTED: Self = Self(8);
+    /// The server hit an internal error
+    pub const INTERNAL: Self = Self(13);
+    /// The service is temporarily unavailable
+    pub const UNAVAILABLE: Self = Self(14);
+
+    /// Status for a numeric code
+    pub fn new(code: u32) -> Self {
+        Self(code)
+    }
+
+    /// Status of a gRPC response
+    ///
+    /// Read from the trailers, or from the headers for a trailers-only
+    /// response. None for responses that aren't gRPC.
+    pub fn from_response(headers: &HeaderMap, trailers: Option<&UpstreamTrailers>) -> Option<Self> {
+        trailers
+            .and_then(|t| t.0.get(GRPC_STATUS))
+            .or_else(|| headers.get(GRPC_STATUS))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Self)
+    }
+
+    /// Parse a status from its name ("UNAVAILABLE", case-insensitive) or code ("14")
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(code) = value.parse() {
+            return Some(Self(code));
+        }
+        GRPC_STATUS_NAMES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+            .map(|code| Self(code as u32))
+    }
+
+    /// Numeric code
+    pub fn code(&self) -> u32 {
+        self.0
+    }
+
+    /// Canonical name, e.g. "UNAVAILABLE"
+    pub fn name(&self) -> &'static str {
+        GRPC_STATUS_NAMES.get(self.0 as usize).copied().unwrap_or("UNKNOWN")
+    }
+}
+
+/// Body that sends trailers after its inner body ends
+pub struct TrailersBody<B> {
+    inner: B,
+    trailers: Option<HeaderMap>,
+}
+
+impl<B> TrailersBody<B> {
+    /// Wrap a body, sending `trailers` after it if given
+    pub fn new(inner: B, trailers: Option<HeaderMap>) -> Self {
+        Self { inner, trailers }
+    }
+}
+
+impl<B> Body for TrailersBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        match Pin::new(&mut this.inner).poll_frame(cx) {
+            Poll::Ready(None) => Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t)))),
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.trailers.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let hint = self.inner.size_hint();
+        if self.trailers.is_none() {
+            return hint;
+        }
+        // An exact length would be sent as Content-Length, leaving no room for trailers
+        let mut open = SizeHint::new();
+        open.set_lower(hint.lower());
+        open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response};
+    use hyper_util::rt::TokioIo;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn grpc_trailers(code: &'static str) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(GRPC_STATUS, HeaderValue::from_static(code));
+        trailers.insert("grpc-message", HeaderValue::from_static("backend%20down"));
+        trailers
+    }
+
+    #[test]
+    fn test_grpc_status() {
+        let trailers = UpstreamTrailers(grpc_trailers("14"));
+        let status = GrpcStatus::from_response(&HeaderMap::new(), Some(&trailers)).unwrap();
+        assert_eq!(status.code(), 14);
+        assert_eq!(status.name(), "UNAVAILABLE");
+
+        // Trailers-only responses carry the status in the headers
+        let headers = grpc_trailers("8");
+        assert_eq!(GrpcStatus::from_response(&headers, None).unwrap().name(), "RESOURCE_EXHAUSTED");
+
+        assert_eq!(GrpcStatus::from_response(&HeaderMap::new(), None), None);
+        assert_eq!(GrpcStatus::new(99).name(), "UNKNOWN");
+
+        assert_eq!(GrpcStatus::parse("unavailable"), Some(GrpcStatus::UNAVAILABLE));
+        assert_eq!(GrpcStatus::parse("8"), Some(GrpcStatus::RESOURCE_EXHAUSTED));
+        assert_eq!(GrpcStatus::parse("NOT_A_STATUS"), None);
+    }
+
+    #[tokio::test]
+    async fn test_trailers_sent_after_body() {
+        let body = TrailersBody::new(Full::new(Bytes::from("data")), Some(grpc_trailers("0")));
+        assert_eq!(body.size_hint().exact(), None);
+
+        let collected = body.collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()[GRPC_STATUS], "0");
+        assert_eq!(collected.to_bytes(), Bytes::from("data"));
+
+        let body = TrailersBody::new(Full::new(Bytes::from("data")), None);
+        assert_eq!(body.size_hint().exact(), Some(4));
+    }
+
+    async fn http1_request(te_trailers: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_req: Request<Incoming>| async {
+                let trailers = UpstreamTrailers(grpc_trailers("0"));
+                let mut response = Response::new(TrailersBody::new(Full::new(Bytes::from("ok")), Some(trailers.0.clone())));
+                trailers.declare(response.headers_mut());
+                Ok::<_, hyper::Error>(response)
+            });
+            let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+        });
+
+        let te = if te_trailers { "TE: trailers\r\n" } else { "" };
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET / HTTP/1.1\r\nHost: test\r\n{}Connection: close\r\n\r\n", te);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.to_ascii_lowercase()
+    }
+
+    #[tokio::test]
+    async fn test_http1_chunked_trailers() {
+        let response = http1_request(true).await;
+        assert!(response.contains("transfer-encoding: chunked"));
+        assert!(response.contains("trailer: grpc-status, grpc-message"));
+        assert!(response.ends_with("0\r\ngrpc-status: 0\r\ngrpc-message: backend%20down\r\n\r\n"));
+
+        // Clients that didn't ask for trailers get the body alone
+        let response = http1_request(false).await;
+        assert!(!response.contains("grpc-status: 0"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Traffic totals between pairs of VPCs
+//!
+//! Every forwarded request is counted against its source VPC and the VPC
+//! of the upstream it went to, for chargeback and capacity planning of
+//! inter-VPC traffic. Requests from or to addresses outside any known VPC
+//! count against "unknown".
+
+use crate::metrics::MetricsCollector;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Label for a side of a request that isn't in a known VPC
+pub const UNKNOWN_VPC: &str = "unknown";
+
+/// Totals for one source and destination VPC
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcPairTraffic {
+    /// Source VPC, as namespace/name
+    pub source_vpc: String,
+    /// Destination VPC, as namespace/name
+    pub destination_vpc: String,
+    /// Requests forwarded
+    pub requests: u64,
+    /// Request bytes sent to the destination
+    pub bytes_sent: u64,
+    /// Response bytes received from the destination
+    pub bytes_received: u64,
+}
+
+/// Records requests per source and destination VPC
+#[derive(Default)]
+pub struct VpcTrafficRecorder {
+    pairs: Mutex<BTreeMap<(String, String), VpcPairTraffic>>,
+    metrics: Option<Arc<MetricsCollector>>,
+}
+
+impl VpcTrafficRecorder {
+    /// Create an empty recorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also export the totals as metrics
+    pub fn with_metrics(mut self, metrics: Arc<MetricsCollector>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Count a request from `source` to `destination`; `None` is an unknown VPC
+    pub fn record(&self, source: Option<&str>, destination: Option<&str>, bytes_sent: u64, bytes_received: u64) {
+        let source = source.unwrap_or(UNKNOWN_VPC);
+        let destination = destination.unwrap_or(UNKNOWN_VPC);
+        {
+            let mut pairs = self.pairs.lock().unwrap_or_else(|e| e.into_inner());
+            let pair = pairs
+                .entry((source.to_string(), destination.to_string()))
+                .or_insert_with(|| VpcPairTraffic {
+                    source_vpc: source.to_string(),
+                    destination_vpc: destination.to_string(),
+                    ..Default::default()
+                });
+            pair.requests += 1;
+            pair.bytes_sent += bytes_sent;
+            pair.bytes_received += bytes_received;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_vpc_pair(source, destination, bytes_sent, bytes_received);
+        }
+    }
+
+    /// Current totals for every pair seen, ordered by source then destination
+    pub fn snapshot(&self) -> Vec<VpcPairTraffic> {
+        self.pairs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_pairs() {
+        let metrics = Arc::new(MetricsCollector::new().unwrap());
+        let recorder = VpcTrafficRecorder::new().with_metrics(metrics.clone());
+        recorder.record(Some("default/web"), Some("default/payments"), 100, 2048);
+        recorder.record(Some("default/web"), Some("default/payments"), 50, 10);
+        recorder.record(None, Some("default/payments"), 1, 2);
+
+        let snapshot = recorder.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!((snapshot[0].source_vpc.as_str(), snapshot[0].requests), ("default/web", 2));
+        assert_eq!((snapshot[0].bytes_sent, snapshot[0].bytes_received), (150, 2058));
+        assert_eq!(snapshot[1].source_vpc, UNKNOWN_VPC);
+
+        let requests = metrics.vpc_pair_requests_total.with_label_values(&["default/web", "default/payments"]);
+        assert_eq!(requests.get(), 2.0);
+        let sent = metrics.vpc_pair_bytes_total.with_label_values(&["default/web", "default/payments", "upstream"]);
+        assert_eq!(sent.get(), 150.0);
+    }
+}
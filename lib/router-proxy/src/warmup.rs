@@ -0,0 +1,189 @@
+//! Warm-up requests for newly healthy endpoints
+//!
+//! An endpoint that just passed its health checks often answers its first
+//! requests slowly while JIT compilers and caches fill. The warmer sends a
+//! few requests of its own first, so users never see that latency.
+
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::{Method, Request};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use router_api::v1alpha1::vpc_service::WarmupPolicy;
+use router_core::{Endpoint, ServiceRegistry};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Warm-up configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct WarmupConfig {
+    /// Path requested during warm-up
+    pub path: String,
+    /// Number of warm-up requests
+    pub requests: u32,
+    /// Timeout for each request
+    pub timeout: Duration,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            path: "/".to_string(),
+            requests: 10,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl WarmupConfig {
+    /// Create a configuration from a VPCService warm-up policy
+    pub fn from_policy(policy: &WarmupPolicy) -> Self {
+        Self {
+            path: policy.path.clone(),
+            requests: policy.requests,
+            timeout: Duration::from_secs(policy.timeout_seconds as u64),
+        }
+    }
+}
+
+/// Sends warm-up requests before endpoints enter rotation
+pub struct EndpointWarmer {
+    config: WarmupConfig,
+    client: Client<HttpConnector, Empty<Bytes>>,
+}
+
+impl EndpointWarmer {
+    /// Create a warmer
+    pub fn new(config: WarmupConfig) -> Self {
+        let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+        Self { config, client }
+    }
+
+    /// Get the warm-up configuration
+    pub fn config(&self) -> &WarmupConfig {
+        &self.config
+    }
+
+    /// Send the warm-up requests to an endpoint, one at a time
+    ///
+    /// Returns how many got a response below 500.
+    pub async fn warm(&self, endpoint: &Endpoint) -> u32 {
+        let uri = format!("http://{}:{}{}", endpoint.ip, endpoint.port, self.config.path);
+        let mut succeeded = 0;
+
+        for _ in 0..self.config.requests {
+            let request = match Request::builder()
+                .method(Method::GET)
+                .uri(&uri)
+                .header("x-router-warmup", "1")
+                .body(Empty::new())
+            {
+                Ok(request) => request,
+                Err(e) => {
+                    warn!("Invalid warm-up request for {}: {}", uri, e);
+                    return succeeded;
+                }
+            };
+
+            match tokio::time::timeout(self.config.timeout, self.client.request(request)).await {
+                Ok(Ok(response)) if !response.status().is_server_error() => succeeded += 1,
+                Ok(Ok(response)) => debug!("Warm-up request to {} returned {}", uri, response.status()),
+                Ok(Err(e)) => debug!("Warm-up request to {} failed: {}", uri, e),
+                Err(_) => debug!("Warm-up request to {} timed out", uri),
+            }
+        }
+        succeeded
+    }
+
+    /// Warm up a newly healthy endpoint, then mark it ready
+    ///
+    /// Warm-up is best effort: the endpoint enters rotation even if some
+    /// requests fail, since it already passed its health checks.
+    pub async fn admit(
+        &self,
+        registry: &ServiceRegistry,
+        service_id: &str,
+        endpoint: &Endpoint,
+    ) -> router_core::Result<()> {
+        let succeeded = self.warm(endpoint).await;
+        if succeeded < self.config.requests {
+            warn!(
+                "Endpoint {}:{} of {} answered {}/{} warm-up requests",
+                endpoint.ip, endpoint.port, service_id, succeeded, self.config.requests
+            );
+        } else {
+            info!("Endpoint {}:{} of {} warmed up", endpoint.ip, endpoint.port, service_id);
+        }
+        registry.set_endpoint_ready(service_id, &endpoint.ip, endpoint.port, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::Response;
+    use hyper_util::rt::TokioIo;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_config_from_policy() {
+        let config = WarmupConfig::from_policy(&WarmupPolicy {
+            path: "/warm".to_string(),
+            requests: 3,
+            timeout_seconds: 2,
+        });
+        assert_eq!(config.path, "/warm");
+        assert_eq!(config.requests, 3);
+        assert_eq!(config.timeout, Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_admit_warms_then_marks_ready() {
+        let hits = Arc::new(AtomicU32::new(0));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server_hits = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let hits = server_hits.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        if req.uri().path() == "/warm" {
+                            hits.fetch_add(1, Ordering::SeqCst);
+                        }
+                        async { Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new())) }
+                    });
+                    let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+                });
+            }
+        });
+
+        let endpoint = Endpoint {
+            ip: "127.0.0.1".to_string(),
+            port,
+            ready: false,
+            priority: 0,
+        };
+        let registry = ServiceRegistry::new();
+        registry
+            .register_service("default".into(), "api".into(), port, "HTTP".into(), vec![endpoint.clone()])
+            .await
+            .unwrap();
+
+        let warmer = EndpointWarmer::new(WarmupConfig {
+            path: "/warm".to_string(),
+            requests: 3,
+            timeout: Duration::from_secs(1),
+        });
+        warmer.admit(&registry, "default/api", &endpoint).await.unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(registry.get_endpoints("default/api").await.unwrap()[0].ready);
+    }
+}
@@ -0,0 +1,723 @@
+//! WebAssembly plugin middleware
+//!
+//! Runs user-provided WebAssembly modules as middleware, so request
+//! processing can be extended without recompiling the router. Plugins are
+//! written against a subset of the proxy-wasm ABI:
+//!
+//! - Callbacks: `proxy_on_context_create`, `proxy_on_request_headers`,
+//!   `proxy_on_request_body`, `proxy_on_response_headers`,
+//!   `proxy_on_response_body`, `proxy_on_done`, and `proxy_on_delete`, all
+//!   optional
+//! - Host functions (module `env`): `proxy_log`,
+//!   `proxy_get_header_map_value`, `proxy_get_header_map_pairs`,
+//!   `proxy_get_buffer_bytes`, `proxy_set_buffer_bytes`, and
+//!   `proxy_send_local_response`
+//! - Memory: the plugin exports `memory` and an allocator,
+//!   `proxy_on_memory_allocate` or `malloc`
+//!
+//! Headers are read-only. Bodies can be rewritten chunk by chunk, and
+//! `proxy_send_local_response` answers the request from the plugin. Other
+//! imports are linked to stubs that trap when called, so a plugin built
+//! with a full SDK loads as long as it sticks to the supported calls.
+//! Returning Pause is treated as Continue, since the gateway never holds a
+//! request waiting for a plugin.
+//!
+//! Each plugin has one instance whose callbacks run one at a time, and each
+//! callback runs on a fuel budget so a runaway plugin is aborted instead of
+//! stalling the gateway.
+
+use crate::middleware::{Middleware, MiddlewareContext};
+use anyhow::{anyhow, bail, Context as _, Result};
+use hyper::body::Bytes;
+use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+use tracing::{debug, error, info, trace, warn};
+use wasmi::{Caller, Config, Engine, Error, Extern, ExternType, Instance, Linker, Memory, Module, Store, Val};
+
+/// Metadata key holding the status of a plugin's local response
+pub const LOCAL_RESPONSE_STATUS: &str = "wasm_local_response_status";
+
+/// Metadata key holding the body of a plugin's local response
+pub const LOCAL_RESPONSE_BODY: &str = "wasm_local_response_body";
+
+/// Context ID of the plugin's root context
+const ROOT_CONTEXT_ID: i32 = 1;
+
+/// Header map types
+const MAP_REQUEST_HEADERS: i32 = 0;
+const MAP_RESPONSE_HEADERS: i32 = 2;
+
+/// Buffer types
+const BUFFER_REQUEST_BODY: i32 = 0;
+const BUFFER_RESPONSE_BODY: i32 = 1;
+
+/// Status codes returned by host functions
+const STATUS_OK: i32 = 0;
+const STATUS_NOT_FOUND: i32 = 1;
+const STATUS_BAD_ARGUMENT: i32 = 2;
+
+/// Action asking the host to stop processing
+const ACTION_PAUSE: i32 = 1;
+
+/// Host functions implemented by the gateway, all in module `env`
+const HOST_FUNCTIONS: [&str; 6] = [
+    "proxy_log",
+    "proxy_get_header_map_value",
+    "proxy_get_header_map_pairs",
+    "proxy_get_buffer_bytes",
+    "proxy_set_buffer_bytes",
+    "proxy_send_local_response",
+];
+
+/// Plugin settings
+#[derive(Clone, Debug)]
+pub struct WasmPluginConfig {
+    /// Name used in logs and errors
+    pub name: String,
+    /// Fuel each callback may burn before it is aborted
+    pub fuel_per_call: u64,
+    /// Let requests through when the plugin fails instead of answering 500
+    pub fail_open: bool,
+}
+
+impl Default for WasmPluginConfig {
+    fn default() -> Self {
+        Self {
+            name: "wasm".to_string(),
+            fuel_per_call: 10_000_000,
+            fail_open: false,
+        }
+    }
+}
+
+/// Response a plugin answered a request with
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Per-call state the host functions operate on
+#[derive(Default)]
+struct HostState {
+    plugin: String,
+    request_headers: Vec<(String, String)>,
+    response_headers: Vec<(String, String)>,
+    /// Type of the body buffer available to the plugin, if any
+    buffer_type: Option<i32>,
+    body: Vec<u8>,
+    local_response: Option<LocalResponse>,
+}
+
+impl HostState {
+    fn header_map(&self, map_type: i32) -> Option<&[(String, String)]> {
+        match map_type {
+            MAP_REQUEST_HEADERS => Some(&self.request_headers),
+            MAP_RESPONSE_HEADERS => Some(&self.response_headers),
+            _ => None,
+        }
+    }
+}
+
+/// A loaded plugin instance
+struct Vm {
+    store: Store<HostState>,
+    instance: Instance,
+}
+
+/// Middleware running a WebAssembly plugin
+pub struct WasmMiddleware {
+    config: WasmPluginConfig,
+    /// Metadata key holding the plugin's context ID for a request
+    context_key: String,
+    next_context_id: AtomicI32,
+    inspects_body: bool,
+    vm: Mutex<Vm>,
+}
+
+impl WasmMiddleware {
+    /// Load a plugin from a `.wasm` file
+    pub fn from_file(path: impl AsRef<Path>, config: WasmPluginConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let wasm = std::fs::read(path).with_context(|| format!("Failed to read plugin {}", path.display()))?;
+        Self::new(&wasm, config)
+    }
+
+    /// Load a plugin from WebAssembly bytecode
+    pub fn new(wasm: &[u8], config: WasmPluginConfig) -> Result<Self> {
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Module::new(&engine, wasm).map_err(|e| anyhow!("Invalid plugin {}: {}", config.name, e))?;
+
+        let mut linker = Linker::<HostState>::new(&engine);
+        define_host_functions(&mut linker)?;
+        for import in module.imports() {
+            let ExternType::Func(ty) = import.ty() else {
+                continue;
+            };
+            if import.module() == "env" && HOST_FUNCTIONS.contains(&import.name()) {
+                continue;
+            }
+            let name = format!("{}.{}", import.module(), import.name());
+            debug!("Plugin {} imports unsupported {}", config.name, name);
+            linker.func_new(import.module(), import.name(), ty.clone(), move |_, _, _| {
+                Err(Error::new(format!("unsupported host function {}", name)))
+            })?;
+        }
+
+        let mut store = Store::new(&engine, HostState::default());
+        store.set_fuel(config.fuel_per_call).map_err(|e| anyhow!("{}", e))?;
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| anyhow!("Failed to instantiate plugin {}: {}", config.name, e))?;
+
+        if instance.get_memory(&store, "memory").is_none() {
+            bail!("Plugin {} exports no memory", config.name);
+        }
+        if instance.get_func(&store, "proxy_on_memory_allocate").is_none() && instance.get_func(&store, "malloc").is_none() {
+            bail!("Plugin {} exports no allocator", config.name);
+        }
+        let inspects_body = ["proxy_on_request_body", "proxy_on_response_body"]
+            .iter()
+            .any(|callback| instance.get_func(&store, callback).is_some());
+
+        let plugin = Self {
+            context_key: format!("wasm_context.{}", config.name),
+            config,
+            next_context_id: AtomicI32::new(ROOT_CONTEXT_ID + 1),
+            inspects_body,
+            vm: Mutex::new(Vm { store, instance }),
+        };
+        plugin.run("_initialize", &[], plugin.host_state())?;
+        plugin.run("proxy_on_context_create", &[ROOT_CONTEXT_ID, 0], plugin.host_state())?;
+        info!("Loaded WebAssembly plugin {}", plugin.config.name);
+        Ok(plugin)
+    }
+
+    /// Name of the plugin
+    pub fn plugin_name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Response a plugin answered the request with, if any
+    pub fn local_response(context: &MiddlewareContext) -> Option<LocalResponse> {
+        let status = context.get_metadata(LOCAL_RESPONSE_STATUS)?.parse().ok()?;
+        let body = context.get_metadata(LOCAL_RESPONSE_BODY).unwrap_or_default();
+        Some(LocalResponse { status, body })
+    }
+
+    /// Empty host state for a call
+    fn host_state(&self) -> HostState {
+        HostState {
+            plugin: self.config.name.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Call an exported callback with `state` available to host functions
+    ///
+    /// Returns the state as the callback left it, or None if the plugin
+    /// doesn't export the callback.
+    fn run(&self, callback: &str, args: &[i32], state: HostState) -> Result<Option<HostState>> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|_| anyhow!("Plugin {} is unusable after a panic", self.config.name))?;
+        let Vm { store, instance } = &mut *vm;
+        let Some(func) = instance.get_func(&*store, callback) else {
+            return Ok(None);
+        };
+
+        *store.data_mut() = state;
+        store.set_fuel(self.config.fuel_per_call).map_err(|e| anyhow!("{}", e))?;
+        let params: Vec<Val> = args.iter().map(|arg| Val::I32(*arg)).collect();
+        let mut results = vec![Val::I32(0); func.ty(&*store).results().len()];
+        let result = func.call(&mut *store, &params, &mut results);
+        let state = std::mem::take(store.data_mut());
+        result.map_err(|e| anyhow!("Plugin {} failed in {}: {}", self.config.name, callback, e))?;
+
+        if results.first().and_then(Val::i32) == Some(ACTION_PAUSE) {
+            debug!("Plugin {} paused in {}, continuing", self.config.name, callback);
+        }
+        Ok(Some(state))
+    }
+
+    /// The plugin's context ID for a request, creating the context on first use
+    fn context_id(&self, context: &MiddlewareContext) -> Result<i32> {
+        if let Some(id) = context.get_metadata(&self.context_key).and_then(|v| v.parse().ok()) {
+            return Ok(id);
+        }
+        let id = self.next_context_id.fetch_add(1, Ordering::Relaxed);
+        context.set_metadata(self.context_key.clone(), id.to_string());
+        self.run("proxy_on_context_create", &[id, ROOT_CONTEXT_ID], self.host_state())?;
+        Ok(id)
+    }
+
+    /// Handle a failed callback on the request path
+    ///
+    /// Fails closed with a 500 unless the plugin is configured to fail open.
+    fn fail(&self, context: &MiddlewareContext, error: anyhow::Error) -> Result<()> {
+        if self.config.fail_open {
+            warn!("{}; continuing", error);
+            return Ok(());
+        }
+        error!("{}", error);
+        record_local_response(
+            context,
+            LocalResponse {
+                status: 500,
+                body: "Plugin failed".to_string(),
+            },
+        );
+        Err(error)
+    }
+
+    /// Run a body callback over a chunk, returning the chunk to pass on
+    fn filter_body(&self, context: &MiddlewareContext, callback: &str, buffer_type: i32, chunk: Bytes, end_of_stream: bool) -> Result<Bytes> {
+        let outcome = self.context_id(context).and_then(|id| {
+            let state = HostState {
+                buffer_type: Some(buffer_type),
+                body: chunk.to_vec(),
+                ..self.host_state()
+            };
+            self.run(callback, &[id, chunk.len() as i32, end_of_stream as i32], state)
+        });
+        match outcome {
+            Ok(Some(state)) => {
+                if let Some(response) = state.local_response {
+                    bail!("plugin {} answered {}: {}", self.config.name, response.status, response.body);
+                }
+                Ok(Bytes::from(state.body))
+            }
+            Ok(None) => Ok(chunk),
+            Err(e) if self.config.fail_open => {
+                warn!("{}; continuing", e);
+                Ok(chunk)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for WasmMiddleware {
+    fn name(&self) -> &'static str {
+        "WasmMiddleware"
+    }
+
+    async fn on_request(&self, context: &MiddlewareContext) -> Result<()> {
+        let outcome = self.context_id(context).and_then(|id| {
+            let state = HostState {
+                request_headers: request_header_map(context),
+                ..self.host_state()
+            };
+            let count = state.request_headers.len() as i32;
+            self.run("proxy_on_request_headers", &[id, count, 0], state)
+        });
+        match outcome {
+            Ok(Some(state)) => match state.local_response {
+                Some(response) => {
+                    debug!("Plugin {} answered {} {} with {}", self.config.name, context.method, context.path, response.status);
+                    let status = response.status;
+                    record_local_response(context, response);
+                    Err(anyhow!("plugin {} answered the request with {}", self.config.name, status))
+                }
+                None => Ok(()),
+            },
+            Ok(None) => Ok(()),
+            Err(e) => self.fail(context, e),
+        }
+    }
+
+    async fn on_response(&self, context: &MiddlewareContext, status: u16) -> Result<()> {
+        let id = self.context_id(context)?;
+        let mut response_headers = vec![(":status".to_string(), status.to_string())];
+        response_headers.extend(context.response_headers.iter().map(|(k, v)| (k.to_lowercase(), v.clone())));
+        let state = HostState {
+            request_headers: request_header_map(context),
+            response_headers,
+            ..self.host_state()
+        };
+        let count = state.response_headers.len() as i32;
+        let outcome = self.run("proxy_on_response_headers", &[id, count, 0], state);
+        if let Ok(Some(HostState { local_response: Some(_), .. })) = &outcome {
+            warn!("Plugin {} tried to answer a request whose response was already sent", self.config.name);
+        }
+
+        // The request is finished; let the plugin release its context
+        self.run("proxy_on_done", &[id], self.host_state())?;
+        self.run("proxy_on_delete", &[id], self.host_state())?;
+        outcome.map(|_| ())
+    }
+
+    fn inspects_body(&self) -> bool {
+        self.inspects_body
+    }
+
+    fn on_request_body(&self, context: &MiddlewareContext, chunk: Bytes) -> Result<Bytes> {
+        self.filter_body(context, "proxy_on_request_body", BUFFER_REQUEST_BODY, chunk, false)
+    }
+
+    fn on_response_body(&self, context: &MiddlewareContext, chunk: Bytes) -> Result<Bytes> {
+        // Response bodies are buffered, so each chunk is the whole body
+        self.filter_body(context, "proxy_on_response_body", BUFFER_RESPONSE_BODY, chunk, true)
+    }
+}
+
+/// Record a plugin's answer unless an earlier plugin already answered
+fn record_local_response(context: &MiddlewareContext, response: LocalResponse) {
+    if context.get_metadata(LOCAL_RESPONSE_STATUS).is_some() {
+        return;
+    }
+    context.set_metadata(LOCAL_RESPONSE_STATUS.to_string(), response.status.to_string());
+    context.set_metadata(LOCAL_RESPONSE_BODY.to_string(), response.body);
+}
+
+/// Request headers as the plugin sees them, with proxy-wasm pseudo-headers
+fn request_header_map(context: &MiddlewareContext) -> Vec<(String, String)> {
+    let path = match &context.query {
+        Some(query) => format!("{}?{}", context.path, query),
+        None => context.path.clone(),
+    };
+    let mut headers = vec![(":method".to_string(), context.method.clone()), (":path".to_string(), path)];
+    if let Some(host) = context.request_headers.get("host") {
+        headers.push((":authority".to_string(), host.clone()));
+    }
+    let mut regular: Vec<(String, String)> = context
+        .request_headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.clone()))
+        .collect();
+    regular.sort();
+    headers.extend(regular);
+    headers
+}
+
+/// Serialize a header map in the proxy-wasm pairs format
+///
+/// The pair count, then each key and value length, then each key and value
+/// followed by a NUL; all integers are little-endian u32.
+fn serialize_pairs(pairs: &[(String, String)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(pairs.len() as u32).to_le_bytes());
+    for (key, value) in pairs {
+        out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (key, value) in pairs {
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+        out.extend_from_slice(value.as_bytes());
+        out.push(0);
+    }
+    out
+}
+
+/// The plugin's exported memory
+fn memory(caller: &Caller<'_, HostState>) -> Result<Memory, Error> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Error::new("plugin exports no memory"))
+}
+
+/// Read `len` bytes of plugin memory at `ptr`
+fn read_bytes(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>, Error> {
+    let mut buffer = vec![0; len as u32 as usize];
+    memory(caller)?.read(caller, ptr as u32 as usize, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Copy `data` into memory allocated by the plugin
+///
+/// The address and length of the copy are written to `ptr_out` and
+/// `len_out`, as host functions returning data do.
+fn return_bytes(caller: &mut Caller<'_, HostState>, data: &[u8], ptr_out: i32, len_out: i32) -> Result<(), Error> {
+    let allocate = caller
+        .get_export("proxy_on_memory_allocate")
+        .or_else(|| caller.get_export("malloc"))
+        .and_then(Extern::into_func)
+        .ok_or_else(|| Error::new("plugin exports no allocator"))?
+        .typed::<i32, i32>(&*caller)?;
+    let address = allocate.call(&mut *caller, data.len() as i32)?;
+    let memory = memory(caller)?;
+    memory.write(&mut *caller, address as u32 as usize, data)?;
+    memory.write(&mut *caller, ptr_out as u32 as usize, &(address as u32).to_le_bytes())?;
+    memory.write(&mut *caller, len_out as u32 as usize, &(data.len() as u32).to_le_bytes())?;
+    Ok(())
+}
+
+/// Link the supported host functions
+fn define_host_functions(linker: &mut Linker<HostState>) -> Result<()> {
+    linker.func_wrap(
+        "env",
+        "proxy_log",
+        |caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| -> Result<i32, Error> {
+            let message = String::from_utf8_lossy(&read_bytes(&caller, ptr, len)?).into_owned();
+            let plugin = &caller.data().plugin;
+            match level {
+                0 => trace!(plugin = %plugin, "{}", message),
+                1 => debug!(plugin = %plugin, "{}", message),
+                2 => info!(plugin = %plugin, "{}", message),
+                3 => warn!(plugin = %plugin, "{}", message),
+                _ => error!(plugin = %plugin, "{}", message),
+            }
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_get_header_map_value",
+        |mut caller: Caller<'_, HostState>, map_type: i32, key_ptr: i32, key_len: i32, ptr_out: i32, len_out: i32| -> Result<i32, Error> {
+            let key = String::from_utf8_lossy(&read_bytes(&caller, key_ptr, key_len)?).to_lowercase();
+            let Some(map) = caller.data().header_map(map_type) else {
+                return Ok(STATUS_BAD_ARGUMENT);
+            };
+            let Some(value) = map.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone()) else {
+                return Ok(STATUS_NOT_FOUND);
+            };
+            return_bytes(&mut caller, value.as_bytes(), ptr_out, len_out)?;
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_get_header_map_pairs",
+        |mut caller: Caller<'_, HostState>, map_type: i32, ptr_out: i32, len_out: i32| -> Result<i32, Error> {
+            let Some(map) = caller.data().header_map(map_type) else {
+                return Ok(STATUS_BAD_ARGUMENT);
+            };
+            let serialized = serialize_pairs(map);
+            return_bytes(&mut caller, &serialized, ptr_out, len_out)?;
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_get_buffer_bytes",
+        |mut caller: Caller<'_, HostState>, buffer_type: i32, start: i32, max_size: i32, ptr_out: i32, len_out: i32| -> Result<i32, Error> {
+            let state = caller.data();
+            if state.buffer_type != Some(buffer_type) {
+                return Ok(STATUS_NOT_FOUND);
+            }
+            let start = (start as u32 as usize).min(state.body.len());
+            let end = start.saturating_add(max_size as u32 as usize).min(state.body.len());
+            let data = state.body[start..end].to_vec();
+            return_bytes(&mut caller, &data, ptr_out, len_out)?;
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_set_buffer_bytes",
+        |mut caller: Caller<'_, HostState>, buffer_type: i32, start: i32, size: i32, data_ptr: i32, data_len: i32| -> Result<i32, Error> {
+            if caller.data().buffer_type != Some(buffer_type) {
+                return Ok(STATUS_NOT_FOUND);
+            }
+            let data = read_bytes(&caller, data_ptr, data_len)?;
+            let body = &mut caller.data_mut().body;
+            let start = start as u32 as usize;
+            if start > body.len() {
+                return Ok(STATUS_BAD_ARGUMENT);
+            }
+            let end = start.saturating_add(size as u32 as usize).min(body.len());
+            body.splice(start..end, data);
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    linker.func_wrap(
+        "env",
+        "proxy_send_local_response",
+        |mut caller: Caller<'_, HostState>,
+         status: i32,
+         _details_ptr: i32,
+         _details_len: i32,
+         body_ptr: i32,
+         body_len: i32,
+         _headers_ptr: i32,
+         _headers_len: i32,
+         _grpc_status: i32|
+         -> Result<i32, Error> {
+            let Ok(status) = u16::try_from(status) else {
+                return Ok(STATUS_BAD_ARGUMENT);
+            };
+            if !(100..=599).contains(&status) {
+                return Ok(STATUS_BAD_ARGUMENT);
+            }
+            let body = String::from_utf8_lossy(&read_bytes(&caller, body_ptr, body_len)?).into_owned();
+            caller.data_mut().local_response = Some(LocalResponse { status, body });
+            Ok(STATUS_OK)
+        },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Bump allocator shared by the test plugins
+    const ALLOCATOR: &str = r#"
+        (memory (export "memory") 1)
+        (global $next (mut i32) (i32.const 4096))
+        (func (export "proxy_on_memory_allocate") (param $size i32) (result i32)
+            (local $addr i32)
+            (local.set $addr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $size)))
+            (local.get $addr))
+    "#;
+
+    fn plugin(body: &str, config: WasmPluginConfig) -> WasmMiddleware {
+        let wat = format!("(module {} {} {})", imports(body), ALLOCATOR, body);
+        WasmMiddleware::new(&wat::parse_str(wat).unwrap(), config).unwrap()
+    }
+
+    /// Host function imports a test plugin's body refers to
+    fn imports(body: &str) -> String {
+        let signatures = [
+            ("proxy_log", "(param i32 i32 i32) (result i32)"),
+            ("proxy_get_header_map_value", "(param i32 i32 i32 i32 i32) (result i32)"),
+            ("proxy_get_buffer_bytes", "(param i32 i32 i32 i32 i32) (result i32)"),
+            ("proxy_set_buffer_bytes", "(param i32 i32 i32 i32 i32) (result i32)"),
+            ("proxy_send_local_response", "(param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)"),
+            ("proxy_set_tick_period_milliseconds", "(param i32) (result i32)"),
+        ];
+        signatures
+            .iter()
+            .filter(|(name, _)| body.contains(&format!("${}", name)))
+            .map(|(name, sig)| format!(r#"(import "env" "{0}" (func ${0} {1}))"#, name, sig))
+            .collect()
+    }
+
+    fn request(headers: &[(&str, &str)]) -> MiddlewareContext {
+        let request_headers: HashMap<String, String> =
+            headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        MiddlewareContext {
+            path: "/admin".to_string(),
+            method: "GET".to_string(),
+            request_headers,
+            ..Default::default()
+        }
+    }
+
+    /// Rejects requests without an x-api-key header with 401
+    const API_KEY_PLUGIN: &str = r#"
+        (data (i32.const 0) "x-api-key")
+        (data (i32.const 16) "missing key")
+        (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+            (if (i32.ne
+                    (call $proxy_get_header_map_value (i32.const 0) (i32.const 0) (i32.const 9) (i32.const 32) (i32.const 36))
+                    (i32.const 0))
+                (then
+                    (drop (call $proxy_send_local_response
+                        (i32.const 401) (i32.const 0) (i32.const 0) (i32.const 16) (i32.const 11)
+                        (i32.const 0) (i32.const 0) (i32.const -1)))))
+            (i32.const 0))
+    "#;
+
+    #[tokio::test]
+    async fn test_local_response_from_request_headers() {
+        let middleware = plugin(API_KEY_PLUGIN, WasmPluginConfig::default());
+        assert!(!middleware.inspects_body());
+
+        let allowed = request(&[("x-api-key", "secret")]);
+        middleware.on_request(&allowed).await.unwrap();
+        assert_eq!(WasmMiddleware::local_response(&allowed), None);
+
+        let denied = request(&[]);
+        assert!(middleware.on_request(&denied).await.is_err());
+        assert_eq!(
+            WasmMiddleware::local_response(&denied),
+            Some(LocalResponse {
+                status: 401,
+                body: "missing key".to_string()
+            })
+        );
+        middleware.on_response(&denied, 401).await.unwrap();
+    }
+
+    /// Replaces the first byte of each body with "J"
+    const BODY_PLUGIN: &str = r#"
+        (data (i32.const 0) "J")
+        (func (export "proxy_on_request_body") (param i32 i32 i32) (result i32)
+            (drop (call $proxy_set_buffer_bytes (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 0) (i32.const 1)))
+            (i32.const 0))
+        (func (export "proxy_on_response_body") (param i32 i32 i32) (result i32)
+            (drop (call $proxy_set_buffer_bytes (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 0) (i32.const 1)))
+            (i32.const 0))
+    "#;
+
+    #[test]
+    fn test_body_rewrite() {
+        let middleware = plugin(BODY_PLUGIN, WasmPluginConfig::default());
+        assert!(middleware.inspects_body());
+        let context = request(&[]);
+        assert_eq!(middleware.on_request_body(&context, Bytes::from("hello")).unwrap(), "Jello");
+        assert_eq!(middleware.on_response_body(&context, Bytes::from("mellow")).unwrap(), "Jellow");
+    }
+
+    /// Never returns from request headers
+    const SPINNING_PLUGIN: &str = r#"
+        (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+            (loop $forever (br $forever))
+            (i32.const 0))
+    "#;
+
+    #[tokio::test]
+    async fn test_runaway_plugin_is_aborted() {
+        let config = WasmPluginConfig {
+            fuel_per_call: 10_000,
+            ..Default::default()
+        };
+        let middleware = plugin(SPINNING_PLUGIN, config.clone());
+        let context = request(&[]);
+        assert!(middleware.on_request(&context).await.is_err());
+        assert_eq!(WasmMiddleware::local_response(&context).map(|r| r.status), Some(500));
+
+        let fail_open = plugin(SPINNING_PLUGIN, WasmPluginConfig { fail_open: true, ..config });
+        let context = request(&[]);
+        fail_open.on_request(&context).await.unwrap();
+        assert_eq!(WasmMiddleware::local_response(&context), None);
+    }
+
+    /// Calls a host function the gateway doesn't implement
+    const UNSUPPORTED_PLUGIN: &str = r#"
+        (func (export "proxy_on_request_headers") (param i32 i32 i32) (result i32)
+            (drop (call $proxy_set_tick_period_milliseconds (i32.const 1000)))
+            (i32.const 0))
+    "#;
+
+    #[tokio::test]
+    async fn test_unsupported_host_function_traps() {
+        let middleware = plugin(UNSUPPORTED_PLUGIN, WasmPluginConfig::default());
+        let error = middleware.on_request(&request(&[])).await.unwrap_err();
+        assert!(error.to_string().contains("proxy_on_request_headers"));
+    }
+
+    #[test]
+    fn test_invalid_plugins_are_refused() {
+        assert!(WasmMiddleware::new(b"not wasm", WasmPluginConfig::default()).is_err());
+        let no_allocator = wat::parse_str(r#"(module (memory (export "memory") 1))"#).unwrap();
+        assert!(WasmMiddleware::new(&no_allocator, WasmPluginConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_serialize_pairs() {
+        let pairs = vec![(":method".to_string(), "GET".to_string())];
+        let serialized = serialize_pairs(&pairs);
+        assert_eq!(&serialized[..4], &1u32.to_le_bytes());
+        assert_eq!(&serialized[4..8], &7u32.to_le_bytes());
+        assert_eq!(&serialized[8..12], &3u32.to_le_bytes());
+        assert_eq!(&serialized[12..], b":method\0GET\0");
+    }
+}
@@ -5,3 +5,9 @@ impl IrohTunnel {
         Self
     }
 }
+
+impl Default for IrohTunnel {
+    fn default() -> Self {
+        Self::new()
+    }
+}